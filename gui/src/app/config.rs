@@ -10,20 +10,21 @@ pub struct Config {
     pub log_level: Option<String>,
     /// Use iced debug feature if true.
     pub debug: Option<bool>,
-    /// hardware wallets config.
-    #[serde(default)]
+    /// hardware wallets config. Only set by older versions, the registration tokens of the
+    /// signing devices are now stored by the daemon.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub hardware_wallets: Vec<HardwareWalletConfig>,
 }
 
 pub const DEFAULT_FILE_NAME: &str = "gui.toml";
 
 impl Config {
-    pub fn new(daemon_config_path: PathBuf, hardware_wallets: Vec<HardwareWalletConfig>) -> Self {
+    pub fn new(daemon_config_path: PathBuf) -> Self {
         Self {
             daemon_config_path,
             log_level: None,
             debug: None,
-            hardware_wallets,
+            hardware_wallets: Vec::new(),
         }
     }
 
@@ -1,5 +1,5 @@
 use crate::daemon::DaemonError;
-use liana::config::ConfigError;
+use liana::{config::ConfigError, miniscript::bitcoin::util::bip32::Fingerprint};
 use std::convert::From;
 use std::io::ErrorKind;
 
@@ -9,6 +9,8 @@ pub enum Error {
     Daemon(DaemonError),
     Unexpected(String),
     HardwareWallet(async_hwi::Error),
+    /// No connected hardware wallet has this fingerprint.
+    HardwareWalletNotConnected(Fingerprint),
 }
 
 impl std::fmt::Display for Error {
@@ -40,6 +42,12 @@ impl std::fmt::Display for Error {
             },
             Self::Unexpected(e) => write!(f, "Unexpected error: {}", e),
             Self::HardwareWallet(e) => write!(f, "{}", e),
+            Self::HardwareWalletNotConnected(fg) => write!(
+                f,
+                "No connected hardware wallet with fingerprint {}. Please connect it and refresh \
+                 the list.",
+                fg
+            ),
         }
     }
 }
@@ -24,6 +24,7 @@ pub enum Message {
     Coins(Result<Vec<Coin>, Error>),
     SpendTxs(Result<Vec<SpendTx>, Error>),
     Psbt(Result<Psbt, Error>),
+    FeerateEstimate(Result<u64, Error>),
     Signed(Result<(Psbt, Fingerprint), Error>),
     Updated(Result<(), Error>),
     StartRescan(Result<(), Error>),
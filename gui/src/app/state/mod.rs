@@ -9,13 +9,16 @@ use std::time::{SystemTime, UNIX_EPOCH};
 
 use iced::{widget::qr_code, Command, Subscription};
 use iced::{widget::Column, Element};
-use liana::miniscript::bitcoin::{Address, Amount};
+use liana::miniscript::bitcoin::{Address, Amount, Denomination};
 
 use super::{cache::Cache, error::Error, menu::Menu, message::Message, view};
 
-use crate::daemon::{
-    model::{remaining_sequence, Coin, HistoryTransaction},
-    Daemon,
+use crate::{
+    daemon::{
+        model::{remaining_sequence, Balance, Coin, HistoryTransaction},
+        Daemon,
+    },
+    ui::component::form,
 };
 pub use coins::CoinsPanel;
 pub use recovery::RecoveryPanel;
@@ -39,7 +42,7 @@ pub trait State {
 }
 
 pub struct Home {
-    balance: Amount,
+    balance: Balance,
     recovery_warning: Option<(Amount, usize)>,
     recovery_alert: Option<(Amount, usize)>,
     pending_events: Vec<HistoryTransaction>,
@@ -51,19 +54,7 @@ pub struct Home {
 impl Home {
     pub fn new(coins: &[Coin]) -> Self {
         Self {
-            balance: Amount::from_sat(
-                coins
-                    .iter()
-                    .map(|coin| {
-                        // If the coin is not spent and is its transaction is confirmed
-                        if coin.spend_info.is_none() && coin.block_height.is_some() {
-                            coin.amount.to_sat()
-                        } else {
-                            0
-                        }
-                    })
-                    .sum(),
-            ),
+            balance: Balance::from_coins(coins),
             recovery_alert: None,
             recovery_warning: None,
             selected_event: None,
@@ -114,12 +105,11 @@ impl State for Home {
                 Err(e) => self.warning = Some(e),
                 Ok(coins) => {
                     self.warning = None;
-                    self.balance = Amount::from_sat(0);
+                    self.balance = Balance::from_coins(&coins);
                     let mut recovery_warning = (Amount::from_sat(0), 0);
                     let mut recovery_alert = (Amount::from_sat(0), 0);
                     for coin in coins {
                         if coin.spend_info.is_none() && coin.block_height.is_some() {
-                            self.balance += coin.amount;
                             let timelock = daemon.config().main_descriptor.timelock_value();
                             let seq = remaining_sequence(&coin, cache.blockheight as u32, timelock);
                             if seq == 0 {
@@ -252,13 +242,74 @@ impl From<Home> for Box<dyn State> {
     }
 }
 
+/// Build a BIP21 payment URI for the given address, with an optional amount and label.
+pub fn bip21_uri(address: &Address, amount: Option<Amount>, label: Option<&str>) -> String {
+    let mut params = Vec::new();
+    if let Some(amount) = amount {
+        params.push(format!("amount={}", amount.to_btc()));
+    }
+    if let Some(label) = label.filter(|l| !l.is_empty()) {
+        params.push(format!("label={}", percent_encode(label)));
+    }
+
+    if params.is_empty() {
+        // Uppercase allows the QR code to use the more compact alphanumeric mode.
+        address.to_qr_uri()
+    } else {
+        format!("bitcoin:{}?{}", address, params.join("&"))
+    }
+}
+
+/// Percent-encode every character of the string but the unreserved ones (RFC 3986).
+fn percent_encode(s: &str) -> String {
+    s.bytes().fold(String::new(), |mut res, b| {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            res.push(b as char);
+        } else {
+            res.push_str(&format!("%{:02X}", b));
+        }
+        res
+    })
+}
+
 #[derive(Default)]
 pub struct ReceivePanel {
     address: Option<Address>,
+    amount: form::Value<String>,
+    label: form::Value<String>,
+    uri: String,
     qr_code: Option<qr_code::State>,
     warning: Option<Error>,
 }
 
+impl ReceivePanel {
+    /// Update the payment URI and its QR code from the address and the user inputs.
+    fn update_uri(&mut self) {
+        if let Some(address) = &self.address {
+            let amount = if self.amount.value.trim().is_empty() {
+                self.amount.valid = true;
+                None
+            } else {
+                let amount = Amount::from_str_in(self.amount.value.trim(), Denomination::Bitcoin)
+                    .ok()
+                    .filter(|a| a.to_sat() > 0);
+                self.amount.valid = amount.is_some();
+                amount
+            };
+            self.uri = bip21_uri(address, amount, Some(self.label.value.trim()));
+            match qr_code::State::new(&self.uri) {
+                Ok(qr) => self.qr_code = Some(qr),
+                Err(e) => {
+                    self.warning = Some(Error::Unexpected(format!(
+                        "Failed to create QR code: {}",
+                        e
+                    )))
+                }
+            }
+        }
+    }
+}
+
 impl State for ReceivePanel {
     fn view<'a>(&'a self, cache: &'a Cache) -> Element<'a, view::Message> {
         if let Some(address) = &self.address {
@@ -266,7 +317,13 @@ impl State for ReceivePanel {
                 &Menu::Receive,
                 cache,
                 self.warning.as_ref(),
-                view::receive::receive(address, self.qr_code.as_ref().unwrap()),
+                view::receive::receive(
+                    address,
+                    &self.uri,
+                    &self.amount,
+                    &self.label,
+                    self.qr_code.as_ref(),
+                ),
             )
         } else {
             view::dashboard(&Menu::Receive, cache, self.warning.as_ref(), Column::new())
@@ -278,15 +335,24 @@ impl State for ReceivePanel {
         _cache: &Cache,
         message: Message,
     ) -> Command<Message> {
-        if let Message::ReceiveAddress(res) = message {
-            match res {
+        match message {
+            Message::ReceiveAddress(res) => match res {
                 Ok(address) => {
                     self.warning = None;
-                    self.qr_code = Some(qr_code::State::new(&address.to_qr_uri()).unwrap());
                     self.address = Some(address);
+                    self.update_uri();
                 }
                 Err(e) => self.warning = Some(e),
+            },
+            Message::View(view::Message::Receive(view::ReceiveMessage::AmountEdited(amount))) => {
+                self.amount.value = amount;
+                self.update_uri();
+            }
+            Message::View(view::Message::Receive(view::ReceiveMessage::LabelEdited(label))) => {
+                self.label.value = label;
+                self.update_uri();
             }
+            _ => {}
         };
         Command::none()
     }
@@ -337,6 +403,31 @@ mod tests {
     use serde_json::json;
     use std::str::FromStr;
 
+    #[test]
+    fn bip21_uri_assembly() {
+        let address = Address::from_str("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4").unwrap();
+        assert_eq!(
+            bip21_uri(&address, None, None),
+            "BITCOIN:BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4"
+        );
+        assert_eq!(
+            bip21_uri(&address, None, Some("")),
+            "BITCOIN:BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4"
+        );
+        assert_eq!(
+            bip21_uri(&address, Some(Amount::from_sat(2_050_000)), None),
+            format!("bitcoin:{}?amount=0.0205", address)
+        );
+        assert_eq!(
+            bip21_uri(&address, None, Some("Lunch with Bob & Alice")),
+            format!("bitcoin:{}?label=Lunch%20with%20Bob%20%26%20Alice", address)
+        );
+        assert_eq!(
+            bip21_uri(&address, Some(Amount::from_sat(1)), Some("café")),
+            format!("bitcoin:{}?amount=0.00000001&label=caf%C3%A9", address)
+        );
+    }
+
     #[tokio::test]
     async fn test_receive_panel() {
         let addr =
@@ -345,7 +436,10 @@ mod tests {
         let daemon = Daemon::new(vec![(
             Some(json!({"method": "getnewaddress", "params": Option::<Request>::None})),
             Ok(json!(GetAddressResult {
-                address: addr.clone()
+                address: addr.clone(),
+                derivation_index: 0,
+                derivation_paths: Vec::new(),
+                is_reused: false,
             })),
         )]);
 
@@ -17,11 +17,14 @@ use crate::{
         model::{remaining_sequence, Coin},
         Daemon,
     },
-    hw::{list_hardware_wallets, HardwareWallet},
+    hw::{list_hardware_wallets, registration_tokens, HardwareWallet},
     ui::component::form,
 };
 
-use liana::miniscript::bitcoin::{util::psbt::Psbt, Address, Amount, Network};
+use liana::{
+    descriptors::address_is_for_network,
+    miniscript::bitcoin::{util::psbt::Psbt, Address, Amount},
+};
 
 pub struct RecoveryPanel {
     config: Config,
@@ -148,11 +151,7 @@ impl State for RecoveryPanel {
                 )) => {
                     self.recipient.value = address;
                     if let Ok(address) = Address::from_str(&self.recipient.value) {
-                        if cache.network == Network::Bitcoin {
-                            self.recipient.valid = address.network == Network::Bitcoin;
-                        } else {
-                            self.recipient.valid = address.network == Network::Testnet;
-                        }
+                        self.recipient.valid = address_is_for_network(&address, cache.network);
                     } else {
                         self.recipient.valid = false;
                     }
@@ -196,7 +195,7 @@ impl State for RecoveryPanel {
     fn load(&self, daemon: Arc<dyn Daemon + Sync + Send>) -> Command<Message> {
         let config = self.config.clone();
         let desc = daemon.config().main_descriptor.to_string();
-        let daemon = daemon.clone();
+        let daemon_hws = daemon.clone();
         Command::batch(vec![
             Command::perform(
                 async move {
@@ -208,15 +207,21 @@ impl State for RecoveryPanel {
                 Message::Coins,
             ),
             Command::perform(
-                list_hws(config, "Liana".to_string(), desc),
+                list_hws(daemon_hws, config, "Liana".to_string(), desc),
                 Message::ConnectedHardwareWallets,
             ),
         ])
     }
 }
 
-async fn list_hws(config: Config, wallet_name: String, descriptor: String) -> Vec<HardwareWallet> {
-    list_hardware_wallets(&config.hardware_wallets, Some((&wallet_name, &descriptor))).await
+async fn list_hws(
+    daemon: Arc<dyn Daemon + Sync + Send>,
+    config: Config,
+    wallet_name: String,
+    descriptor: String,
+) -> Vec<HardwareWallet> {
+    let tokens = registration_tokens(&*daemon, &config.hardware_wallets);
+    list_hardware_wallets(&tokens, Some((&wallet_name, &descriptor))).await
 }
 
 async fn send_funds(
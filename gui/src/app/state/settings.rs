@@ -211,10 +211,19 @@ impl Setting for BitcoindSettings {
 
                 if self.addr.valid & self.cookie_path.valid {
                     let mut daemon_config = daemon.config().clone();
-                    daemon_config.bitcoind_config = Some(liana::config::BitcoindConfig {
-                        cookie_path: new_path.unwrap(),
-                        addr: new_addr.unwrap(),
-                    });
+                    match daemon_config.bitcoind_config.as_mut() {
+                        Some(bitcoind_config) => {
+                            bitcoind_config.cookie_path = new_path.unwrap();
+                            bitcoind_config.addr = new_addr.unwrap();
+                        }
+                        None => {
+                            daemon_config.bitcoind_config =
+                                Some(liana::config::BitcoindConfig::new(
+                                    new_path.unwrap(),
+                                    new_addr.unwrap(),
+                                ));
+                        }
+                    }
                     self.processing = true;
                     return Command::perform(async move { daemon_config }, |cfg| {
                         Message::LoadDaemonConfig(Box::new(cfg))
@@ -1,7 +1,12 @@
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
 
-use iced::{Command, Element};
-use liana::miniscript::bitcoin::util::{bip32::Fingerprint, psbt::Psbt};
+use iced::{time, widget::qr_code, Command, Element, Subscription};
+use liana::miniscript::bitcoin::{
+    util::{bip32::Fingerprint, psbt::Psbt},
+    Txid,
+};
 
 use crate::{
     app::{
@@ -11,9 +16,15 @@ use crate::{
         model::{SpendStatus, SpendTx},
         Daemon,
     },
-    hw::{list_hardware_wallets, HardwareWallet},
+    hw::{list_hardware_wallets, registration_tokens, HardwareWallet},
+    ui::component::form,
 };
 
+/// Maximum number of characters of the PSBT displayed in a single QR code frame.
+const QR_FRAME_MAX_SIZE: usize = 800;
+/// Time during which a frame of an animated QR code is displayed.
+const QR_FRAME_INTERVAL: Duration = Duration::from_millis(500);
+
 trait Action {
     fn warning(&self) -> Option<&Error> {
         None
@@ -33,6 +44,9 @@ trait Action {
     ) -> Command<Message> {
         Command::none()
     }
+    fn subscription(&self) -> Subscription<Message> {
+        Subscription::none()
+    }
     fn view(&self) -> Element<view::Message>;
 }
 
@@ -73,6 +87,10 @@ impl SpendTxState {
                     self.action = Box::new(DeleteAction::default());
                     self.action.load(daemon.clone())
                 }
+                view::SpendTxMessage::Export => {
+                    self.action = Box::new(ExportAction::new(&self.tx.psbt));
+                    self.action.load(daemon.clone())
+                }
                 _ => self
                     .action
                     .update(daemon.clone(), cache, message, &mut self.tx),
@@ -90,6 +108,10 @@ impl SpendTxState {
         }
     }
 
+    pub fn subscription(&self) -> Subscription<Message> {
+        self.action.subscription()
+    }
+
     pub fn view<'a>(&'a self, cache: &'a Cache) -> Element<'a, view::Message> {
         detail::spend_view(
             self.action.warning(),
@@ -289,7 +311,7 @@ impl Action for SignAction {
         let config = self.config.clone();
         let desc = daemon.config().main_descriptor.to_string();
         Command::perform(
-            list_hws(config, "Liana".to_string(), desc),
+            list_hws(daemon, config, "Liana".to_string(), desc),
             Message::ConnectedHardwareWallets,
         )
     }
@@ -303,33 +325,32 @@ impl Action for SignAction {
         match message {
             Message::View(view::Message::Spend(view::SpendTxMessage::SelectHardwareWallet(i))) => {
                 if let Some(hw) = self.hws.get(i) {
-                    let device = hw.device.clone();
+                    let fingerprint = hw.fingerprint;
                     self.chosen_hw = Some(i);
                     self.processing = true;
-                    let psbt = tx.psbt.clone();
                     return Command::perform(
-                        sign_psbt(device, hw.fingerprint, psbt),
-                        Message::Signed,
+                        sign_spend(
+                            daemon,
+                            self.hws.clone(),
+                            tx.psbt.unsigned_tx.txid(),
+                            fingerprint,
+                        ),
+                        move |res| Message::Signed(res.map(|psbt| (psbt, fingerprint))),
                     );
                 }
             }
-            Message::Signed(res) => match res {
-                Err(e) => self.error = Some(e),
-                Ok((psbt, fingerprint)) => {
-                    self.error = None;
-                    self.signed.push(fingerprint);
-                    let daemon = daemon.clone();
-                    tx.psbt = psbt.clone();
-                    return Command::perform(
-                        async move { daemon.update_spend_tx(&psbt).map_err(|e| e.into()) },
-                        Message::Updated,
-                    );
+            Message::Signed(res) => {
+                self.processing = false;
+                match res {
+                    Err(e) => self.error = Some(e),
+                    Ok((psbt, fingerprint)) => {
+                        self.error = None;
+                        self.signed.push(fingerprint);
+                        self.updated = true;
+                        tx.psbt = psbt;
+                    }
                 }
-            },
-            Message::Updated(res) => match res {
-                Ok(()) => self.updated = true,
-                Err(e) => self.error = Some(e),
-            },
+            }
             // We add the new hws without dropping the reference of the previous ones.
             Message::ConnectedHardwareWallets(hws) => {
                 for h in hws {
@@ -350,17 +371,189 @@ impl Action for SignAction {
     }
 }
 
-async fn list_hws(config: Config, wallet_name: String, descriptor: String) -> Vec<HardwareWallet> {
-    list_hardware_wallets(&config.hardware_wallets, Some((&wallet_name, &descriptor))).await
+async fn list_hws(
+    daemon: Arc<dyn Daemon + Sync + Send>,
+    config: Config,
+    wallet_name: String,
+    descriptor: String,
+) -> Vec<HardwareWallet> {
+    let tokens = registration_tokens(&*daemon, &config.hardware_wallets);
+    list_hardware_wallets(&tokens, Some((&wallet_name, &descriptor))).await
 }
 
-async fn sign_psbt(
-    hw: std::sync::Arc<dyn async_hwi::HWI + Send + Sync>,
+/// Sign the stored spend transaction with the connected hardware wallet of the given
+/// fingerprint, and store the signed PSBT. The daemon merges the new signatures with the ones
+/// it already has, the resulting PSBT is returned.
+pub async fn sign_spend(
+    daemon: Arc<dyn Daemon + Sync + Send>,
+    hws: Vec<HardwareWallet>,
+    txid: Txid,
     fingerprint: Fingerprint,
-    mut psbt: Psbt,
-) -> Result<(Psbt, Fingerprint), Error> {
-    hw.sign_tx(&mut psbt).await.map_err(Error::from)?;
-    Ok((psbt, fingerprint))
+) -> Result<Psbt, Error> {
+    let hw = hws
+        .into_iter()
+        .find(|hw| hw.fingerprint == fingerprint)
+        .ok_or(Error::HardwareWalletNotConnected(fingerprint))?;
+    sign_stored_spend(daemon, txid, |mut psbt| async move {
+        hw.device.sign_tx(&mut psbt).await.map_err(Error::from)?;
+        Ok(psbt)
+    })
+    .await
+}
+
+async fn sign_stored_spend<F, S>(
+    daemon: Arc<dyn Daemon + Sync + Send>,
+    txid: Txid,
+    signer: S,
+) -> Result<Psbt, Error>
+where
+    F: Future<Output = Result<Psbt, Error>>,
+    S: FnOnce(Psbt) -> F,
+{
+    let stored_psbt = |daemon: &Arc<dyn Daemon + Sync + Send>| -> Result<Psbt, Error> {
+        daemon
+            .list_spend_txs()?
+            .spend_txs
+            .into_iter()
+            .map(|entry| entry.psbt)
+            .find(|psbt| psbt.unsigned_tx.txid() == txid)
+            .ok_or_else(|| Error::Unexpected(format!("Unknown spend transaction {}", txid)))
+    };
+
+    let psbt = signer(stored_psbt(&daemon)?).await?;
+    daemon.update_spend_tx(&psbt)?;
+    stored_psbt(&daemon)
+}
+
+/// Split the data in frames small enough to each fit in a QR code.
+/// If the data does not fit in a single frame, each frame is prefixed by its position in the
+/// sequence of frames, as in "p1of3 ", so it can be reassembled by the scanner.
+fn split_qr_frames(data: &str, max_size: usize) -> Vec<String> {
+    if data.len() <= max_size {
+        return vec![data.to_string()];
+    }
+
+    // The data is ascii (base64), so slicing it by bytes is safe.
+    let chunks: Vec<&str> = data
+        .as_bytes()
+        .chunks(max_size)
+        .map(|c| std::str::from_utf8(c).expect("Chunks of ascii data"))
+        .collect();
+    let total = chunks.len();
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| format!("p{}of{} {}", i + 1, total, chunk))
+        .collect()
+}
+
+pub struct ExportAction {
+    psbt: String,
+    qr_codes: Vec<qr_code::State>,
+    current_frame: usize,
+    path: form::Value<String>,
+    exported: bool,
+    error: Option<Error>,
+}
+
+impl ExportAction {
+    pub fn new(psbt: &Psbt) -> Self {
+        let psbt = psbt.to_string();
+        let mut error = None;
+        let qr_codes = split_qr_frames(&psbt, QR_FRAME_MAX_SIZE)
+            .iter()
+            .filter_map(|frame| match qr_code::State::new(frame) {
+                Ok(qr) => Some(qr),
+                Err(e) => {
+                    error = Some(Error::Unexpected(format!(
+                        "Failed to create QR code: {}",
+                        e
+                    )));
+                    None
+                }
+            })
+            .collect();
+        Self {
+            psbt,
+            // Do not display a partial sequence of frames.
+            qr_codes: if error.is_some() {
+                Vec::new()
+            } else {
+                qr_codes
+            },
+            current_frame: 0,
+            path: form::Value::default(),
+            exported: false,
+            error,
+        }
+    }
+}
+
+impl Action for ExportAction {
+    fn warning(&self) -> Option<&Error> {
+        self.error.as_ref()
+    }
+
+    fn update(
+        &mut self,
+        _daemon: Arc<dyn Daemon + Sync + Send>,
+        _cache: &Cache,
+        message: Message,
+        _tx: &mut SpendTx,
+    ) -> Command<Message> {
+        match message {
+            Message::View(view::Message::Spend(view::SpendTxMessage::ExportPathEdited(path))) => {
+                self.path.value = path;
+                self.path.valid = true;
+                self.exported = false;
+            }
+            Message::View(view::Message::Spend(view::SpendTxMessage::NextQrFrame)) => {
+                if !self.qr_codes.is_empty() {
+                    self.current_frame = (self.current_frame + 1) % self.qr_codes.len();
+                }
+            }
+            Message::View(view::Message::Spend(view::SpendTxMessage::Confirm)) => {
+                let path = self.path.value.trim();
+                if path.is_empty() {
+                    self.path.valid = false;
+                    return Command::none();
+                }
+                match std::fs::write(path, &self.psbt) {
+                    Ok(()) => {
+                        self.error = None;
+                        self.exported = true;
+                    }
+                    Err(e) => {
+                        self.error = Some(Error::Unexpected(format!(
+                            "Failed to write PSBT to '{}': {}",
+                            path, e
+                        )))
+                    }
+                }
+            }
+            _ => {}
+        }
+        Command::none()
+    }
+
+    fn subscription(&self) -> Subscription<Message> {
+        if self.qr_codes.len() > 1 {
+            time::every(QR_FRAME_INTERVAL)
+                .map(|_| Message::View(view::Message::Spend(view::SpendTxMessage::NextQrFrame)))
+        } else {
+            Subscription::none()
+        }
+    }
+
+    fn view(&self) -> Element<view::Message> {
+        detail::export_action(
+            &self.path,
+            self.qr_codes.get(self.current_frame),
+            self.current_frame,
+            self.qr_codes.len(),
+            self.exported,
+        )
+    }
 }
 
 #[derive(Default)]
@@ -371,3 +564,132 @@ impl Action for NoAction {
         iced::widget::Column::new().into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        daemon::{
+            client::Lianad,
+            model::{ListSpendEntry, ListSpendResult},
+        },
+        utils::mock::{fake_daemon_config, Daemon},
+    };
+    use liana::miniscript::bitcoin::{
+        consensus,
+        secp256k1::{self, Secp256k1},
+        EcdsaSig, EcdsaSighashType, PackedLockTime, PublicKey, Script, Transaction, TxIn, TxOut,
+    };
+    use serde_json::json;
+
+    fn join_qr_frames(frames: &[String]) -> String {
+        if frames.len() == 1 {
+            return frames[0].clone();
+        }
+        frames
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| {
+                let prefix = format!("p{}of{} ", i + 1, frames.len());
+                frame.strip_prefix(&prefix).expect("Frame is in order")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn qr_frames_split() {
+        // Data fitting in a single frame is not prefixed.
+        let frames = split_qr_frames("cHNidP8B", 8);
+        assert_eq!(frames, vec!["cHNidP8B".to_string()]);
+
+        let data = "a".repeat(2500);
+        let frames = split_qr_frames(&data, 1000);
+        assert_eq!(frames.len(), 3);
+        assert!(frames[0].starts_with("p1of3 "));
+        assert!(frames[2].starts_with("p3of3 "));
+        assert_eq!(frames[2].len(), "p3of3 ".len() + 500);
+        assert_eq!(join_qr_frames(&frames), data);
+
+        // An exact multiple of the frame size does not produce an empty frame.
+        let data = "b".repeat(2000);
+        let frames = split_qr_frames(&data, 1000);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(join_qr_frames(&frames), data);
+    }
+
+    fn list_spend_response(psbt: &Psbt) -> serde_json::Value {
+        json!(ListSpendResult {
+            spend_txs: vec![ListSpendEntry {
+                psbt: psbt.clone(),
+                conflicted: false,
+                updated_at: 0,
+                info: Default::default(),
+            }],
+        })
+    }
+
+    #[tokio::test]
+    async fn sign_spend_stores_signed_psbt() {
+        let psbt = Psbt::from_unsigned_tx(Transaction {
+            version: 2,
+            lock_time: PackedLockTime(0),
+            input: vec![TxIn::default()],
+            output: vec![TxOut {
+                value: 10_000,
+                script_pubkey: Script::new(),
+            }],
+        })
+        .unwrap();
+        let txid = psbt.unsigned_tx.txid();
+
+        // The partial signature our stub signer adds to the PSBT.
+        let secp = Secp256k1::signing_only();
+        let key = secp256k1::SecretKey::from_slice(&[1; 32]).unwrap();
+        let pubkey = PublicKey::new(secp256k1::PublicKey::from_secret_key(&secp, &key));
+        let sig = EcdsaSig {
+            sig: secp.sign_ecdsa(&secp256k1::Message::from_slice(&[2; 32]).unwrap(), &key),
+            hash_ty: EcdsaSighashType::All,
+        };
+        let mut signed_psbt = psbt.clone();
+        signed_psbt.inputs[0].partial_sigs.insert(pubkey, sig);
+
+        let daemon = Daemon::new(vec![
+            (
+                Some(json!({"method": "listspendtxs", "params": Option::<()>::None})),
+                Ok(list_spend_response(&psbt)),
+            ),
+            (
+                Some(json!({
+                    "method": "updatespend",
+                    "params": vec![base64::encode(consensus::serialize(&signed_psbt))],
+                })),
+                Ok(json!({})),
+            ),
+            // The merging of the signatures by the daemon is tested in the daemon, we only check
+            // the stored PSBT is returned.
+            (
+                Some(json!({"method": "listspendtxs", "params": Option::<()>::None})),
+                Ok(list_spend_response(&signed_psbt)),
+            ),
+        ]);
+        let daemon: Arc<dyn crate::daemon::Daemon + Sync + Send> =
+            Arc::new(Lianad::new(daemon.run(), fake_daemon_config()));
+
+        let res = sign_stored_spend(daemon, txid, |mut psbt: Psbt| async move {
+            psbt.inputs[0].partial_sigs.insert(pubkey, sig);
+            Ok(psbt)
+        })
+        .await
+        .unwrap();
+        assert_eq!(res.inputs[0].partial_sigs.get(&pubkey), Some(&sig));
+
+        // Without a matching connected device, nothing is asked to the daemon.
+        let daemon: Arc<dyn crate::daemon::Daemon + Sync + Send> =
+            Arc::new(Lianad::new(Daemon::new(vec![]).run(), fake_daemon_config()));
+        let fingerprint = Fingerprint::from(&[1, 2, 3, 4][..]);
+        match sign_spend(daemon, Vec::new(), txid, fingerprint).await {
+            Err(Error::HardwareWalletNotConnected(fg)) => assert_eq!(fg, fingerprint),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use iced::{Command, Element};
+use liana::miniscript::bitcoin::{consensus, util::psbt::Psbt};
+
+use crate::{
+    app::{cache::Cache, error::Error, message::Message, view},
+    daemon::Daemon,
+    ui::component::form,
+};
+
+/// Parse a PSBT from user input.
+/// The input is either a base64 encoded PSBT or the path to a file containing one, encoded in
+/// base64 or as raw bytes.
+fn psbt_from_input(input: &str) -> Result<Psbt, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err("Please enter a PSBT or the path to a PSBT file".to_string());
+    }
+
+    let path = Path::new(input);
+    if path.is_file() {
+        let content = std::fs::read(path)
+            .map_err(|e| format!("Failed to read file '{}': {}", path.display(), e))?;
+        if let Ok(psbt) = consensus::deserialize(&content) {
+            return Ok(psbt);
+        }
+        return match std::str::from_utf8(&content) {
+            Ok(s) => psbt_from_base64(s.trim()),
+            Err(_) => Err("The file does not contain a valid PSBT".to_string()),
+        };
+    }
+
+    psbt_from_base64(input)
+}
+
+fn psbt_from_base64(s: &str) -> Result<Psbt, String> {
+    let bytes = base64::decode(s).map_err(|e| format!("Invalid base64 encoding: {}", e))?;
+    consensus::deserialize(&bytes).map_err(|e| format!("Invalid PSBT: {}", e))
+}
+
+#[derive(Default)]
+pub struct ImportPsbtState {
+    psbt: form::Value<String>,
+    error: Option<String>,
+    warning: Option<Error>,
+    processing: bool,
+    imported: bool,
+}
+
+impl ImportPsbtState {
+    pub fn imported(&self) -> bool {
+        self.imported
+    }
+
+    pub fn update(
+        &mut self,
+        daemon: Arc<dyn Daemon + Sync + Send>,
+        _cache: &Cache,
+        message: Message,
+    ) -> Command<Message> {
+        match message {
+            Message::View(view::Message::ImportSpend(view::ImportSpendMessage::PsbtEdited(s))) => {
+                self.psbt.value = s;
+                self.psbt.valid = true;
+                self.error = None;
+            }
+            Message::View(view::Message::ImportSpend(view::ImportSpendMessage::Confirm)) => {
+                match psbt_from_input(&self.psbt.value) {
+                    Ok(psbt) => {
+                        self.processing = true;
+                        self.warning = None;
+                        return Command::perform(
+                            async move { daemon.update_spend_tx(&psbt).map_err(|e| e.into()) },
+                            Message::Updated,
+                        );
+                    }
+                    Err(e) => {
+                        self.psbt.valid = false;
+                        self.error = Some(e);
+                    }
+                }
+            }
+            Message::Updated(res) => {
+                self.processing = false;
+                match res {
+                    Ok(()) => self.imported = true,
+                    Err(e) => self.warning = Some(e),
+                }
+            }
+            _ => {}
+        }
+        Command::none()
+    }
+
+    pub fn view(&self) -> Element<view::Message> {
+        view::spend::import_psbt_view(
+            &self.psbt,
+            self.error.as_deref(),
+            self.warning.as_ref(),
+            self.processing,
+            self.imported,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use liana::miniscript::bitcoin::{PackedLockTime, Script, Transaction, TxIn, TxOut};
+
+    fn dummy_psbt() -> Psbt {
+        Psbt::from_unsigned_tx(Transaction {
+            version: 2,
+            lock_time: PackedLockTime(0),
+            input: vec![TxIn::default()],
+            output: vec![TxOut {
+                value: 10_000,
+                script_pubkey: Script::new(),
+            }],
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn import_psbt_from_base64() {
+        let psbt = dummy_psbt();
+        let encoded = base64::encode(consensus::serialize(&psbt));
+        assert_eq!(psbt_from_input(&encoded).unwrap(), psbt);
+        // Surrounding whitespace, as often found in pasted text, is ignored.
+        assert_eq!(psbt_from_input(&format!(" {}\n", encoded)).unwrap(), psbt);
+
+        assert!(psbt_from_input("").is_err());
+        assert!(psbt_from_input("not a psbt")
+            .unwrap_err()
+            .starts_with("Invalid base64 encoding"));
+        // Valid base64 but not a PSBT.
+        assert!(psbt_from_input(&base64::encode("garbage"))
+            .unwrap_err()
+            .starts_with("Invalid PSBT"));
+    }
+
+    #[test]
+    fn import_psbt_from_file() {
+        let psbt = dummy_psbt();
+        let dir =
+            std::env::temp_dir().join(format!("liana-gui-import-psbt-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let raw_path = dir.join("raw.psbt");
+        std::fs::write(&raw_path, consensus::serialize(&psbt)).unwrap();
+        assert_eq!(psbt_from_input(raw_path.to_str().unwrap()).unwrap(), psbt);
+
+        let base64_path = dir.join("base64.psbt");
+        std::fs::write(&base64_path, base64::encode(consensus::serialize(&psbt))).unwrap();
+        assert_eq!(
+            psbt_from_input(base64_path.to_str().unwrap()).unwrap(),
+            psbt
+        );
+
+        let garbage_path = dir.join("garbage.psbt");
+        std::fs::write(&garbage_path, [0xff, 0xfe, 0x00]).unwrap();
+        assert!(psbt_from_input(garbage_path.to_str().unwrap()).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
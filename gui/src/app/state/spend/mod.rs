@@ -1,8 +1,9 @@
 mod detail;
+mod import;
 mod step;
 use std::sync::Arc;
 
-use iced::{Command, Element};
+use iced::{Command, Element, Subscription};
 
 use super::{redirect, State};
 use crate::{
@@ -16,6 +17,7 @@ use crate::{
 pub struct SpendPanel {
     config: Config,
     selected_tx: Option<detail::SpendTxState>,
+    import_psbt: Option<import::ImportPsbtState>,
     spend_txs: Vec<SpendTx>,
     warning: Option<Error>,
 }
@@ -27,6 +29,7 @@ impl SpendPanel {
             spend_txs: spend_txs.to_vec(),
             warning: None,
             selected_tx: None,
+            import_psbt: None,
         }
     }
 }
@@ -35,6 +38,8 @@ impl State for SpendPanel {
     fn view<'a>(&'a self, cache: &'a Cache) -> Element<'a, view::Message> {
         if let Some(tx) = &self.selected_tx {
             tx.view(cache)
+        } else if let Some(import_psbt) = &self.import_psbt {
+            import_psbt.view()
         } else {
             view::dashboard(
                 &Menu::Spend,
@@ -64,6 +69,16 @@ impl State for SpendPanel {
                     self.selected_tx = None;
                     return self.load(daemon);
                 }
+                if let Some(import_psbt) = self.import_psbt.take() {
+                    if import_psbt.imported() {
+                        return self.load(daemon);
+                    }
+                }
+            }
+            Message::View(view::Message::ImportSpend(view::ImportSpendMessage::Import)) => {
+                if self.selected_tx.is_none() {
+                    self.import_psbt = Some(import::ImportPsbtState::default());
+                }
             }
             Message::View(view::Message::Select(i)) => {
                 if let Some(tx) = self.spend_txs.get(i) {
@@ -77,11 +92,22 @@ impl State for SpendPanel {
                 if let Some(tx) = &mut self.selected_tx {
                     return tx.update(daemon, cache, message);
                 }
+                if let Some(import_psbt) = &mut self.import_psbt {
+                    return import_psbt.update(daemon, cache, message);
+                }
             }
         }
         Command::none()
     }
 
+    fn subscription(&self) -> Subscription<Message> {
+        if let Some(tx) = &self.selected_tx {
+            tx.subscription()
+        } else {
+            Subscription::none()
+        }
+    }
+
     fn load(&self, daemon: Arc<dyn Daemon + Sync + Send>) -> Command<Message> {
         let daemon = daemon.clone();
         Command::perform(
@@ -160,6 +186,10 @@ impl State for CreateSpendPanel {
         Command::none()
     }
 
+    fn subscription(&self) -> Subscription<Message> {
+        self.steps.get(self.current).unwrap().subscription()
+    }
+
     fn load(&self, daemon: Arc<dyn Daemon + Sync + Send>) -> Command<Message> {
         let daemon = daemon.clone();
         Command::perform(
@@ -2,9 +2,10 @@ use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
 
-use iced::{Command, Element};
+use iced::{Command, Element, Subscription};
 use liana::{
     config::Config as DaemonConfig,
+    descriptors::address_is_for_network,
     miniscript::bitcoin::{
         self, util::psbt::Psbt, Address, Amount, Denomination, Network, OutPoint,
     },
@@ -39,6 +40,9 @@ pub trait Step {
     ) -> Command<Message>;
     fn apply(&self, _draft: &mut TransactionDraft) {}
     fn load(&mut self, _draft: &TransactionDraft) {}
+    fn subscription(&self) -> Subscription<Message> {
+        Subscription::none()
+    }
 }
 
 pub struct ChooseRecipients {
@@ -176,9 +180,7 @@ impl Recipient {
             view::CreateSpendMessage::RecipientEdited(_, "address", address) => {
                 self.address.value = address;
                 if let Ok(address) = Address::from_str(&self.address.value) {
-                    self.address.valid = address.network == network
-                        || (address.network == bitcoin::Network::Testnet
-                            && network == bitcoin::Network::Signet);
+                    self.address.valid = address_is_for_network(&address, network);
                     if !self.amount.value.is_empty() {
                         self.amount.valid = self.amount().is_ok();
                     }
@@ -215,6 +217,8 @@ pub struct ChooseCoins {
 
     amount_left_to_select: Option<Amount>,
     feerate: form::Value<String>,
+    /// The preset the feerate was estimated for, if it wasn't set by the user.
+    feerate_preset: Option<view::FeeratePreset>,
     generated: Option<Psbt>,
     warning: Option<Error>,
 }
@@ -247,12 +251,23 @@ impl ChooseCoins {
             coins,
             recipients: Vec::new(),
             feerate: form::Value::default(),
+            feerate_preset: None,
             generated: None,
             warning: None,
             amount_left_to_select: None,
         }
     }
 
+    fn set_feerate(&mut self, feerate: String, cfg: &DaemonConfig) {
+        self.feerate.valid = feerate.is_empty() || is_valid_feerate(&feerate);
+        self.feerate.value = feerate;
+        if self.feerate.valid {
+            self.amount_left_to_select(cfg);
+        } else {
+            self.amount_left_to_select = None;
+        }
+    }
+
     fn amount_left_to_select(&mut self, cfg: &DaemonConfig) {
         // We need the feerate in order to compute the required amount of BTC to
         // select. Return early if we don't to not do unnecessary computation.
@@ -339,20 +354,36 @@ impl Step for ChooseCoins {
             Message::View(view::Message::CreateSpend(view::CreateSpendMessage::FeerateEdited(
                 s,
             ))) => {
-                if s.parse::<u64>().is_ok() {
-                    self.feerate.value = s;
-                    self.feerate.valid = true;
-                    self.amount_left_to_select(daemon.config());
-                } else if s.is_empty() {
-                    self.feerate.value = "".to_string();
-                    self.feerate.valid = true;
-                    self.amount_left_to_select = None;
-                } else {
-                    self.feerate.valid = false;
-                    self.amount_left_to_select = None;
-                }
+                self.feerate_preset = None;
+                self.set_feerate(s, daemon.config());
                 self.warning = None;
             }
+            Message::View(view::Message::CreateSpend(
+                view::CreateSpendMessage::FeeratePresetSelected(preset),
+            )) => {
+                self.feerate_preset = Some(preset);
+                self.warning = None;
+                return Command::perform(
+                    async move {
+                        daemon
+                            .estimate_feerate(preset.target_blocks())
+                            .map_err(|e| e.into())
+                    },
+                    Message::FeerateEstimate,
+                );
+            }
+            Message::FeerateEstimate(res) => match res {
+                // Only use the estimate if the user didn't change their mind in the meantime.
+                Ok(feerate) => {
+                    if self.feerate_preset.is_some() {
+                        self.set_feerate(feerate.to_string(), daemon.config());
+                    }
+                }
+                Err(e) => {
+                    self.feerate_preset = None;
+                    self.warning = Some(e);
+                }
+            },
             Message::View(view::Message::CreateSpend(view::CreateSpendMessage::Generate)) => {
                 let inputs: Vec<OutPoint> = self
                     .coins
@@ -400,11 +431,17 @@ impl Step for ChooseCoins {
             &self.coins,
             self.amount_left_to_select.as_ref(),
             &self.feerate,
+            self.feerate_preset,
             self.warning.as_ref(),
         )
     }
 }
 
+/// Whether this is a valid feerate in sat/vb: a strictly positive integer.
+fn is_valid_feerate(feerate: &str) -> bool {
+    feerate.parse::<u64>().map(|f| f > 0).unwrap_or(false)
+}
+
 pub struct SaveSpend {
     config: Config,
     spend: Option<detail::SpendTxState>,
@@ -442,7 +479,42 @@ impl Step for SaveSpend {
         }
     }
 
+    fn subscription(&self) -> Subscription<Message> {
+        if let Some(spend) = &self.spend {
+            spend.subscription()
+        } else {
+            Subscription::none()
+        }
+    }
+
     fn view<'a>(&'a self, cache: &'a Cache) -> Element<'a, view::Message> {
         self.spend.as_ref().unwrap().view(cache)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feerate_presets() {
+        // The higher the feerate, the sooner we expect it to confirm.
+        let targets: Vec<u16> = view::FeeratePreset::ALL
+            .iter()
+            .map(|p| p.target_blocks())
+            .collect();
+        assert_eq!(targets, vec![2, 6, 144]);
+        assert!(targets.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn custom_feerate_validation() {
+        assert!(is_valid_feerate("1"));
+        assert!(is_valid_feerate("250"));
+        assert!(!is_valid_feerate("0"));
+        assert!(!is_valid_feerate(""));
+        assert!(!is_valid_feerate("-1"));
+        assert!(!is_valid_feerate("1.5"));
+        assert!(!is_valid_feerate("abc"));
+    }
+}
@@ -16,13 +16,41 @@ use liana::miniscript::bitcoin;
 
 use crate::{
     app::{cache::Cache, view::message::Message},
-    daemon::model::HistoryTransaction,
+    daemon::model::{Balance, HistoryTransaction},
 };
 
 pub const HISTORY_EVENT_PAGE_SIZE: u64 = 20;
 
+/// A part of the balance that cannot be spent right away.
+#[derive(Debug, PartialEq)]
+struct BalanceDetail {
+    label: &'static str,
+    amount: bitcoin::Amount,
+    color: iced::Color,
+}
+
+/// The parts of the balance displayed besides the confirmed amount, if any.
+fn balance_details(balance: &Balance) -> Vec<BalanceDetail> {
+    let mut details = Vec::new();
+    if balance.pending.to_sat() > 0 {
+        details.push(BalanceDetail {
+            label: "Pending",
+            amount: balance.pending,
+            color: color::WARNING,
+        });
+    }
+    if balance.frozen.to_sat() > 0 {
+        details.push(BalanceDetail {
+            label: "Frozen",
+            amount: balance.frozen,
+            color: color::DARK_GREY,
+        });
+    }
+    details
+}
+
 pub fn home_view<'a>(
-    balance: &'a bitcoin::Amount,
+    balance: &'a Balance,
     recovery_warning: Option<&(bitcoin::Amount, usize)>,
     recovery_alert: Option<&(bitcoin::Amount, usize)>,
     pending_events: &[HistoryTransaction],
@@ -30,7 +58,20 @@ pub fn home_view<'a>(
 ) -> Element<'a, Message> {
     Column::new()
         .push(Column::new().padding(40))
-        .push(text(format!("{} BTC", balance.to_btc())).bold().size(50))
+        .push(
+            text(format!("{} BTC", balance.confirmed.to_btc()))
+                .bold()
+                .size(50),
+        )
+        .push(balance_details(balance).into_iter().fold(
+            Row::new().spacing(20).align_items(Alignment::Center),
+            |row, detail| {
+                row.push(
+                    text(format!("{}: {} BTC", detail.label, detail.amount.to_btc()))
+                        .style(detail.color),
+                )
+            },
+        ))
         .push_maybe(recovery_warning.map(|(a, c)| {
             Row::new()
                 .spacing(15)
@@ -107,6 +148,8 @@ fn event_list_view<'a>(i: usize, event: &HistoryTransaction) -> Element<'a, Mess
                     Row::new()
                         .push(if event.is_external() {
                             badge::receive()
+                        } else if event.is_self_transfer() {
+                            badge::self_transfer()
                         } else {
                             badge::spend()
                         })
@@ -130,6 +173,15 @@ fn event_list_view<'a>(i: usize, event: &HistoryTransaction) -> Element<'a, Mess
                             text({
                                 if event.is_external() {
                                     format!("+ {:.8}", event.incoming_amount.to_btc())
+                                } else if event.is_self_transfer() {
+                                    // Only the fee actually left the wallet.
+                                    format!(
+                                        "- {:.8}",
+                                        event
+                                            .fee_amount
+                                            .unwrap_or(bitcoin::Amount::from_sat(0))
+                                            .to_btc()
+                                    )
                                 } else {
                                     format!("- {:.8}", event.outgoing_amount.to_btc())
                                 }
@@ -158,6 +210,8 @@ pub fn event_view<'a>(cache: &Cache, event: &'a HistoryTransaction) -> Element<'
             Row::new()
                 .push(if event.is_external() {
                     badge::receive()
+                } else if event.is_self_transfer() {
+                    badge::self_transfer()
                 } else {
                     badge::spend()
                 })
@@ -168,6 +222,14 @@ pub fn event_view<'a>(cache: &Cache, event: &'a HistoryTransaction) -> Element<'
             text({
                 if event.is_external() {
                     format!("+ {} BTC", event.incoming_amount.to_btc())
+                } else if event.is_self_transfer() {
+                    format!(
+                        "- {} BTC",
+                        event
+                            .fee_amount
+                            .unwrap_or(bitcoin::Amount::from_sat(0))
+                            .to_btc()
+                    )
                 } else {
                     format!("- {} BTC", event.outgoing_amount.to_btc())
                 }
@@ -229,3 +291,45 @@ pub fn event_view<'a>(cache: &Cache, event: &'a HistoryTransaction) -> Element<'
         .max_width(750)
         .into()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn balance_details_labels() {
+        let balance = Balance {
+            confirmed: bitcoin::Amount::from_sat(100_000),
+            pending: bitcoin::Amount::ZERO,
+            frozen: bitcoin::Amount::ZERO,
+        };
+        // A fully confirmed balance has nothing to detail.
+        assert!(balance_details(&balance).is_empty());
+
+        let balance = Balance {
+            confirmed: bitcoin::Amount::from_sat(100_000),
+            pending: bitcoin::Amount::from_sat(20_000),
+            frozen: bitcoin::Amount::from_sat(3_000),
+        };
+        let details = balance_details(&balance);
+        let labels: Vec<(&str, u64)> = details
+            .iter()
+            .map(|d| (d.label, d.amount.to_sat()))
+            .collect();
+        assert_eq!(labels, vec![("Pending", 20_000), ("Frozen", 3_000)]);
+        assert_eq!(details[0].color, color::WARNING);
+
+        let balance = Balance {
+            confirmed: bitcoin::Amount::ZERO,
+            pending: bitcoin::Amount::ZERO,
+            frozen: bitcoin::Amount::from_sat(3_000),
+        };
+        assert_eq!(
+            balance_details(&balance)
+                .iter()
+                .map(|d| d.label)
+                .collect::<Vec<_>>(),
+            vec!["Frozen"]
+        );
+    }
+}
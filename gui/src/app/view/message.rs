@@ -10,6 +10,8 @@ pub enum Message {
     Settings(usize, SettingsMessage),
     CreateSpend(CreateSpendMessage),
     Spend(SpendTxMessage),
+    ImportSpend(ImportSpendMessage),
+    Receive(ReceiveMessage),
     Next,
     Previous,
 }
@@ -21,9 +23,39 @@ pub enum CreateSpendMessage {
     SelectCoin(usize),
     RecipientEdited(usize, &'static str, String),
     FeerateEdited(String),
+    FeeratePresetSelected(FeeratePreset),
     Generate,
 }
 
+/// A feerate to be estimated by the node for a given confirmation target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeeratePreset {
+    High,
+    Medium,
+    Low,
+}
+
+impl FeeratePreset {
+    pub const ALL: [FeeratePreset; 3] = [Self::High, Self::Medium, Self::Low];
+
+    /// The number of blocks within which a transaction paying this feerate should confirm.
+    pub fn target_blocks(&self) -> u16 {
+        match self {
+            Self::High => 2,
+            Self::Medium => 6,
+            Self::Low => 144,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::High => "High",
+            Self::Medium => "Medium",
+            Self::Low => "Low",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum SpendTxMessage {
     Delete,
@@ -31,6 +63,22 @@ pub enum SpendTxMessage {
     Cancel,
     SelectHardwareWallet(usize),
     Next,
+    Export,
+    ExportPathEdited(String),
+    NextQrFrame,
+}
+
+#[derive(Debug, Clone)]
+pub enum ImportSpendMessage {
+    Import,
+    PsbtEdited(String),
+    Confirm,
+}
+
+#[derive(Debug, Clone)]
+pub enum ReceiveMessage {
+    AmountEdited(String),
+    LabelEdited(String),
 }
 
 #[derive(Debug, Clone)]
@@ -1,24 +1,31 @@
 use iced::{
     widget::{
         qr_code::{self, QRCode},
-        Button, Column, Row,
+        Button, Column, Container, Row,
     },
-    Alignment, Element,
+    Alignment, Element, Length,
 };
 
 use liana::miniscript::bitcoin;
 
 use crate::ui::{
-    component::{button, card, text::*},
+    component::{button, card, form, text::*},
     icon,
+    util::Collection,
 };
 
-use super::message::Message;
+use super::message::{Message, ReceiveMessage};
 
-pub fn receive<'a>(address: &'a bitcoin::Address, qr: &'a qr_code::State) -> Element<'a, Message> {
+pub fn receive<'a>(
+    address: &'a bitcoin::Address,
+    uri: &'a str,
+    amount: &form::Value<String>,
+    label: &form::Value<String>,
+    qr: Option<&'a qr_code::State>,
+) -> Element<'a, Message> {
     card::simple(
         Column::new()
-            .push(QRCode::new(qr).cell_size(10))
+            .push_maybe(qr.map(|qr| QRCode::new(qr).cell_size(10)))
             .push(
                 Row::new()
                     .push(text(address.to_string()).small())
@@ -29,6 +36,38 @@ pub fn receive<'a>(address: &'a bitcoin::Address, qr: &'a qr_code::State) -> Ele
                     )
                     .align_items(Alignment::Center),
             )
+            .push(
+                Row::new()
+                    .push(
+                        Container::new(
+                            form::Form::new("Amount (BTC, optional)", amount, |msg| {
+                                Message::Receive(ReceiveMessage::AmountEdited(msg))
+                            })
+                            .warning("Please enter a correct amount")
+                            .size(20)
+                            .padding(10),
+                        )
+                        .width(Length::Units(250)),
+                    )
+                    .push(
+                        form::Form::new("Label (optional)", label, |msg| {
+                            Message::Receive(ReceiveMessage::LabelEdited(msg))
+                        })
+                        .size(20)
+                        .padding(10),
+                    )
+                    .spacing(10),
+            )
+            .push(
+                Row::new()
+                    .push(text(uri).small())
+                    .push(
+                        Button::new(icon::clipboard_icon())
+                            .on_press(Message::Clipboard(uri.to_string()))
+                            .style(button::Style::TransparentBorder.into()),
+                    )
+                    .align_items(Alignment::Center),
+            )
             .align_items(Alignment::Center)
             .spacing(20),
     )
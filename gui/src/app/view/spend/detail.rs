@@ -1,5 +1,8 @@
 use iced::{
-    widget::{Button, Column, Container, Row, Scrollable},
+    widget::{
+        qr_code::{self, QRCode},
+        Button, Column, Container, Row, Scrollable,
+    },
     Alignment, Element, Length,
 };
 
@@ -16,7 +19,7 @@ use crate::{
         component::{
             badge, button, card,
             collapse::Collapse,
-            container, separation,
+            container, form, separation,
             text::{text, Text},
         },
         icon,
@@ -93,6 +96,54 @@ pub fn broadcast_action<'a>(saved: bool) -> Element<'a, Message> {
     }
 }
 
+pub fn export_action<'a>(
+    path: &form::Value<String>,
+    qr: Option<&'a qr_code::State>,
+    frame: usize,
+    frames: usize,
+    exported: bool,
+) -> Element<'a, Message> {
+    card::simple(
+        Column::new()
+            .spacing(10)
+            .align_items(Alignment::Center)
+            .push(text("Export the PSBT to sign it with an air-gapped device").width(Length::Fill))
+            .push_maybe(qr.map(|qr| QRCode::new(qr).cell_size(5)))
+            .push_maybe(if frames > 1 {
+                Some(text(format!("QR code {}/{}", frame + 1, frames)).small())
+            } else {
+                None
+            })
+            .push(
+                form::Form::new("Path of the file to write", path, |msg| {
+                    Message::Spend(SpendTxMessage::ExportPathEdited(msg))
+                })
+                .warning("Please enter a file path")
+                .size(20)
+                .padding(10),
+            )
+            .push(
+                Row::new()
+                    .push(if exported {
+                        Container::new(text("PSBT is exported")).width(Length::Fill)
+                    } else {
+                        Container::new(Column::new()).width(Length::Fill)
+                    })
+                    .push(
+                        button::transparent(None, "Cancel")
+                            .on_press(Message::Spend(SpendTxMessage::Cancel)),
+                    )
+                    .push(
+                        button::primary(None, "Save to file")
+                            .on_press(Message::Spend(SpendTxMessage::Confirm)),
+                    )
+                    .align_items(Alignment::Center),
+            ),
+    )
+    .width(Length::Fill)
+    .into()
+}
+
 pub fn delete_action<'a>(deleted: bool) -> Element<'a, Message> {
     if deleted {
         card::simple(text("Transaction is deleted"))
@@ -251,6 +302,10 @@ fn spend_overview_view<'a>(tx: &SpendTx) -> Element<'a, Message> {
                                 button::transparent(Some(icon::clipboard_icon()), "Copy")
                                     .on_press(Message::Clipboard(tx.psbt.to_string())),
                             )
+                            .push(
+                                button::transparent(None, "Export")
+                                    .on_press(Message::Spend(SpendTxMessage::Export)),
+                            )
                             .align_items(Alignment::Center),
                     ),
             )
@@ -7,24 +7,34 @@ use iced::{
 };
 
 use crate::{
-    app::menu::Menu,
+    app::{error::Error, menu::Menu},
     daemon::model::{SpendStatus, SpendTx},
     ui::{
-        component::{badge, button, card, text::*},
+        component::{badge, button, card, form, text::*},
         icon,
         util::Collection,
     },
 };
 
-use super::message::Message;
+use super::{
+    message::{ImportSpendMessage, Message},
+    modal,
+};
 
 pub fn spend_view<'a>(spend_txs: &[SpendTx]) -> Element<'a, Message> {
     Column::new()
         .push(
-            Row::new().push(Column::new().width(Length::Fill)).push(
-                button::primary(Some(icon::plus_icon()), "New transaction")
-                    .on_press(Message::Menu(Menu::CreateSpendTx)),
-            ),
+            Row::new()
+                .spacing(10)
+                .push(Column::new().width(Length::Fill))
+                .push(
+                    button::border(Some(icon::import_icon()), "Import PSBT")
+                        .on_press(Message::ImportSpend(ImportSpendMessage::Import)),
+                )
+                .push(
+                    button::primary(Some(icon::plus_icon()), "New transaction")
+                        .on_press(Message::Menu(Menu::CreateSpendTx)),
+                ),
         )
         .push(
             Container::new(
@@ -49,6 +59,55 @@ pub fn spend_view<'a>(spend_txs: &[SpendTx]) -> Element<'a, Message> {
         .into()
 }
 
+pub fn import_psbt_view<'a>(
+    psbt: &form::Value<String>,
+    error: Option<&'a str>,
+    warning: Option<&Error>,
+    processing: bool,
+    imported: bool,
+) -> Element<'a, Message> {
+    modal(
+        false,
+        warning,
+        Column::new()
+            .push(text("Import a PSBT").bold().size(50))
+            .push(if imported {
+                card::simple(text("PSBT is imported"))
+                    .width(Length::Fill)
+                    .align_x(iced::alignment::Horizontal::Center)
+            } else {
+                card::simple(
+                    Column::new()
+                        .spacing(10)
+                        .push(text(
+                            "Paste a base64 encoded PSBT or enter the path to a PSBT file",
+                        ))
+                        .push(
+                            form::Form::new("PSBT or file path", psbt, |msg| {
+                                Message::ImportSpend(ImportSpendMessage::PsbtEdited(msg))
+                            })
+                            .warning(error.unwrap_or("Please enter a valid PSBT"))
+                            .size(20)
+                            .padding(10),
+                        )
+                        .push(Row::new().push(Column::new().width(Length::Fill)).push(
+                            if processing {
+                                button::primary(None, "Importing...")
+                            } else {
+                                button::primary(None, "Import")
+                                    .on_press(Message::ImportSpend(ImportSpendMessage::Confirm))
+                            },
+                        )),
+                )
+                .width(Length::Fill)
+            })
+            .max_width(1000)
+            .spacing(20)
+            .align_items(Alignment::Center),
+        None::<Element<Message>>,
+    )
+}
+
 fn spend_tx_list_view<'a>(i: usize, tx: &SpendTx) -> Element<'a, Message> {
     Container::new(
         Button::new(
@@ -115,6 +115,7 @@ pub fn choose_coins_view<'a>(
     coins: &[(Coin, bool)],
     amount_left: Option<&Amount>,
     feerate: &form::Value<String>,
+    feerate_preset: Option<FeeratePreset>,
     error: Option<&Error>,
 ) -> Element<'a, Message> {
     modal(
@@ -122,9 +123,28 @@ pub fn choose_coins_view<'a>(
         error,
         Column::new()
             .push(text("Choose coins and feerate").bold().size(50))
+            .push(
+                FeeratePreset::ALL
+                    .iter()
+                    .fold(Row::new().spacing(10), |row, preset| {
+                        let preset_button = if feerate_preset == Some(*preset) {
+                            button::primary(None, preset.label())
+                        } else {
+                            button::border(None, preset.label())
+                        };
+                        row.push(
+                            preset_button
+                                .on_press(Message::CreateSpend(
+                                    CreateSpendMessage::FeeratePresetSelected(*preset),
+                                ))
+                                .width(Length::Units(100)),
+                        )
+                    })
+                    .align_items(Alignment::Center),
+            )
             .push(
                 Container::new(
-                    form::Form::new("Feerate (sat/vbyte)", feerate, move |msg| {
+                    form::Form::new("Custom feerate (sat/vbyte)", feerate, move |msg| {
                         Message::CreateSpend(CreateSpendMessage::FeerateEdited(msg))
                     })
                     .warning("Please enter correct feerate (sat/vbyte)")
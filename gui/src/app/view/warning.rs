@@ -37,6 +37,9 @@ impl From<&Error> for WarningMessage {
             },
             Error::Unexpected(_) => WarningMessage("Unknown error".to_string()),
             Error::HardwareWallet(_) => WarningMessage("Hardware wallet error".to_string()),
+            Error::HardwareWalletNotConnected(_) => {
+                WarningMessage("Hardware wallet not connected".to_string())
+            }
         }
     }
 }
@@ -38,6 +38,7 @@ use log::debug;
 pub struct JsonRPCClient {
     sockpath: PathBuf,
     timeout: Option<Duration>,
+    cookie_path: Option<PathBuf>,
 }
 
 impl super::Client for JsonRPCClient {
@@ -58,9 +59,17 @@ impl JsonRPCClient {
         JsonRPCClient {
             sockpath: sockpath.as_ref().to_path_buf(),
             timeout: None,
+            cookie_path: None,
         }
     }
 
+    /// Present the cookie in this file to the server before sending requests, as required by
+    /// lianad when `rpc_cookie` is set in its configuration.
+    pub fn with_cookie<P: AsRef<Path>>(mut self, cookie_path: P) -> JsonRPCClient {
+        self.cookie_path = Some(cookie_path.as_ref().to_path_buf());
+        self
+    }
+
     /// Set an optional timeout for requests
     #[allow(dead_code)]
     pub fn set_timeout(&mut self, timeout: Option<Duration>) {
@@ -88,6 +97,19 @@ impl JsonRPCClient {
         stream.set_read_timeout(self.timeout)?;
         stream.set_write_timeout(self.timeout)?;
 
+        // The connection must be authenticated before sending any command.
+        if let Some(cookie_path) = &self.cookie_path {
+            let cookie = std::fs::read_to_string(cookie_path)?;
+            let auth = Request {
+                method: "auth",
+                params: Some(serde_json::json!({ "cookie": cookie.trim() })),
+                id: std::process::id(),
+                jsonrpc: "2.0",
+            };
+            debug!("Authenticating to lianad");
+            exchange::<_, serde_json::Value>(&mut stream, &auth)?.into_result()?;
+        }
+
         let request = Request {
             method,
             params,
@@ -97,23 +119,7 @@ impl JsonRPCClient {
 
         debug!("Sending to lianad: {:#?}", request);
 
-        stream.write_all(&[serde_json::to_string(&request).unwrap().as_bytes(), b"\n"].concat())?;
-
-        let response: Response<D> = Deserializer::from_reader(&mut stream)
-            .into_iter()
-            .next()
-            .map_or(Err(Error::NoErrorOrResult), |res| Ok(res?))?;
-        if response
-            .jsonrpc
-            .as_ref()
-            .map_or(false, |version| version != "2.0")
-        {
-            return Err(Error::VersionMismatch);
-        }
-
-        if response.id != request.id {
-            return Err(Error::NonceMismatch);
-        }
+        let response = exchange(&mut stream, &request)?;
 
         debug!("Received from lianad: {:#?}", response);
 
@@ -121,6 +127,33 @@ impl JsonRPCClient {
     }
 }
 
+/// Send a request on the connection and read the response to it.
+#[cfg(not(windows))]
+fn exchange<S: Serialize, D: DeserializeOwned>(
+    stream: &mut UnixStream,
+    request: &Request<S>,
+) -> Result<Response<D>, Error> {
+    stream.write_all(&[serde_json::to_string(request).unwrap().as_bytes(), b"\n"].concat())?;
+
+    let response: Response<D> = Deserializer::from_reader(stream)
+        .into_iter()
+        .next()
+        .map_or(Err(Error::NoErrorOrResult), |res| Ok(res?))?;
+    if response
+        .jsonrpc
+        .as_ref()
+        .map_or(false, |version| version != "2.0")
+    {
+        return Err(Error::VersionMismatch);
+    }
+
+    if response.id != request.id {
+        return Err(Error::NonceMismatch);
+    }
+
+    Ok(response)
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 /// A JSONRPC request object
 pub struct Request<'f, T: Serialize> {
@@ -265,3 +298,63 @@ pub struct RpcError {
     /// Additional data specific to the error
     pub data: Option<serde_json::Value>,
 }
+
+#[cfg(all(test, not(windows)))]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixListener;
+
+    #[test]
+    fn cookie_authentication() {
+        let dir = std::env::temp_dir().join(format!("liana-gui-jsonrpc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket_path = dir.join("lianad_rpc");
+        let cookie_path = dir.join("lianad_rpc.cookie");
+        std::fs::write(&cookie_path, "abcd").unwrap();
+
+        // A server which expects the cookie in an 'auth' request first, then answers a single
+        // command.
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut methods = Vec::new();
+            for result in [
+                serde_json::json!({}),
+                serde_json::json!({"block_height": 42}),
+            ]
+            .iter()
+            {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let req: serde_json::Value = serde_json::from_str(&line).unwrap();
+                methods.push((req["method"].clone(), req["params"].clone()));
+                let response = serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "result": result,
+                    "id": req["id"],
+                });
+                writer
+                    .write_all(serde_json::to_string(&response).unwrap().as_bytes())
+                    .unwrap();
+            }
+            methods
+        });
+
+        let client = JsonRPCClient::new(&socket_path).with_cookie(&cookie_path);
+        let res: serde_json::Value = client
+            .send_request("getinfo", Option::<serde_json::Value>::None)
+            .unwrap()
+            .into_result()
+            .unwrap();
+        assert_eq!(res["block_height"], 42);
+        let methods = server.join().unwrap();
+        assert_eq!(methods[0].0, "auth");
+        assert_eq!(methods[0].1, serde_json::json!({"cookie": "abcd"}));
+        assert_eq!(methods[1].0, "getinfo");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
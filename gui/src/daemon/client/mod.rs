@@ -11,7 +11,12 @@ pub mod jsonrpc;
 
 use liana::{
     config::Config,
-    miniscript::bitcoin::{consensus, util::psbt::Psbt, Address, OutPoint, Txid},
+    miniscript::bitcoin::{
+        consensus,
+        hashes::hex::ToHex,
+        util::{bip32::Fingerprint, psbt::Psbt},
+        Address, OutPoint, Txid,
+    },
 };
 
 use super::{model::*, Daemon, DaemonError};
@@ -142,6 +147,32 @@ impl<C: Client + Debug> Daemon for Lianad<C> {
         )?;
         Ok(res.psbt)
     }
+
+    fn estimate_feerate(&self, target_blocks: u16) -> Result<u64, DaemonError> {
+        let res: EstimateFeerateResult = self.call("estimatefeerate", Some(vec![target_blocks]))?;
+        Ok(res.feerate)
+    }
+
+    fn store_hw_token(
+        &self,
+        fingerprint: &Fingerprint,
+        token: &[u8; 32],
+    ) -> Result<(), DaemonError> {
+        let _res: serde_json::value::Value = self.call(
+            "storehwtoken",
+            Some(vec![fingerprint.to_string(), token.to_hex()]),
+        )?;
+        Ok(())
+    }
+
+    fn list_hw_tokens(&self) -> Result<ListHwTokensResult, DaemonError> {
+        self.call("listhwtokens", Option::<Request>::None)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct EstimateFeerateResult {
+    feerate: u64,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
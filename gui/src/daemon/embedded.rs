@@ -4,7 +4,10 @@ use std::sync::RwLock;
 use super::{model::*, Daemon, DaemonError};
 use liana::{
     config::Config,
-    miniscript::bitcoin::{util::psbt::Psbt, Address, OutPoint, Txid},
+    miniscript::bitcoin::{
+        util::{bip32::Fingerprint, psbt::Psbt},
+        Address, OutPoint, Txid,
+    },
     DaemonHandle,
 };
 
@@ -93,7 +96,7 @@ impl Daemon for EmbeddedDaemon {
             .read()
             .unwrap()
             .control
-            .list_coins())
+            .list_coins(&[], &[]))
     }
 
     fn list_spend_txs(&self) -> Result<ListSpendResult, DaemonError> {
@@ -104,7 +107,7 @@ impl Daemon for EmbeddedDaemon {
             .read()
             .unwrap()
             .control
-            .list_spend())
+            .list_spend(None))
     }
 
     fn list_confirmed_txs(
@@ -120,7 +123,7 @@ impl Daemon for EmbeddedDaemon {
             .read()
             .unwrap()
             .control
-            .list_confirmed_transactions(start, end, limit))
+            .list_confirmed_transactions(start, end, limit, None, None))
     }
 
     fn list_txs(&self, txids: &[Txid]) -> Result<ListTransactionsResult, DaemonError> {
@@ -146,7 +149,7 @@ impl Daemon for EmbeddedDaemon {
             .read()
             .unwrap()
             .control
-            .create_spend(destinations, coins_outpoints, feerate_vb)
+            .create_spend(destinations, coins_outpoints, feerate_vb, None, None)
             .map_err(|e| DaemonError::Unexpected(e.to_string()))
     }
 
@@ -158,6 +161,7 @@ impl Daemon for EmbeddedDaemon {
             .unwrap()
             .control
             .update_spend(psbt.clone())
+            .map(|_| ())
             .map_err(|e| DaemonError::Unexpected(e.to_string()))
     }
 
@@ -190,7 +194,7 @@ impl Daemon for EmbeddedDaemon {
             .read()
             .unwrap()
             .control
-            .start_rescan(t)
+            .start_rescan(t, false)
             .map_err(|e| DaemonError::Unexpected(e.to_string()))
     }
 
@@ -201,8 +205,45 @@ impl Daemon for EmbeddedDaemon {
             .read()
             .unwrap()
             .control
-            .create_recovery(address, feerate_vb)
+            .create_recovery(address, feerate_vb, None)
             .map_err(|e| DaemonError::Unexpected(e.to_string()))
             .map(|res| res.psbt)
     }
+
+    fn estimate_feerate(&self, target_blocks: u16) -> Result<u64, DaemonError> {
+        self.handle
+            .as_ref()
+            .ok_or(DaemonError::NoAnswer)?
+            .read()
+            .unwrap()
+            .control
+            .estimate_feerate(target_blocks)
+            .map_err(|e| DaemonError::Unexpected(e.to_string()))
+    }
+
+    fn store_hw_token(
+        &self,
+        fingerprint: &Fingerprint,
+        token: &[u8; 32],
+    ) -> Result<(), DaemonError> {
+        self.handle
+            .as_ref()
+            .ok_or(DaemonError::NoAnswer)?
+            .read()
+            .unwrap()
+            .control
+            .store_hw_token(fingerprint, token);
+        Ok(())
+    }
+
+    fn list_hw_tokens(&self) -> Result<ListHwTokensResult, DaemonError> {
+        Ok(self
+            .handle
+            .as_ref()
+            .ok_or(DaemonError::NoAnswer)?
+            .read()
+            .unwrap()
+            .control
+            .list_hw_tokens())
+    }
 }
@@ -8,7 +8,10 @@ use std::io::ErrorKind;
 
 use liana::{
     config::Config,
-    miniscript::bitcoin::{util::psbt::Psbt, Address, OutPoint, Txid},
+    miniscript::bitcoin::{
+        util::{bip32::Fingerprint, psbt::Psbt},
+        Address, OutPoint, Txid,
+    },
     StartupError,
 };
 
@@ -69,7 +72,27 @@ pub trait Daemon: Debug {
         _limit: u64,
     ) -> Result<model::ListTransactionsResult, DaemonError>;
     fn create_recovery(&self, address: Address, feerate_vb: u64) -> Result<Psbt, DaemonError>;
+    /// Feerate in sat/vb for a transaction to confirm within this number of blocks, as
+    /// estimated by the node.
+    fn estimate_feerate(&self, target_blocks: u16) -> Result<u64, DaemonError>;
     fn list_txs(&self, txid: &[Txid]) -> Result<model::ListTransactionsResult, DaemonError>;
+    /// Store the token a signing device returned when registering the wallet descriptor.
+    fn store_hw_token(
+        &self,
+        fingerprint: &Fingerprint,
+        token: &[u8; 32],
+    ) -> Result<(), DaemonError>;
+    fn list_hw_tokens(&self) -> Result<model::ListHwTokensResult, DaemonError>;
+
+    /// The registration tokens of the signing devices, by fingerprint.
+    fn hw_tokens(&self) -> Result<HashMap<Fingerprint, [u8; 32]>, DaemonError> {
+        Ok(self
+            .list_hw_tokens()?
+            .tokens
+            .into_iter()
+            .map(|t| (t.fingerprint, t.token))
+            .collect())
+    }
 
     fn list_spend_transactions(&self) -> Result<Vec<model::SpendTx>, DaemonError> {
         let coins = self.list_coins()?.coins;
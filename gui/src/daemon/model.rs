@@ -1,7 +1,8 @@
 pub use liana::{
     commands::{
-        CreateSpendResult, GetAddressResult, GetInfoResult, ListCoinsEntry, ListCoinsResult,
-        ListSpendEntry, ListSpendResult, ListTransactionsResult, TransactionInfo,
+        CreateSpendResult, GetAddressResult, GetInfoResult, HwToken, ListCoinsEntry,
+        ListCoinsResult, ListHwTokensResult, ListSpendEntry, ListSpendResult,
+        ListTransactionsResult, TransactionInfo,
     },
     miniscript::bitcoin::{util::psbt::Psbt, Amount, Transaction},
 };
@@ -43,7 +44,9 @@ impl SpendTx {
         let (change_amount, spend_amount) = psbt.unsigned_tx.output.iter().enumerate().fold(
             (Amount::from_sat(0), Amount::from_sat(0)),
             |(change, spend), (i, output)| {
-                if !psbt.outputs[i].bip32_derivation.is_empty() {
+                if !psbt.outputs[i].bip32_derivation.is_empty()
+                    || !psbt.outputs[i].tap_key_origins.is_empty()
+                {
                     change_indexes.push(i);
                     (change + Amount::from_sat(output.value), spend)
                 } else {
@@ -76,8 +79,20 @@ impl SpendTx {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionKind {
+    /// A transaction paying to us, not spending any of our coins.
+    Incoming,
+    /// A transaction spending some of our coins to external addresses.
+    Outgoing,
+    /// A transaction spending some of our coins only to ourselves, for instance a
+    /// consolidation. Its only economic effect is the miner fee.
+    SelfTransfer,
+}
+
 #[derive(Debug, Clone)]
 pub struct HistoryTransaction {
+    pub kind: TransactionKind,
     pub coins: Vec<Coin>,
     pub change_indexes: Vec<usize>,
     pub tx: Transaction,
@@ -118,7 +133,16 @@ impl HistoryTransaction {
             None
         };
 
+        let kind = if coins.is_empty() {
+            TransactionKind::Incoming
+        } else if change_indexes.len() == tx.output.len() {
+            TransactionKind::SelfTransfer
+        } else {
+            TransactionKind::Outgoing
+        };
+
         Self {
+            kind,
             tx,
             coins,
             change_indexes,
@@ -133,4 +157,140 @@ impl HistoryTransaction {
     pub fn is_external(&self) -> bool {
         self.coins.is_empty()
     }
+
+    pub fn is_self_transfer(&self) -> bool {
+        self.kind == TransactionKind::SelfTransfer
+    }
+}
+
+/// The wallet balance, split by how readily its coins can be spent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Balance {
+    /// Unspent coins whose transaction is confirmed.
+    pub confirmed: Amount,
+    /// Unspent coins whose transaction is not confirmed yet.
+    pub pending: Amount,
+    /// Unspent confirmed coins frozen by the user.
+    pub frozen: Amount,
+}
+
+impl Balance {
+    pub fn from_coins(coins: &[Coin]) -> Self {
+        coins.iter().filter(|coin| coin.spend_info.is_none()).fold(
+            Self::default(),
+            |mut balance, coin| {
+                if coin.block_height.is_none() {
+                    balance.pending += coin.amount;
+                } else if coin.frozen {
+                    balance.frozen += coin.amount;
+                } else {
+                    balance.confirmed += coin.amount;
+                }
+                balance
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use liana::{
+        commands::{LCSpendInfo, SpendStatus as CoinSpendStatus},
+        miniscript::bitcoin::{hashes::Hash, OutPoint, PackedLockTime, Script, TxOut, Txid},
+    };
+    use std::str::FromStr;
+
+    #[test]
+    fn history_self_transfer() {
+        let coin = Coin {
+            amount: Amount::from_sat(100_000),
+            outpoint: OutPoint::from_str(
+                "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+            )
+            .unwrap(),
+            block_height: Some(1),
+            confirmations: 1,
+            sat_weight: 0,
+            spend_info: None,
+            spend_status: CoinSpendStatus::NotSpent,
+            blocks_until_recovery: Some(0),
+            recovery_paths: Vec::new(),
+            frozen: false,
+        };
+        let tx = Transaction {
+            version: 2,
+            lock_time: PackedLockTime(0),
+            input: vec![],
+            output: vec![
+                TxOut {
+                    value: 60_000,
+                    script_pubkey: Script::new(),
+                },
+                TxOut {
+                    value: 39_000,
+                    script_pubkey: Script::new(),
+                },
+            ],
+        };
+
+        // All the outputs are ours: it's a self transfer which only costs the fee.
+        let event = HistoryTransaction::new(tx.clone(), Some(2), Some(2), vec![coin], vec![0, 1]);
+        assert_eq!(event.kind, TransactionKind::SelfTransfer);
+        assert_eq!(event.outgoing_amount, Amount::from_sat(0));
+        assert_eq!(event.fee_amount, Some(Amount::from_sat(1_000)));
+
+        // If one of them isn't, it's a payment.
+        let event = HistoryTransaction::new(tx.clone(), Some(2), Some(2), vec![coin], vec![1]);
+        assert_eq!(event.kind, TransactionKind::Outgoing);
+        assert_eq!(event.outgoing_amount, Amount::from_sat(60_000));
+
+        // And if it doesn't spend any of our coins it's a deposit.
+        let event = HistoryTransaction::new(tx, Some(2), Some(2), vec![], vec![0]);
+        assert_eq!(event.kind, TransactionKind::Incoming);
+    }
+
+    #[test]
+    fn balance_breakdown() {
+        let coin = |amount: u64, block_height: Option<i32>, spent: bool, frozen: bool| Coin {
+            amount: Amount::from_sat(amount),
+            outpoint: OutPoint::default(),
+            block_height,
+            confirmations: block_height.map(|_| 1).unwrap_or(0),
+            sat_weight: 0,
+            spend_info: if spent {
+                Some(LCSpendInfo {
+                    txid: Txid::all_zeros(),
+                    height: None,
+                })
+            } else {
+                None
+            },
+            spend_status: if spent {
+                CoinSpendStatus::Broadcast
+            } else {
+                CoinSpendStatus::NotSpent
+            },
+            blocks_until_recovery: block_height.map(|_| 10),
+            recovery_paths: Vec::new(),
+            frozen,
+        };
+        let coins = vec![
+            coin(100_000, Some(1), false, false),
+            coin(50_000, Some(2), false, false),
+            coin(20_000, None, false, false),
+            coin(3_000, Some(3), false, true),
+            // Coins being spent are not part of the balance anymore.
+            coin(1_000_000, Some(1), true, false),
+        ];
+        assert_eq!(
+            Balance::from_coins(&coins),
+            Balance {
+                confirmed: Amount::from_sat(150_000),
+                pending: Amount::from_sat(20_000),
+                frozen: Amount::from_sat(3_000),
+            }
+        );
+        assert_eq!(Balance::from_coins(&[]), Balance::default());
+    }
 }
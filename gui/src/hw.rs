@@ -1,13 +1,17 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_hwi::{ledger, specter, DeviceKind, Error as HWIError, HWI};
-use liana::miniscript::bitcoin::{
-    hashes::hex::{FromHex, ToHex},
-    util::bip32::Fingerprint,
-};
-use log::debug;
+use iced::futures::future::{join_all, BoxFuture, FutureExt};
+use liana::miniscript::bitcoin::{hashes::hex::FromHex, util::bip32::Fingerprint};
+use log::{debug, error};
 use serde::{Deserialize, Serialize};
 
+use crate::daemon::Daemon;
+
 #[derive(Debug, Clone)]
 pub struct HardwareWallet {
     pub device: Arc<dyn HWI + Send + Sync>,
@@ -15,8 +19,72 @@ pub struct HardwareWallet {
     pub fingerprint: Fingerprint,
 }
 
+/// How long to wait for a device to answer while listing the connected devices. A locked or
+/// misbehaving device may never answer, we don't want it to block the others.
+pub const ENUMERATION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for the user to unlock the device and confirm a request on it.
+pub const UNLOCK_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub enum Error {
+    /// The device must be unlocked (PIN or passphrase) before it can be used.
+    Locked,
+    /// The device did not answer in time.
+    Timeout(Duration),
+    Device(HWIError),
+}
+
+impl From<HWIError> for Error {
+    fn from(e: HWIError) -> Self {
+        if is_locked_error(&e) {
+            Error::Locked
+        } else {
+            Error::Device(e)
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Locked => write!(f, "The device is locked, please unlock it and try again"),
+            Self::Timeout(t) => write!(
+                f,
+                "The device did not answer within {} seconds. Make sure it is unlocked and \
+                 the request was confirmed on it.",
+                t.as_secs()
+            ),
+            Self::Device(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+// The devices don't report their lock status in a structured way, the Ledger for instance
+// answers with the 0x5515 status word until its PIN is entered.
+fn is_locked_error(e: &HWIError) -> bool {
+    match e {
+        HWIError::Device(msg) => {
+            let msg = msg.to_lowercase();
+            msg.contains("locked") || msg.contains("5515")
+        }
+        _ => false,
+    }
+}
+
+/// Send a request to a device, giving up if it does not answer within the given duration.
+pub async fn with_timeout<T>(
+    timeout: Duration,
+    request: impl Future<Output = Result<T, HWIError>>,
+) -> Result<T, Error> {
+    match tokio::time::timeout(timeout, request).await {
+        Ok(res) => res.map_err(Error::from),
+        Err(_) => Err(Error::Timeout(timeout)),
+    }
+}
+
 impl HardwareWallet {
-    async fn new(device: Arc<dyn HWI + Send + Sync>) -> Result<Self, HWIError> {
+    async fn new(device: Arc<dyn HWI + Send + Sync>) -> Result<Self, Error> {
         let kind = device.device_kind();
         let fingerprint = device.get_master_fingerprint().await?;
         Ok(Self {
@@ -27,6 +95,12 @@ impl HardwareWallet {
     }
 }
 
+/// Whether this kind of device needs the token it returned when registering a descriptor in order
+/// to later sign for it.
+pub fn requires_registration_token(kind: &DeviceKind) -> bool {
+    matches!(kind, DeviceKind::Ledger | DeviceKind::LedgerSimulator)
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct HardwareWalletConfig {
     pub kind: String,
@@ -35,14 +109,6 @@ pub struct HardwareWalletConfig {
 }
 
 impl HardwareWalletConfig {
-    pub fn new(kind: &async_hwi::DeviceKind, fingerprint: &Fingerprint, token: &[u8; 32]) -> Self {
-        Self {
-            kind: kind.to_string(),
-            fingerprint: fingerprint.to_string(),
-            token: token.to_hex(),
-        }
-    }
-
     fn token(&self) -> [u8; 32] {
         let mut res = [0x00; 32];
         res.copy_from_slice(&Vec::from_hex(&self.token).unwrap());
@@ -50,94 +116,252 @@ impl HardwareWalletConfig {
     }
 }
 
-pub async fn list_hardware_wallets(
+/// The registration tokens of the signing devices, by fingerprint. They are stored by the daemon,
+/// but wallets created by older versions kept them in the GUI configuration: those are used for
+/// the devices the daemon has no token for.
+pub fn registration_tokens(
+    daemon: &dyn Daemon,
     cfg: &[HardwareWalletConfig],
-    wallet: Option<(&str, &str)>,
-) -> Vec<HardwareWallet> {
-    let mut hws: Vec<HardwareWallet> = Vec::new();
-    match specter::SpecterSimulator::try_connect().await {
-        Ok(device) => match HardwareWallet::new(Arc::new(device)).await {
-            Ok(hw) => hws.push(hw),
-            Err(e) => {
-                debug!("{}", e);
-            }
-        },
-        Err(HWIError::DeviceNotFound) => {}
-        Err(e) => {
-            debug!("{}", e);
-        }
+) -> HashMap<Fingerprint, [u8; 32]> {
+    let mut tokens: HashMap<Fingerprint, [u8; 32]> = cfg
+        .iter()
+        .filter_map(|cfg| {
+            Fingerprint::from_str(&cfg.fingerprint)
+                .ok()
+                .map(|fg| (fg, cfg.token()))
+        })
+        .collect();
+    match daemon.hw_tokens() {
+        Ok(stored) => tokens.extend(stored),
+        Err(e) => error!("Error fetching the signing devices tokens: {}", e),
+    }
+    tokens
+}
+
+/// The devices found while listing the connected hardware wallets.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectedDevices {
+    /// The devices ready to be used.
+    pub hws: Vec<HardwareWallet>,
+    /// The devices that were detected but could not be used, along with the reason.
+    pub errors: Vec<(DeviceKind, Error)>,
+}
+
+impl ConnectedDevices {
+    /// The devices that need to be unlocked by the user before they can be used.
+    pub fn locked(&self) -> impl Iterator<Item = &DeviceKind> {
+        self.errors
+            .iter()
+            .filter(|(_, e)| matches!(e, Error::Locked | Error::Timeout(_)))
+            .map(|(kind, _)| kind)
     }
-    match specter::Specter::try_connect_serial().await {
-        Ok(device) => match HardwareWallet::new(Arc::new(device)).await {
-            Ok(hw) => hws.push(hw),
+}
+
+/// Probing a kind of device returns `None` if no such device is connected.
+type Probe<'a, T> = (DeviceKind, BoxFuture<'a, Result<Option<T>, Error>>);
+
+/// Run the probes concurrently, each bounded by the given timeout. A device failing or not
+/// answering in time does not prevent the others from being returned.
+async fn probe_all<T>(
+    probes: Vec<Probe<'_, T>>,
+    timeout: Duration,
+) -> (Vec<T>, Vec<(DeviceKind, Error)>) {
+    let results = join_all(probes.into_iter().map(|(kind, probe)| async move {
+        let res = match tokio::time::timeout(timeout, probe).await {
+            Ok(res) => res,
+            Err(_) => Err(Error::Timeout(timeout)),
+        };
+        (kind, res)
+    }))
+    .await;
+
+    let mut found = Vec::new();
+    let mut errors = Vec::new();
+    for (kind, res) in results {
+        match res {
+            Ok(Some(device)) => found.push(device),
+            Ok(None) => {}
             Err(e) => {
-                debug!("{}", e);
+                debug!("{}: {}", kind, e);
+                errors.push((kind, e));
             }
-        },
-        Err(HWIError::DeviceNotFound) => {}
-        Err(e) => {
-            debug!("{}", e);
         }
     }
-    match ledger::LedgerSimulator::try_connect().await {
-        Ok(mut device) => match device.get_master_fingerprint().await {
-            Ok(fingerprint) => {
-                if let Some((name, descriptor)) = wallet {
-                    device
-                        .load_wallet(
-                            name,
-                            descriptor,
-                            cfg.iter()
-                                .find(|cfg| cfg.fingerprint == fingerprint.to_string())
-                                .map(|cfg| cfg.token()),
-                        )
-                        .expect("Configuration must be correct");
-                }
+    (found, errors)
+}
 
-                hws.push(HardwareWallet {
-                    kind: device.device_kind(),
-                    fingerprint,
-                    device: Arc::new(device),
-                });
+pub async fn list_hardware_wallets(
+    tokens: &HashMap<Fingerprint, [u8; 32]>,
+    wallet: Option<(&str, &str)>,
+) -> Vec<HardwareWallet> {
+    list_devices(tokens, wallet).await.hws
+}
+
+pub async fn list_devices(
+    tokens: &HashMap<Fingerprint, [u8; 32]>,
+    wallet: Option<(&str, &str)>,
+) -> ConnectedDevices {
+    let probes: Vec<Probe<HardwareWallet>> = vec![
+        (
+            DeviceKind::SpecterSimulator,
+            async {
+                match specter::SpecterSimulator::try_connect().await {
+                    Ok(device) => HardwareWallet::new(Arc::new(device)).await.map(Some),
+                    Err(HWIError::DeviceNotFound) => Ok(None),
+                    Err(e) => Err(e.into()),
+                }
             }
-            Err(e) => {
-                debug!("{}", e);
+            .boxed(),
+        ),
+        (
+            DeviceKind::Specter,
+            async {
+                match specter::Specter::try_connect_serial().await {
+                    Ok(device) => HardwareWallet::new(Arc::new(device)).await.map(Some),
+                    Err(HWIError::DeviceNotFound) => Ok(None),
+                    Err(e) => Err(e.into()),
+                }
             }
-        },
-        Err(HWIError::DeviceNotFound) => {}
-        Err(e) => {
-            debug!("{}", e);
-        }
-    }
-    match ledger::Ledger::try_connect_hid() {
-        Ok(mut device) => match device.get_master_fingerprint().await {
-            Ok(fingerprint) => {
-                if let Some((name, descriptor)) = wallet {
-                    device
-                        .load_wallet(
-                            name,
-                            descriptor,
-                            cfg.iter()
-                                .find(|cfg| cfg.fingerprint == fingerprint.to_string())
-                                .map(|cfg| cfg.token()),
-                        )
-                        .expect("Configuration must be correct");
+            .boxed(),
+        ),
+        (
+            DeviceKind::LedgerSimulator,
+            async move {
+                match ledger::LedgerSimulator::try_connect().await {
+                    Ok(mut device) => {
+                        let fingerprint = device.get_master_fingerprint().await?;
+                        if let Some((name, descriptor)) = wallet {
+                            device
+                                .load_wallet(name, descriptor, tokens.get(&fingerprint).copied())
+                                .expect("Configuration must be correct");
+                        }
+                        Ok(Some(HardwareWallet {
+                            kind: device.device_kind(),
+                            fingerprint,
+                            device: Arc::new(device),
+                        }))
+                    }
+                    Err(HWIError::DeviceNotFound) => Ok(None),
+                    Err(e) => Err(e.into()),
                 }
-
-                hws.push(HardwareWallet {
-                    kind: device.device_kind(),
-                    fingerprint,
-                    device: Arc::new(device),
-                });
             }
-            Err(e) => {
-                debug!("{}", e);
+            .boxed(),
+        ),
+        (
+            DeviceKind::Ledger,
+            async move {
+                match ledger::Ledger::try_connect_hid() {
+                    Ok(mut device) => {
+                        let fingerprint = device.get_master_fingerprint().await?;
+                        if let Some((name, descriptor)) = wallet {
+                            device
+                                .load_wallet(name, descriptor, tokens.get(&fingerprint).copied())
+                                .expect("Configuration must be correct");
+                        }
+                        Ok(Some(HardwareWallet {
+                            kind: device.device_kind(),
+                            fingerprint,
+                            device: Arc::new(device),
+                        }))
+                    }
+                    Err(HWIError::DeviceNotFound) => Ok(None),
+                    Err(e) => Err(e.into()),
+                }
             }
-        },
-        Err(HWIError::DeviceNotFound) => {}
-        Err(e) => {
-            debug!("{}", e);
-        }
+            .boxed(),
+        ),
+    ];
+    let (hws, errors) = probe_all(probes, ENUMERATION_TIMEOUT).await;
+    ConnectedDevices { hws, errors }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    // A stub of a device waiting for its PIN to be entered.
+    async fn locked_device() -> Result<Fingerprint, HWIError> {
+        Err(HWIError::Device("Locked device (0x5515)".to_string()))
+    }
+
+    // A stub of a device waiting for the user to enter a passphrase, which never comes.
+    async fn unresponsive_device() -> Result<Fingerprint, HWIError> {
+        std::future::pending().await
+    }
+
+    #[tokio::test]
+    async fn locked_device_error() {
+        let res = with_timeout(ENUMERATION_TIMEOUT, locked_device()).await;
+        assert!(matches!(res, Err(Error::Locked)));
+        assert_eq!(
+            Error::Locked.to_string(),
+            "The device is locked, please unlock it and try again"
+        );
+
+        let timeout = Duration::from_millis(10);
+        let res = with_timeout(timeout, unresponsive_device()).await;
+        assert!(matches!(res, Err(Error::Timeout(t)) if t == timeout));
+
+        // Other errors are passed through.
+        let res = with_timeout(timeout, async {
+            Err::<Fingerprint, _>(HWIError::DeviceNotFound)
+        })
+        .await;
+        assert!(matches!(res, Err(Error::Device(HWIError::DeviceNotFound))));
+
+        let res = with_timeout(timeout, async { Ok(Fingerprint::default()) }).await;
+        assert_eq!(res.unwrap(), Fingerprint::default());
+
+        let devices = ConnectedDevices {
+            hws: Vec::new(),
+            errors: vec![
+                (DeviceKind::Ledger, Error::Locked),
+                (DeviceKind::Specter, Error::Device(HWIError::DeviceNotFound)),
+                (DeviceKind::LedgerSimulator, Error::Timeout(timeout)),
+            ],
+        };
+        let locked: Vec<&DeviceKind> = devices.locked().collect();
+        assert!(matches!(
+            locked[..],
+            [DeviceKind::Ledger, DeviceKind::LedgerSimulator]
+        ));
+    }
+
+    #[tokio::test]
+    async fn concurrent_enumeration() {
+        let timeout = Duration::from_millis(200);
+        let fg_a = Fingerprint::from_str("aabbccdd").unwrap();
+        let fg_b = Fingerprint::from_str("00112233").unwrap();
+        let probes: Vec<Probe<Fingerprint>> = vec![
+            (DeviceKind::Specter, async move { Ok(Some(fg_a)) }.boxed()),
+            // Not connected.
+            (DeviceKind::SpecterSimulator, async { Ok(None) }.boxed()),
+            (
+                DeviceKind::LedgerSimulator,
+                async { Err(HWIError::DeviceNotFound.into()) }.boxed(),
+            ),
+            // Never answers.
+            (DeviceKind::Ledger, std::future::pending().boxed()),
+            (DeviceKind::Ledger, std::future::pending().boxed()),
+            (DeviceKind::Ledger, async move { Ok(Some(fg_b)) }.boxed()),
+        ];
+
+        let start = std::time::Instant::now();
+        let (found, errors) = probe_all(probes, timeout).await;
+        // The unresponsive devices were waited for concurrently.
+        assert!(start.elapsed() < timeout * 2);
+
+        assert_eq!(found, vec![fg_a, fg_b]);
+        assert_eq!(errors.len(), 3);
+        assert!(matches!(
+            errors[0],
+            (
+                DeviceKind::LedgerSimulator,
+                Error::Device(HWIError::DeviceNotFound)
+            )
+        ));
+        assert!(matches!(errors[1], (DeviceKind::Ledger, Error::Timeout(_))));
+        assert!(matches!(errors[2], (DeviceKind::Ledger, Error::Timeout(_))));
     }
-    hws
 }
@@ -17,10 +17,13 @@ impl TryFrom<Context> for LianaConfig {
             #[cfg(unix)]
             daemon: false,
             log_level: log::LevelFilter::Info,
+            rpc_cookie: false,
+            rpc_max_request_size: 1024 * 1024,
             main_descriptor: ctx.descriptor.unwrap(),
             data_dir: Some(ctx.data_dir),
             bitcoin_config: ctx.bitcoin_config,
             bitcoind_config: ctx.bitcoind_config,
+            birthday_timestamp: ctx.birthday_timestamp,
         })
     }
 }
@@ -1,14 +1,16 @@
+use async_hwi::DeviceKind;
 use liana::miniscript::bitcoin::{util::bip32::Fingerprint, Network};
 use std::path::PathBuf;
 
 use super::Error;
-use crate::hw::HardwareWallet;
+use crate::hw::ConnectedDevices;
 
 #[derive(Debug, Clone)]
 pub enum Message {
     CreateWallet,
     ImportWallet,
     BackupDone(bool),
+    ExportBackup,
     Event(iced_native::Event),
     Exit(PathBuf),
     Clibpboard(String),
@@ -22,23 +24,67 @@ pub enum Message {
     Network(Network),
     DefineBitcoind(DefineBitcoind),
     DefineDescriptor(DefineDescriptor),
-    ConnectedHardwareWallets(Vec<HardwareWallet>),
-    WalletRegistered(Result<(Fingerprint, Option<[u8; 32]>), Error>),
+    ConnectedDevices(ConnectedDevices),
+    WalletRegistered(Result<(DeviceKind, Fingerprint, Option<[u8; 32]>), Error>),
 }
 
 #[derive(Debug, Clone)]
 pub enum DefineBitcoind {
     CookiePathEdited(String),
+    RpcUserEdited(String),
+    RpcPasswordEdited(String),
     AddressEdited(String),
 }
 
 #[derive(Debug, Clone)]
 pub enum DefineDescriptor {
     ImportDescriptor(String),
+    BirthdayEdited(String),
     ImportUserHWXpub,
-    ImportHeirHWXpub,
+    ImportHeirHWXpub(usize),
     XpubImported(Result<String, Error>),
+    AccountEdited(String),
     UserXpubEdited(String),
-    HeirXpubEdited(String),
-    SequenceEdited(String),
+    HeirXpubEdited(usize, String),
+    SequenceEdited(usize, String),
+    SequenceUnitSelected(usize, SequenceUnit),
+    AddRecoveryPath,
+    DeleteRecoveryPath(usize),
+}
+
+/// The unit a recovery timelock is entered in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceUnit {
+    Blocks,
+    Days,
+    Months,
+}
+
+impl SequenceUnit {
+    pub const ALL: [SequenceUnit; 3] = [Self::Blocks, Self::Days, Self::Months];
+
+    /// The approximate number of blocks in one of this unit, with a block every 10 minutes.
+    pub fn blocks(&self) -> u32 {
+        match self {
+            Self::Blocks => 1,
+            Self::Days => 144,
+            Self::Months => 144 * 30,
+        }
+    }
+}
+
+impl Default for SequenceUnit {
+    fn default() -> Self {
+        Self::Blocks
+    }
+}
+
+impl std::fmt::Display for SequenceUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Blocks => write!(f, "blocks"),
+            Self::Days => write!(f, "days"),
+            Self::Months => write!(f, "months"),
+        }
+    }
 }
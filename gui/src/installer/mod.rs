@@ -12,14 +12,12 @@ use std::convert::TryInto;
 use std::io::Write;
 use std::path::PathBuf;
 
-use crate::{
-    app::config as gui_config, hw::HardwareWalletConfig, installer::config::DEFAULT_FILE_NAME,
-};
+use crate::{app::config as gui_config, installer::config::DEFAULT_FILE_NAME};
 
 pub use message::Message;
 use step::{
     BackupDescriptor, Context, DefineBitcoind, DefineDescriptor, Final, ImportDescriptor,
-    RegisterDescriptor, Step, Welcome,
+    PolicyPreview, RegisterDescriptor, Step, Welcome,
 };
 
 pub struct Installer {
@@ -102,6 +100,7 @@ impl Installer {
                 self.steps = vec![
                     Welcome::default().into(),
                     DefineDescriptor::new().into(),
+                    PolicyPreview::default().into(),
                     BackupDescriptor::default().into(),
                     RegisterDescriptor::default().into(),
                     DefineBitcoind::new().into(),
@@ -113,6 +112,7 @@ impl Installer {
                 self.steps = vec![
                     Welcome::default().into(),
                     ImportDescriptor::new().into(),
+                    PolicyPreview::default().into(),
                     RegisterDescriptor::default().into(),
                     DefineBitcoind::new().into(),
                     Final::new().into(),
@@ -165,14 +165,10 @@ impl Installer {
 }
 
 pub async fn install(ctx: Context) -> Result<PathBuf, Error> {
-    let hardware_wallets = ctx
+    let hw_tokens: Vec<_> = ctx
         .hws
         .iter()
-        .filter_map(|(kind, fingerprint, token)| {
-            token
-                .as_ref()
-                .map(|token| HardwareWalletConfig::new(kind, fingerprint, token))
-        })
+        .filter_map(|(_, fingerprint, token)| token.map(|token| (*fingerprint, token)))
         .collect();
 
     let mut cfg: liana::config::Config = ctx
@@ -182,6 +178,11 @@ pub async fn install(ctx: Context) -> Result<PathBuf, Error> {
     let daemon = liana::DaemonHandle::start_default(cfg.clone()).map_err(|e| {
         Error::Unexpected(format!("Failed to start daemon with entered config: {}", e))
     })?;
+    // The signing devices need the token they returned when registering the descriptor to sign
+    // for it later on.
+    for (fingerprint, token) in &hw_tokens {
+        daemon.control.store_hw_token(fingerprint, token);
+    }
     daemon.shutdown();
 
     cfg.data_dir =
@@ -218,7 +219,6 @@ pub async fn install(ctx: Context) -> Result<PathBuf, Error> {
                 daemon_config_path.canonicalize().map_err(|e| {
                     Error::Unexpected(format!("Failed to canonicalize daemon config path: {}", e))
                 })?,
-                hardware_wallets,
             ))
             .unwrap()
             .as_bytes(),
@@ -234,11 +234,18 @@ pub enum Error {
     CannotCreateFile(String),
     CannotWriteToFile(String),
     Unexpected(String),
-    HardwareWallet(async_hwi::Error),
+    HardwareWallet(crate::hw::Error),
+    MissingRegistrationToken(bitcoin::util::bip32::Fingerprint),
 }
 
 impl From<async_hwi::Error> for Error {
     fn from(error: async_hwi::Error) -> Self {
+        Error::HardwareWallet(error.into())
+    }
+}
+
+impl From<crate::hw::Error> for Error {
+    fn from(error: crate::hw::Error) -> Self {
         Error::HardwareWallet(error)
     }
 }
@@ -251,6 +258,11 @@ impl std::fmt::Display for Error {
             Self::CannotCreateFile(e) => write!(f, "Failed to create file: {}", e),
             Self::Unexpected(e) => write!(f, "Unexpected: {}", e),
             Self::HardwareWallet(e) => write!(f, "Hardware Wallet: {}", e),
+            Self::MissingRegistrationToken(fg) => write!(
+                f,
+                "Device {} did not return the token needed to later sign with the registered descriptor. Please register it again.",
+                fg
+            ),
         }
     }
 }
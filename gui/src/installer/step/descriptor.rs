@@ -1,9 +1,14 @@
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+use async_hwi::DeviceKind;
 use iced::{Command, Element};
 use liana::{
-    descriptors::MultipathDescriptor,
+    backup::WalletBackup,
+    descriptors::{xpub_network, MultipathDescriptor, PathInfo},
     miniscript::{
         bitcoin::{
             util::bip32::{DerivationPath, ExtendedPubKey, Fingerprint},
@@ -14,25 +19,115 @@ use liana::{
 };
 
 use crate::{
-    hw::{list_hardware_wallets, HardwareWallet},
+    hw::{
+        self, list_devices, requires_registration_token, with_timeout, HardwareWallet,
+        UNLOCK_TIMEOUT,
+    },
     installer::{
-        message::{self, Message},
+        message::{self, Message, SequenceUnit},
         step::{Context, Step},
         view, Error,
     },
     ui::component::form,
 };
 
-const LIANA_STANDARD_PATH: &str = "m/48'/0'/0'/2'";
-const LIANA_TESTNET_STANDARD_PATH: &str = "m/48'/1'/0'/2'";
+/// The BIP48 derivation path of the P2WSH account with the given index, used for Liana keys.
+/// Returns `None` if the account index is out of the hardened range.
+fn liana_derivation_path(network: Network, account: u32) -> Option<DerivationPath> {
+    let coin_type = if network == Network::Bitcoin { 0 } else { 1 };
+    DerivationPath::from_str(&format!("m/48'/{}'/{}'/2'", coin_type, account)).ok()
+}
+
+/// Whether the data directory of a network is available for a new wallet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataDirStatus {
+    /// The directory does not exist yet, it will be created.
+    Missing,
+    /// The directory exists but does not contain a wallet, it will be reused.
+    NoWallet,
+    /// The directory already contains the database of another wallet.
+    HasWallet,
+}
+
+impl DataDirStatus {
+    pub fn is_valid(&self) -> bool {
+        *self != Self::HasWallet
+    }
+}
+
+/// Check whether the data directory of the given network already contains a wallet, by looking
+/// for the database of the daemon.
+fn network_datadir_status(data_dir: &Path, network: Network) -> DataDirStatus {
+    let network_datadir = data_dir.join(network.to_string());
+    if !network_datadir.exists() {
+        return DataDirStatus::Missing;
+    }
+
+    // An SQLite database starts with this header. An empty file isn't a wallet.
+    let mut header = [0; 16];
+    match std::fs::File::open(network_datadir.join("lianad.sqlite3")) {
+        Ok(mut db) if db.read_exact(&mut header).is_ok() && &header == b"SQLite format 3\0" => {
+            DataDirStatus::HasWallet
+        }
+        _ => DataDirStatus::NoWallet,
+    }
+}
+
+/// The maximum relative timelock, in blocks, of a recovery path.
+const MAX_SEQUENCE: u32 = u16::MAX as u32;
+
+/// Convert a duration in the given unit to the number of blocks of the relative timelock.
+/// Returns `None` if the duration is null or exceeds the maximum relative timelock.
+fn sequence_from_duration(value: u32, unit: SequenceUnit) -> Option<u16> {
+    let blocks = value.checked_mul(unit.blocks())?;
+    if blocks == 0 || blocks > MAX_SEQUENCE {
+        return None;
+    }
+    Some(blocks as u16)
+}
+
+/// The approximate calendar time a number of blocks represents, rounded to the nearest unit.
+fn sequence_duration(blocks: u16) -> String {
+    let blocks = blocks as u32;
+    let hours = (blocks * 10 + 30) / 60;
+    let days = (blocks + 72) / 144;
+    let months = (blocks + 2160) / 4320;
+    if blocks < 6 {
+        format!("~{} minutes", blocks * 10)
+    } else if blocks < 144 {
+        format!("~{} hour{}", hours, if hours == 1 { "" } else { "s" })
+    } else if blocks < 144 * 60 {
+        format!("~{} day{}", days, if days == 1 { "" } else { "s" })
+    } else {
+        format!("~{} months", months)
+    }
+}
+
+#[derive(Default)]
+struct RecoveryPath {
+    heir_xpub: form::Value<String>,
+    sequence: form::Value<String>,
+    sequence_unit: SequenceUnit,
+}
+
+impl RecoveryPath {
+    fn is_empty(&self) -> bool {
+        self.heir_xpub.value.is_empty() && self.sequence.value.is_empty()
+    }
+
+    /// The relative timelock of this path in blocks, if valid.
+    fn sequence(&self) -> Option<u16> {
+        let value = self.sequence.value.parse::<u32>().ok()?;
+        sequence_from_duration(value, self.sequence_unit)
+    }
+}
 
 pub struct DefineDescriptor {
     network: Network,
-    network_valid: bool,
+    network_status: DataDirStatus,
     data_dir: Option<PathBuf>,
     user_xpub: form::Value<String>,
-    heir_xpub: form::Value<String>,
-    sequence: form::Value<String>,
+    recovery_paths: Vec<RecoveryPath>,
     modal: Option<GetHardwareWalletXpubModal>,
 
     error: Option<String>,
@@ -43,10 +138,9 @@ impl DefineDescriptor {
         Self {
             network: Network::Bitcoin,
             data_dir: None,
-            network_valid: true,
+            network_status: DataDirStatus::Missing,
             user_xpub: form::Value::default(),
-            heir_xpub: form::Value::default(),
-            sequence: form::Value::default(),
+            recovery_paths: vec![RecoveryPath::default()],
             modal: None,
             error: None,
         }
@@ -63,9 +157,8 @@ impl Step for DefineDescriptor {
             }
             Message::Network(network) => {
                 self.network = network;
-                let mut network_datadir = self.data_dir.clone().unwrap();
-                network_datadir.push(self.network.to_string());
-                self.network_valid = !network_datadir.exists();
+                self.network_status =
+                    network_datadir_status(self.data_dir.as_ref().unwrap(), self.network);
             }
             Message::DefineDescriptor(msg) => {
                 match msg {
@@ -74,25 +167,46 @@ impl Step for DefineDescriptor {
                         self.user_xpub.valid = true;
                         self.modal = None;
                     }
-                    message::DefineDescriptor::HeirXpubEdited(xpub) => {
-                        self.heir_xpub.value = xpub;
-                        self.heir_xpub.valid = true;
+                    message::DefineDescriptor::HeirXpubEdited(i, xpub) => {
+                        if let Some(path) = self.recovery_paths.get_mut(i) {
+                            path.heir_xpub.value = xpub;
+                            path.heir_xpub.valid = true;
+                        }
                         self.modal = None;
                     }
-                    message::DefineDescriptor::SequenceEdited(seq) => {
-                        self.sequence.valid = true;
-                        if seq.is_empty() || seq.parse::<u16>().is_ok() {
-                            self.sequence.value = seq;
+                    message::DefineDescriptor::SequenceEdited(i, seq) => {
+                        if let Some(path) = self.recovery_paths.get_mut(i) {
+                            if seq.is_empty() || seq.parse::<u32>().is_ok() {
+                                path.sequence.value = seq;
+                            }
+                            path.sequence.valid =
+                                path.sequence.value.is_empty() || path.sequence().is_some();
+                        }
+                    }
+                    message::DefineDescriptor::SequenceUnitSelected(i, unit) => {
+                        if let Some(path) = self.recovery_paths.get_mut(i) {
+                            path.sequence_unit = unit;
+                            path.sequence.valid =
+                                path.sequence.value.is_empty() || path.sequence().is_some();
+                        }
+                    }
+                    message::DefineDescriptor::AddRecoveryPath => {
+                        self.recovery_paths.push(RecoveryPath::default());
+                    }
+                    message::DefineDescriptor::DeleteRecoveryPath(i) => {
+                        // There must always be at least one recovery path.
+                        if self.recovery_paths.len() > 1 && i < self.recovery_paths.len() {
+                            self.recovery_paths.remove(i);
                         }
                     }
                     message::DefineDescriptor::ImportUserHWXpub => {
-                        let modal = GetHardwareWalletXpubModal::new(false, self.network);
+                        let modal = GetHardwareWalletXpubModal::new(None, self.network);
                         let cmd = modal.load();
                         self.modal = Some(modal);
                         return cmd;
                     }
-                    message::DefineDescriptor::ImportHeirHWXpub => {
-                        let modal = GetHardwareWalletXpubModal::new(true, self.network);
+                    message::DefineDescriptor::ImportHeirHWXpub(i) => {
+                        let modal = GetHardwareWalletXpubModal::new(Some(i), self.network);
                         let cmd = modal.load();
                         self.modal = Some(modal);
                         return cmd;
@@ -116,40 +230,62 @@ impl Step for DefineDescriptor {
     fn load_context(&mut self, ctx: &Context) {
         self.network = ctx.bitcoin_config.network;
         self.data_dir = Some(ctx.data_dir.clone());
-        let mut network_datadir = ctx.data_dir.clone();
-        network_datadir.push(self.network.to_string());
-        self.network_valid = !network_datadir.exists();
+        self.network_status = network_datadir_status(&ctx.data_dir, self.network);
     }
 
     fn apply(&mut self, ctx: &mut Context) -> bool {
         ctx.bitcoin_config.network = self.network;
+        self.error = None;
         // descriptor forms for import or creation cannot be both empty or filled.
         let user_key = DescriptorPublicKey::from_str(&format!("{}/<0;1>/*", &self.user_xpub.value));
         self.user_xpub.valid = user_key.is_ok();
         if let Ok(key) = &user_key {
             self.user_xpub.valid = check_key_network(key, self.network);
         }
+        let user_fingerprint = user_key.as_ref().ok().map(|key| key.master_fingerprint());
 
-        let heir_key = DescriptorPublicKey::from_str(&format!("{}/<0;1>/*", &self.heir_xpub.value));
-        self.heir_xpub.valid = heir_key.is_ok();
-        if let Ok(key) = &heir_key {
-            self.heir_xpub.valid = check_key_network(key, self.network);
-        }
+        let mut recovery_paths = BTreeMap::new();
+        let mut recovery_paths_valid = true;
+        for path in self.recovery_paths.iter_mut() {
+            let heir_key =
+                DescriptorPublicKey::from_str(&format!("{}/<0;1>/*", &path.heir_xpub.value));
+            path.heir_xpub.valid = heir_key.is_ok();
+            if let Ok(key) = &heir_key {
+                path.heir_xpub.valid = check_key_network(key, self.network);
 
-        let sequence = self.sequence.value.parse::<u16>();
-        self.sequence.valid = sequence.is_ok();
+                // Using the same key for the primary and a recovery path would make the timelock
+                // pointless.
+                if Some(key.master_fingerprint()) == user_fingerprint {
+                    path.heir_xpub.valid = false;
+                    self.user_xpub.valid = false;
+                    self.error = Some(
+                        "The same key is used for the primary path and a recovery path. The \
+                        recovery path would provide no additional security."
+                            .to_string(),
+                    );
+                }
+            }
 
-        if !self.network_valid
-            || !self.user_xpub.valid
-            || !self.heir_xpub.valid
-            || !self.sequence.valid
-        {
+            let sequence = path.sequence();
+            // Two recovery paths can't share the same timelock.
+            path.sequence.valid = sequence
+                .as_ref()
+                .map(|seq| !recovery_paths.contains_key(seq))
+                .unwrap_or(false);
+
+            if !path.heir_xpub.valid || !path.sequence.valid {
+                recovery_paths_valid = false;
+                continue;
+            }
+            recovery_paths.insert(sequence.unwrap(), PathInfo::Single(heir_key.unwrap()));
+        }
+
+        if !self.network_status.is_valid() || !self.user_xpub.valid || !recovery_paths_valid {
             return false;
         }
 
         let desc =
-            match MultipathDescriptor::new(user_key.unwrap(), heir_key.unwrap(), sequence.unwrap())
-            {
+            match MultipathDescriptor::new(PathInfo::Single(user_key.unwrap()), recovery_paths) {
                 Ok(desc) => desc,
                 Err(e) => {
                     self.error = Some(e.to_string());
@@ -165,13 +301,29 @@ impl Step for DefineDescriptor {
         if let Some(modal) = &self.modal {
             modal.view()
         } else {
+            let removable = self.recovery_paths.len() > 1;
             view::define_descriptor(
                 progress,
                 self.network,
-                self.network_valid,
+                self.network_status,
                 &self.user_xpub,
-                &self.heir_xpub,
-                &self.sequence,
+                self.recovery_paths
+                    .iter()
+                    .enumerate()
+                    .map(|(i, path)| {
+                        view::recovery_path(
+                            i,
+                            self.network,
+                            &path.heir_xpub,
+                            &path.sequence,
+                            path.sequence_unit,
+                            path.sequence().map(|seq| (seq, sequence_duration(seq))),
+                            removable,
+                        )
+                    })
+                    .collect(),
+                self.user_xpub.value.is_empty()
+                    && self.recovery_paths.iter().all(|path| path.is_empty()),
                 self.error.as_ref(),
             )
         }
@@ -180,20 +332,8 @@ impl Step for DefineDescriptor {
 
 fn check_key_network(key: &DescriptorPublicKey, network: Network) -> bool {
     match key {
-        DescriptorPublicKey::XPub(key) => {
-            if network == Network::Bitcoin {
-                key.xkey.network == Network::Bitcoin
-            } else {
-                key.xkey.network == Network::Testnet
-            }
-        }
-        DescriptorPublicKey::MultiXPub(key) => {
-            if network == Network::Bitcoin {
-                key.xkey.network == Network::Bitcoin
-            } else {
-                key.xkey.network == Network::Testnet
-            }
-        }
+        DescriptorPublicKey::XPub(key) => key.xkey.network == xpub_network(network),
+        DescriptorPublicKey::MultiXPub(key) => key.xkey.network == xpub_network(network),
         _ => true,
     }
 }
@@ -211,40 +351,64 @@ impl From<DefineDescriptor> for Box<dyn Step> {
 }
 
 pub struct GetHardwareWalletXpubModal {
-    is_heir: bool,
+    /// The index of the recovery path this key is imported for, if it's not the user's key.
+    heir_index: Option<usize>,
     chosen_hw: Option<usize>,
     processing: bool,
     hws: Vec<HardwareWallet>,
+    /// The devices that were detected but could not be used.
+    hw_errors: Vec<(DeviceKind, hw::Error)>,
     error: Option<Error>,
     network: Network,
+    /// The index of the BIP48 account to get the xpub for.
+    account: form::Value<String>,
 }
 
 impl GetHardwareWalletXpubModal {
-    fn new(is_heir: bool, network: Network) -> Self {
+    fn new(heir_index: Option<usize>, network: Network) -> Self {
         Self {
-            is_heir,
+            heir_index,
             chosen_hw: None,
             processing: false,
             hws: Vec::new(),
+            hw_errors: Vec::new(),
             error: None,
             network,
+            account: form::Value {
+                value: "0".to_string(),
+                valid: true,
+            },
         }
     }
+    fn derivation_path(&self) -> Option<DerivationPath> {
+        self.account
+            .value
+            .parse::<u32>()
+            .ok()
+            .and_then(|account| liana_derivation_path(self.network, account))
+    }
     fn load(&self) -> Command<Message> {
         Command::perform(
-            list_hardware_wallets(&[], None),
-            Message::ConnectedHardwareWallets,
+            list_devices(&HashMap::new(), None),
+            Message::ConnectedDevices,
         )
     }
     fn update(&mut self, message: Message) -> Command<Message> {
         match message {
             Message::Select(i) => {
                 if let Some(hw) = self.hws.get(i) {
+                    let derivation_path = match self.derivation_path() {
+                        Some(path) => path,
+                        None => {
+                            self.account.valid = false;
+                            return Command::none();
+                        }
+                    };
                     let device = hw.device.clone();
                     self.chosen_hw = Some(i);
                     self.processing = true;
                     return Command::perform(
-                        get_extended_pubkey(device, hw.fingerprint, self.network),
+                        get_extended_pubkey(device, hw.fingerprint, derivation_path),
                         |res| {
                             Message::DefineDescriptor(message::DefineDescriptor::XpubImported(
                                 res.map(|key| key.to_string()),
@@ -253,8 +417,15 @@ impl GetHardwareWalletXpubModal {
                     );
                 }
             }
-            Message::ConnectedHardwareWallets(hws) => {
-                self.hws = hws;
+            Message::ConnectedDevices(devices) => {
+                self.hws = devices.hws;
+                self.hw_errors = devices.errors;
+            }
+            Message::DefineDescriptor(message::DefineDescriptor::AccountEdited(account)) => {
+                if account.is_empty() || account.parse::<u32>().is_ok() {
+                    self.account.value = account;
+                    self.account.valid = self.derivation_path().is_some();
+                }
             }
             Message::Reload => {
                 return self.load();
@@ -263,11 +434,10 @@ impl GetHardwareWalletXpubModal {
                 self.processing = false;
                 match res {
                     Ok(key) => {
-                        if self.is_heir {
-                            return Command::perform(
-                                async move { key },
-                                message::DefineDescriptor::HeirXpubEdited,
-                            )
+                        if let Some(i) = self.heir_index {
+                            return Command::perform(async move { key }, move |key| {
+                                message::DefineDescriptor::HeirXpubEdited(i, key)
+                            })
                             .map(Message::DefineDescriptor);
                         } else {
                             return Command::perform(
@@ -288,8 +458,11 @@ impl GetHardwareWalletXpubModal {
     }
     fn view(&self) -> Element<Message> {
         view::hardware_wallet_xpubs_modal(
-            self.is_heir,
+            self.heir_index.is_some(),
+            self.network,
+            &self.account,
             &self.hws,
+            &self.hw_errors,
             self.error.as_ref(),
             self.processing,
             self.chosen_hw,
@@ -322,18 +495,14 @@ impl std::fmt::Display for XKey {
 async fn get_extended_pubkey(
     hw: std::sync::Arc<dyn async_hwi::HWI + Send + Sync>,
     fingerprint: Fingerprint,
-    network: Network,
+    derivation_path: DerivationPath,
 ) -> Result<XKey, Error> {
-    let derivation_path = DerivationPath::from_str(if network == Network::Bitcoin {
-        LIANA_STANDARD_PATH
-    } else {
-        LIANA_TESTNET_STANDARD_PATH
-    })
-    .unwrap();
-    let key = hw
-        .get_extended_pubkey(&derivation_path, false)
-        .await
-        .map_err(Error::from)?;
+    // The user may be prompted to unlock the device first.
+    let key = with_timeout(
+        UNLOCK_TIMEOUT,
+        hw.get_extended_pubkey(&derivation_path, false),
+    )
+    .await?;
     Ok(XKey {
         origin: Some((fingerprint, derivation_path)),
         key,
@@ -342,9 +511,10 @@ async fn get_extended_pubkey(
 
 pub struct ImportDescriptor {
     network: Network,
-    network_valid: bool,
+    network_status: DataDirStatus,
     data_dir: Option<PathBuf>,
     imported_descriptor: form::Value<String>,
+    birthday: form::Value<String>,
     error: Option<String>,
 }
 
@@ -352,9 +522,10 @@ impl ImportDescriptor {
     pub fn new() -> Self {
         Self {
             network: Network::Bitcoin,
-            network_valid: true,
+            network_status: DataDirStatus::Missing,
             data_dir: None,
             imported_descriptor: form::Value::default(),
+            birthday: form::Value::default(),
             error: None,
         }
     }
@@ -367,14 +538,17 @@ impl Step for ImportDescriptor {
         match message {
             Message::Network(network) => {
                 self.network = network;
-                let mut network_datadir = self.data_dir.clone().unwrap();
-                network_datadir.push(self.network.to_string());
-                self.network_valid = !network_datadir.exists();
+                self.network_status =
+                    network_datadir_status(self.data_dir.as_ref().unwrap(), self.network);
             }
             Message::DefineDescriptor(message::DefineDescriptor::ImportDescriptor(desc)) => {
                 self.imported_descriptor.value = desc;
                 self.imported_descriptor.valid = true;
             }
+            Message::DefineDescriptor(message::DefineDescriptor::BirthdayEdited(date)) => {
+                self.birthday.value = date;
+                self.birthday.valid = true;
+            }
             _ => {}
         };
         Command::none()
@@ -383,22 +557,50 @@ impl Step for ImportDescriptor {
     fn load_context(&mut self, ctx: &Context) {
         self.network = ctx.bitcoin_config.network;
         self.data_dir = Some(ctx.data_dir.clone());
-        let mut network_datadir = ctx.data_dir.clone();
-        network_datadir.push(self.network.to_string());
-        self.network_valid = !network_datadir.exists();
+        self.network_status = network_datadir_status(&ctx.data_dir, self.network);
     }
 
     fn apply(&mut self, ctx: &mut Context) -> bool {
         ctx.bitcoin_config.network = self.network;
+        if !self.network_status.is_valid() {
+            return false;
+        }
+        // The creation date of the wallet is optional, but if given it must be valid.
+        ctx.birthday_timestamp = None;
+        if !self.birthday.value.trim().is_empty() {
+            match parse_birthday(&self.birthday.value) {
+                Ok(timestamp) => {
+                    self.birthday.valid = true;
+                    ctx.birthday_timestamp = Some(timestamp);
+                }
+                Err(e) => {
+                    self.birthday.valid = false;
+                    self.error = Some(e);
+                    return false;
+                }
+            }
+        }
         // descriptor forms for import or creation cannot be both empty or filled.
         if !self.imported_descriptor.value.is_empty() {
-            if let Ok(desc) = MultipathDescriptor::from_str(&self.imported_descriptor.value) {
-                self.imported_descriptor.valid = true;
-                ctx.descriptor = Some(desc);
-                true
+            // The user may either paste a descriptor or give the path to a backup file.
+            let backup_path = Path::new(self.imported_descriptor.value.trim());
+            let res = if backup_path.is_file() {
+                check_imported_backup(backup_path, self.network)
             } else {
-                self.imported_descriptor.valid = false;
-                false
+                check_imported_descriptor(&self.imported_descriptor.value, self.network)
+            };
+            match res {
+                Ok(desc) => {
+                    self.imported_descriptor.valid = true;
+                    self.error = None;
+                    ctx.descriptor = Some(desc);
+                    true
+                }
+                Err(e) => {
+                    self.imported_descriptor.valid = false;
+                    self.error = Some(e);
+                    false
+                }
             }
         } else {
             false
@@ -409,13 +611,60 @@ impl Step for ImportDescriptor {
         view::import_descriptor(
             progress,
             self.network,
-            self.network_valid,
+            self.network_status,
             &self.imported_descriptor,
+            &self.birthday,
             self.error.as_ref(),
         )
     }
 }
 
+/// Parse the creation date of an imported wallet, given as `YYYY-MM-DD`, into a UNIX timestamp.
+fn parse_birthday(date: &str) -> Result<u32, String> {
+    let timestamp = chrono::NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+        .map_err(|e| format!("Invalid wallet creation date: {}", e))?
+        .and_hms(0, 0, 0)
+        .timestamp();
+    if timestamp > chrono::Utc::now().timestamp() {
+        return Err("The wallet creation date is in the future".to_string());
+    }
+    timestamp
+        .try_into()
+        .map_err(|_| "The wallet creation date is out of range".to_string())
+}
+
+/// Parse a descriptor imported by the user, making sure it is a Liana descriptor (a primary
+/// spending path and timelocked recovery paths, with keys for both receive and change addresses)
+/// for the given network.
+fn check_imported_descriptor(desc: &str, network: Network) -> Result<MultipathDescriptor, String> {
+    let desc = MultipathDescriptor::from_str(desc.trim())
+        .map_err(|e| format!("Invalid descriptor: {}", e))?;
+
+    if !desc.all_xpubs_net_is(xpub_network(network)) {
+        return Err(format!(
+            "The descriptor keys are not valid for network {}",
+            network
+        ));
+    }
+
+    Ok(desc)
+}
+
+/// Read the wallet backup file at this path, and check the descriptor it contains as we would for
+/// a descriptor imported by the user.
+fn check_imported_backup(path: &Path, network: Network) -> Result<MultipathDescriptor, String> {
+    let content =
+        std::fs::read_to_string(path).map_err(|e| format!("Could not read backup file: {}", e))?;
+    let backup = WalletBackup::from_json(&content).map_err(|e| e.to_string())?;
+    if backup.network != network {
+        return Err(format!(
+            "The backup is for network {}, not {}",
+            backup.network, network
+        ));
+    }
+    check_imported_descriptor(&backup.descriptor.to_string(), network)
+}
+
 impl Default for ImportDescriptor {
     fn default() -> Self {
         Self::new()
@@ -431,28 +680,64 @@ impl From<ImportDescriptor> for Box<dyn Step> {
 #[derive(Default)]
 pub struct RegisterDescriptor {
     descriptor: Option<MultipathDescriptor>,
+    /// Fingerprints of the signing devices of the primary path, on which the descriptor must be
+    /// registered before going further.
+    required_fingerprints: Vec<Fingerprint>,
     processing: bool,
     chosen_hw: Option<usize>,
-    hws: Vec<(HardwareWallet, Option<[u8; 32]>, bool)>,
+    hws: Vec<HardwareWallet>,
+    /// The devices that were detected but could not be used.
+    hw_errors: Vec<(DeviceKind, hw::Error)>,
+    /// The devices the descriptor was registered on, along with the token they returned if any.
+    registered: HashMap<Fingerprint, (DeviceKind, Option<[u8; 32]>)>,
     error: Option<Error>,
 }
 
+impl RegisterDescriptor {
+    fn all_registered(&self) -> bool {
+        self.required_fingerprints
+            .iter()
+            .all(|fg| self.registered.contains_key(fg))
+    }
+}
+
+/// The master fingerprints of the keys of the primary spending path, for those whose origin is
+/// known. Keys without origin can't be matched to a signing device.
+fn primary_path_fingerprints(desc: &MultipathDescriptor) -> Vec<Fingerprint> {
+    let mut fingerprints = Vec::new();
+    for key in desc.info().primary_path().keys() {
+        if let DescriptorPublicKey::MultiXPub(xpub) = key {
+            if let Some((fingerprint, _)) = &xpub.origin {
+                if !fingerprints.contains(fingerprint) {
+                    fingerprints.push(*fingerprint);
+                }
+            }
+        }
+    }
+    fingerprints
+}
+
 impl Step for RegisterDescriptor {
     fn load_context(&mut self, ctx: &Context) {
         self.descriptor = ctx.descriptor.clone();
+        self.required_fingerprints = self
+            .descriptor
+            .as_ref()
+            .map(primary_path_fingerprints)
+            .unwrap_or_default();
     }
     fn update(&mut self, message: Message) -> Command<Message> {
         match message {
             Message::Select(i) => {
-                if let Some((hw, hmac, _)) = self.hws.get(i) {
-                    if hmac.is_none() {
+                if let Some(hw) = self.hws.get(i) {
+                    if !self.registered.contains_key(&hw.fingerprint) {
                         let device = hw.device.clone();
                         let descriptor = self.descriptor.as_ref().unwrap().to_string();
                         self.chosen_hw = Some(i);
                         self.processing = true;
                         self.error = None;
                         return Command::perform(
-                            register_wallet(device, hw.fingerprint, descriptor),
+                            register_wallet(device, hw.kind, hw.fingerprint, descriptor),
                             Message::WalletRegistered,
                         );
                     }
@@ -462,29 +747,25 @@ impl Step for RegisterDescriptor {
                 self.processing = false;
                 self.chosen_hw = None;
                 match res {
-                    Ok((fingerprint, hmac)) => {
-                        if let Some(hw_h) = self
-                            .hws
-                            .iter_mut()
-                            .find(|hw_h| hw_h.0.fingerprint == fingerprint)
-                        {
-                            hw_h.1 = hmac;
-                            hw_h.2 = true;
+                    Ok((kind, fingerprint, hmac)) => {
+                        // Some devices may not return the token on success, but we won't be able
+                        // to sign with them without it.
+                        if hmac.is_none() && requires_registration_token(&kind) {
+                            self.error = Some(Error::MissingRegistrationToken(fingerprint));
+                        } else {
+                            self.registered.insert(fingerprint, (kind, hmac));
                         }
                     }
                     Err(e) => self.error = Some(e),
                 }
             }
-            Message::ConnectedHardwareWallets(hws) => {
-                for hw in hws {
-                    if !self
-                        .hws
-                        .iter()
-                        .any(|(h, _, _)| h.fingerprint == hw.fingerprint)
-                    {
-                        self.hws.push((hw, None, false));
+            Message::ConnectedDevices(devices) => {
+                for hw in devices.hws {
+                    if !self.hws.iter().any(|h| h.fingerprint == hw.fingerprint) {
+                        self.hws.push(hw);
                     }
                 }
+                self.hw_errors = devices.errors;
             }
             Message::Reload => {
                 return self.load();
@@ -494,17 +775,21 @@ impl Step for RegisterDescriptor {
         Command::none()
     }
     fn apply(&mut self, ctx: &mut Context) -> bool {
-        for (hw, token, registered) in &self.hws {
-            if *registered {
-                ctx.hws.push((hw.kind, hw.fingerprint, *token));
-            }
+        // The descriptor must be registered on all the devices of the primary path.
+        if !self.all_registered() {
+            return false;
         }
+        ctx.hws = self
+            .registered
+            .iter()
+            .map(|(fingerprint, (kind, token))| (*kind, *fingerprint, *token))
+            .collect();
         true
     }
     fn load(&self) -> Command<Message> {
         Command::perform(
-            list_hardware_wallets(&[], None),
-            Message::ConnectedHardwareWallets,
+            list_devices(&HashMap::new(), None),
+            Message::ConnectedDevices,
         )
     }
     fn view(&self, progress: (usize, usize)) -> Element<Message> {
@@ -513,6 +798,9 @@ impl Step for RegisterDescriptor {
             progress,
             desc.to_string(),
             &self.hws,
+            &self.hw_errors,
+            &self.required_fingerprints,
+            &self.registered,
             self.error.as_ref(),
             self.processing,
             self.chosen_hw,
@@ -522,14 +810,15 @@ impl Step for RegisterDescriptor {
 
 async fn register_wallet(
     hw: std::sync::Arc<dyn async_hwi::HWI + Send + Sync>,
+    kind: DeviceKind,
     fingerprint: Fingerprint,
     descriptor: String,
-) -> Result<(Fingerprint, Option<[u8; 32]>), Error> {
+) -> Result<(DeviceKind, Fingerprint, Option<[u8; 32]>), Error> {
     let hmac = hw
         .register_wallet("Liana", &descriptor)
         .await
         .map_err(Error::from)?;
-    Ok((fingerprint, hmac))
+    Ok((kind, fingerprint, hmac))
 }
 
 impl From<RegisterDescriptor> for Box<dyn Step> {
@@ -538,25 +827,147 @@ impl From<RegisterDescriptor> for Box<dyn Step> {
     }
 }
 
+/// A spending path of the descriptor, in terms the user can review.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathSummary {
+    /// The number of signatures required to spend through this path.
+    pub threshold: usize,
+    /// The master fingerprints of the keys of this path.
+    pub keys: Vec<Fingerprint>,
+    /// The relative timelock in blocks and the approximate time it represents, for a recovery
+    /// path.
+    pub timelock: Option<(u16, String)>,
+}
+
+impl PathSummary {
+    fn new(path: &PathInfo, timelock: Option<u16>) -> Self {
+        Self {
+            threshold: path.threshold(),
+            keys: path
+                .keys()
+                .into_iter()
+                .map(|key| key.master_fingerprint())
+                .collect(),
+            timelock: timelock.map(|seq| (seq, sequence_duration(seq))),
+        }
+    }
+
+    /// Who can spend through this path, and when.
+    pub fn description(&self) -> String {
+        let who = if self.keys.len() == 1 {
+            format!("The key {}", self.keys[0])
+        } else {
+            let keys: Vec<String> = self.keys.iter().map(|k| k.to_string()).collect();
+            format!("{} of the keys {}", self.threshold, keys.join(", "))
+        };
+        match &self.timelock {
+            None => format!("{} can spend the coins at any time.", who),
+            Some((blocks, duration)) => format!(
+                "{} can spend the coins once they have been unmoved for {} blocks ({}).",
+                who, blocks, duration
+            ),
+        }
+    }
+}
+
+/// The spending policy of a descriptor: the primary path and the recovery paths, ordered by
+/// timelock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicySummary {
+    pub primary: PathSummary,
+    pub recovery: Vec<PathSummary>,
+}
+
+impl PolicySummary {
+    pub fn new(descriptor: &MultipathDescriptor) -> Self {
+        let info = descriptor.info();
+        Self {
+            primary: PathSummary::new(info.primary_path(), None),
+            recovery: info
+                .recovery_paths()
+                .iter()
+                .map(|(seq, path)| PathSummary::new(path, Some(*seq)))
+                .collect(),
+        }
+    }
+}
+
+/// Let the user review in plain language the policy of the descriptor before going further.
+#[derive(Default)]
+pub struct PolicyPreview {
+    summary: Option<PolicySummary>,
+}
+
+impl Step for PolicyPreview {
+    fn load_context(&mut self, ctx: &Context) {
+        self.summary = ctx.descriptor.as_ref().map(PolicySummary::new);
+    }
+    fn view(&self, progress: (usize, usize)) -> Element<Message> {
+        view::policy_preview(progress, self.summary.as_ref())
+    }
+}
+
+impl From<PolicyPreview> for Box<dyn Step> {
+    fn from(s: PolicyPreview) -> Box<dyn Step> {
+        Box::new(s)
+    }
+}
+
 #[derive(Default)]
 pub struct BackupDescriptor {
     done: bool,
     descriptor: Option<MultipathDescriptor>,
+    network: Option<Network>,
+    data_dir: Option<PathBuf>,
+    exported: Option<Result<PathBuf, Error>>,
+}
+
+/// Write a structured backup of a freshly created wallet in the data directory, returning the
+/// path to the backup file.
+fn export_backup(
+    descriptor: &MultipathDescriptor,
+    network: Network,
+    data_dir: &Path,
+) -> Result<PathBuf, Error> {
+    std::fs::create_dir_all(data_dir).map_err(|e| Error::CannotCreateDatadir(e.to_string()))?;
+    let backup = WalletBackup::new(descriptor.clone(), network, 0.into(), 0.into());
+    let path = data_dir.join(format!(
+        "liana-{}-{}-backup.json",
+        network,
+        descriptor.checksum()
+    ));
+    std::fs::write(&path, backup.to_json()).map_err(|e| Error::CannotWriteToFile(e.to_string()))?;
+    Ok(path)
 }
 
 impl Step for BackupDescriptor {
     fn update(&mut self, message: Message) -> Command<Message> {
-        if let Message::BackupDone(done) = message {
-            self.done = done;
+        match message {
+            Message::BackupDone(done) => self.done = done,
+            Message::ExportBackup => {
+                if let (Some(desc), Some(network), Some(data_dir)) =
+                    (&self.descriptor, self.network, &self.data_dir)
+                {
+                    self.exported = Some(export_backup(desc, network, data_dir));
+                }
+            }
+            _ => {}
         }
         Command::none()
     }
     fn load_context(&mut self, ctx: &Context) {
         self.descriptor = ctx.descriptor.clone();
+        self.network = Some(ctx.bitcoin_config.network);
+        self.data_dir = Some(ctx.data_dir.clone());
     }
     fn view(&self, progress: (usize, usize)) -> Element<Message> {
         let desc = self.descriptor.as_ref().unwrap();
-        view::backup_descriptor(progress, desc.to_string(), self.done)
+        view::backup_descriptor(
+            progress,
+            desc.to_string(),
+            self.exported.as_ref(),
+            self.done,
+        )
     }
 }
 
@@ -565,3 +976,477 @@ impl From<BackupDescriptor> for Box<dyn Step> {
         Box::new(s)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    const TESTNET_DESC: &str = "wsh(andor(pk(tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk(tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#5f6qd0d9";
+
+    fn import(step: &mut ImportDescriptor, ctx: &mut Context, desc: &str) -> bool {
+        let _ = step.update(Message::DefineDescriptor(
+            message::DefineDescriptor::ImportDescriptor(desc.to_string()),
+        ));
+        step.apply(ctx)
+    }
+
+    fn define_descriptor_step(
+        user_xpub: &str,
+        heir_xpub: &str,
+        sequence: &str,
+    ) -> (DefineDescriptor, Context) {
+        let ctx = Context::new(Network::Testnet, std::env::temp_dir());
+        let mut step = DefineDescriptor::new();
+        step.load_context(&ctx);
+        let _ = step.update(Message::DefineDescriptor(
+            message::DefineDescriptor::UserXpubEdited(user_xpub.to_string()),
+        ));
+        let _ = step.update(Message::DefineDescriptor(
+            message::DefineDescriptor::HeirXpubEdited(0, heir_xpub.to_string()),
+        ));
+        let _ = step.update(Message::DefineDescriptor(
+            message::DefineDescriptor::SequenceEdited(0, sequence.to_string()),
+        ));
+        (step, ctx)
+    }
+
+    #[test]
+    fn define_descriptor_key_reuse() {
+        let xpub_a = "tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr";
+        let xpub_b = "tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV";
+
+        // The same xpub for both the primary and the recovery paths is refused.
+        let (mut step, mut ctx) = define_descriptor_step(xpub_a, xpub_a, "1000");
+        assert!(!step.apply(&mut ctx));
+        assert!(step.error.as_ref().unwrap().contains("same key"));
+        assert!(!step.user_xpub.valid);
+        assert!(!step.recovery_paths[0].heir_xpub.valid);
+        assert!(ctx.descriptor.is_none());
+
+        // Different xpubs are fine.
+        let (mut step, mut ctx) = define_descriptor_step(xpub_a, xpub_b, "1000");
+        assert!(step.apply(&mut ctx));
+        assert!(step.error.is_none());
+        assert!(ctx.descriptor.is_some());
+    }
+
+    #[test]
+    fn key_network() {
+        let tpub = DescriptorPublicKey::from_str("tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*").unwrap();
+        let xpub = DescriptorPublicKey::from_str("xpub68JJTXc1MWK8KLW4HGLXZBJknja7kDUJuFHnM424LbziEXsfkh1WQCiEjjHw4zLqSUm4rvhgyGkkuRowE9tCJSgt3TQB5J3SKAbZ2SdcKST/<0;1>/*").unwrap();
+
+        // Signet and regtest use the testnet encoding of extended keys.
+        for network in &[Network::Testnet, Network::Signet, Network::Regtest] {
+            assert!(check_key_network(&tpub, *network));
+            assert!(!check_key_network(&xpub, *network));
+        }
+        assert!(check_key_network(&xpub, Network::Bitcoin));
+        assert!(!check_key_network(&tpub, Network::Bitcoin));
+
+        // Same when importing a descriptor.
+        assert!(check_imported_descriptor(TESTNET_DESC, Network::Signet).is_ok());
+        assert!(check_imported_descriptor(TESTNET_DESC, Network::Regtest).is_ok());
+        assert!(check_imported_descriptor(TESTNET_DESC, Network::Bitcoin).is_err());
+    }
+
+    #[test]
+    fn xpub_modal_derivation_path() {
+        assert_eq!(
+            liana_derivation_path(Network::Bitcoin, 0)
+                .unwrap()
+                .to_string(),
+            "m/48'/0'/0'/2'"
+        );
+        assert_eq!(
+            liana_derivation_path(Network::Testnet, 0)
+                .unwrap()
+                .to_string(),
+            "m/48'/1'/0'/2'"
+        );
+        // The account index must be hardened.
+        assert!(liana_derivation_path(Network::Bitcoin, 1 << 31).is_none());
+
+        // The user may choose another account.
+        let mut modal = GetHardwareWalletXpubModal::new(None, Network::Bitcoin);
+        assert_eq!(
+            modal.derivation_path().unwrap().to_string(),
+            "m/48'/0'/0'/2'"
+        );
+        let _ = modal.update(Message::DefineDescriptor(
+            message::DefineDescriptor::AccountEdited("7".to_string()),
+        ));
+        assert!(modal.account.valid);
+        assert_eq!(
+            modal.derivation_path().unwrap().to_string(),
+            "m/48'/0'/7'/2'"
+        );
+        let mut modal = GetHardwareWalletXpubModal::new(Some(0), Network::Signet);
+        let _ = modal.update(Message::DefineDescriptor(
+            message::DefineDescriptor::AccountEdited("42".to_string()),
+        ));
+        assert_eq!(
+            modal.derivation_path().unwrap().to_string(),
+            "m/48'/1'/42'/2'"
+        );
+
+        // An account index out of the hardened range isn't valid, and neither is an empty one.
+        let _ = modal.update(Message::DefineDescriptor(
+            message::DefineDescriptor::AccountEdited((1u32 << 31).to_string()),
+        ));
+        assert!(!modal.account.valid);
+        assert!(modal.derivation_path().is_none());
+        let _ = modal.update(Message::DefineDescriptor(
+            message::DefineDescriptor::AccountEdited(String::new()),
+        ));
+        assert!(!modal.account.valid);
+    }
+
+    #[test]
+    fn register_descriptor_multiple_devices() {
+        let primary_keys: Vec<DescriptorPublicKey> = [
+            "[aabbccdd/48'/1'/0'/2']tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*",
+            "[11223344/48'/1'/0'/2']tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*",
+            "[deadbeef/48'/1'/0'/2']tpubDExU4YLJkyQ9RRbVScQq2brFxWWha7WmAUByPWyaWYwmcTv3Shx8aHp6mVwuE5n4TeM4z5DTWGf2YhNPmXtfvyr8cUDVvA3txdrFnFgNdF7/<0;1>/*",
+        ]
+        .iter()
+        .map(|k| DescriptorPublicKey::from_str(k).unwrap())
+        .collect();
+        let heir_key = DescriptorPublicKey::from_str("[cafebabe/48'/1'/0'/2']tpubD6NzVbkrYhZ4WzTf9SsD6h7AH7oQEippXK2KP8qvhMMqFoNeN5YFVi7vRyeRSDGtgd2bPyMxUNmHui8t5yCgszxPPxMafu1VVzDpg9aruYW/<0;1>/*").unwrap();
+        let mut recovery_paths = BTreeMap::new();
+        recovery_paths.insert(1000, PathInfo::Single(heir_key));
+        let desc =
+            MultipathDescriptor::new(PathInfo::Multi(2, primary_keys), recovery_paths).unwrap();
+
+        let mut ctx = Context::new(Network::Testnet, std::env::temp_dir());
+        ctx.descriptor = Some(desc);
+        let mut step = RegisterDescriptor::default();
+        step.load_context(&ctx);
+
+        // All the devices of the primary path must register the descriptor, but not the heir's.
+        let fingerprints: Vec<Fingerprint> = ["aabbccdd", "11223344", "deadbeef"]
+            .iter()
+            .map(|fg| Fingerprint::from_str(fg).unwrap())
+            .collect();
+        assert_eq!(step.required_fingerprints, fingerprints);
+        assert!(!step.apply(&mut ctx));
+
+        // Registration completes one device at a time.
+        let _ = step.update(Message::WalletRegistered(Ok((
+            DeviceKind::Ledger,
+            fingerprints[0],
+            Some([1; 32]),
+        ))));
+        assert!(!step.apply(&mut ctx));
+        let _ = step.update(Message::WalletRegistered(Err(Error::Unexpected(
+            "Device disconnected".to_string(),
+        ))));
+        assert!(step.error.is_some());
+        assert!(!step.apply(&mut ctx));
+        let _ = step.update(Message::WalletRegistered(Ok((
+            DeviceKind::Ledger,
+            fingerprints[1],
+            Some([2; 32]),
+        ))));
+        assert!(!step.apply(&mut ctx));
+        // Some devices don't return a token.
+        let _ = step.update(Message::WalletRegistered(Ok((
+            DeviceKind::Specter,
+            fingerprints[2],
+            None,
+        ))));
+        assert!(step.apply(&mut ctx));
+        assert_eq!(ctx.hws.len(), 3);
+    }
+
+    #[test]
+    fn register_descriptor_missing_token() {
+        let mut step = RegisterDescriptor::default();
+        let fingerprint = Fingerprint::from_str("aabbccdd").unwrap();
+        step.required_fingerprints = vec![fingerprint];
+
+        // A Ledger needs the token to sign later on. If it doesn't return it, the user is told to
+        // register again.
+        let _ = step.update(Message::WalletRegistered(Ok((
+            DeviceKind::Ledger,
+            fingerprint,
+            None,
+        ))));
+        assert!(matches!(
+            step.error,
+            Some(Error::MissingRegistrationToken(fg)) if fg == fingerprint
+        ));
+        assert!(step.registered.is_empty());
+        let mut ctx = Context::new(Network::Testnet, std::env::temp_dir());
+        assert!(!step.apply(&mut ctx));
+
+        // Once it does, we can go on.
+        let _ = step.update(Message::WalletRegistered(Ok((
+            DeviceKind::Ledger,
+            fingerprint,
+            Some([3; 32]),
+        ))));
+        assert!(step.apply(&mut ctx));
+        assert_eq!(ctx.hws.len(), 1);
+        assert!(matches!(ctx.hws[0].0, DeviceKind::Ledger));
+        assert_eq!((ctx.hws[0].1, ctx.hws[0].2), (fingerprint, Some([3; 32])));
+    }
+
+    #[test]
+    fn import_descriptor_validation() {
+        let mut ctx = Context::new(Network::Testnet, std::env::temp_dir());
+        let mut step = ImportDescriptor::new();
+        step.load_context(&ctx);
+
+        // Not a descriptor.
+        assert!(!import(&mut step, &mut ctx, "wsh(andor(pk("));
+        assert!(step
+            .error
+            .as_ref()
+            .unwrap()
+            .starts_with("Invalid descriptor"));
+        assert!(!step.imported_descriptor.valid);
+
+        // A key without the multipath step for change addresses.
+        let desc = "wsh(andor(pk(tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/0/*),older(10000),pk(tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))";
+        assert!(!import(&mut step, &mut ctx, desc));
+        assert!(step.error.as_ref().unwrap().contains("Invalid key"));
+
+        // No timelocked recovery path.
+        let desc = "wsh(or_d(pk(tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),pk(tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))";
+        assert!(!import(&mut step, &mut ctx, desc));
+        assert!(step.error.is_some());
+        assert!(ctx.descriptor.is_none());
+
+        // A valid descriptor, but for another network.
+        let mut mainnet_ctx = Context::new(Network::Bitcoin, std::env::temp_dir());
+        let mut mainnet_step = ImportDescriptor::new();
+        mainnet_step.load_context(&mainnet_ctx);
+        assert!(!import(&mut mainnet_step, &mut mainnet_ctx, TESTNET_DESC));
+        assert!(mainnet_step
+            .error
+            .as_ref()
+            .unwrap()
+            .contains("not valid for network"));
+
+        // Finally, a valid descriptor.
+        assert!(import(&mut step, &mut ctx, TESTNET_DESC));
+        assert!(step.error.is_none());
+        assert_eq!(
+            ctx.descriptor.as_ref().unwrap().to_string(),
+            MultipathDescriptor::from_str(TESTNET_DESC)
+                .unwrap()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn import_descriptor_birthday() {
+        let mut ctx = Context::new(Network::Testnet, std::env::temp_dir());
+        let mut step = ImportDescriptor::new();
+        step.load_context(&ctx);
+
+        // The creation date is optional.
+        assert!(import(&mut step, &mut ctx, TESTNET_DESC));
+        assert!(ctx.birthday_timestamp.is_none());
+
+        // But must be valid if given.
+        for date in &["2023-13-01", "01/01/2023", "2999-01-01"] {
+            let _ = step.update(Message::DefineDescriptor(
+                message::DefineDescriptor::BirthdayEdited(date.to_string()),
+            ));
+            assert!(!import(&mut step, &mut ctx, TESTNET_DESC));
+            assert!(!step.birthday.valid);
+        }
+
+        // It's stored in the context as a timestamp, to be written in the daemon configuration.
+        let _ = step.update(Message::DefineDescriptor(
+            message::DefineDescriptor::BirthdayEdited("2023-01-01".to_string()),
+        ));
+        assert!(import(&mut step, &mut ctx, TESTNET_DESC));
+        assert_eq!(ctx.birthday_timestamp, Some(1_672_531_200));
+        let config = liana::config::Config::try_from(ctx).unwrap();
+        assert_eq!(config.birthday_timestamp, Some(1_672_531_200));
+    }
+
+    #[test]
+    fn backup_file_roundtrip() {
+        // Export the backup file of a freshly created wallet.
+        let data_dir = std::env::temp_dir().join(format!("liana-backup-{}", std::process::id()));
+        let mut ctx = Context::new(Network::Testnet, data_dir.clone());
+        ctx.descriptor = Some(MultipathDescriptor::from_str(TESTNET_DESC).unwrap());
+        let mut backup_step = BackupDescriptor::default();
+        backup_step.load_context(&ctx);
+        let _ = backup_step.update(Message::ExportBackup);
+        let backup_path = backup_step.exported.clone().unwrap().unwrap();
+        let backup =
+            WalletBackup::from_json(&std::fs::read_to_string(&backup_path).unwrap()).unwrap();
+        assert_eq!(&backup.descriptor, ctx.descriptor.as_ref().unwrap());
+        assert_eq!(backup.network, Network::Testnet);
+        assert_eq!((backup.receive_index, backup.change_index), (0, 0));
+
+        // Reconstruct the wallet from this file.
+        let mut import_ctx = Context::new(Network::Testnet, data_dir.clone());
+        let mut step = ImportDescriptor::new();
+        step.load_context(&import_ctx);
+        assert!(import(
+            &mut step,
+            &mut import_ctx,
+            backup_path.to_str().unwrap()
+        ));
+        assert_eq!(import_ctx.descriptor, ctx.descriptor);
+
+        // But not for another network.
+        let mut mainnet_ctx = Context::new(Network::Bitcoin, data_dir.clone());
+        let mut mainnet_step = ImportDescriptor::new();
+        mainnet_step.load_context(&mainnet_ctx);
+        assert!(!import(
+            &mut mainnet_step,
+            &mut mainnet_ctx,
+            backup_path.to_str().unwrap()
+        ));
+        assert!(mainnet_step
+            .error
+            .as_ref()
+            .unwrap()
+            .contains("The backup is for network"));
+
+        std::fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn datadir_status() {
+        let data_dir =
+            std::env::temp_dir().join(format!("liana-datadir-status-{}", std::process::id()));
+        let network_datadir = data_dir.join(Network::Testnet.to_string());
+
+        // The directory does not exist yet.
+        assert_eq!(
+            network_datadir_status(&data_dir, Network::Testnet),
+            DataDirStatus::Missing
+        );
+
+        // An empty directory, or one with unrelated files, is reused.
+        std::fs::create_dir_all(&network_datadir).unwrap();
+        assert_eq!(
+            network_datadir_status(&data_dir, Network::Testnet),
+            DataDirStatus::NoWallet
+        );
+        std::fs::write(network_datadir.join("bitcoind.conf"), "server=1").unwrap();
+        std::fs::write(network_datadir.join("lianad.sqlite3"), "").unwrap();
+        assert_eq!(
+            network_datadir_status(&data_dir, Network::Testnet),
+            DataDirStatus::NoWallet
+        );
+
+        // A directory containing a wallet database is in use by another wallet.
+        let mut db = b"SQLite format 3\0".to_vec();
+        db.extend_from_slice(&[0; 84]);
+        std::fs::write(network_datadir.join("lianad.sqlite3"), &db).unwrap();
+        assert_eq!(
+            network_datadir_status(&data_dir, Network::Testnet),
+            DataDirStatus::HasWallet
+        );
+        // Only for this network though.
+        assert_eq!(
+            network_datadir_status(&data_dir, Network::Bitcoin),
+            DataDirStatus::Missing
+        );
+
+        // The wallet creation steps refuse to use it.
+        let mut ctx = Context::new(Network::Testnet, data_dir.clone());
+        let mut step = ImportDescriptor::new();
+        step.load_context(&ctx);
+        assert!(!import(&mut step, &mut ctx, TESTNET_DESC));
+        let mut step = ImportDescriptor::new();
+        step.load_context(&Context::new(Network::Bitcoin, data_dir.clone()));
+        let _ = step.update(Message::Network(Network::Testnet));
+        assert_eq!(step.network_status, DataDirStatus::HasWallet);
+        let _ = step.update(Message::Network(Network::Bitcoin));
+        assert_eq!(step.network_status, DataDirStatus::Missing);
+
+        std::fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn sequence_duration_to_blocks() {
+        assert_eq!(sequence_from_duration(1, SequenceUnit::Blocks), Some(1));
+        assert_eq!(sequence_from_duration(1, SequenceUnit::Days), Some(144));
+        assert_eq!(sequence_from_duration(1, SequenceUnit::Months), Some(4320));
+
+        // A null timelock is not a valid recovery path.
+        assert_eq!(sequence_from_duration(0, SequenceUnit::Blocks), None);
+        assert_eq!(sequence_from_duration(0, SequenceUnit::Days), None);
+
+        // Boundaries of the relative timelock.
+        assert_eq!(
+            sequence_from_duration(65535, SequenceUnit::Blocks),
+            Some(65535)
+        );
+        assert_eq!(sequence_from_duration(65536, SequenceUnit::Blocks), None);
+        assert_eq!(sequence_from_duration(455, SequenceUnit::Days), Some(65520));
+        assert_eq!(sequence_from_duration(456, SequenceUnit::Days), None);
+        assert_eq!(
+            sequence_from_duration(15, SequenceUnit::Months),
+            Some(64800)
+        );
+        assert_eq!(sequence_from_duration(16, SequenceUnit::Months), None);
+        assert_eq!(sequence_from_duration(u32::MAX, SequenceUnit::Days), None);
+
+        assert_eq!(sequence_duration(1), "~10 minutes");
+        assert_eq!(sequence_duration(6), "~1 hour");
+        assert_eq!(sequence_duration(143), "~24 hours");
+        assert_eq!(sequence_duration(144), "~1 day");
+        assert_eq!(sequence_duration(1000), "~7 days");
+        assert_eq!(sequence_duration(4320), "~30 days");
+        assert_eq!(sequence_duration(8640), "~2 months");
+        assert_eq!(sequence_duration(65535), "~15 months");
+
+        let mut path = RecoveryPath::default();
+        path.sequence.value = "2".to_string();
+        assert_eq!(path.sequence(), Some(2));
+        path.sequence_unit = SequenceUnit::Days;
+        assert_eq!(path.sequence(), Some(288));
+        path.sequence.value = "500".to_string();
+        assert_eq!(path.sequence(), None);
+    }
+
+    #[test]
+    fn policy_summary() {
+        let desc = MultipathDescriptor::from_str(TESTNET_DESC).unwrap();
+        let summary = PolicySummary::new(&desc);
+        assert_eq!(
+            summary.primary,
+            PathSummary {
+                threshold: 1,
+                keys: vec![Fingerprint::from_str("44e133a4").unwrap()],
+                timelock: None,
+            }
+        );
+        assert_eq!(
+            summary.recovery,
+            vec![PathSummary {
+                threshold: 1,
+                keys: vec![Fingerprint::from_str("7a0877a6").unwrap()],
+                timelock: Some((10000, "~69 days".to_string())),
+            }]
+        );
+        assert_eq!(
+            summary.primary.description(),
+            "The key 44e133a4 can spend the coins at any time."
+        );
+        assert_eq!(
+            summary.recovery[0].description(),
+            "The key 7a0877a6 can spend the coins once they have been unmoved for 10000 blocks \
+             (~69 days)."
+        );
+
+        let mut ctx = Context::new(Network::Testnet, PathBuf::from("/tmp"));
+        let mut step = PolicyPreview::default();
+        step.load_context(&ctx);
+        assert_eq!(step.summary, None);
+        ctx.descriptor = Some(desc);
+        step.load_context(&ctx);
+        assert_eq!(step.summary, Some(summary));
+    }
+}
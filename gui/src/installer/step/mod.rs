@@ -1,5 +1,8 @@
 mod descriptor;
-pub use descriptor::{BackupDescriptor, DefineDescriptor, ImportDescriptor, RegisterDescriptor};
+pub use descriptor::{
+    BackupDescriptor, DataDirStatus, DefineDescriptor, ImportDescriptor, PathSummary,
+    PolicyPreview, PolicySummary, RegisterDescriptor,
+};
 
 use std::path::PathBuf;
 use std::str::FromStr;
@@ -48,6 +51,8 @@ pub struct Context {
         Option<[u8; 32]>,
     )>,
     pub data_dir: PathBuf,
+    /// The creation date of an imported wallet, if known.
+    pub birthday_timestamp: Option<u32>,
 }
 
 impl Context {
@@ -56,11 +61,20 @@ impl Context {
             bitcoin_config: BitcoinConfig {
                 network,
                 poll_interval_secs: Duration::from_secs(30),
+                gap_limit: 20,
+                dust_output_sats: 5_000,
+                max_fee_sats: bitcoin::blockdata::constants::COIN_VALUE,
+                max_feerate_sats_vb: 1_000,
+                min_confirmations: 1,
+                psbt_non_witness_utxo: true,
+                long_term_feerate_vb: None,
+                rescan_timeout_secs: None,
             },
             hws: Vec::new(),
             bitcoind_config: None,
             descriptor: None,
             data_dir,
+            birthday_timestamp: None,
         }
     }
 }
@@ -82,7 +96,10 @@ impl From<Welcome> for Box<dyn Step> {
 
 pub struct DefineBitcoind {
     cookie_path: form::Value<String>,
+    rpc_user: form::Value<String>,
+    rpc_password: form::Value<String>,
     address: form::Value<String>,
+    error: Option<String>,
 }
 
 fn bitcoind_default_cookie_path(network: &bitcoin::Network) -> Option<String> {
@@ -132,7 +149,10 @@ impl DefineBitcoind {
     pub fn new() -> Self {
         Self {
             cookie_path: form::Value::default(),
+            rpc_user: form::Value::default(),
+            rpc_password: form::Value::default(),
             address: form::Value::default(),
+            error: None,
         }
     }
 }
@@ -156,7 +176,16 @@ impl Step for DefineBitcoind {
                 }
                 message::DefineBitcoind::CookiePathEdited(path) => {
                     self.cookie_path.value = path;
-                    self.address.valid = true;
+                    self.cookie_path.valid = true;
+                }
+                message::DefineBitcoind::RpcUserEdited(user) => {
+                    self.rpc_user.value = user;
+                    self.rpc_user.valid = true;
+                    self.cookie_path.valid = true;
+                }
+                message::DefineBitcoind::RpcPasswordEdited(password) => {
+                    self.rpc_password.value = password;
+                    self.rpc_password.valid = true;
                 }
             };
         };
@@ -164,35 +193,52 @@ impl Step for DefineBitcoind {
     }
 
     fn apply(&mut self, ctx: &mut Context) -> bool {
-        match (
-            PathBuf::from_str(&self.cookie_path.value),
-            std::net::SocketAddr::from_str(&self.address.value),
-        ) {
-            (Err(_), Ok(_)) => {
-                self.cookie_path.valid = false;
-                false
-            }
-            (Ok(_), Err(_)) => {
-                self.address.valid = false;
-                false
-            }
-            (Err(_), Err(_)) => {
-                self.cookie_path.valid = false;
-                self.address.valid = false;
-                false
-            }
-            (Ok(path), Ok(addr)) => {
-                ctx.bitcoind_config = Some(BitcoindConfig {
-                    cookie_path: path,
-                    addr,
-                });
-                true
+        self.error = None;
+        let addr = std::net::SocketAddr::from_str(&self.address.value);
+        self.address.valid = addr.is_ok();
+
+        // The RPC user and password are only needed if there is no cookie file.
+        let use_user_pass = !self.rpc_user.value.is_empty();
+        if use_user_pass {
+            self.rpc_password.valid = !self.rpc_password.value.is_empty();
+        } else {
+            self.cookie_path.valid = PathBuf::from(&self.cookie_path.value).is_file();
+            if !self.cookie_path.valid {
+                self.error = Some(
+                    "No cookie file was found at this path. Check it, or enter the RPC user and \
+                    password set in the bitcoind configuration."
+                        .to_string(),
+                );
             }
         }
+        if !self.address.valid || !self.cookie_path.valid || !self.rpc_password.valid {
+            return false;
+        }
+
+        let addr = addr.expect("Checked above");
+        let bitcoind_config = if use_user_pass {
+            BitcoindConfig::with_user_pass(&self.rpc_user.value, &self.rpc_password.value, addr)
+        } else {
+            BitcoindConfig::new(PathBuf::from(&self.cookie_path.value), addr)
+        };
+        // Make sure we will be able to use this bitcoind before creating the wallet.
+        if let Err(e) = liana::check_bitcoind(&bitcoind_config, ctx.bitcoin_config.network) {
+            self.error = Some(e.to_string());
+            return false;
+        }
+        ctx.bitcoind_config = Some(bitcoind_config);
+        true
     }
 
     fn view(&self, progress: (usize, usize)) -> Element<Message> {
-        view::define_bitcoin(progress, &self.address, &self.cookie_path)
+        view::define_bitcoin(
+            progress,
+            &self.address,
+            &self.cookie_path,
+            &self.rpc_user,
+            &self.rpc_password,
+            self.error.as_ref(),
+        )
     }
 }
 
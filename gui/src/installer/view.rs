@@ -1,13 +1,19 @@
+use std::collections::HashMap;
+
 use iced::widget::{Button, Checkbox, Column, Container, PickList, Row, Scrollable};
 use iced::{Alignment, Element, Length};
 
-use liana::miniscript::bitcoin;
+use async_hwi::DeviceKind;
+use liana::{
+    config::BitcoindConfig,
+    miniscript::bitcoin::{self, util::bip32::Fingerprint},
+};
 
 use crate::{
-    hw::HardwareWallet,
+    hw::{self, HardwareWallet},
     installer::{
         message::{self, Message},
-        step::Context,
+        step::{Context, DataDirStatus, PathSummary, PolicySummary},
         Error,
     },
     ui::{
@@ -117,13 +123,30 @@ pub fn welcome<'a>() -> Element<'a, Message> {
     .into()
 }
 
+fn network_status_view<'a>(status: DataDirStatus) -> Option<Element<'a, Message>> {
+    match status {
+        DataDirStatus::Missing => None,
+        DataDirStatus::NoWallet => Some(
+            text("A data directory already exists for this network, it will be reused")
+                .small()
+                .into(),
+        ),
+        DataDirStatus::HasWallet => Some(
+            card::warning(
+                "A wallet already exists in the data directory for this network".to_string(),
+            )
+            .into(),
+        ),
+    }
+}
+
 pub fn define_descriptor<'a>(
     progress: (usize, usize),
     network: bitcoin::Network,
-    network_valid: bool,
+    network_status: DataDirStatus,
     user_xpub: &form::Value<String>,
-    heir_xpub: &form::Value<String>,
-    sequence: &form::Value<String>,
+    recovery_paths: Vec<Element<'a, Message>>,
+    is_empty: bool,
     error: Option<&String>,
 ) -> Element<'a, Message> {
     let row_network = Row::new()
@@ -136,13 +159,7 @@ pub fn define_descriptor<'a>(
             })
             .padding(10),
         ))
-        .push_maybe(if network_valid {
-            None
-        } else {
-            Some(card::warning(
-                "A data directory already exists for this network".to_string(),
-            ))
-        });
+        .push_maybe(network_status_view(network_status));
 
     let col_user_xpub = Column::new()
         .push(text("Your public key:").bold())
@@ -169,16 +186,101 @@ pub fn define_descriptor<'a>(
         )
         .spacing(10);
 
+    let col_recovery_paths = recovery_paths
+        .into_iter()
+        .fold(Column::new().spacing(25), |col, path| col.push(path))
+        .push(
+            button::border(Some(icon::plus_icon()), "Add a recovery path").on_press(
+                Message::DefineDescriptor(message::DefineDescriptor::AddRecoveryPath),
+            ),
+        );
+
+    layout(
+        progress,
+        Column::new()
+            .push(text("Create the wallet").bold().size(50))
+            .push(
+                Column::new()
+                    .push(row_network)
+                    .push(col_user_xpub)
+                    .push(col_recovery_paths)
+                    .spacing(25),
+            )
+            .push(if is_empty {
+                button::primary(None, "Next").width(Length::Units(200))
+            } else {
+                button::primary(None, "Next")
+                    .width(Length::Units(200))
+                    .on_press(Message::Next)
+            })
+            .push_maybe(error.map(|e| card::error("Failed to create descriptor", e.to_string())))
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .padding(100)
+            .spacing(50)
+            .align_items(Alignment::Center),
+    )
+}
+
+pub fn recovery_path<'a>(
+    index: usize,
+    network: bitcoin::Network,
+    heir_xpub: &form::Value<String>,
+    sequence: &form::Value<String>,
+    sequence_unit: message::SequenceUnit,
+    // The timelock in blocks and the approximate time it represents, if valid.
+    sequence_blocks: Option<(u16, String)>,
+    removable: bool,
+) -> Element<'a, Message> {
+    let col_sequence = Column::new()
+        .push(text("Time before enabling recovery:").bold())
+        .push(
+            Row::new()
+                .push(
+                    Container::new(
+                        form::Form::new("Duration", sequence, move |msg| {
+                            Message::DefineDescriptor(message::DefineDescriptor::SequenceEdited(
+                                index, msg,
+                            ))
+                        })
+                        .warning("Please enter a duration of at most 65535 blocks (~455 days)")
+                        .size(20)
+                        .padding(10),
+                    )
+                    .width(Length::Units(150)),
+                )
+                .push(
+                    PickList::new(
+                        &message::SequenceUnit::ALL[..],
+                        Some(sequence_unit),
+                        move |u| {
+                            Message::DefineDescriptor(
+                                message::DefineDescriptor::SequenceUnitSelected(index, u),
+                            )
+                        },
+                    )
+                    .padding(10),
+                )
+                .push_maybe(sequence_blocks.map(|(blocks, duration)| {
+                    text(format!("{} blocks, {}", blocks, duration)).small()
+                }))
+                .spacing(10)
+                .align_items(Alignment::Center),
+        )
+        .spacing(10);
+
     let col_heir_xpub = Column::new()
         .push(text("Public key of the recovery key:").bold())
         .push(
             Row::new()
                 .push(button::border(Some(icon::chip_icon()), "Import").on_press(
-                    Message::DefineDescriptor(message::DefineDescriptor::ImportHeirHWXpub),
+                    Message::DefineDescriptor(message::DefineDescriptor::ImportHeirHWXpub(index)),
                 ))
                 .push(
-                    form::Form::new("Xpub", heir_xpub, |msg| {
-                        Message::DefineDescriptor(message::DefineDescriptor::HeirXpubEdited(msg))
+                    form::Form::new("Xpub", heir_xpub, move |msg| {
+                        Message::DefineDescriptor(message::DefineDescriptor::HeirXpubEdited(
+                            index, msg,
+                        ))
                     })
                     .warning(if network == bitcoin::Network::Bitcoin {
                         "Please enter correct xpub"
@@ -194,59 +296,36 @@ pub fn define_descriptor<'a>(
         )
         .spacing(10);
 
-    let col_sequence = Column::new()
-        .push(text("Number of block before enabling recovery:").bold())
+    Column::new()
         .push(
-            Container::new(
-                form::Form::new("Number of block", sequence, |msg| {
-                    Message::DefineDescriptor(message::DefineDescriptor::SequenceEdited(msg))
-                })
-                .warning("Please enter correct block number")
-                .size(20)
-                .padding(10),
-            )
-            .width(Length::Units(150)),
-        )
-        .spacing(10);
-
-    layout(
-        progress,
-        Column::new()
-            .push(text("Create the wallet").bold().size(50))
-            .push(
-                Column::new()
-                    .push(row_network)
-                    .push(col_user_xpub)
-                    .push(col_sequence)
-                    .push(col_heir_xpub)
-                    .spacing(25),
-            )
-            .push(
-                if user_xpub.value.is_empty()
-                    && heir_xpub.value.is_empty()
-                    && sequence.value.is_empty()
-                {
-                    button::primary(None, "Next").width(Length::Units(200))
+            Row::new()
+                .align_items(Alignment::Center)
+                .push(
+                    Container::new(text(format!("Recovery path #{}", index + 1)).bold())
+                        .width(Length::Fill),
+                )
+                .push_maybe(if removable {
+                    Some(button::alert(Some(icon::trash_icon()), "Delete").on_press(
+                        Message::DefineDescriptor(message::DefineDescriptor::DeleteRecoveryPath(
+                            index,
+                        )),
+                    ))
                 } else {
-                    button::primary(None, "Next")
-                        .width(Length::Units(200))
-                        .on_press(Message::Next)
-                },
-            )
-            .push_maybe(error.map(|e| card::error("Failed to create descriptor", e.to_string())))
-            .width(Length::Fill)
-            .height(Length::Fill)
-            .padding(100)
-            .spacing(50)
-            .align_items(Alignment::Center),
-    )
+                    None
+                }),
+        )
+        .push(col_sequence)
+        .push(col_heir_xpub)
+        .spacing(10)
+        .into()
 }
 
 pub fn import_descriptor<'a>(
     progress: (usize, usize),
     network: bitcoin::Network,
-    network_valid: bool,
+    network_status: DataDirStatus,
     imported_descriptor: &form::Value<String>,
+    birthday: &form::Value<String>,
     error: Option<&String>,
 ) -> Element<'a, Message> {
     let row_network = Row::new()
@@ -259,24 +338,34 @@ pub fn import_descriptor<'a>(
             })
             .padding(10),
         ))
-        .push_maybe(if network_valid {
-            None
-        } else {
-            Some(card::warning(
-                "A data directory already exists for this network".to_string(),
-            ))
-        });
+        .push_maybe(network_status_view(network_status));
     let col_descriptor = Column::new()
-        .push(text("Descriptor:").bold())
+        .push(text("Descriptor or backup file:").bold())
         .push(
-            form::Form::new("Descriptor", imported_descriptor, |msg| {
-                Message::DefineDescriptor(message::DefineDescriptor::ImportDescriptor(msg))
-            })
+            form::Form::new(
+                "Descriptor, or path to a backup file",
+                imported_descriptor,
+                |msg| Message::DefineDescriptor(message::DefineDescriptor::ImportDescriptor(msg)),
+            )
             .warning("Please enter correct descriptor")
             .size(20)
             .padding(10),
         )
         .spacing(10);
+    let col_birthday = Column::new()
+        .push(text("Wallet creation date (optional):").bold())
+        .push(text(
+            "If you know when the wallet was created, the block chain will only be scanned for its transactions from this date.",
+        ))
+        .push(
+            form::Form::new("YYYY-MM-DD", birthday, |msg| {
+                Message::DefineDescriptor(message::DefineDescriptor::BirthdayEdited(msg))
+            })
+            .warning("Please enter a valid date, as YYYY-MM-DD")
+            .size(20)
+            .padding(10),
+        )
+        .spacing(10);
     layout(
         progress,
         Column::new()
@@ -285,7 +374,8 @@ pub fn import_descriptor<'a>(
                 Column::new()
                     .spacing(20)
                     .push(row_network)
-                    .push(col_descriptor),
+                    .push(col_descriptor)
+                    .push(col_birthday),
             )
             .push(if imported_descriptor.value.is_empty() {
                 button::primary(None, "Next").width(Length::Units(200))
@@ -303,14 +393,21 @@ pub fn import_descriptor<'a>(
     )
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn register_descriptor<'a>(
     progress: (usize, usize),
     descriptor: String,
-    hws: &[(HardwareWallet, Option<[u8; 32]>, bool)],
+    hws: &[HardwareWallet],
+    hw_errors: &[(DeviceKind, hw::Error)],
+    required_fingerprints: &[Fingerprint],
+    registered: &HashMap<Fingerprint, (DeviceKind, Option<[u8; 32]>)>,
     error: Option<&Error>,
     processing: bool,
     chosen_hw: Option<usize>,
 ) -> Element<'a, Message> {
+    let all_registered = required_fingerprints
+        .iter()
+        .all(|fg| registered.contains_key(fg));
     layout(
         progress,
         Column::new()
@@ -329,6 +426,39 @@ pub fn register_descriptor<'a>(
                     .max_width(1000),
             ))
             .push_maybe(error.map(|e| card::error("Failed to register descriptor", e.to_string())))
+            .push_maybe(if required_fingerprints.is_empty() {
+                None
+            } else {
+                Some(
+                    Column::new()
+                        .push(
+                            text("The descriptor must be registered on the following devices:")
+                                .bold(),
+                        )
+                        .push(required_fingerprints.iter().fold(
+                            Column::new().spacing(5),
+                            |col, fg| {
+                                col.push(
+                                    Row::new()
+                                        .spacing(10)
+                                        .align_items(Alignment::Center)
+                                        .push(text(format!("fingerprint: {}", fg)))
+                                        .push(if registered.contains_key(fg) {
+                                            Row::new()
+                                                .spacing(5)
+                                                .push(
+                                                    icon::circle_check_icon().style(color::SUCCESS),
+                                                )
+                                                .push(text("Registered").small())
+                                        } else {
+                                            Row::new().push(text("Not registered yet").small())
+                                        }),
+                                )
+                            },
+                        ))
+                        .spacing(10),
+                )
+            })
             .push(
                 Column::new()
                     .push(
@@ -354,16 +484,17 @@ pub fn register_descriptor<'a>(
                             .fold(Column::new().spacing(10), |col, (i, hw)| {
                                 col.push(hw_list_view(
                                     i,
-                                    &hw.0,
+                                    hw,
                                     Some(i) == chosen_hw,
                                     processing,
-                                    hw.2,
+                                    registered.contains_key(&hw.fingerprint),
                                 ))
                             }),
                     )
+                    .push(hw_errors_view(hw_errors))
                     .width(Length::Fill),
             )
-            .push(if processing {
+            .push(if processing || !all_registered {
                 button::primary(None, "Next").width(Length::Units(200))
             } else {
                 button::primary(None, "Next")
@@ -381,6 +512,7 @@ pub fn register_descriptor<'a>(
 pub fn backup_descriptor<'a>(
     progress: (usize, usize),
     descriptor: String,
+    exported: Option<&Result<std::path::PathBuf, Error>>,
     done: bool,
 ) -> Element<'a, Message> {
     layout(
@@ -424,11 +556,26 @@ pub fn backup_descriptor<'a>(
                     .push(text("The descriptor:").small().bold())
                     .push(text(descriptor.clone()).small())
                     .push(
-                        Row::new().push(Column::new().width(Length::Fill)).push(
-                            button::transparent_border(Some(icon::clipboard_icon()), "Copy")
-                                .on_press(Message::Clibpboard(descriptor)),
-                        ),
+                        Row::new()
+                            .spacing(10)
+                            .push(Column::new().width(Length::Fill))
+                            .push(
+                                button::transparent_border(None, "Export backup file")
+                                    .on_press(Message::ExportBackup),
+                            )
+                            .push(
+                                button::transparent_border(Some(icon::clipboard_icon()), "Copy")
+                                    .on_press(Message::Clibpboard(descriptor)),
+                            ),
                     )
+                    .push_maybe(exported.map(|res| match res {
+                        Ok(path) => Element::<'a, Message>::from(
+                            text(format!("Backup file written to {}", path.display())).small(),
+                        ),
+                        Err(e) => {
+                            card::error("Failed to export the backup file", e.to_string()).into()
+                        }
+                    }))
                     .spacing(10)
                     .max_width(1000),
             ))
@@ -452,6 +599,59 @@ pub fn backup_descriptor<'a>(
     )
 }
 
+pub fn policy_preview<'a>(
+    progress: (usize, usize),
+    summary: Option<&PolicySummary>,
+) -> Element<'a, Message> {
+    layout(
+        progress,
+        Column::new()
+            .push(text("Review your wallet policy").bold().size(50))
+            .push(
+                Container::new(text(
+                    "Make sure the spending conditions below are the ones you intended. \
+                    Keys are identified by their master fingerprint.",
+                ))
+                .max_width(1000),
+            )
+            .push_maybe(summary.map(|summary| {
+                Column::new()
+                    .push(policy_path("Primary spending path", &summary.primary))
+                    .push(
+                        summary
+                            .recovery
+                            .iter()
+                            .fold(Column::new().spacing(20), |col, path| {
+                                col.push(policy_path("Recovery path", path))
+                            }),
+                    )
+                    .spacing(20)
+                    .max_width(1000)
+            }))
+            .push(
+                button::primary(None, "Next")
+                    .on_press(Message::Next)
+                    .width(Length::Units(200)),
+            )
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .padding(100)
+            .spacing(50)
+            .align_items(Alignment::Center),
+    )
+}
+
+fn policy_path<'a>(title: &'static str, path: &PathSummary) -> Element<'a, Message> {
+    card::simple(
+        Column::new()
+            .push(text(title).bold())
+            .push(text(path.description()))
+            .spacing(10)
+            .width(Length::Fill),
+    )
+    .into()
+}
+
 pub fn help_backup<'a>() -> Element<'a, Message> {
     text(super::prompt::BACKUP_DESCRIPTOR_HELP).small().into()
 }
@@ -460,6 +660,9 @@ pub fn define_bitcoin<'a>(
     progress: (usize, usize),
     address: &form::Value<String>,
     cookie_path: &form::Value<String>,
+    rpc_user: &form::Value<String>,
+    rpc_password: &form::Value<String>,
+    error: Option<&String>,
 ) -> Element<'a, Message> {
     let col_address = Column::new()
         .push(text("Address:").bold())
@@ -485,6 +688,30 @@ pub fn define_bitcoin<'a>(
         )
         .spacing(10);
 
+    let col_user_pass = Column::new()
+        .push(text("Or, if bitcoind is configured with 'rpcuser' and 'rpcpassword':").bold())
+        .push(
+            Row::new()
+                .push(
+                    form::Form::new("RPC user", rpc_user, |msg| {
+                        Message::DefineBitcoind(message::DefineBitcoind::RpcUserEdited(msg))
+                    })
+                    .size(20)
+                    .padding(10),
+                )
+                .push(
+                    form::Form::new("RPC password", rpc_password, |msg| {
+                        Message::DefineBitcoind(message::DefineBitcoind::RpcPasswordEdited(msg))
+                    })
+                    .warning("Please enter the RPC password")
+                    .password()
+                    .size(20)
+                    .padding(10),
+                )
+                .spacing(10),
+        )
+        .spacing(10);
+
     layout(
         progress,
         Column::new()
@@ -495,6 +722,8 @@ pub fn define_bitcoin<'a>(
             )
             .push(col_address)
             .push(col_cookie)
+            .push(col_user_pass)
+            .push_maybe(error.map(|e| card::error("Cannot use this Bitcoin node", e.to_string())))
             .push(
                 button::primary(None, "Next")
                     .on_press(Message::Next)
@@ -508,6 +737,14 @@ pub fn define_bitcoin<'a>(
     )
 }
 
+/// How we authenticate to bitcoind, without revealing the RPC password.
+fn bitcoind_auth_summary(config: &BitcoindConfig) -> String {
+    match config.rpc_auth {
+        Some(ref auth) => format!("RPC user '{}'", auth.split(':').next().unwrap_or_default()),
+        None => format!("cookie file '{}'", config.cookie_path.to_string_lossy()),
+    }
+}
+
 pub fn install<'a>(
     progress: (usize, usize),
     context: &Context,
@@ -554,16 +791,10 @@ pub fn install<'a>(
                                     Row::new()
                                         .spacing(5)
                                         .align_items(Alignment::Center)
-                                        .push(text("Cookie path:").small())
+                                        .push(text("Authentication:").small())
                                         .push(
-                                            text(format!(
-                                                "{}",
-                                                context
-                                                    .bitcoind_config
-                                                    .as_ref()
-                                                    .unwrap()
-                                                    .cookie_path
-                                                    .to_string_lossy()
+                                            text(bitcoind_auth_summary(
+                                                context.bitcoind_config.as_ref().unwrap(),
                                             ))
                                             .small(),
                                         ),
@@ -629,7 +860,10 @@ pub fn install<'a>(
 
 pub fn hardware_wallet_xpubs_modal<'a>(
     is_heir: bool,
+    network: bitcoin::Network,
+    account: &form::Value<String>,
     hws: &[HardwareWallet],
+    hw_errors: &[(DeviceKind, hw::Error)],
     error: Option<&Error>,
     processing: bool,
     chosen_hw: Option<usize>,
@@ -646,6 +880,32 @@ pub fn hardware_wallet_xpubs_modal<'a>(
                 .size(50),
             )
             .push_maybe(error.map(|e| card::error("Failed to import xpub", e.to_string())))
+            .push(
+                Row::new()
+                    .spacing(10)
+                    .align_items(Alignment::Center)
+                    .push(text(format!(
+                        "Account (derivation path m/48'/{}'/<account>'/2'):",
+                        if network == bitcoin::Network::Bitcoin {
+                            0
+                        } else {
+                            1
+                        }
+                    )))
+                    .push(
+                        Container::new(
+                            form::Form::new("Account", account, |msg| {
+                                Message::DefineDescriptor(message::DefineDescriptor::AccountEdited(
+                                    msg,
+                                ))
+                            })
+                            .warning("Please enter a valid account index")
+                            .size(20)
+                            .padding(10),
+                        )
+                        .width(Length::Units(150)),
+                    ),
+            )
             .push(
                 Column::new()
                     .push(
@@ -678,6 +938,7 @@ pub fn hardware_wallet_xpubs_modal<'a>(
                                 ))
                             }),
                     )
+                    .push(hw_errors_view(hw_errors))
                     .width(Length::Fill),
             )
             .width(Length::Fill)
@@ -688,6 +949,24 @@ pub fn hardware_wallet_xpubs_modal<'a>(
     )
 }
 
+/// The devices that were detected but could not be used. Devices waiting to be unlocked are
+/// pointed out so the user can unlock them and refresh the list.
+fn hw_errors_view<'a>(hw_errors: &[(DeviceKind, hw::Error)]) -> Element<'a, Message> {
+    hw_errors
+        .iter()
+        .fold(Column::new().spacing(10), |col, (kind, e)| {
+            col.push(match e {
+                hw::Error::Locked | hw::Error::Timeout(_) => card::warning(format!(
+                    "{} is locked or not answering. Please unlock it with its PIN or \
+                     passphrase, then refresh the list.",
+                    kind
+                )),
+                e => card::warning(format!("{} cannot be used: {}", kind, e)),
+            })
+        })
+        .into()
+}
+
 fn hw_list_view<'a>(
     i: usize,
     hw: &HardwareWallet,
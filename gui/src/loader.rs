@@ -266,16 +266,17 @@ pub fn view<'a>(datadir_path: Option<&'a PathBuf>, step: &'a Step) -> Element<'a
                 .width(Length::Fill)
                 .align_items(Alignment::Center)
                 .push(icon::plug_icon().size(100).width(Length::Units(300)))
-                .push(
-                    if matches!(
-                        error.as_ref(),
-                        Error::Daemon(DaemonError::Start(StartupError::Bitcoind(_)))
-                    ) {
+                .push(match error.as_ref() {
+                    Error::Daemon(DaemonError::Start(StartupError::Bitcoind(e)))
+                        if e.is_filesystem_error() =>
+                    {
+                        text("Bitcoind cannot create the wallet directory, please check its permissions")
+                    }
+                    Error::Daemon(DaemonError::Start(StartupError::Bitcoind(_))) => {
                         text("Liana failed to start, please check if bitcoind is running")
-                    } else {
-                        text("Liana failed to start")
-                    },
-                )
+                    }
+                    _ => text("Liana failed to start"),
+                })
                 .push(
                     Row::new()
                         .spacing(10)
@@ -314,7 +315,10 @@ async fn connect(
     socket_path: PathBuf,
     config: Config,
 ) -> Result<Arc<dyn Daemon + Sync + Send>, Error> {
-    let client = client::jsonrpc::JsonRPCClient::new(socket_path);
+    let mut client = client::jsonrpc::JsonRPCClient::new(&socket_path);
+    if config.rpc_cookie {
+        client = client.with_cookie(socket_path.with_file_name("lianad_rpc.cookie"));
+    }
     let daemon = Lianad::new(client, config);
 
     debug!("Searching for external daemon");
@@ -105,6 +105,15 @@ pub fn spend<T>() -> widget::container::Container<'static, T> {
         .center_y()
 }
 
+pub fn self_transfer<T>() -> widget::container::Container<'static, T> {
+    Container::new(icon::turnback_icon().width(Length::Units(20)))
+        .width(Length::Units(40))
+        .height(Length::Units(40))
+        .style(Style::Standard)
+        .center_x()
+        .center_y()
+}
+
 pub fn coin<T>() -> widget::container::Container<'static, T> {
     Container::new(icon::coin_icon().width(Length::Units(20)))
         .width(Length::Units(40))
@@ -56,6 +56,12 @@ where
         self
     }
 
+    /// Hides the value of the [`Form`], as for a password.
+    pub fn password(mut self) -> Self {
+        self.input = self.input.password();
+        self
+    }
+
     /// Sets the padding of the [`Form`].
     pub fn padding(mut self, units: u16) -> Self {
         self.input = self.input.padding(units);
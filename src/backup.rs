@@ -0,0 +1,194 @@
+//! Wallet backup.
+//!
+//! A structured and versioned (JSON) backup of everything needed to recover a wallet, but the
+//! signing keys. This is more than a copy-pasted descriptor: it records the network, the
+//! derivation indices at the time of the export (so a recovery doesn't miss coins beyond the
+//! gap limit) and the origin of the keys.
+//!
+//! A backup can only be restored into a daemon configured with the very same descriptor (and
+//! network): it complements the configuration, it doesn't replace it.
+//!
+//! The backup is neither signed nor authenticated, and there is no checksum beyond the one of the
+//! descriptor. Protecting it against tampering (for instance the derivation indices or the
+//! labels) is out of scope and left to the storage it is written to.
+
+use crate::{commands::KeyOrigin, descriptors::MultipathDescriptor};
+
+use std::{collections::BTreeMap, error, fmt, str::FromStr};
+
+use miniscript::bitcoin::{self, util::bip32};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// The version of the backup format we write. We can read any backup up to this version.
+pub const BACKUP_VERSION: u32 = 0;
+
+#[derive(Debug)]
+pub enum BackupError {
+    Json(serde_json::Error),
+    UnsupportedVersion(u32),
+    KeysMismatch,
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Json(e) => write!(f, "Invalid backup file: '{}'.", e),
+            Self::UnsupportedVersion(v) => write!(
+                f,
+                "Unsupported backup version '{}'. The latest supported version is '{}'.",
+                v, BACKUP_VERSION
+            ),
+            Self::KeysMismatch => write!(
+                f,
+                "The keys origins in the backup don't match the keys of its descriptor."
+            ),
+        }
+    }
+}
+
+impl error::Error for BackupError {}
+
+fn ser_to_string<S: Serializer, T: fmt::Display>(t: T, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&t.to_string())
+}
+
+fn deser_from_str<'de, D, T>(d: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    <T as FromStr>::Err: fmt::Display,
+{
+    let string = String::deserialize(d)?;
+    T::from_str(&string).map_err(de::Error::custom)
+}
+
+/// A backup of a wallet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletBackup {
+    /// Version of the backup format.
+    pub version: u32,
+    pub network: bitcoin::Network,
+    #[serde(serialize_with = "ser_to_string", deserialize_with = "deser_from_str")]
+    pub descriptor: MultipathDescriptor,
+    /// The next derivation index to be used for receiving addresses at the time of the export.
+    pub receive_index: u32,
+    /// The next derivation index to be used for change addresses at the time of the export.
+    pub change_index: u32,
+    /// The timestamp from which the block chain was being rescanned at the time of the export, if
+    /// a rescan was ongoing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rescan_timestamp: Option<u32>,
+    /// Origin of all the keys in the descriptor.
+    pub keys: Vec<KeyOrigin>,
+    /// Optional labels, for instance for the keys or the addresses.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+// The origins of all the keys in this descriptor.
+fn keys_origins(descriptor: &MultipathDescriptor) -> Vec<KeyOrigin> {
+    descriptor
+        .keys_origins()
+        .into_iter()
+        .map(|(fingerprint, derivation_path, xpub)| KeyOrigin {
+            fingerprint,
+            derivation_path,
+            xpub,
+        })
+        .collect()
+}
+
+impl WalletBackup {
+    pub fn new(
+        descriptor: MultipathDescriptor,
+        network: bitcoin::Network,
+        receive_index: bip32::ChildNumber,
+        change_index: bip32::ChildNumber,
+    ) -> WalletBackup {
+        let keys = keys_origins(&descriptor);
+        WalletBackup {
+            version: BACKUP_VERSION,
+            network,
+            descriptor,
+            receive_index: receive_index.into(),
+            change_index: change_index.into(),
+            rescan_timestamp: None,
+            keys,
+            labels: BTreeMap::new(),
+        }
+    }
+
+    /// Serialize this backup to be written to a file.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Serialization can't fail")
+    }
+
+    /// Parse and check a backup file.
+    pub fn from_json(s: &str) -> Result<WalletBackup, BackupError> {
+        let backup: WalletBackup = serde_json::from_str(s).map_err(BackupError::Json)?;
+        if backup.version > BACKUP_VERSION {
+            return Err(BackupError::UnsupportedVersion(backup.version));
+        }
+        if backup.keys != keys_origins(&backup.descriptor) {
+            return Err(BackupError::KeysMismatch);
+        }
+        Ok(backup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backup_json() {
+        let desc = MultipathDescriptor::from_str("wsh(or_d(pk([aabbccdd/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*),and_v(v:pkh(xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/<0;1>/*),older(52560))))").unwrap();
+        let mut backup =
+            WalletBackup::new(desc.clone(), bitcoin::Network::Bitcoin, 12.into(), 3.into());
+        assert_eq!(backup.keys.len(), 2);
+        backup
+            .labels
+            .insert("aabbccdd".to_string(), "Owner".to_string());
+
+        let json = backup.to_json();
+        let read_backup = WalletBackup::from_json(&json).unwrap();
+        assert_eq!(read_backup, backup);
+        assert_eq!(read_backup.descriptor, desc);
+        assert_eq!(
+            (read_backup.receive_index, read_backup.change_index),
+            (12, 3)
+        );
+
+        // The descriptor is stored as a string, and the labels and rescan timestamp are optional.
+        let mut value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["descriptor"], serde_json::json!(desc.to_string()));
+        assert!(value.get("rescan_timestamp").is_none());
+        value.as_object_mut().unwrap().remove("labels");
+        let read_backup = WalletBackup::from_json(&value.to_string()).unwrap();
+        assert!(read_backup.labels.is_empty());
+        assert!(read_backup.rescan_timestamp.is_none());
+        backup.rescan_timestamp = Some(1_600_000_000);
+        let read_backup = WalletBackup::from_json(&backup.to_json()).unwrap();
+        assert_eq!(read_backup.rescan_timestamp, Some(1_600_000_000));
+
+        // We refuse backups from the future.
+        value["version"] = serde_json::json!(BACKUP_VERSION + 1);
+        assert!(matches!(
+            WalletBackup::from_json(&value.to_string()),
+            Err(BackupError::UnsupportedVersion(_))
+        ));
+
+        // And inconsistent ones.
+        value["version"] = serde_json::json!(BACKUP_VERSION);
+        value["keys"].as_array_mut().unwrap().pop();
+        assert!(matches!(
+            WalletBackup::from_json(&value.to_string()),
+            Err(BackupError::KeysMismatch)
+        ));
+        value["descriptor"] = serde_json::json!("wsh(pk(xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*))");
+        assert!(matches!(
+            WalletBackup::from_json(&value.to_string()),
+            Err(BackupError::Json(_))
+        ));
+    }
+}
@@ -1,9 +1,9 @@
 use liana::config::{config_folder_path, Config};
 
 use std::{
-    env,
+    env, fs,
     io::{Read, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
     process,
 };
 
@@ -100,6 +100,43 @@ fn socket_file(conf_file: Option<PathBuf>) -> PathBuf {
     .collect()
 }
 
+// If the daemon requires it, authenticate the connection by presenting the RPC cookie.
+fn authenticate(socket: &mut UnixStream, socket_file: &Path) {
+    let cookie_file = socket_file.with_file_name("lianad_rpc.cookie");
+    if !cookie_file.exists() {
+        return;
+    }
+    let cookie = fs::read_to_string(&cookie_file).unwrap_or_else(|e| {
+        eprintln!("Reading cookie at {:?}: '{}'", &cookie_file, e);
+        process::exit(1);
+    });
+
+    let request = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": format!("liana-cli-auth-{}", process::id()),
+        "method": "auth",
+        "params": { "cookie": cookie.trim() },
+    });
+    socket
+        .write_all(&[request.to_string().as_bytes(), b"\n"].concat())
+        .unwrap_or_else(|e| {
+            eprintln!("Writing to {:?}: '{}'", socket_file, e);
+            process::exit(1);
+        });
+    let response = serde_json::Deserializer::from_reader(&*socket)
+        .into_iter::<Json>()
+        .next()
+        .and_then(|r| r.ok())
+        .unwrap_or_else(|| {
+            eprintln!("Reading authentication response from {:?}", socket_file);
+            process::exit(1);
+        });
+    if let Some(e) = response.get("error") {
+        println!("{:#}", serde_json::json!({ "error": e }));
+        process::exit(1);
+    }
+}
+
 fn trimmed(mut vec: Vec<u8>, bytes_read: usize) -> Vec<u8> {
     vec.truncate(bytes_read);
 
@@ -126,6 +163,7 @@ fn main() {
         eprintln!("Could not connect to {:?}: '{}'", socket_file, e);
         process::exit(1);
     });
+    authenticate(&mut socket, &socket_file);
     socket
         .write_all(&[request.to_string().as_bytes(), b"\n"].concat())
         .unwrap_or_else(|e| {
@@ -3,10 +3,11 @@
 ///! We use the RPC interface and a watchonly descriptor wallet.
 mod utils;
 use crate::{
-    bitcoin::{Block, BlockChainTip},
+    bitcoin::{Block, BlockChainTip, RescanInfo},
     config,
     descriptors::MultipathDescriptor,
 };
+pub use utils::btc_kvb_to_sat_vb;
 use utils::{block_before_date, roundup_progress};
 
 use std::{
@@ -26,14 +27,6 @@ use miniscript::{
 
 use serde_json::Value as Json;
 
-// If bitcoind takes more than 3 minutes to answer one of our queries, fail.
-const RPC_SOCKET_TIMEOUT: u64 = 180;
-
-// Number of retries the client is allowed to do in case of timeout or i/o error
-// while communicating with the bitcoin daemon.
-// A retry happens every 1 second, this makes us give up after one minute.
-const BITCOIND_RETRY_LIMIT: usize = 60;
-
 // The minimum bitcoind version that can be used with lianad.
 const MIN_BITCOIND_VERSION: u64 = 240000;
 
@@ -41,18 +34,28 @@ const MIN_BITCOIND_VERSION: u64 = 240000;
 #[derive(Debug)]
 pub enum BitcoindError {
     CookieFile(io::Error),
+    /// Bitcoind refused our credentials.
+    Unauthorized,
+    /// Nothing is listening at the configured address.
+    ConnectionRefused,
     /// Bitcoind server error.
     Server(jsonrpc::error::Error),
     /// They replied to a batch request omitting some responses.
     BatchMissingResponse,
     WalletCreation(String),
+    /// Bitcoind could not access the filesystem when creating the watchonly wallet.
+    WalletFilesystem(String),
     DescriptorImport(String),
     WalletLoading(String),
     MissingOrTooManyWallet,
+    /// Bitcoind was compiled without wallet support, or started with the wallet disabled.
+    WalletDisabled,
     InvalidVersion(u64),
     NetworkMismatch(String /*config*/, String /*bitcoind*/),
     MissingDescriptor,
     StartRescan,
+    /// The watchonly wallet is already being rescanned.
+    AlreadyRescanning,
 }
 
 impl BitcoindError {
@@ -81,18 +84,113 @@ impl BitcoindError {
             _ => false,
         }
     }
+
+    /// Did bitcoind refuse our credentials?
+    pub fn is_unauthorized(&self) -> bool {
+        match self {
+            BitcoindError::Unauthorized => true,
+            BitcoindError::Server(jsonrpc::Error::Transport(ref e)) => matches!(
+                e.downcast_ref::<simple_http::Error>(),
+                Some(simple_http::Error::HttpErrorCode(401))
+            ),
+            _ => false,
+        }
+    }
+
+    /// Was the connection to bitcoind refused?
+    pub fn is_connection_refused(&self) -> bool {
+        match self {
+            BitcoindError::ConnectionRefused => true,
+            BitcoindError::Server(jsonrpc::Error::Transport(ref e)) => {
+                match e.downcast_ref::<simple_http::Error>() {
+                    Some(simple_http::Error::SocketError(e)) => {
+                        e.kind() == io::ErrorKind::ConnectionRefused
+                    }
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// Is it bitcoind failing to access the filesystem, for instance because it's not allowed to
+    /// write to the wallet directory? Retrying won't help.
+    pub fn is_filesystem_error(&self) -> bool {
+        match self {
+            BitcoindError::WalletFilesystem(_) => true,
+            BitcoindError::Server(jsonrpc::error::Error::Rpc(jsonrpc::error::RpcError {
+                message,
+                ..
+            })) => {
+                let message = message.to_lowercase();
+                [
+                    "permission denied",
+                    "filesystem error",
+                    "read-only file system",
+                ]
+                .iter()
+                .any(|e| message.contains(e))
+            }
+            _ => false,
+        }
+    }
+
+    /// Is bitcoind refusing the request because the wallet is currently being rescanned?
+    pub fn is_already_rescanning(&self) -> bool {
+        match self {
+            BitcoindError::AlreadyRescanning => true,
+            // RPC_WALLET_ERROR, the generic wallet error code.
+            BitcoindError::Server(jsonrpc::error::Error::Rpc(jsonrpc::error::RpcError {
+                code,
+                message,
+                ..
+            })) => *code == -4 && message.contains("currently rescanning"),
+            _ => false,
+        }
+    }
+
+    /// Is it a transient failure, for which it's worth retrying the request?
+    fn is_transient(&self) -> bool {
+        if self.is_warming_up() {
+            return true;
+        }
+        match self {
+            BitcoindError::Server(jsonrpc::Error::Transport(ref e)) => matches!(
+                e.downcast_ref::<simple_http::Error>(),
+                Some(simple_http::Error::Timeout)
+                    | Some(simple_http::Error::SocketError(_))
+                    | Some(simple_http::Error::HttpErrorCode(503))
+            ),
+            _ => false,
+        }
+    }
 }
 
 impl std::fmt::Display for BitcoindError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             BitcoindError::CookieFile(e) => write!(f, "Reading bitcoind cookie file: {}", e),
+            BitcoindError::Unauthorized => write!(
+                f,
+                "Authentication to bitcoind failed. Check the cookie file or the RPC user and \
+                 password."
+            ),
+            BitcoindError::ConnectionRefused => write!(
+                f,
+                "Connection to bitcoind refused. Check it is running and listening for RPC \
+                 connections at this address."
+            ),
             BitcoindError::Server(ref e) => write!(f, "Bitcoind RPC server error: {}", e),
             BitcoindError::BatchMissingResponse => write!(
                 f,
                 "Bitcoind server replied without enough responses to our batched request"
             ),
             BitcoindError::WalletCreation(s) => write!(f, "Error creating watchonly wallet: {}", s),
+            BitcoindError::WalletFilesystem(s) => write!(
+                f,
+                "Cannot create the watchonly wallet directory, check bitcoind is allowed to write there: '{}'",
+                s
+            ),
             BitcoindError::DescriptorImport(s) => write!(
                 f,
                 "Error importing descriptor. Response from bitcoind: '{}'",
@@ -101,6 +199,13 @@ impl std::fmt::Display for BitcoindError {
             BitcoindError::WalletLoading(s) => {
                 write!(f, "Error when loading watchonly wallet: '{}'.", s)
             }
+            BitcoindError::WalletDisabled => {
+                write!(
+                    f,
+                    "Bitcoind wallet support is not available. Make sure bitcoind was compiled \
+                     with wallet support and isn't started with 'disablewallet'."
+                )
+            }
             BitcoindError::InvalidVersion(v) => {
                 write!(
                     f,
@@ -130,6 +235,9 @@ impl std::fmt::Display for BitcoindError {
                     "Error while triggering the rescan for the bitcoind watchonly wallet."
                 )
             }
+            BitcoindError::AlreadyRescanning => {
+                write!(f, "The bitcoind watchonly wallet is already being rescanned.")
+            }
         }
     }
 }
@@ -158,6 +266,8 @@ pub struct BitcoinD {
     watchonly_wallet_path: String,
     /// How many times we'll retry upon failure to send a request.
     retries: usize,
+    /// How long we'll wait before retrying.
+    retry_interval: Duration,
 }
 
 macro_rules! params {
@@ -170,6 +280,15 @@ macro_rules! params {
     };
 }
 
+/// The "user:password" string to authenticate to bitcoind with, either from the configuration or
+/// from the cookie file.
+fn rpc_credentials(config: &config::BitcoindConfig) -> Result<String, BitcoindError> {
+    match config.rpc_auth {
+        Some(ref auth) => Ok(auth.clone()),
+        None => fs::read_to_string(&config.cookie_path).map_err(BitcoindError::CookieFile),
+    }
+}
+
 impl BitcoinD {
     /// Create a new bitcoind interface. This tests the connection to bitcoind and disables retries
     /// on failure to send a request.
@@ -177,8 +296,7 @@ impl BitcoinD {
         config: &config::BitcoindConfig,
         watchonly_wallet_path: String,
     ) -> Result<BitcoinD, BitcoindError> {
-        let cookie_string =
-            fs::read_to_string(&config.cookie_path).map_err(BitcoindError::CookieFile)?;
+        let cookie_string = rpc_credentials(config)?;
         let watchonly_url = format!("http://{}/wallet/{}", config.addr, watchonly_wallet_path);
 
         // Create a dummy bitcoind with clients using a low timeout to sanity check the connection.
@@ -212,6 +330,7 @@ impl BitcoinD {
             watchonly_client: dummy_wo_client,
             watchonly_wallet_path: watchonly_wallet_path.clone(),
             retries: 0,
+            retry_interval: config.rpc_retry_interval_secs,
         };
         dummy_bitcoind.check_connection()?;
 
@@ -220,7 +339,7 @@ impl BitcoinD {
             SimpleHttpTransport::builder()
                 .url(&config.addr.to_string())
                 .map_err(BitcoindError::from)?
-                .timeout(Duration::from_secs(RPC_SOCKET_TIMEOUT))
+                .timeout(config.rpc_timeout_secs)
                 .cookie_auth(cookie_string.clone())
                 .build(),
         );
@@ -236,7 +355,7 @@ impl BitcoinD {
             SimpleHttpTransport::builder()
                 .url(&watchonly_url)
                 .map_err(BitcoindError::from)?
-                .timeout(Duration::from_secs(RPC_SOCKET_TIMEOUT))
+                .timeout(config.rpc_timeout_secs)
                 .cookie_auth(cookie_string)
                 .build(),
         );
@@ -245,10 +364,52 @@ impl BitcoinD {
             sendonly_client,
             watchonly_client,
             watchonly_wallet_path,
-            retries: BITCOIND_RETRY_LIMIT,
+            retries: config.rpc_retries,
+            retry_interval: config.rpc_retry_interval_secs,
         })
     }
 
+    /// Check the bitcoind at this address can be used for a new wallet, before setting it up.
+    /// This makes sure it is reachable, of a supported version, on the expected network and with
+    /// wallet support.
+    pub fn preflight_check(
+        config: &config::BitcoindConfig,
+        network: bitcoin::Network,
+    ) -> Result<(), BitcoindError> {
+        let cookie_string = rpc_credentials(config)?;
+        // The watchonly wallet doesn't exist yet, only the node client is used.
+        let node_client = || -> Result<Client, BitcoindError> {
+            Ok(Client::with_transport(
+                SimpleHttpTransport::builder()
+                    .url(&config.addr.to_string())
+                    .map_err(BitcoindError::from)?
+                    .timeout(Duration::from_secs(3))
+                    .cookie_auth(cookie_string.clone())
+                    .build(),
+            ))
+        };
+        let bitcoind = BitcoinD {
+            node_client: node_client()?,
+            sendonly_client: node_client()?,
+            watchonly_client: node_client()?,
+            watchonly_wallet_path: String::new(),
+            retries: 0,
+            retry_interval: config.rpc_retry_interval_secs,
+        };
+        bitcoind.check_client(&bitcoind.node_client).map_err(|e| {
+            if e.is_unauthorized() {
+                BitcoindError::Unauthorized
+            } else if e.is_connection_refused() {
+                BitcoindError::ConnectionRefused
+            } else {
+                e
+            }
+        })?;
+        bitcoind.check_version_and_network(network)?;
+        bitcoind.list_wallets()?;
+        Ok(())
+    }
+
     fn check_client(&self, client: &Client) -> Result<(), BitcoindError> {
         if let Err(e) = self.make_request(client, "echo", &[]) {
             if e.is_warming_up() {
@@ -280,25 +441,15 @@ impl BitcoinD {
         for i in 0..self.retries + 1 {
             match request() {
                 Ok(res) => return Ok(res),
+                // Don't retry on RPC errors (other than bitcoind warming up), they won't go away.
+                // In particular for filesystem errors the user needs to take action.
+                Err(e) if !e.is_transient() => return Err(e),
                 Err(e) => {
-                    if e.is_warming_up() {
-                        error = Some(e)
-                    } else if let BitcoindError::Server(jsonrpc::Error::Transport(ref err)) = e {
-                        match err.downcast_ref::<simple_http::Error>() {
-                            Some(simple_http::Error::Timeout)
-                            | Some(simple_http::Error::SocketError(_))
-                            | Some(simple_http::Error::HttpErrorCode(503)) => {
-                                if i <= self.retries {
-                                    std::thread::sleep(Duration::from_secs(1));
-                                    log::debug!("Retrying RPC request to bitcoind: attempt #{}", i);
-                                }
-                                error = Some(e);
-                            }
-                            _ => return Err(e),
-                        }
-                    } else {
-                        return Err(e);
+                    if !e.is_warming_up() && i <= self.retries {
+                        std::thread::sleep(self.retry_interval);
+                        log::debug!("Retrying RPC request to bitcoind: attempt #{}", i);
                     }
+                    error = Some(e);
                 }
             }
         }
@@ -373,7 +524,7 @@ impl BitcoinD {
 
     fn make_node_request(&self, method: &str, params: &[Box<serde_json::value::RawValue>]) -> Json {
         self.make_request(&self.sendonly_client, method, params)
-            .expect("We must not fail to make a request for more than a minute")
+            .expect("We must not fail to make a request after exhausting our retries")
     }
 
     fn make_wallet_request(
@@ -382,7 +533,7 @@ impl BitcoinD {
         params: &[Box<serde_json::value::RawValue>],
     ) -> Json {
         self.make_request(&self.watchonly_client, method, params)
-            .expect("We must not fail to make a request for more than a minute")
+            .expect("We must not fail to make a request after exhausting our retries")
     }
 
     fn make_faillible_wallet_request(
@@ -393,23 +544,34 @@ impl BitcoinD {
         self.make_request(&self.watchonly_client, method, params)
     }
 
-    fn get_bitcoind_version(&self) -> u64 {
-        self.make_node_request("getnetworkinfo", &[])
+    fn get_bitcoind_version(&self) -> Result<u64, BitcoindError> {
+        Ok(self
+            .make_fallible_node_request("getnetworkinfo", &[])?
             .get("version")
             .and_then(Json::as_u64)
-            .expect("Missing or invalid 'version' in 'getnetworkinfo' result?")
+            .expect("Missing or invalid 'version' in 'getnetworkinfo' result?"))
     }
 
-    fn get_network_bip70(&self) -> String {
-        self.make_node_request("getblockchaininfo", &[])
+    fn get_network_bip70(&self) -> Result<String, BitcoindError> {
+        Ok(self
+            .make_fallible_node_request("getblockchaininfo", &[])?
             .get("chain")
             .and_then(Json::as_str)
             .expect("Missing or invalid 'chain' in 'getblockchaininfo' result?")
-            .to_string()
+            .to_string())
     }
 
-    fn list_wallets(&self) -> Vec<String> {
-        self.make_node_request("listwallets", &[])
+    fn list_wallets(&self) -> Result<Vec<String>, BitcoindError> {
+        Ok(self
+            .make_fallible_node_request("listwallets", &[])
+            .map_err(|e| match e {
+                // The wallet RPCs aren't registered if the wallet is disabled.
+                BitcoindError::Server(jsonrpc::error::Error::Rpc(jsonrpc::error::RpcError {
+                    code: -32601,
+                    ..
+                })) => BitcoindError::WalletDisabled,
+                e => e,
+            })?
             .as_array()
             .expect("API break, 'listwallets' didn't return an array.")
             .iter()
@@ -419,7 +581,7 @@ impl BitcoinD {
                     .expect("API break: 'listwallets' contains a non-string value")
                     .to_string()
             })
-            .collect()
+            .collect())
     }
 
     fn unload_wallet(&self, wallet_path: String) -> Option<String> {
@@ -436,26 +598,37 @@ impl BitcoinD {
             })
     }
 
-    fn create_wallet(&self, wallet_path: String) -> Option<String> {
-        let res = self.make_node_request(
-            "createwallet",
-            &params!(
-                Json::String(wallet_path),
-                Json::Bool(true), // watchonly
-                Json::Bool(true), // blank
-            ),
-        );
+    fn create_wallet(&self, wallet_path: String) -> Result<(), BitcoindError> {
+        let res = self
+            .make_request(
+                &self.sendonly_client,
+                "createwallet",
+                &params!(
+                    Json::String(wallet_path),
+                    Json::Bool(true), // watchonly
+                    Json::Bool(true), // blank
+                ),
+            )
+            .map_err(|e| {
+                if e.is_filesystem_error() {
+                    BitcoindError::WalletFilesystem(e.to_string())
+                } else {
+                    BitcoindError::WalletCreation(e.to_string())
+                }
+            })?;
 
         if let Some(warning) = res.get("warning").and_then(Json::as_str) {
             if !warning.is_empty() {
-                return Some(warning.to_string());
+                return Err(BitcoindError::WalletCreation(warning.to_string()));
             }
         }
         if res.get("name").is_none() {
-            return Some("Unknown error when create watchonly wallet".to_string());
+            return Err(BitcoindError::WalletCreation(
+                "Unknown error when create watchonly wallet".to_string(),
+            ));
         }
 
-        None
+        Ok(())
     }
 
     // Import the receive and change descriptors from the multipath descriptor to bitcoind.
@@ -531,7 +704,7 @@ impl BitcoinD {
     ) -> Result<(), BitcoindError> {
         // Remove any leftover. This can happen if we delete the watchonly wallet but don't restart
         // bitcoind.
-        while self.list_wallets().contains(&self.watchonly_wallet_path) {
+        while self.list_wallets()?.contains(&self.watchonly_wallet_path) {
             log::info!("Found a leftover watchonly wallet loaded on bitcoind. Removing it.");
             if let Some(e) = self.unload_wallet(self.watchonly_wallet_path.clone()) {
                 log::error!(
@@ -543,9 +716,7 @@ impl BitcoinD {
         }
 
         // Now create the wallet and import the main descriptor.
-        if let Some(err) = self.create_wallet(self.watchonly_wallet_path.clone()) {
-            return Err(BitcoindError::WalletCreation(err));
-        }
+        self.create_wallet(self.watchonly_wallet_path.clone())?;
         if let Some(err) = self.import_descriptor(main_descriptor) {
             return Err(BitcoindError::DescriptorImport(err));
         }
@@ -555,7 +726,7 @@ impl BitcoinD {
 
     /// Load the watchonly wallet on bitcoind, if it isn't already.
     pub fn maybe_load_watchonly_wallet(&self) -> Result<(), BitcoindError> {
-        if !self.list_wallets().contains(&self.watchonly_wallet_path) {
+        if !self.list_wallets()?.contains(&self.watchonly_wallet_path) {
             self.make_fallible_node_request(
                 "loadwallet",
                 &params!(Json::String(self.watchonly_wallet_path.clone()),),
@@ -570,30 +741,11 @@ impl BitcoinD {
         main_descriptor: &MultipathDescriptor,
         config_network: bitcoin::Network,
     ) -> Result<(), BitcoindError> {
-        // Check the minimum supported bitcoind version
-        let version = self.get_bitcoind_version();
-        if version < MIN_BITCOIND_VERSION {
-            return Err(BitcoindError::InvalidVersion(version));
-        }
-
-        // Check bitcoind is running on the right network
-        let bitcoind_net = self.get_network_bip70();
-        let bip70_net = match config_network {
-            bitcoin::Network::Bitcoin => "main",
-            bitcoin::Network::Testnet => "test",
-            bitcoin::Network::Regtest => "regtest",
-            bitcoin::Network::Signet => "signet",
-        };
-        if bitcoind_net != bip70_net {
-            return Err(BitcoindError::NetworkMismatch(
-                bip70_net.to_string(),
-                bitcoind_net,
-            ));
-        }
+        self.check_version_and_network(config_network)?;
 
         // Check our watchonly wallet is loaded
         if self
-            .list_wallets()
+            .list_wallets()?
             .iter()
             .filter(|s| s == &&self.watchonly_wallet_path)
             .count()
@@ -619,6 +771,35 @@ impl BitcoinD {
         Ok(())
     }
 
+    /// Check bitcoind is of a supported version and running on the given network.
+    fn check_version_and_network(
+        &self,
+        config_network: bitcoin::Network,
+    ) -> Result<(), BitcoindError> {
+        // Check the minimum supported bitcoind version
+        let version = self.get_bitcoind_version()?;
+        if version < MIN_BITCOIND_VERSION {
+            return Err(BitcoindError::InvalidVersion(version));
+        }
+
+        // Check bitcoind is running on the right network
+        let bitcoind_net = self.get_network_bip70()?;
+        let bip70_net = match config_network {
+            bitcoin::Network::Bitcoin => "main",
+            bitcoin::Network::Testnet => "test",
+            bitcoin::Network::Regtest => "regtest",
+            bitcoin::Network::Signet => "signet",
+        };
+        if bitcoind_net != bip70_net {
+            return Err(BitcoindError::NetworkMismatch(
+                bip70_net.to_string(),
+                bitcoind_net,
+            ));
+        }
+
+        Ok(())
+    }
+
     fn block_chain_info(&self) -> Json {
         self.make_node_request("getblockchaininfo", &[])
     }
@@ -687,6 +868,12 @@ impl BitcoinD {
         .map(|res| res.into())
     }
 
+    /// Whether this transaction is in our mempool.
+    pub fn is_in_mempool(&self, txid: &bitcoin::Txid) -> bool {
+        self.make_fallible_node_request("getmempoolentry", &params!(Json::String(txid.to_string())))
+            .is_ok()
+    }
+
     /// Efficient check that a coin is spent.
     pub fn is_spent(&self, op: &bitcoin::OutPoint) -> bool {
         // The result of gettxout is empty if the outpoint is spent.
@@ -921,6 +1108,14 @@ impl BitcoinD {
                 "importdescriptors",
                 &params!(Json::Array(desc_json.clone())),
             ) {
+                // Someone else may have triggered a rescan of the wallet in the meantime. Unless
+                // it's ours from a previous attempt, don't interfere with it.
+                if e.is_already_rescanning() {
+                    if self.check_descs_timestamp(&desc_str, timestamp) {
+                        return Ok(());
+                    }
+                    return Err(BitcoindError::AlreadyRescanning);
+                }
                 log::error!(
                     "Error when calling 'importdescriptors' for rescanning: {}",
                     e
@@ -941,12 +1136,74 @@ impl BitcoinD {
 
     /// Get the progress of the ongoing rescan, if there is any.
     pub fn rescan_progress(&self) -> Option<f64> {
-        self.make_wallet_request("getwalletinfo", &[])
+        self.rescan_info().map(|info| info.progress)
+    }
+
+    /// Get the progress and duration of the ongoing rescan, if there is any.
+    pub fn rescan_info(&self) -> Option<RescanInfo> {
+        let scanning = self
+            .make_wallet_request("getwalletinfo", &[])
             .get("scanning")
             // If no rescan is ongoing, it will fail cause it would be 'false'
             .and_then(Json::as_object)
-            .and_then(|map| map.get("progress"))
+            .cloned()?;
+        let progress = scanning.get("progress").and_then(Json::as_f64)?;
+        let duration = scanning
+            .get("duration")
+            .and_then(Json::as_u64)
+            .and_then(|d| d.try_into().ok())
+            .unwrap_or(0);
+        Some(RescanInfo { progress, duration })
+    }
+
+    /// Abort the ongoing rescan of the watchonly wallet, if there is any. Returns whether a
+    /// rescan was aborted.
+    pub fn abort_rescan(&self) -> bool {
+        self.make_wallet_request("abortrescan", &[])
+            .as_bool()
+            .unwrap_or(false)
+    }
+
+    /// Get the minimum feerate, in sats/vb, for a transaction to be relayed by this node.
+    pub fn relay_feerate(&self) -> u64 {
+        let min_fee = self
+            .make_node_request("getmempoolinfo", &[])
+            .get("minrelaytxfee")
             .and_then(Json::as_f64)
+            .expect("No valid 'minrelaytxfee' in 'getmempoolinfo' response?");
+        btc_kvb_to_sat_vb(min_fee)
+    }
+
+    /// Get the minimum feerate, in sats/vb, by which a replacement must increase the fee for it
+    /// to be relayed by this node.
+    pub fn incremental_relay_feerate(&self) -> u64 {
+        let incremental_fee = self
+            .make_node_request("getnetworkinfo", &[])
+            .get("incrementalfee")
+            .and_then(Json::as_f64)
+            .expect("No valid 'incrementalfee' in 'getnetworkinfo' response?");
+        btc_kvb_to_sat_vb(incremental_fee)
+    }
+
+    /// Get the estimated feerate, in BTC/kvb, for a transaction to confirm within the given
+    /// number of blocks. `None` if bitcoind doesn't have enough data to estimate it.
+    pub fn estimate_smart_fee(&self, target: u16) -> Option<f64> {
+        self.make_node_request("estimatesmartfee", &params!(Json::Number(target.into()),))
+            .get("feerate")
+            .and_then(Json::as_f64)
+    }
+
+    /// Get the total amount, in BTC, received by this address of the watchonly wallet. Includes
+    /// unconfirmed transactions.
+    pub fn received_by_address(&self, address: &bitcoin::Address) -> f64 {
+        // bitcoind errors if the address isn't part of the wallet, in which case it's unused.
+        self.make_faillible_wallet_request(
+            "getreceivedbyaddress",
+            &params!(Json::String(address.to_string()), Json::Number(0.into())),
+        )
+        .ok()
+        .and_then(|res| res.as_f64())
+        .unwrap_or(0.0)
     }
 
     /// Get the height and hash of the last block with a timestamp below the given one.
@@ -1063,6 +1320,8 @@ impl From<Json> for LSBlockRes {
 
 #[derive(Debug, Clone)]
 pub struct GetTxRes {
+    /// Negative if the transaction conflicts with a confirmed one.
+    pub confirmations: i32,
     pub conflicting_txs: Vec<bitcoin::Txid>,
     pub block: Option<Block>,
     pub tx: bitcoin::Transaction,
@@ -1081,6 +1340,10 @@ impl From<Json> for GetTxRes {
             .get("blocktime")
             .and_then(Json::as_u64)
             .map(|bt| bt as u32);
+        let confirmations = json
+            .get("confirmations")
+            .and_then(Json::as_i64)
+            .expect("Must be present in bitcoind response") as i32;
         let conflicting_txs = json
             .get("walletconflicts")
             .and_then(Json::as_array)
@@ -1104,6 +1367,7 @@ impl From<Json> for GetTxRes {
         let tx: bitcoin::Transaction = bitcoin::consensus::encode::deserialize(&bytes)
             .expect("bitcoind returned a wrong transaction format");
         GetTxRes {
+            confirmations,
             conflicting_txs: conflicting_txs.unwrap_or_default(),
             block,
             tx,
@@ -1120,3 +1384,88 @@ pub struct BlockStats {
     pub time: u32,
     pub median_time_past: u32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+
+    fn dummy_bitcoind(retries: usize) -> BitcoinD {
+        let client = || Client::with_transport(SimpleHttpTransport::new());
+        BitcoinD {
+            node_client: client(),
+            sendonly_client: client(),
+            watchonly_client: client(),
+            watchonly_wallet_path: "dummy".to_string(),
+            retries,
+            retry_interval: Duration::from_millis(1),
+        }
+    }
+
+    // A request failing the given number of times with the given error before succeeding.
+    fn flaky_request<'a>(
+        failures: usize,
+        error: &'a dyn Fn() -> BitcoindError,
+        calls: &'a Cell<usize>,
+    ) -> impl Fn() -> Result<(), BitcoindError> + 'a {
+        move || {
+            calls.set(calls.get() + 1);
+            if calls.get() <= failures {
+                Err(error())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn retry_count() {
+        let timeout = || {
+            BitcoindError::Server(jsonrpc::Error::Transport(Box::new(
+                simple_http::Error::Timeout,
+            )))
+        };
+        let warming_up = || {
+            BitcoindError::Server(jsonrpc::Error::Rpc(jsonrpc::error::RpcError {
+                code: -28,
+                message: "Loading block index...".to_string(),
+                data: None,
+            }))
+        };
+        let bitcoind = dummy_bitcoind(3);
+
+        // Transient errors are retried as many times as configured.
+        for error in &[&timeout as &dyn Fn() -> BitcoindError, &warming_up] {
+            let calls = Cell::new(0);
+            assert!(bitcoind.retry(flaky_request(3, *error, &calls)).is_ok());
+            assert_eq!(calls.get(), 4);
+
+            let calls = Cell::new(0);
+            assert!(bitcoind.retry(flaky_request(4, *error, &calls)).is_err());
+            assert_eq!(calls.get(), 4);
+        }
+
+        // Other errors aren't retried.
+        let fs_error = || {
+            BitcoindError::Server(jsonrpc::Error::Rpc(jsonrpc::error::RpcError {
+                code: -1,
+                message: "filesystem error: Permission denied".to_string(),
+                data: None,
+            }))
+        };
+        let calls = Cell::new(0);
+        let err = bitcoind
+            .retry(flaky_request(1, &fs_error, &calls))
+            .unwrap_err();
+        assert!(err.is_filesystem_error());
+        assert_eq!(calls.get(), 1);
+
+        // Without retries, the first transient error is returned.
+        let calls = Cell::new(0);
+        assert!(dummy_bitcoind(0)
+            .retry(flaky_request(1, &timeout, &calls))
+            .is_err());
+        assert_eq!(calls.get(), 1);
+    }
+}
@@ -18,6 +18,15 @@ pub fn roundup_progress(progress: f64) -> f64 {
     }
 }
 
+/// Convert a feerate in BTC/kvb, as returned by bitcoind, to sats/vb. It is rounded up, as we
+/// only use it as a lower bound.
+pub fn btc_kvb_to_sat_vb(feerate: f64) -> u64 {
+    let sat_kvb = bitcoin::Amount::from_btc(feerate)
+        .expect("bitcoind returned an invalid feerate")
+        .to_sat();
+    sat_kvb.checked_add(999).unwrap() / 1_000
+}
+
 // As a standalone function to unit test it.
 /// Get the last block of the chain before the given date by performing a binary search.
 pub fn block_before_date<Fh, Fs>(
@@ -374,4 +383,14 @@ mod tests {
         assert_eq!(roundup_progress(0.9998), 1.0);
         assert_eq!(roundup_progress(0.9991), 1.0);
     }
+
+    #[test]
+    fn bitcoind_feerate_conversion() {
+        assert_eq!(btc_kvb_to_sat_vb(0.0), 0);
+        assert_eq!(btc_kvb_to_sat_vb(0.00001), 1);
+        assert_eq!(btc_kvb_to_sat_vb(0.000001), 1);
+        assert_eq!(btc_kvb_to_sat_vb(0.00002), 2);
+        assert_eq!(btc_kvb_to_sat_vb(0.000015), 2);
+        assert_eq!(btc_kvb_to_sat_vb(0.001), 100);
+    }
 }
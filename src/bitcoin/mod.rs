@@ -28,6 +28,32 @@ pub struct BlockChainTip {
     pub height: i32,
 }
 
+/// Information about an ongoing rescan, as reported by the Bitcoin backend.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RescanInfo {
+    /// Progress percentage. Between 0 and 1.
+    pub progress: f64,
+    /// Time elapsed since the rescan started, in seconds.
+    pub duration: u32,
+}
+
+/// An error when triggering a rescan on the Bitcoin backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RescanError {
+    /// The backend is already rescanning, possibly because someone else triggered it.
+    AlreadyRescanning,
+    Other(String),
+}
+
+impl fmt::Display for RescanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::AlreadyRescanning => write!(f, "The backend is already rescanning."),
+            Self::Other(s) => write!(f, "{}", s),
+        }
+    }
+}
+
 impl fmt::Display for BlockChainTip {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "({},{})", self.height, self.hash)
@@ -64,6 +90,11 @@ pub trait BitcoinInterface: Send {
         outpoints: &[bitcoin::OutPoint],
     ) -> Vec<(bitcoin::OutPoint, i32, u32)>;
 
+    /// Get the coins among these unconfirmed ones whose creating transaction was dropped because
+    /// it was double spent. A transaction merely evicted from the mempool is not considered
+    /// dropped, as it may still be rebroadcast and confirmed.
+    fn dropped_coins(&self, outpoints: &[bitcoin::OutPoint]) -> Vec<bitcoin::OutPoint>;
+
     /// Get all coins that are being spent, and the spending txid.
     fn spending_coins(
         &self,
@@ -88,11 +119,17 @@ pub trait BitcoinInterface: Send {
         &self,
         desc: &descriptors::MultipathDescriptor,
         timestamp: u32,
-    ) -> Result<(), String>;
+    ) -> Result<(), RescanError>;
 
     /// Rescan progress percentage. Between 0 and 1.
     fn rescan_progress(&self) -> Option<f64>;
 
+    /// Progress and duration of the ongoing rescan, if there is any.
+    fn rescan_info(&self) -> Option<RescanInfo>;
+
+    /// Abort the ongoing rescan, if there is any. Returns whether a rescan was aborted.
+    fn abort_rescan(&self) -> bool;
+
     /// Get the last block chain tip with a timestamp below this. Timestamp must be a valid block
     /// timestamp.
     fn block_before_date(&self, timestamp: u32) -> Option<BlockChainTip>;
@@ -102,6 +139,19 @@ pub trait BitcoinInterface: Send {
         &self,
         txid: &bitcoin::Txid,
     ) -> Option<(bitcoin::Transaction, Option<Block>)>;
+
+    /// Minimum feerate (in sats/vb, rounded up) for a transaction to be relayed by our node.
+    fn relay_feerate(&self) -> u64;
+
+    /// Minimum feerate (in sats/vb, rounded up) by which a replacement transaction must increase
+    /// the fee of the transactions it replaces to be relayed by our node.
+    fn incremental_relay_feerate(&self) -> u64;
+
+    /// Estimated feerate (in BTC/kvb) for a transaction to confirm within this number of blocks.
+    fn estimate_smart_fee(&self, target: u16) -> Option<f64>;
+
+    /// Whether this address of the wallet ever received any coin, confirmed or not.
+    fn is_address_used(&self, address: &bitcoin::Address) -> bool;
 }
 
 impl BitcoinInterface for d::BitcoinD {
@@ -183,6 +233,30 @@ impl BitcoinInterface for d::BitcoinD {
         confirmed
     }
 
+    fn dropped_coins(&self, outpoints: &[bitcoin::OutPoint]) -> Vec<bitcoin::OutPoint> {
+        let mut dropped_txs = HashMap::new();
+
+        outpoints
+            .iter()
+            .filter(|op| {
+                *dropped_txs.entry(op.txid).or_insert_with(|| {
+                    // Don't drop coins for a transaction the wallet doesn't know about, it
+                    // might just be a bitcoind hiccup. Only drop it if it conflicts with a
+                    // confirmed transaction, or was replaced by one of the wallet's transactions.
+                    self.get_transaction(&op.txid)
+                        .map(|res| {
+                            res.block.is_none()
+                                && (res.confirmations < 0
+                                    || (!res.conflicting_txs.is_empty()
+                                        && !self.is_in_mempool(&op.txid)))
+                        })
+                        .unwrap_or(false)
+                })
+            })
+            .copied()
+            .collect()
+    }
+
     fn spending_coins(
         &self,
         outpoints: &[bitcoin::OutPoint],
@@ -291,16 +365,26 @@ impl BitcoinInterface for d::BitcoinD {
         &self,
         desc: &descriptors::MultipathDescriptor,
         timestamp: u32,
-    ) -> Result<(), String> {
+    ) -> Result<(), RescanError> {
         // FIXME: in theory i think this could potentially fail to actually start the rescan.
-        self.start_rescan(desc, timestamp)
-            .map_err(|e| e.to_string())
+        self.start_rescan(desc, timestamp).map_err(|e| match e {
+            BitcoindError::AlreadyRescanning => RescanError::AlreadyRescanning,
+            e => RescanError::Other(e.to_string()),
+        })
     }
 
     fn rescan_progress(&self) -> Option<f64> {
         self.rescan_progress()
     }
 
+    fn rescan_info(&self) -> Option<RescanInfo> {
+        self.rescan_info()
+    }
+
+    fn abort_rescan(&self) -> bool {
+        self.abort_rescan()
+    }
+
     fn block_before_date(&self, timestamp: u32) -> Option<BlockChainTip> {
         self.tip_before_timestamp(timestamp)
     }
@@ -316,6 +400,22 @@ impl BitcoinInterface for d::BitcoinD {
     ) -> Option<(bitcoin::Transaction, Option<Block>)> {
         self.get_transaction(txid).map(|res| (res.tx, res.block))
     }
+
+    fn relay_feerate(&self) -> u64 {
+        self.relay_feerate()
+    }
+
+    fn incremental_relay_feerate(&self) -> u64 {
+        self.incremental_relay_feerate()
+    }
+
+    fn estimate_smart_fee(&self, target: u16) -> Option<f64> {
+        self.estimate_smart_fee(target)
+    }
+
+    fn is_address_used(&self, address: &bitcoin::Address) -> bool {
+        self.received_by_address(address) > 0.0
+    }
 }
 
 // FIXME: do we need to repeat the entire trait implemenation? Isn't there a nicer way?
@@ -351,6 +451,10 @@ impl BitcoinInterface for sync::Arc<sync::Mutex<dyn BitcoinInterface + 'static>>
         self.lock().unwrap().confirmed_coins(outpoints)
     }
 
+    fn dropped_coins(&self, outpoints: &[bitcoin::OutPoint]) -> Vec<bitcoin::OutPoint> {
+        self.lock().unwrap().dropped_coins(outpoints)
+    }
+
     fn spending_coins(
         &self,
         outpoints: &[bitcoin::OutPoint],
@@ -377,7 +481,7 @@ impl BitcoinInterface for sync::Arc<sync::Mutex<dyn BitcoinInterface + 'static>>
         &self,
         desc: &descriptors::MultipathDescriptor,
         timestamp: u32,
-    ) -> Result<(), String> {
+    ) -> Result<(), RescanError> {
         self.lock().unwrap().start_rescan(desc, timestamp)
     }
 
@@ -385,6 +489,14 @@ impl BitcoinInterface for sync::Arc<sync::Mutex<dyn BitcoinInterface + 'static>>
         self.lock().unwrap().rescan_progress()
     }
 
+    fn rescan_info(&self) -> Option<RescanInfo> {
+        self.lock().unwrap().rescan_info()
+    }
+
+    fn abort_rescan(&self) -> bool {
+        self.lock().unwrap().abort_rescan()
+    }
+
     fn block_before_date(&self, timestamp: u32) -> Option<BlockChainTip> {
         self.lock().unwrap().block_before_date(timestamp)
     }
@@ -399,6 +511,22 @@ impl BitcoinInterface for sync::Arc<sync::Mutex<dyn BitcoinInterface + 'static>>
     ) -> Option<(bitcoin::Transaction, Option<Block>)> {
         self.lock().unwrap().wallet_transaction(txid)
     }
+
+    fn relay_feerate(&self) -> u64 {
+        self.lock().unwrap().relay_feerate()
+    }
+
+    fn incremental_relay_feerate(&self) -> u64 {
+        self.lock().unwrap().incremental_relay_feerate()
+    }
+
+    fn estimate_smart_fee(&self, target: u16) -> Option<f64> {
+        self.lock().unwrap().estimate_smart_fee(target)
+    }
+
+    fn is_address_used(&self, address: &bitcoin::Address) -> bool {
+        self.lock().unwrap().is_address_used(address)
+    }
 }
 
 // FIXME: We could avoid this type (and all the conversions entailing allocations) if bitcoind
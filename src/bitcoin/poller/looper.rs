@@ -1,15 +1,17 @@
 use crate::{
-    bitcoin::{BitcoinInterface, BlockChainTip, UTxO},
+    bitcoin::{BitcoinInterface, BlockChainTip, RescanInfo, UTxO},
     database::{Coin, CoinType, DatabaseConnection, DatabaseInterface},
     descriptors,
+    notifications::{Notifier, WalletEvent},
 };
 
 use std::{
+    cmp,
     sync::{self, atomic},
     thread, time,
 };
 
-use miniscript::bitcoin::{self, secp256k1};
+use miniscript::bitcoin::{self, secp256k1, util::bip32};
 
 #[derive(Debug, Clone)]
 struct UpdatedCoins {
@@ -17,6 +19,35 @@ struct UpdatedCoins {
     pub confirmed: Vec<(bitcoin::OutPoint, i32, u32)>,
     pub spending: Vec<(bitcoin::OutPoint, bitcoin::Txid)>,
     pub spent: Vec<(bitcoin::OutPoint, bitcoin::Txid, i32, u32)>,
+    pub dropped: Vec<Coin>,
+}
+
+// Look for the derivation index of an address we don't have in database among the next
+// `gap_limit` indexes of both the receive and change chains, past the addresses we do store.
+fn derivation_index_in_gap(
+    db_conn: &mut Box<dyn DatabaseConnection>,
+    address: &bitcoin::Address,
+    descs: &[descriptors::InheritanceDescriptor],
+    gap_limit: u32,
+    secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+) -> Option<(bip32::ChildNumber, bool)> {
+    let script_pubkey = address.script_pubkey();
+    let start_index: u32 = db_conn.first_unstored_index().into();
+
+    for index in start_index..start_index.saturating_add(gap_limit) {
+        let index = match bip32::ChildNumber::from_normal_idx(index) {
+            Ok(index) => index,
+            Err(_) => break,
+        };
+        if descs[0].derive(index, secp).script_pubkey() == script_pubkey {
+            return Some((index, false));
+        }
+        if descs[1].derive(index, secp).script_pubkey() == script_pubkey {
+            return Some((index, true));
+        }
+    }
+
+    None
 }
 
 // Update the state of our coins. There may be new unspent, and existing ones may become confirmed
@@ -28,6 +59,7 @@ fn update_coins(
     db_conn: &mut Box<dyn DatabaseConnection>,
     previous_tip: &BlockChainTip,
     descs: &[descriptors::InheritanceDescriptor],
+    gap_limit: u32,
     secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
 ) -> UpdatedCoins {
     let curr_coins = db_conn.coins(CoinType::All);
@@ -36,10 +68,13 @@ fn update_coins(
     // Start by fetching newly received coins.
     let mut received = Vec::new();
     for utxo in bit.received_coins(previous_tip, descs) {
-        // We can only really treat them if we know the derivation index that was used.
-        if let Some((derivation_index, is_change)) =
-            db_conn.derivation_index_by_address(&utxo.address)
-        {
+        // We can only really treat them if we know the derivation index that was used. If the
+        // address isn't in our database, it may have been derived past our current indexes (for
+        // instance by another wallet using the same descriptor). Look for it within the gap limit.
+        let der_info = db_conn
+            .derivation_index_by_address(&utxo.address)
+            .or_else(|| derivation_index_in_gap(db_conn, &utxo.address, descs, gap_limit, secp));
+        if let Some((derivation_index, is_change)) = der_info {
             // First of if we are receiving coins that are beyond our next derivation index,
             // adjust it.
             if derivation_index > db_conn.receive_index() {
@@ -63,11 +98,12 @@ fn update_coins(
                     block_time: None,
                     spend_txid: None,
                     spend_block: None,
+                    spend_broadcast_time: None,
+                    is_frozen: false,
                 };
                 received.push(coin);
             }
         } else {
-            // TODO: maybe we could try out something here? Like bruteforcing the next 200 indexes?
             log::error!(
                 "Could not get derivation index for coin '{}' (address: '{}')",
                 &utxo.outpoint,
@@ -77,6 +113,26 @@ fn update_coins(
     }
     log::debug!("Newly received coins: {:?}", received);
 
+    // Some of the unconfirmed coins we knew about may have vanished, for instance because the
+    // transaction creating them was double spent. Forget about them.
+    let unconfirmed: Vec<bitcoin::OutPoint> = curr_coins
+        .values()
+        .filter_map(|coin| {
+            if coin.block_height.is_none() {
+                Some(coin.outpoint)
+            } else {
+                None
+            }
+        })
+        .collect();
+    let dropped: Vec<Coin> = bit
+        .dropped_coins(&unconfirmed)
+        .into_iter()
+        .filter_map(|op| curr_coins.get(&op).cloned())
+        .collect();
+    log::debug!("Dropped coins: {:?}", dropped);
+    let is_dropped = |coin: &Coin| dropped.iter().any(|d| d.outpoint == coin.outpoint);
+
     // We need to take the newly received ones into account as well, as they may have been
     // confirmed within the previous tip and the current one, and we may not poll this chunk of the
     // chain anymore.
@@ -84,7 +140,7 @@ fn update_coins(
         .values()
         .chain(received.iter())
         .filter_map(|coin| {
-            if coin.block_height.is_none() {
+            if coin.block_height.is_none() && !is_dropped(coin) {
                 Some(coin.outpoint)
             } else {
                 None
@@ -101,7 +157,7 @@ fn update_coins(
         .values()
         .chain(received.iter())
         .filter_map(|coin| {
-            if coin.spend_txid.is_none() {
+            if coin.spend_txid.is_none() && !is_dropped(coin) {
                 Some(coin.outpoint)
             } else {
                 None
@@ -118,6 +174,7 @@ fn update_coins(
     let spending_coins: Vec<(bitcoin::OutPoint, bitcoin::Txid)> = db_conn
         .list_spending_coins()
         .values()
+        .filter(|coin| !is_dropped(coin))
         .map(|coin| (coin.outpoint, coin.spend_txid.expect("Coin is spending")))
         .chain(spending.iter().cloned())
         .collect();
@@ -133,6 +190,7 @@ fn update_coins(
         confirmed,
         spending,
         spent,
+        dropped,
     }
 }
 
@@ -187,7 +245,9 @@ fn updates(
     bit: &impl BitcoinInterface,
     db: &impl DatabaseInterface,
     descs: &[descriptors::InheritanceDescriptor],
+    gap_limit: u32,
     secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+    notifier: &Notifier,
 ) {
     let mut db_conn = db.connection();
 
@@ -201,18 +261,18 @@ fn updates(
             // between our former chain and the new one, then restart fresh.
             db_conn.rollback_tip(&new_tip);
             log::info!("Tip was rolled back to '{}'.", new_tip);
-            return updates(bit, db, descs, secp);
+            return updates(bit, db, descs, gap_limit, secp, notifier);
         }
     };
 
     // Then check the state of our coins. Do it even if the tip did not change since last poll, as
     // we may have unconfirmed transactions.
-    let updated_coins = update_coins(bit, &mut db_conn, &current_tip, descs, secp);
+    let updated_coins = update_coins(bit, &mut db_conn, &current_tip, descs, gap_limit, secp);
 
     // If the tip changed while we were polling our Bitcoin interface, start over.
     if bit.chain_tip() != latest_tip {
         log::info!("Chain tip changed while we were updating our state. Starting over.");
-        return updates(bit, db, descs, secp);
+        return updates(bit, db, descs, gap_limit, secp, notifier);
     }
 
     // The chain tip did not change since we started our updates. Record them and the latest tip.
@@ -222,20 +282,117 @@ fn updates(
     db_conn.confirm_coins(&updated_coins.confirmed);
     db_conn.spend_coins(&updated_coins.spending);
     db_conn.confirm_spend(&updated_coins.spent);
+    let dropped: Vec<bitcoin::OutPoint> =
+        updated_coins.dropped.iter().map(|c| c.outpoint).collect();
+    db_conn.remove_coins(&dropped);
     if latest_tip != current_tip {
         db_conn.update_tip(&latest_tip);
         log::debug!("New tip: '{}'", latest_tip);
     }
 
+    // Now they are recorded, let the subscribers know about them.
+    for coin in &updated_coins.received {
+        let derivation_index: u32 = coin.derivation_index.into();
+        log::info!(
+            "New coin: outpoint={} amount_sat={} derivation_index={} is_change={}",
+            coin.outpoint,
+            coin.amount.to_sat(),
+            derivation_index,
+            coin.is_change
+        );
+        notifier.notify(WalletEvent::NewCoin {
+            outpoint: coin.outpoint,
+            amount_sat: coin.amount.to_sat(),
+            derivation_index,
+            is_change: coin.is_change,
+        });
+    }
+    for (outpoint, txid, height, _) in &updated_coins.spent {
+        notifier.notify(WalletEvent::SpendConfirmed {
+            outpoint: *outpoint,
+            txid: *txid,
+            height: *height,
+        });
+    }
+    for coin in &updated_coins.dropped {
+        log::warn!(
+            "Coin '{}' was dropped: its transaction was double spent.",
+            coin.outpoint
+        );
+        notifier.notify(WalletEvent::CoinDropped {
+            outpoint: coin.outpoint,
+            amount_sat: coin.amount.to_sat(),
+        });
+    }
+    if latest_tip != current_tip {
+        notifier.notify(WalletEvent::NewBlock {
+            height: latest_tip.height,
+            hash: latest_tip.hash,
+        });
+    }
+
     log::debug!("Updates done.");
 }
 
-// Check if there is any rescan of the backend ongoing or one that just finished.
+// Record a lower bound of how far the rescan from this timestamp got. We interpolate linearly
+// between the rescan and tip timestamps. The progress reported by the backend is weighted by the
+// number of transactions, which grows over time, so it underestimates how far it got. If the
+// backend is rescanning from a later timestamp (it was resumed), we underestimate it even more.
+fn record_rescan_progress(
+    bit: &impl BitcoinInterface,
+    db_conn: &mut Box<dyn DatabaseConnection>,
+    timestamp: u32,
+    progress: f64,
+) {
+    let span = bit.tip_time().saturating_sub(timestamp);
+    let reached = timestamp + (span as f64 * progress.max(0.0).min(1.0)) as u32;
+    // Never go backward, for instance after resuming the rescan.
+    if db_conn
+        .rescan_progress()
+        .map(|p| reached > p)
+        .unwrap_or(true)
+    {
+        db_conn.set_rescan_progress(reached);
+    }
+}
+
+// If we were stopped while rescanning and the backend isn't anymore, resume the rescan from how
+// far it got. Otherwise we would treat it as completed at the next poll.
+fn maybe_resume_rescan(
+    bit: &impl BitcoinInterface,
+    db: &impl DatabaseInterface,
+    desc: &descriptors::MultipathDescriptor,
+) {
+    let mut db_conn = db.connection();
+
+    let timestamp = match db_conn.rescan_timestamp() {
+        Some(timestamp) => timestamp,
+        None => return,
+    };
+    if bit.rescan_progress().is_some() {
+        return;
+    }
+    let resume_timestamp = db_conn.rescan_progress().unwrap_or(timestamp);
+    log::info!(
+        "Resuming the rescan from '{}' at '{}'.",
+        timestamp,
+        resume_timestamp
+    );
+    if let Err(e) = bit.start_rescan(desc, resume_timestamp) {
+        log::error!("Error resuming the rescan: '{}'.", e);
+    }
+}
+
+// Check if there is any rescan of the backend ongoing or one that just finished. A rescan we
+// asked for which is still ongoing after the given timeout is aborted.
 fn rescan_check(
     bit: &impl BitcoinInterface,
     db: &impl DatabaseInterface,
     descs: &[descriptors::InheritanceDescriptor],
+    gap_limit: u32,
+    rescan_timeout: Option<time::Duration>,
     secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+    notifier: &Notifier,
 ) {
     log::debug!("Checking the state of an ongoing rescan if there is any");
     let mut db_conn = db.connection();
@@ -245,11 +402,27 @@ fn rescan_check(
     // Upon completion of the rescan from the given timestamp on the backend, we rollback our state
     // down to the height before this timestamp to rescan everything that happened since then.
     let rescan_timestamp = db_conn.rescan_timestamp();
-    if let Some(progress) = bit.rescan_progress() {
+    if let Some(RescanInfo { progress, duration }) = bit.rescan_info() {
         log::info!("Rescan progress: {:.2}%.", progress * 100.0);
-        if rescan_timestamp.is_none() {
+        notifier.notify(WalletEvent::RescanProgress { progress });
+        if let Some(timestamp) = rescan_timestamp {
+            record_rescan_progress(bit, &mut db_conn, timestamp, progress);
+        } else {
             log::warn!("Backend is rescanning but we didn't ask for it.");
         }
+        if let (Some(_), Some(timeout)) = (rescan_timestamp, rescan_timeout) {
+            if u64::from(duration) >= timeout.as_secs() {
+                log::warn!(
+                    "Rescan still ongoing after {} seconds, aborting it at {:.2}% progress.",
+                    duration,
+                    progress * 100.0
+                );
+                if !bit.abort_rescan() {
+                    log::error!("Could not abort the rescan on the backend.");
+                }
+                db_conn.cancel_rescan();
+            }
+        }
     } else if let Some(timestamp) = rescan_timestamp {
         log::info!("Rescan completed on the backend.");
         // TODO: we could check if the timestamp of the descriptors in the Bitcoin backend are
@@ -273,7 +446,7 @@ fn rescan_check(
             "Rolling back our internal tip to '{}' to update our internal state with past transactions.",
             rescan_tip
         );
-        updates(bit, db, descs, secp)
+        updates(bit, db, descs, gap_limit, secp, notifier)
     } else {
         log::debug!("No ongoing rescan.");
     }
@@ -289,16 +462,39 @@ fn maybe_initialize_tip(bit: &impl BitcoinInterface, db: &impl DatabaseInterface
     }
 }
 
+// How often to poll while the Bitcoin backend is syncing or rescanning, as there are many updates
+// to pick up then.
+const BUSY_POLL_INTERVAL: time::Duration = time::Duration::from_secs(5);
+
+// How long to wait before the next poll. We poll more often while the Bitcoin backend is catching
+// up with the chain or rescanning it, but never less often than the configured interval.
+fn next_poll_interval(
+    bit: &impl BitcoinInterface,
+    db: &impl DatabaseInterface,
+    poll_interval: time::Duration,
+) -> time::Duration {
+    let is_busy = bit.sync_progress() < 1.0 || db.connection().rescan_timestamp().is_some();
+    if is_busy {
+        cmp::min(poll_interval, BUSY_POLL_INTERVAL)
+    } else {
+        poll_interval
+    }
+}
+
 /// Main event loop. Repeatedly polls the Bitcoin interface until told to stop through the
-/// `shutdown` atomic.
+/// `shutdown` atomic. The given poll interval is the maximum time between two polls.
 pub fn looper(
     bit: sync::Arc<sync::Mutex<dyn BitcoinInterface>>,
     db: sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
     shutdown: sync::Arc<atomic::AtomicBool>,
     poll_interval: time::Duration,
+    gap_limit: u32,
+    rescan_timeout: Option<time::Duration>,
     desc: descriptors::MultipathDescriptor,
+    notifier: Notifier,
 ) {
     let mut last_poll = None;
+    let mut interval = poll_interval;
     let mut synced = false;
     let descs = [
         desc.receive_descriptor().clone(),
@@ -307,12 +503,13 @@ pub fn looper(
     let secp = secp256k1::Secp256k1::verification_only();
 
     maybe_initialize_tip(&bit, &db);
+    maybe_resume_rescan(&bit, &db, &desc);
 
     while !shutdown.load(atomic::Ordering::Relaxed) || last_poll.is_none() {
         let now = time::Instant::now();
 
         if let Some(last_poll) = last_poll {
-            if now.duration_since(last_poll) < poll_interval {
+            if now.duration_since(last_poll) < interval {
                 thread::sleep(time::Duration::from_millis(500));
                 continue;
             }
@@ -328,15 +525,517 @@ pub fn looper(
             );
             synced = sync_progress == 1.0;
             if !synced {
-                // Avoid harassing bitcoind..
-                // TODO: be smarter, like in revaultd, but more generic too.
-                #[cfg(not(test))]
-                thread::sleep(time::Duration::from_secs(30));
+                interval = next_poll_interval(&bit, &db, poll_interval);
                 continue;
             }
         }
 
-        updates(&bit, &db, &descs, &secp);
-        rescan_check(&bit, &db, &descs, &secp);
+        updates(&bit, &db, &descs, gap_limit, &secp, &notifier);
+        rescan_check(
+            &bit,
+            &db,
+            &descs,
+            gap_limit,
+            rescan_timeout,
+            &secp,
+            &notifier,
+        );
+        interval = next_poll_interval(&bit, &db, poll_interval);
+    }
+
+    // We were asked to stop. Record the latest state of the chain before returning, so we don't
+    // have to redo this work at the next startup. The caller knows it's done once the thread
+    // exits.
+    if synced {
+        log::info!("Persisting the latest updates before stopping the poller.");
+        updates(&bit, &db, &descs, gap_limit, &secp, &notifier);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        database::sqlite::{SqliteDb, LOOK_AHEAD_LIMIT},
+        testutils::*,
+    };
+
+    use std::{fs, path, str::FromStr};
+
+    #[test]
+    fn gap_limit_discovery() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let desc_str = "wsh(andor(pk(tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk(tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#5f6qd0d9";
+        let desc = descriptors::MultipathDescriptor::from_str(desc_str).unwrap();
+        let descs = [
+            desc.receive_descriptor().clone(),
+            desc.change_descriptor().clone(),
+        ];
+        let db = DummyDatabase::new();
+        let mut db_conn = db.connection();
+        db_conn.set_receive_index(3.into(), &secp);
+
+        // A coin was received 5 addresses past our current receive index. The address isn't in
+        // database but it's within the gap limit, so we'll find it and advance our index.
+        let address = descs[0]
+            .derive(8.into(), &secp)
+            .address(bitcoin::Network::Bitcoin);
+        let mut bitcoind = DummyBitcoind::new();
+        bitcoind.received.push(UTxO {
+            outpoint: bitcoin::OutPoint::from_str(
+                "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+            )
+            .unwrap(),
+            amount: bitcoin::Amount::from_sat(100_000),
+            block_height: None,
+            address,
+        });
+        let tip = bitcoind.chain_tip();
+        let updated = update_coins(&bitcoind, &mut db_conn, &tip, &descs, 20, &secp);
+        assert_eq!(updated.received.len(), 1);
+        assert_eq!(updated.received[0].derivation_index, 8.into());
+        assert!(!updated.received[0].is_change);
+        assert_eq!(db_conn.receive_index(), 8.into());
+
+        // A coin received on a change address past the gap limit won't be found..
+        let address = descs[1]
+            .derive(30.into(), &secp)
+            .address(bitcoin::Network::Bitcoin);
+        bitcoind.received[0].address = address;
+        let updated = update_coins(&bitcoind, &mut db_conn, &tip, &descs, 20, &secp);
+        assert!(updated.received.is_empty());
+
+        // .. Unless we look further.
+        let updated = update_coins(&bitcoind, &mut db_conn, &tip, &descs, 50, &secp);
+        assert_eq!(updated.received.len(), 1);
+        assert_eq!(updated.received[0].derivation_index, 30.into());
+        assert!(updated.received[0].is_change);
+        assert_eq!(db_conn.change_index(), 30.into());
+    }
+
+    #[test]
+    fn gap_limit_discovery_past_lookahead() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let tmp_dir = tmp_dir();
+        fs::create_dir_all(&tmp_dir).unwrap();
+        let db_path: path::PathBuf = [tmp_dir.as_path(), path::Path::new("lianad.sqlite3")]
+            .iter()
+            .collect();
+        let options = crate::database::sqlite::tests::dummy_options();
+        let desc = options.main_descriptor.clone();
+        let descs = [
+            desc.receive_descriptor().clone(),
+            desc.change_descriptor().clone(),
+        ];
+        let network = options.bitcoind_network;
+        let db = SqliteDb::new(db_path, Some(options), &secp).unwrap();
+        let mut db_conn = DatabaseInterface::connection(&db);
+        db_conn.set_receive_index(3.into(), &secp);
+
+        // An address within the lookahead is found directly from the database.
+        let mut bitcoind = DummyBitcoind::new();
+        bitcoind.received.push(UTxO {
+            outpoint: bitcoin::OutPoint::from_str(
+                "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+            )
+            .unwrap(),
+            amount: bitcoin::Amount::from_sat(100_000),
+            block_height: None,
+            address: descs[0].derive(8.into(), &secp).address(network),
+        });
+        let tip = bitcoind.chain_tip();
+        let updated = update_coins(&bitcoind, &mut db_conn, &tip, &descs, 20, &secp);
+        assert_eq!(updated.received.len(), 1);
+        assert_eq!(updated.received[0].derivation_index, 8.into());
+
+        // A change address just past the lookahead isn't stored, but it is within the gap limit.
+        // Note the coin was not stored by `update_coins`, so it's received again.
+        let first_unstored: u32 = db_conn.first_unstored_index().into();
+        assert_eq!(first_unstored, 3 + LOOK_AHEAD_LIMIT);
+        let index = first_unstored + 10;
+        bitcoind.received[0].address = descs[1].derive(index.into(), &secp).address(network);
+        let updated = update_coins(&bitcoind, &mut db_conn, &tip, &descs, 20, &secp);
+        assert_eq!(updated.received.len(), 1);
+        assert_eq!(updated.received[0].derivation_index, index.into());
+        assert!(updated.received[0].is_change);
+
+        // This advanced our indexes, and the lookahead along with them. An address past the gap
+        // limit from the new lookahead, though, won't be found.
+        let first_unstored: u32 = db_conn.first_unstored_index().into();
+        assert_eq!(first_unstored, index + LOOK_AHEAD_LIMIT);
+        let index = first_unstored + 25;
+        bitcoind.received[0].address = descs[0].derive(index.into(), &secp).address(network);
+        let updated = update_coins(&bitcoind, &mut db_conn, &tip, &descs, 20, &secp);
+        assert!(updated.received.is_empty());
+
+        fs::remove_dir_all(&tmp_dir).unwrap();
+    }
+
+    #[test]
+    fn new_coin_event() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let desc_str = "wsh(andor(pk(tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk(tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#5f6qd0d9";
+        let desc = descriptors::MultipathDescriptor::from_str(desc_str).unwrap();
+        let descs = [
+            desc.receive_descriptor().clone(),
+            desc.change_descriptor().clone(),
+        ];
+        let db = DummyDatabase::new();
+        let notifier = Notifier::new();
+        let events = notifier.subscribe();
+
+        // A coin is received on a change address.
+        let outpoint = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let address = descs[1]
+            .derive(2.into(), &secp)
+            .address(bitcoin::Network::Bitcoin);
+        let mut bitcoind = DummyBitcoind::new();
+        bitcoind.received.push(UTxO {
+            outpoint,
+            amount: bitcoin::Amount::from_sat(100_000),
+            block_height: None,
+            address,
+        });
+        maybe_initialize_tip(&bitcoind, &db);
+        updates(&bitcoind, &db, &descs, 20, &secp, &notifier);
+
+        // Once recorded, the subscribers are told about it along with where it was derived from.
+        assert!(db.connection().coins(CoinType::All).contains_key(&outpoint));
+        let new_coins: Vec<WalletEvent> = events
+            .try_iter()
+            .filter(|event| matches!(event, WalletEvent::NewCoin { .. }))
+            .collect();
+        assert_eq!(
+            new_coins,
+            vec![WalletEvent::NewCoin {
+                outpoint,
+                amount_sat: 100_000,
+                derivation_index: 2,
+                is_change: true,
+            }]
+        );
+
+        // It's only notified once.
+        updates(&bitcoind, &db, &descs, 20, &secp, &notifier);
+        assert!(!events
+            .try_iter()
+            .any(|event| matches!(event, WalletEvent::NewCoin { .. })));
+    }
+
+    #[test]
+    fn dropped_unconfirmed_coins() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let desc_str = "wsh(andor(pk(tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk(tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#5f6qd0d9";
+        let desc = descriptors::MultipathDescriptor::from_str(desc_str).unwrap();
+        let descs = [
+            desc.receive_descriptor().clone(),
+            desc.change_descriptor().clone(),
+        ];
+        let coin = |outpoint: &str, block_height: Option<i32>| Coin {
+            outpoint: bitcoin::OutPoint::from_str(outpoint).unwrap(),
+            block_height,
+            block_time: block_height.map(|h| h as u32),
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: 0.into(),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        };
+        let unconf_a = coin(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+            None,
+        );
+        let unconf_b = coin(
+            "617eab1fc0b03ee7f82ba70166725291783461f1a0e7975eaf8b5f8f674234f3:1",
+            None,
+        );
+        let confirmed = coin(
+            "0f32b3a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c78:0",
+            Some(50),
+        );
+
+        let mut db = DummyDatabase::new();
+        db.insert_coins(vec![unconf_a, unconf_b, confirmed]);
+        let mut bitcoind = DummyBitcoind::new();
+        db.connection().update_tip(&bitcoind.chain_tip());
+        let notifier = Notifier::new();
+        let events = notifier.subscribe();
+
+        // The transaction creating one of our unconfirmed coins got double spent. The backend
+        // would never report a confirmed coin as dropped, but make sure we don't remove it anyway.
+        bitcoind.conflicted = vec![unconf_a.outpoint.txid, confirmed.outpoint.txid];
+        updates(&bitcoind, &db, &descs, 20, &secp, &notifier);
+        let coins = db.connection().coins(CoinType::All);
+        assert_eq!(coins.len(), 2);
+        assert!(!coins.contains_key(&unconf_a.outpoint));
+        assert!(coins.contains_key(&unconf_b.outpoint));
+        assert!(coins.contains_key(&confirmed.outpoint));
+        assert_eq!(
+            events.try_recv().unwrap(),
+            WalletEvent::CoinDropped {
+                outpoint: unconf_a.outpoint,
+                amount_sat: 100_000,
+            }
+        );
+        assert!(events.try_recv().is_err());
+
+        // It's not reported again afterward.
+        updates(&bitcoind, &db, &descs, 20, &secp, &notifier);
+        assert_eq!(db.connection().coins(CoinType::All).len(), 2);
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn evicted_unconfirmed_coins_are_kept() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let desc_str = "wsh(andor(pk(tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk(tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#5f6qd0d9";
+        let desc = descriptors::MultipathDescriptor::from_str(desc_str).unwrap();
+        let descs = [
+            desc.receive_descriptor().clone(),
+            desc.change_descriptor().clone(),
+        ];
+        let unconf = Coin {
+            outpoint: bitcoin::OutPoint::from_str(
+                "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+            )
+            .unwrap(),
+            block_height: None,
+            block_time: None,
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: 0.into(),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        };
+
+        let mut db = DummyDatabase::new();
+        db.insert_coins(vec![unconf]);
+        let bitcoind = DummyBitcoind::new();
+        db.connection().update_tip(&bitcoind.chain_tip());
+        let notifier = Notifier::new();
+        let events = notifier.subscribe();
+
+        // The transaction creating our coin is neither in a block nor in the mempool, for
+        // instance because it was evicted. But it wasn't double spent, so we keep the coin.
+        assert!(!bitcoind.in_mempool(&unconf.outpoint.txid));
+        updates(&bitcoind, &db, &descs, 20, &secp, &notifier);
+        let coins = db.connection().coins(CoinType::All);
+        assert_eq!(coins.len(), 1);
+        assert!(coins.contains_key(&unconf.outpoint));
+        assert!(!events
+            .try_iter()
+            .any(|event| matches!(event, WalletEvent::CoinDropped { .. })));
+    }
+
+    #[test]
+    fn reorg_unconfirms_coins() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let desc_str = "wsh(andor(pk(tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk(tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#5f6qd0d9";
+        let desc = descriptors::MultipathDescriptor::from_str(desc_str).unwrap();
+        let descs = [
+            desc.receive_descriptor().clone(),
+            desc.change_descriptor().clone(),
+        ];
+        let txid = bitcoin::Txid::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810",
+        )
+        .unwrap();
+        let spend_txid = bitcoin::Txid::from_str(
+            "617eab1fc0b03ee7f82ba70166725291783461f1a0e7975eaf8b5f8f674234f3",
+        )
+        .unwrap();
+        let coin = |vout: u32, block_height: Option<i32>, spend_height: Option<i32>| Coin {
+            outpoint: bitcoin::OutPoint::new(txid, vout),
+            block_height,
+            block_time: block_height.map(|h| h as u32),
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: vout.into(),
+            is_change: false,
+            spend_txid: spend_height.map(|_| spend_txid),
+            spend_block: spend_height.map(|height| crate::database::SpendBlock {
+                height,
+                time: height as u32,
+            }),
+            spend_broadcast_time: None,
+            is_frozen: false,
+        };
+
+        // Our tip is at the same height as the backend's, but on another chain. The blocks past
+        // height 98 were reorged out.
+        let mut db = DummyDatabase::new();
+        db.insert_coins(vec![
+            coin(0, Some(98), None),
+            coin(1, Some(99), None),
+            coin(2, Some(100), None),
+            coin(3, Some(90), Some(99)),
+        ]);
+        let mut bitcoind = DummyBitcoind::new();
+        db.connection().update_tip(&bitcoind.chain_tip());
+        bitcoind.reorg(2, 2);
+        let tip_height = bitcoind.chain_tip().height;
+        assert_eq!(tip_height, 100);
+
+        // Before the reorg is processed, all coins are confirmed.
+        let coins = db.connection().coins(CoinType::All);
+        assert!(coins.values().all(|c| c.is_confirmed()));
+        assert_eq!(
+            coins[&coin(2, None, None).outpoint].confirmations(tip_height),
+            1
+        );
+
+        // The backend doesn't know about any confirmation anymore, the coins confirmed in the
+        // reorged out blocks must revert to unconfirmed. So do the spends.
+        updates(&bitcoind, &db, &descs, 20, &secp, &Notifier::new());
+        let coins = db.connection().coins(CoinType::All);
+        let conf_0 = &coins[&coin(0, None, None).outpoint];
+        assert_eq!(conf_0.block_height, Some(98));
+        assert_eq!(conf_0.confirmations(tip_height), 3);
+        for vout in &[1, 2] {
+            let c = &coins[&coin(*vout, None, None).outpoint];
+            assert!(!c.is_confirmed());
+            assert!(c.block_time.is_none());
+            assert_eq!(c.confirmations(tip_height), 0);
+        }
+        let spent = &coins[&coin(3, None, None).outpoint];
+        assert_eq!(spent.block_height, Some(90));
+        assert_eq!(spent.spend_txid, Some(spend_txid));
+        assert!(spent.spend_block.is_none());
+        assert_eq!(db.connection().chain_tip(), Some(bitcoind.chain_tip()));
+    }
+
+    #[test]
+    fn rescan_timeout() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let desc_str = "wsh(andor(pk(tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk(tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#5f6qd0d9";
+        let desc = descriptors::MultipathDescriptor::from_str(desc_str).unwrap();
+        let descs = [
+            desc.receive_descriptor().clone(),
+            desc.change_descriptor().clone(),
+        ];
+        let notifier = Notifier::new();
+        let mut bitcoind = DummyBitcoind::new();
+        bitcoind.rescan_info = Some(RescanInfo {
+            progress: 0.3,
+            duration: 600,
+        });
+        let db = DummyDatabase::new();
+        db.connection().set_rescan(1_600_000_000);
+
+        // Without a timeout, or before it expires, the rescan is left alone.
+        rescan_check(&bitcoind, &db, &descs, 20, None, &secp, &notifier);
+        let timeout = Some(time::Duration::from_secs(601));
+        rescan_check(&bitcoind, &db, &descs, 20, timeout, &secp, &notifier);
+        assert!(!bitcoind.rescan_aborted.load(atomic::Ordering::Relaxed));
+        assert_eq!(db.connection().rescan_timestamp(), Some(1_600_000_000));
+
+        // Once it expired the rescan is aborted, and not treated as completed.
+        let wallet_timestamp = db.connection().wallet_timestamp();
+        let timeout = Some(time::Duration::from_secs(600));
+        rescan_check(&bitcoind, &db, &descs, 20, timeout, &secp, &notifier);
+        assert!(bitcoind.rescan_aborted.load(atomic::Ordering::Relaxed));
+        assert!(db.connection().rescan_timestamp().is_none());
+        assert_eq!(db.connection().wallet_timestamp(), wallet_timestamp);
+    }
+
+    #[test]
+    fn rescan_resumption() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let desc_str = "wsh(andor(pk(tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk(tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#5f6qd0d9";
+        let desc = descriptors::MultipathDescriptor::from_str(desc_str).unwrap();
+        let descs = [
+            desc.receive_descriptor().clone(),
+            desc.change_descriptor().clone(),
+        ];
+        let notifier = Notifier::new();
+        let db = DummyDatabase::new();
+        let mut bitcoind = DummyBitcoind::new();
+        let rescan_timestamp = bitcoind.tip_time() - 100_000;
+        db.connection().set_rescan(rescan_timestamp);
+
+        // While the backend rescans, we record how far it got.
+        bitcoind.rescan_info = Some(RescanInfo {
+            progress: 0.5,
+            duration: 60,
+        });
+        rescan_check(&bitcoind, &db, &descs, 20, None, &secp, &notifier);
+        let progress = db.connection().rescan_progress().unwrap();
+        assert_eq!(progress, rescan_timestamp + 50_000);
+        // It never goes backward.
+        bitcoind.rescan_info = Some(RescanInfo {
+            progress: 0.2,
+            duration: 90,
+        });
+        rescan_check(&bitcoind, &db, &descs, 20, None, &secp, &notifier);
+        assert_eq!(db.connection().rescan_progress(), Some(progress));
+
+        // We restart while the backend is still rescanning. There is nothing to resume.
+        maybe_resume_rescan(&bitcoind, &db, &desc);
+        assert!(bitcoind.last_rescan_timestamp.lock().unwrap().is_none());
+
+        // We restart after the backend was interrupted. We resume from where it got, not from the
+        // start, and still remember the start to update our state upon completion.
+        bitcoind.rescan_info = None;
+        maybe_resume_rescan(&bitcoind, &db, &desc);
+        assert_eq!(
+            *bitcoind.last_rescan_timestamp.lock().unwrap(),
+            Some(progress)
+        );
+        assert_eq!(db.connection().rescan_timestamp(), Some(rescan_timestamp));
+
+        // Without any recorded progress, it is resumed from the start.
+        db.connection().set_rescan(rescan_timestamp);
+        maybe_resume_rescan(&bitcoind, &db, &desc);
+        assert_eq!(
+            *bitcoind.last_rescan_timestamp.lock().unwrap(),
+            Some(rescan_timestamp)
+        );
+    }
+
+    #[test]
+    fn adaptive_poll_interval() {
+        let db = DummyDatabase::new();
+        let mut bitcoind = DummyBitcoind::new();
+        let poll_interval = time::Duration::from_secs(30);
+
+        // Poll more often while the backend is syncing.
+        bitcoind.sync_progress = 0.5;
+        assert_eq!(
+            next_poll_interval(&bitcoind, &db, poll_interval),
+            BUSY_POLL_INTERVAL
+        );
+
+        // Then back off to the configured interval once it's done.
+        bitcoind.sync_progress = 1.0;
+        assert_eq!(
+            next_poll_interval(&bitcoind, &db, poll_interval),
+            poll_interval
+        );
+
+        // Same during a rescan.
+        db.connection().set_rescan(1_000);
+        assert_eq!(
+            next_poll_interval(&bitcoind, &db, poll_interval),
+            BUSY_POLL_INTERVAL
+        );
+        db.connection().complete_rescan();
+        assert_eq!(
+            next_poll_interval(&bitcoind, &db, poll_interval),
+            poll_interval
+        );
+
+        // The configured interval is always the ceiling.
+        let poll_interval = time::Duration::from_secs(1);
+        bitcoind.sync_progress = 0.5;
+        assert_eq!(
+            next_poll_interval(&bitcoind, &db, poll_interval),
+            poll_interval
+        );
     }
 }
@@ -4,6 +4,7 @@ use crate::{
     bitcoin::{poller::looper::looper, BitcoinInterface},
     database::DatabaseInterface,
     descriptors,
+    notifications::Notifier,
 };
 
 use std::{
@@ -22,20 +23,35 @@ impl Poller {
         bit: sync::Arc<sync::Mutex<dyn BitcoinInterface>>,
         db: sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
         poll_interval: time::Duration,
+        gap_limit: u32,
+        rescan_timeout: Option<time::Duration>,
         desc: descriptors::MultipathDescriptor,
+        notifier: Notifier,
     ) -> Poller {
         let shutdown = sync::Arc::from(atomic::AtomicBool::from(false));
         let handle = thread::Builder::new()
             .name("Bitcoin poller".to_string())
             .spawn({
                 let shutdown = shutdown.clone();
-                move || looper(bit, db, shutdown, poll_interval, desc)
+                move || {
+                    looper(
+                        bit,
+                        db,
+                        shutdown,
+                        poll_interval,
+                        gap_limit,
+                        rescan_timeout,
+                        desc,
+                        notifier,
+                    )
+                }
             })
             .expect("Must not fail");
 
         Poller { shutdown, handle }
     }
 
+    /// Stop the poller. Returns once it recorded the latest updates in database.
     pub fn stop(self) {
         self.shutdown.store(true, atomic::Ordering::Relaxed);
         self.handle.join().expect("The poller loop must not fail");
@@ -46,3 +62,39 @@ impl Poller {
         self.shutdown.store(true, atomic::Ordering::Relaxed);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutils::*;
+
+    use std::str::FromStr;
+
+    #[test]
+    fn poller_shutdown_persists_tip() {
+        let desc = descriptors::MultipathDescriptor::from_str("wsh(andor(pk(tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk(tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#5f6qd0d9").unwrap();
+        let bitcoind = sync::Arc::new(sync::Mutex::new(DummyBitcoind::new()));
+        let db = sync::Arc::new(sync::Mutex::new(DummyDatabase::new()));
+
+        // Poll once at startup, then not before a long time.
+        let poller = Poller::start(
+            bitcoind.clone(),
+            db.clone(),
+            time::Duration::from_secs(3600),
+            20,
+            None,
+            desc,
+            Notifier::new(),
+        );
+        let initial_tip = bitcoind.lock().unwrap().chain_tip();
+        while db.lock().unwrap().connection().chain_tip() != Some(initial_tip) {
+            thread::sleep(time::Duration::from_millis(10));
+        }
+
+        // A new block is connected while the poller sleeps. It gets recorded upon shutdown.
+        let new_tip = bitcoind.lock().unwrap().mine_block();
+        assert_eq!(new_tip.height, initial_tip.height + 1);
+        poller.stop();
+        assert_eq!(db.lock().unwrap().connection().chain_tip(), Some(new_tip));
+    }
+}
@@ -5,66 +5,111 @@
 mod utils;
 
 use crate::{
-    bitcoin::BitcoinInterface,
-    database::{Coin, CoinType, DatabaseInterface},
+    backup::WalletBackup,
+    bitcoin::{d::btc_kvb_to_sat_vb, BitcoinInterface, RescanError, RescanInfo},
+    config::BitcoinConfig,
+    database::{Coin, CoinStatus, CoinType, DatabaseConnection, DatabaseInterface},
     descriptors, DaemonControl, VERSION,
 };
 
 use utils::{deser_amount_from_sats, deser_base64, deser_hex, ser_amount, ser_base64, ser_hex};
 
 use std::{
-    collections::{hash_map, BTreeMap, HashMap},
+    cmp,
+    collections::{hash_map, BTreeMap, BTreeSet, HashMap, HashSet},
     convert::TryInto,
-    fmt,
+    fmt, time,
 };
 
 use miniscript::{
     bitcoin::{
         self,
-        util::psbt::{Input as PsbtIn, Output as PsbtOut, PartiallySignedTransaction as Psbt},
+        blockdata::{
+            opcodes::all::{OP_PUSHBYTES_0, OP_PUSHNUM_16, OP_RETURN},
+            script::Instruction,
+        },
+        consensus::encode,
+        hashes::{hex::FromHex, Hash},
+        util::{
+            bip32,
+            psbt::{Input as PsbtIn, Output as PsbtOut, PartiallySignedTransaction as Psbt},
+            sighash,
+        },
     },
+    interpreter::Interpreter,
     psbt::PsbtExt,
 };
 use serde::{Deserialize, Serialize};
 
-// We would never create a transaction with an output worth less than this.
-// That's 1$ at 20_000$ per BTC.
-const DUST_OUTPUT_SATS: u64 = 5_000;
-
-// Assume that paying more than 1BTC in fee is a bug.
-const MAX_FEE: u64 = bitcoin::blockdata::constants::COIN_VALUE;
-
-// Assume that paying more than 1000sat/vb in feerate is a bug.
-const MAX_FEERATE: u64 = 1_000;
-
-// Timestamp in the header of the genesis block. Used for sanity checks.
-const MAINNET_GENESIS_TIME: u32 = 1231006505;
+// Maximum size of an OP_RETURN output script for it to be relayed by Bitcoin Core.
+const MAX_OP_RETURN_RELAY: usize = 83;
+
+// Maximum weight of a transaction for it to be relayed by Bitcoin Core.
+const MAX_STANDARD_TX_WEIGHT: usize = 400_000;
+
+// The networks whose genesis block we know.
+const KNOWN_NETWORKS: [bitcoin::Network; 4] = [
+    bitcoin::Network::Bitcoin,
+    bitcoin::Network::Testnet,
+    bitcoin::Network::Signet,
+    bitcoin::Network::Regtest,
+];
+
+// Timestamp in the header of the genesis block with this hash, if it is the one of a network we
+// know. Used for sanity checks.
+fn genesis_time(genesis_hash: &bitcoin::BlockHash) -> Option<u32> {
+    KNOWN_NETWORKS
+        .iter()
+        .map(|network| bitcoin::blockdata::constants::genesis_block(*network))
+        .find(|block| block.block_hash() == *genesis_hash)
+        .map(|block| block.header.time)
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum CommandError {
     NoOutpoint,
     NoDestination,
     InvalidFeerate(/* sats/vb */ u64),
+    FeerateBelowRelay(/* sats/vb */ u64, /* relay feerate sats/vb */ u64),
     UnknownOutpoint(bitcoin::OutPoint),
     AlreadySpent(bitcoin::OutPoint),
     AddressNetwork(bitcoin::Address, /* Expected */ bitcoin::Network),
     InvalidOutputValue(bitcoin::Amount),
+    /// An output script that would make the transaction non-standard.
+    NonStandardOutput(bitcoin::Script),
+    /// The output asked to pay for the fee isn't one of the destinations.
+    UnknownFeePayer(bitcoin::Address),
     InsufficientFunds(
         /* in value */ bitcoin::Amount,
         /* out value */ bitcoin::Amount,
         /* target feerate */ u64,
     ),
+    /// The replacement doesn't increase the fee enough over the transaction it replaces.
+    InsufficientFeeBump(
+        /* fee */ bitcoin::Amount,
+        /* min fee */ bitcoin::Amount,
+    ),
     FetchingTransaction(bitcoin::OutPoint),
     SanityCheckFailure(Psbt),
     UnknownSpend(bitcoin::Txid),
+    UndecodableSpend,
+    PsbtCombination(String),
     // FIXME: when upgrading Miniscript put the actual error there
     SpendFinalization(String),
+    SpendVerification(String),
     TxBroadcast(String),
     AlreadyRescanning,
+    NoOngoingRescan,
     InsaneRescanTimestamp(u32),
     /// An error that might occur in the racy rescan triggering logic.
     RescanTrigger(String),
     RecoveryNotAvailable,
+    /// None of the recovery paths of our descriptor has this timelock.
+    UnknownRecoveryPath(/* timelock */ u16),
+    InvalidBackup(String),
+    InvalidDerivationIndex(u32),
+    InvalidFeeTarget(/* blocks */ u16),
+    FeeEstimation(/* blocks */ u16),
 }
 
 impl fmt::Display for CommandError {
@@ -73,6 +118,11 @@ impl fmt::Display for CommandError {
             Self::NoOutpoint => write!(f, "No provided outpoint. Need at least one."),
             Self::NoDestination => write!(f, "No provided destination. Need at least one."),
             Self::InvalidFeerate(sats_vb) => write!(f, "Invalid feerate: {} sats/vb.", sats_vb),
+            Self::FeerateBelowRelay(sats_vb, relay_sats_vb) => write!(
+                f,
+                "Feerate of {} sats/vb is below our node's minimum relay feerate of {} sats/vb.",
+                sats_vb, relay_sats_vb
+            ),
             Self::AlreadySpent(op) => write!(f, "Coin at '{}' is already spent.", op),
             Self::UnknownOutpoint(op) => write!(f, "Unknown outpoint '{}'.", op),
             Self::AddressNetwork(addr, expected) => write!(
@@ -81,11 +131,26 @@ impl fmt::Display for CommandError {
                 addr, expected, addr.network
             ),
             Self::InvalidOutputValue(amount) => write!(f, "Invalid output value '{}'.", amount),
+            Self::NonStandardOutput(script) => write!(
+                f,
+                "Output script '{}' is not standard, the transaction would not be relayed.",
+                script
+            ),
+            Self::UnknownFeePayer(addr) => write!(
+                f,
+                "Address '{}' to subtract the fee from is not among the destinations.",
+                addr
+            ),
             Self::InsufficientFunds(in_val, out_val, feerate) => write!(
                 f,
                 "Cannot create a {} sat/vb transaction with input value {} and output value {}",
                 feerate, in_val, out_val
             ),
+            Self::InsufficientFeeBump(fee, min_fee) => write!(
+                f,
+                "The replacement would pay a fee of {}, but it must pay at least {} to be relayed.",
+                fee, min_fee
+            ),
             Self::FetchingTransaction(op) => {
                 write!(f, "Could not fetch transaction for coin {}", op)
             }
@@ -95,20 +160,52 @@ impl fmt::Display for CommandError {
                 psbt
             ),
             Self::UnknownSpend(txid) => write!(f, "Unknown spend transaction '{}'.", txid),
+            Self::UndecodableSpend => write!(
+                f,
+                "Could not decode the spend transaction. Expected a base64 or hex encoded PSBT, or a hex encoded transaction."
+            ),
+            Self::PsbtCombination(e) => write!(f, "Failed to combine PSBTs: '{}'.", e),
             Self::SpendFinalization(e) => {
                 write!(f, "Failed to finalize the spend transaction PSBT: '{}'.", e)
             }
+            Self::SpendVerification(e) => write!(
+                f,
+                "The finalized spend transaction does not satisfy the spent coins' scripts: '{}'.",
+                e
+            ),
             Self::TxBroadcast(e) => write!(f, "Failed to broadcast transaction: '{}'.", e),
             Self::AlreadyRescanning => write!(
                 f,
                 "There is already a rescan ongoing. Please wait for it to complete first."
             ),
+            Self::NoOngoingRescan => write!(f, "There is no ongoing rescan."),
             Self::InsaneRescanTimestamp(t) => write!(f, "Insane timestamp '{}'.", t),
             Self::RescanTrigger(s) => write!(f, "Error while starting rescan: '{}'", s),
             Self::RecoveryNotAvailable => write!(
                 f,
                 "No coin currently available through the timelocked recovery path."
             ),
+            Self::UnknownRecoveryPath(timelock) => write!(
+                f,
+                "There is no recovery path with a timelock of {} blocks in our descriptor.",
+                timelock
+            ),
+            Self::InvalidBackup(e) => write!(f, "Invalid backup for this wallet: '{}'.", e),
+            Self::InvalidDerivationIndex(index) => write!(
+                f,
+                "Invalid derivation index '{}'. Must be less than 2^31.",
+                index
+            ),
+            Self::InvalidFeeTarget(target) => write!(
+                f,
+                "Invalid confirmation target: {} blocks. Must be at least 1.",
+                target
+            ),
+            Self::FeeEstimation(target) => write!(
+                f,
+                "Could not estimate the feerate for a confirmation within {} blocks. The node may not have enough data yet.",
+                target
+            ),
         }
     }
 }
@@ -116,10 +213,13 @@ impl fmt::Display for CommandError {
 impl std::error::Error for CommandError {}
 
 // Sanity check the value of a transaction output.
-fn check_output_value(value: bitcoin::Amount) -> Result<(), CommandError> {
+fn check_output_value(
+    bitcoin_config: &BitcoinConfig,
+    value: bitcoin::Amount,
+) -> Result<(), CommandError> {
     // NOTE: the network parameter isn't used upstream
     if value.to_sat() > bitcoin::blockdata::constants::max_money(bitcoin::Network::Bitcoin)
-        || value.to_sat() < DUST_OUTPUT_SATS
+        || value.to_sat() < bitcoin_config.dust_output_sats
     {
         Err(CommandError::InvalidOutputValue(value))
     } else {
@@ -127,9 +227,65 @@ fn check_output_value(value: bitcoin::Amount) -> Result<(), CommandError> {
     }
 }
 
+// Sanity check the value of a change output. Whether it's worth creating is checked separately,
+// against the dust limit at the feerate of the transaction.
+fn check_change_value(value: bitcoin::Amount) -> Result<(), CommandError> {
+    if value.to_sat() > bitcoin::blockdata::constants::max_money(bitcoin::Network::Bitcoin) {
+        Err(CommandError::InvalidOutputValue(value))
+    } else {
+        Ok(())
+    }
+}
+
+// Check the script of a transaction output is standard, as the transaction would not be relayed
+// by the network otherwise.
+fn check_output_script(script: &bitcoin::Script) -> Result<(), CommandError> {
+    let is_standard = if script.is_op_return() {
+        // Only data pushes may follow the OP_RETURN.
+        script.len() <= MAX_OP_RETURN_RELAY
+            && script.instructions().skip(1).all(|ins| match ins {
+                Ok(Instruction::PushBytes(_)) => true,
+                Ok(Instruction::Op(op)) => op.to_u8() <= OP_PUSHNUM_16.to_u8(),
+                Err(_) => false,
+            })
+    } else if script.is_witness_program() {
+        // Version 0 witness programs must be either P2WPKH or P2WSH.
+        script.as_bytes()[0] != OP_PUSHBYTES_0.to_u8()
+            || script.is_v0_p2wpkh()
+            || script.is_v0_p2wsh()
+    } else {
+        script.is_p2pkh() || script.is_p2sh()
+    };
+
+    if is_standard {
+        Ok(())
+    } else {
+        Err(CommandError::NonStandardOutput(script.clone()))
+    }
+}
+
+// The OP_RETURN output script embedding this data.
+fn data_script(data: &[u8]) -> bitcoin::Script {
+    bitcoin::blockdata::script::Builder::new()
+        .push_opcode(OP_RETURN)
+        .push_slice(data)
+        .into_script()
+}
+
+// Get the data embedded in an OP_RETURN output script, if it's one.
+fn script_data(script: &bitcoin::Script) -> Option<Vec<u8>> {
+    if !script.is_op_return() {
+        return None;
+    }
+    match script.instructions().nth(1) {
+        Some(Ok(Instruction::PushBytes(data))) => Some(data.to_vec()),
+        _ => Some(Vec::new()),
+    }
+}
+
 // Apply some sanity checks on a created transaction's PSBT.
 // TODO: add more sanity checks from revault_tx
-fn sanity_check_psbt(psbt: &Psbt) -> Result<(), CommandError> {
+fn sanity_check_psbt(bitcoin_config: &BitcoinConfig, psbt: &Psbt) -> Result<(), CommandError> {
     let tx = &psbt.unsigned_tx;
 
     // Must have as many in/out in the PSBT and Bitcoin tx.
@@ -141,7 +297,7 @@ fn sanity_check_psbt(psbt: &Psbt) -> Result<(), CommandError> {
     // index set for signing devices to recognize them as ours.
     let mut value_in = 0;
     for psbtin in psbt.inputs.iter() {
-        if psbtin.bip32_derivation.is_empty() {
+        if psbtin.bip32_derivation.is_empty() && psbtin.tap_key_origins.is_empty() {
             return Err(CommandError::SanityCheckFailure(psbt.clone()));
         }
         value_in += psbtin
@@ -156,7 +312,7 @@ fn sanity_check_psbt(psbt: &Psbt) -> Result<(), CommandError> {
     let abs_fee = value_in
         .checked_sub(value_out)
         .ok_or_else(|| CommandError::SanityCheckFailure(psbt.clone()))?;
-    if abs_fee > MAX_FEE {
+    if abs_fee > bitcoin_config.max_fee_sats {
         return Err(CommandError::SanityCheckFailure(psbt.clone()));
     }
 
@@ -165,7 +321,7 @@ fn sanity_check_psbt(psbt: &Psbt) -> Result<(), CommandError> {
     let feerate_sats_vb = abs_fee
         .checked_div(tx_vb)
         .ok_or_else(|| CommandError::SanityCheckFailure(psbt.clone()))?;
-    if !(1..=MAX_FEERATE).contains(&feerate_sats_vb) {
+    if !(1..=bitcoin_config.max_feerate_sats_vb).contains(&feerate_sats_vb) {
         return Err(CommandError::SanityCheckFailure(psbt.clone()));
     }
 
@@ -179,29 +335,237 @@ fn sanity_check_psbt(psbt: &Psbt) -> Result<(), CommandError> {
     Ok(())
 }
 
+// Sort the inputs and outputs of the PSBT according to BIP69, moving the PSBT inputs and outputs
+// along with the transaction's. This makes the transaction independent of the order in which
+// coins and destinations were given, and doesn't reveal the change output by its position.
+fn bip69_sort(psbt: &mut Psbt) {
+    let mut inputs: Vec<(bitcoin::TxIn, PsbtIn)> = psbt
+        .unsigned_tx
+        .input
+        .drain(..)
+        .zip(psbt.inputs.drain(..))
+        .collect();
+    // Previous txids are compared in reversed byte order, as displayed.
+    inputs.sort_by_key(|(txin, _)| {
+        let mut txid = txin.previous_output.txid.into_inner();
+        txid.reverse();
+        (txid, txin.previous_output.vout)
+    });
+    let (txins, psbt_ins) = inputs.into_iter().unzip();
+    psbt.unsigned_tx.input = txins;
+    psbt.inputs = psbt_ins;
+
+    let mut outputs: Vec<(bitcoin::TxOut, PsbtOut)> = psbt
+        .unsigned_tx
+        .output
+        .drain(..)
+        .zip(psbt.outputs.drain(..))
+        .collect();
+    outputs.sort_by(|(a, _), (b, _)| {
+        a.value
+            .cmp(&b.value)
+            .then_with(|| a.script_pubkey.as_bytes().cmp(b.script_pubkey.as_bytes()))
+    });
+    let (txouts, psbt_outs) = outputs.into_iter().unzip();
+    psbt.unsigned_tx.output = txouts;
+    psbt.outputs = psbt_outs;
+}
+
 // Get the size of a type that can be serialized (txos, transactions, ..)
 fn serializable_size<T: bitcoin::consensus::Encodable + ?Sized>(t: &T) -> u64 {
     bitcoin::consensus::serialize(t).len().try_into().unwrap()
 }
 
+// Get the number of blocks until a coin confirmed at the given height is spendable through the
+// recovery path, `0` meaning it can be included in the *next* block. `None` if unconfirmed.
+fn blocks_until_recovery(tip_height: i32, block_height: Option<i32>, timelock: u32) -> Option<i32> {
+    let timelock: i32 = timelock
+        .try_into()
+        .expect("Must fit, it's effectively a u16");
+    block_height.map(|h| cmp::max(h + timelock - (tip_height + 1), 0))
+}
+
+// Decode the inputs and outputs of this Spend transaction, telling which of them are ours, along
+// with its fee and how far it is from being signed.
+fn spend_tx_info(
+    db_conn: &mut Box<dyn DatabaseConnection>,
+    desc: &descriptors::MultipathDescriptor,
+    network: bitcoin::Network,
+    psbt: &Psbt,
+    coins: &HashMap<bitcoin::OutPoint, Coin>,
+) -> SpendTxInfo {
+    let tx = &psbt.unsigned_tx;
+
+    // Use the value of our coins as recorded in database, but fall back to the PSBT for the
+    // inputs we don't know about.
+    let inputs: Vec<SpendTxInput> = tx
+        .input
+        .iter()
+        .zip(psbt.inputs.iter())
+        .map(|(txin, psbtin)| {
+            let outpoint = txin.previous_output;
+            let coin = coins.get(&outpoint);
+            let value = coin
+                .map(|c| c.amount.to_sat())
+                .or_else(|| psbtin.witness_utxo.as_ref().map(|txo| txo.value));
+            SpendTxInput {
+                outpoint,
+                value,
+                is_ours: coin.is_some(),
+            }
+        })
+        .collect();
+
+    // An output is our change if it pays to one of our change addresses, or if it carries
+    // derivation information for signing devices to recognize it as such.
+    let outputs: Vec<SpendTxOutput> = tx
+        .output
+        .iter()
+        .zip(psbt.outputs.iter())
+        .map(|(txout, psbtout)| {
+            let address = bitcoin::Address::from_script(&txout.script_pubkey, network);
+            let is_change = address
+                .as_ref()
+                .and_then(|addr| db_conn.derivation_index_by_address(addr))
+                .map(|(_, is_change)| is_change)
+                .unwrap_or_else(|| {
+                    !psbtout.bip32_derivation.is_empty() || !psbtout.tap_key_origins.is_empty()
+                });
+            SpendTxOutput {
+                address,
+                value: txout.value,
+                is_change,
+            }
+        })
+        .collect();
+
+    // The fee can only be computed if we know the value of all the inputs. The feerate accounts
+    // for the size of the inputs once satisfied.
+    let in_value: Option<u64> = inputs.iter().map(|i| i.value).sum();
+    let out_value: u64 = outputs.iter().map(|o| o.value).sum();
+    let fee = in_value.and_then(|v| v.checked_sub(out_value));
+    let vbytes = tx.vsize() as u64 + (tx.input.len() * desc.max_sat_vbytes()) as u64;
+    let feerate_vb = fee.map(|f| f / vbytes);
+
+    let signing_status = signing_status(desc, psbt);
+
+    SpendTxInfo {
+        inputs,
+        outputs,
+        fee,
+        feerate_vb,
+        signing_status,
+    }
+}
+
+// Get how far this PSBT is from being signed through the primary path of our descriptor. Only
+// the signatures by keys of the primary path count, as identified by the origin of the signing
+// key in the PSBT input.
+fn signing_status(desc: &descriptors::MultipathDescriptor, psbt: &Psbt) -> SigningStatus {
+    let desc_info = desc.info();
+    let primary_path = desc_info.primary_path();
+    let threshold = primary_path.threshold();
+    let primary_origins = primary_path.keys_origins();
+    let is_primary = |(fg, der_path): &(bip32::Fingerprint, bip32::DerivationPath)| {
+        primary_origins.iter().any(|(key_fg, key_path)| {
+            key_fg == fg && der_path.as_ref().starts_with(key_path.as_ref())
+        })
+    };
+
+    let inputs: Vec<InputSigningStatus> = psbt
+        .inputs
+        .iter()
+        .map(|psbtin| {
+            let ecdsa_sigs = psbtin
+                .partial_sigs
+                .keys()
+                .filter(|pk| {
+                    psbtin
+                        .bip32_derivation
+                        .get(&pk.inner)
+                        .map(&is_primary)
+                        .unwrap_or(false)
+                })
+                .count();
+            // A key may sign for more than one leaf, only count it once.
+            let mut tap_signers: HashSet<_> =
+                psbtin.tap_script_sigs.keys().map(|(key, _)| *key).collect();
+            if let (Some(_), Some(key)) = (psbtin.tap_key_sig, psbtin.tap_internal_key) {
+                tap_signers.insert(key);
+            }
+            let schnorr_sigs = tap_signers
+                .into_iter()
+                .filter(|key| {
+                    psbtin
+                        .tap_key_origins
+                        .get(key)
+                        .map(|(_, origin)| is_primary(origin))
+                        .unwrap_or(false)
+                })
+                .count();
+            InputSigningStatus {
+                signatures: ecdsa_sigs + schnorr_sigs,
+                threshold,
+            }
+        })
+        .collect();
+    let finalizable = !inputs.is_empty() && inputs.iter().all(|i| i.signatures >= i.threshold);
+
+    SigningStatus {
+        inputs,
+        finalizable,
+    }
+}
+
+// Create the listing of these Spend transactions, telling which of them are conflicted.
+fn list_spend_result(
+    db_conn: &mut Box<dyn DatabaseConnection>,
+    desc: &descriptors::MultipathDescriptor,
+    network: bitcoin::Network,
+    spends: Vec<(Psbt, u32)>,
+) -> ListSpendResult {
+    let spend_txs = spends
+        .into_iter()
+        .map(|(psbt, updated_at)| {
+            // A Spend is conflicted if one of its coins was spent by another transaction
+            // which got confirmed (for instance a replacement).
+            let txid = psbt.unsigned_tx.txid();
+            let outpoints: Vec<bitcoin::OutPoint> = psbt
+                .unsigned_tx
+                .input
+                .iter()
+                .map(|txin| txin.previous_output)
+                .collect();
+            let coins = db_conn.coins_by_outpoints(&outpoints);
+            let conflicted = coins
+                .values()
+                .any(|coin| coin.spend_block.is_some() && coin.spend_txid != Some(txid));
+            let info = spend_tx_info(db_conn, desc, network, &psbt, &coins);
+            ListSpendEntry {
+                psbt,
+                conflicted,
+                updated_at,
+                info,
+            }
+        })
+        .collect();
+    ListSpendResult { spend_txs }
+}
+
 impl DaemonControl {
-    // Get the derived descriptor for this coin
+    // Get the derived descriptor for this coin. Derivations are cached.
     fn derived_desc(&self, coin: &Coin) -> descriptors::DerivedInheritanceDescriptor {
-        let desc = if coin.is_change {
-            self.config.main_descriptor.change_descriptor()
-        } else {
-            self.config.main_descriptor.receive_descriptor()
-        };
-        desc.derive(coin.derivation_index, &self.secp)
+        self.derived_descs.lock().unwrap().derive(
+            &self.config.main_descriptor,
+            coin.derivation_index,
+            coin.is_change,
+            &self.secp,
+        )
     }
 
     // Check whether this address is valid for the network we are operating on.
     fn validate_address(&self, addr: &bitcoin::Address) -> Result<(), CommandError> {
-        // NOTE: signet uses testnet addresses
-        if addr.network == self.config.bitcoin_config.network
-            || (addr.network == bitcoin::Network::Testnet
-                && self.config.bitcoin_config.network == bitcoin::Network::Signet)
-        {
+        if descriptors::address_is_for_network(addr, self.config.bitcoin_config.network) {
             return Ok(());
         }
 
@@ -221,6 +585,32 @@ impl DaemonControl {
         let rescan_progress = db_conn
             .rescan_timestamp()
             .map(|_| self.bitcoin.rescan_progress().unwrap_or(1.0));
+
+        // A recovery path is available as soon as our oldest confirmed coin is spendable through
+        // it, at the *next* block. Frozen coins are never swept, as in `create_recovery`.
+        let tip_height = self.bitcoin.chain_tip().height;
+        let oldest_coin_height = db_conn
+            .coins(CoinType::Unspent)
+            .into_iter()
+            .filter(|(_, c)| !c.is_frozen)
+            .filter_map(|(_, c)| c.block_height)
+            .min();
+        let recovery_paths: Vec<GetInfoRecoveryPath> = self
+            .config
+            .main_descriptor
+            .timelock_values()
+            .into_iter()
+            .map(|timelock| GetInfoRecoveryPath {
+                timelock,
+                available: blocks_until_recovery(tip_height, oldest_coin_height, timelock.into())
+                    == Some(0),
+                height: oldest_coin_height.map(|h| h + i32::from(timelock)),
+            })
+            .collect();
+        // The first recovery path to become available is the one with the shortest timelock.
+        let recovery_available = recovery_paths[0].available;
+        let recovery_height = recovery_paths[0].height;
+
         GetInfoResult {
             version: VERSION.to_string(),
             network: self.config.bitcoin_config.network,
@@ -228,8 +618,149 @@ impl DaemonControl {
             sync: self.bitcoin.sync_progress(),
             descriptors: GetInfoDescriptors {
                 main: self.config.main_descriptor.clone(),
+                checksum: self.config.main_descriptor.checksum(),
+                keys: self
+                    .config
+                    .main_descriptor
+                    .keys_origins()
+                    .into_iter()
+                    .map(|(fingerprint, derivation_path, xpub)| KeyOrigin {
+                        fingerprint,
+                        derivation_path,
+                        xpub,
+                    })
+                    .collect(),
             },
             rescan_progress,
+            wallet_birthday: db_conn.wallet_timestamp(),
+            recovery_available,
+            recovery_height,
+            recovery_paths,
+        }
+    }
+
+    /// Store the token a signing device returned when registering our descriptor, replacing any
+    /// existing one for this device.
+    pub fn store_hw_token(&self, fingerprint: &bip32::Fingerprint, token: &[u8; 32]) {
+        self.db
+            .connection()
+            .store_hw_token(&self.config.main_descriptor, fingerprint, token);
+    }
+
+    /// Get the tokens the signing devices returned when registering our descriptor.
+    pub fn list_hw_tokens(&self) -> ListHwTokensResult {
+        let mut tokens: Vec<HwToken> = self
+            .db
+            .connection()
+            .hw_tokens(&self.config.main_descriptor)
+            .into_iter()
+            .map(|(fingerprint, token)| HwToken { fingerprint, token })
+            .collect();
+        tokens.sort_unstable_by_key(|t| t.fingerprint);
+        ListHwTokensResult { tokens }
+    }
+
+    /// Get the single-path receive and change descriptors derived from our main descriptor.
+    pub fn list_descriptors(&self) -> ListDescriptorsResult {
+        ListDescriptorsResult {
+            receive: self.config.main_descriptor.receive_descriptor().clone(),
+            change: self.config.main_descriptor.change_descriptor().clone(),
+        }
+    }
+
+    /// Get a backup of this wallet, with the current derivation indices and the ongoing rescan if
+    /// there is any.
+    pub fn wallet_backup(&self) -> WalletBackup {
+        let mut db_conn = self.db.connection();
+        let mut backup = WalletBackup::new(
+            self.config.main_descriptor.clone(),
+            self.config.bitcoin_config.network,
+            db_conn.receive_index(),
+            db_conn.change_index(),
+        );
+        backup.rescan_timestamp = db_conn.rescan_timestamp();
+        backup.labels = db_conn.labels();
+        backup
+    }
+
+    /// Restore the derivation indices and the labels from a backup of this wallet. The indices are
+    /// only ever increased, so we keep on watching all the addresses we may have given out. The
+    /// labels of the backup replace ours for the same items. If a rescan was ongoing at the time
+    /// of the backup, it is started anew unless we are already rescanning.
+    pub fn import_backup(&self, backup: &WalletBackup) -> Result<(), CommandError> {
+        if backup.descriptor != self.config.main_descriptor {
+            return Err(CommandError::InvalidBackup(
+                "descriptor mismatch".to_string(),
+            ));
+        }
+        if backup.network != self.config.bitcoin_config.network {
+            return Err(CommandError::InvalidBackup(format!(
+                "backup is for network '{}'",
+                backup.network
+            )));
+        }
+        let receive_index = bip32::ChildNumber::from_normal_idx(backup.receive_index)
+            .map_err(|e| CommandError::InvalidBackup(e.to_string()))?;
+        let change_index = bip32::ChildNumber::from_normal_idx(backup.change_index)
+            .map_err(|e| CommandError::InvalidBackup(e.to_string()))?;
+
+        let mut db_conn = self.db.connection();
+        if backup.receive_index > u32::from(db_conn.receive_index()) {
+            db_conn.set_receive_index(receive_index, &self.secp);
+        }
+        if backup.change_index > u32::from(db_conn.change_index()) {
+            db_conn.set_change_index(change_index, &self.secp);
+        }
+        db_conn.update_labels(&backup.labels);
+
+        if let Some(timestamp) = backup.rescan_timestamp {
+            if db_conn.rescan_timestamp().is_none() {
+                self.start_rescan(timestamp, false)?;
+            } else {
+                log::info!(
+                    "Not resuming the rescan from '{}' of the backup, we are already rescanning.",
+                    timestamp
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get an estimate of the feerate, in sats/vb, for a transaction to confirm within the given
+    /// number of blocks.
+    pub fn estimate_feerate(&self, target_blocks: u16) -> Result<u64, CommandError> {
+        if target_blocks < 1 {
+            return Err(CommandError::InvalidFeeTarget(target_blocks));
+        }
+        self.bitcoin
+            .estimate_smart_fee(target_blocks)
+            .map(btc_kvb_to_sat_vb)
+            .ok_or(CommandError::FeeEstimation(target_blocks))
+    }
+
+    // The deposit address at this derivation index.
+    fn receive_address(&self, index: bip32::ChildNumber) -> GetAddressResult {
+        let derived_desc = self
+            .config
+            .main_descriptor
+            .receive_descriptor()
+            .derive(index, &self.secp);
+        let address = derived_desc.address(self.config.bitcoin_config.network);
+        let is_reused = self.bitcoin.is_address_used(&address);
+        let derivation_paths = derived_desc
+            .bip32_derivations()
+            .into_iter()
+            .map(|(_, (fingerprint, derivation_path))| KeyDerivation {
+                fingerprint,
+                derivation_path,
+            })
+            .collect();
+        GetAddressResult {
+            address,
+            derivation_index: index.into(),
+            derivation_paths,
+            is_reused,
         }
     }
 
@@ -242,52 +773,257 @@ impl DaemonControl {
             .increment()
             .expect("Can't get into hardened territory");
         db_conn.set_receive_index(new_index, &self.secp);
-        let address = self
-            .config
-            .main_descriptor
-            .receive_descriptor()
-            .derive(index, &self.secp)
-            .address(self.config.bitcoin_config.network);
-        GetAddressResult { address }
+        let res = self.receive_address(index);
+        if res.is_reused {
+            log::warn!(
+                "New deposit address '{}' at index '{}' already received coins. Our derivation index may be out of sync.",
+                res.address,
+                index
+            );
+        }
+        res
+    }
+
+    /// Get the deposit address at the given derivation index, or the one which would be returned
+    /// by the next call to `get_new_address` if none is given. This never updates the deposit
+    /// derivation index.
+    pub fn peek_address(&self, index: Option<u32>) -> Result<GetAddressResult, CommandError> {
+        let index = match index {
+            Some(index) => bip32::ChildNumber::from_normal_idx(index)
+                .map_err(|_| CommandError::InvalidDerivationIndex(index))?,
+            None => self.db.connection().receive_index(),
+        };
+        Ok(self.receive_address(index))
+    }
+
+    /// Get a list of all known coins, optionally only those in the given statuses and among
+    /// the given outpoints.
+    pub fn list_coins(
+        &self,
+        statuses: &[CoinStatus],
+        outpoints: &[bitcoin::OutPoint],
+    ) -> ListCoinsResult {
+        let mut db_conn = self.db.connection();
+        let coins = db_conn.list_coins(statuses, outpoints);
+        self.list_coins_result(coins.values().copied())
+    }
+
+    /// Same as [`list_coins`](Self::list_coins), but only get at most `count` coins after
+    /// skipping the first `start` ones. Coins are always returned in the same order, so that
+    /// successive pages don't overlap.
+    pub fn list_coins_page(
+        &self,
+        statuses: &[CoinStatus],
+        outpoints: &[bitcoin::OutPoint],
+        start: u64,
+        count: u64,
+    ) -> ListCoinsResult {
+        let mut db_conn = self.db.connection();
+        let coins = db_conn.list_coins_page(statuses, outpoints, start, count);
+        self.list_coins_result(coins)
     }
 
-    /// Get a list of all known coins.
-    pub fn list_coins(&self) -> ListCoinsResult {
+    /// Get the coins with the given outpoints. Unknown outpoints are ignored.
+    pub fn get_coins(&self, outpoints: &[bitcoin::OutPoint]) -> ListCoinsResult {
         let mut db_conn = self.db.connection();
-        #[allow(clippy::iter_kv_map)] // Because Rust 1.48
-        let coins: Vec<ListCoinsEntry> = db_conn
-            .coins(CoinType::All)
-            // Can't use into_values as of Rust 1.48
+        let coins = db_conn.coins_by_outpoints(outpoints);
+        self.list_coins_result(coins.values().copied())
+    }
+
+    fn list_coins_result(&self, coins: impl IntoIterator<Item = Coin>) -> ListCoinsResult {
+        let tip_height = self.bitcoin.chain_tip().height;
+        let timelocks = self.config.main_descriptor.timelock_values();
+        // The satisfaction weight doesn't depend on the derivation index, don't derive the
+        // descriptor for each coin.
+        let sat_weight = self.config.main_descriptor.max_sat_weight() as u64;
+        let coins: Vec<ListCoinsEntry> = coins
             .into_iter()
-            .map(|(_, coin)| {
+            .map(|coin| {
                 let Coin {
                     amount,
                     outpoint,
                     block_height,
                     spend_txid,
                     spend_block,
+                    is_frozen,
                     ..
                 } = coin;
                 let spend_info = spend_txid.map(|txid| LCSpendInfo {
                     txid,
                     height: spend_block.map(|b| b.height),
                 });
+                let spend_status = match (spend_txid, spend_block) {
+                    (None, _) => SpendStatus::NotSpent,
+                    (Some(_), None) => SpendStatus::Broadcast,
+                    (Some(_), Some(_)) => SpendStatus::Confirmed,
+                };
+                let confirmations = coin.confirmations(tip_height);
+                let recovery_paths: Vec<LCRecoveryPath> = timelocks
+                    .iter()
+                    .map(|timelock| LCRecoveryPath {
+                        timelock: *timelock,
+                        blocks_until_recovery: blocks_until_recovery(
+                            tip_height,
+                            block_height,
+                            (*timelock).into(),
+                        ),
+                    })
+                    .collect();
+                // The shortest timelock recovery path is the first one to become available.
+                let blocks_until_recovery = recovery_paths[0].blocks_until_recovery;
                 ListCoinsEntry {
                     amount,
                     outpoint,
                     block_height,
+                    confirmations,
+                    sat_weight,
                     spend_info,
+                    spend_status,
+                    blocks_until_recovery,
+                    recovery_paths,
+                    frozen: is_frozen,
                 }
             })
             .collect();
         ListCoinsResult { coins }
     }
 
+    /// Get the maximum size in WU of the satisfaction of this coin, if it exists.
+    pub fn coin_sat_weight(&self, outpoint: &bitcoin::OutPoint) -> Option<u64> {
+        let mut db_conn = self.db.connection();
+        db_conn
+            .coins_by_outpoints(&[*outpoint])
+            .get(outpoint)
+            .map(|coin| self.derived_desc(coin).max_sat_weight() as u64)
+    }
+
+    /// Freeze or unfreeze the given coins. Frozen coins are not spent unless explicitly
+    /// selected.
+    pub fn set_frozen(
+        &self,
+        outpoints: &[bitcoin::OutPoint],
+        frozen: bool,
+    ) -> Result<(), CommandError> {
+        let mut db_conn = self.db.connection();
+        let coins = db_conn.coins_by_outpoints(outpoints);
+        if let Some(op) = outpoints.iter().find(|op| !coins.contains_key(op)) {
+            return Err(CommandError::UnknownOutpoint(*op));
+        }
+        db_conn.set_frozen(outpoints, frozen);
+        Ok(())
+    }
+
+    /// Create a Spend transaction. If `subtract_fee_from` is set, the fee is deducted from the
+    /// output paying to this destination instead of being paid for by the inputs. If `data` is
+    /// set, it is embedded in the transaction in an OP_RETURN output.
     pub fn create_spend(
         &self,
         destinations: &HashMap<bitcoin::Address, u64>,
         coins_outpoints: &[bitcoin::OutPoint],
         feerate_vb: u64,
+        subtract_fee_from: Option<&bitcoin::Address>,
+        data: Option<&[u8]>,
+    ) -> Result<CreateSpendResult, CommandError> {
+        self.create_spend_internal(
+            destinations,
+            coins_outpoints,
+            feerate_vb,
+            subtract_fee_from,
+            data,
+            None,
+        )
+    }
+
+    // Get the descriptor for a new change output, without updating our next change index.
+    fn change_desc(
+        &self,
+        db_conn: &mut Box<dyn DatabaseConnection>,
+    ) -> descriptors::DerivedInheritanceDescriptor {
+        self.config
+            .main_descriptor
+            .change_descriptor()
+            .derive(db_conn.change_index(), &self.secp)
+    }
+
+    // Get the descriptor for a new change output, and update our next change index.
+    fn next_change_desc(
+        &self,
+        db_conn: &mut Box<dyn DatabaseConnection>,
+    ) -> descriptors::DerivedInheritanceDescriptor {
+        let change_index = db_conn.change_index();
+        let change_desc = self.change_desc(db_conn);
+        let next_index = change_index
+            .increment()
+            .expect("Must not get into hardened territory");
+        db_conn.set_change_index(next_index, &self.secp);
+        change_desc
+    }
+
+    /// Get the dust limit for our change outputs at the given feerate, in sats. That is the
+    /// value under which Bitcoin Core considers an output costs more to spend than it's worth.
+    pub fn dust_threshold(&self, feerate_vb: u64) -> u64 {
+        let change_txo = bitcoin::TxOut {
+            value: 0,
+            script_pubkey: self
+                .config
+                .main_descriptor
+                .change_descriptor()
+                .derive(0.into(), &self.secp)
+                .script_pubkey(),
+        };
+        // Bitcoin Core assumes a 107 bytes satisfaction, discounted if it's in the witness.
+        let spending_size = if change_txo.script_pubkey.is_witness_program() {
+            32 + 4 + 1 + 107 / 4 + 4
+        } else {
+            32 + 4 + 1 + 107 + 4
+        };
+        (serializable_size(&change_txo) + spending_size)
+            .checked_mul(feerate_vb)
+            .unwrap()
+    }
+
+    // Whether it's worth creating a change output with this value. It must be above the dust limit
+    // at the feerate of the transaction, and if a long term feerate is configured it must be
+    // worth more than the cost of spending it later.
+    fn is_change_worth_it(&self, change_amount: bitcoin::Amount, feerate_vb: u64) -> bool {
+        let bitcoin_config = &self.config.bitcoin_config;
+        let dust_threshold = self.dust_threshold(feerate_vb);
+        if change_amount.to_sat() < dust_threshold {
+            log::debug!(
+                "Not creating a change output worth {}, below the dust limit of {} sats at {} sats/vb.",
+                change_amount,
+                dust_threshold,
+                feerate_vb
+            );
+            return false;
+        }
+        if let Some(long_term_feerate_vb) = bitcoin_config.long_term_feerate_vb {
+            let spending_cost = (self.config.main_descriptor.spender_input_size() as u64)
+                .checked_mul(long_term_feerate_vb)
+                .unwrap();
+            if change_amount.to_sat() <= spending_cost {
+                log::debug!(
+                    "Not creating a change output worth {}, spending it later would cost {} sats.",
+                    change_amount,
+                    spending_cost
+                );
+                return false;
+            }
+        }
+        true
+    }
+
+    // Create a Spend transaction. If it replaces another transaction, given along with the fee it
+    // pays, the coins spent by the replaced transaction may be used and the fee must be increased
+    // enough for the replacement to be relayed.
+    fn create_spend_internal(
+        &self,
+        destinations: &HashMap<bitcoin::Address, u64>,
+        coins_outpoints: &[bitcoin::OutPoint],
+        feerate_vb: u64,
+        subtract_fee_from: Option<&bitcoin::Address>,
+        data: Option<&[u8]>,
+        replaced: Option<(&bitcoin::Txid, bitcoin::Amount)>,
     ) -> Result<CreateSpendResult, CommandError> {
         if coins_outpoints.is_empty() {
             return Err(CommandError::NoOutpoint);
@@ -298,6 +1034,16 @@ impl DaemonControl {
         if feerate_vb < 1 {
             return Err(CommandError::InvalidFeerate(feerate_vb));
         }
+        // Don't create a transaction our node would refuse to broadcast.
+        let relay_feerate = self.bitcoin.relay_feerate();
+        if feerate_vb < relay_feerate {
+            return Err(CommandError::FeerateBelowRelay(feerate_vb, relay_feerate));
+        }
+        if let Some(addr) = subtract_fee_from {
+            if !destinations.contains_key(addr) {
+                return Err(CommandError::UnknownFeePayer(addr.clone()));
+            }
+        }
         let mut db_conn = self.db.connection();
 
         // Iterate through given outpoints to fetch the coins (hence checking their existence
@@ -315,11 +1061,18 @@ impl DaemonControl {
         for op in coins_outpoints {
             // Get the coin from our in-DB unspent txos
             let coin = coins.get(op).ok_or(CommandError::UnknownOutpoint(*op))?;
-            if coin.is_spent() {
+            let is_replaced = coin.spend_block.is_none()
+                && coin.spend_txid.as_ref() == replaced.map(|(txid, _)| txid);
+            if coin.is_spent() && !is_replaced {
                 return Err(CommandError::AlreadySpent(*op));
             }
+            // Frozen coins are not selected automatically, but the user may still explicitly
+            // choose to spend them.
+            if coin.is_frozen {
+                log::warn!("Creating a Spend transaction with frozen coin '{}'.", op);
+            }
             // Fetch the transaction that created it if necessary
-            if !spent_txs.contains_key(op) {
+            if self.config.bitcoin_config.psbt_non_witness_utxo && !spent_txs.contains_key(op) {
                 let tx = self
                     .bitcoin
                     .wallet_transaction(&op.txid)
@@ -338,20 +1091,18 @@ impl DaemonControl {
             // Populate the PSBT input with the information needed by signers.
             let coin_desc = self.derived_desc(coin);
             sat_vb += txin_sat_vb;
-            let witness_script = Some(coin_desc.witness_script());
             let witness_utxo = Some(bitcoin::TxOut {
                 value: coin.amount.to_sat(),
                 script_pubkey: coin_desc.script_pubkey(),
             });
             let non_witness_utxo = spent_txs.get(op).cloned();
-            let bip32_derivation = coin_desc.bip32_derivations();
-            psbt_ins.push(PsbtIn {
-                witness_script,
+            let mut psbt_in = PsbtIn {
                 witness_utxo,
-                bip32_derivation,
                 non_witness_utxo,
                 ..PsbtIn::default()
-            });
+            };
+            coin_desc.update_psbt_in(&mut psbt_in);
+            psbt_ins.push(psbt_in);
         }
 
         // Add the destinations outputs to the transaction and PSBT. At the same time record the
@@ -361,9 +1112,10 @@ impl DaemonControl {
         let mut psbt_outs = Vec::with_capacity(destinations.len());
         for (address, value_sat) in destinations {
             self.validate_address(address)?;
+            check_output_script(&address.script_pubkey())?;
 
             let amount = bitcoin::Amount::from_sat(*value_sat);
-            check_output_value(amount)?;
+            check_output_value(&self.config.bitcoin_config, amount)?;
             out_value = out_value.checked_add(amount).unwrap();
 
             txouts.push(bitcoin::TxOut {
@@ -371,22 +1123,29 @@ impl DaemonControl {
                 script_pubkey: address.script_pubkey(),
             });
             // If it's an address of ours, signal it as change to signing devices by adding the
-            // BIP32 derivation path to the PSBT output.
-            let bip32_derivation =
-                if let Some((index, is_change)) = db_conn.derivation_index_by_address(address) {
-                    let desc = if is_change {
-                        self.config.main_descriptor.change_descriptor()
-                    } else {
-                        self.config.main_descriptor.receive_descriptor()
-                    };
-                    desc.derive(index, &self.secp).bip32_derivations()
+            // key origins and the script information to the PSBT output.
+            let mut psbt_out = PsbtOut::default();
+            if let Some((index, is_change)) = db_conn.derivation_index_by_address(address) {
+                let desc = if is_change {
+                    self.config.main_descriptor.change_descriptor()
                 } else {
-                    Default::default()
+                    self.config.main_descriptor.receive_descriptor()
                 };
-            psbt_outs.push(PsbtOut {
-                bip32_derivation,
-                ..PsbtOut::default()
+                desc.derive(index, &self.secp)
+                    .update_psbt_out(&mut psbt_out);
+            }
+            psbt_outs.push(psbt_out);
+        }
+
+        // If asked, embed the given data in an OP_RETURN output. It doesn't carry any value.
+        if let Some(data) = data {
+            let script_pubkey = data_script(data);
+            check_output_script(&script_pubkey)?;
+            txouts.push(bitcoin::TxOut {
+                value: 0,
+                script_pubkey,
             });
+            psbt_outs.push(PsbtOut::default());
         }
 
         // Now create the transaction, compute its fees and already sanity check if its feerate
@@ -404,60 +1163,92 @@ impl DaemonControl {
                 .ok_or(CommandError::InsufficientFunds(
                     in_value, out_value, feerate_vb,
                 ))?;
-        let nochange_feerate_vb = absolute_fee.to_sat().checked_div(nochange_vb).unwrap();
-        if nochange_feerate_vb.checked_mul(10).unwrap() < feerate_vb.checked_mul(9).unwrap() {
-            return Err(CommandError::InsufficientFunds(
-                in_value, out_value, feerate_vb,
-            ));
-        }
 
-        // If necessary, add a change output. The computation here is a bit convoluted: we infer
-        // the needed change value from the target feerate and the size of the transaction *with
-        // an added output* (for the change).
-        if nochange_feerate_vb > feerate_vb {
-            // Get the change address to create a dummy change txo.
-            let change_index = db_conn.change_index();
-            let change_desc = self
-                .config
-                .main_descriptor
-                .change_descriptor()
-                .derive(change_index, &self.secp);
-            // Don't forget to update our next change index!
-            let next_index = change_index
-                .increment()
-                .expect("Must not get into hardened territory");
-            db_conn.set_change_index(next_index, &self.secp);
-            let mut change_txo = bitcoin::TxOut {
-                value: std::u64::MAX,
-                script_pubkey: change_desc.script_pubkey(),
-            };
-            // Serialized size is equal to the virtual size for an output.
-            let change_vb: u64 = serializable_size(&change_txo);
-            // We assume the added output does not increase the size of the varint for
-            // the output count.
-            let with_change_vb = nochange_vb.checked_add(change_vb).unwrap();
-            let with_change_feerate_vb = absolute_fee.to_sat().checked_div(with_change_vb).unwrap();
-
-            if with_change_feerate_vb > feerate_vb {
-                let target_fee = with_change_vb.checked_mul(feerate_vb).unwrap();
-                let change_amount = absolute_fee
-                    .checked_sub(bitcoin::Amount::from_sat(target_fee))
-                    .unwrap();
-                if change_amount.to_sat() >= DUST_OUTPUT_SATS {
-                    check_output_value(change_amount)?;
-
-                    // TODO: shuffle once we have Taproot
-                    change_txo.value = change_amount.to_sat();
-                    tx.output.push(change_txo);
-                    psbt_outs.push(PsbtOut {
-                        bip32_derivation: change_desc.bip32_derivations(),
-                        ..PsbtOut::default()
-                    });
+        // Our next change index is only updated once we know the transaction can be created.
+        let mut uses_change_index = false;
+
+        // If the fee is paid by one of the recipients, the excess of input value goes to a
+        // change output if it's worth it, to the fee otherwise. The payer's output is then
+        // reduced by what's left to pay for the target feerate.
+        if let Some(payer_addr) = subtract_fee_from {
+            let payer_spk = payer_addr.script_pubkey();
+            let payer_index = tx
+                .output
+                .iter()
+                .position(|txo| txo.script_pubkey == payer_spk)
+                .expect("We checked it's among the destinations");
+            let mut paid_fee = absolute_fee;
+            if self.is_change_worth_it(absolute_fee, feerate_vb) {
+                check_change_value(absolute_fee)?;
+                let change_desc = self.change_desc(&mut db_conn);
+                uses_change_index = true;
+                tx.output.push(bitcoin::TxOut {
+                    value: absolute_fee.to_sat(),
+                    script_pubkey: change_desc.script_pubkey(),
+                });
+                let mut psbt_out = PsbtOut::default();
+                change_desc.update_psbt_out(&mut psbt_out);
+                psbt_outs.push(psbt_out);
+                paid_fee = bitcoin::Amount::from_sat(0);
+            }
+            let target_fee = ((tx.vsize() + sat_vb) as u64)
+                .checked_mul(feerate_vb)
+                .unwrap();
+            let payer_fee = target_fee.saturating_sub(paid_fee.to_sat());
+            let payer_value = tx.output[payer_index].value.saturating_sub(payer_fee);
+            check_output_value(
+                &self.config.bitcoin_config,
+                bitcoin::Amount::from_sat(payer_value),
+            )?;
+            tx.output[payer_index].value = payer_value;
+        } else {
+            let nochange_feerate_vb = absolute_fee.to_sat().checked_div(nochange_vb).unwrap();
+            if nochange_feerate_vb.checked_mul(10).unwrap() < feerate_vb.checked_mul(9).unwrap() {
+                return Err(CommandError::InsufficientFunds(
+                    in_value, out_value, feerate_vb,
+                ));
+            }
+
+            // If necessary, add a change output. The computation here is a bit convoluted: we
+            // infer the needed change value from the target feerate and the size of the
+            // transaction *with an added output* (for the change).
+            if nochange_feerate_vb > feerate_vb {
+                // Get the change address to create a dummy change txo.
+                let change_desc = self.change_desc(&mut db_conn);
+                uses_change_index = true;
+                let mut change_txo = bitcoin::TxOut {
+                    value: std::u64::MAX,
+                    script_pubkey: change_desc.script_pubkey(),
+                };
+                // Serialized size is equal to the virtual size for an output.
+                let change_vb: u64 = serializable_size(&change_txo);
+                // We assume the added output does not increase the size of the varint for
+                // the output count.
+                let with_change_vb = nochange_vb.checked_add(change_vb).unwrap();
+                let with_change_feerate_vb =
+                    absolute_fee.to_sat().checked_div(with_change_vb).unwrap();
+
+                if with_change_feerate_vb > feerate_vb {
+                    let target_fee = with_change_vb.checked_mul(feerate_vb).unwrap();
+                    let change_amount = absolute_fee
+                        .checked_sub(bitcoin::Amount::from_sat(target_fee))
+                        .unwrap();
+                    if self.is_change_worth_it(change_amount, feerate_vb) {
+                        check_change_value(change_amount)?;
+
+                        change_txo.value = change_amount.to_sat();
+                        tx.output.push(change_txo);
+                        // Signal the change to the signing devices so they don't display it
+                        // as a payment to an external address.
+                        let mut psbt_out = PsbtOut::default();
+                        change_desc.update_psbt_out(&mut psbt_out);
+                        psbt_outs.push(psbt_out);
+                    }
                 }
             }
         }
 
-        let psbt = Psbt {
+        let mut psbt = Psbt {
             unsigned_tx: tx,
             version: 0,
             xpub: BTreeMap::new(),
@@ -466,46 +1257,294 @@ impl DaemonControl {
             inputs: psbt_ins,
             outputs: psbt_outs,
         };
-        sanity_check_psbt(&psbt)?;
+        bip69_sort(&mut psbt);
+        sanity_check_psbt(&self.config.bitcoin_config, &psbt)?;
         // TODO: maybe check for common standardness rules (max size, ..)?
 
+        // A replacement must pay for its own relay on top of the fee of the transaction it
+        // replaces (BIP125 rule 4). A higher feerate isn't enough if the transaction grew.
+        if let Some((_, replaced_fee)) = replaced {
+            let tx = &psbt.unsigned_tx;
+            let out_value: u64 = tx.output.iter().map(|txo| txo.value).sum();
+            let fee = in_value
+                .checked_sub(bitcoin::Amount::from_sat(out_value))
+                .expect("We checked the fee isn't negative");
+            let relay_fee = ((tx.vsize() + sat_vb) as u64)
+                .checked_mul(self.bitcoin.incremental_relay_feerate())
+                .unwrap();
+            let min_fee = replaced_fee
+                .checked_add(bitcoin::Amount::from_sat(relay_fee))
+                .unwrap();
+            if fee < min_fee {
+                return Err(CommandError::InsufficientFeeBump(fee, min_fee));
+            }
+        }
+
+        if uses_change_index {
+            self.next_change_desc(&mut db_conn);
+        }
+
         Ok(CreateSpendResult { psbt })
     }
 
-    pub fn update_spend(&self, mut psbt: Psbt) -> Result<(), CommandError> {
+    /// Create a transaction replacing the given stored Spend transaction, paying to the same
+    /// destinations at a higher feerate. The fee increase is taken from the change if possible.
+    /// Otherwise the largest confirmed coin that isn't frozen is added to the inputs to fund it.
+    pub fn rbf_spend(
+        &self,
+        txid: &bitcoin::Txid,
+        feerate_vb: u64,
+    ) -> Result<CreateSpendResult, CommandError> {
         let mut db_conn = self.db.connection();
-        let tx = &psbt.unsigned_tx;
+        let prev_psbt = db_conn
+            .spend_tx(txid)
+            .ok_or(CommandError::UnknownSpend(*txid))?;
+        let prev_tx = &prev_psbt.unsigned_tx;
 
-        // If the transaction already exists in DB, merge the signatures for each input on a best
-        // effort basis.
-        // We work on the newly provided PSBT, in case its content was updated.
-        let txid = tx.txid();
-        if let Some(db_psbt) = db_conn.spend_tx(&txid) {
-            let db_tx = db_psbt.unsigned_tx;
-            for i in 0..db_tx.input.len() {
-                if tx
-                    .input
-                    .get(i)
-                    .map(|tx_in| tx_in.previous_output == db_tx.input[i].previous_output)
-                    != Some(true)
-                {
-                    continue;
-                }
-                let psbtin = match psbt.inputs.get_mut(i) {
-                    Some(psbtin) => psbtin,
-                    None => continue,
-                };
-                let db_psbtin = match db_psbt.inputs.get(i) {
-                    Some(db_psbtin) => db_psbtin,
-                    None => continue,
-                };
-                psbtin
-                    .partial_sigs
-                    .extend(db_psbtin.partial_sigs.clone().into_iter());
+        // The replacement must pay a higher feerate than the transaction it replaces.
+        let mut outpoints: Vec<bitcoin::OutPoint> = prev_tx
+            .input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .collect();
+        let prev_coins = db_conn.coins_by_outpoints(&outpoints);
+        let prev_in_value: u64 = prev_coins.values().map(|c| c.amount.to_sat()).sum();
+        let prev_out_value: u64 = prev_tx.output.iter().map(|o| o.value).sum();
+        let prev_vb = prev_tx.vsize() as u64
+            + (prev_tx.input.len() * self.config.main_descriptor.max_sat_vbytes()) as u64;
+        let prev_fee = prev_in_value.saturating_sub(prev_out_value);
+        let prev_feerate_vb = prev_fee / prev_vb;
+        if feerate_vb <= prev_feerate_vb {
+            return Err(CommandError::InvalidFeerate(feerate_vb));
+        }
+        let replaced = Some((txid, bitcoin::Amount::from_sat(prev_fee)));
+
+        // Keep all the outputs but our change, which is recomputed for the new feerate.
+        let network = self.config.bitcoin_config.network;
+        let mut destinations = HashMap::with_capacity(prev_tx.output.len());
+        let mut data = None;
+        for txout in prev_tx.output.iter() {
+            if let Some(d) = script_data(&txout.script_pubkey) {
+                data = Some(d);
+                continue;
             }
-        } else {
-            // If the transaction doesn't exist in DB already, sanity check its inputs.
-            // FIXME: should we allow for external inputs?
+            let address = bitcoin::Address::from_script(&txout.script_pubkey, network)
+                .ok_or_else(|| CommandError::SanityCheckFailure(prev_psbt.clone()))?;
+            if let Some((_, true)) = db_conn.derivation_index_by_address(&address) {
+                continue;
+            }
+            // Several outputs may pay to the same address, keep their whole value.
+            *destinations.entry(address).or_insert(0) += txout.value;
+        }
+
+        let insufficient_funds_err = match self.create_spend_internal(
+            &destinations,
+            &outpoints,
+            feerate_vb,
+            None,
+            data.as_deref(),
+            replaced,
+        ) {
+            Err(e @ CommandError::InsufficientFunds(..)) => e,
+            res => return res,
+        };
+
+        // The change couldn't cover the fee increase. Keep all the original inputs and fund it
+        // with an additional coin, which must be buried deep enough.
+        let tip_height = self.bitcoin.chain_tip().height;
+        let min_confs = self.config.bitcoin_config.min_confirmations;
+        let additional_coin = db_conn
+            .coins(CoinType::Unspent)
+            .into_iter()
+            .map(|(_, coin)| coin)
+            .filter(|coin| {
+                coin.confirmations(tip_height) >= min_confs
+                    && !coin.is_frozen
+                    && !outpoints.contains(&coin.outpoint)
+            })
+            .max_by_key(|coin| coin.amount)
+            .ok_or(insufficient_funds_err)?;
+        outpoints.push(additional_coin.outpoint);
+        self.create_spend_internal(
+            &destinations,
+            &outpoints,
+            feerate_vb,
+            None,
+            data.as_deref(),
+            replaced,
+        )
+    }
+
+    /// Create transactions spending all our unspent and unfrozen coins back to the wallet. The
+    /// coins are split across as many transactions as necessary for each of them to be standard
+    /// and to spend at most `max_inputs_per_tx` coins, if set. A batch of coins which isn't worth
+    /// the fee to spend it at this feerate is skipped.
+    pub fn consolidate(
+        &self,
+        feerate_vb: u64,
+        max_inputs_per_tx: Option<usize>,
+    ) -> Result<Vec<CreateSpendResult>, CommandError> {
+        // Leave room for the transaction header and the single output, and assume the worst case
+        // satisfaction for each input.
+        let txin_weight = 4 * serializable_size(&bitcoin::TxIn::default()) as usize
+            + self.config.main_descriptor.max_sat_weight();
+        let header_weight = 4 * (4 + 4 + 3 + 1 + 43) + 2;
+        let max_inputs = cmp::max((MAX_STANDARD_TX_WEIGHT - header_weight) / txin_weight, 1);
+        let max_inputs = match max_inputs_per_tx {
+            Some(0) => return Err(CommandError::NoOutpoint),
+            Some(max) => cmp::min(max, max_inputs),
+            None => max_inputs,
+        };
+
+        let mut db_conn = self.db.connection();
+        let mut coins: Vec<Coin> = db_conn
+            .coins(CoinType::Unspent)
+            .into_iter()
+            .map(|(_, coin)| coin)
+            .filter(|coin| !coin.is_frozen)
+            .collect();
+        if coins.is_empty() {
+            return Err(CommandError::NoOutpoint);
+        }
+        coins.sort_unstable_by_key(|coin| coin.outpoint);
+
+        // Each transaction pays the value of its coins minus the fee to a new change address.
+        let network = self.config.bitcoin_config.network;
+        let mut results = Vec::new();
+        let mut skipped = None;
+        for batch in coins.chunks(max_inputs) {
+            let outpoints: Vec<bitcoin::OutPoint> =
+                batch.iter().map(|coin| coin.outpoint).collect();
+            let in_value: u64 = batch.iter().map(|coin| coin.amount.to_sat()).sum();
+            // Only move to the next change index once we know the transaction can be created.
+            let change_addr = self
+                .config
+                .main_descriptor
+                .change_descriptor()
+                .derive(db_conn.change_index(), &self.secp)
+                .address(network);
+            let destinations: HashMap<bitcoin::Address, u64> =
+                [(change_addr.clone(), in_value)].iter().cloned().collect();
+            match self.create_spend_internal(
+                &destinations,
+                &outpoints,
+                feerate_vb,
+                Some(&change_addr),
+                None,
+                None,
+            ) {
+                Ok(res) => {
+                    self.next_change_desc(&mut db_conn);
+                    results.push(res);
+                }
+                // The fee to spend these coins would leave a dust output.
+                Err(e @ CommandError::InvalidOutputValue(_)) => {
+                    log::warn!("Not consolidating coins {:?}: {}", outpoints, e);
+                    skipped = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        match skipped {
+            Some(e) if results.is_empty() => Err(e),
+            _ => Ok(results),
+        }
+    }
+
+    // Warn about the outputs of this Spend transaction which pay to an address which was already
+    // used: either one of ours which already received coins, or an external one which another
+    // stored Spend transaction pays to.
+    fn address_reuse_warnings(
+        &self,
+        db_conn: &mut Box<dyn DatabaseConnection>,
+        psbt: &Psbt,
+    ) -> Vec<String> {
+        let network = self.config.bitcoin_config.network;
+        let txid = psbt.unsigned_tx.txid();
+        let other_spends: Vec<Psbt> = db_conn
+            .list_spend(None)
+            .into_iter()
+            .map(|(psbt, _)| psbt)
+            .filter(|psbt| psbt.unsigned_tx.txid() != txid)
+            .collect();
+
+        let mut warnings = Vec::new();
+        for (i, txout) in psbt.unsigned_tx.output.iter().enumerate() {
+            let address = match bitcoin::Address::from_script(&txout.script_pubkey, network) {
+                Some(address) => address,
+                None => continue,
+            };
+            // Coins created by this very transaction, if it was broadcast, aren't a reuse.
+            let is_used_by_us = match db_conn.derivation_index_by_address(&address) {
+                Some(index) => db_conn
+                    .coins_by_derivation_indexes(&[index])
+                    .keys()
+                    .any(|op| op.txid != txid),
+                None => false,
+            };
+            if is_used_by_us {
+                warnings.push(format!(
+                    "Output {} pays to address '{}' of this wallet, which already received coins.",
+                    i, address
+                ));
+                continue;
+            }
+            if let Some(other) = other_spends.iter().find(|other| {
+                other
+                    .unsigned_tx
+                    .output
+                    .iter()
+                    .any(|o| o.script_pubkey == txout.script_pubkey)
+            }) {
+                warnings.push(format!(
+                    "Output {} pays to address '{}', which Spend transaction '{}' already pays to.",
+                    i,
+                    address,
+                    other.unsigned_tx.txid()
+                ));
+            }
+        }
+
+        warnings
+    }
+
+    /// Store a Spend transaction, updating it if it already exists. Paying to an address which
+    /// was already used isn't refused, but is reported in the returned warnings.
+    pub fn update_spend(&self, mut psbt: Psbt) -> Result<UpdateSpendResult, CommandError> {
+        let mut db_conn = self.db.connection();
+        let tx = &psbt.unsigned_tx;
+
+        // If the transaction already exists in DB, merge the signatures for each input on a best
+        // effort basis.
+        // We work on the newly provided PSBT, in case its content was updated.
+        let txid = tx.txid();
+        if let Some(db_psbt) = db_conn.spend_tx(&txid) {
+            let db_tx = db_psbt.unsigned_tx;
+            for i in 0..db_tx.input.len() {
+                if tx
+                    .input
+                    .get(i)
+                    .map(|tx_in| tx_in.previous_output == db_tx.input[i].previous_output)
+                    != Some(true)
+                {
+                    continue;
+                }
+                let psbtin = match psbt.inputs.get_mut(i) {
+                    Some(psbtin) => psbtin,
+                    None => continue,
+                };
+                let db_psbtin = match db_psbt.inputs.get(i) {
+                    Some(db_psbtin) => db_psbtin,
+                    None => continue,
+                };
+                psbtin
+                    .partial_sigs
+                    .extend(db_psbtin.partial_sigs.clone().into_iter());
+            }
+        } else {
+            // If the transaction doesn't exist in DB already, sanity check its inputs.
+            // FIXME: should we allow for external inputs?
             let outpoints: Vec<bitcoin::OutPoint> =
                 tx.input.iter().map(|txin| txin.previous_output).collect();
             let coins = db_conn.coins_by_outpoints(&outpoints);
@@ -518,20 +1557,136 @@ impl DaemonControl {
             }
         }
 
+        let warnings = self.address_reuse_warnings(&mut db_conn, &psbt);
+        for warning in warnings.iter() {
+            log::warn!("Spend transaction '{}': {}", txid, warning);
+        }
+
         // Finally, insert (or update) the PSBT in database.
         db_conn.store_spend(&psbt);
 
-        Ok(())
+        Ok(UpdateSpendResult { warnings })
     }
 
-    pub fn list_spend(&self) -> ListSpendResult {
+    /// Store a Spend transaction as `update_spend` would, from either a base64-encoded PSBT, a
+    /// hex-encoded PSBT or a hex-encoded (possibly finalized) transaction.
+    pub fn update_spend_encoded(&self, encoded: &str) -> Result<UpdateSpendResult, CommandError> {
+        if let Some(psbt) = base64::decode(encoded)
+            .ok()
+            .and_then(|bytes| encode::deserialize::<Psbt>(&bytes).ok())
+        {
+            return self.update_spend(psbt);
+        }
+
+        let bytes = Vec::<u8>::from_hex(encoded).map_err(|_| CommandError::UndecodableSpend)?;
+        if let Ok(psbt) = encode::deserialize::<Psbt>(&bytes) {
+            return self.update_spend(psbt);
+        }
+        let tx: bitcoin::Transaction =
+            encode::deserialize(&bytes).map_err(|_| CommandError::UndecodableSpend)?;
+
+        // Build a PSBT for this transaction, starting from the one we have in database if any.
+        // The signatures of a finalized transaction are kept as the final scripts of the inputs.
         let mut db_conn = self.db.connection();
-        let spend_txs = db_conn
-            .list_spend()
-            .into_iter()
-            .map(|psbt| ListSpendEntry { psbt })
-            .collect();
-        ListSpendResult { spend_txs }
+        let mut psbt = match db_conn.spend_tx(&tx.txid()) {
+            Some(psbt) => psbt,
+            None => {
+                let mut unsigned_tx = tx.clone();
+                for txin in unsigned_tx.input.iter_mut() {
+                    txin.script_sig = bitcoin::Script::new();
+                    txin.witness = bitcoin::Witness::new();
+                }
+                Psbt::from_unsigned_tx(unsigned_tx).expect("Scripts were just emptied")
+            }
+        };
+        let outpoints: Vec<bitcoin::OutPoint> =
+            tx.input.iter().map(|txin| txin.previous_output).collect();
+        let coins = db_conn.coins_by_outpoints(&outpoints);
+        for (txin, psbtin) in tx.input.iter().zip(psbt.inputs.iter_mut()) {
+            if let Some(coin) = coins.get(&txin.previous_output) {
+                if psbtin.witness_utxo.is_none() {
+                    let coin_desc = self.derived_desc(coin);
+                    psbtin.witness_utxo = Some(bitcoin::TxOut {
+                        value: coin.amount.to_sat(),
+                        script_pubkey: coin_desc.script_pubkey(),
+                    });
+                    coin_desc.update_psbt_in(psbtin);
+                }
+            }
+            if !txin.script_sig.is_empty() {
+                psbtin.final_script_sig = Some(txin.script_sig.clone());
+            }
+            if !txin.witness.is_empty() {
+                psbtin.final_script_witness = Some(txin.witness.clone());
+            }
+        }
+
+        self.update_spend(psbt)
+    }
+
+    /// Combine PSBTs for the same Spend transaction as per BIP174, and store the result.
+    ///
+    /// Unlike `update_spend` this merges all the fields of the inputs and outputs, not only the
+    /// partial signatures. The combined PSBT is stored as if passed to `update_spend`, therefore
+    /// it's also merged with the partial signatures of an existing entry in database.
+    pub fn combine_psbts(&self, psbts: &[Psbt]) -> Result<Psbt, CommandError> {
+        let (first, others) = psbts
+            .split_first()
+            .ok_or_else(|| CommandError::PsbtCombination("No PSBT provided".to_string()))?;
+        let mut psbt = first.clone();
+        for other in others {
+            if other.unsigned_tx != psbt.unsigned_tx {
+                return Err(CommandError::PsbtCombination(format!(
+                    "PSBT for transaction '{}' does not match '{}'",
+                    other.unsigned_tx.txid(),
+                    psbt.unsigned_tx.txid()
+                )));
+            }
+            psbt.combine(other.clone())
+                .map_err(|e| CommandError::PsbtCombination(e.to_string()))?;
+        }
+
+        let txid = psbt.unsigned_tx.txid();
+        self.update_spend(psbt)?;
+        Ok(self
+            .db
+            .connection()
+            .spend_tx(&txid)
+            .expect("We just stored it"))
+    }
+
+    /// List the stored Spend transactions. If `since` is set, only list those updated at or
+    /// after this timestamp. This lets a client which polls this command only fetch the Spends
+    /// which changed since its last call.
+    pub fn list_spend(&self, since: Option<u32>) -> ListSpendResult {
+        let mut db_conn = self.db.connection();
+        let spends = db_conn.list_spend(since);
+        list_spend_result(
+            &mut db_conn,
+            &self.config.main_descriptor,
+            self.config.bitcoin_config.network,
+            spends,
+        )
+    }
+
+    /// Same as [`list_spend`](Self::list_spend), but only get at most `count` Spend transactions
+    /// after skipping the first `start` ones.
+    pub fn list_spend_page(&self, since: Option<u32>, start: u64, count: u64) -> ListSpendResult {
+        let mut db_conn = self.db.connection();
+        let spends = db_conn.list_spend_page(since, start, count);
+        list_spend_result(
+            &mut db_conn,
+            &self.config.main_descriptor,
+            self.config.bitcoin_config.network,
+            spends,
+        )
+    }
+
+    /// Get how many signatures are present and needed for each input of this Spend transaction
+    /// to be finalized through the primary path. `None` if we don't know this Spend.
+    pub fn spend_signing_status(&self, txid: &bitcoin::Txid) -> Option<SigningStatus> {
+        let psbt = self.db.connection().spend_tx(txid)?;
+        Some(signing_status(&self.config.main_descriptor, &psbt))
     }
 
     pub fn delete_spend(&self, txid: &bitcoin::Txid) {
@@ -539,66 +1694,305 @@ impl DaemonControl {
         db_conn.delete_spend(txid);
     }
 
-    /// Finalize and broadcast this stored Spend transaction.
-    pub fn broadcast_spend(&self, txid: &bitcoin::Txid) -> Result<(), CommandError> {
+    // Try to finalize this stored Spend transaction with the elements contained in its PSBT.
+    fn finalized_spend_tx(
+        &self,
+        txid: &bitcoin::Txid,
+    ) -> Result<bitcoin::Transaction, CommandError> {
         let mut db_conn = self.db.connection();
 
-        // First, try to finalize the spending transaction with the elements contained
-        // in the PSBT.
         let mut spend_psbt = db_conn
             .spend_tx(txid)
             .ok_or(CommandError::UnknownSpend(*txid))?;
-        spend_psbt.finalize_mut(&self.secp).map_err(|e| {
-            CommandError::SpendFinalization(
-                e.into_iter()
-                    .next()
-                    .map(|e| e.to_string())
-                    .unwrap_or_default(),
+        for index in 0..spend_psbt.inputs.len() {
+            // The inputs of a transaction finalized by another software are kept as is. They are
+            // checked along with the others below.
+            if spend_psbt.inputs[index].final_script_witness.is_some() {
+                continue;
+            }
+            spend_psbt
+                .finalize_inp_mut(&self.secp, index)
+                .map_err(|e| CommandError::SpendFinalization(e.to_string()))?;
+        }
+
+        let final_tx = spend_psbt.extract_tx();
+        self.verify_final_tx(&spend_psbt, &final_tx)?;
+        Ok(final_tx)
+    }
+
+    // Check each input's final witness satisfies the Script of the coin it spends, before we
+    // hand the transaction to the network. This catches invalid signatures with a clearer
+    // error than the broadcast would.
+    fn verify_final_tx(&self, psbt: &Psbt, tx: &bitcoin::Transaction) -> Result<(), CommandError> {
+        let prevouts = psbt
+            .inputs
+            .iter()
+            .enumerate()
+            .map(|(i, psbtin)| {
+                psbtin.witness_utxo.clone().ok_or_else(|| {
+                    CommandError::SpendVerification(format!("Missing witness utxo for input {}", i))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let all_prevouts = sighash::Prevouts::All(&prevouts);
+
+        for (index, (txin, prevout)) in tx.input.iter().zip(prevouts.iter()).enumerate() {
+            let interpreter = Interpreter::from_txdata(
+                &prevout.script_pubkey,
+                &txin.script_sig,
+                &txin.witness,
+                txin.sequence,
+                tx.lock_time.into(),
             )
-        })?;
+            .map_err(|e| CommandError::SpendVerification(format!("input {}: {}", index, e)))?;
+            for res in interpreter.iter(&self.secp, tx, index, &all_prevouts) {
+                res.map_err(|e| {
+                    CommandError::SpendVerification(format!("input {}: {}", index, e))
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finalize this stored Spend transaction and return the hex-encoded network serialization
+    /// of the final transaction, without broadcasting it.
+    ///
+    /// Note this doesn't mark the spent coins as such. They will be once the poller notices the
+    /// transaction, after it was broadcast by other means.
+    pub fn finalize_spend(&self, txid: &bitcoin::Txid) -> Result<String, CommandError> {
+        let final_tx = self.finalized_spend_tx(txid)?;
+        Ok(bitcoin::consensus::encode::serialize_hex(&final_tx))
+    }
+
+    /// Finalize and broadcast this stored Spend transaction.
+    pub fn broadcast_spend(&self, txid: &bitcoin::Txid) -> Result<(), CommandError> {
+        // First, try to finalize the spending transaction with the elements contained
+        // in the PSBT.
+        let final_tx = self.finalized_spend_tx(txid)?;
 
         // Then, broadcast it (or try to, we never know if we are not going to hit an
         // error at broadcast time).
-        let final_tx = spend_psbt.extract_tx();
         self.bitcoin
             .broadcast_tx(&final_tx)
-            .map_err(CommandError::TxBroadcast)
+            .map_err(CommandError::TxBroadcast)?;
+
+        // Mark the coins as spent right away instead of waiting for the poller to notice the
+        // transaction. Some of them may already have been marked if the poller was faster.
+        let mut db_conn = self.db.connection();
+        let outpoints: Vec<bitcoin::OutPoint> = final_tx
+            .input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .collect();
+        let spent: Vec<(bitcoin::OutPoint, bitcoin::Txid)> = db_conn
+            .coins_by_outpoints(&outpoints)
+            .into_iter()
+            .filter(|(_, coin)| coin.spend_txid.is_none())
+            .map(|(op, _)| (op, *txid))
+            .collect();
+        db_conn.spend_coins(&spent);
+
+        Ok(())
     }
 
     /// Trigger a rescan of the block chain for transactions involving our main descriptor between
     /// the given date and the current tip.
     /// The date must be after the genesis block time and before the current tip blocktime.
-    pub fn start_rescan(&self, timestamp: u32) -> Result<(), CommandError> {
+    ///
+    /// There can't be any transaction involving our descriptor before the wallet was created. If
+    /// `use_wallet_birthday` is set, a date preceding the wallet creation is clamped to it.
+    pub fn start_rescan(
+        &self,
+        timestamp: u32,
+        use_wallet_birthday: bool,
+    ) -> Result<(), CommandError> {
         let mut db_conn = self.db.connection();
 
-        if timestamp < MAINNET_GENESIS_TIME || timestamp >= self.bitcoin.tip_time() {
+        // Check against the genesis block of the chain the backend is actually on. Only fall back
+        // to the one of the configured network if we don't know it.
+        let genesis_hash = self.bitcoin.genesis_block().hash;
+        let genesis_time = genesis_time(&genesis_hash).unwrap_or_else(|| {
+            bitcoin::blockdata::constants::genesis_block(self.config.bitcoin_config.network)
+                .header
+                .time
+        });
+        if timestamp < genesis_time || timestamp >= self.bitcoin.tip_time() {
             return Err(CommandError::InsaneRescanTimestamp(timestamp));
         }
+        let wallet_timestamp = db_conn.wallet_timestamp();
+        let timestamp = if timestamp >= wallet_timestamp {
+            timestamp
+        } else if use_wallet_birthday {
+            log::info!(
+                "Rescan timestamp '{}' precedes the wallet creation, rescanning from '{}' instead.",
+                timestamp,
+                wallet_timestamp
+            );
+            wallet_timestamp
+        } else {
+            log::warn!(
+                "Rescanning from '{}', before the wallet creation at '{}'. This may take longer than necessary.",
+                timestamp,
+                wallet_timestamp
+            );
+            timestamp
+        };
+
+        // Don't race with another call between checking whether a rescan is ongoing and starting
+        // it. Someone else may still trigger a rescan of the backend in the meantime, but it is
+        // reported by the backend as such.
+        let _rescan_guard = self.rescan_lock.lock().unwrap();
         if db_conn.rescan_timestamp().is_some() || self.bitcoin.rescan_progress().is_some() {
             return Err(CommandError::AlreadyRescanning);
         }
-
-        // TODO: there is a race with the above check for whether the backend is already
-        // rescanning. This could make us crash with the bitcoind backend if someone triggered a
-        // rescan of the wallet just after we checked above and did now.
         self.bitcoin
             .start_rescan(&self.config.main_descriptor, timestamp)
-            .map_err(CommandError::RescanTrigger)?;
+            .map_err(|e| match e {
+                RescanError::AlreadyRescanning => CommandError::AlreadyRescanning,
+                RescanError::Other(s) => CommandError::RescanTrigger(s),
+            })?;
         db_conn.set_rescan(timestamp);
 
         Ok(())
     }
 
+    /// Abort the ongoing rescan. Transactions the backend found before being interrupted may not
+    /// be picked up until the next rescan covering them.
+    pub fn cancel_rescan(&self) -> Result<(), CommandError> {
+        let _rescan_guard = self.rescan_lock.lock().unwrap();
+        let mut db_conn = self.db.connection();
+        if db_conn.rescan_timestamp().is_none() {
+            return Err(CommandError::NoOngoingRescan);
+        }
+        if !self.bitcoin.abort_rescan() {
+            log::warn!("No rescan to abort on the backend. It might have just completed.");
+        }
+        db_conn.cancel_rescan();
+
+        Ok(())
+    }
+
+    /// Get detailed information about the ongoing rescan, if there is any.
+    pub fn rescan_status(&self) -> Option<RescanStatus> {
+        let mut db_conn = self.db.connection();
+        let timestamp = db_conn.rescan_timestamp()?;
+
+        let start_height = self
+            .bitcoin
+            .block_before_date(timestamp)
+            .unwrap_or_else(|| self.bitcoin.genesis_block())
+            .height;
+        let target_height = self.bitcoin.chain_tip().height;
+        let now = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .expect("Can't be before the epoch")
+            .as_secs()
+            .try_into()
+            .expect("u32 until 2106");
+
+        Some(RescanStatus::new(
+            start_height,
+            target_height,
+            self.bitcoin.rescan_info(),
+            now,
+        ))
+    }
+
     /// list_confirmed_transactions retrieves a limited list of transactions which occured between two given dates.
+    ///
+    /// The list may be restricted to the transactions creating or spending any of the given
+    /// coins, or any coin paying to one of the given addresses.
     pub fn list_confirmed_transactions(
         &self,
         start: u32,
         end: u32,
         limit: u64,
+        outpoints: Option<&[bitcoin::OutPoint]>,
+        addresses: Option<&[bitcoin::Address]>,
     ) -> ListTransactionsResult {
         let mut db_conn = self.db.connection();
-        let txids = db_conn.list_txids(start, end, limit);
-        let transactions = txids
+        let txids = if outpoints.is_none() && addresses.is_none() {
+            db_conn.list_txids(start, end, limit)
+        } else {
+            // An address we don't know about can't be referenced by any of our coins.
+            let deriv_indexes: Vec<(bip32::ChildNumber, bool)> = addresses
+                .unwrap_or(&[])
+                .iter()
+                .filter_map(|addr| db_conn.derivation_index_by_address(addr))
+                .collect();
+            // Only query the referenced coins, the lookup by derivation index is indexed.
+            let mut coins = db_conn.coins_by_derivation_indexes(&deriv_indexes);
+            if let Some(outpoints) = outpoints.filter(|ops| !ops.is_empty()) {
+                coins.extend(db_conn.coins_by_outpoints(outpoints));
+            }
+            let mut times_and_txids = BTreeSet::new();
+            for coin in coins.values() {
+                if let Some(time) = coin.block_time {
+                    if time >= start && time <= end {
+                        times_and_txids.insert((time, coin.outpoint.txid));
+                    }
+                }
+                if let (Some(txid), Some(block)) = (coin.spend_txid, coin.spend_block) {
+                    if block.time >= start && block.time <= end {
+                        times_and_txids.insert((block.time, txid));
+                    }
+                }
+            }
+            // The limit must only be applied once we filtered the transactions.
+            times_and_txids
+                .into_iter()
+                .rev()
+                .take(limit as usize)
+                .map(|(_, txid)| txid)
+                .collect()
+        };
+        let transactions = self.transactions_info(db_conn.as_mut(), &txids);
+        ListTransactionsResult { transactions }
+    }
+
+    /// Get the transaction with this txid, along with its confirmation info if any.
+    pub fn get_transaction(&self, txid: &bitcoin::Txid) -> Option<TransactionInfo> {
+        let mut db_conn = self.db.connection();
+        self.transactions_info(db_conn.as_mut(), &[*txid]).pop()
+    }
+
+    /// Retrieve up to `limit` confirmed transactions strictly older than the given cursor, or
+    /// the most recent ones if there is none.
+    ///
+    /// Transactions are ordered by (block time, txid), from the most recent to the oldest, so
+    /// that passing the returned cursor to the next call lists the following page without
+    /// duplicates or gaps, even if several transactions share the same block time.
+    pub fn list_confirmed_transactions_page(
+        &self,
+        before: Option<(u32, bitcoin::Txid)>,
+        limit: u64,
+    ) -> ListConfirmedPageResult {
+        let times_and_txids = self.db.connection().list_txids_page(before, limit);
+
+        // If the page is full, there may be more transactions after the last one.
+        let next_cursor = if times_and_txids.len() as u64 == limit {
+            times_and_txids.last().copied()
+        } else {
+            None
+        };
+        let txids: Vec<bitcoin::Txid> = times_and_txids.into_iter().map(|(_, txid)| txid).collect();
+        let transactions = self.list_transactions(&txids).transactions;
+        ListConfirmedPageResult {
+            transactions,
+            next_cursor,
+        }
+    }
+
+    // Get the transactions with these txids from the Bitcoin backend. If we have a price feed,
+    // value the effect of the confirmed ones on our balance in fiat at the time they were mined.
+    fn transactions_info(
+        &self,
+        db_conn: &mut dyn DatabaseConnection,
+        txids: &[bitcoin::Txid],
+    ) -> Vec<TransactionInfo> {
+        let price_feed = self.price_feed.as_ref().map(|feed| feed.lock().unwrap());
+        let txs: Vec<TransactionInfo> = txids
             .iter()
             .filter_map(|txid| {
                 // TODO: batch those calls to the Bitcoin backend
@@ -609,44 +2003,83 @@ impl DaemonControl {
                         tx,
                         height: block.map(|b| b.height),
                         time: block.map(|b| b.time),
+                        fiat_value: None,
+                        is_wallet_tx: false,
                     })
             })
             .collect();
-        ListTransactionsResult { transactions }
+
+        // A transaction may only create coins at its outputs and spend coins at its inputs, so
+        // only query those.
+        let mut outpoints = Vec::new();
+        for tx_info in txs.iter() {
+            let txid = tx_info.tx.txid();
+            outpoints.extend(tx_info.tx.input.iter().map(|txin| txin.previous_output));
+            outpoints.extend(
+                (0..tx_info.tx.output.len()).map(|vout| bitcoin::OutPoint::new(txid, vout as u32)),
+            );
+        }
+        let coins = db_conn.coins_by_outpoints(&outpoints);
+
+        txs.into_iter()
+            .map(|mut tx_info| {
+                let txid = tx_info.tx.txid();
+                let created: Vec<&Coin> = (0..tx_info.tx.output.len())
+                    .filter_map(|vout| coins.get(&bitcoin::OutPoint::new(txid, vout as u32)))
+                    .collect();
+                let spent: Vec<&Coin> = tx_info
+                    .tx
+                    .input
+                    .iter()
+                    .filter_map(|txin| coins.get(&txin.previous_output))
+                    .filter(|coin| coin.spend_txid == Some(txid))
+                    .collect();
+                tx_info.is_wallet_tx = !created.is_empty() || !spent.is_empty();
+                if let (Some(feed), Some(time)) = (&price_feed, tx_info.time) {
+                    let received: i64 = created.iter().map(|c| c.amount.to_sat() as i64).sum();
+                    let sent: i64 = spent.iter().map(|c| c.amount.to_sat() as i64).sum();
+                    let net_sats = received - sent;
+                    tx_info.fiat_value = feed
+                        .price_at(time)
+                        .map(|price| bitcoin::SignedAmount::from_sat(net_sats).to_btc() * price);
+                }
+                tx_info
+            })
+            .collect()
     }
 
     /// list_transactions retrieves the transactions with the given txids.
     pub fn list_transactions(&self, txids: &[bitcoin::Txid]) -> ListTransactionsResult {
-        let transactions = txids
-            .iter()
-            .filter_map(|txid| {
-                // TODO: batch those calls to the Bitcoin backend
-                // so it can in turn optimize its queries.
-                self.bitcoin
-                    .wallet_transaction(txid)
-                    .map(|(tx, block)| TransactionInfo {
-                        tx,
-                        height: block.map(|b| b.height),
-                        time: block.map(|b| b.time),
-                    })
-            })
-            .collect();
+        let mut db_conn = self.db.connection();
+        let transactions = self.transactions_info(db_conn.as_mut(), txids);
         ListTransactionsResult { transactions }
     }
 
     /// Create a transaction that sweeps all coins whose timelocked recovery path is currently
     /// available to a provided address with the provided feerate.
     ///
+    /// The recovery path to use is selected by its `timelock`. If not given, the one with the
+    /// shortest timelock is used.
+    ///
     /// Note that not all coins may be spendable through the recovery path at the same time.
     pub fn create_recovery(
         &self,
         address: bitcoin::Address,
         feerate_vb: u64,
+        timelock: Option<u16>,
     ) -> Result<CreateRecoveryResult, CommandError> {
         if feerate_vb < 1 {
             return Err(CommandError::InvalidFeerate(feerate_vb));
         }
         self.validate_address(&address)?;
+        let timelocks = self.config.main_descriptor.timelock_values();
+        let csv_value = match timelock {
+            Some(timelock) if !timelocks.contains(&timelock) => {
+                return Err(CommandError::UnknownRecoveryPath(timelock))
+            }
+            Some(timelock) => timelock,
+            None => timelocks[0],
+        };
         let mut db_conn = self.db.connection();
 
         // The transaction template. We'll fill-in the inputs afterward.
@@ -670,25 +2103,19 @@ impl DaemonControl {
 
         // Query the coins that we can spend through the recovery path from the database.
         let current_height = self.bitcoin.chain_tip().height;
-        let desc_timelock = self.config.main_descriptor.timelock_value();
-        let timelock: i32 = desc_timelock
-            .try_into()
-            .expect("Must fit, it's effectively a u16");
         let sweepable_coins = db_conn
             .coins(CoinType::Unspent)
             .into_iter()
             .filter(|(_, c)| {
-                // We are interested in coins available at the *next* block
-                c.block_height
-                    .map(|h| current_height + 1 >= h + timelock)
-                    .unwrap_or(false)
+                // We are interested in coins available at the *next* block. Frozen coins are
+                // never swept.
+                !c.is_frozen
+                    && blocks_until_recovery(current_height, c.block_height, csv_value.into())
+                        == Some(0)
             });
 
         // Fill-in the transaction inputs and PSBT inputs information. Record the value
         // that is fed to the transaction while doing so, to compute the fees afterward.
-        let csv_value: u16 = desc_timelock
-            .try_into()
-            .expect("Must fit, it's effectively a u16");
         let mut in_value = bitcoin::Amount::from_sat(0);
         let txin_sat_vb = self.config.main_descriptor.max_sat_vbytes();
         let mut sat_vb = 0;
@@ -703,30 +2130,30 @@ impl DaemonControl {
             });
 
             // Fetch the transaction that created this coin if necessary
-            if let hash_map::Entry::Vacant(e) = spent_txs.entry(coin.outpoint) {
-                let tx = self
-                    .bitcoin
-                    .wallet_transaction(&coin.outpoint.txid)
-                    .ok_or(CommandError::FetchingTransaction(coin.outpoint))?;
-                e.insert(tx.0);
+            if self.config.bitcoin_config.psbt_non_witness_utxo {
+                if let hash_map::Entry::Vacant(e) = spent_txs.entry(coin.outpoint) {
+                    let tx = self
+                        .bitcoin
+                        .wallet_transaction(&coin.outpoint.txid)
+                        .ok_or(CommandError::FetchingTransaction(coin.outpoint))?;
+                    e.insert(tx.0);
+                }
             }
 
             let coin_desc = self.derived_desc(&coin);
             sat_vb += txin_sat_vb;
-            let witness_script = Some(coin_desc.witness_script());
             let witness_utxo = Some(bitcoin::TxOut {
                 value: coin.amount.to_sat(),
                 script_pubkey: coin_desc.script_pubkey(),
             });
             let non_witness_utxo = spent_txs.get(&coin.outpoint).cloned();
-            let bip32_derivation = coin_desc.bip32_derivations();
-            psbt.inputs.push(PsbtIn {
-                witness_script,
+            let mut psbt_in = PsbtIn {
                 witness_utxo,
                 non_witness_utxo,
-                bip32_derivation,
                 ..PsbtIn::default()
-            });
+            };
+            coin_desc.update_psbt_in(&mut psbt_in);
+            psbt.inputs.push(psbt_in);
         }
 
         // The sweepable_coins iterator may have been empty.
@@ -742,7 +2169,7 @@ impl DaemonControl {
         })?;
         psbt.unsigned_tx.output[0].value = output_value.to_sat();
 
-        sanity_check_psbt(&psbt)?;
+        sanity_check_psbt(&self.config.bitcoin_config, &psbt)?;
 
         Ok(CreateRecoveryResult { psbt })
     }
@@ -751,6 +2178,28 @@ impl DaemonControl {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GetInfoDescriptors {
     pub main: descriptors::MultipathDescriptor,
+    /// Checksum of the main descriptor.
+    pub checksum: String,
+    /// Origin of all the keys in the main descriptor.
+    pub keys: Vec<KeyOrigin>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListDescriptorsResult {
+    /// The descriptor for receive addresses.
+    pub receive: descriptors::InheritanceDescriptor,
+    /// The descriptor for change addresses.
+    pub change: descriptors::InheritanceDescriptor,
+}
+
+/// Where a key in the descriptor comes from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyOrigin {
+    /// Fingerprint of the master key.
+    pub fingerprint: bip32::Fingerprint,
+    /// Derivation path from the master key to the xpub.
+    pub derivation_path: bip32::DerivationPath,
+    pub xpub: bip32::ExtendedPubKey,
 }
 
 /// Information about the daemon
@@ -763,11 +2212,99 @@ pub struct GetInfoResult {
     pub descriptors: GetInfoDescriptors,
     /// The progress as a percentage (between 0 and 1) of an ongoing rescan if there is any
     pub rescan_progress: Option<f64>,
+    /// Timestamp of the creation of the wallet, before which it is assumed to have no transaction
+    pub wallet_birthday: u32,
+    /// Whether one of our coins can be spent through the recovery path in the next block.
+    pub recovery_available: bool,
+    /// The height of the first block in which our oldest confirmed coin can be spent through the
+    /// recovery path. `None` if we don't have any confirmed coin.
+    pub recovery_height: Option<i32>,
+    /// The same information for each of the recovery paths of our descriptor, from the shortest
+    /// to the longest timelock. The fields above are about the first one.
+    #[serde(default)]
+    pub recovery_paths: Vec<GetInfoRecoveryPath>,
+}
+
+/// Whether our coins can be spent through one of the recovery paths of our descriptor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetInfoRecoveryPath {
+    /// The relative timelock of this recovery path, in blocks.
+    pub timelock: u16,
+    /// Whether one of our coins can be spent through this path in the next block.
+    pub available: bool,
+    /// The height of the first block in which our oldest confirmed coin can be spent through
+    /// this path. `None` if we don't have any confirmed coin.
+    pub height: Option<i32>,
+}
+
+/// Detailed progress of an ongoing rescan.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RescanStatus {
+    /// The progress as a percentage (between 0 and 1).
+    pub progress: f64,
+    /// The height the rescan started from.
+    pub start_height: i32,
+    /// The (estimated) height of the block currently being scanned.
+    pub current_height: i32,
+    /// The height the rescan will stop at.
+    pub target_height: i32,
+    /// The estimated timestamp at which the rescan will complete, derived from the scan rate so
+    /// far. `None` if it can't be estimated yet.
+    pub eta: Option<u32>,
+}
+
+impl RescanStatus {
+    /// Compute the status of a rescan from the information reported by the backend. If the
+    /// backend doesn't report a rescan anymore, it completed and we just didn't notice yet.
+    fn new(
+        start_height: i32,
+        target_height: i32,
+        info: Option<RescanInfo>,
+        now: u32,
+    ) -> RescanStatus {
+        let (progress, duration) = info
+            .map(|info| (info.progress.max(0.0).min(1.0), info.duration))
+            .unwrap_or((1.0, 0));
+        let current_height =
+            start_height + ((target_height - start_height) as f64 * progress).round() as i32;
+        let eta = if progress >= 1.0 {
+            Some(now)
+        } else if progress > 0.0 && duration > 0 {
+            let remaining = duration as f64 * (1.0 - progress) / progress;
+            Some(now.saturating_add(remaining.round() as u32))
+        } else {
+            None
+        };
+
+        RescanStatus {
+            progress,
+            start_height,
+            current_height,
+            target_height,
+            eta,
+        }
+    }
+}
+
+/// The full derivation path of a key used in an address.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyDerivation {
+    /// Fingerprint of the master key.
+    pub fingerprint: bip32::Fingerprint,
+    /// Derivation path from the master key to the key used in the address.
+    pub derivation_path: bip32::DerivationPath,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GetAddressResult {
     pub address: bitcoin::Address,
+    /// The derivation index of this address on the receive chain.
+    pub derivation_index: u32,
+    /// The full derivation path of each key used in this address.
+    pub derivation_paths: Vec<KeyDerivation>,
+    /// Whether this address already received coins. Reusing an address harms privacy.
+    #[serde(default)]
+    pub is_reused: bool,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -777,17 +2314,76 @@ pub struct LCSpendInfo {
     pub height: Option<i32>,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-pub struct ListCoinsEntry {
-    #[serde(
-        serialize_with = "ser_amount",
-        deserialize_with = "deser_amount_from_sats"
-    )]
+/// How far a coin is from being spendable through one of the recovery paths of our descriptor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LCRecoveryPath {
+    /// The relative timelock of this recovery path, in blocks.
+    pub timelock: u16,
+    /// Number of blocks until the coin is spendable through this path. `0` if it already is,
+    /// `None` if the coin is unconfirmed.
+    pub blocks_until_recovery: Option<i32>,
+}
+
+/// Whether a coin was spent, and if so whether the spending transaction is confirmed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpendStatus {
+    NotSpent,
+    /// The spending transaction was broadcast but isn't confirmed yet.
+    Broadcast,
+    Confirmed,
+}
+
+// For the entries of daemons which didn't report the spend status yet. Not derived, as
+// `#[default]` isn't available with our MSRV.
+#[allow(clippy::derivable_impls)]
+impl Default for SpendStatus {
+    fn default() -> Self {
+        Self::NotSpent
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ListCoinsEntry {
+    #[serde(
+        serialize_with = "ser_amount",
+        deserialize_with = "deser_amount_from_sats"
+    )]
     pub amount: bitcoin::Amount,
     pub outpoint: bitcoin::OutPoint,
     pub block_height: Option<i32>,
+    /// Number of confirmations of the transaction creating this coin, `0` if unconfirmed.
+    #[serde(default)]
+    pub confirmations: u32,
+    /// The maximum size in WU of a satisfaction for this coin.
+    #[serde(default)]
+    pub sat_weight: u64,
     /// Information about the transaction spending this coin.
     pub spend_info: Option<LCSpendInfo>,
+    #[serde(default)]
+    pub spend_status: SpendStatus,
+    /// Number of blocks until this coin is spendable through the recovery path. `0` if it
+    /// already is, `None` if the coin is unconfirmed.
+    pub blocks_until_recovery: Option<i32>,
+    /// The same information for each of the recovery paths of our descriptor, from the shortest
+    /// to the longest timelock. `blocks_until_recovery` is about the first one.
+    #[serde(default)]
+    pub recovery_paths: Vec<LCRecoveryPath>,
+    /// Whether this coin was frozen by the user.
+    #[serde(default)]
+    pub frozen: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HwToken {
+    pub fingerprint: bip32::Fingerprint,
+    #[serde(serialize_with = "ser_hex", deserialize_with = "deser_hex")]
+    pub token: [u8; 32],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListHwTokensResult {
+    pub tokens: Vec<HwToken>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -801,10 +2397,71 @@ pub struct CreateSpendResult {
     pub psbt: Psbt,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UpdateSpendResult {
+    /// Human readable warnings about this Spend transaction, such as paying to an address which
+    /// was already used.
+    pub warnings: Vec<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ListSpendEntry {
     #[serde(serialize_with = "ser_base64", deserialize_with = "deser_base64")]
     pub psbt: Psbt,
+    /// Whether a coin spent by this transaction was spent by another confirmed transaction.
+    pub conflicted: bool,
+    /// The last time this Spend transaction was stored or updated.
+    pub updated_at: u32,
+    /// The decoded content of this Spend transaction.
+    #[serde(default)]
+    pub info: SpendTxInfo,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpendTxInput {
+    pub outpoint: bitcoin::OutPoint,
+    /// The value of the spent coin in sats, `None` if unknown.
+    pub value: Option<u64>,
+    /// Whether the spent coin is one of ours.
+    pub is_ours: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpendTxOutput {
+    /// The address this output pays to, `None` for a non-standard Script (such as OP_RETURN).
+    pub address: Option<bitcoin::Address>,
+    /// The value of this output in sats.
+    pub value: u64,
+    /// Whether this output is our change.
+    pub is_change: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputSigningStatus {
+    /// The number of signatures present for this input by keys of the primary path.
+    pub signatures: usize,
+    /// The number of signatures needed to spend this input through the primary path.
+    pub threshold: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SigningStatus {
+    /// The signing status of each input.
+    pub inputs: Vec<InputSigningStatus>,
+    /// Whether all inputs have enough signatures to be finalized through the primary path.
+    pub finalizable: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpendTxInfo {
+    pub inputs: Vec<SpendTxInput>,
+    pub outputs: Vec<SpendTxOutput>,
+    /// The absolute fee in sats, `None` if the value of an input is unknown.
+    pub fee: Option<u64>,
+    /// The feerate in sats/vb, estimated using the maximum size of the satisfaction of the
+    /// inputs. `None` if the fee is unknown.
+    pub feerate_vb: Option<u64>,
+    pub signing_status: SigningStatus,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -823,6 +2480,20 @@ pub struct TransactionInfo {
     pub tx: bitcoin::Transaction,
     pub height: Option<i32>,
     pub time: Option<u32>,
+    /// The value of this transaction for our wallet in fiat currency at the time it was
+    /// confirmed, if a price feed was configured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fiat_value: Option<f64>,
+    /// Whether this transaction creates or spends one of our coins.
+    #[serde(default)]
+    pub is_wallet_tx: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListConfirmedPageResult {
+    pub transactions: Vec<TransactionInfo>,
+    /// The (block time, txid) cursor to pass to get the next page, if there may be one.
+    pub next_cursor: Option<(u32, bitcoin::Txid)>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -841,189 +2512,3060 @@ mod tests {
         util::bip32::ChildNumber,
         OutPoint, PackedLockTime, Script, Sequence, Transaction, Txid, Witness,
     };
-    use std::str::FromStr;
+    use std::{collections::HashSet, str::FromStr};
 
     use bitcoin::util::bip32;
 
     #[test]
-    fn getinfo() {
+    fn start_rescan() {
+        // Someone else triggered a rescan of the backend right after we checked it wasn't
+        // rescanning. We must gracefully tell the user.
+        let mut bitcoind = DummyBitcoind::new();
+        bitcoind.rescan_error = Some(RescanError::AlreadyRescanning);
+        let ms = DummyLiana::new(bitcoind, DummyDatabase::new());
+        let control = &ms.handle.control;
+        assert_eq!(
+            control.start_rescan(1_600_000_000, false),
+            Err(CommandError::AlreadyRescanning)
+        );
+        // We didn't record a rescan that we didn't start.
+        assert!(control
+            .db()
+            .lock()
+            .unwrap()
+            .connection()
+            .rescan_timestamp()
+            .is_none());
+        ms.shutdown();
+
+        // Any other error is reported as such.
+        let mut bitcoind = DummyBitcoind::new();
+        bitcoind.rescan_error = Some(RescanError::Other("Oops".to_string()));
+        let ms = DummyLiana::new(bitcoind, DummyDatabase::new());
+        assert_eq!(
+            ms.handle.control.start_rescan(1_600_000_000, false),
+            Err(CommandError::RescanTrigger("Oops".to_string()))
+        );
+        ms.shutdown();
+
+        // Otherwise it's started, and can't be started twice.
         let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
-        // We can query getinfo
-        ms.handle.control.get_info();
+        let control = &ms.handle.control;
+        control.start_rescan(1_600_000_000, false).unwrap();
+        assert_eq!(
+            control.start_rescan(1_600_000_000, false),
+            Err(CommandError::AlreadyRescanning)
+        );
         ms.shutdown();
     }
 
     #[test]
-    fn getnewaddress() {
+    fn cancel_rescan() {
+        // There is nothing to cancel.
         let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        assert_eq!(
+            ms.handle.control.cancel_rescan(),
+            Err(CommandError::NoOngoingRescan)
+        );
+        ms.shutdown();
 
+        // Cancelling an ongoing rescan aborts it on the backend and drops its timestamp.
+        let mut bitcoind = DummyBitcoind::new();
+        bitcoind.rescan_info = Some(RescanInfo {
+            progress: 0.5,
+            duration: 60,
+        });
+        let rescan_aborted = bitcoind.rescan_aborted.clone();
+        let db = DummyDatabase::new();
+        db.connection().set_rescan(1_600_000_000);
+        let ms = DummyLiana::new(bitcoind, db);
         let control = &ms.handle.control;
-        // We can get an address
-        let addr = control.get_new_address().address;
-        assert_eq!(
-            addr,
-            bitcoin::Address::from_str(
-                "bc1q9ksrc647hx8zp2cewl8p5f487dgux3777yees8rjcx46t4daqzzqt7yga8"
-            )
+        control.cancel_rescan().unwrap();
+        assert!(rescan_aborted.load(std::sync::atomic::Ordering::Relaxed));
+        assert!(control
+            .db()
+            .lock()
             .unwrap()
-        );
-        // We won't get the same twice.
-        let addr2 = control.get_new_address().address;
-        assert_ne!(addr, addr2);
+            .connection()
+            .rescan_timestamp()
+            .is_none());
+        assert!(control.rescan_status().is_none());
+        assert_eq!(control.cancel_rescan(), Err(CommandError::NoOngoingRescan));
+        ms.shutdown();
+    }
+
+    #[test]
+    fn start_rescan_wallet_birthday() {
+        let birthday = 1_600_000_000;
+        let mut db = DummyDatabase::new();
+        db.set_wallet_timestamp(birthday);
+        let ms = DummyLiana::new(DummyBitcoind::new(), db);
+        let control = &ms.handle.control;
+        let rescan_timestamp = || control.db().lock().unwrap().connection().rescan_timestamp();
+
+        // A timestamp earlier than the wallet birthday is clamped to it.
+        control.start_rescan(birthday - 1_000, true).unwrap();
+        assert_eq!(rescan_timestamp(), Some(birthday));
+        control.db().lock().unwrap().connection().complete_rescan();
+
+        // A later one is left untouched.
+        control.start_rescan(birthday + 1_000, true).unwrap();
+        assert_eq!(rescan_timestamp(), Some(birthday + 1_000));
+        control.db().lock().unwrap().connection().complete_rescan();
+
+        // Without the flag we rescan from the requested timestamp.
+        control.start_rescan(birthday - 1_000, false).unwrap();
+        assert_eq!(rescan_timestamp(), Some(birthday - 1_000));
 
         ms.shutdown();
     }
 
     #[test]
-    fn create_spend() {
-        let dummy_op = bitcoin::OutPoint::from_str(
-            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
-        )
-        .unwrap();
-        let mut dummy_bitcoind = DummyBitcoind::new();
-        dummy_bitcoind.txs.insert(
-            dummy_op.txid,
+    fn start_rescan_genesis_time() {
+        let networks = [
+            (bitcoin::Network::Bitcoin, 1_231_006_505),
+            (bitcoin::Network::Testnet, 1_296_688_602),
+            (bitcoin::Network::Signet, 1_598_918_400),
+            (bitcoin::Network::Regtest, 1_296_688_602),
+        ];
+        for (network, genesis) in &networks {
+            let genesis_hash = bitcoin::blockdata::constants::genesis_block(*network).block_hash();
+            assert_eq!(genesis_time(&genesis_hash), Some(*genesis));
+
+            // We can't rescan from before the genesis block of the backend's chain.
+            let ms = DummyLiana::builder()
+                .network(*network)
+                .bitcoind(DummyBitcoind::with_network(*network))
+                .build();
+            let control = &ms.handle.control;
+            assert_eq!(
+                control.start_rescan(genesis - 1, false),
+                Err(CommandError::InsaneRescanTimestamp(genesis - 1))
+            );
+            control.start_rescan(*genesis, false).unwrap();
+            ms.shutdown();
+        }
+
+        // The regtest genesis block is more recent than the mainnet one. A timestamp before it is
+        // fine on mainnet.
+        let ms = DummyLiana::builder().build();
+        ms.handle
+            .control
+            .start_rescan(1_296_688_601, false)
+            .unwrap();
+        ms.shutdown();
+
+        // An unknown genesis block isn't in the lookup table.
+        assert!(genesis_time(&bitcoin::BlockHash::default()).is_none());
+    }
+
+    #[test]
+    fn rescan_status() {
+        // No rescan ongoing.
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        assert!(ms.handle.control.rescan_status().is_none());
+        ms.shutdown();
+
+        // The backend reports a rescan from height 20 to the tip (100) a quarter done after a
+        // minute. The remaining three quarters should take three minutes.
+        let mut bitcoind = DummyBitcoind::new();
+        bitcoind.rescan_info = Some(RescanInfo {
+            progress: 0.25,
+            duration: 60,
+        });
+        let rescan_timestamp = bitcoind.block_time(20) + 1;
+        let ms = DummyLiana::new(bitcoind, DummyDatabase::new());
+        let control = &ms.handle.control;
+        control
+            .db()
+            .lock()
+            .unwrap()
+            .connection()
+            .set_rescan(rescan_timestamp);
+        let before = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32;
+        let status = control.rescan_status().unwrap();
+        assert_eq!(status.progress, 0.25);
+        assert_eq!(
             (
-                bitcoin::Transaction {
-                    version: 2,
-                    lock_time: bitcoin::PackedLockTime(0),
-                    input: vec![],
-                    output: vec![],
-                },
-                None,
+                status.start_height,
+                status.current_height,
+                status.target_height
             ),
+            (20, 40, 100)
         );
-        let ms = DummyLiana::new(dummy_bitcoind, DummyDatabase::new());
-        let control = &ms.handle.control;
+        let eta = status.eta.unwrap();
+        assert!(eta >= before + 180 && eta <= before + 181);
+        // It's still exposed as a single percentage for compatibility.
+        assert_eq!(control.get_info().rescan_progress, Some(0.25));
+        ms.shutdown();
 
-        // Arguments sanity checking
-        let dummy_addr =
-            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
-        let dummy_value = 10_000;
-        let mut destinations: HashMap<bitcoin::Address, u64> = [(dummy_addr.clone(), dummy_value)]
-            .iter()
-            .cloned()
-            .collect();
+        // The ETA computation.
+        let info = |progress, duration| Some(RescanInfo { progress, duration });
         assert_eq!(
-            control.create_spend(&destinations, &[], 1),
-            Err(CommandError::NoOutpoint)
+            RescanStatus::new(0, 1000, info(0.5, 10), 1_000).eta,
+            Some(1_010)
         );
         assert_eq!(
-            control.create_spend(&HashMap::new(), &[dummy_op], 1),
-            Err(CommandError::NoDestination)
+            RescanStatus::new(0, 1000, info(0.1, 10), 1_000).eta,
+            Some(1_090)
         );
+        // Can't estimate it before the rescan made any progress.
+        assert_eq!(RescanStatus::new(0, 1000, info(0.0, 10), 1_000).eta, None);
+        assert_eq!(RescanStatus::new(0, 1000, info(0.3, 0), 1_000).eta, None);
+        // The backend isn't rescanning anymore: it's done.
+        let status = RescanStatus::new(10, 1000, None, 1_000);
         assert_eq!(
-            control.create_spend(&destinations, &[dummy_op], 0),
-            Err(CommandError::InvalidFeerate(0))
+            (status.progress, status.current_height, status.eta),
+            (1.0, 1000, Some(1_000))
         );
+    }
 
-        // The coin doesn't exist. If we create a new unspent one at this outpoint with a much
-        // higher value, we'll get a Spend transaction with a change output.
+    #[test]
+    fn getinfo_network() {
+        let ms = DummyLiana::builder()
+            .network(bitcoin::Network::Regtest)
+            .build();
         assert_eq!(
-            control.create_spend(&destinations, &[dummy_op], 1),
-            Err(CommandError::UnknownOutpoint(dummy_op))
+            ms.handle.control.get_info().network,
+            bitcoin::Network::Regtest
         );
-        let mut db_conn = control.db().lock().unwrap().connection();
-        db_conn.new_unspent_coins(&[Coin {
-            outpoint: dummy_op,
-            block_height: None,
-            block_time: None,
-            amount: bitcoin::Amount::from_sat(100_000),
-            derivation_index: bip32::ChildNumber::from(13),
-            is_change: false,
-            spend_txid: None,
-            spend_block: None,
-        }]);
-        let res = control.create_spend(&destinations, &[dummy_op], 1).unwrap();
-        assert!(res.psbt.inputs[0].non_witness_utxo.is_some());
-        let tx = res.psbt.unsigned_tx;
-        assert_eq!(tx.input.len(), 1);
-        assert_eq!(tx.input[0].previous_output, dummy_op);
-        assert_eq!(tx.output.len(), 2);
-        assert_eq!(tx.output[0].script_pubkey, dummy_addr.script_pubkey());
-        assert_eq!(tx.output[0].value, dummy_value);
-
-        // Transaction is 1 in (P2WSH satisfaction), 2 outs. At 1sat/vb, it's 171 sats fees.
-        // At 2sats/vb, it's twice that.
-        assert_eq!(tx.output[1].value, 89_829);
-        let res = control.create_spend(&destinations, &[dummy_op], 2).unwrap();
-        let tx = res.psbt.unsigned_tx;
-        assert_eq!(tx.output[1].value, 89_658);
+        ms.shutdown();
 
-        // If we ask for a too high feerate, or a too large/too small output, it'll fail.
-        assert_eq!(
-            control.create_spend(&destinations, &[dummy_op], 10_000),
-            Err(CommandError::InsufficientFunds(
-                bitcoin::Amount::from_sat(100_000),
-                bitcoin::Amount::from_sat(10_000),
-                10_000
-            ))
-        );
-        *destinations.get_mut(&dummy_addr).unwrap() = 100_001;
+        let ms = DummyLiana::builder().build();
         assert_eq!(
-            control.create_spend(&destinations, &[dummy_op], 1),
-            Err(CommandError::InsufficientFunds(
-                bitcoin::Amount::from_sat(100_000),
-                bitcoin::Amount::from_sat(100_001),
-                1
-            ))
-        );
-        *destinations.get_mut(&dummy_addr).unwrap() = 4_500;
-        assert_eq!(
-            control.create_spend(&destinations, &[dummy_op], 1),
-            Err(CommandError::InvalidOutputValue(bitcoin::Amount::from_sat(
-                4_500
-            )))
+            ms.handle.control.get_info().network,
+            bitcoin::Network::Bitcoin
         );
+        ms.shutdown();
+    }
 
-        // If we ask to create an output for an address from another network, it will fail.
-        let invalid_addr = bitcoin::Address {
-            network: bitcoin::Network::Testnet,
-            payload: dummy_addr.payload.clone(),
+    #[test]
+    fn address_network() {
+        let mainnet_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let addr_for = |network| bitcoin::Address {
+            network,
+            payload: mainnet_addr.payload.clone(),
         };
-        let invalid_destinations: HashMap<bitcoin::Address, u64> =
-            [(invalid_addr.clone(), dummy_value)]
-                .iter()
-                .cloned()
-                .collect();
+
+        // A mainnet wallet only accepts mainnet addresses.
+        let ms = DummyLiana::builder().build();
+        let control = &ms.handle.control;
+        control.validate_address(&mainnet_addr).unwrap();
+        for network in &[bitcoin::Network::Testnet, bitcoin::Network::Regtest] {
+            assert_eq!(
+                control.validate_address(&addr_for(*network)),
+                Err(CommandError::AddressNetwork(
+                    addr_for(*network),
+                    bitcoin::Network::Bitcoin
+                ))
+            );
+        }
+        ms.shutdown();
+
+        // And the other way around.
+        let ms = DummyLiana::builder()
+            .network(bitcoin::Network::Testnet)
+            .build();
         assert_eq!(
-            control.create_spend(&invalid_destinations, &[dummy_op], 1),
+            ms.handle.control.validate_address(&mainnet_addr),
             Err(CommandError::AddressNetwork(
-                invalid_addr,
-                bitcoin::Network::Bitcoin
+                mainnet_addr.clone(),
+                bitcoin::Network::Testnet
             ))
         );
+        ms.shutdown();
+
+        // Signet uses testnet addresses.
+        let ms = DummyLiana::builder()
+            .network(bitcoin::Network::Signet)
+            .build();
+        let control = &ms.handle.control;
+        control
+            .validate_address(&addr_for(bitcoin::Network::Testnet))
+            .unwrap();
+        assert!(control.validate_address(&mainnet_addr).is_err());
+        ms.shutdown();
+    }
+
+    #[test]
+    fn list_descriptors() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.handle.control;
+        let res = control.list_descriptors();
+        let (receive, change) = (res.receive.to_string(), res.change.to_string());
+
+        // Both carry a valid checksum.
+        for desc in &[&receive, &change] {
+            assert!(desc.contains('#'));
+            miniscript::Descriptor::<miniscript::DescriptorPublicKey>::from_str(desc).unwrap();
+        }
+        let (receive, change) = (
+            receive.split('#').next().unwrap(),
+            change.split('#').next().unwrap(),
+        );
 
-        // If we ask for a large, but valid, output we won't get a change output. 95_000 because we
-        // won't create an output lower than 5k sats.
-        *destinations.get_mut(&dummy_addr).unwrap() = 95_000;
-        let res = control.create_spend(&destinations, &[dummy_op], 1).unwrap();
-        let tx = res.psbt.unsigned_tx;
-        assert_eq!(tx.input.len(), 1);
-        assert_eq!(tx.input[0].previous_output, dummy_op);
-        assert_eq!(tx.output.len(), 1);
-        assert_eq!(tx.output[0].script_pubkey, dummy_addr.script_pubkey());
-        assert_eq!(tx.output[0].value, 95_000);
+        // They only differ in how the multipath step of the main descriptor is resolved.
+        let main = control.config.main_descriptor.to_string();
+        let main = main.split('#').next().unwrap();
+        assert!(main.contains("<0;1>"));
+        assert_eq!(receive, main.replace("<0;1>", "0"));
+        assert_eq!(change, main.replace("<0;1>", "1"));
+        assert_ne!(receive, change);
 
-        // Now if we mark the coin as spent, we won't create another Spend transaction containing
-        // it.
-        db_conn.spend_coins(&[(
-            dummy_op,
-            bitcoin::Txid::from_str(
-                "ef78f79ba747813887747cf8582897a48f1a09f1ca04d2cd3d6fcfdcbb5e0797",
-            )
-            .unwrap(),
-        )]);
+        ms.shutdown();
+    }
+
+    #[test]
+    fn getinfo() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        // We can query getinfo
+        ms.handle.control.get_info();
+        ms.shutdown();
+
+        // The wallet birthday is the timestamp stored in database at creation.
+        let mut db = DummyDatabase::new();
+        db.set_wallet_timestamp(1_600_000_000);
+        let ms = DummyLiana::new(DummyBitcoind::new(), db);
+        assert_eq!(ms.handle.control.get_info().wallet_birthday, 1_600_000_000);
+        ms.shutdown();
+
+        // The descriptor checksum and the origins of its keys are returned.
+        let desc_str = "wsh(andor(pk([aabbccdd/48'/0'/0'/2']xpub68JJTXc1MWK8KLW4HGLXZBJknja7kDUJuFHnM424LbziEXsfkh1WQCiEjjHw4zLqSUm4rvhgyGkkuRowE9tCJSgt3TQB5J3SKAbZ2SdcKST/<0;1>/*),older(10000),pk([11223344/48'/0'/0'/2']xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*)))";
+        let desc = descriptors::MultipathDescriptor::from_str(desc_str).unwrap();
+        let ms = DummyLiana::with_descriptor(DummyBitcoind::new(), DummyDatabase::new(), desc);
+        let info = ms.handle.control.get_info();
         assert_eq!(
-            control.create_spend(&destinations, &[dummy_op], 1),
-            Err(CommandError::AlreadySpent(dummy_op))
+            info.descriptors.checksum,
+            info.descriptors.main.to_string().split('#').nth(1).unwrap()
+        );
+        let fingerprints: Vec<_> = info
+            .descriptors
+            .keys
+            .iter()
+            .map(|key| key.fingerprint)
+            .collect();
+        assert_eq!(
+            fingerprints,
+            vec![
+                bip32::Fingerprint::from_str("aabbccdd").unwrap(),
+                bip32::Fingerprint::from_str("11223344").unwrap()
+            ]
         );
+        assert!(info
+            .descriptors
+            .keys
+            .iter()
+            .all(|key| key.derivation_path
+                == bip32::DerivationPath::from_str("m/48'/0'/0'/2'").unwrap()));
+        ms.shutdown();
+    }
+
+    #[test]
+    fn getinfo_recovery_available() {
+        // Use a descriptor with a short timelock of 10 blocks. The dummy tip is at height 100.
+        let desc = descriptors::MultipathDescriptor::from_str("wsh(or_d(pk(xpub68JJTXc1MWK8KLW4HGLXZBJknja7kDUJuFHnM424LbziEXsfkh1WQCiEjjHw4zLqSUm4rvhgyGkkuRowE9tCJSgt3TQB5J3SKAbZ2SdcKST/<0;1>/*),and_v(v:pkh(xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*),older(10))))").unwrap();
+        let ms = DummyLiana::with_descriptor(DummyBitcoind::new(), DummyDatabase::new(), desc);
+        let control = &ms.handle.control;
+        let mut db_conn = control.db().lock().unwrap().connection();
+        let coin = |vout, block_height| Coin {
+            outpoint: bitcoin::OutPoint::new(
+                bitcoin::Txid::from_str(
+                    "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810",
+                )
+                .unwrap(),
+                vout,
+            ),
+            block_height,
+            block_time: block_height.map(|_| 1_111_899),
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(vout),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        };
+
+        // Without any confirmed coin, there is nothing to recover.
+        let info = control.get_info();
+        assert!(!info.recovery_available);
+        assert_eq!(info.recovery_height, None);
+        db_conn.new_unspent_coins(&[coin(0, None)]);
+        let info = control.get_info();
+        assert!(!info.recovery_available);
+        assert_eq!(info.recovery_height, None);
+
+        // A coin confirmed at height 95 can only be recovered in block 105.
+        db_conn.new_unspent_coins(&[coin(1, Some(95))]);
+        let info = control.get_info();
+        assert!(!info.recovery_available);
+        assert_eq!(info.recovery_height, Some(105));
+
+        // Once a coin is deep enough for the recovery path to be available in the next block,
+        // the flag is set. The oldest coin is reported.
+        db_conn.new_unspent_coins(&[coin(2, Some(91))]);
+        let info = control.get_info();
+        assert!(info.recovery_available);
+        assert_eq!(info.recovery_height, Some(101));
+        db_conn.new_unspent_coins(&[coin(3, Some(50))]);
+        let info = control.get_info();
+        assert!(info.recovery_available);
+        assert_eq!(info.recovery_height, Some(60));
+
+        // Frozen coins are never swept.
+        db_conn.set_frozen(&[coin(2, None).outpoint, coin(3, None).outpoint], true);
+        let info = control.get_info();
+        assert!(!info.recovery_available);
+        assert_eq!(info.recovery_height, Some(105));
 
         ms.shutdown();
     }
 
     #[test]
-    fn update_spend() {
+    fn estimate_feerate() {
+        // Without enough data, bitcoind won't give an estimate.
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.handle.control;
+        assert_eq!(
+            control.estimate_feerate(6),
+            Err(CommandError::FeeEstimation(6))
+        );
+        assert_eq!(
+            control.estimate_feerate(0),
+            Err(CommandError::InvalidFeeTarget(0))
+        );
+        ms.shutdown();
+
+        // The estimate in BTC/kvb is converted to sats/vb, rounded up.
+        let mut dummy_bitcoind = DummyBitcoind::new();
+        dummy_bitcoind.fee_estimate = Some(0.00012345);
+        let ms = DummyLiana::new(dummy_bitcoind, DummyDatabase::new());
+        assert_eq!(ms.handle.control.estimate_feerate(6), Ok(13));
+        ms.shutdown();
+
+        let mut dummy_bitcoind = DummyBitcoind::new();
+        dummy_bitcoind.fee_estimate = Some(0.0002);
+        let ms = DummyLiana::new(dummy_bitcoind, DummyDatabase::new());
+        assert_eq!(ms.handle.control.estimate_feerate(1), Ok(20));
+        ms.shutdown();
+    }
+
+    #[test]
+    fn getnewaddress() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+
+        let control = &ms.handle.control;
+        // We can get an address
+        let addr = control.get_new_address().address;
+        assert_eq!(
+            addr,
+            bitcoin::Address::from_str(
+                "bc1q9ksrc647hx8zp2cewl8p5f487dgux3777yees8rjcx46t4daqzzqt7yga8"
+            )
+            .unwrap()
+        );
+        // We won't get the same twice.
+        let addr2 = control.get_new_address().address;
+        assert_ne!(addr, addr2);
+
+        // The derivation index of the address is returned along with it, and is incremented
+        // between calls.
+        let res = control.get_new_address();
+        let res2 = control.get_new_address();
+        assert_eq!(res.derivation_index, 2);
+        assert_eq!(res2.derivation_index, res.derivation_index + 1);
+        // The full derivation path of each key is returned, down to the derivation index on
+        // the receive keychain.
+        assert_eq!(
+            res.derivation_paths.len(),
+            control
+                .config
+                .main_descriptor
+                .receive_descriptor()
+                .derive(res.derivation_index.into(), &control.secp)
+                .bip32_derivations()
+                .len()
+        );
+        for key in &res.derivation_paths {
+            let path: &[bip32::ChildNumber] = key.derivation_path.as_ref();
+            assert_eq!(
+                &path[path.len() - 2..],
+                &[bip32::ChildNumber::from(0), bip32::ChildNumber::from(2)]
+            );
+        }
+        let derived_addr = control
+            .config
+            .main_descriptor
+            .receive_descriptor()
+            .derive(res2.derivation_index.into(), &control.secp)
+            .address(control.config.bitcoin_config.network);
+        assert_eq!(res2.address, derived_addr);
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn getnewaddress_reuse() {
+        // The backend reports the first deposit address as already used, for instance because our
+        // derivation index was reset.
+        let addr = bitcoin::Address::from_str(
+            "bc1q9ksrc647hx8zp2cewl8p5f487dgux3777yees8rjcx46t4daqzzqt7yga8",
+        )
+        .unwrap();
+        let mut bitcoind = DummyBitcoind::new();
+        bitcoind.used_addresses.push(addr.clone());
+        let ms = DummyLiana::new(bitcoind, DummyDatabase::new());
+        let control = &ms.handle.control;
+
+        assert!(control.peek_address(None).unwrap().is_reused);
+        let res = control.get_new_address();
+        assert_eq!(res.address, addr);
+        assert!(res.is_reused);
+        assert!(!control.get_new_address().is_reused);
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn peek_address() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.handle.control;
+
+        // Peeking at the next address twice returns the same one, and doesn't use it up.
+        let addr = control.peek_address(None).unwrap().address;
+        assert_eq!(control.peek_address(None).unwrap().address, addr);
+        assert_eq!(
+            control.db().lock().unwrap().connection().receive_index(),
+            0.into()
+        );
+        assert_eq!(control.get_new_address().address, addr);
+
+        // Once allocated, the next address is the following one.
+        let addr2 = control.peek_address(None).unwrap().address;
+        assert_ne!(addr, addr2);
+        assert_eq!(control.peek_address(Some(0)).unwrap().address, addr);
+        assert_eq!(control.peek_address(Some(1)).unwrap().address, addr2);
+        assert_eq!(control.get_new_address().address, addr2);
+
+        // Peeking at a given index doesn't move the derivation index either.
+        control.peek_address(Some(42)).unwrap();
+        assert_eq!(
+            control.db().lock().unwrap().connection().receive_index(),
+            2.into()
+        );
+        assert!(matches!(
+            control.peek_address(Some(1 << 31)),
+            Err(CommandError::InvalidDerivationIndex(_))
+        ));
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn list_coins() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.handle.control;
+        assert!(control.list_coins(&[], &[]).coins.is_empty());
+
+        // The dummy tip is at height 100 and the descriptor's timelock is 10_000 blocks.
+        let dummy_txid =
+            Txid::from_str("3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810")
+                .unwrap();
+        let coin = |vout: u32, block_height: Option<i32>| Coin {
+            outpoint: OutPoint::new(dummy_txid, vout),
+            block_height,
+            block_time: block_height.map(|_| 1_111_899),
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: ChildNumber::from(vout),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        };
+        let mut db_conn = control.db().lock().unwrap().connection();
+        db_conn.new_unspent_coins(&[coin(0, None), coin(1, Some(100)), coin(2, Some(42))]);
+
+        let coins: HashMap<OutPoint, ListCoinsEntry> = control
+            .list_coins(&[], &[])
+            .coins
+            .into_iter()
+            .map(|c| (c.outpoint, c))
+            .collect();
+        assert_eq!(coins.len(), 3);
+        assert_eq!(
+            coins[&OutPoint::new(dummy_txid, 0)].blocks_until_recovery,
+            None
+        );
+        // Confirmed in the tip block, it needs 9_999 more blocks to be spendable by the heir at
+        // the next block.
+        assert_eq!(
+            coins[&OutPoint::new(dummy_txid, 1)].blocks_until_recovery,
+            Some(9_999)
+        );
+        assert_eq!(
+            coins[&OutPoint::new(dummy_txid, 2)].blocks_until_recovery,
+            Some(9_941)
+        );
+        assert_eq!(coins[&OutPoint::new(dummy_txid, 0)].confirmations, 0);
+        assert_eq!(coins[&OutPoint::new(dummy_txid, 1)].confirmations, 1);
+        assert_eq!(coins[&OutPoint::new(dummy_txid, 2)].confirmations, 59);
+
+        // All coins have the same worst case satisfaction size as the descriptor.
+        let sat_weight = control.config.main_descriptor.max_sat_weight() as u64;
+        assert!(coins.values().all(|c| c.sat_weight == sat_weight));
+        assert_eq!(
+            control.coin_sat_weight(&OutPoint::new(dummy_txid, 1)),
+            Some(sat_weight)
+        );
+        assert_eq!(
+            (sat_weight as usize + 3) / 4,
+            control.config.main_descriptor.max_sat_vbytes()
+        );
+        assert_eq!(control.coin_sat_weight(&OutPoint::new(dummy_txid, 3)), None);
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn list_coins_page() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.handle.control;
+
+        let dummy_txid =
+            Txid::from_str("3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810")
+                .unwrap();
+        let coins: Vec<Coin> = (0..1_000)
+            .map(|vout: u32| Coin {
+                outpoint: OutPoint::new(dummy_txid, vout),
+                block_height: if vout % 2 == 0 { Some(42) } else { None },
+                block_time: if vout % 2 == 0 { Some(1_111_899) } else { None },
+                amount: bitcoin::Amount::from_sat(100_000),
+                derivation_index: ChildNumber::from(vout),
+                is_change: false,
+                spend_txid: None,
+                spend_block: None,
+                spend_broadcast_time: None,
+                is_frozen: false,
+            })
+            .collect();
+        let mut db_conn = control.db().lock().unwrap().connection();
+        db_conn.new_unspent_coins(&coins);
+
+        // Pages are disjoint and together they are the whole listing, filtered or not.
+        for statuses in &[vec![], vec![CoinStatus::Confirmed]] {
+            let all: HashSet<OutPoint> = control
+                .list_coins(statuses, &[])
+                .coins
+                .into_iter()
+                .map(|c| c.outpoint)
+                .collect();
+            let mut paged = HashSet::new();
+            let mut start = 0;
+            loop {
+                let page = control.list_coins_page(statuses, &[], start, 64).coins;
+                assert!(page.len() <= 64);
+                if page.is_empty() {
+                    break;
+                }
+                for coin in page {
+                    assert!(paged.insert(coin.outpoint));
+                }
+                start += 64;
+            }
+            assert_eq!(paged, all);
+        }
+        assert_eq!(control.list_coins(&[], &[]).coins.len(), 1_000);
+        assert_eq!(
+            control
+                .list_coins_page(&[CoinStatus::Unconfirmed], &[], 480, 100)
+                .coins
+                .len(),
+            20
+        );
+        let outpoints = [OutPoint::new(dummy_txid, 1), OutPoint::new(dummy_txid, 2)];
+        assert_eq!(
+            control.list_coins_page(&[], &outpoints, 0, 10).coins.len(),
+            2
+        );
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn get_coins() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.handle.control;
+
+        let dummy_txid =
+            Txid::from_str("3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810")
+                .unwrap();
+        let coin = |vout: u32| Coin {
+            outpoint: OutPoint::new(dummy_txid, vout),
+            block_height: Some(42),
+            block_time: Some(1_111_899),
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: ChildNumber::from(vout),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        };
+        let mut db_conn = control.db().lock().unwrap().connection();
+        db_conn.new_unspent_coins(&[coin(0), coin(1), coin(2)]);
+
+        // Only the known coins among the requested ones are returned.
+        let (op_a, op_b) = (OutPoint::new(dummy_txid, 0), OutPoint::new(dummy_txid, 2));
+        let unknown_op = OutPoint::new(dummy_txid, 3);
+        let mut outpoints: Vec<OutPoint> = control
+            .get_coins(&[op_b, unknown_op, op_a])
+            .coins
+            .into_iter()
+            .map(|c| c.outpoint)
+            .collect();
+        outpoints.sort();
+        assert_eq!(outpoints, vec![op_a, op_b]);
+        assert!(control.get_coins(&[unknown_op]).coins.is_empty());
+        assert!(control.get_coins(&[]).coins.is_empty());
+
+        // The entries are the same as the ones returned by listcoins.
+        assert_eq!(
+            serde_json::json!(control.get_coins(&[op_a])),
+            serde_json::json!(control.list_coins(&[], &[op_a]))
+        );
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn list_coins_spend_status() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.handle.control;
+        let op = OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let spend_txid =
+            Txid::from_str("0c62a990d20d54429e70859292e82374ba6b1b951a3ab60f26bb65fee5724ff7")
+                .unwrap();
+        let mut db_conn = control.db().lock().unwrap().connection();
+        db_conn.new_unspent_coins(&[Coin {
+            outpoint: op,
+            block_height: Some(90),
+            block_time: Some(1_111_899),
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: ChildNumber::from(0),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        }]);
+        let status = || control.list_coins(&[], &[]).coins[0].spend_status;
+
+        assert_eq!(status(), SpendStatus::NotSpent);
+        db_conn.spend_coins(&[(op, spend_txid)]);
+        assert_eq!(status(), SpendStatus::Broadcast);
+        db_conn.confirm_spend(&[(op, spend_txid, 95, 1_112_000)]);
+        assert_eq!(status(), SpendStatus::Confirmed);
+
+        // It may be absent from the entries of previous versions.
+        let mut entry = serde_json::to_value(&control.list_coins(&[], &[]).coins[0]).unwrap();
+        assert_eq!(entry["spend_status"], "confirmed");
+        entry.as_object_mut().unwrap().remove("spend_status");
+        let entry: ListCoinsEntry = serde_json::from_value(entry).unwrap();
+        assert_eq!(entry.spend_status, SpendStatus::NotSpent);
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn list_coins_filters() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.handle.control;
+
+        // One coin in each state.
+        let dummy_txid =
+            Txid::from_str("3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810")
+                .unwrap();
+        let spend_txid =
+            Txid::from_str("617eab1fc0b03ee7f82ba70166725291783461f1a0e7975eaf8b5f8f674234f3")
+                .unwrap();
+        let coin = |vout: u32, block_height: Option<i32>| Coin {
+            outpoint: OutPoint::new(dummy_txid, vout),
+            block_height,
+            block_time: block_height.map(|_| 1_111_899),
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: ChildNumber::from(vout),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        };
+        let (unconfirmed, confirmed, spending, spent) = (
+            coin(0, None),
+            coin(1, Some(90)),
+            coin(2, Some(91)),
+            coin(3, Some(92)),
+        );
+        let mut db_conn = control.db().lock().unwrap().connection();
+        db_conn.new_unspent_coins(&[unconfirmed, confirmed, spending, spent]);
+        db_conn.spend_coins(&[
+            (spending.outpoint, spend_txid),
+            (spent.outpoint, spend_txid),
+        ]);
+        db_conn.confirm_spend(&[(spent.outpoint, spend_txid, 95, 1_112_000)]);
+
+        let listed = |statuses: &[CoinStatus], outpoints: &[OutPoint]| {
+            let mut ops: Vec<OutPoint> = control
+                .list_coins(statuses, outpoints)
+                .coins
+                .into_iter()
+                .map(|c| c.outpoint)
+                .collect();
+            ops.sort();
+            ops
+        };
+        assert_eq!(listed(&[], &[]).len(), 4);
+        assert_eq!(
+            listed(&[CoinStatus::Unconfirmed], &[]),
+            vec![unconfirmed.outpoint]
+        );
+        assert_eq!(
+            listed(&[CoinStatus::Confirmed], &[]),
+            vec![confirmed.outpoint]
+        );
+        assert_eq!(
+            listed(&[CoinStatus::Spending], &[]),
+            vec![spending.outpoint]
+        );
+        assert_eq!(listed(&[CoinStatus::Spent], &[]), vec![spent.outpoint]);
+
+        // The spendable coins.
+        assert_eq!(
+            listed(&[CoinStatus::Unconfirmed, CoinStatus::Confirmed], &[]),
+            vec![unconfirmed.outpoint, confirmed.outpoint]
+        );
+
+        // Filtering by outpoint, with and without status.
+        assert_eq!(
+            listed(&[], &[spent.outpoint, confirmed.outpoint]),
+            vec![confirmed.outpoint, spent.outpoint]
+        );
+        assert_eq!(
+            listed(
+                &[CoinStatus::Confirmed],
+                &[spent.outpoint, confirmed.outpoint]
+            ),
+            vec![confirmed.outpoint]
+        );
+        assert!(listed(&[CoinStatus::Spending], &[confirmed.outpoint]).is_empty());
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn blocks_until_recovery_computation() {
+        assert_eq!(blocks_until_recovery(100, None, 10), None);
+        assert_eq!(blocks_until_recovery(100, Some(100), 10), Some(9));
+        assert_eq!(blocks_until_recovery(100, Some(92), 10), Some(1));
+        assert_eq!(blocks_until_recovery(100, Some(91), 10), Some(0));
+        assert_eq!(blocks_until_recovery(100, Some(1), 10), Some(0));
+    }
+
+    #[test]
+    fn wallet_backup() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.handle.control;
+        let mut db_conn = control.db().lock().unwrap().connection();
+        db_conn.set_receive_index(12.into(), &control.secp);
+        db_conn.set_change_index(3.into(), &control.secp);
+        let mut labels = BTreeMap::new();
+        labels.insert("aabbccdd".to_string(), "Owner".to_string());
+        labels.insert("11223344".to_string(), "Heir".to_string());
+        db_conn.update_labels(&labels);
+
+        // The backup file roundtrips, with the descriptor, the indices at export time and the
+        // labels.
+        let backup = WalletBackup::from_json(&control.wallet_backup().to_json()).unwrap();
+        assert_eq!(backup.descriptor, control.config.main_descriptor);
+        assert_eq!(backup.network, control.config.bitcoin_config.network);
+        assert_eq!((backup.receive_index, backup.change_index), (12, 3));
+        assert_eq!(backup.labels, labels);
+
+        // It can be used to restore the indices and the labels of a fresh wallet with the same
+        // descriptor.
+        let fresh_ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let fresh_control = &fresh_ms.handle.control;
+        fresh_control.import_backup(&backup).unwrap();
+        let fresh_backup = fresh_control.wallet_backup();
+        assert_eq!(fresh_backup.descriptor, backup.descriptor);
+        assert_eq!(
+            (fresh_backup.receive_index, fresh_backup.change_index),
+            (12, 3)
+        );
+        assert_eq!(fresh_backup.labels, labels);
+
+        // The indices are never decreased. The labels of the backup replace ours, the others are
+        // kept.
+        let mut old_backup = backup.clone();
+        old_backup.receive_index = 2;
+        old_backup.labels = BTreeMap::new();
+        old_backup
+            .labels
+            .insert("aabbccdd".to_string(), "Spouse".to_string());
+        fresh_control.import_backup(&old_backup).unwrap();
+        let fresh_backup = fresh_control.wallet_backup();
+        assert_eq!(fresh_backup.receive_index, 12);
+        labels.insert("aabbccdd".to_string(), "Spouse".to_string());
+        assert_eq!(fresh_backup.labels, labels);
+
+        // An ongoing rescan is part of the backup, and is resumed upon import.
+        assert!(backup.rescan_timestamp.is_none());
+        control.start_rescan(1_600_000_000, false).unwrap();
+        let rescan_backup = WalletBackup::from_json(&control.wallet_backup().to_json()).unwrap();
+        assert_eq!(rescan_backup.rescan_timestamp, Some(1_600_000_000));
+        fresh_control.import_backup(&rescan_backup).unwrap();
+        let fresh_backup = fresh_control.wallet_backup();
+        assert_eq!(fresh_backup.rescan_timestamp, Some(1_600_000_000));
+        assert_eq!(
+            (fresh_backup.receive_index, fresh_backup.change_index),
+            (12, 3)
+        );
+
+        // It must be a backup of this very wallet.
+        let mut other_backup = backup.clone();
+        other_backup.network = bitcoin::Network::Testnet;
+        assert!(matches!(
+            fresh_control.import_backup(&other_backup),
+            Err(CommandError::InvalidBackup(_))
+        ));
+        let other_desc = descriptors::MultipathDescriptor::from_str("wsh(andor(pk(tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk(tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#5f6qd0d9").unwrap();
+        let other_backup = WalletBackup::new(
+            other_desc,
+            control.config.bitcoin_config.network,
+            12.into(),
+            3.into(),
+        );
+        assert!(matches!(
+            fresh_control.import_backup(&other_backup),
+            Err(CommandError::InvalidBackup(_))
+        ));
+
+        fresh_ms.shutdown();
+        ms.shutdown();
+    }
+
+    #[test]
+    fn create_spend() {
+        let dummy_op = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let mut dummy_bitcoind = DummyBitcoind::new();
+        dummy_bitcoind.txs.insert(
+            dummy_op.txid,
+            (
+                bitcoin::Transaction {
+                    version: 2,
+                    lock_time: bitcoin::PackedLockTime(0),
+                    input: vec![],
+                    output: vec![],
+                },
+                None,
+            ),
+        );
+        let ms = DummyLiana::new(dummy_bitcoind, DummyDatabase::new());
+        let control = &ms.handle.control;
+
+        // Arguments sanity checking
+        let dummy_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let dummy_value = 10_000;
+        let mut destinations: HashMap<bitcoin::Address, u64> = [(dummy_addr.clone(), dummy_value)]
+            .iter()
+            .cloned()
+            .collect();
+        assert_eq!(
+            control.create_spend(&destinations, &[], 1, None, None),
+            Err(CommandError::NoOutpoint)
+        );
+        assert_eq!(
+            control.create_spend(&HashMap::new(), &[dummy_op], 1, None, None),
+            Err(CommandError::NoDestination)
+        );
+        assert_eq!(
+            control.create_spend(&destinations, &[dummy_op], 0, None, None),
+            Err(CommandError::InvalidFeerate(0))
+        );
+
+        // The coin doesn't exist. If we create a new unspent one at this outpoint with a much
+        // higher value, we'll get a Spend transaction with a change output.
+        assert_eq!(
+            control.create_spend(&destinations, &[dummy_op], 1, None, None),
+            Err(CommandError::UnknownOutpoint(dummy_op))
+        );
+        let mut db_conn = control.db().lock().unwrap().connection();
+        db_conn.new_unspent_coins(&[Coin {
+            outpoint: dummy_op,
+            block_height: None,
+            block_time: None,
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(13),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        }]);
+        let res = control
+            .create_spend(&destinations, &[dummy_op], 1, None, None)
+            .unwrap();
+        assert!(res.psbt.inputs[0].non_witness_utxo.is_some());
+        let tx = res.psbt.unsigned_tx;
+        assert_eq!(tx.input.len(), 1);
+        assert_eq!(tx.input[0].previous_output, dummy_op);
+        assert_eq!(tx.output.len(), 2);
+        assert_eq!(tx.output[0].script_pubkey, dummy_addr.script_pubkey());
+        assert_eq!(tx.output[0].value, dummy_value);
+
+        // Transaction is 1 in (P2WSH satisfaction), 2 outs. At 1sat/vb, it's 171 sats fees.
+        // At 2sats/vb, it's twice that.
+        assert_eq!(tx.output[1].value, 89_829);
+
+        // The change output is signaled to signing devices, the recipient's one isn't.
+        let change_desc = control
+            .config
+            .main_descriptor
+            .change_descriptor()
+            .derive(0.into(), &control.secp);
+        assert_eq!(tx.output[1].script_pubkey, change_desc.script_pubkey());
+        assert_eq!(
+            res.psbt.outputs[1].bip32_derivation,
+            change_desc.bip32_derivations()
+        );
+        assert_eq!(res.psbt.outputs[1].bip32_derivation.len(), 2);
+        assert!(res.psbt.outputs[1]
+            .bip32_derivation
+            .values()
+            .all(|(_, der_path)| der_path.to_string() == "m/1/0"));
+        assert_eq!(
+            res.psbt.outputs[1].witness_script,
+            change_desc.witness_script()
+        );
+        assert!(res.psbt.outputs[0].bip32_derivation.is_empty());
+        assert!(res.psbt.outputs[0].witness_script.is_none());
+        let res = control
+            .create_spend(&destinations, &[dummy_op], 2, None, None)
+            .unwrap();
+        let tx = res.psbt.unsigned_tx;
+        assert_eq!(tx.output[1].value, 89_658);
+
+        // Each of the two Spend transactions with a change output used a fresh change index, but
+        // the receive index was left untouched.
+        assert_eq!(db_conn.change_index(), 2.into());
+        assert_eq!(db_conn.receive_index(), 0.into());
+
+        // If we ask for a too high feerate, or a too large/too small output, it'll fail.
+        assert_eq!(
+            control.create_spend(&destinations, &[dummy_op], 10_000, None, None),
+            Err(CommandError::InsufficientFunds(
+                bitcoin::Amount::from_sat(100_000),
+                bitcoin::Amount::from_sat(10_000),
+                10_000
+            ))
+        );
+        *destinations.get_mut(&dummy_addr).unwrap() = 100_001;
+        assert_eq!(
+            control.create_spend(&destinations, &[dummy_op], 1, None, None),
+            Err(CommandError::InsufficientFunds(
+                bitcoin::Amount::from_sat(100_000),
+                bitcoin::Amount::from_sat(100_001),
+                1
+            ))
+        );
+        *destinations.get_mut(&dummy_addr).unwrap() = 4_500;
+        assert_eq!(
+            control.create_spend(&destinations, &[dummy_op], 1, None, None),
+            Err(CommandError::InvalidOutputValue(bitcoin::Amount::from_sat(
+                4_500
+            )))
+        );
+
+        // If we ask to create an output for an address from another network, it will fail.
+        let invalid_addr = bitcoin::Address {
+            network: bitcoin::Network::Testnet,
+            payload: dummy_addr.payload.clone(),
+        };
+        let invalid_destinations: HashMap<bitcoin::Address, u64> =
+            [(invalid_addr.clone(), dummy_value)]
+                .iter()
+                .cloned()
+                .collect();
+        assert_eq!(
+            control.create_spend(&invalid_destinations, &[dummy_op], 1, None, None),
+            Err(CommandError::AddressNetwork(
+                invalid_addr,
+                bitcoin::Network::Bitcoin
+            ))
+        );
+
+        // If we ask for a large, but valid, output we won't get a change output. 95_000 because we
+        // won't create an output lower than 5k sats.
+        *destinations.get_mut(&dummy_addr).unwrap() = 95_000;
+        let res = control
+            .create_spend(&destinations, &[dummy_op], 1, None, None)
+            .unwrap();
+        let tx = res.psbt.unsigned_tx;
+        assert_eq!(tx.input.len(), 1);
+        assert_eq!(tx.input[0].previous_output, dummy_op);
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(tx.output[0].script_pubkey, dummy_addr.script_pubkey());
+        assert_eq!(tx.output[0].value, 95_000);
+
+        // We can embed some data in an additional OP_RETURN output, as long as it doesn't make
+        // the transaction non-standard.
+        let data = [0x42; 80];
+        let tx = control
+            .create_spend(&destinations, &[dummy_op], 1, None, Some(&data))
+            .unwrap()
+            .psbt
+            .unsigned_tx;
+        assert_eq!(tx.output.len(), 2);
+        let data_txo = tx
+            .output
+            .iter()
+            .find(|txo| txo.script_pubkey.is_op_return())
+            .unwrap();
+        assert_eq!(data_txo.value, 0);
+        assert_eq!(script_data(&data_txo.script_pubkey), Some(data.to_vec()));
+        let too_much_data = [0x42; 81];
+        assert_eq!(
+            control.create_spend(&destinations, &[dummy_op], 1, None, Some(&too_much_data)),
+            Err(CommandError::NonStandardOutput(data_script(&too_much_data)))
+        );
+
+        // Now if we mark the coin as spent, we won't create another Spend transaction containing
+        // it.
+        db_conn.spend_coins(&[(
+            dummy_op,
+            bitcoin::Txid::from_str(
+                "ef78f79ba747813887747cf8582897a48f1a09f1ca04d2cd3d6fcfdcbb5e0797",
+            )
+            .unwrap(),
+        )]);
+        assert_eq!(
+            control.create_spend(&destinations, &[dummy_op], 1, None, None),
+            Err(CommandError::AlreadySpent(dummy_op))
+        );
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn output_script_standardness() {
+        let addr = |s| bitcoin::Address::from_str(s).unwrap().script_pubkey();
+        // P2PKH, P2SH, P2WPKH, P2WSH, P2TR.
+        for spk in &[
+            addr("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2"),
+            addr("3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy"),
+            addr("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"),
+            addr("bc1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qccfmv3"),
+            addr("bc1p5d7rjq7g6rdk2yhzks9smlaqtedr4dekq08ge8ztwac72sfr9rusxg3297"),
+        ] {
+            check_output_script(spk).unwrap();
+        }
+
+        // Small OP_RETURNs are fine, but not large ones or those with non-push opcodes.
+        check_output_script(&data_script(&[])).unwrap();
+        check_output_script(&data_script(&[0xff; 80])).unwrap();
+        assert!(check_output_script(&data_script(&[0xff; 81])).is_err());
+        let op_return_checksig = bitcoin::blockdata::script::Builder::new()
+            .push_opcode(OP_RETURN)
+            .push_opcode(bitcoin::blockdata::opcodes::all::OP_CHECKSIG)
+            .into_script();
+        assert!(check_output_script(&op_return_checksig).is_err());
+
+        // Bare multisig isn't standard.
+        let key = bitcoin::PublicKey::from_str(
+            "02a1b26313f430c4b15bb1fdce663207659d8cac749a0e53d70eff01874496feff",
+        )
+        .unwrap();
+        let bare_multisig = bitcoin::blockdata::script::Builder::new()
+            .push_int(1)
+            .push_key(&key)
+            .push_int(1)
+            .push_opcode(bitcoin::blockdata::opcodes::all::OP_CHECKMULTISIG)
+            .into_script();
+        assert_eq!(
+            check_output_script(&bare_multisig),
+            Err(CommandError::NonStandardOutput(bare_multisig))
+        );
+
+        // Neither is a version 0 witness program of an unexpected size.
+        let v0_unknown = bitcoin::blockdata::script::Builder::new()
+            .push_int(0)
+            .push_slice(&[0; 25])
+            .into_script();
+        assert!(check_output_script(&v0_unknown).is_err());
+    }
+
+    #[test]
+    fn create_spend_below_relay_feerate() {
+        let mut dummy_bitcoind = DummyBitcoind::new();
+        dummy_bitcoind.relay_feerate = 3;
+        let ms = DummyLiana::new(dummy_bitcoind, DummyDatabase::new());
+        let control = &ms.handle.control;
+
+        let dummy_op = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let dummy_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let destinations: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr, 10_000)].iter().cloned().collect();
+
+        // A feerate below the node's minimum relay feerate is refused. One equal to it is
+        // accepted (here we then fail because the coin doesn't exist).
+        assert_eq!(
+            control.create_spend(&destinations, &[dummy_op], 2, None, None),
+            Err(CommandError::FeerateBelowRelay(2, 3))
+        );
+        assert_eq!(
+            control.create_spend(&destinations, &[dummy_op], 3, None, None),
+            Err(CommandError::UnknownOutpoint(dummy_op))
+        );
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn create_spend_op_return() {
+        let dummy_op = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let mut dummy_bitcoind = DummyBitcoind::new();
+        dummy_bitcoind.txs.insert(
+            dummy_op.txid,
+            (
+                bitcoin::Transaction {
+                    version: 2,
+                    lock_time: bitcoin::PackedLockTime(0),
+                    input: vec![],
+                    output: vec![],
+                },
+                None,
+            ),
+        );
+        let ms = DummyLiana::new(dummy_bitcoind, DummyDatabase::new());
+        let control = &ms.handle.control;
+        let mut db_conn = control.db().lock().unwrap().connection();
+        db_conn.new_unspent_coins(&[Coin {
+            outpoint: dummy_op,
+            block_height: None,
+            block_time: None,
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(13),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        }]);
+        let dummy_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let destinations: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr.clone(), 10_000)].iter().cloned().collect();
+        let change_value = |tx: &bitcoin::Transaction| {
+            tx.output
+                .iter()
+                .find(|txo| {
+                    txo.script_pubkey != dummy_addr.script_pubkey()
+                        && !txo.script_pubkey.is_op_return()
+                })
+                .unwrap()
+                .value
+        };
+
+        // A 40 bytes payload is embedded in a zero-value OP_RETURN output.
+        let payload = [0x17; 40];
+        let tx = control
+            .create_spend(&destinations, &[dummy_op], 2, None, Some(&payload))
+            .unwrap()
+            .psbt
+            .unsigned_tx;
+        assert_eq!(tx.output.len(), 3);
+        let op_return = tx
+            .output
+            .iter()
+            .find(|txo| txo.script_pubkey.is_op_return())
+            .unwrap();
+        assert_eq!(op_return.value, 0);
+        assert_eq!(op_return.script_pubkey, data_script(&payload));
+
+        // The size of this output is accounted for in the fees: the change output is smaller by
+        // exactly the cost of its size.
+        let tx_no_data = control
+            .create_spend(&destinations, &[dummy_op], 2, None, None)
+            .unwrap()
+            .psbt
+            .unsigned_tx;
+        assert_eq!(
+            change_value(&tx_no_data) - change_value(&tx),
+            serializable_size(op_return) * 2
+        );
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn create_spend_subtract_fee() {
+        let dummy_op = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let mut dummy_bitcoind = DummyBitcoind::new();
+        dummy_bitcoind.txs.insert(
+            dummy_op.txid,
+            (
+                bitcoin::Transaction {
+                    version: 2,
+                    lock_time: bitcoin::PackedLockTime(0),
+                    input: vec![],
+                    output: vec![],
+                },
+                None,
+            ),
+        );
+        let ms = DummyLiana::new(dummy_bitcoind, DummyDatabase::new());
+        let control = &ms.handle.control;
+        let mut db_conn = control.db().lock().unwrap().connection();
+        db_conn.new_unspent_coins(&[Coin {
+            outpoint: dummy_op,
+            block_height: None,
+            block_time: None,
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(13),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        }]);
+        let txin_sat_vb = control.config.main_descriptor.max_sat_vbytes();
+        let dummy_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let other_addr =
+            bitcoin::Address::from_str("bc1q39srgatmkp6k2ne3l52yhkjprdvunvspqydmkx").unwrap();
+
+        // Sending the whole value of the coin: the recipient pays for the fee and there is no
+        // change output. The input value is exactly the sum of the outputs and the fee.
+        let destinations: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr.clone(), 100_000)].iter().cloned().collect();
+        assert!(matches!(
+            control.create_spend(&destinations, &[dummy_op], 1, None, None),
+            Err(CommandError::InsufficientFunds(..))
+        ));
+        let tx = control
+            .create_spend(&destinations, &[dummy_op], 1, Some(&dummy_addr), None)
+            .unwrap()
+            .psbt
+            .unsigned_tx;
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(tx.output[0].script_pubkey, dummy_addr.script_pubkey());
+        let fee = (tx.vsize() + txin_sat_vb) as u64;
+        assert_eq!(tx.output[0].value + fee, 100_000);
+        let tx = control
+            .create_spend(&destinations, &[dummy_op], 2, Some(&dummy_addr), None)
+            .unwrap()
+            .psbt
+            .unsigned_tx;
+        assert_eq!(tx.output[0].value + fee * 2, 100_000);
+
+        // If the inputs value exceeds the destinations', the excess goes to a change output.
+        // The recipient still pays the whole fee.
+        let destinations: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr.clone(), 40_000), (other_addr.clone(), 30_000)]
+                .iter()
+                .cloned()
+                .collect();
+        let psbt = control
+            .create_spend(&destinations, &[dummy_op], 1, Some(&dummy_addr), None)
+            .unwrap()
+            .psbt;
+        let tx = &psbt.unsigned_tx;
+        assert_eq!(tx.output.len(), 3);
+        let fee = (tx.vsize() + txin_sat_vb) as u64;
+        let value_of = |addr: &bitcoin::Address| {
+            tx.output
+                .iter()
+                .find(|txo| txo.script_pubkey == addr.script_pubkey())
+                .unwrap()
+                .value
+        };
+        assert_eq!(value_of(&dummy_addr), 40_000 - fee);
+        assert_eq!(value_of(&other_addr), 30_000);
+        let change_index = psbt
+            .outputs
+            .iter()
+            .position(|o| !o.bip32_derivation.is_empty())
+            .unwrap();
+        assert_eq!(tx.output[change_index].value, 30_000);
+
+        // The recipient's output can't go below the dust threshold.
+        let destinations: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr.clone(), 5_100)].iter().cloned().collect();
+        assert!(matches!(
+            control.create_spend(&destinations, &[dummy_op], 1, Some(&dummy_addr), None),
+            Err(CommandError::InvalidOutputValue(..))
+        ));
+
+        // And it must be one of the destinations.
+        assert_eq!(
+            control.create_spend(&destinations, &[dummy_op], 1, Some(&other_addr), None),
+            Err(CommandError::UnknownFeePayer(other_addr))
+        );
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn rbf_spend() {
+        let op_a = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let (op_b, op_c, op_d) = (
+            bitcoin::OutPoint { vout: 1, ..op_a },
+            bitcoin::OutPoint { vout: 2, ..op_a },
+            bitcoin::OutPoint { vout: 3, ..op_a },
+        );
+        let mut dummy_bitcoind = DummyBitcoind::new();
+        dummy_bitcoind.txs.insert(
+            op_a.txid,
+            (
+                bitcoin::Transaction {
+                    version: 2,
+                    lock_time: bitcoin::PackedLockTime(0),
+                    input: vec![],
+                    output: vec![],
+                },
+                None,
+            ),
+        );
+        // Record our first change addresses, so the change outputs are recognized as ours.
+        let secp = bitcoin::secp256k1::Secp256k1::verification_only();
+        let mut db = DummyDatabase::new();
+        let change_desc = default_descriptor(bitcoin::Network::Bitcoin)
+            .change_descriptor()
+            .clone();
+        for i in 0..10u32 {
+            let address = change_desc
+                .derive(i.into(), &secp)
+                .address(bitcoin::Network::Bitcoin);
+            db.insert_address(address, i.into(), true);
+        }
+        let ms = DummyLiana::new(dummy_bitcoind, db);
+        let control = &ms.handle.control;
+        let mut db_conn = control.db().lock().unwrap().connection();
+        let coin = |outpoint, amount, block_height: Option<i32>, is_frozen| Coin {
+            outpoint,
+            block_height,
+            block_time: block_height.map(|_| 1_000),
+            amount: bitcoin::Amount::from_sat(amount),
+            derivation_index: bip32::ChildNumber::from(13),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen,
+        };
+        db_conn.new_unspent_coins(&[
+            coin(op_a, 100_000, Some(1), false),
+            coin(op_b, 50_000, Some(1), false),
+            // Neither a frozen nor an unconfirmed coin may be used to fund the bump.
+            coin(op_c, 200_000, Some(1), true),
+            coin(op_d, 300_000, None, false),
+        ]);
+
+        // We can't replace an unknown Spend.
+        let dummy_txid = op_a.txid;
+        assert_eq!(
+            control.rbf_spend(&dummy_txid, 2),
+            Err(CommandError::UnknownSpend(dummy_txid))
+        );
+
+        // A Spend with no change output, which was broadcast.
+        let dummy_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let destinations: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr.clone(), 99_800)].iter().cloned().collect();
+        let psbt = control
+            .create_spend(&destinations, &[op_a], 1, None, None)
+            .unwrap()
+            .psbt;
+        assert_eq!(psbt.unsigned_tx.output.len(), 1);
+        let txid = psbt.unsigned_tx.txid();
+        db_conn.store_spend(&psbt);
+        db_conn.spend_coins(&[(op_a, txid)]);
+
+        // The replacement must have a higher feerate.
+        assert_eq!(
+            control.rbf_spend(&txid, 1),
+            Err(CommandError::InvalidFeerate(1))
+        );
+
+        // There is no change to take the fee increase from, so another coin is added.
+        let rbf_psbt = control.rbf_spend(&txid, 2).unwrap().psbt;
+        let tx = &rbf_psbt.unsigned_tx;
+        assert_eq!(tx.input.len(), psbt.unsigned_tx.input.len() + 1);
+        let mut prevouts: Vec<bitcoin::OutPoint> =
+            tx.input.iter().map(|txin| txin.previous_output).collect();
+        prevouts.sort();
+        assert_eq!(prevouts, vec![op_a, op_b]);
+        assert!(tx
+            .output
+            .iter()
+            .any(|txo| txo.script_pubkey == dummy_addr.script_pubkey() && txo.value == 99_800));
+        let in_value: u64 = 150_000;
+        let out_value: u64 = tx.output.iter().map(|txo| txo.value).sum();
+        let vsize = tx.vsize() + tx.input.len() * control.config.main_descriptor.max_sat_vbytes();
+        assert!((in_value - out_value) / vsize as u64 >= 2);
+
+        // If the Spend pays several times to the same address, the replacement pays it the sum.
+        let mut dup_psbt = psbt.clone();
+        dup_psbt.unsigned_tx.output[0].value = 49_900;
+        dup_psbt
+            .unsigned_tx
+            .output
+            .push(dup_psbt.unsigned_tx.output[0].clone());
+        dup_psbt.outputs.push(dup_psbt.outputs[0].clone());
+        let dup_txid = dup_psbt.unsigned_tx.txid();
+        db_conn.store_spend(&dup_psbt);
+        let rbf_psbt = control.rbf_spend(&dup_txid, 2).unwrap().psbt;
+        let dest_outputs: Vec<_> = rbf_psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .filter(|txo| txo.script_pubkey == dummy_addr.script_pubkey())
+            .collect();
+        assert_eq!(dest_outputs.len(), 1);
+        assert_eq!(dest_outputs[0].value, 99_800);
+        db_conn.delete_spend(&dup_txid);
+
+        // A Spend with a change output, paying a fee just below 2 sats/vb.
+        let destinations: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr.clone(), 20_000)].iter().cloned().collect();
+        let mut change_psbt = control
+            .create_spend(&destinations, &[op_b], 2, None, None)
+            .unwrap()
+            .psbt;
+        let change_tx = &mut change_psbt.unsigned_tx;
+        let change_txo = change_tx
+            .output
+            .iter_mut()
+            .find(|txo| txo.script_pubkey != dummy_addr.script_pubkey())
+            .unwrap();
+        change_txo.value += 1;
+        let vsize = (change_tx.vsize()
+            + change_tx.input.len() * control.config.main_descriptor.max_sat_vbytes())
+            as u64;
+        let prev_fee = 50_000 - change_tx.output.iter().map(|txo| txo.value).sum::<u64>();
+        assert_eq!(prev_fee, 2 * vsize - 1);
+        let change_txid = change_tx.txid();
+        db_conn.store_spend(&change_psbt);
+        db_conn.spend_coins(&[(op_b, change_txid)]);
+
+        // Replacing it at 2 sats/vb would only increase the fee by a single sat, not enough to pay
+        // for the relay of the replacement. No change index is used by the rejected replacement.
+        let change_index = db_conn.change_index();
+        assert_eq!(
+            control.rbf_spend(&change_txid, 2),
+            Err(CommandError::InsufficientFeeBump(
+                bitcoin::Amount::from_sat(prev_fee + 1),
+                bitcoin::Amount::from_sat(prev_fee + vsize)
+            ))
+        );
+        assert_eq!(db_conn.change_index(), change_index);
+
+        // At 3 sats/vb it pays for it, and the fee increase is taken from the change.
+        let rbf_psbt = control.rbf_spend(&change_txid, 3).unwrap().psbt;
+        let tx = &rbf_psbt.unsigned_tx;
+        assert_eq!(tx.input.len(), 1);
+        assert_eq!(tx.input[0].previous_output, op_b);
+        assert_eq!(tx.output.len(), 2);
+        let out_value: u64 = tx.output.iter().map(|txo| txo.value).sum();
+        assert_eq!(50_000 - out_value, 3 * vsize);
+        assert_eq!(db_conn.change_index(), change_index.increment().unwrap());
+        db_conn.delete_spend(&change_txid);
+
+        // Once the coin of the original transaction is confirmed as spent, it can't be replaced.
+        db_conn.confirm_spend(&[(op_a, txid, 2, 2_000)]);
+        assert_eq!(
+            control.rbf_spend(&txid, 2),
+            Err(CommandError::AlreadySpent(op_a))
+        );
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn create_spend_taproot() {
+        let dummy_op = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let mut dummy_bitcoind = DummyBitcoind::new();
+        dummy_bitcoind.txs.insert(
+            dummy_op.txid,
+            (
+                bitcoin::Transaction {
+                    version: 2,
+                    lock_time: bitcoin::PackedLockTime(0),
+                    input: vec![],
+                    output: vec![],
+                },
+                None,
+            ),
+        );
+        let desc_str = "tr(xpub68JJTXc1MWK8KLW4HGLXZBJknja7kDUJuFHnM424LbziEXsfkh1WQCiEjjHw4zLqSUm4rvhgyGkkuRowE9tCJSgt3TQB5J3SKAbZ2SdcKST/<0;1>/*,and_v(v:pk(xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*),older(10000)))";
+        let desc = descriptors::MultipathDescriptor::from_str(desc_str).unwrap();
+        let ms = DummyLiana::with_descriptor(dummy_bitcoind, DummyDatabase::new(), desc);
+        let control = &ms.handle.control;
+
+        // Our receive addresses are Taproot ones.
+        let addr = control.get_new_address().address;
+        assert!(addr.script_pubkey().is_v1_p2tr());
+
+        let mut db_conn = control.db().lock().unwrap().connection();
+        db_conn.new_unspent_coins(&[Coin {
+            outpoint: dummy_op,
+            block_height: None,
+            block_time: None,
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(13),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        }]);
+        let dummy_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let destinations: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr.clone(), 10_000)].iter().cloned().collect();
+        let res = control
+            .create_spend(&destinations, &[dummy_op], 1, None, None)
+            .unwrap();
+        let tx = &res.psbt.unsigned_tx;
+        assert_eq!(tx.output.len(), 2);
+        assert_eq!(tx.output[0].script_pubkey, dummy_addr.script_pubkey());
+
+        // The input has the Taproot fields populated instead of the witness script.
+        let coin_desc = control
+            .config
+            .main_descriptor
+            .receive_descriptor()
+            .derive(13.into(), &control.secp);
+        let psbtin = &res.psbt.inputs[0];
+        assert_eq!(
+            psbtin.witness_utxo.as_ref().unwrap().script_pubkey,
+            coin_desc.script_pubkey()
+        );
+        assert!(psbtin.witness_script.is_none());
+        assert!(psbtin.bip32_derivation.is_empty());
+        assert!(psbtin.tap_internal_key.is_some());
+        assert_eq!(psbtin.tap_scripts.len(), 1);
+        assert_eq!(psbtin.tap_key_origins, coin_desc.tap_key_origins());
+        assert_eq!(psbtin.tap_key_origins.len(), 2);
+
+        // The change output is a Taproot one, signaled to signing devices.
+        let change_desc = control
+            .config
+            .main_descriptor
+            .change_descriptor()
+            .derive(0.into(), &control.secp);
+        assert!(tx.output[1].script_pubkey.is_v1_p2tr());
+        assert_eq!(tx.output[1].script_pubkey, change_desc.script_pubkey());
+        assert!(res.psbt.outputs[1].tap_internal_key.is_some());
+        assert_eq!(
+            res.psbt.outputs[1].tap_key_origins,
+            change_desc.tap_key_origins()
+        );
+        assert!(res.psbt.outputs[0].tap_internal_key.is_none());
+        assert!(res.psbt.outputs[0].tap_key_origins.is_empty());
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn create_spend_bip69() {
+        let op_a = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:1",
+        )
+        .unwrap();
+        let op_b = bitcoin::OutPoint::from_str(
+            "0c62a990d20d54429e70859292e82374ba6b1b951a3ab60f26bb65fee5724ff7:0",
+        )
+        .unwrap();
+        let op_c = bitcoin::OutPoint::new(op_a.txid, 0);
+        let mut dummy_bitcoind = DummyBitcoind::new();
+        for txid in &[op_a.txid, op_b.txid] {
+            dummy_bitcoind.txs.insert(
+                *txid,
+                (
+                    bitcoin::Transaction {
+                        version: 2,
+                        lock_time: bitcoin::PackedLockTime(0),
+                        input: vec![],
+                        output: vec![],
+                    },
+                    None,
+                ),
+            );
+        }
+        let ms = DummyLiana::new(dummy_bitcoind, DummyDatabase::new());
+        let control = &ms.handle.control;
+        let mut db_conn = control.db().lock().unwrap().connection();
+        let coins: Vec<Coin> = [op_a, op_b, op_c]
+            .iter()
+            .map(|op| Coin {
+                outpoint: *op,
+                block_height: None,
+                block_time: None,
+                amount: bitcoin::Amount::from_sat(40_000),
+                derivation_index: bip32::ChildNumber::from(13),
+                is_change: false,
+                spend_txid: None,
+                spend_block: None,
+                spend_broadcast_time: None,
+                is_frozen: false,
+            })
+            .collect();
+        db_conn.new_unspent_coins(&coins);
+
+        let addr_a =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let addr_b =
+            bitcoin::Address::from_str("bc1q39srgatmkp6k2ne3l52yhkjprdvunvspqydmkx").unwrap();
+        let addr_c =
+            bitcoin::Address::from_str("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4").unwrap();
+        let destinations: HashMap<bitcoin::Address, u64> = [
+            (addr_a.clone(), 30_000),
+            (addr_b.clone(), 10_000),
+            (addr_c.clone(), 40_000),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+
+        // Inputs are sorted by txid (as displayed) then vout, outputs by value then scriptPubKey.
+        // The change output isn't necessarily the last one, but it's still the one with a BIP32
+        // derivation.
+        let psbt = control
+            .create_spend(&destinations, &[op_a, op_b, op_c], 1, None, None)
+            .unwrap()
+            .psbt;
+        let tx = &psbt.unsigned_tx;
+        let prevouts: Vec<bitcoin::OutPoint> =
+            tx.input.iter().map(|txin| txin.previous_output).collect();
+        assert_eq!(prevouts, vec![op_b, op_c, op_a]);
+        assert_eq!(tx.output.len(), 4);
+        assert_eq!(tx.output[0].script_pubkey, addr_b.script_pubkey());
+        assert_eq!(tx.output[1].script_pubkey, addr_a.script_pubkey());
+        assert!(tx.output[2].value > 30_000 && tx.output[2].value < 40_000);
+        assert_eq!(tx.output[3].script_pubkey, addr_c.script_pubkey());
+        let change_indexes: Vec<usize> = psbt
+            .outputs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, psbtout)| {
+                if psbtout.bip32_derivation.is_empty() {
+                    None
+                } else {
+                    Some(i)
+                }
+            })
+            .collect();
+        assert_eq!(change_indexes, vec![2]);
+
+        // Creating it again, even with coins in another order, gives the same ordering. Only the
+        // change address differs, since a new change index is used for each Spend.
+        let other_psbt = control
+            .create_spend(&destinations, &[op_c, op_a, op_b], 1, None, None)
+            .unwrap()
+            .psbt;
+        let other_tx = &other_psbt.unsigned_tx;
+        assert_eq!(other_tx.input, tx.input);
+        assert_eq!(other_tx.output.len(), tx.output.len());
+        for (i, (a, b)) in tx.output.iter().zip(other_tx.output.iter()).enumerate() {
+            assert_eq!(a.value, b.value);
+            if i != 2 {
+                assert_eq!(a.script_pubkey, b.script_pubkey);
+            }
+        }
+        assert!(!other_psbt.outputs[2].bip32_derivation.is_empty());
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn create_spend_sanity_bounds() {
+        let dummy_op = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let dummy_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let destinations: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr, 10_000)].iter().cloned().collect();
+        let desc = descriptors::MultipathDescriptor::from_str("wsh(or_d(pk(xpub68JJTXc1MWK8KLW4HGLXZBJknja7kDUJuFHnM424LbziEXsfkh1WQCiEjjHw4zLqSUm4rvhgyGkkuRowE9tCJSgt3TQB5J3SKAbZ2SdcKST/<0;1>/*),and_v(v:pkh(xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*),older(10000))))").unwrap();
+
+        // A wallet with a single 2BTC coin.
+        let wallet = |bitcoin_config: BitcoinConfig| {
+            let mut dummy_bitcoind = DummyBitcoind::new();
+            dummy_bitcoind.txs.insert(
+                dummy_op.txid,
+                (
+                    bitcoin::Transaction {
+                        version: 2,
+                        lock_time: bitcoin::PackedLockTime(0),
+                        input: vec![],
+                        output: vec![],
+                    },
+                    None,
+                ),
+            );
+            let db = DummyDatabase::new();
+            db.connection().new_unspent_coins(&[Coin {
+                outpoint: dummy_op,
+                block_height: None,
+                block_time: None,
+                amount: bitcoin::Amount::from_sat(200_000_000),
+                derivation_index: bip32::ChildNumber::from(13),
+                is_change: false,
+                spend_txid: None,
+                spend_block: None,
+                spend_broadcast_time: None,
+                is_frozen: false,
+            }]);
+            DummyLiana::with_bitcoin_config(dummy_bitcoind, db, desc.clone(), bitcoin_config)
+        };
+
+        // By default, paying more than 1BTC of fees is assumed to be a bug.
+        let mut bitcoin_config = BitcoinConfig {
+            network: bitcoin::Network::Bitcoin,
+            poll_interval_secs: std::time::Duration::from_secs(2),
+            gap_limit: 20,
+            dust_output_sats: 5_000,
+            max_fee_sats: bitcoin::blockdata::constants::COIN_VALUE,
+            max_feerate_sats_vb: 1_000,
+            min_confirmations: 1,
+            psbt_non_witness_utxo: true,
+            long_term_feerate_vb: None,
+            rescan_timeout_secs: None,
+        };
+        let ms = wallet(bitcoin_config.clone());
+        assert!(matches!(
+            ms.handle
+                .control
+                .create_spend(&destinations, &[dummy_op], 1_000_000, None, None),
+            Err(CommandError::SanityCheckFailure(_))
+        ));
+        ms.shutdown();
+
+        // But it's accepted if the user raised the bounds.
+        bitcoin_config.max_fee_sats = 3 * bitcoin::blockdata::constants::COIN_VALUE;
+        bitcoin_config.max_feerate_sats_vb = 10_000_000;
+        let ms = wallet(bitcoin_config.clone());
+        let psbt = ms
+            .handle
+            .control
+            .create_spend(&destinations, &[dummy_op], 1_000_000, None, None)
+            .unwrap()
+            .psbt;
+        let out_value: u64 = psbt.unsigned_tx.output.iter().map(|o| o.value).sum();
+        assert!(200_000_000 - out_value > bitcoin::blockdata::constants::COIN_VALUE);
+        ms.shutdown();
+
+        // Similarly, the dust threshold for outputs is configurable.
+        bitcoin_config.dust_output_sats = 20_000;
+        let ms = wallet(bitcoin_config);
+        assert_eq!(
+            ms.handle
+                .control
+                .create_spend(&destinations, &[dummy_op], 1, None, None),
+            Err(CommandError::InvalidOutputValue(bitcoin::Amount::from_sat(
+                10_000
+            )))
+        );
+        ms.shutdown();
+    }
+
+    #[test]
+    fn dust_threshold() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.handle.control;
+
+        // Our change is P2WSH. At Bitcoin Core's default dust relay feerate of 3sats/vb its dust
+        // limit is the well known 330 sats.
+        let change_spk = control
+            .config
+            .main_descriptor
+            .change_descriptor()
+            .derive(0.into(), &control.secp)
+            .script_pubkey();
+        assert!(change_spk.is_v0_p2wsh());
+        assert_eq!(control.dust_threshold(3), 330);
+        assert_eq!(control.dust_threshold(3), change_spk.dust_value().to_sat());
+        assert_eq!(control.dust_threshold(1), 110);
+        assert_eq!(control.dust_threshold(100), 11_000);
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn consolidate() {
+        let outpoints: Vec<bitcoin::OutPoint> = (0..6)
+            .map(|i| bitcoin::OutPoint {
+                txid: bitcoin::Txid::from_slice(&[i as u8 + 1; 32]).unwrap(),
+                vout: i,
+            })
+            .collect();
+        let mut dummy_bitcoind = DummyBitcoind::new();
+        for op in outpoints.iter() {
+            dummy_bitcoind.txs.insert(
+                op.txid,
+                (
+                    bitcoin::Transaction {
+                        version: 2,
+                        lock_time: bitcoin::PackedLockTime(0),
+                        input: vec![],
+                        output: vec![],
+                    },
+                    None,
+                ),
+            );
+        }
+        // Record our first change addresses, so the outputs paying to them are recognized as ours.
+        let secp = bitcoin::secp256k1::Secp256k1::verification_only();
+        let mut db = DummyDatabase::new();
+        let change_desc = default_descriptor(bitcoin::Network::Bitcoin)
+            .change_descriptor()
+            .clone();
+        for i in 0..4u32 {
+            let address = change_desc
+                .derive(i.into(), &secp)
+                .address(bitcoin::Network::Bitcoin);
+            db.insert_address(address, i.into(), true);
+        }
+        let ms = DummyLiana::new(dummy_bitcoind, db);
+        let control = &ms.handle.control;
+        let mut db_conn = control.db().lock().unwrap().connection();
+
+        // Nothing to consolidate in an empty wallet.
+        assert_eq!(control.consolidate(1, None), Err(CommandError::NoOutpoint));
+
+        // Five coins, one of them frozen.
+        let coins: Vec<Coin> = outpoints[..5]
+            .iter()
+            .map(|op| Coin {
+                outpoint: *op,
+                block_height: Some(100),
+                block_time: Some(1_111_899),
+                amount: bitcoin::Amount::from_sat(100_000),
+                derivation_index: bip32::ChildNumber::from(op.vout),
+                is_change: false,
+                spend_txid: None,
+                spend_block: None,
+                spend_broadcast_time: None,
+                is_frozen: false,
+            })
+            .collect();
+        db_conn.new_unspent_coins(&coins);
+        db_conn.set_frozen(&[outpoints[4]], true);
+
+        // Without a cap, all the unfrozen coins fit in a single transaction.
+        let res = control.consolidate(1, None).unwrap();
+        assert_eq!(res.len(), 1);
+        let tx = &res[0].psbt.unsigned_tx;
+        assert_eq!(tx.input.len(), 4);
+        assert!(tx
+            .input
+            .iter()
+            .all(|txin| txin.previous_output != outpoints[4]));
+
+        // With a cap of 3 inputs per transaction, it takes two transactions. Each of them pays
+        // the value of its coins minus the fee to a different change output of ours.
+        let res = control.consolidate(1, Some(3)).unwrap();
+        assert_eq!(res.len(), 2);
+        let mut spent = HashSet::new();
+        for CreateSpendResult { psbt } in res.iter() {
+            let tx = &psbt.unsigned_tx;
+            assert!(tx.input.len() <= 3);
+            assert!(tx.weight() <= MAX_STANDARD_TX_WEIGHT);
+            assert_eq!(tx.output.len(), 1);
+            assert!(!psbt.outputs[0].bip32_derivation.is_empty());
+            let in_value = tx.input.len() as u64 * 100_000;
+            assert!(tx.output[0].value < in_value);
+            spent.extend(tx.input.iter().map(|txin| txin.previous_output));
+        }
+        assert_eq!(spent.len(), 4);
+        assert_ne!(
+            res[0].psbt.unsigned_tx.output[0].script_pubkey,
+            res[1].psbt.unsigned_tx.output[0].script_pubkey
+        );
+
+        // A cap of 0 inputs can't create any transaction.
+        assert_eq!(
+            control.consolidate(1, Some(0)),
+            Err(CommandError::NoOutpoint)
+        );
+
+        // A coin barely above the dust limit isn't worth spending on its own. Its batch is
+        // skipped, without using a change address, but the others are still consolidated.
+        let small_coin = Coin {
+            outpoint: outpoints[5],
+            amount: bitcoin::Amount::from_sat(5_050),
+            ..coins[0]
+        };
+        db_conn.new_unspent_coins(&[small_coin]);
+        let change_index = db_conn.change_index();
+        let res = control.consolidate(1, Some(4)).unwrap();
+        assert_eq!(res.len(), 1);
+        assert!(res[0]
+            .psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .all(|txin| txin.previous_output != small_coin.outpoint));
+        assert_eq!(db_conn.change_index(), change_index.increment().unwrap());
+
+        // If no batch is worth it, the error is returned.
+        assert!(matches!(
+            control.consolidate(10_000, Some(1)),
+            Err(CommandError::InvalidOutputValue(_))
+        ));
+        assert_eq!(db_conn.change_index(), change_index.increment().unwrap());
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn create_spend_non_witness_utxo() {
+        let prev_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime(0),
+            input: vec![],
+            output: vec![bitcoin::TxOut {
+                value: 100_000,
+                script_pubkey: bitcoin::Script::new(),
+            }],
+        };
+        let dummy_op = bitcoin::OutPoint::new(prev_tx.txid(), 0);
+        let dummy_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let destinations: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr, 10_000)].iter().cloned().collect();
+        let desc = descriptors::MultipathDescriptor::from_str("wsh(or_d(pk(xpub68JJTXc1MWK8KLW4HGLXZBJknja7kDUJuFHnM424LbziEXsfkh1WQCiEjjHw4zLqSUm4rvhgyGkkuRowE9tCJSgt3TQB5J3SKAbZ2SdcKST/<0;1>/*),and_v(v:pkh(xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*),older(10000))))").unwrap();
+
+        // A wallet with a single coin, whose creating transaction may be known by the backend.
+        let wallet = |bitcoin_config: BitcoinConfig, knows_tx: bool| {
+            let mut dummy_bitcoind = DummyBitcoind::new();
+            if knows_tx {
+                dummy_bitcoind
+                    .txs
+                    .insert(dummy_op.txid, (prev_tx.clone(), None));
+            }
+            let db = DummyDatabase::new();
+            db.connection().new_unspent_coins(&[Coin {
+                outpoint: dummy_op,
+                block_height: None,
+                block_time: None,
+                amount: bitcoin::Amount::from_sat(100_000),
+                derivation_index: bip32::ChildNumber::from(13),
+                is_change: false,
+                spend_txid: None,
+                spend_block: None,
+                spend_broadcast_time: None,
+                is_frozen: false,
+            }]);
+            DummyLiana::with_bitcoin_config(dummy_bitcoind, db, desc.clone(), bitcoin_config)
+        };
+
+        // By default the full previous transaction is included along with the spent output.
+        let mut bitcoin_config = BitcoinConfig {
+            network: bitcoin::Network::Bitcoin,
+            poll_interval_secs: std::time::Duration::from_secs(2),
+            gap_limit: 20,
+            dust_output_sats: 5_000,
+            max_fee_sats: bitcoin::blockdata::constants::COIN_VALUE,
+            max_feerate_sats_vb: 1_000,
+            min_confirmations: 1,
+            psbt_non_witness_utxo: true,
+            long_term_feerate_vb: None,
+            rescan_timeout_secs: None,
+        };
+        let ms = wallet(bitcoin_config.clone(), true);
+        let psbt = ms
+            .handle
+            .control
+            .create_spend(&destinations, &[dummy_op], 1, None, None)
+            .unwrap()
+            .psbt;
+        assert_eq!(psbt.inputs[0].non_witness_utxo, Some(prev_tx.clone()));
+        assert_eq!(
+            psbt.inputs[0].witness_utxo.as_ref().map(|txo| txo.value),
+            Some(100_000)
+        );
+        ms.shutdown();
+
+        // So we need to be able to fetch it.
+        let ms = wallet(bitcoin_config.clone(), false);
+        assert_eq!(
+            ms.handle
+                .control
+                .create_spend(&destinations, &[dummy_op], 1, None, None),
+            Err(CommandError::FetchingTransaction(dummy_op))
+        );
+        ms.shutdown();
+
+        // If disabled, only the spent output is included and we don't need the transaction.
+        bitcoin_config.psbt_non_witness_utxo = false;
+        let ms = wallet(bitcoin_config, false);
+        let psbt = ms
+            .handle
+            .control
+            .create_spend(&destinations, &[dummy_op], 1, None, None)
+            .unwrap()
+            .psbt;
+        assert!(psbt.inputs[0].non_witness_utxo.is_none());
+        assert_eq!(
+            psbt.inputs[0].witness_utxo.as_ref().map(|txo| txo.value),
+            Some(100_000)
+        );
+        ms.shutdown();
+    }
+
+    #[test]
+    fn create_spend_long_term_feerate() {
+        let dummy_op = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let dummy_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        // Leaves a change output of a bit less than 7k sats, above the dust threshold.
+        let destinations: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr, 93_000)].iter().cloned().collect();
+        let desc = descriptors::MultipathDescriptor::from_str("wsh(or_d(pk(xpub68JJTXc1MWK8KLW4HGLXZBJknja7kDUJuFHnM424LbziEXsfkh1WQCiEjjHw4zLqSUm4rvhgyGkkuRowE9tCJSgt3TQB5J3SKAbZ2SdcKST/<0;1>/*),and_v(v:pkh(xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*),older(10000))))").unwrap();
+        let spender_input_size = desc.spender_input_size() as u64;
+
+        // A wallet with a single 100k sats coin.
+        let wallet = |bitcoin_config: BitcoinConfig| {
+            let mut dummy_bitcoind = DummyBitcoind::new();
+            dummy_bitcoind.txs.insert(
+                dummy_op.txid,
+                (
+                    bitcoin::Transaction {
+                        version: 2,
+                        lock_time: bitcoin::PackedLockTime(0),
+                        input: vec![],
+                        output: vec![],
+                    },
+                    None,
+                ),
+            );
+            let db = DummyDatabase::new();
+            db.connection().new_unspent_coins(&[Coin {
+                outpoint: dummy_op,
+                block_height: None,
+                block_time: None,
+                amount: bitcoin::Amount::from_sat(100_000),
+                derivation_index: bip32::ChildNumber::from(13),
+                is_change: false,
+                spend_txid: None,
+                spend_block: None,
+                spend_broadcast_time: None,
+                is_frozen: false,
+            }]);
+            DummyLiana::with_bitcoin_config(dummy_bitcoind, db, desc.clone(), bitcoin_config)
+        };
+        let create_spend = |bitcoin_config: BitcoinConfig| {
+            let ms = wallet(bitcoin_config);
+            let tx = ms
+                .handle
+                .control
+                .create_spend(&destinations, &[dummy_op], 1, None, None)
+                .unwrap()
+                .psbt
+                .unsigned_tx;
+            ms.shutdown();
+            tx
+        };
+
+        // By default, the change is only checked against the dust threshold.
+        let mut bitcoin_config = BitcoinConfig {
+            network: bitcoin::Network::Bitcoin,
+            poll_interval_secs: std::time::Duration::from_secs(2),
+            gap_limit: 20,
+            dust_output_sats: 5_000,
+            max_fee_sats: bitcoin::blockdata::constants::COIN_VALUE,
+            max_feerate_sats_vb: 1_000,
+            min_confirmations: 1,
+            psbt_non_witness_utxo: true,
+            long_term_feerate_vb: None,
+            rescan_timeout_secs: None,
+        };
+        let tx = create_spend(bitcoin_config.clone());
+        assert_eq!(tx.output.len(), 2);
+        let change_value = tx.output.iter().map(|o| o.value).min().unwrap();
+        assert!(change_value > 6_000 && change_value < 7_000);
+
+        // The configured dust threshold only applies to the recipients' outputs, not to our change.
+        bitcoin_config.dust_output_sats = 10_000;
+        let tx = create_spend(bitcoin_config.clone());
+        assert_eq!(tx.output.len(), 2);
+        assert_eq!(
+            tx.output.iter().map(|o| o.value).min().unwrap(),
+            change_value
+        );
+
+        // At a low long term feerate, the change output is still worth creating.
+        bitcoin_config.long_term_feerate_vb = Some(1);
+        assert!(change_value > spender_input_size);
+        let tx = create_spend(bitcoin_config.clone());
+        assert_eq!(tx.output.len(), 2);
+
+        // But not if it would cost more to spend it than its value.
+        bitcoin_config.long_term_feerate_vb = Some(100);
+        assert!(change_value < spender_input_size * 100);
+        let tx = create_spend(bitcoin_config);
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(tx.output[0].value, 93_000);
+    }
+
+    #[test]
+    fn create_recovery() {
+        let dummy_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime(0),
+            input: vec![],
+            output: vec![],
+        };
+        let dummy_txid = dummy_tx.txid();
+        let mut dummy_bitcoind = DummyBitcoind::new();
+        dummy_bitcoind.txs.insert(dummy_txid, (dummy_tx, None));
+
+        // Use a descriptor with a short timelock of 10 blocks. The dummy tip is at height 100.
+        let desc = descriptors::MultipathDescriptor::from_str("wsh(or_d(pk(xpub68JJTXc1MWK8KLW4HGLXZBJknja7kDUJuFHnM424LbziEXsfkh1WQCiEjjHw4zLqSUm4rvhgyGkkuRowE9tCJSgt3TQB5J3SKAbZ2SdcKST/<0;1>/*),and_v(v:pkh(xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*),older(10))))").unwrap();
+        let ms = DummyLiana::with_descriptor(dummy_bitcoind, DummyDatabase::new(), desc);
+        let control = &ms.handle.control;
+        let dummy_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+
+        // Arguments sanity checking
+        assert_eq!(
+            control.create_recovery(dummy_addr.clone(), 0, None),
+            Err(CommandError::InvalidFeerate(0))
+        );
+        let testnet_addr = bitcoin::Address {
+            network: bitcoin::Network::Testnet,
+            payload: dummy_addr.payload.clone(),
+        };
+        assert_eq!(
+            control.create_recovery(testnet_addr.clone(), 1, None),
+            Err(CommandError::AddressNetwork(
+                testnet_addr,
+                bitcoin::Network::Bitcoin
+            ))
+        );
+
+        // Without any coin, there is nothing to recover.
+        assert_eq!(
+            control.create_recovery(dummy_addr.clone(), 1, None),
+            Err(CommandError::RecoveryNotAvailable)
+        );
+
+        // Insert coins at various depths. Only those for which the timelock is expired at the
+        // next block (that is, confirmed at height 91 or lower) can be swept.
+        let coin = |vout: u32, block_height: Option<i32>| Coin {
+            outpoint: bitcoin::OutPoint::new(dummy_txid, vout),
+            block_height,
+            block_time: block_height.map(|h| 1_000 + h as u32),
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(vout),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        };
+        let mut db_conn = control.db().lock().unwrap().connection();
+        db_conn.new_unspent_coins(&[coin(0, None), coin(1, Some(99)), coin(2, Some(92))]);
+        assert_eq!(
+            control.create_recovery(dummy_addr.clone(), 1, None),
+            Err(CommandError::RecoveryNotAvailable)
+        );
+        db_conn.new_unspent_coins(&[coin(3, Some(91)), coin(4, Some(50))]);
+        let psbt = control
+            .create_recovery(dummy_addr.clone(), 1, None)
+            .unwrap()
+            .psbt;
+        let tx = psbt.unsigned_tx;
+        let mut swept: Vec<u32> = tx
+            .input
+            .iter()
+            .map(|txin| txin.previous_output.vout)
+            .collect();
+        swept.sort_unstable();
+        assert_eq!(swept, vec![3, 4]);
+        assert!(tx
+            .input
+            .iter()
+            .all(|txin| txin.sequence == Sequence::from_height(10)));
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(tx.output[0].script_pubkey, dummy_addr.script_pubkey());
+        assert!(tx.output[0].value < 200_000);
+        assert!(psbt
+            .inputs
+            .iter()
+            .all(|psbtin| psbtin.witness_utxo.is_some()));
+
+        // A spent coin can't be swept.
+        db_conn.spend_coins(&[(
+            bitcoin::OutPoint::new(dummy_txid, 4),
+            bitcoin::Txid::from_str(
+                "ef78f79ba747813887747cf8582897a48f1a09f1ca04d2cd3d6fcfdcbb5e0797",
+            )
+            .unwrap(),
+        )]);
+        let tx = control
+            .create_recovery(dummy_addr.clone(), 1, None)
+            .unwrap()
+            .psbt
+            .unsigned_tx;
+        assert_eq!(tx.input.len(), 1);
+        assert_eq!(tx.input[0].previous_output.vout, 3);
+
+        // A frozen coin isn't swept either, until it's unfrozen.
+        let frozen_op = bitcoin::OutPoint::new(dummy_txid, 3);
+        control.set_frozen(&[frozen_op], true).unwrap();
+        assert!(
+            control
+                .list_coins(&[], &[])
+                .coins
+                .iter()
+                .find(|c| c.outpoint == frozen_op)
+                .unwrap()
+                .frozen
+        );
+        assert_eq!(
+            control.create_recovery(dummy_addr.clone(), 1, None),
+            Err(CommandError::RecoveryNotAvailable)
+        );
+        control.set_frozen(&[frozen_op], false).unwrap();
+        let tx = control
+            .create_recovery(dummy_addr, 1, None)
+            .unwrap()
+            .psbt
+            .unsigned_tx;
+        assert_eq!(tx.input.len(), 1);
+        assert_eq!(tx.input[0].previous_output, frozen_op);
+
+        // We can't freeze an unknown coin.
+        let unknown_op = bitcoin::OutPoint::new(dummy_txid, 42);
+        assert_eq!(
+            control.set_frozen(&[frozen_op, unknown_op], true),
+            Err(CommandError::UnknownOutpoint(unknown_op))
+        );
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn create_recovery_multiple_paths() {
+        let dummy_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime(0),
+            input: vec![],
+            output: vec![],
+        };
+        let dummy_txid = dummy_tx.txid();
+        let mut dummy_bitcoind = DummyBitcoind::new();
+        dummy_bitcoind.txs.insert(dummy_txid, (dummy_tx, None));
+
+        // A descriptor with a first recovery path after 10 blocks and a second one after 20
+        // blocks. The dummy tip is at height 100.
+        let desc = descriptors::MultipathDescriptor::from_str("wsh(or_d(pk(xpub68JJTXc1MWK8KLW4HGLXZBJknja7kDUJuFHnM424LbziEXsfkh1WQCiEjjHw4zLqSUm4rvhgyGkkuRowE9tCJSgt3TQB5J3SKAbZ2SdcKST/<0;1>/*),or_i(and_v(v:pkh(xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*),older(10)),and_v(v:pkh(xpub661MyMwAqRbcFG59fiikD8UV762quhruT8K8bdjqy6N2o3LG7yohoCdLg1m2HAY1W6rfBrtauHkBhbfA4AQ3iazaJj5wVPhwgaRCHBW2DBg/<0;1>/*),older(20)))))").unwrap();
+        assert_eq!(desc.timelock_values(), vec![10, 20]);
+        let ms = DummyLiana::with_descriptor(dummy_bitcoind, DummyDatabase::new(), desc);
+        let control = &ms.handle.control;
+        let dummy_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let coin = |vout: u32, block_height: Option<i32>| Coin {
+            block_height,
+            block_time: block_height.map(|h| 1_000 + h as u32),
+            derivation_index: bip32::ChildNumber::from(vout),
+            ..dummy_coin(bitcoin::OutPoint::new(dummy_txid, vout), 100_000)
+        };
+        let mut db_conn = control.db().lock().unwrap().connection();
+        db_conn.new_unspent_coins(&[coin(0, Some(91)), coin(1, Some(85))]);
+
+        // Both coins are available through the first recovery path, none through the second.
+        let coins = control.list_coins(&[], &[]).coins;
+        let recovery_paths = |vout| {
+            coins
+                .iter()
+                .find(|c| c.outpoint.vout == vout)
+                .unwrap()
+                .recovery_paths
+                .clone()
+        };
+        assert_eq!(
+            recovery_paths(0),
+            vec![
+                LCRecoveryPath {
+                    timelock: 10,
+                    blocks_until_recovery: Some(0)
+                },
+                LCRecoveryPath {
+                    timelock: 20,
+                    blocks_until_recovery: Some(10)
+                }
+            ]
+        );
+        assert_eq!(recovery_paths(1)[1].blocks_until_recovery, Some(4));
+        let info = control.get_info();
+        assert!(info.recovery_available);
+        assert_eq!(info.recovery_height, Some(95));
+        assert_eq!(
+            info.recovery_paths,
+            vec![
+                GetInfoRecoveryPath {
+                    timelock: 10,
+                    available: true,
+                    height: Some(95)
+                },
+                GetInfoRecoveryPath {
+                    timelock: 20,
+                    available: false,
+                    height: Some(105)
+                }
+            ]
+        );
+
+        // By default the first recovery path is used.
+        let tx = control
+            .create_recovery(dummy_addr.clone(), 1, None)
+            .unwrap()
+            .psbt
+            .unsigned_tx;
+        assert_eq!(tx.input.len(), 2);
+        assert!(tx
+            .input
+            .iter()
+            .all(|txin| txin.sequence == Sequence::from_height(10)));
+
+        // The second one can be selected by its timelock, once it's available.
+        assert_eq!(
+            control.create_recovery(dummy_addr.clone(), 1, Some(20)),
+            Err(CommandError::RecoveryNotAvailable)
+        );
+        db_conn.new_unspent_coins(&[coin(2, Some(80))]);
+        assert!(control.get_info().recovery_paths[1].available);
+        let tx = control
+            .create_recovery(dummy_addr.clone(), 1, Some(20))
+            .unwrap()
+            .psbt
+            .unsigned_tx;
+        assert_eq!(tx.input.len(), 1);
+        assert_eq!(tx.input[0].previous_output.vout, 2);
+        assert_eq!(tx.input[0].sequence, Sequence::from_height(20));
+
+        // There is no recovery path with another timelock.
+        assert_eq!(
+            control.create_recovery(dummy_addr, 1, Some(15)),
+            Err(CommandError::UnknownRecoveryPath(15))
+        );
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn update_spend() {
+        let dummy_op_a = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let dummy_op_b = bitcoin::OutPoint::from_str(
+            "4753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:1",
+        )
+        .unwrap();
+        let mut dummy_bitcoind = DummyBitcoind::new();
+        let dummy_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime(0),
+            input: vec![],
+            output: vec![],
+        };
+        dummy_bitcoind
+            .txs
+            .insert(dummy_op_a.txid, (dummy_tx.clone(), None));
+        dummy_bitcoind.txs.insert(dummy_op_b.txid, (dummy_tx, None));
+        let ms = DummyLiana::new(dummy_bitcoind, DummyDatabase::new());
+        let control = &ms.handle.control;
+        let mut db_conn = control.db().lock().unwrap().connection();
+
+        // Add two (unconfirmed) coins in DB
+        db_conn.new_unspent_coins(&[
+            Coin {
+                outpoint: dummy_op_a,
+                block_height: None,
+                block_time: None,
+                amount: bitcoin::Amount::from_sat(100_000),
+                derivation_index: bip32::ChildNumber::from(13),
+                is_change: false,
+                spend_txid: None,
+                spend_block: None,
+                spend_broadcast_time: None,
+                is_frozen: false,
+            },
+            Coin {
+                outpoint: dummy_op_b,
+                block_height: None,
+                block_time: None,
+                amount: bitcoin::Amount::from_sat(115_680),
+                derivation_index: bip32::ChildNumber::from(34),
+                is_change: false,
+                spend_txid: None,
+                spend_block: None,
+                spend_broadcast_time: None,
+                is_frozen: false,
+            },
+        ]);
+
+        // Now create three transactions spending those coins differently
+        let dummy_addr_a =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let dummy_addr_b =
+            bitcoin::Address::from_str("bc1q39srgatmkp6k2ne3l52yhkjprdvunvspqydmkx").unwrap();
+        let dummy_value_a = 50_000;
+        let dummy_value_b = 60_000;
+        let destinations_a: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr_a.clone(), dummy_value_a)]
+                .iter()
+                .cloned()
+                .collect();
+        let destinations_b: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr_b.clone(), dummy_value_b)]
+                .iter()
+                .cloned()
+                .collect();
+        let destinations_c: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr_a, dummy_value_a), (dummy_addr_b, dummy_value_b)]
+                .iter()
+                .cloned()
+                .collect();
+        let mut psbt_a = control
+            .create_spend(&destinations_a, &[dummy_op_a], 1, None, None)
+            .unwrap()
+            .psbt;
+        let txid_a = psbt_a.unsigned_tx.txid();
+        let psbt_b = control
+            .create_spend(&destinations_b, &[dummy_op_b], 10, None, None)
+            .unwrap()
+            .psbt;
+        let txid_b = psbt_b.unsigned_tx.txid();
+        let psbt_c = control
+            .create_spend(&destinations_c, &[dummy_op_a, dummy_op_b], 100, None, None)
+            .unwrap()
+            .psbt;
+        let txid_c = psbt_c.unsigned_tx.txid();
+
+        // We can store and query them all
+        control.update_spend(psbt_a.clone()).unwrap();
+        assert_eq!(db_conn.spend_tx(&txid_a).unwrap(), psbt_a);
+        control.update_spend(psbt_b.clone()).unwrap();
+        assert_eq!(db_conn.spend_tx(&txid_b).unwrap(), psbt_b);
+        control.update_spend(psbt_c.clone()).unwrap();
+        assert_eq!(db_conn.spend_tx(&txid_c).unwrap(), psbt_c);
+
+        // As well as update them, with or without new signatures
+        let sig = bitcoin::EcdsaSig::from_str("304402204004fcdbb9c0d0cbf585f58cee34dccb012efbd8fc2b0d5e97760045ae35803802201a0bd7ec2383e0b93748abc9946c8e17a8312e314dab85982aeba650e738cbf401").unwrap();
+        psbt_a.inputs[0].partial_sigs.insert(
+            bitcoin::PublicKey::from_str(
+                "023a664c5617412f0b292665b1fd9d766456a7a3b1614c7e7c5f411200ff1958ef",
+            )
+            .unwrap(),
+            sig,
+        );
+        control.update_spend(psbt_a.clone()).unwrap();
+        assert_eq!(db_conn.spend_tx(&txid_a).unwrap(), psbt_a);
+        control.update_spend(psbt_b.clone()).unwrap();
+        assert_eq!(db_conn.spend_tx(&txid_b).unwrap(), psbt_b);
+        control.update_spend(psbt_c.clone()).unwrap();
+        assert_eq!(db_conn.spend_tx(&txid_c).unwrap(), psbt_c);
+
+        // We can't store a PSBT spending an external coin
+        let external_op = bitcoin::OutPoint::from_str(
+            "8753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:2",
+        )
+        .unwrap();
+        psbt_a.unsigned_tx.input[0].previous_output = external_op;
+        assert_eq!(
+            control.update_spend(psbt_a),
+            Err(CommandError::UnknownOutpoint(external_op))
+        );
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn sign_spend_merges_signature() {
+        let dummy_op = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let ms = DummyLiana::with_coins(
+            DummyBitcoind::new(),
+            &[Coin {
+                derivation_index: bip32::ChildNumber::from(13),
+                ..dummy_coin(dummy_op, 100_000)
+            }],
+        );
+        let control = &ms.handle.control;
+        let dummy_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let destinations: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr, 50_000)].iter().cloned().collect();
+        let psbt = control
+            .create_spend(&destinations, &[dummy_op], 1, None, None)
+            .unwrap()
+            .psbt;
+        let txid = psbt.unsigned_tx.txid();
+
+        // A first signer already signed the stored Spend.
+        let pubkey_a = bitcoin::PublicKey::from_str(
+            "023a664c5617412f0b292665b1fd9d766456a7a3b1614c7e7c5f411200ff1958ef",
+        )
+        .unwrap();
+        let pubkey_b = bitcoin::PublicKey::from_str(
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        )
+        .unwrap();
+        // The signatures aren't checked, so we can get away with using the same.
+        let sig = bitcoin::EcdsaSig::from_str("304402204004fcdbb9c0d0cbf585f58cee34dccb012efbd8fc2b0d5e97760045ae35803802201a0bd7ec2383e0b93748abc9946c8e17a8312e314dab85982aeba650e738cbf401").unwrap();
+        let mut psbt_a = psbt;
+        psbt_a.inputs[0].partial_sigs.insert(pubkey_a, sig);
+        control.update_spend(psbt_a).unwrap();
+
+        // The GUI loads the stored Spend and has a signing device sign it. Like some devices do,
+        // the stub signer only returns its own signature.
+        let stored_psbt = control
+            .list_spend(None)
+            .spend_txs
+            .into_iter()
+            .map(|entry| entry.psbt)
+            .find(|psbt| psbt.unsigned_tx.txid() == txid)
+            .unwrap();
+        let stub_signer = |mut psbt: Psbt| {
+            psbt.inputs[0].partial_sigs.clear();
+            psbt.inputs[0].partial_sigs.insert(pubkey_b, sig);
+            psbt
+        };
+        control.update_spend(stub_signer(stored_psbt)).unwrap();
+
+        // The new signature was merged with the existing one.
+        let merged_psbt = control
+            .list_spend(None)
+            .spend_txs
+            .into_iter()
+            .map(|entry| entry.psbt)
+            .find(|psbt| psbt.unsigned_tx.txid() == txid)
+            .unwrap();
+        let partial_sigs = &merged_psbt.inputs[0].partial_sigs;
+        assert_eq!(partial_sigs.len(), 2);
+        assert_eq!(partial_sigs.get(&pubkey_a), Some(&sig));
+        assert_eq!(partial_sigs.get(&pubkey_b), Some(&sig));
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn update_spend_address_reuse() {
+        let dummy_op_a = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let dummy_op_b = bitcoin::OutPoint::from_str(
+            "4753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:1",
+        )
+        .unwrap();
+        let mut dummy_bitcoind = DummyBitcoind::new();
+        let dummy_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime(0),
+            input: vec![],
+            output: vec![],
+        };
+        dummy_bitcoind
+            .txs
+            .insert(dummy_op_a.txid, (dummy_tx.clone(), None));
+        dummy_bitcoind.txs.insert(dummy_op_b.txid, (dummy_tx, None));
+        // Our first deposit address, which already received a coin.
+        let used_addr = bitcoin::Address::from_str(
+            "bc1q9ksrc647hx8zp2cewl8p5f487dgux3777yees8rjcx46t4daqzzqt7yga8",
+        )
+        .unwrap();
+        let mut db = DummyDatabase::new();
+        db.insert_address(used_addr.clone(), 0.into(), false);
+        let ms = DummyLiana::new(dummy_bitcoind, db);
+        let control = &ms.handle.control;
+        let mut db_conn = control.db().lock().unwrap().connection();
+        let coin = |outpoint, index: u32| Coin {
+            outpoint,
+            block_height: None,
+            block_time: None,
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(index),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        };
+        db_conn.new_unspent_coins(&[coin(dummy_op_a, 0), coin(dummy_op_b, 13)]);
+
+        // A Spend paying to a fresh external address doesn't raise any warning.
+        let dummy_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let destinations: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr.clone(), 50_000)].iter().cloned().collect();
+        let psbt_a = control
+            .create_spend(&destinations, &[dummy_op_a], 1, None, None)
+            .unwrap()
+            .psbt;
+        let txid_a = psbt_a.unsigned_tx.txid();
+        let res = control.update_spend(psbt_a.clone()).unwrap();
+        assert!(res.warnings.is_empty());
+        // Updating it doesn't either, it isn't reusing its own address.
+        let res = control.update_spend(psbt_a).unwrap();
+        assert!(res.warnings.is_empty());
+
+        // Another Spend paying to the same external address is stored, but with a warning.
+        let psbt_b = control
+            .create_spend(&destinations, &[dummy_op_b], 1, None, None)
+            .unwrap()
+            .psbt;
+        let txid_b = psbt_b.unsigned_tx.txid();
+        let res = control.update_spend(psbt_b).unwrap();
+        assert_eq!(res.warnings.len(), 1);
+        assert!(res.warnings[0].contains(&dummy_addr.to_string()));
+        assert!(res.warnings[0].contains(&txid_a.to_string()));
+        assert!(db_conn.spend_tx(&txid_b).is_some());
+        db_conn.delete_spend(&txid_b);
+
+        // Same for a Spend paying to one of our addresses which already received coins.
+        let destinations: HashMap<bitcoin::Address, u64> =
+            [(used_addr.clone(), 50_000)].iter().cloned().collect();
+        let psbt_c = control
+            .create_spend(&destinations, &[dummy_op_b], 1, None, None)
+            .unwrap()
+            .psbt;
+        let res = control.update_spend(psbt_c).unwrap();
+        assert_eq!(res.warnings.len(), 1);
+        assert!(res.warnings[0].contains(&used_addr.to_string()));
+        assert!(res.warnings[0].contains("already received coins"));
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn finalize_spend() {
+        let dummy_op = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let mut dummy_bitcoind = DummyBitcoind::new();
+        dummy_bitcoind.txs.insert(
+            dummy_op.txid,
+            (
+                bitcoin::Transaction {
+                    version: 2,
+                    lock_time: bitcoin::PackedLockTime(0),
+                    input: vec![],
+                    output: vec![],
+                },
+                None,
+            ),
+        );
+        let ms = DummyLiana::new(dummy_bitcoind, DummyDatabase::new());
+        let control = &ms.handle.control;
+        let mut db_conn = control.db().lock().unwrap().connection();
+        db_conn.new_unspent_coins(&[Coin {
+            outpoint: dummy_op,
+            block_height: None,
+            block_time: None,
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(13),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        }]);
+        let dummy_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let destinations: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr, 50_000)].iter().cloned().collect();
+        let psbt = control
+            .create_spend(&destinations, &[dummy_op], 1, None, None)
+            .unwrap()
+            .psbt;
+        let txid = psbt.unsigned_tx.txid();
+
+        // We can't finalize an unknown Spend.
+        assert_eq!(
+            control.finalize_spend(&txid),
+            Err(CommandError::UnknownSpend(txid))
+        );
+
+        // Nor one which isn't signed. It fails the same way as when broadcasting, and doesn't
+        // mark the coin as spent.
+        control.update_spend(psbt).unwrap();
+        assert!(matches!(
+            control.finalize_spend(&txid),
+            Err(CommandError::SpendFinalization(_))
+        ));
+        assert!(matches!(
+            control.broadcast_spend(&txid),
+            Err(CommandError::SpendFinalization(_))
+        ));
+        assert!(db_conn.coins_by_outpoints(&[dummy_op])[&dummy_op]
+            .spend_txid
+            .is_none());
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn broadcast_spend_invalid_signature() {
+        let dummy_op = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let mut dummy_bitcoind = DummyBitcoind::new();
+        dummy_bitcoind.txs.insert(
+            dummy_op.txid,
+            (
+                bitcoin::Transaction {
+                    version: 2,
+                    lock_time: bitcoin::PackedLockTime(0),
+                    input: vec![],
+                    output: vec![],
+                },
+                None,
+            ),
+        );
+        let ms = DummyLiana::new(dummy_bitcoind, DummyDatabase::new());
+        let control = &ms.handle.control;
+        let mut db_conn = control.db().lock().unwrap().connection();
+        db_conn.new_unspent_coins(&[Coin {
+            outpoint: dummy_op,
+            block_height: None,
+            block_time: None,
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(13),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        }]);
+        let dummy_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let destinations: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr, 50_000)].iter().cloned().collect();
+        let mut psbt = control
+            .create_spend(&destinations, &[dummy_op], 1, None, None)
+            .unwrap()
+            .psbt;
+        let txid = psbt.unsigned_tx.txid();
+
+        // A well-formed signature, but for another message. Note the dummy bitcoind would panic
+        // if we ever tried to broadcast.
+        let secp = bitcoin::secp256k1::Secp256k1::signing_only();
+        let bad_sig = bitcoin::EcdsaSig {
+            sig: secp.sign_ecdsa(
+                &bitcoin::secp256k1::Message::from_slice(&[1; 32]).unwrap(),
+                &bitcoin::secp256k1::SecretKey::from_slice(&[2; 32]).unwrap(),
+            ),
+            hash_ty: bitcoin::EcdsaSighashType::All,
+        };
+
+        // A corrupted partial signature is refused before broadcast.
+        let mut corrupted_psbt = psbt.clone();
+        let keys: Vec<_> = corrupted_psbt.inputs[0]
+            .bip32_derivation
+            .keys()
+            .cloned()
+            .collect();
+        for key in keys {
+            corrupted_psbt.inputs[0]
+                .partial_sigs
+                .insert(bitcoin::PublicKey::new(key), bad_sig);
+        }
+        control.update_spend(corrupted_psbt).unwrap();
+        assert!(control.broadcast_spend(&txid).is_err());
+        control.delete_spend(&txid);
+
+        // So is a transaction finalized by another software with an invalid signature.
+        let coin_desc = control
+            .config
+            .main_descriptor
+            .receive_descriptor()
+            .derive(13.into(), &control.secp);
+        let mut tx = psbt.unsigned_tx.clone();
+        tx.input[0].witness = bitcoin::Witness::from_vec(vec![
+            bad_sig.to_vec(),
+            coin_desc.witness_script().unwrap().to_bytes(),
+        ]);
+        control
+            .update_spend_encoded(&bitcoin::consensus::encode::serialize_hex(&tx))
+            .unwrap();
+        assert!(matches!(
+            control.finalize_spend(&txid),
+            Err(CommandError::SpendVerification(_))
+        ));
+        assert!(matches!(
+            control.broadcast_spend(&txid),
+            Err(CommandError::SpendVerification(_))
+        ));
+        assert!(db_conn.coins_by_outpoints(&[dummy_op])[&dummy_op]
+            .spend_txid
+            .is_none());
+
+        // Missing the witness utxo, we can't verify it.
+        control.delete_spend(&txid);
+        psbt.inputs[0].final_script_witness = Some(tx.input[0].witness.clone());
+        psbt.inputs[0].witness_utxo = None;
+        control.update_spend(psbt).unwrap();
+        assert!(matches!(
+            control.broadcast_spend(&txid),
+            Err(CommandError::SpendVerification(_))
+        ));
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn update_spend_encoded() {
+        let dummy_op = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let mut dummy_bitcoind = DummyBitcoind::new();
+        dummy_bitcoind.txs.insert(
+            dummy_op.txid,
+            (
+                bitcoin::Transaction {
+                    version: 2,
+                    lock_time: bitcoin::PackedLockTime(0),
+                    input: vec![],
+                    output: vec![],
+                },
+                None,
+            ),
+        );
+        let ms = DummyLiana::new(dummy_bitcoind, DummyDatabase::new());
+        let control = &ms.handle.control;
+        let mut db_conn = control.db().lock().unwrap().connection();
+        db_conn.new_unspent_coins(&[Coin {
+            outpoint: dummy_op,
+            block_height: None,
+            block_time: None,
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(13),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        }]);
+        let dummy_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let destinations: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr, 50_000)].iter().cloned().collect();
+        let psbt = control
+            .create_spend(&destinations, &[dummy_op], 1, None, None)
+            .unwrap()
+            .psbt;
+        let txid = psbt.unsigned_tx.txid();
+
+        // A raw transaction is accepted for a Spend we don't know yet. The information about our
+        // coin is filled in the created PSBT.
+        let unsigned_tx_hex = encode::serialize_hex(&psbt.unsigned_tx);
+        control.update_spend_encoded(&unsigned_tx_hex).unwrap();
+        let stored = db_conn.spend_tx(&txid).unwrap();
+        assert_eq!(stored.inputs[0].witness_utxo, psbt.inputs[0].witness_utxo);
+        assert_eq!(
+            stored.inputs[0].witness_script,
+            psbt.inputs[0].witness_script
+        );
+        db_conn.delete_spend(&txid);
+
+        // So are a base64 and a hex encoded PSBT, which are stored as is.
+        control
+            .update_spend_encoded(&base64::encode(encode::serialize(&psbt)))
+            .unwrap();
+        assert_eq!(db_conn.spend_tx(&txid).unwrap(), psbt);
+        db_conn.delete_spend(&txid);
+        control
+            .update_spend_encoded(&encode::serialize_hex(&psbt))
+            .unwrap();
+        assert_eq!(db_conn.spend_tx(&txid).unwrap(), psbt);
+
+        // A finalized transaction updates the existing Spend with its final scripts.
+        let mut final_tx = psbt.unsigned_tx.clone();
+        final_tx.input[0].witness = bitcoin::Witness::from_vec(vec![vec![1; 72], vec![2; 33]]);
+        control
+            .update_spend_encoded(&encode::serialize_hex(&final_tx))
+            .unwrap();
+        assert_eq!(db_conn.list_spend(None).len(), 1);
+        let stored = db_conn.spend_tx(&txid).unwrap();
+        assert_eq!(
+            stored.inputs[0].final_script_witness,
+            Some(final_tx.input[0].witness.clone())
+        );
+        assert_eq!(
+            stored.inputs[0].bip32_derivation,
+            psbt.inputs[0].bip32_derivation
+        );
+
+        // Anything else is refused.
+        assert_eq!(
+            control.update_spend_encoded("not a psbt"),
+            Err(CommandError::UndecodableSpend)
+        );
+        assert_eq!(
+            control.update_spend_encoded("00"),
+            Err(CommandError::UndecodableSpend)
+        );
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn list_spend_conflicted() {
         let dummy_op_a = bitcoin::OutPoint::from_str(
             "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
         )
@@ -1033,116 +5575,482 @@ mod tests {
         )
         .unwrap();
         let mut dummy_bitcoind = DummyBitcoind::new();
-        let dummy_tx = bitcoin::Transaction {
-            version: 2,
-            lock_time: bitcoin::PackedLockTime(0),
-            input: vec![],
-            output: vec![],
-        };
-        dummy_bitcoind
-            .txs
-            .insert(dummy_op_a.txid, (dummy_tx.clone(), None));
-        dummy_bitcoind.txs.insert(dummy_op_b.txid, (dummy_tx, None));
+        let dummy_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime(0),
+            input: vec![],
+            output: vec![],
+        };
+        dummy_bitcoind
+            .txs
+            .insert(dummy_op_a.txid, (dummy_tx.clone(), None));
+        dummy_bitcoind.txs.insert(dummy_op_b.txid, (dummy_tx, None));
+        let ms = DummyLiana::new(dummy_bitcoind, DummyDatabase::new());
+        let control = &ms.handle.control;
+        let mut db_conn = control.db().lock().unwrap().connection();
+        let coin = |outpoint| Coin {
+            outpoint,
+            block_height: None,
+            block_time: None,
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(13),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        };
+        db_conn.new_unspent_coins(&[coin(dummy_op_a), coin(dummy_op_b)]);
+
+        // Store a Spend for the first coin, and another one spending both.
+        let dummy_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let destinations: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr, 50_000)].iter().cloned().collect();
+        let psbt_a = control
+            .create_spend(&destinations, &[dummy_op_a], 1, None, None)
+            .unwrap()
+            .psbt;
+        let txid_a = psbt_a.unsigned_tx.txid();
+        let psbt_ab = control
+            .create_spend(&destinations, &[dummy_op_a, dummy_op_b], 1, None, None)
+            .unwrap()
+            .psbt;
+        let txid_ab = psbt_ab.unsigned_tx.txid();
+        control.update_spend(psbt_a).unwrap();
+        control.update_spend(psbt_ab).unwrap();
+        let conflicted = |txid| {
+            control
+                .list_spend(None)
+                .spend_txs
+                .into_iter()
+                .find(|entry| entry.psbt.unsigned_tx.txid() == txid)
+                .unwrap()
+                .conflicted
+        };
+        assert!(!conflicted(txid_a));
+        assert!(!conflicted(txid_ab));
+
+        // The first coin is spent by the first Spend. As long as it's unconfirmed, the second
+        // Spend isn't conflicted as it could still replace it.
+        db_conn.spend_coins(&[(dummy_op_a, txid_a)]);
+        assert!(!conflicted(txid_a));
+        assert!(!conflicted(txid_ab));
+
+        // Once it's confirmed the second one is.
+        db_conn.confirm_spend(&[(dummy_op_a, txid_a, 101, 1_112_000)]);
+        assert!(!conflicted(txid_a));
+        assert!(conflicted(txid_ab));
+
+        // Spends can be filtered by the time of their last update.
+        let spends = control.list_spend(None).spend_txs;
+        assert_eq!(spends.len(), 2);
+        let last_update = spends.iter().map(|entry| entry.updated_at).max().unwrap();
+        let last_updated = spends
+            .iter()
+            .filter(|entry| entry.updated_at == last_update)
+            .count();
+        assert_eq!(
+            control.list_spend(Some(last_update)).spend_txs.len(),
+            last_updated
+        );
+        assert!(control
+            .list_spend(Some(last_update + 1))
+            .spend_txs
+            .is_empty());
+        assert!(control
+            .list_spend_page(Some(last_update + 1), 0, 10)
+            .spend_txs
+            .is_empty());
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn list_spend_info() {
+        let dummy_op = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let mut dummy_bitcoind = DummyBitcoind::new();
+        dummy_bitcoind.txs.insert(
+            dummy_op.txid,
+            (
+                bitcoin::Transaction {
+                    version: 2,
+                    lock_time: bitcoin::PackedLockTime(0),
+                    input: vec![],
+                    output: vec![],
+                },
+                None,
+            ),
+        );
+        let ms = DummyLiana::new(dummy_bitcoind, DummyDatabase::new());
+        let control = &ms.handle.control;
+        let mut db_conn = control.db().lock().unwrap().connection();
+        db_conn.new_unspent_coins(&[Coin {
+            outpoint: dummy_op,
+            block_height: None,
+            block_time: None,
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(13),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        }]);
+
+        // Create a Spend with a change output and store it.
+        let dummy_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let destinations: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr.clone(), 50_000)].iter().cloned().collect();
+        let psbt = control
+            .create_spend(&destinations, &[dummy_op], 2, None, None)
+            .unwrap()
+            .psbt;
+        control.update_spend(psbt.clone()).unwrap();
+
+        // The decoded model must match the created transaction.
+        let spends = control.list_spend(None).spend_txs;
+        assert_eq!(spends.len(), 1);
+        let info = &spends[0].info;
+        assert_eq!(
+            info.inputs,
+            vec![SpendTxInput {
+                outpoint: dummy_op,
+                value: Some(100_000),
+                is_ours: true,
+            }]
+        );
+        assert_eq!(info.outputs.len(), 2);
+        let dest_out = info
+            .outputs
+            .iter()
+            .find(|o| o.address.as_ref() == Some(&dummy_addr))
+            .unwrap();
+        assert_eq!(dest_out.value, 50_000);
+        assert!(!dest_out.is_change);
+        let change_out = info.outputs.iter().find(|o| o.is_change).unwrap();
+        let change_index = psbt
+            .outputs
+            .iter()
+            .position(|o| !o.bip32_derivation.is_empty())
+            .unwrap();
+        assert_eq!(
+            change_out.value,
+            psbt.unsigned_tx.output[change_index].value
+        );
+        let out_value: u64 = psbt.unsigned_tx.output.iter().map(|o| o.value).sum();
+        assert_eq!(info.fee, Some(100_000 - out_value));
+        assert_eq!(info.feerate_vb, Some(2));
+        assert_eq!(
+            info.signing_status,
+            SigningStatus {
+                inputs: vec![InputSigningStatus {
+                    signatures: 0,
+                    threshold: 1,
+                }],
+                finalizable: false,
+            }
+        );
+
+        // Signatures by the primary key are accounted for. The value of an input which isn't
+        // ours is taken from the PSBT, and the fee is unknown if it's missing.
+        let mut other_psbt = psbt.clone();
+        let (primary_fg, _) = control
+            .config
+            .main_descriptor
+            .info()
+            .primary_path()
+            .keys_origins()[0]
+            .clone();
+        let primary_key = other_psbt.inputs[0]
+            .bip32_derivation
+            .iter()
+            .find(|(_, (fg, _))| *fg == primary_fg)
+            .map(|(key, _)| *key)
+            .unwrap();
+        let sig = bitcoin::EcdsaSig::from_str("304402204004fcdbb9c0d0cbf585f58cee34dccb012efbd8fc2b0d5e97760045ae35803802201a0bd7ec2383e0b93748abc9946c8e17a8312e314dab85982aeba650e738cbf401").unwrap();
+        other_psbt.inputs[0]
+            .partial_sigs
+            .insert(bitcoin::PublicKey::new(primary_key), sig);
+        let other_op = bitcoin::OutPoint::from_str(
+            "4753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:1",
+        )
+        .unwrap();
+        other_psbt.unsigned_tx.input.push(bitcoin::TxIn {
+            previous_output: other_op,
+            ..bitcoin::TxIn::default()
+        });
+        other_psbt.inputs.push(PsbtIn::default());
+        let coins = db_conn.coins_by_outpoints(&[dummy_op, other_op]);
+        let other_info = spend_tx_info(
+            &mut db_conn,
+            &control.config.main_descriptor,
+            control.config.bitcoin_config.network,
+            &other_psbt,
+            &coins,
+        );
+        let signatures: Vec<usize> = other_info
+            .signing_status
+            .inputs
+            .iter()
+            .map(|i| i.signatures)
+            .collect();
+        assert_eq!(signatures, vec![1, 0]);
+        assert!(!other_info.signing_status.finalizable);
+        assert_eq!(
+            other_info.inputs[1],
+            SpendTxInput {
+                outpoint: other_op,
+                value: None,
+                is_ours: false,
+            }
+        );
+        assert_eq!(other_info.fee, None);
+        assert_eq!(other_info.feerate_vb, None);
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn spend_signing_status() {
+        let dummy_op = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let mut dummy_bitcoind = DummyBitcoind::new();
+        dummy_bitcoind.txs.insert(
+            dummy_op.txid,
+            (
+                bitcoin::Transaction {
+                    version: 2,
+                    lock_time: bitcoin::PackedLockTime(0),
+                    input: vec![],
+                    output: vec![],
+                },
+                None,
+            ),
+        );
+
+        // A 2-of-2 primary path, with a single key recovery path.
+        let key = |xpub: &str| {
+            miniscript::descriptor::DescriptorPublicKey::from_str(&format!("{}/<0;1>/*", xpub))
+                .unwrap()
+        };
+        let primary_keys = vec![
+            key("xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW"),
+            key("xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe"),
+        ];
+        let mut recovery_paths = BTreeMap::new();
+        recovery_paths.insert(
+            10_000,
+            descriptors::PathInfo::Single(key("xpub661MyMwAqRbcFG59fiikD8UV762quhruT8K8bdjqy6N2o3LG7yohoCdLg1m2HAY1W6rfBrtauHkBhbfA4AQ3iazaJj5wVPhwgaRCHBW2DBg")),
+        );
+        let desc = descriptors::MultipathDescriptor::new(
+            descriptors::PathInfo::Multi(2, primary_keys),
+            recovery_paths,
+        )
+        .unwrap();
+        let primary_fg = desc.info().primary_path().keys_origins()[0].0;
+        let ms = DummyLiana::with_descriptor(dummy_bitcoind, DummyDatabase::new(), desc);
+        let control = &ms.handle.control;
+        let mut db_conn = control.db().lock().unwrap().connection();
+        db_conn.new_unspent_coins(&[Coin {
+            outpoint: dummy_op,
+            block_height: None,
+            block_time: None,
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(13),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        }]);
+        let dummy_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let destinations: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr, 50_000)].iter().cloned().collect();
+        let mut psbt = control
+            .create_spend(&destinations, &[dummy_op], 1, None, None)
+            .unwrap()
+            .psbt;
+        let txid = psbt.unsigned_tx.txid();
+
+        // We don't know about this Spend yet.
+        assert!(control.spend_signing_status(&txid).is_none());
+        control.update_spend(psbt.clone()).unwrap();
+        assert_eq!(
+            control.spend_signing_status(&txid).unwrap(),
+            SigningStatus {
+                inputs: vec![InputSigningStatus {
+                    signatures: 0,
+                    threshold: 2,
+                }],
+                finalizable: false,
+            }
+        );
+
+        // Add a signature for one of the primary keys. It's now signed 1-of-2, still not enough to
+        // finalize it.
+        let sig = bitcoin::EcdsaSig::from_str("304402204004fcdbb9c0d0cbf585f58cee34dccb012efbd8fc2b0d5e97760045ae35803802201a0bd7ec2383e0b93748abc9946c8e17a8312e314dab85982aeba650e738cbf401").unwrap();
+        let signer = |psbt: &Psbt, fg: bip32::Fingerprint| {
+            psbt.inputs[0]
+                .bip32_derivation
+                .iter()
+                .find(|(_, (key_fg, _))| *key_fg == fg)
+                .map(|(key, _)| bitcoin::PublicKey::new(*key))
+                .unwrap()
+        };
+        let primary_key = signer(&psbt, primary_fg);
+        psbt.inputs[0].partial_sigs.insert(primary_key, sig);
+        control.update_spend(psbt.clone()).unwrap();
+        assert_eq!(
+            control.spend_signing_status(&txid).unwrap(),
+            SigningStatus {
+                inputs: vec![InputSigningStatus {
+                    signatures: 1,
+                    threshold: 2,
+                }],
+                finalizable: false,
+            }
+        );
+
+        // A signature by the recovery key doesn't count toward the primary path.
+        let recovery_fg = control
+            .config
+            .main_descriptor
+            .info()
+            .recovery_paths()
+            .values()
+            .next()
+            .unwrap()
+            .keys_origins()[0]
+            .0;
+        let recovery_key = signer(&psbt, recovery_fg);
+        psbt.inputs[0].partial_sigs.insert(recovery_key, sig);
+        let status = signing_status(&control.config.main_descriptor, &psbt);
+        assert_eq!(status.inputs[0].signatures, 1);
+        assert!(!status.finalizable);
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn combine_psbts() {
+        let dummy_op = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let mut dummy_bitcoind = DummyBitcoind::new();
+        dummy_bitcoind.txs.insert(
+            dummy_op.txid,
+            (
+                bitcoin::Transaction {
+                    version: 2,
+                    lock_time: bitcoin::PackedLockTime(0),
+                    input: vec![],
+                    output: vec![],
+                },
+                None,
+            ),
+        );
         let ms = DummyLiana::new(dummy_bitcoind, DummyDatabase::new());
         let control = &ms.handle.control;
         let mut db_conn = control.db().lock().unwrap().connection();
-
-        // Add two (unconfirmed) coins in DB
-        db_conn.new_unspent_coins(&[
-            Coin {
-                outpoint: dummy_op_a,
-                block_height: None,
-                block_time: None,
-                amount: bitcoin::Amount::from_sat(100_000),
-                derivation_index: bip32::ChildNumber::from(13),
-                is_change: false,
-                spend_txid: None,
-                spend_block: None,
-            },
-            Coin {
-                outpoint: dummy_op_b,
-                block_height: None,
-                block_time: None,
-                amount: bitcoin::Amount::from_sat(115_680),
-                derivation_index: bip32::ChildNumber::from(34),
-                is_change: false,
-                spend_txid: None,
-                spend_block: None,
-            },
-        ]);
-
-        // Now create three transactions spending those coins differently
-        let dummy_addr_a =
+        db_conn.new_unspent_coins(&[Coin {
+            outpoint: dummy_op,
+            block_height: None,
+            block_time: None,
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(13),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        }]);
+        let dummy_addr =
             bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
-        let dummy_addr_b =
-            bitcoin::Address::from_str("bc1q39srgatmkp6k2ne3l52yhkjprdvunvspqydmkx").unwrap();
-        let dummy_value_a = 50_000;
-        let dummy_value_b = 60_000;
-        let destinations_a: HashMap<bitcoin::Address, u64> =
-            [(dummy_addr_a.clone(), dummy_value_a)]
-                .iter()
-                .cloned()
-                .collect();
-        let destinations_b: HashMap<bitcoin::Address, u64> =
-            [(dummy_addr_b.clone(), dummy_value_b)]
-                .iter()
-                .cloned()
-                .collect();
-        let destinations_c: HashMap<bitcoin::Address, u64> =
-            [(dummy_addr_a, dummy_value_a), (dummy_addr_b, dummy_value_b)]
-                .iter()
-                .cloned()
-                .collect();
-        let mut psbt_a = control
-            .create_spend(&destinations_a, &[dummy_op_a], 1)
-            .unwrap()
-            .psbt;
-        let txid_a = psbt_a.unsigned_tx.txid();
-        let psbt_b = control
-            .create_spend(&destinations_b, &[dummy_op_b], 10)
-            .unwrap()
-            .psbt;
-        let txid_b = psbt_b.unsigned_tx.txid();
-        let psbt_c = control
-            .create_spend(&destinations_c, &[dummy_op_a, dummy_op_b], 100)
+        let destinations: HashMap<bitcoin::Address, u64> =
+            [(dummy_addr, 50_000)].iter().cloned().collect();
+        let psbt = control
+            .create_spend(&destinations, &[dummy_op], 1, None, None)
             .unwrap()
             .psbt;
-        let txid_c = psbt_c.unsigned_tx.txid();
-
-        // We can store and query them all
-        control.update_spend(psbt_a.clone()).unwrap();
-        assert_eq!(db_conn.spend_tx(&txid_a).unwrap(), psbt_a);
-        control.update_spend(psbt_b.clone()).unwrap();
-        assert_eq!(db_conn.spend_tx(&txid_b).unwrap(), psbt_b);
-        control.update_spend(psbt_c.clone()).unwrap();
-        assert_eq!(db_conn.spend_tx(&txid_c).unwrap(), psbt_c);
-
-        // As well as update them, with or without new signatures
-        let sig = bitcoin::EcdsaSig::from_str("304402204004fcdbb9c0d0cbf585f58cee34dccb012efbd8fc2b0d5e97760045ae35803802201a0bd7ec2383e0b93748abc9946c8e17a8312e314dab85982aeba650e738cbf401").unwrap();
-        psbt_a.inputs[0].partial_sigs.insert(
-            bitcoin::PublicKey::from_str(
-                "023a664c5617412f0b292665b1fd9d766456a7a3b1614c7e7c5f411200ff1958ef",
-            )
-            .unwrap(),
-            sig,
-        );
-        control.update_spend(psbt_a.clone()).unwrap();
-        assert_eq!(db_conn.spend_tx(&txid_a).unwrap(), psbt_a);
-        control.update_spend(psbt_b.clone()).unwrap();
-        assert_eq!(db_conn.spend_tx(&txid_b).unwrap(), psbt_b);
-        control.update_spend(psbt_c.clone()).unwrap();
-        assert_eq!(db_conn.spend_tx(&txid_c).unwrap(), psbt_c);
-
-        // We can't store a PSBT spending an external coin
-        let external_op = bitcoin::OutPoint::from_str(
-            "8753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:2",
+        let txid = psbt.unsigned_tx.txid();
+
+        // We need at least one PSBT.
+        assert!(matches!(
+            control.combine_psbts(&[]),
+            Err(CommandError::PsbtCombination(_))
+        ));
+
+        // Each signer provides a PSBT with their own signature and key origin, and is missing
+        // some information another signer has.
+        let pubkey_a = bitcoin::PublicKey::from_str(
+            "023a664c5617412f0b292665b1fd9d766456a7a3b1614c7e7c5f411200ff1958ef",
         )
         .unwrap();
-        psbt_a.unsigned_tx.input[0].previous_output = external_op;
+        let pubkey_b = bitcoin::PublicKey::from_str(
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        )
+        .unwrap();
+        let sig_a = bitcoin::EcdsaSig::from_str("304402204004fcdbb9c0d0cbf585f58cee34dccb012efbd8fc2b0d5e97760045ae35803802201a0bd7ec2383e0b93748abc9946c8e17a8312e314dab85982aeba650e738cbf401").unwrap();
+        // The signatures aren't checked, so we can get away with using the same.
+        let sig_b = sig_a;
+        let origin_a = (
+            bip32::Fingerprint::from_str("aabbccdd").unwrap(),
+            bip32::DerivationPath::from_str("m/0/13").unwrap(),
+        );
+        let origin_b = (
+            bip32::Fingerprint::from_str("11223344").unwrap(),
+            bip32::DerivationPath::from_str("m/0/13").unwrap(),
+        );
+
+        let mut psbt_a = psbt.clone();
+        psbt_a.inputs[0].partial_sigs.insert(pubkey_a, sig_a);
+        psbt_a.inputs[0]
+            .bip32_derivation
+            .insert(pubkey_a.inner, origin_a.clone());
+        psbt_a.outputs[1].bip32_derivation.clear();
+        let mut psbt_b = psbt.clone();
+        psbt_b.inputs[0].partial_sigs.insert(pubkey_b, sig_b);
+        psbt_b.inputs[0]
+            .bip32_derivation
+            .insert(pubkey_b.inner, origin_b.clone());
+        psbt_b.inputs[0].witness_script = None;
+
+        // Combining them unions all the fields, and stores the result.
+        let combined = control.combine_psbts(&[psbt_a.clone(), psbt_b]).unwrap();
+        let psbtin = &combined.inputs[0];
+        assert_eq!(psbtin.partial_sigs.get(&pubkey_a), Some(&sig_a));
+        assert_eq!(psbtin.partial_sigs.get(&pubkey_b), Some(&sig_b));
         assert_eq!(
-            control.update_spend(psbt_a),
-            Err(CommandError::UnknownOutpoint(external_op))
+            psbtin.bip32_derivation.get(&pubkey_a.inner),
+            Some(&origin_a)
+        );
+        assert_eq!(
+            psbtin.bip32_derivation.get(&pubkey_b.inner),
+            Some(&origin_b)
         );
+        for (key, origin) in psbt.inputs[0].bip32_derivation.iter() {
+            assert_eq!(psbtin.bip32_derivation.get(key), Some(origin));
+        }
+        assert_eq!(psbtin.witness_script, psbt.inputs[0].witness_script);
+        assert_eq!(combined.outputs, psbt.outputs);
+        assert_eq!(db_conn.spend_tx(&txid).unwrap(), combined);
+
+        // We can't combine PSBTs for different transactions.
+        let mut other_psbt = psbt;
+        other_psbt.unsigned_tx.output[0].value += 1;
+        assert!(matches!(
+            control.combine_psbts(&[psbt_a, other_psbt]),
+            Err(CommandError::PsbtCombination(_))
+        ));
 
         ms.shutdown();
     }
@@ -1239,6 +6147,8 @@ mod tests {
                 derivation_index: ChildNumber::from(0),
                 amount: bitcoin::Amount::from_sat(100_000_000),
                 spend_txid: Some(spend_tx.txid()),
+                spend_broadcast_time: None,
+                is_frozen: false,
             },
             // Deposit 2
             Coin {
@@ -1253,6 +6163,8 @@ mod tests {
                 derivation_index: ChildNumber::from(1),
                 amount: bitcoin::Amount::from_sat(2000),
                 spend_txid: None,
+                spend_broadcast_time: None,
+                is_frozen: false,
             },
             // This coin is a change output.
             Coin {
@@ -1264,6 +6176,8 @@ mod tests {
                 derivation_index: ChildNumber::from(2),
                 amount: bitcoin::Amount::from_sat(100_000_000 - 4000 - 1000),
                 spend_txid: None,
+                spend_broadcast_time: None,
+                is_frozen: false,
             },
             // Deposit 3
             Coin {
@@ -1278,6 +6192,8 @@ mod tests {
                 derivation_index: ChildNumber::from(3),
                 amount: bitcoin::Amount::from_sat(3000),
                 spend_txid: None,
+                spend_broadcast_time: None,
+                is_frozen: false,
             },
         ]);
 
@@ -1343,7 +6259,9 @@ mod tests {
 
         let control = &ms.handle.control;
 
-        let transactions = control.list_confirmed_transactions(0, 4, 10).transactions;
+        let transactions = control
+            .list_confirmed_transactions(0, 4, 10, None, None)
+            .transactions;
         assert_eq!(transactions.len(), 4);
 
         assert_eq!(transactions[0].time, Some(4));
@@ -1358,19 +6276,256 @@ mod tests {
         assert_eq!(transactions[3].time, Some(1));
         assert_eq!(transactions[3].tx, deposit1);
 
-        let transactions = control.list_confirmed_transactions(2, 3, 10).transactions;
+        let transactions = control
+            .list_confirmed_transactions(2, 3, 10, None, None)
+            .transactions;
         assert_eq!(transactions.len(), 2);
 
         assert_eq!(transactions[0].time, Some(3));
         assert_eq!(transactions[1].time, Some(2));
         assert_eq!(transactions[1].tx, deposit2);
 
-        let transactions = control.list_confirmed_transactions(2, 3, 1).transactions;
+        let transactions = control
+            .list_confirmed_transactions(2, 3, 1, None, None)
+            .transactions;
         assert_eq!(transactions.len(), 1);
 
         assert_eq!(transactions[0].time, Some(3));
         assert_eq!(transactions[0].tx, spend_tx);
 
+        // We can filter the transactions by the coins they reference. The first deposit was
+        // spent by the spend transaction.
+        let deposit1_op = OutPoint::new(deposit1.txid(), 0);
+        let transactions = control
+            .list_confirmed_transactions(0, 4, 10, Some(&[deposit1_op]), None)
+            .transactions;
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].tx, spend_tx);
+        assert_eq!(transactions[1].tx, deposit1);
+
+        // The filter is applied before the limit.
+        let deposit2_op = OutPoint::new(deposit2.txid(), 0);
+        let transactions = control
+            .list_confirmed_transactions(0, 4, 1, Some(&[deposit2_op, deposit1_op]), None)
+            .transactions;
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].tx, spend_tx);
+        let transactions = control
+            .list_confirmed_transactions(0, 4, 1, Some(&[deposit2_op]), None)
+            .transactions;
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].tx, deposit2);
+
+        // The time window still applies.
+        let transactions = control
+            .list_confirmed_transactions(2, 4, 10, Some(&[deposit1_op]), None)
+            .transactions;
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].tx, spend_tx);
+
+        // An address that isn't ours doesn't match anything.
+        let unknown_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let transactions = control
+            .list_confirmed_transactions(0, 4, 10, None, Some(&[unknown_addr]))
+            .transactions;
+        assert!(transactions.is_empty());
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn list_confirmed_transactions_page() {
+        // Five deposits, three of which were confirmed in the same block.
+        let prev_outpoint = OutPoint::new(
+            Txid::from_str("617eab1fc0b03ee7f82ba70166725291783461f1a0e7975eaf8b5f8f674234f3")
+                .unwrap(),
+            0,
+        );
+        let block_times = [1, 2, 2, 2, 3];
+        let deposits: Vec<Transaction> = (0..block_times.len())
+            .map(|i| Transaction {
+                version: 1,
+                lock_time: PackedLockTime(1),
+                input: vec![TxIn {
+                    witness: Witness::new(),
+                    previous_output: prev_outpoint,
+                    script_sig: Script::new(),
+                    sequence: Sequence(0),
+                }],
+                output: vec![TxOut {
+                    script_pubkey: Script::new(),
+                    value: 1_000 * (i as u64 + 1),
+                }],
+            })
+            .collect();
+
+        let mut db = DummyDatabase::new();
+        let mut btc = DummyBitcoind::new();
+        for (i, (tx, time)) in deposits.iter().zip(block_times.iter()).enumerate() {
+            db.insert_coins(vec![Coin {
+                is_change: false,
+                outpoint: OutPoint::new(tx.txid(), 0),
+                block_time: Some(*time),
+                block_height: Some(*time as i32),
+                spend_block: None,
+                derivation_index: ChildNumber::from(i as u32),
+                amount: bitcoin::Amount::from_sat(tx.output[0].value),
+                spend_txid: None,
+                spend_broadcast_time: None,
+                is_frozen: false,
+            }]);
+            btc.txs.insert(
+                tx.txid(),
+                (
+                    tx.clone(),
+                    Some(Block {
+                        hash: bitcoin::BlockHash::from_str(
+                            "0000000000000000000326b8fca8d3f820647c97ea33ef722096b3c7b2c8ee94",
+                        )
+                        .unwrap(),
+                        time: *time,
+                        height: *time as i32,
+                    }),
+                ),
+            );
+        }
+        let ms = DummyLiana::new(btc, db);
+        let control = &ms.handle.control;
+
+        // The first page stops in the middle of the transactions sharing a block time.
+        let first_page = control.list_confirmed_transactions_page(None, 3);
+        assert_eq!(first_page.transactions.len(), 3);
+        assert_eq!(first_page.transactions[0].time, Some(3));
+        assert_eq!(first_page.transactions[1].time, Some(2));
+        assert_eq!(first_page.transactions[2].time, Some(2));
+        let cursor = first_page.next_cursor.unwrap();
+        assert_eq!(cursor.1, first_page.transactions[2].tx.txid());
+
+        // The second page has the rest of them, without overlap.
+        let second_page = control.list_confirmed_transactions_page(Some(cursor), 3);
+        assert_eq!(second_page.transactions.len(), 2);
+        assert_eq!(second_page.transactions[0].time, Some(2));
+        assert_eq!(second_page.transactions[1].time, Some(1));
+        assert!(second_page.next_cursor.is_none());
+        let mut txids: Vec<Txid> = first_page
+            .transactions
+            .iter()
+            .chain(second_page.transactions.iter())
+            .map(|t| t.tx.txid())
+            .collect();
+        txids.sort();
+        txids.dedup();
+        assert_eq!(txids.len(), deposits.len());
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn list_transactions_fiat_value() {
+        struct StubPriceFeed;
+        impl crate::price::PriceFeed for StubPriceFeed {
+            fn price_at(&self, timestamp: u32) -> Option<f64> {
+                if timestamp < 1_500 {
+                    Some(20_000.0)
+                } else {
+                    Some(30_000.0)
+                }
+            }
+        }
+
+        let external_txid =
+            Txid::from_str("617eab1fc0b03ee7f82ba70166725291783461f1a0e7975eaf8b5f8f674234f3")
+                .unwrap();
+        let tx = |previous_output: OutPoint, values: &[u64]| Transaction {
+            version: 2,
+            lock_time: PackedLockTime(0),
+            input: vec![TxIn {
+                witness: Witness::new(),
+                previous_output,
+                script_sig: Script::new(),
+                sequence: Sequence(0),
+            }],
+            output: values
+                .iter()
+                .map(|value| TxOut {
+                    script_pubkey: Script::new(),
+                    value: *value,
+                })
+                .collect(),
+        };
+        let block = |time: u32| Block {
+            hash: bitcoin::BlockHash::from_str(
+                "0000000000000000000326b8fca8d3f820647c97ea33ef722096b3c7b2c8ee94",
+            )
+            .unwrap(),
+            time,
+            height: time as i32,
+        };
+        // We receive 1BTC, spend it to an external address leaving 0.3BTC of change, and have
+        // an unconfirmed deposit.
+        let deposit = tx(OutPoint::new(external_txid, 0), &[100_000_000]);
+        let spend = tx(OutPoint::new(deposit.txid(), 0), &[69_990_000, 30_000_000]);
+        let unconfirmed = tx(OutPoint::new(external_txid, 1), &[10_000]);
+        let coin = |outpoint, amount, block_time: Option<u32>, spend_time: Option<u32>| Coin {
+            outpoint,
+            block_height: block_time.map(|t| t as i32),
+            block_time,
+            amount: bitcoin::Amount::from_sat(amount),
+            derivation_index: ChildNumber::from(0),
+            is_change: false,
+            spend_txid: spend_time.map(|_| spend.txid()),
+            spend_block: spend_time.map(|time| SpendBlock {
+                time,
+                height: time as i32,
+            }),
+            spend_broadcast_time: None,
+            is_frozen: false,
+        };
+        let mut db = DummyDatabase::new();
+        db.insert_coins(vec![
+            coin(
+                OutPoint::new(deposit.txid(), 0),
+                100_000_000,
+                Some(1_000),
+                Some(2_000),
+            ),
+            coin(
+                OutPoint::new(spend.txid(), 1),
+                30_000_000,
+                Some(2_000),
+                None,
+            ),
+            coin(OutPoint::new(unconfirmed.txid(), 0), 10_000, None, None),
+        ]);
+        let mut btc = DummyBitcoind::new();
+        btc.txs
+            .insert(deposit.txid(), (deposit.clone(), Some(block(1_000))));
+        btc.txs
+            .insert(spend.txid(), (spend.clone(), Some(block(2_000))));
+        btc.txs
+            .insert(unconfirmed.txid(), (unconfirmed.clone(), None));
+        let mut ms = DummyLiana::new(btc, db);
+        let txids = [deposit.txid(), spend.txid(), unconfirmed.txid()];
+
+        // By default transactions aren't valued.
+        let transactions = ms.handle.control.list_transactions(&txids).transactions;
+        assert!(transactions.iter().all(|tx| tx.fiat_value.is_none()));
+
+        // With a price feed, confirmed ones are at the price of the time they were mined.
+        ms.handle.control.set_price_feed(StubPriceFeed);
+        let transactions = ms.handle.control.list_transactions(&txids).transactions;
+        assert_eq!(transactions[0].fiat_value, Some(20_000.0));
+        assert_eq!(transactions[1].fiat_value, Some(-0.7 * 30_000.0));
+        assert_eq!(transactions[2].fiat_value, None);
+        let transactions = ms
+            .handle
+            .control
+            .list_confirmed_transactions(0, 3_000, 10, None, None)
+            .transactions;
+        assert_eq!(transactions[0].fiat_value, Some(-0.7 * 30_000.0));
+        assert_eq!(transactions[1].fiat_value, Some(20_000.0));
+
         ms.shutdown();
     }
 
@@ -1495,4 +6650,83 @@ mod tests {
 
         ms.shutdown();
     }
+
+    #[test]
+    fn get_transaction() {
+        let dummy_op = OutPoint::new(
+            Txid::from_str("617eab1fc0b03ee7f82ba70166725291783461f1a0e7975eaf8b5f8f674234f3")
+                .unwrap(),
+            0,
+        );
+        let deposit = Transaction {
+            version: 2,
+            lock_time: PackedLockTime(0),
+            input: vec![TxIn {
+                previous_output: dummy_op,
+                ..TxIn::default()
+            }],
+            output: vec![TxOut {
+                script_pubkey: Script::new(),
+                value: 100_000,
+            }],
+        };
+        let unrelated = Transaction {
+            version: 2,
+            lock_time: PackedLockTime(1),
+            input: deposit.input.clone(),
+            output: deposit.output.clone(),
+        };
+        let block = Block {
+            hash: bitcoin::BlockHash::from_str(
+                "0000000000000000000326b8fca8d3f820647c97ea33ef722096b3c7b2c8ee94",
+            )
+            .unwrap(),
+            time: 1_600_000_000,
+            height: 42,
+        };
+
+        let mut btc = DummyBitcoind::new();
+        btc.txs
+            .insert(deposit.txid(), (deposit.clone(), Some(block)));
+        btc.txs.insert(unrelated.txid(), (unrelated.clone(), None));
+        let mut db = DummyDatabase::new();
+        db.insert_coins(vec![Coin {
+            is_change: false,
+            outpoint: OutPoint::new(deposit.txid(), 0),
+            block_time: Some(block.time),
+            block_height: Some(block.height),
+            spend_block: None,
+            derivation_index: ChildNumber::from(0),
+            amount: bitcoin::Amount::from_sat(100_000),
+            spend_txid: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        }]);
+        let ms = DummyLiana::new(btc, db);
+        let control = &ms.handle.control;
+
+        // The transaction is returned hex-encoded along with its confirmation info.
+        let tx_info = control.get_transaction(&deposit.txid()).unwrap();
+        assert_eq!(tx_info.tx, deposit);
+        assert_eq!(
+            (tx_info.height, tx_info.time),
+            (Some(42), Some(1_600_000_000))
+        );
+        assert!(tx_info.is_wallet_tx);
+        let json = serde_json::json!(&tx_info);
+        assert_eq!(
+            json["tx"],
+            serde_json::json!(encode::serialize_hex(&deposit))
+        );
+
+        // A transaction not involving any of our coins, and unconfirmed.
+        let tx_info = control.get_transaction(&unrelated.txid()).unwrap();
+        assert_eq!((tx_info.height, tx_info.time), (None, None));
+        assert!(!tx_info.is_wallet_tx);
+
+        // An unknown transaction.
+        assert!(control.get_transaction(&dummy_op.txid).is_none());
+
+        ms.shutdown();
+    }
 }
@@ -1,17 +1,28 @@
 ///! Database interface for Liana.
 ///!
 ///! Record wallet metadata, spent and unspent coins, ongoing transactions.
+#[cfg(feature = "postgres")]
+pub mod postgres;
 pub mod sqlite;
 
+#[cfg(feature = "postgres")]
+use crate::database::postgres::{PostgresConn, PostgresDb};
 use crate::{
     bitcoin::BlockChainTip,
     database::sqlite::{
         schema::{DbCoin, DbSpendBlock, DbTip},
-        SqliteConn, SqliteDb,
+        SqliteConn, SqliteDb, LOOK_AHEAD_LIMIT,
     },
+    descriptors::MultipathDescriptor,
 };
 
-use std::{collections::HashMap, sync};
+use std::{
+    cmp,
+    collections::{BTreeMap, HashMap},
+    sync,
+};
+
+use serde::{Deserialize, Serialize};
 
 use miniscript::bitcoin::{
     self, secp256k1,
@@ -28,6 +39,13 @@ impl DatabaseInterface for SqliteDb {
     }
 }
 
+#[cfg(feature = "postgres")]
+impl DatabaseInterface for PostgresDb {
+    fn connection(&self) -> Box<dyn DatabaseConnection> {
+        Box::new(self.connection().expect("Database must be available"))
+    }
+}
+
 // FIXME: do we need to repeat the entire trait implemenation? Isn't there a nicer way?
 impl DatabaseInterface for sync::Arc<sync::Mutex<dyn DatabaseInterface>> {
     fn connection(&self) -> Box<dyn DatabaseConnection> {
@@ -65,15 +83,33 @@ pub trait DatabaseConnection {
         secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
     );
 
+    /// Get the first derivation index from which the addresses of neither chain are stored in
+    /// database. Addresses below it can be looked up using `derivation_index_by_address`.
+    fn first_unstored_index(&mut self) -> bip32::ChildNumber;
+
+    /// Get the timestamp of the wallet creation. We have seen all the events related to our
+    /// descriptor since then.
+    fn wallet_timestamp(&mut self) -> u32;
+
     /// Get the timestamp at which to start rescaning from, if any.
     fn rescan_timestamp(&mut self) -> Option<u32>;
 
-    /// Set a timestamp at which to start rescaning the block chain from.
+    /// Set a timestamp at which to start rescaning the block chain from. Resets the progress of
+    /// any previous rescan.
     fn set_rescan(&mut self, timestamp: u32);
 
+    /// Get a lower bound of how far the ongoing rescan got, as a block timestamp, if recorded.
+    fn rescan_progress(&mut self) -> Option<u32>;
+
+    /// Record how far the ongoing rescan got, as a block timestamp.
+    fn set_rescan_progress(&mut self, timestamp: u32);
+
     /// Mark the rescan as complete.
     fn complete_rescan(&mut self);
 
+    /// Drop the rescan timestamp without marking the rescan as complete.
+    fn cancel_rescan(&mut self);
+
     /// Get the derivation index for this address, as well as whether this address is change.
     fn derivation_index_by_address(
         &mut self,
@@ -83,9 +119,28 @@ pub trait DatabaseConnection {
     /// Get all our coins, past or present, spent or not.
     fn coins(&mut self, coin_type: CoinType) -> HashMap<bitcoin::OutPoint, Coin>;
 
-    /// List coins that are being spent and whose spending transaction is still unconfirmed.
+    /// List coins that are being spent and whose spending transaction is still unconfirmed. Their
+    /// `spend_broadcast_time` tells for how long the spend has been pending.
     fn list_spending_coins(&mut self) -> HashMap<bitcoin::OutPoint, Coin>;
 
+    /// Get the coins in any of the given statuses, among the given outpoints. An empty list of
+    /// statuses or outpoints means no filtering on this criterion.
+    fn list_coins(
+        &mut self,
+        statuses: &[CoinStatus],
+        outpoints: &[bitcoin::OutPoint],
+    ) -> HashMap<bitcoin::OutPoint, Coin>;
+
+    /// Same as [`list_coins`](Self::list_coins) but ordered by insertion, skipping the first
+    /// `start` coins and returning at most `count` of them.
+    fn list_coins_page(
+        &mut self,
+        statuses: &[CoinStatus],
+        outpoints: &[bitcoin::OutPoint],
+        start: u64,
+        count: u64,
+    ) -> Vec<Coin>;
+
     /// Store new UTxOs. Coins must not already be in database.
     fn new_unspent_coins(&mut self, coins: &[Coin]);
 
@@ -93,24 +148,45 @@ pub trait DatabaseConnection {
     fn confirm_coins(&mut self, outpoints: &[(bitcoin::OutPoint, i32, u32)]);
 
     /// Mark a set of coins as being spent by a specified txid of a pending transaction.
+    /// All coins must be in database, otherwise none of them is updated.
     fn spend_coins(&mut self, outpoints: &[(bitcoin::OutPoint, bitcoin::Txid)]);
 
     /// Mark a set of coins as spent by a specified txid at a specified block time.
+    /// All coins must be in database, otherwise none of them is updated.
     fn confirm_spend(&mut self, outpoints: &[(bitcoin::OutPoint, bitcoin::Txid, i32, u32)]);
 
+    /// Freeze or unfreeze a set of coins.
+    fn set_frozen(&mut self, outpoints: &[bitcoin::OutPoint], frozen: bool);
+
+    /// Remove these unconfirmed coins, for instance because the transaction creating them was
+    /// double spent. Confirmed coins are never removed.
+    fn remove_coins(&mut self, outpoints: &[bitcoin::OutPoint]);
+
     /// Get specific coins from the database.
     fn coins_by_outpoints(
         &mut self,
         outpoints: &[bitcoin::OutPoint],
     ) -> HashMap<bitcoin::OutPoint, Coin>;
 
+    /// Get the coins paying to any of the addresses at these derivation indexes, each given
+    /// along with whether it's for the change keychain.
+    fn coins_by_derivation_indexes(
+        &mut self,
+        indexes: &[(bip32::ChildNumber, bool)],
+    ) -> HashMap<bitcoin::OutPoint, Coin>;
+
     fn spend_tx(&mut self, txid: &bitcoin::Txid) -> Option<Psbt>;
 
     /// Insert a new Spend transaction or replace an existing one.
     fn store_spend(&mut self, psbt: &Psbt);
 
-    /// List all existing Spend transactions.
-    fn list_spend(&mut self) -> Vec<Psbt>;
+    /// List all existing Spend transactions along with the last time they were updated. If
+    /// `since` is set, only list those updated at or after this timestamp.
+    fn list_spend(&mut self, since: Option<u32>) -> Vec<(Psbt, u32)>;
+
+    /// List at most `count` Spend transactions ordered by insertion, skipping the first `start`.
+    /// If `since` is set, only consider those updated at or after this timestamp.
+    fn list_spend_page(&mut self, since: Option<u32>, start: u64, count: u64) -> Vec<(Psbt, u32)>;
 
     /// Delete a Spend transaction from database.
     fn delete_spend(&mut self, txid: &bitcoin::Txid);
@@ -120,6 +196,35 @@ pub trait DatabaseConnection {
 
     /// Retrieve a limited list of txids that where deposited or spent between the start and end timestamps (inclusive bounds)
     fn list_txids(&mut self, start: u32, end: u32, limit: u64) -> Vec<bitcoin::Txid>;
+
+    /// Retrieve up to `limit` confirmed txids along with their block time, ordered by (block
+    /// time, txid) from the most recent. If `before` is set, only those strictly before it.
+    fn list_txids_page(
+        &mut self,
+        before: Option<(u32, bitcoin::Txid)>,
+        limit: u64,
+    ) -> Vec<(u32, bitcoin::Txid)>;
+
+    /// Store the registration token of a signing device for this descriptor, replacing any
+    /// existing one for this device.
+    fn store_hw_token(
+        &mut self,
+        descriptor: &MultipathDescriptor,
+        fingerprint: &bip32::Fingerprint,
+        token: &[u8; 32],
+    );
+
+    /// Get the registration tokens of all the signing devices for this descriptor.
+    fn hw_tokens(
+        &mut self,
+        descriptor: &MultipathDescriptor,
+    ) -> HashMap<bip32::Fingerprint, [u8; 32]>;
+
+    /// Store these labels, replacing the existing ones for the same items.
+    fn update_labels(&mut self, labels: &BTreeMap<String, String>);
+
+    /// Get all the labels.
+    fn labels(&mut self) -> BTreeMap<String, String>;
 }
 
 impl DatabaseConnection for SqliteConn {
@@ -166,6 +271,20 @@ impl DatabaseConnection for SqliteConn {
         self.set_derivation_index(index, true, secp)
     }
 
+    fn first_unstored_index(&mut self) -> bip32::ChildNumber {
+        let wallet = self.db_wallet();
+        let highest: u32 = cmp::max(
+            wallet.deposit_derivation_index,
+            wallet.change_derivation_index,
+        )
+        .into();
+        bip32::ChildNumber::from(highest.saturating_add(LOOK_AHEAD_LIMIT))
+    }
+
+    fn wallet_timestamp(&mut self) -> u32 {
+        self.db_wallet().timestamp
+    }
+
     fn rescan_timestamp(&mut self) -> Option<u32> {
         self.db_wallet().rescan_timestamp
     }
@@ -174,10 +293,22 @@ impl DatabaseConnection for SqliteConn {
         self.set_wallet_rescan_timestamp(timestamp)
     }
 
+    fn rescan_progress(&mut self) -> Option<u32> {
+        self.db_wallet().rescan_progress_timestamp
+    }
+
+    fn set_rescan_progress(&mut self, timestamp: u32) {
+        self.set_wallet_rescan_progress(timestamp)
+    }
+
     fn complete_rescan(&mut self) {
         self.complete_wallet_rescan()
     }
 
+    fn cancel_rescan(&mut self) {
+        self.cancel_wallet_rescan()
+    }
+
     fn coins(&mut self, coin_type: CoinType) -> HashMap<bitcoin::OutPoint, Coin> {
         self.coins(coin_type)
             .into_iter()
@@ -192,6 +323,30 @@ impl DatabaseConnection for SqliteConn {
             .collect()
     }
 
+    fn list_coins(
+        &mut self,
+        statuses: &[CoinStatus],
+        outpoints: &[bitcoin::OutPoint],
+    ) -> HashMap<bitcoin::OutPoint, Coin> {
+        self.list_coins(statuses, outpoints)
+            .into_iter()
+            .map(|db_coin| (db_coin.outpoint, db_coin.into()))
+            .collect()
+    }
+
+    fn list_coins_page(
+        &mut self,
+        statuses: &[CoinStatus],
+        outpoints: &[bitcoin::OutPoint],
+        start: u64,
+        count: u64,
+    ) -> Vec<Coin> {
+        self.list_coins_page(statuses, outpoints, start, count)
+            .into_iter()
+            .map(Coin::from)
+            .collect()
+    }
+
     fn new_unspent_coins<'a>(&mut self, coins: &[Coin]) {
         self.new_unspent_coins(coins)
     }
@@ -208,6 +363,14 @@ impl DatabaseConnection for SqliteConn {
         self.confirm_spend(outpoints)
     }
 
+    fn set_frozen(&mut self, outpoints: &[bitcoin::OutPoint], frozen: bool) {
+        self.set_frozen(outpoints, frozen)
+    }
+
+    fn remove_coins(&mut self, outpoints: &[bitcoin::OutPoint]) {
+        self.remove_coins(outpoints)
+    }
+
     fn derivation_index_by_address(
         &mut self,
         address: &bitcoin::Address,
@@ -226,6 +389,16 @@ impl DatabaseConnection for SqliteConn {
             .collect()
     }
 
+    fn coins_by_derivation_indexes(
+        &mut self,
+        indexes: &[(bip32::ChildNumber, bool)],
+    ) -> HashMap<bitcoin::OutPoint, Coin> {
+        self.db_coins_by_derivation_indexes(indexes)
+            .into_iter()
+            .map(|db_coin| (db_coin.outpoint, db_coin.into()))
+            .collect()
+    }
+
     fn spend_tx(&mut self, txid: &bitcoin::Txid) -> Option<Psbt> {
         self.db_spend(txid).map(|db_spend| db_spend.psbt)
     }
@@ -234,10 +407,17 @@ impl DatabaseConnection for SqliteConn {
         self.store_spend(psbt)
     }
 
-    fn list_spend(&mut self) -> Vec<Psbt> {
-        self.list_spend()
+    fn list_spend(&mut self, since: Option<u32>) -> Vec<(Psbt, u32)> {
+        self.list_spend(since)
             .into_iter()
-            .map(|db_spend| db_spend.psbt)
+            .map(|db_spend| (db_spend.psbt, db_spend.updated_at))
+            .collect()
+    }
+
+    fn list_spend_page(&mut self, since: Option<u32>, start: u64, count: u64) -> Vec<(Psbt, u32)> {
+        self.list_spend_page(since, start, count)
+            .into_iter()
+            .map(|db_spend| (db_spend.psbt, db_spend.updated_at))
             .collect()
     }
 
@@ -252,6 +432,290 @@ impl DatabaseConnection for SqliteConn {
     fn list_txids(&mut self, start: u32, end: u32, limit: u64) -> Vec<bitcoin::Txid> {
         self.db_list_txids(start, end, limit)
     }
+
+    fn list_txids_page(
+        &mut self,
+        before: Option<(u32, bitcoin::Txid)>,
+        limit: u64,
+    ) -> Vec<(u32, bitcoin::Txid)> {
+        self.db_list_txids_page(before, limit)
+    }
+
+    fn store_hw_token(
+        &mut self,
+        descriptor: &MultipathDescriptor,
+        fingerprint: &bip32::Fingerprint,
+        token: &[u8; 32],
+    ) {
+        self.store_hw_token(descriptor, fingerprint, token)
+    }
+
+    fn hw_tokens(
+        &mut self,
+        descriptor: &MultipathDescriptor,
+    ) -> HashMap<bip32::Fingerprint, [u8; 32]> {
+        self.db_hw_tokens(descriptor)
+            .into_iter()
+            .map(|db_token| (db_token.fingerprint, db_token.token))
+            .collect()
+    }
+
+    fn update_labels(&mut self, labels: &BTreeMap<String, String>) {
+        self.update_labels(labels)
+    }
+
+    fn labels(&mut self) -> BTreeMap<String, String> {
+        self.db_labels()
+            .into_iter()
+            .map(|db_label| (db_label.item, db_label.value))
+            .collect()
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl DatabaseConnection for PostgresConn {
+    fn chain_tip(&mut self) -> Option<BlockChainTip> {
+        match self.db_tip() {
+            DbTip {
+                block_height: Some(height),
+                block_hash: Some(hash),
+                ..
+            } => Some(BlockChainTip { height, hash }),
+            _ => None,
+        }
+    }
+
+    fn network(&mut self) -> bitcoin::Network {
+        self.db_tip().network
+    }
+
+    fn update_tip(&mut self, tip: &BlockChainTip) {
+        self.update_tip(tip)
+    }
+
+    fn receive_index(&mut self) -> bip32::ChildNumber {
+        self.db_wallet().deposit_derivation_index
+    }
+
+    fn set_receive_index(
+        &mut self,
+        index: bip32::ChildNumber,
+        secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+    ) {
+        self.set_derivation_index(index, false, secp)
+    }
+
+    fn change_index(&mut self) -> bip32::ChildNumber {
+        self.db_wallet().change_derivation_index
+    }
+
+    fn set_change_index(
+        &mut self,
+        index: bip32::ChildNumber,
+        secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+    ) {
+        self.set_derivation_index(index, true, secp)
+    }
+
+    fn first_unstored_index(&mut self) -> bip32::ChildNumber {
+        let wallet = self.db_wallet();
+        let highest: u32 = cmp::max(
+            wallet.deposit_derivation_index,
+            wallet.change_derivation_index,
+        )
+        .into();
+        bip32::ChildNumber::from(highest.saturating_add(LOOK_AHEAD_LIMIT))
+    }
+
+    fn wallet_timestamp(&mut self) -> u32 {
+        self.db_wallet().timestamp
+    }
+
+    fn rescan_timestamp(&mut self) -> Option<u32> {
+        self.db_wallet().rescan_timestamp
+    }
+
+    fn set_rescan(&mut self, timestamp: u32) {
+        self.set_wallet_rescan_timestamp(timestamp)
+    }
+
+    fn rescan_progress(&mut self) -> Option<u32> {
+        self.db_wallet().rescan_progress_timestamp
+    }
+
+    fn set_rescan_progress(&mut self, timestamp: u32) {
+        self.set_wallet_rescan_progress(timestamp)
+    }
+
+    fn complete_rescan(&mut self) {
+        self.complete_wallet_rescan()
+    }
+
+    fn cancel_rescan(&mut self) {
+        self.cancel_wallet_rescan()
+    }
+
+    fn coins(&mut self, coin_type: CoinType) -> HashMap<bitcoin::OutPoint, Coin> {
+        self.coins(coin_type)
+            .into_iter()
+            .map(|db_coin| (db_coin.outpoint, db_coin.into()))
+            .collect()
+    }
+
+    fn list_spending_coins(&mut self) -> HashMap<bitcoin::OutPoint, Coin> {
+        self.list_spending_coins()
+            .into_iter()
+            .map(|db_coin| (db_coin.outpoint, db_coin.into()))
+            .collect()
+    }
+
+    fn list_coins(
+        &mut self,
+        statuses: &[CoinStatus],
+        outpoints: &[bitcoin::OutPoint],
+    ) -> HashMap<bitcoin::OutPoint, Coin> {
+        self.list_coins(statuses, outpoints)
+            .into_iter()
+            .map(|db_coin| (db_coin.outpoint, db_coin.into()))
+            .collect()
+    }
+
+    fn list_coins_page(
+        &mut self,
+        statuses: &[CoinStatus],
+        outpoints: &[bitcoin::OutPoint],
+        start: u64,
+        count: u64,
+    ) -> Vec<Coin> {
+        self.list_coins_page(statuses, outpoints, start, count)
+            .into_iter()
+            .map(Coin::from)
+            .collect()
+    }
+
+    fn new_unspent_coins<'a>(&mut self, coins: &[Coin]) {
+        self.new_unspent_coins(coins)
+    }
+
+    fn confirm_coins<'a>(&mut self, outpoints: &[(bitcoin::OutPoint, i32, u32)]) {
+        self.confirm_coins(outpoints)
+    }
+
+    fn spend_coins<'a>(&mut self, outpoints: &[(bitcoin::OutPoint, bitcoin::Txid)]) {
+        self.spend_coins(outpoints)
+    }
+
+    fn confirm_spend<'a>(&mut self, outpoints: &[(bitcoin::OutPoint, bitcoin::Txid, i32, u32)]) {
+        self.confirm_spend(outpoints)
+    }
+
+    fn set_frozen(&mut self, outpoints: &[bitcoin::OutPoint], frozen: bool) {
+        self.set_frozen(outpoints, frozen)
+    }
+
+    fn remove_coins(&mut self, outpoints: &[bitcoin::OutPoint]) {
+        self.remove_coins(outpoints)
+    }
+
+    fn derivation_index_by_address(
+        &mut self,
+        address: &bitcoin::Address,
+    ) -> Option<(bip32::ChildNumber, bool)> {
+        self.db_address(address)
+            .map(|db_addr| (db_addr.derivation_index, address == &db_addr.change_address))
+    }
+
+    fn coins_by_outpoints(
+        &mut self,
+        outpoints: &[bitcoin::OutPoint],
+    ) -> HashMap<bitcoin::OutPoint, Coin> {
+        self.db_coins(outpoints)
+            .into_iter()
+            .map(|db_coin| (db_coin.outpoint, db_coin.into()))
+            .collect()
+    }
+
+    fn coins_by_derivation_indexes(
+        &mut self,
+        indexes: &[(bip32::ChildNumber, bool)],
+    ) -> HashMap<bitcoin::OutPoint, Coin> {
+        self.db_coins_by_derivation_indexes(indexes)
+            .into_iter()
+            .map(|db_coin| (db_coin.outpoint, db_coin.into()))
+            .collect()
+    }
+
+    fn spend_tx(&mut self, txid: &bitcoin::Txid) -> Option<Psbt> {
+        self.db_spend(txid).map(|db_spend| db_spend.psbt)
+    }
+
+    fn store_spend(&mut self, psbt: &Psbt) {
+        self.store_spend(psbt)
+    }
+
+    fn list_spend(&mut self, since: Option<u32>) -> Vec<(Psbt, u32)> {
+        self.list_spend(since)
+            .into_iter()
+            .map(|db_spend| (db_spend.psbt, db_spend.updated_at))
+            .collect()
+    }
+
+    fn list_spend_page(&mut self, since: Option<u32>, start: u64, count: u64) -> Vec<(Psbt, u32)> {
+        self.list_spend_page(since, start, count)
+            .into_iter()
+            .map(|db_spend| (db_spend.psbt, db_spend.updated_at))
+            .collect()
+    }
+
+    fn delete_spend(&mut self, txid: &bitcoin::Txid) {
+        self.delete_spend(txid)
+    }
+
+    fn rollback_tip(&mut self, new_tip: &BlockChainTip) {
+        self.rollback_tip(new_tip)
+    }
+
+    fn list_txids(&mut self, start: u32, end: u32, limit: u64) -> Vec<bitcoin::Txid> {
+        self.db_list_txids(start, end, limit)
+    }
+
+    fn list_txids_page(
+        &mut self,
+        before: Option<(u32, bitcoin::Txid)>,
+        limit: u64,
+    ) -> Vec<(u32, bitcoin::Txid)> {
+        self.db_list_txids_page(before, limit)
+    }
+
+    fn store_hw_token(
+        &mut self,
+        descriptor: &MultipathDescriptor,
+        fingerprint: &bip32::Fingerprint,
+        token: &[u8; 32],
+    ) {
+        self.store_hw_token(descriptor, fingerprint, token)
+    }
+
+    fn hw_tokens(
+        &mut self,
+        descriptor: &MultipathDescriptor,
+    ) -> HashMap<bip32::Fingerprint, [u8; 32]> {
+        self.db_hw_tokens(descriptor)
+            .into_iter()
+            .map(|db_token| (db_token.fingerprint, db_token.token))
+            .collect()
+    }
+
+    fn update_labels(&mut self, labels: &BTreeMap<String, String>) {
+        self.update_labels(labels)
+    }
+
+    fn labels(&mut self) -> BTreeMap<String, String> {
+        self.db_labels()
+            .into_iter()
+            .map(|db_label| (db_label.item, db_label.value))
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -279,6 +743,10 @@ pub struct Coin {
     pub is_change: bool,
     pub spend_txid: Option<bitcoin::Txid>,
     pub spend_block: Option<SpendBlock>,
+    /// When the coin was marked as spent by an unconfirmed transaction, if it ever was.
+    pub spend_broadcast_time: Option<u32>,
+    /// Whether the user asked for this coin to not be spent unless explicitly selected.
+    pub is_frozen: bool,
 }
 
 impl std::convert::From<DbCoin> for Coin {
@@ -292,6 +760,8 @@ impl std::convert::From<DbCoin> for Coin {
             is_change,
             spend_txid,
             spend_block,
+            spend_broadcast_time,
+            is_frozen,
             ..
         } = db_coin;
         Coin {
@@ -303,6 +773,8 @@ impl std::convert::From<DbCoin> for Coin {
             is_change,
             spend_txid,
             spend_block: spend_block.map(SpendBlock::from),
+            spend_broadcast_time,
+            is_frozen,
         }
     }
 }
@@ -312,6 +784,14 @@ impl Coin {
         self.block_height.is_some()
     }
 
+    /// Number of confirmations of the transaction creating this coin given the current tip
+    /// height. `0` if it is unconfirmed.
+    pub fn confirmations(&self, tip_height: i32) -> u32 {
+        self.block_height
+            .map(|h| cmp::max(tip_height - h + 1, 0) as u32)
+            .unwrap_or(0)
+    }
+
     pub fn is_spent(&self) -> bool {
         self.spend_txid.is_some()
     }
@@ -323,3 +803,391 @@ pub enum CoinType {
     Unspent,
     Spent,
 }
+
+/// The state of a coin. Each coin is in exactly one of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CoinStatus {
+    /// Not spent, and the transaction creating it isn't confirmed.
+    Unconfirmed,
+    /// Not spent, and the transaction creating it is confirmed.
+    Confirmed,
+    /// Spent by an unconfirmed transaction.
+    Spending,
+    /// Spent by a confirmed transaction.
+    Spent,
+}
+
+impl CoinStatus {
+    /// Whether this coin is in this state.
+    pub fn matches(&self, coin: &Coin) -> bool {
+        match self {
+            Self::Unconfirmed => coin.spend_txid.is_none() && coin.block_height.is_none(),
+            Self::Confirmed => coin.spend_txid.is_none() && coin.block_height.is_some(),
+            Self::Spending => coin.spend_txid.is_some() && coin.spend_block.is_none(),
+            Self::Spent => coin.spend_block.is_some(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::database::sqlite::FreshDbOptions;
+
+    use std::str::FromStr;
+
+    /// Exercise a freshly created database through the `DatabaseConnection` interface. This is
+    /// run against all the backends to make sure they behave the same.
+    pub fn conformance_checks(db: &dyn DatabaseInterface, options: &FreshDbOptions) {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let mut conn = db.connection();
+
+        // Tip
+        assert_eq!(conn.network(), options.bitcoind_network);
+        assert!(conn.chain_tip().is_none());
+        let tip = BlockChainTip {
+            height: 746756,
+            hash: bitcoin::BlockHash::from_str(
+                "00000000000000000006d50e4c9fd269ddf690c94f422dff85e96f1a84b3a615",
+            )
+            .unwrap(),
+        };
+        conn.update_tip(&tip);
+        assert_eq!(conn.chain_tip(), Some(tip));
+
+        // Derivation indexes, and the addresses mapping past them.
+        assert_eq!(conn.receive_index(), 0.into());
+        assert_eq!(conn.change_index(), 0.into());
+        conn.set_receive_index(12.into(), &secp);
+        conn.set_change_index(3.into(), &secp);
+        assert_eq!(conn.receive_index(), 12.into());
+        assert_eq!(conn.change_index(), 3.into());
+        let look_ahead_index = 12 + sqlite::LOOK_AHEAD_LIMIT - 1;
+        let receive_addr = options
+            .main_descriptor
+            .receive_descriptor()
+            .derive(look_ahead_index.into(), &secp)
+            .address(options.bitcoind_network);
+        let change_addr = options
+            .main_descriptor
+            .change_descriptor()
+            .derive(look_ahead_index.into(), &secp)
+            .address(options.bitcoind_network);
+        assert_eq!(
+            conn.derivation_index_by_address(&receive_addr),
+            Some((look_ahead_index.into(), false))
+        );
+        assert_eq!(
+            conn.derivation_index_by_address(&change_addr),
+            Some((look_ahead_index.into(), true))
+        );
+
+        // Rescan
+        assert!(conn.rescan_timestamp().is_none());
+        conn.set_rescan(1_001);
+        assert_eq!(conn.rescan_timestamp(), Some(1_001));
+        assert!(conn.rescan_progress().is_none());
+        conn.set_rescan_progress(1_500);
+        assert_eq!(conn.rescan_progress(), Some(1_500));
+        conn.complete_rescan();
+        assert!(conn.rescan_timestamp().is_none());
+        assert!(conn.rescan_progress().is_none());
+        // Cancelling a rescan drops its timestamp but doesn't touch the wallet timestamp.
+        let wallet_timestamp = conn.wallet_timestamp();
+        conn.set_rescan(1_000);
+        conn.set_rescan_progress(1_500);
+        conn.cancel_rescan();
+        assert!(conn.rescan_timestamp().is_none());
+        assert!(conn.rescan_progress().is_none());
+        // Starting a new rescan resets the progress of the previous one.
+        conn.set_rescan(1_000);
+        conn.set_rescan_progress(1_500);
+        conn.set_rescan(1_200);
+        assert!(conn.rescan_progress().is_none());
+        conn.cancel_rescan();
+        assert_eq!(conn.wallet_timestamp(), wallet_timestamp);
+
+        // Coins lifecycle
+        let txid = bitcoin::Txid::from_str(
+            "6f0dc85a369f44d4e5b7b7a6b1e0c2f1e2d7a7c0f5b2b5c1d1a4f0b8a3b2c1d0",
+        )
+        .unwrap();
+        let coin_a = Coin {
+            outpoint: bitcoin::OutPoint::new(txid, 0),
+            block_height: None,
+            block_time: None,
+            amount: bitcoin::Amount::from_sat(98765),
+            derivation_index: 10.into(),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        };
+        let coin_b = Coin {
+            outpoint: bitcoin::OutPoint::new(txid, 1),
+            derivation_index: 2.into(),
+            is_change: true,
+            ..coin_a
+        };
+        conn.new_unspent_coins(&[coin_a, coin_b]);
+        assert_eq!(conn.coins(CoinType::All).len(), 2);
+        assert_eq!(conn.coins(CoinType::Unspent).len(), 2);
+        assert_eq!(
+            conn.coins_by_outpoints(&[coin_b.outpoint])[&coin_b.outpoint],
+            coin_b
+        );
+
+        conn.confirm_coins(&[(coin_a.outpoint, 746_000, 1_660_000_000)]);
+        let spend_txid = bitcoin::Txid::from_str(
+            "0c62a990d20d54429e70859292e82374ba6b1b951a3ab60f26bb65fee5724ff7",
+        )
+        .unwrap();
+
+        // Marking a batch of coins as spent is all-or-nothing: if one of them is unknown, none
+        // is marked.
+        let unknown_op = bitcoin::OutPoint::new(spend_txid, 42);
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            conn.spend_coins(&[(coin_a.outpoint, spend_txid), (unknown_op, spend_txid)])
+        }));
+        assert!(res.is_err());
+        assert!(conn.coins(CoinType::Spent).is_empty());
+
+        let before = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32;
+        conn.spend_coins(&[(coin_a.outpoint, spend_txid)]);
+        assert_eq!(conn.coins(CoinType::Spent).len(), 1);
+        assert_eq!(conn.coins(CoinType::Unspent).len(), 1);
+        // The time at which it was marked as spending is recorded.
+        let broadcast_time = conn.list_spending_coins()[&coin_a.outpoint]
+            .spend_broadcast_time
+            .unwrap();
+        assert!(broadcast_time >= before && broadcast_time <= before + 10);
+        assert!(!conn.coins_by_outpoints(&[coin_b.outpoint])[&coin_b.outpoint].is_frozen);
+        conn.set_frozen(&[coin_b.outpoint], true);
+        assert!(conn.coins_by_outpoints(&[coin_b.outpoint])[&coin_b.outpoint].is_frozen);
+        conn.set_frozen(&[coin_b.outpoint], false);
+        assert!(!conn.coins_by_outpoints(&[coin_b.outpoint])[&coin_b.outpoint].is_frozen);
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            conn.confirm_spend(&[
+                (coin_a.outpoint, spend_txid, 746_500, 1_660_100_000),
+                (unknown_op, spend_txid, 746_500, 1_660_100_000),
+            ])
+        }));
+        assert!(res.is_err());
+        assert!(conn.list_spending_coins().contains_key(&coin_a.outpoint));
+
+        conn.confirm_spend(&[(coin_a.outpoint, spend_txid, 746_500, 1_660_100_000)]);
+        assert!(conn.list_spending_coins().is_empty());
+        let db_coin_a = conn.coins_by_outpoints(&[coin_a.outpoint])[&coin_a.outpoint];
+        assert_eq!(db_coin_a.block_height, Some(746_000));
+        assert_eq!(db_coin_a.block_time, Some(1_660_000_000));
+        assert_eq!(
+            db_coin_a.spend_block,
+            Some(SpendBlock {
+                height: 746_500,
+                time: 1_660_100_000
+            })
+        );
+        let mut txids = conn.list_txids(1_660_000_000, 1_660_100_000, 10);
+        txids.sort();
+        let mut expected = vec![spend_txid, txid];
+        expected.sort();
+        assert_eq!(txids, expected);
+        assert_eq!(
+            conn.list_txids(1_660_000_000, 1_660_000_000, 10),
+            vec![txid]
+        );
+        assert_eq!(conn.list_txids(1_660_000_000, 1_660_100_000, 1).len(), 1);
+        // They can also be listed by pages, from the most recent.
+        assert_eq!(
+            conn.list_txids_page(None, 10),
+            vec![(1_660_100_000, spend_txid), (1_660_000_000, txid)]
+        );
+        assert_eq!(
+            conn.list_txids_page(None, 1),
+            vec![(1_660_100_000, spend_txid)]
+        );
+        assert_eq!(
+            conn.list_txids_page(Some((1_660_100_000, spend_txid)), 10),
+            vec![(1_660_000_000, txid)]
+        );
+        assert!(conn
+            .list_txids_page(Some((1_660_000_000, txid)), 10)
+            .is_empty());
+
+        // Coins can be filtered by status and outpoint.
+        assert_eq!(conn.list_coins(&[], &[]).len(), 2);
+        let spent = conn.list_coins(&[CoinStatus::Spent], &[]);
+        assert_eq!(spent.len(), 1);
+        assert!(spent.contains_key(&coin_a.outpoint));
+        let unconfirmed = conn.list_coins(&[CoinStatus::Unconfirmed], &[]);
+        assert_eq!(unconfirmed.len(), 1);
+        assert!(unconfirmed.contains_key(&coin_b.outpoint));
+        assert!(conn
+            .list_coins(&[CoinStatus::Confirmed, CoinStatus::Spending], &[])
+            .is_empty());
+        assert_eq!(
+            conn.list_coins(&[CoinStatus::Unconfirmed, CoinStatus::Spent], &[])
+                .len(),
+            2
+        );
+        assert_eq!(conn.list_coins(&[], &[coin_b.outpoint]).len(), 1);
+        assert!(conn
+            .list_coins(&[CoinStatus::Spent], &[coin_b.outpoint])
+            .is_empty());
+
+        // Rolling back the tip unconfirms what was confirmed after it.
+        let new_tip = BlockChainTip {
+            height: 746_200,
+            ..tip
+        };
+        conn.rollback_tip(&new_tip);
+        assert_eq!(conn.chain_tip(), Some(new_tip));
+        let db_coin_a = conn.coins_by_outpoints(&[coin_a.outpoint])[&coin_a.outpoint];
+        assert_eq!(db_coin_a.block_height, Some(746_000));
+        assert_eq!(db_coin_a.spend_txid, Some(spend_txid));
+        assert!(db_coin_a.spend_block.is_none());
+        assert!(conn
+            .list_coins(&[CoinStatus::Spending], &[])
+            .contains_key(&coin_a.outpoint));
+
+        // Spend transactions
+        let tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime(0),
+            input: vec![bitcoin::TxIn {
+                previous_output: coin_b.outpoint,
+                ..bitcoin::TxIn::default()
+            }],
+            output: vec![bitcoin::TxOut {
+                value: 90_000,
+                script_pubkey: receive_addr.script_pubkey(),
+            }],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        let spend_txid = psbt.unsigned_tx.txid();
+        assert!(conn.spend_tx(&spend_txid).is_none());
+        conn.store_spend(&psbt);
+        assert_eq!(conn.spend_tx(&spend_txid), Some(psbt.clone()));
+        psbt.inputs[0].witness_utxo = Some(bitcoin::TxOut {
+            value: coin_b.amount.to_sat(),
+            script_pubkey: change_addr.script_pubkey(),
+        });
+        let before = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32;
+        conn.store_spend(&psbt);
+        let spends = conn.list_spend(None);
+        assert_eq!(spends.len(), 1);
+        assert_eq!(spends[0].0, psbt);
+        // The time of the last update is recorded, and Spends can be filtered on it.
+        let updated_at = spends[0].1;
+        assert!(updated_at >= before && updated_at <= before + 10);
+        assert_eq!(conn.list_spend(Some(updated_at)), spends);
+        assert!(conn.list_spend(Some(updated_at + 1)).is_empty());
+        assert!(conn.list_spend_page(Some(updated_at + 1), 0, 10).is_empty());
+        conn.delete_spend(&spend_txid);
+        assert!(conn.list_spend(None).is_empty());
+
+        // Coins and Spend transactions can be listed by pages, which don't overlap and cover the
+        // whole listing.
+        let many_txid = bitcoin::Txid::from_str(
+            "8c1b5d4b4e5d5f6a6b2b1f2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60",
+        )
+        .unwrap();
+        let many_coins: Vec<Coin> = (0..150)
+            .map(|vout| Coin {
+                outpoint: bitcoin::OutPoint::new(many_txid, vout),
+                derivation_index: vout.into(),
+                ..coin_b
+            })
+            .collect();
+        conn.new_unspent_coins(&many_coins);
+        let unconfirmed = conn.list_coins(&[CoinStatus::Unconfirmed], &[]);
+        assert_eq!(unconfirmed.len(), 151);
+        let mut paged = HashMap::new();
+        let mut start = 0;
+        loop {
+            let page = conn.list_coins_page(&[CoinStatus::Unconfirmed], &[], start, 40);
+            assert!(page.len() <= 40);
+            if page.is_empty() {
+                break;
+            }
+            for coin in page {
+                assert!(paged.insert(coin.outpoint, coin).is_none());
+            }
+            start += 40;
+        }
+        assert_eq!(paged, unconfirmed);
+        assert_eq!(
+            conn.list_coins_page(&[], &[coin_a.outpoint, coin_b.outpoint], 1, 10)
+                .len(),
+            1
+        );
+        assert_eq!(conn.list_coins_page(&[], &[], 0, u64::MAX).len(), 152);
+        assert_eq!(conn.list_coins_page(&[], &[], u64::MAX, 10).len(), 0);
+
+        // Coins can be looked up by derivation index and keychain.
+        let indexes: [(bip32::ChildNumber, bool); 3] =
+            [(2.into(), true), (3.into(), false), (149.into(), true)];
+        let by_index = conn.coins_by_derivation_indexes(&indexes);
+        assert_eq!(by_index.len(), 3);
+        assert!(by_index.contains_key(&coin_b.outpoint));
+        let scanned: HashMap<bitcoin::OutPoint, Coin> = conn
+            .coins(CoinType::All)
+            .into_iter()
+            .filter(|(_, c)| indexes.contains(&(c.derivation_index, c.is_change)))
+            .collect();
+        assert_eq!(by_index, scanned);
+        assert!(conn.coins_by_derivation_indexes(&[]).is_empty());
+
+        let psbts: Vec<Psbt> = (0..5)
+            .map(|i| {
+                let mut psbt = psbt.clone();
+                psbt.unsigned_tx.output[0].value += i;
+                psbt
+            })
+            .collect();
+        for psbt in &psbts {
+            conn.store_spend(psbt);
+        }
+        let mut paged: Vec<Psbt> = Vec::new();
+        for start in &[0, 2, 4, 6] {
+            let page = conn.list_spend_page(None, *start, 2);
+            assert!(page.len() <= 2);
+            paged.extend(page.into_iter().map(|(psbt, _)| psbt));
+        }
+        assert_eq!(paged.len(), psbts.len());
+        assert!(psbts.iter().all(|psbt| paged.contains(psbt)));
+        for psbt in &psbts {
+            conn.delete_spend(&psbt.unsigned_tx.txid());
+        }
+
+        // Signing devices registration tokens
+        let fingerprint = bip32::Fingerprint::from(&[0xde, 0xad, 0xbe, 0xef][..]);
+        assert!(conn.hw_tokens(&options.main_descriptor).is_empty());
+        conn.store_hw_token(&options.main_descriptor, &fingerprint, &[1; 32]);
+        conn.store_hw_token(&options.main_descriptor, &fingerprint, &[2; 32]);
+        let tokens = conn.hw_tokens(&options.main_descriptor);
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[&fingerprint], [2; 32]);
+
+        // Labels
+        assert!(conn.labels().is_empty());
+        let mut labels = BTreeMap::new();
+        labels.insert("aabbccdd".to_string(), "Owner".to_string());
+        labels.insert("11223344".to_string(), "Heir".to_string());
+        conn.update_labels(&labels);
+        assert_eq!(conn.labels(), labels);
+        let mut new_labels = BTreeMap::new();
+        new_labels.insert("aabbccdd".to_string(), "Spouse".to_string());
+        conn.update_labels(&new_labels);
+        labels.insert("aabbccdd".to_string(), "Spouse".to_string());
+        assert_eq!(conn.labels(), labels);
+    }
+}
@@ -0,0 +1,1097 @@
+///! Implementation of the database interface using Postgres.
+///!
+///! This allows multiple machines to share a database. It mirrors the SQLite implementation, and
+///! uses the same schema version.
+pub mod schema;
+
+use crate::{
+    bitcoin::BlockChainTip,
+    database::{
+        postgres::schema::SCHEMA,
+        sqlite::{
+            schema::{DbAddress, DbCoin, DbHwToken, DbLabel, DbSpendTransaction, DbTip, DbWallet},
+            FreshDbOptions, DB_VERSION, LOOK_AHEAD_LIMIT,
+        },
+        Coin, CoinStatus, CoinType,
+    },
+    descriptors::MultipathDescriptor,
+};
+
+use std::{
+    cmp,
+    collections::BTreeMap,
+    convert::{TryFrom, TryInto},
+    fmt, time,
+};
+
+use miniscript::bitcoin::{
+    self,
+    consensus::encode,
+    secp256k1,
+    util::{bip32, psbt::PartiallySignedTransaction as Psbt},
+};
+
+#[derive(Debug)]
+pub enum PostgresDbError {
+    UnsupportedVersion(i64),
+    InvalidNetwork(bitcoin::Network),
+    DescriptorMismatch(Box<MultipathDescriptor>),
+    Postgres(postgres::Error),
+}
+
+impl std::fmt::Display for PostgresDbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PostgresDbError::UnsupportedVersion(v) => {
+                write!(f, "Unsupported database version '{}'.", v)
+            }
+            PostgresDbError::InvalidNetwork(net) => {
+                write!(f, "Database was created for network '{}'.", net)
+            }
+            PostgresDbError::DescriptorMismatch(desc) => write!(
+                f,
+                "Config descriptor does not match wallet database descriptor '{}'.",
+                desc
+            ),
+            PostgresDbError::Postgres(e) => write!(f, "Postgres error: '{}'", e),
+        }
+    }
+}
+
+impl std::error::Error for PostgresDbError {}
+
+impl From<postgres::Error> for PostgresDbError {
+    fn from(e: postgres::Error) -> Self {
+        PostgresDbError::Postgres(e)
+    }
+}
+
+/// Perform a set of modifications to the database inside a single transaction
+fn db_exec<F>(client: &mut postgres::Client, modifications: F) -> Result<(), postgres::Error>
+where
+    F: FnOnce(&mut postgres::Transaction) -> Result<(), postgres::Error>,
+{
+    let mut tx = client.transaction()?;
+    modifications(&mut tx)?;
+    tx.commit()
+}
+
+/// Internal helper for queries boilerplate
+fn db_query<T>(
+    client: &mut impl postgres::GenericClient,
+    stmt_str: &str,
+    params: &[&(dyn postgres::types::ToSql + Sync)],
+) -> Result<Vec<T>, postgres::Error>
+where
+    T: for<'a> TryFrom<&'a postgres::Row, Error = postgres::Error>,
+{
+    client
+        .query(stmt_str, params)?
+        .iter()
+        .map(T::try_from)
+        .collect()
+}
+
+// Postgres doesn't have unsigned integers, we store our u32s as i64s.
+fn u32_param(n: impl Into<u32>) -> i64 {
+    let n: u32 = n.into();
+    n.into()
+}
+
+// Postgres integers are signed, cap a LIMIT or OFFSET value to the largest one it can represent.
+fn page_param(n: u64) -> i64 {
+    n.try_into().unwrap_or(i64::MAX)
+}
+
+// The current time as a UNIX timestamp.
+fn curr_timestamp() -> u32 {
+    time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map(|dur| {
+            dur.as_secs()
+                .try_into()
+                .expect("Is this the year 2106 yet? Misconfigured system clock.")
+        })
+        .expect("System clock went backward the epoch?")
+}
+
+// Fill a fresh database with the schema and the initial wallet data.
+fn create_fresh_db(
+    client: &mut postgres::Client,
+    options: FreshDbOptions,
+    secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+) -> Result<(), PostgresDbError> {
+    let timestamp: u32 = options.birthday_timestamp.unwrap_or_else(curr_timestamp);
+
+    db_exec(client, |tx| {
+        tx.batch_execute(SCHEMA)?;
+        tx.execute("INSERT INTO version (version) VALUES ($1)", &[&DB_VERSION])?;
+        tx.execute(
+            "INSERT INTO tip (network, blockheight, blockhash) VALUES ($1, NULL, NULL)",
+            &[&options.bitcoind_network.to_string()],
+        )?;
+        tx.execute(
+            "INSERT INTO wallets (timestamp, main_descriptor, deposit_derivation_index, change_derivation_index) \
+                     VALUES ($1, $2, 0, 0)",
+            &[&u32_param(timestamp), &options.main_descriptor.to_string()],
+        )?;
+
+        // Fill the initial addresses. On a fresh database, the deposit_derivation_index is
+        // necessarily 0.
+        let stmt = tx.prepare(
+            "INSERT INTO addresses (receive_address, change_address, derivation_index) VALUES ($1, $2, $3)",
+        )?;
+        for index in 0..LOOK_AHEAD_LIMIT {
+            let receive_address = options
+                .main_descriptor
+                .receive_descriptor()
+                .derive(index.into(), secp)
+                .address(options.bitcoind_network);
+            let change_address = options
+                .main_descriptor
+                .change_descriptor()
+                .derive(index.into(), secp)
+                .address(options.bitcoind_network);
+            tx.execute(
+                &stmt,
+                &[
+                    &receive_address.to_string(),
+                    &change_address.to_string(),
+                    &u32_param(index),
+                ],
+            )?;
+        }
+
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+// Add the 'is_frozen' column to the coins table.
+fn migrate_v0_to_v1(client: &mut postgres::Client) -> Result<(), postgres::Error> {
+    db_exec(client, |tx| {
+        tx.batch_execute(
+            "ALTER TABLE coins ADD COLUMN is_frozen BOOLEAN NOT NULL DEFAULT FALSE;
+             UPDATE version SET version = 1;",
+        )
+    })
+}
+
+// Add the 'spend_broadcast_time' column to the coins table. The coins currently being spent are
+// considered to have been broadcast at the time of the migration.
+fn migrate_v1_to_v2(client: &mut postgres::Client) -> Result<(), postgres::Error> {
+    db_exec(client, |tx| {
+        tx.batch_execute("ALTER TABLE coins ADD COLUMN spend_broadcast_time BIGINT;")?;
+        tx.execute(
+            "UPDATE coins SET spend_broadcast_time = $1 WHERE spend_txid IS NOT NULL AND spend_block_time IS NULL",
+            &[&u32_param(curr_timestamp())],
+        )?;
+        tx.batch_execute("UPDATE version SET version = 2;")
+    })
+}
+
+// Add the 'rescan_progress_timestamp' column to the wallets table. It is left empty: an ongoing
+// rescan will be resumed from its start.
+fn migrate_v2_to_v3(client: &mut postgres::Client) -> Result<(), postgres::Error> {
+    db_exec(client, |tx| {
+        tx.batch_execute(
+            "ALTER TABLE wallets ADD COLUMN rescan_progress_timestamp BIGINT; \
+             UPDATE version SET version = 3;",
+        )
+    })
+}
+
+// Index the coins by derivation index and keychain, to look them up by address without deriving
+// the addresses.
+fn migrate_v3_to_v4(client: &mut postgres::Client) -> Result<(), postgres::Error> {
+    db_exec(client, |tx| {
+        tx.batch_execute(
+            "CREATE INDEX coins_derivation_index ON coins (derivation_index, is_change); \
+             UPDATE version SET version = 4;",
+        )
+    })
+}
+
+// Add the 'updated_at' column to the spend_transactions table. The existing Spend transactions are
+// considered to have been updated at the time of the migration.
+fn migrate_v4_to_v5(client: &mut postgres::Client) -> Result<(), postgres::Error> {
+    db_exec(client, |tx| {
+        tx.batch_execute("ALTER TABLE spend_transactions ADD COLUMN updated_at BIGINT;")?;
+        tx.execute(
+            "UPDATE spend_transactions SET updated_at = $1",
+            &[&u32_param(curr_timestamp())],
+        )?;
+        tx.batch_execute(
+            "ALTER TABLE spend_transactions ALTER COLUMN updated_at SET NOT NULL; \
+             UPDATE version SET version = 5;",
+        )
+    })
+}
+
+// Create the 'labels' table.
+fn migrate_v5_to_v6(client: &mut postgres::Client) -> Result<(), postgres::Error> {
+    db_exec(client, |tx| {
+        tx.batch_execute(
+            "CREATE TABLE labels ( \
+                id BIGSERIAL PRIMARY KEY, \
+                item TEXT UNIQUE NOT NULL, \
+                value TEXT NOT NULL \
+            ); \
+            UPDATE version SET version = 6;",
+        )
+    })
+}
+
+// Create the 'hw_tokens' table. It was only part of the schema of fresh databases, hence the
+// 'IF NOT EXISTS'.
+fn migrate_v6_to_v7(client: &mut postgres::Client) -> Result<(), postgres::Error> {
+    db_exec(client, |tx| {
+        tx.batch_execute(
+            "CREATE TABLE IF NOT EXISTS hw_tokens ( \
+                id BIGSERIAL PRIMARY KEY, \
+                descriptor TEXT NOT NULL, \
+                fingerprint BYTEA NOT NULL, \
+                token BYTEA NOT NULL, \
+                UNIQUE (descriptor, fingerprint) \
+            ); \
+            UPDATE version SET version = 7;",
+        )
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct PostgresDb {
+    config: postgres::Config,
+}
+
+impl PostgresDb {
+    /// Instanciate a Postgres database, creating the tables in a fresh one if `fresh_options`
+    /// are given.
+    pub fn new(
+        config: postgres::Config,
+        fresh_options: Option<FreshDbOptions>,
+        secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+    ) -> Result<PostgresDb, PostgresDbError> {
+        let db = PostgresDb { config };
+
+        if let Some(options) = fresh_options {
+            create_fresh_db(&mut db.connection()?.client, options, secp)?;
+            log::info!("Created a fresh Postgres database.");
+        }
+
+        Ok(db)
+    }
+
+    /// Get a new connection to the database.
+    pub fn connection(&self) -> Result<PostgresConn, PostgresDbError> {
+        let client = self.config.connect(postgres::NoTls)?;
+        Ok(PostgresConn { client })
+    }
+
+    /// Whether the wallet tables were already created in this database, possibly by another
+    /// machine.
+    pub fn is_initialized(&self) -> Result<bool, PostgresDbError> {
+        let row = self.connection()?.client.query_one(
+            "SELECT EXISTS (SELECT 1 FROM information_schema.tables \
+                WHERE table_schema = current_schema() AND table_name = 'version')",
+            &[],
+        )?;
+        Ok(row.try_get(0)?)
+    }
+
+    /// Upgrade the database schema to the current version if it was created by a previous
+    /// version of the software. The migrations are the same as for the SQLite backend.
+    pub fn maybe_apply_migrations(&self) -> Result<(), PostgresDbError> {
+        let mut conn = self.connection()?;
+        let mut db_version = conn.db_version();
+
+        while db_version < DB_VERSION {
+            log::warn!(
+                "Upgrading database from version {} to version {}.",
+                db_version,
+                db_version + 1
+            );
+            match db_version {
+                0 => migrate_v0_to_v1(&mut conn.client)?,
+                1 => migrate_v1_to_v2(&mut conn.client)?,
+                2 => migrate_v2_to_v3(&mut conn.client)?,
+                3 => migrate_v3_to_v4(&mut conn.client)?,
+                4 => migrate_v4_to_v5(&mut conn.client)?,
+                5 => migrate_v5_to_v6(&mut conn.client)?,
+                6 => migrate_v6_to_v7(&mut conn.client)?,
+                _ => return Err(PostgresDbError::UnsupportedVersion(db_version)),
+            }
+            db_version += 1;
+            log::warn!("Database upgraded to version {}.", db_version);
+        }
+
+        Ok(())
+    }
+
+    /// Perform startup sanity checks.
+    pub fn sanity_check(
+        &self,
+        bitcoind_network: bitcoin::Network,
+        main_descriptor: &MultipathDescriptor,
+    ) -> Result<(), PostgresDbError> {
+        let mut conn = self.connection()?;
+
+        // Check if there database isn't from the future.
+        // NOTE: migrations must be applied to both backends alike.
+        let db_version = conn.db_version();
+        if db_version != DB_VERSION {
+            return Err(PostgresDbError::UnsupportedVersion(db_version));
+        }
+
+        // The config and the db should be on the same network.
+        let db_tip = conn.db_tip();
+        if db_tip.network != bitcoind_network {
+            return Err(PostgresDbError::InvalidNetwork(db_tip.network));
+        }
+
+        // The config and db descriptors must match!
+        let db_wallet = conn.db_wallet();
+        if &db_wallet.main_descriptor != main_descriptor {
+            return Err(PostgresDbError::DescriptorMismatch(
+                db_wallet.main_descriptor.into(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+pub struct PostgresConn {
+    client: postgres::Client,
+}
+
+impl PostgresConn {
+    pub fn db_version(&mut self) -> i64 {
+        self.client
+            .query_one("SELECT version FROM version", &[])
+            .and_then(|row| row.try_get(0))
+            .expect("There is always a row in the version table")
+    }
+
+    /// Get the network tip.
+    pub fn db_tip(&mut self) -> DbTip {
+        db_query(&mut self.client, "SELECT * FROM tip", &[])
+            .expect("Db must not fail")
+            .pop()
+            .expect("There is always a row in the tip table")
+    }
+
+    /// Get the information about the wallet.
+    pub fn db_wallet(&mut self) -> DbWallet {
+        db_query(&mut self.client, "SELECT * FROM wallets", &[])
+            .expect("Db must not fail")
+            .pop()
+            .expect("There is always a row in the wallet table")
+    }
+
+    /// Update the network tip.
+    pub fn update_tip(&mut self, tip: &BlockChainTip) {
+        self.client
+            .execute(
+                "UPDATE tip SET blockheight = $1, blockhash = $2",
+                &[&tip.height, &tip.hash.to_vec()],
+            )
+            .expect("Database must be available");
+    }
+
+    /// Set the derivation index for receiving or change addresses.
+    ///
+    /// This will populate the address->deriv_index mapping with all the new entries between the
+    /// former and new gap limit indexes.
+    pub fn set_derivation_index(
+        &mut self,
+        index: bip32::ChildNumber,
+        change: bool,
+        secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+    ) {
+        let network = self.db_tip().network;
+
+        db_exec(&mut self.client, |db_tx| {
+            // Lock the wallet row, as we read the current indexes before updating them.
+            let db_wallet: DbWallet = db_query(db_tx, "SELECT * FROM wallets FOR UPDATE", &[])?
+                .pop()
+                .expect("There is always a row in the wallet table");
+
+            // First of all set the derivation index
+            let index_u32: u32 = index.into();
+            if change {
+                db_tx.execute(
+                    "UPDATE wallets SET change_derivation_index = $1",
+                    &[&u32_param(index_u32)],
+                )?;
+            } else {
+                db_tx.execute(
+                    "UPDATE wallets SET deposit_derivation_index = $1",
+                    &[&u32_param(index_u32)],
+                )?;
+            }
+
+            // Now if this new index is higher than the highest of our current derivation indexes,
+            // populate the addresses mapping for derivation indexes between our previous "gap
+            // limit index" and the new one.
+            let curr_highest_index = cmp::max(
+                db_wallet.deposit_derivation_index,
+                db_wallet.change_derivation_index,
+            )
+            .into();
+            if index_u32 > curr_highest_index {
+                let receive_desc = db_wallet.main_descriptor.receive_descriptor();
+                let change_desc = db_wallet.main_descriptor.change_descriptor();
+
+                for index in curr_highest_index + 1..=index_u32 {
+                    let la_index = index + LOOK_AHEAD_LIMIT - 1;
+                    let receive_addr = receive_desc.derive(la_index.into(), secp).address(network);
+                    let change_addr = change_desc.derive(la_index.into(), secp).address(network);
+                    db_tx.execute(
+                        "INSERT INTO addresses (receive_address, change_address, derivation_index) VALUES ($1, $2, $3)",
+                        &[&receive_addr.to_string(), &change_addr.to_string(), &u32_param(la_index)],
+                    )?;
+                }
+            }
+
+            Ok(())
+        })
+        .expect("Database must be available")
+    }
+
+    pub fn set_wallet_rescan_timestamp(&mut self, timestamp: u32) {
+        // NOTE: this will need to be updated if we ever implement multi-wallet support
+        self.client
+            .execute(
+                "UPDATE wallets SET rescan_timestamp = $1, rescan_progress_timestamp = NULL",
+                &[&u32_param(timestamp)],
+            )
+            .expect("Database must be available");
+    }
+
+    /// Record how far the ongoing rescan got.
+    pub fn set_wallet_rescan_progress(&mut self, timestamp: u32) {
+        // NOTE: this will need to be updated if we ever implement multi-wallet support
+        self.client
+            .execute(
+                "UPDATE wallets SET rescan_progress_timestamp = $1",
+                &[&u32_param(timestamp)],
+            )
+            .expect("Database must be available");
+    }
+
+    /// Drop the rescan timestamp, and set it as the wallet creation timestamp if it
+    /// predates it.
+    ///
+    /// # Panics
+    /// - If called while rescan_timestamp is not set
+    pub fn complete_wallet_rescan(&mut self) {
+        let db_wallet = self.db_wallet();
+        let new_timestamp = cmp::min(
+            db_wallet.rescan_timestamp.expect("Must be set"),
+            db_wallet.timestamp,
+        );
+
+        // NOTE: this will need to be updated if we ever implement multi-wallet support
+        self.client
+            .execute(
+                "UPDATE wallets SET timestamp = $1, rescan_timestamp = NULL, rescan_progress_timestamp = NULL",
+                &[&u32_param(new_timestamp)],
+            )
+            .expect("Database must be available");
+    }
+
+    /// Drop the rescan timestamp without updating the wallet creation timestamp, as the rescan
+    /// did not complete.
+    pub fn cancel_wallet_rescan(&mut self) {
+        // NOTE: this will need to be updated if we ever implement multi-wallet support
+        self.client
+            .execute(
+                "UPDATE wallets SET rescan_timestamp = NULL, rescan_progress_timestamp = NULL",
+                &[],
+            )
+            .expect("Database must be available");
+    }
+
+    /// Get all the coins from DB.
+    pub fn coins(&mut self, coin_type: CoinType) -> Vec<DbCoin> {
+        db_query(
+            &mut self.client,
+            match coin_type {
+                CoinType::All => "SELECT * FROM coins",
+                CoinType::Unspent => "SELECT * FROM coins WHERE spend_txid IS NULL",
+                CoinType::Spent => "SELECT * FROM coins WHERE spend_txid IS NOT NULL",
+            },
+            &[],
+        )
+        .expect("Db must not fail")
+    }
+
+    /// List the coins in any of the given statuses, among the given outpoints. Empty lists
+    /// aren't used as filters.
+    pub fn list_coins(
+        &mut self,
+        statuses: &[CoinStatus],
+        outpoints: &[bitcoin::OutPoint],
+    ) -> Vec<DbCoin> {
+        self.db_list_coins(statuses, outpoints, None)
+    }
+
+    /// Same as `list_coins` but ordered by insertion, skipping the first `start` coins and
+    /// returning at most `count` of them.
+    pub fn list_coins_page(
+        &mut self,
+        statuses: &[CoinStatus],
+        outpoints: &[bitcoin::OutPoint],
+        start: u64,
+        count: u64,
+    ) -> Vec<DbCoin> {
+        self.db_list_coins(statuses, outpoints, Some((start, count)))
+    }
+
+    fn db_list_coins(
+        &mut self,
+        statuses: &[CoinStatus],
+        outpoints: &[bitcoin::OutPoint],
+        page: Option<(u64, u64)>,
+    ) -> Vec<DbCoin> {
+        let mut conditions = Vec::with_capacity(2);
+        if !statuses.is_empty() {
+            let statuses: Vec<&str> = statuses.iter().map(status_condition).collect();
+            conditions.push(format!("({})", statuses.join(" OR ")));
+        }
+        let txids: Vec<Vec<u8>> = outpoints.iter().map(|op| op.txid.to_vec()).collect();
+        let vouts: Vec<i64> = outpoints.iter().map(|op| u32_param(op.vout)).collect();
+        let mut params: Vec<&(dyn postgres::types::ToSql + Sync)> = Vec::with_capacity(4);
+        if !outpoints.is_empty() {
+            conditions.push(
+                "(txid, vout) IN (SELECT * FROM UNNEST($1::BYTEA[], $2::BIGINT[]))".to_string(),
+            );
+            params.push(&txids);
+            params.push(&vouts);
+        }
+        let mut query = "SELECT * FROM coins".to_string();
+        if !conditions.is_empty() {
+            query += " WHERE ";
+            query += &conditions.join(" AND ");
+        }
+        let page = page.map(|(start, count)| (page_param(start), page_param(count)));
+        if let Some((start, count)) = &page {
+            query += &format!(
+                " ORDER BY id LIMIT ${} OFFSET ${}",
+                params.len() + 1,
+                params.len() + 2
+            );
+            params.push(count);
+            params.push(start);
+        }
+
+        db_query(&mut self.client, &query, &params).expect("Db must not fail")
+    }
+
+    /// List coins that are being spent and whose spending transaction is still unconfirmed.
+    pub fn list_spending_coins(&mut self) -> Vec<DbCoin> {
+        db_query(
+            &mut self.client,
+            "SELECT * FROM coins WHERE spend_txid IS NOT NULL AND spend_block_time IS NULL",
+            &[],
+        )
+        .expect("Db must not fail")
+    }
+
+    /// Store new, unconfirmed and unspent, coins.
+    /// Will panic if given a coin that is already in DB.
+    pub fn new_unspent_coins<'a>(&mut self, coins: impl IntoIterator<Item = &'a Coin>) {
+        let wallet_id = self.db_wallet().id;
+
+        db_exec(&mut self.client, |db_tx| {
+            for coin in coins {
+                let amount: i64 = coin
+                    .amount
+                    .to_sat()
+                    .try_into()
+                    .expect("Amounts are never larger than 21M BTC");
+                db_tx.execute(
+                    "INSERT INTO coins (wallet_id, txid, vout, amount_sat, derivation_index, is_change) \
+                         VALUES ($1, $2, $3, $4, $5, $6)",
+                    &[
+                        &wallet_id,
+                        &coin.outpoint.txid.to_vec(),
+                        &u32_param(coin.outpoint.vout),
+                        &amount,
+                        &u32_param(coin.derivation_index),
+                        &coin.is_change,
+                    ],
+                )?;
+            }
+            Ok(())
+        })
+        .expect("Database must be available")
+    }
+
+    /// Mark a set of coins as confirmed.
+    pub fn confirm_coins<'a>(
+        &mut self,
+        outpoints: impl IntoIterator<Item = &'a (bitcoin::OutPoint, i32, u32)>,
+    ) {
+        db_exec(&mut self.client, |db_tx| {
+            // Prepare the statement once for the whole batch, there may be thousands of coins to
+            // confirm during the initial sync of a busy wallet.
+            let stmt = db_tx.prepare(
+                "UPDATE coins SET blockheight = $1, blocktime = $2 WHERE txid = $3 AND vout = $4",
+            )?;
+            for (outpoint, height, time) in outpoints {
+                let updated = db_tx.execute(
+                    &stmt,
+                    &[
+                        height,
+                        &u32_param(*time),
+                        &outpoint.txid.to_vec(),
+                        &u32_param(outpoint.vout),
+                    ],
+                )?;
+                assert_eq!(updated, 1, "Unknown coin '{}'", outpoint);
+            }
+
+            Ok(())
+        })
+        .expect("Database must be available")
+    }
+
+    /// Mark a set of coins as spent, recording the current time as their broadcast time.
+    ///
+    /// All coins must be in database. The update is atomic: if one of them isn't, none is marked.
+    pub fn spend_coins<'a>(
+        &mut self,
+        outpoints: impl IntoIterator<Item = &'a (bitcoin::OutPoint, bitcoin::Txid)>,
+    ) {
+        let now = u32_param(curr_timestamp());
+        db_exec(&mut self.client, |db_tx| {
+            for (outpoint, spend_txid) in outpoints {
+                let updated = db_tx.execute(
+                    "UPDATE coins SET spend_txid = $1, spend_broadcast_time = $2 WHERE txid = $3 AND vout = $4",
+                    &[
+                        &spend_txid.to_vec(),
+                        &now,
+                        &outpoint.txid.to_vec(),
+                        &u32_param(outpoint.vout),
+                    ],
+                )?;
+                // Panicking here drops the transaction without committing it.
+                assert_eq!(updated, 1, "Unknown coin '{}'", outpoint);
+            }
+
+            Ok(())
+        })
+        .expect("Database must be available")
+    }
+
+    /// Freeze or unfreeze a set of coins.
+    pub fn set_frozen<'a>(
+        &mut self,
+        outpoints: impl IntoIterator<Item = &'a bitcoin::OutPoint>,
+        frozen: bool,
+    ) {
+        db_exec(&mut self.client, |db_tx| {
+            for outpoint in outpoints {
+                db_tx.execute(
+                    "UPDATE coins SET is_frozen = $1 WHERE txid = $2 AND vout = $3",
+                    &[&frozen, &outpoint.txid.to_vec(), &u32_param(outpoint.vout)],
+                )?;
+            }
+
+            Ok(())
+        })
+        .expect("Database must be available")
+    }
+
+    /// Remove a set of unconfirmed coins.
+    pub fn remove_coins<'a>(&mut self, outpoints: impl IntoIterator<Item = &'a bitcoin::OutPoint>) {
+        db_exec(&mut self.client, |db_tx| {
+            for outpoint in outpoints {
+                db_tx.execute(
+                    "DELETE FROM coins WHERE txid = $1 AND vout = $2 AND blockheight IS NULL",
+                    &[&outpoint.txid.to_vec(), &u32_param(outpoint.vout)],
+                )?;
+            }
+
+            Ok(())
+        })
+        .expect("Database must be available")
+    }
+
+    /// Mark the Spend transaction of a given set of coins as being confirmed at a given
+    /// block.
+    ///
+    /// All coins must be in database. The update is atomic: if one of them isn't, none is marked.
+    pub fn confirm_spend<'a>(
+        &mut self,
+        outpoints: impl IntoIterator<Item = &'a (bitcoin::OutPoint, bitcoin::Txid, i32, u32)>,
+    ) {
+        db_exec(&mut self.client, |db_tx| {
+            let stmt = db_tx.prepare(
+                "UPDATE coins SET spend_txid = $1, spend_block_height = $2, spend_block_time = $3 WHERE txid = $4 AND vout = $5",
+            )?;
+            for (outpoint, spend_txid, height, time) in outpoints {
+                let updated = db_tx.execute(
+                    &stmt,
+                    &[
+                        &spend_txid.to_vec(),
+                        height,
+                        &u32_param(*time),
+                        &outpoint.txid.to_vec(),
+                        &u32_param(outpoint.vout),
+                    ],
+                )?;
+                assert_eq!(updated, 1, "Unknown coin '{}'", outpoint);
+            }
+
+            Ok(())
+        })
+        .expect("Database must be available")
+    }
+
+    pub fn db_address(&mut self, address: &bitcoin::Address) -> Option<DbAddress> {
+        db_query(
+            &mut self.client,
+            "SELECT * FROM addresses WHERE receive_address = $1 OR change_address = $1",
+            &[&address.to_string()],
+        )
+        .expect("Db must not fail")
+        .pop()
+    }
+
+    /// Get the coins paying to any of the addresses at these (derivation index, is change)
+    /// pairs. This is an indexed query, addresses don't need to be derived.
+    pub fn db_coins_by_derivation_indexes(
+        &mut self,
+        indexes: &[(bip32::ChildNumber, bool)],
+    ) -> Vec<DbCoin> {
+        let deriv_indexes: Vec<i64> = indexes.iter().map(|(index, _)| u32_param(*index)).collect();
+        let is_change: Vec<bool> = indexes.iter().map(|(_, is_change)| *is_change).collect();
+
+        db_query(
+            &mut self.client,
+            "SELECT * FROM coins WHERE (derivation_index, is_change) IN (SELECT * FROM UNNEST($1::BIGINT[], $2::BOOLEAN[]))",
+            &[&deriv_indexes, &is_change],
+        )
+        .expect("Db must not fail")
+    }
+
+    pub fn db_coins(&mut self, outpoints: &[bitcoin::OutPoint]) -> Vec<DbCoin> {
+        let txids: Vec<Vec<u8>> = outpoints.iter().map(|op| op.txid.to_vec()).collect();
+        let vouts: Vec<i64> = outpoints.iter().map(|op| u32_param(op.vout)).collect();
+
+        db_query(
+            &mut self.client,
+            "SELECT * FROM coins WHERE (txid, vout) IN (SELECT * FROM UNNEST($1::BYTEA[], $2::BIGINT[]))",
+            &[&txids, &vouts],
+        )
+        .expect("Db must not fail")
+    }
+
+    pub fn db_spend(&mut self, txid: &bitcoin::Txid) -> Option<DbSpendTransaction> {
+        db_query(
+            &mut self.client,
+            "SELECT * FROM spend_transactions WHERE txid = $1",
+            &[&txid.to_vec()],
+        )
+        .expect("Db must not fail")
+        .pop()
+    }
+
+    /// Insert a new Spend transaction or replace an existing one.
+    pub fn store_spend(&mut self, psbt: &Psbt) {
+        let txid = psbt.unsigned_tx.txid().to_vec();
+        let psbt = encode::serialize(psbt);
+        let now = u32_param(curr_timestamp());
+
+        self.client
+            .execute(
+                "INSERT INTO spend_transactions (psbt, txid, updated_at) VALUES ($1, $2, $3) \
+                 ON CONFLICT (txid) DO UPDATE SET psbt = EXCLUDED.psbt, updated_at = EXCLUDED.updated_at",
+                &[&psbt, &txid, &now],
+            )
+            .expect("Db must not fail");
+    }
+
+    /// List the Spend transactions, optionally only those updated at or after `since`.
+    pub fn list_spend(&mut self, since: Option<u32>) -> Vec<DbSpendTransaction> {
+        db_query(
+            &mut self.client,
+            "SELECT * FROM spend_transactions WHERE updated_at >= $1",
+            &[&u32_param(since.unwrap_or(0))],
+        )
+        .expect("Db must not fail")
+    }
+
+    /// List at most `count` Spend transactions ordered by insertion, skipping the first `start`.
+    /// Optionally only consider those updated at or after `since`.
+    pub fn list_spend_page(
+        &mut self,
+        since: Option<u32>,
+        start: u64,
+        count: u64,
+    ) -> Vec<DbSpendTransaction> {
+        db_query(
+            &mut self.client,
+            "SELECT * FROM spend_transactions WHERE updated_at >= $1 ORDER BY id LIMIT $2 OFFSET $3",
+            &[
+                &u32_param(since.unwrap_or(0)),
+                &page_param(count),
+                &page_param(start),
+            ],
+        )
+        .expect("Db must not fail")
+    }
+
+    /// Retrieves a limited and ordered list of transactions ids that happened during the given
+    /// range.
+    pub fn db_list_txids(&mut self, start: u32, end: u32, limit: u64) -> Vec<bitcoin::Txid> {
+        let limit: i64 = limit.try_into().unwrap_or(i64::MAX);
+
+        self.client
+            .query(
+                "SELECT txid FROM ( \
+                    SELECT txid, blocktime AS date FROM coins \
+                    WHERE blocktime >= $1 AND blocktime <= $2 \
+                    UNION \
+                    SELECT spend_txid AS txid, spend_block_time AS date FROM coins \
+                    WHERE spend_block_time >= $1 AND spend_block_time <= $2 \
+                ) AS txs \
+                GROUP BY txid ORDER BY MAX(date) DESC LIMIT $3",
+                &[&u32_param(start), &u32_param(end), &limit],
+            )
+            .expect("Db must not fail")
+            .iter()
+            .map(|row| {
+                let txid: Vec<u8> = row.get(0);
+                encode::deserialize(&txid).expect("We only store valid txids")
+            })
+            .collect()
+    }
+
+    /// Get up to `limit` confirmed txids and their block time, ordered by (block time, txid) from
+    /// the most recent. Only those strictly before `before` if it's set.
+    pub fn db_list_txids_page(
+        &mut self,
+        before: Option<(u32, bitcoin::Txid)>,
+        limit: u64,
+    ) -> Vec<(u32, bitcoin::Txid)> {
+        let (before_time, before_txid) = match before {
+            Some((time, txid)) => (Some(u32_param(time)), Some(txid.to_vec())),
+            None => (None, None),
+        };
+
+        self.client
+            .query(
+                "SELECT date, txid FROM ( \
+                    SELECT blocktime AS date, txid FROM coins WHERE blocktime IS NOT NULL \
+                    UNION \
+                    SELECT spend_block_time AS date, spend_txid AS txid FROM coins \
+                    WHERE spend_block_time IS NOT NULL \
+                ) AS txs \
+                WHERE $1::BIGINT IS NULL OR (date, txid) < ($1, $2::BYTEA) \
+                ORDER BY date DESC, txid DESC LIMIT $3",
+                &[&before_time, &before_txid, &page_param(limit)],
+            )
+            .expect("Db must not fail")
+            .iter()
+            .map(|row| {
+                let time: i64 = row.get(0);
+                let txid: Vec<u8> = row.get(1);
+                (
+                    time as u32,
+                    encode::deserialize(&txid).expect("We only store valid txids"),
+                )
+            })
+            .collect()
+    }
+
+    pub fn delete_spend(&mut self, txid: &bitcoin::Txid) {
+        self.client
+            .execute(
+                "DELETE FROM spend_transactions WHERE txid = $1",
+                &[&txid.to_vec()],
+            )
+            .expect("Db must not fail");
+    }
+
+    /// Store the registration token of the device with this fingerprint for this descriptor,
+    /// replacing any existing one.
+    pub fn store_hw_token(
+        &mut self,
+        descriptor: &MultipathDescriptor,
+        fingerprint: &bip32::Fingerprint,
+        token: &[u8; 32],
+    ) {
+        self.client
+            .execute(
+                "INSERT INTO hw_tokens (descriptor, fingerprint, token) VALUES ($1, $2, $3) \
+                 ON CONFLICT (descriptor, fingerprint) DO UPDATE SET token = EXCLUDED.token",
+                &[
+                    &descriptor.to_string(),
+                    &fingerprint[..].to_vec(),
+                    &token.to_vec(),
+                ],
+            )
+            .expect("Db must not fail");
+    }
+
+    /// Get all the registration tokens stored for this descriptor.
+    pub fn db_hw_tokens(&mut self, descriptor: &MultipathDescriptor) -> Vec<DbHwToken> {
+        db_query(
+            &mut self.client,
+            "SELECT * FROM hw_tokens WHERE descriptor = $1",
+            &[&descriptor.to_string()],
+        )
+        .expect("Db must not fail")
+    }
+
+    /// Store these labels, replacing the existing ones for the same items.
+    pub fn update_labels(&mut self, labels: &BTreeMap<String, String>) {
+        db_exec(&mut self.client, |tx| {
+            for (item, value) in labels {
+                tx.execute(
+                    "INSERT INTO labels (item, value) VALUES ($1, $2) \
+                     ON CONFLICT (item) DO UPDATE SET value = EXCLUDED.value",
+                    &[item, value],
+                )?;
+            }
+            Ok(())
+        })
+        .expect("Db must not fail");
+    }
+
+    /// Get all the labels.
+    pub fn db_labels(&mut self) -> Vec<DbLabel> {
+        db_query(&mut self.client, "SELECT * FROM labels", &[]).expect("Db must not fail")
+    }
+
+    /// Unconfirm all data that was marked as being confirmed *after* the given chain
+    /// tip, and set it as our new best block seen.
+    ///
+    /// This includes:
+    /// - Coins
+    /// - Spending transactions confirmation
+    /// - Tip
+    ///
+    /// This will have to be updated if we are to add new fields based on block data
+    /// in the database eventually.
+    pub fn rollback_tip(&mut self, new_tip: &BlockChainTip) {
+        db_exec(&mut self.client, |db_tx| {
+            db_tx.execute(
+                "UPDATE coins SET blockheight = NULL, blocktime = NULL, spend_block_height = NULL, spend_block_time = NULL WHERE blockheight > $1",
+                &[&new_tip.height],
+            )?;
+            db_tx.execute(
+                "UPDATE coins SET spend_block_height = NULL, spend_block_time = NULL WHERE spend_block_height > $1",
+                &[&new_tip.height],
+            )?;
+            db_tx.execute(
+                "UPDATE tip SET blockheight = $1, blockhash = $2",
+                &[&new_tip.height, &new_tip.hash.to_vec()],
+            )?;
+            Ok(())
+        })
+        .expect("Db must not fail");
+    }
+}
+
+// The condition on a row of the coins table for this coin to be in the given state.
+fn status_condition(status: &CoinStatus) -> &'static str {
+    match status {
+        CoinStatus::Unconfirmed => "(spend_txid IS NULL AND blockheight IS NULL)",
+        CoinStatus::Confirmed => "(spend_txid IS NULL AND blockheight IS NOT NULL)",
+        CoinStatus::Spending => "(spend_txid IS NOT NULL AND spend_block_height IS NULL)",
+        CoinStatus::Spent => "spend_block_height IS NOT NULL",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{sqlite::tests::dummy_options, tests::conformance_checks};
+
+    use std::{
+        env,
+        str::FromStr,
+        sync::atomic::{AtomicUsize, Ordering},
+        time,
+    };
+
+    // These tests need a running Postgres server. They are skipped if the connection string to a
+    // database we can freely write to isn't set in this environment variable.
+    const POSTGRES_URL_ENV: &str = "LIANA_POSTGRES_URL";
+
+    static SCHEMA_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    // Get the configuration to a fresh, empty, Postgres schema. Each test gets its own schema so
+    // they can run in parallel.
+    fn dummy_config() -> Option<postgres::Config> {
+        let url = match env::var(POSTGRES_URL_ENV) {
+            Ok(url) => url,
+            Err(_) => {
+                eprintln!("'{}' not set, skipping test.", POSTGRES_URL_ENV);
+                return None;
+            }
+        };
+        let mut config = postgres::Config::from_str(&url).unwrap();
+
+        let now = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let schema = format!(
+            "lianatest_{}_{}_{}",
+            std::process::id(),
+            now,
+            SCHEMA_COUNTER.fetch_add(1, Ordering::SeqCst)
+        );
+        let mut client = config.connect(postgres::NoTls).unwrap();
+        client
+            .batch_execute(&format!("CREATE SCHEMA {}", schema))
+            .unwrap();
+        config.options(&format!("-c search_path={}", schema));
+
+        Some(config)
+    }
+
+    #[test]
+    fn postgres_startup_sanity_checks() {
+        let config = match dummy_config() {
+            Some(c) => c,
+            None => return,
+        };
+        let secp = secp256k1::Secp256k1::verification_only();
+        let options = dummy_options();
+
+        assert!(!PostgresDb::new(config.clone(), None, &secp)
+            .unwrap()
+            .is_initialized()
+            .unwrap());
+        let db = PostgresDb::new(config, Some(options.clone()), &secp).unwrap();
+        assert!(db.is_initialized().unwrap());
+        db.sanity_check(options.bitcoind_network, &options.main_descriptor)
+            .unwrap();
+        assert!(db
+            .sanity_check(bitcoin::Network::Testnet, &options.main_descriptor)
+            .unwrap_err()
+            .to_string()
+            .contains("Database was created for network"));
+    }
+
+    #[test]
+    fn postgres_conformance() {
+        let config = match dummy_config() {
+            Some(c) => c,
+            None => return,
+        };
+        let secp = secp256k1::Secp256k1::verification_only();
+        let options = dummy_options();
+
+        let db = PostgresDb::new(config, Some(options.clone()), &secp).unwrap();
+        conformance_checks(&db, &options);
+    }
+}
@@ -0,0 +1,322 @@
+///! The Postgres schema. It mirrors the SQLite one, and the rows are parsed into the same
+///! structures.
+use crate::{
+    database::sqlite::schema::{
+        DbAddress, DbCoin, DbHwToken, DbLabel, DbSpendBlock, DbSpendTransaction, DbTip, DbWallet,
+    },
+    descriptors::MultipathDescriptor,
+};
+
+use std::{
+    convert::{TryFrom, TryInto},
+    str::FromStr,
+};
+
+use miniscript::bitcoin::{
+    self,
+    consensus::encode,
+    util::{bip32, psbt::PartiallySignedTransaction as Psbt},
+};
+
+// NOTE: Postgres doesn't have unsigned integers. The timestamps, output indexes and derivation
+// indexes are u32s, so we store them as BIGINTs.
+pub const SCHEMA: &str = "\
+CREATE TABLE version (
+    version BIGINT NOT NULL
+);
+
+/* About the Bitcoin network. */
+CREATE TABLE tip (
+    network TEXT NOT NULL,
+    blockheight INTEGER,
+    blockhash BYTEA
+);
+
+/* This stores metadata about our wallet. We only support single wallet for
+ * now (and the foreseeable future).
+ *
+ * The 'timestamp' field is the creation date of the wallet. We guarantee to have seen all
+ * information related to our descriptor(s) that occured after this date.
+ * The optional 'rescan_timestamp' field is a the timestamp we need to rescan the chain
+ * for events related to our descriptor(s) from.
+ * The optional 'rescan_progress_timestamp' field is a lower bound of how far the ongoing
+ * rescan got, to resume it from there if it gets interrupted.
+ */
+CREATE TABLE wallets (
+    id BIGSERIAL PRIMARY KEY,
+    timestamp BIGINT NOT NULL,
+    main_descriptor TEXT NOT NULL,
+    deposit_derivation_index BIGINT NOT NULL,
+    change_derivation_index BIGINT NOT NULL,
+    rescan_timestamp BIGINT,
+    rescan_progress_timestamp BIGINT
+);
+
+/* Our (U)TxOs.
+ *
+ * The 'spend_block_height' and 'spend_block.time' are only present if the spending
+ * transaction for this coin exists and was confirmed.
+ * The 'is_frozen' field marks coins the user does not want to be spent unless explicitly
+ * selected.
+ * The 'spend_broadcast_time' field is the time at which the coin was last marked as spent by an
+ * unconfirmed transaction.
+ */
+CREATE TABLE coins (
+    id BIGSERIAL PRIMARY KEY,
+    wallet_id BIGINT NOT NULL,
+    blockheight INTEGER,
+    blocktime BIGINT,
+    txid BYTEA NOT NULL,
+    vout BIGINT NOT NULL,
+    amount_sat BIGINT NOT NULL,
+    derivation_index BIGINT NOT NULL,
+    is_change BOOLEAN NOT NULL,
+    spend_txid BYTEA,
+    spend_block_height INTEGER,
+    spend_block_time BIGINT,
+    is_frozen BOOLEAN NOT NULL DEFAULT FALSE,
+    spend_broadcast_time BIGINT,
+    UNIQUE (txid, vout),
+    FOREIGN KEY (wallet_id) REFERENCES wallets (id)
+        ON UPDATE RESTRICT
+        ON DELETE RESTRICT
+);
+
+/* Coins are looked up by the address they pay to, that is by derivation index and keychain. */
+CREATE INDEX coins_derivation_index ON coins (derivation_index, is_change);
+
+/* A mapping from descriptor address to derivation index. Necessary until
+ * we can get the derivation index from the parent descriptor from bitcoind.
+ */
+CREATE TABLE addresses (
+    receive_address TEXT NOT NULL UNIQUE,
+    change_address TEXT NOT NULL UNIQUE,
+    derivation_index BIGINT NOT NULL UNIQUE
+);
+
+/* Transactions we created that spend some of our coins.
+ *
+ * The 'updated_at' field is the last time the transaction was stored or updated.
+ */
+CREATE TABLE spend_transactions (
+    id BIGSERIAL PRIMARY KEY,
+    psbt BYTEA UNIQUE NOT NULL,
+    txid BYTEA UNIQUE NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+/* The tokens returned by the signing devices upon registration of a descriptor (for instance
+ * the Ledger's hmac), needed to sign with the device afterward. Keyed by descriptor and device
+ * fingerprint.
+ */
+CREATE TABLE hw_tokens (
+    id BIGSERIAL PRIMARY KEY,
+    descriptor TEXT NOT NULL,
+    fingerprint BYTEA NOT NULL,
+    token BYTEA NOT NULL,
+    UNIQUE (descriptor, fingerprint)
+);
+
+/* Labels given by the user, for instance to the keys or the addresses. Keyed by the labelled
+ * item.
+ */
+CREATE TABLE labels (
+    id BIGSERIAL PRIMARY KEY,
+    item TEXT UNIQUE NOT NULL,
+    value TEXT NOT NULL
+);
+";
+
+// Get a u32 stored as a BIGINT.
+fn get_u32(row: &postgres::Row, idx: usize) -> Result<u32, postgres::Error> {
+    let n: i64 = row.try_get(idx)?;
+    Ok(n.try_into().expect("We only store u32s in there"))
+}
+
+fn get_opt_u32(row: &postgres::Row, idx: usize) -> Result<Option<u32>, postgres::Error> {
+    let n: Option<i64> = row.try_get(idx)?;
+    Ok(n.map(|n| n.try_into().expect("We only store u32s in there")))
+}
+
+impl TryFrom<&postgres::Row> for DbTip {
+    type Error = postgres::Error;
+
+    fn try_from(row: &postgres::Row) -> Result<Self, Self::Error> {
+        let network: String = row.try_get(0)?;
+        let network = bitcoin::Network::from_str(&network)
+            .expect("Insane database: can't parse network string");
+
+        let block_height: Option<i32> = row.try_get(1)?;
+        let block_hash: Option<Vec<u8>> = row.try_get(2)?;
+        let block_hash: Option<bitcoin::BlockHash> = block_hash
+            .map(|h| encode::deserialize(&h).expect("Insane database: can't parse network string"));
+
+        Ok(DbTip {
+            network,
+            block_height,
+            block_hash,
+        })
+    }
+}
+
+impl TryFrom<&postgres::Row> for DbWallet {
+    type Error = postgres::Error;
+
+    fn try_from(row: &postgres::Row) -> Result<Self, Self::Error> {
+        let id = row.try_get(0)?;
+        let timestamp = get_u32(row, 1)?;
+
+        let desc_str: String = row.try_get(2)?;
+        let main_descriptor = MultipathDescriptor::from_str(&desc_str)
+            .expect("Insane database: can't parse deposit descriptor");
+
+        let deposit_derivation_index = bip32::ChildNumber::from(get_u32(row, 3)?);
+        let change_derivation_index = bip32::ChildNumber::from(get_u32(row, 4)?);
+
+        let rescan_timestamp = get_opt_u32(row, 5)?;
+        let rescan_progress_timestamp = get_opt_u32(row, 6)?;
+
+        Ok(DbWallet {
+            id,
+            timestamp,
+            main_descriptor,
+            deposit_derivation_index,
+            change_derivation_index,
+            rescan_timestamp,
+            rescan_progress_timestamp,
+        })
+    }
+}
+
+impl TryFrom<&postgres::Row> for DbCoin {
+    type Error = postgres::Error;
+
+    fn try_from(row: &postgres::Row) -> Result<Self, Self::Error> {
+        let id = row.try_get(0)?;
+        let wallet_id = row.try_get(1)?;
+
+        let block_height = row.try_get(2)?;
+        let block_time = get_opt_u32(row, 3)?;
+        let txid: Vec<u8> = row.try_get(4)?;
+        let txid: bitcoin::Txid = encode::deserialize(&txid).expect("We only store valid txids");
+        let vout = get_u32(row, 5)?;
+        let outpoint = bitcoin::OutPoint { txid, vout };
+
+        let amount: i64 = row.try_get(6)?;
+        let amount = bitcoin::Amount::from_sat(amount.try_into().expect("Never negative"));
+        let derivation_index = bip32::ChildNumber::from(get_u32(row, 7)?);
+        let is_change: bool = row.try_get(8)?;
+
+        let spend_txid: Option<Vec<u8>> = row.try_get(9)?;
+        let spend_txid =
+            spend_txid.map(|txid| encode::deserialize(&txid).expect("We only store valid txids"));
+        let spend_height: Option<i32> = row.try_get(10)?;
+        let spend_time = get_opt_u32(row, 11)?;
+        assert_eq!(spend_height.is_none(), spend_time.is_none());
+        let spend_block = spend_height.map(|height| DbSpendBlock {
+            height,
+            time: spend_time.expect("Must be there if height is"),
+        });
+        let is_frozen: bool = row.try_get(12)?;
+        let spend_broadcast_time = get_opt_u32(row, 13)?;
+
+        Ok(DbCoin {
+            id,
+            wallet_id,
+            outpoint,
+            block_height,
+            block_time,
+            amount,
+            derivation_index,
+            is_change,
+            spend_txid,
+            spend_block,
+            spend_broadcast_time,
+            is_frozen,
+        })
+    }
+}
+
+impl TryFrom<&postgres::Row> for DbAddress {
+    type Error = postgres::Error;
+
+    fn try_from(row: &postgres::Row) -> Result<Self, Self::Error> {
+        let receive_address: String = row.try_get(0)?;
+        let receive_address =
+            bitcoin::Address::from_str(&receive_address).expect("We only store valid addresses");
+
+        let change_address: String = row.try_get(1)?;
+        let change_address =
+            bitcoin::Address::from_str(&change_address).expect("We only store valid addresses");
+
+        let derivation_index = bip32::ChildNumber::from(get_u32(row, 2)?);
+        assert!(derivation_index.is_normal());
+
+        Ok(DbAddress {
+            receive_address,
+            change_address,
+            derivation_index,
+        })
+    }
+}
+
+impl TryFrom<&postgres::Row> for DbSpendTransaction {
+    type Error = postgres::Error;
+
+    fn try_from(row: &postgres::Row) -> Result<Self, Self::Error> {
+        let id: i64 = row.try_get(0)?;
+
+        let psbt: Vec<u8> = row.try_get(1)?;
+        let psbt: Psbt = encode::deserialize(&psbt).expect("We only store valid PSBTs");
+
+        let txid: Vec<u8> = row.try_get(2)?;
+        let txid: bitcoin::Txid = encode::deserialize(&txid).expect("We only store valid txids");
+        assert_eq!(txid, psbt.unsigned_tx.txid());
+
+        let updated_at = get_u32(row, 3)?;
+
+        Ok(DbSpendTransaction {
+            id,
+            psbt,
+            txid,
+            updated_at,
+        })
+    }
+}
+
+impl TryFrom<&postgres::Row> for DbHwToken {
+    type Error = postgres::Error;
+
+    fn try_from(row: &postgres::Row) -> Result<Self, Self::Error> {
+        let id: i64 = row.try_get(0)?;
+        let descriptor: String = row.try_get(1)?;
+
+        let fingerprint: Vec<u8> = row.try_get(2)?;
+        let fingerprint = bip32::Fingerprint::from(fingerprint.as_slice());
+
+        let token: Vec<u8> = row.try_get(3)?;
+        let token: [u8; 32] = token
+            .as_slice()
+            .try_into()
+            .expect("We only store 32 bytes tokens");
+
+        Ok(DbHwToken {
+            id,
+            descriptor,
+            fingerprint,
+            token,
+        })
+    }
+}
+
+impl TryFrom<&postgres::Row> for DbLabel {
+    type Error = postgres::Error;
+
+    fn try_from(row: &postgres::Row) -> Result<Self, Self::Error> {
+        let id: i64 = row.try_get(0)?;
+        let item: String = row.try_get(1)?;
+        let value: String = row.try_get(2)?;
+
+        Ok(DbLabel { id, item, value })
+    }
+}
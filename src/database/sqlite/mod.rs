@@ -9,19 +9,24 @@
 pub mod schema;
 mod utils;
 
+pub use utils::LOOK_AHEAD_LIMIT;
+
 use crate::{
     bitcoin::BlockChainTip,
     database::{
         sqlite::{
-            schema::{DbAddress, DbCoin, DbSpendTransaction, DbTip, DbWallet},
-            utils::{create_fresh_db, db_exec, db_query, db_tx_query, LOOK_AHEAD_LIMIT},
+            schema::{DbAddress, DbCoin, DbHwToken, DbLabel, DbSpendTransaction, DbTip, DbWallet},
+            utils::{
+                create_fresh_db, curr_timestamp, db_exec, db_query, db_tx_query,
+                maybe_apply_migrations,
+            },
         },
-        Coin, CoinType,
+        Coin, CoinStatus, CoinType,
     },
     descriptors::MultipathDescriptor,
 };
 
-use std::{cmp, convert::TryInto, fmt, io, path};
+use std::{cmp, collections::BTreeMap, convert::TryInto, fmt, io, path};
 
 use miniscript::bitcoin::{
     self,
@@ -31,7 +36,9 @@ use miniscript::bitcoin::{
     util::{bip32, psbt::PartiallySignedTransaction as Psbt},
 };
 
-const DB_VERSION: i64 = 0;
+/// The version of the database schema. The schemas of all the database backends are kept in sync
+/// and share this version.
+pub const DB_VERSION: i64 = 7;
 
 #[derive(Debug)]
 pub enum SqliteDbError {
@@ -58,9 +65,11 @@ impl std::fmt::Display for SqliteDbError {
             SqliteDbError::InvalidNetwork(net) => {
                 write!(f, "Database was created for network '{}'.", net)
             }
-            SqliteDbError::DescriptorMismatch(desc) => {
-                write!(f, "Database descriptor mismatch: '{}'.", desc)
-            }
+            SqliteDbError::DescriptorMismatch(desc) => write!(
+                f,
+                "Config descriptor does not match wallet database descriptor '{}'.",
+                desc
+            ),
             SqliteDbError::Rusqlite(e) => write!(f, "SQLite error: '{}'", e),
         }
     }
@@ -84,6 +93,8 @@ impl From<rusqlite::Error> for SqliteDbError {
 pub struct FreshDbOptions {
     pub bitcoind_network: bitcoin::Network,
     pub main_descriptor: MultipathDescriptor,
+    /// The creation date of the wallet if it was imported. Defaults to now.
+    pub birthday_timestamp: Option<u32>,
 }
 
 #[derive(Debug, Clone)]
@@ -118,6 +129,14 @@ impl SqliteDb {
         Ok(SqliteConn { conn })
     }
 
+    /// Upgrade the database schema to the current version if it was created by a previous
+    /// version of the software.
+    pub fn maybe_apply_migrations(&self) -> Result<(), SqliteDbError> {
+        let mut conn = self.connection()?;
+        let db_version = conn.db_version();
+        maybe_apply_migrations(&mut conn.conn, db_version)
+    }
+
     /// Perform startup sanity checks.
     pub fn sanity_check(
         &self,
@@ -126,8 +145,8 @@ impl SqliteDb {
     ) -> Result<(), SqliteDbError> {
         let mut conn = self.connection()?;
 
-        // Check if there database isn't from the future.
-        // NOTE: we'll do migration there eventually. Until then be strict on the check.
+        // Check if there database isn't from the future. Migrations must have been applied
+        // beforehand.
         let db_version = conn.db_version();
         if db_version != DB_VERSION {
             return Err(SqliteDbError::UnsupportedVersion(db_version));
@@ -280,7 +299,21 @@ impl SqliteConn {
             // NOTE: this will need to be updated if we ever implement multi-wallet support
             db_tx
                 .execute(
-                    "UPDATE wallets SET rescan_timestamp = (?1)",
+                    "UPDATE wallets SET rescan_timestamp = (?1), rescan_progress_timestamp = NULL",
+                    rusqlite::params![timestamp],
+                )
+                .map(|_| ())
+        })
+        .expect("Database must be available")
+    }
+
+    /// Record how far the ongoing rescan got.
+    pub fn set_wallet_rescan_progress(&mut self, timestamp: u32) {
+        db_exec(&mut self.conn, |db_tx| {
+            // NOTE: this will need to be updated if we ever implement multi-wallet support
+            db_tx
+                .execute(
+                    "UPDATE wallets SET rescan_progress_timestamp = (?1)",
                     rusqlite::params![timestamp],
                 )
                 .map(|_| ())
@@ -304,7 +337,7 @@ impl SqliteConn {
             // NOTE: this will need to be updated if we ever implement multi-wallet support
             db_tx
                 .execute(
-                    "UPDATE wallets SET timestamp = (?1), rescan_timestamp = NULL",
+                    "UPDATE wallets SET timestamp = (?1), rescan_timestamp = NULL, rescan_progress_timestamp = NULL",
                     rusqlite::params![new_timestamp],
                 )
                 .map(|_| ())
@@ -312,6 +345,21 @@ impl SqliteConn {
         .expect("Database must be available");
     }
 
+    /// Drop the rescan timestamp without updating the wallet creation timestamp, as the rescan
+    /// did not complete.
+    pub fn cancel_wallet_rescan(&mut self) {
+        db_exec(&mut self.conn, |db_tx| {
+            // NOTE: this will need to be updated if we ever implement multi-wallet support
+            db_tx
+                .execute(
+                    "UPDATE wallets SET rescan_timestamp = NULL, rescan_progress_timestamp = NULL",
+                    rusqlite::params![],
+                )
+                .map(|_| ())
+        })
+        .expect("Database must be available");
+    }
+
     /// Get all the coins from DB.
     pub fn coins(&mut self, coin_type: CoinType) -> Vec<DbCoin> {
         db_query(
@@ -327,6 +375,39 @@ impl SqliteConn {
         .expect("Db must not fail")
     }
 
+    /// List the coins in any of the given statuses, among the given outpoints. Empty lists
+    /// aren't used as filters.
+    pub fn list_coins(
+        &mut self,
+        statuses: &[CoinStatus],
+        outpoints: &[bitcoin::OutPoint],
+    ) -> Vec<DbCoin> {
+        let query = list_coins_query(statuses, outpoints);
+        db_query(&mut self.conn, &query, rusqlite::params![], |row| {
+            row.try_into()
+        })
+        .expect("Db must not fail")
+    }
+
+    /// Same as `list_coins` but ordered by insertion, skipping the first `start` coins and
+    /// returning at most `count` of them.
+    pub fn list_coins_page(
+        &mut self,
+        statuses: &[CoinStatus],
+        outpoints: &[bitcoin::OutPoint],
+        start: u64,
+        count: u64,
+    ) -> Vec<DbCoin> {
+        let query = list_coins_query(statuses, outpoints) + " ORDER BY id LIMIT ?1 OFFSET ?2";
+        db_query(
+            &mut self.conn,
+            &query,
+            rusqlite::params![page_param(count), page_param(start)],
+            |row| row.try_into(),
+        )
+        .expect("Db must not fail")
+    }
+
     /// List coins that are being spent and whose spending transaction is still unconfirmed.
     pub fn list_spending_coins(&mut self) -> Vec<DbCoin> {
         db_query(
@@ -369,11 +450,19 @@ impl SqliteConn {
         outpoints: impl IntoIterator<Item = &'a (bitcoin::OutPoint, i32, u32)>,
     ) {
         db_exec(&mut self.conn, |db_tx| {
+            // Compile the statement once for the whole batch, there may be thousands of coins
+            // to confirm during the initial sync of a busy wallet.
+            let mut stmt = db_tx.prepare(
+                "UPDATE coins SET blockheight = ?1, blocktime = ?2 WHERE txid = ?3 AND vout = ?4",
+            )?;
             for (outpoint, height, time) in outpoints {
-                db_tx.execute(
-                    "UPDATE coins SET blockheight = ?1, blocktime = ?2 WHERE txid = ?3 AND vout = ?4",
-                    rusqlite::params![height, time, outpoint.txid.to_vec(), outpoint.vout,],
-                )?;
+                let updated = stmt.execute(rusqlite::params![
+                    height,
+                    time,
+                    outpoint.txid.to_vec(),
+                    outpoint.vout,
+                ])?;
+                assert_eq!(updated, 1, "Unknown coin '{}'", outpoint);
             }
 
             Ok(())
@@ -381,16 +470,60 @@ impl SqliteConn {
         .expect("Database must be available")
     }
 
-    /// Mark a set of coins as spent.
+    /// Mark a set of coins as spent, recording the current time as their broadcast time.
+    ///
+    /// All coins must be in database. The update is atomic: if one of them isn't, none is marked.
     pub fn spend_coins<'a>(
         &mut self,
         outpoints: impl IntoIterator<Item = &'a (bitcoin::OutPoint, bitcoin::Txid)>,
     ) {
+        let now = curr_timestamp();
         db_exec(&mut self.conn, |db_tx| {
             for (outpoint, spend_txid) in outpoints {
+                let updated = db_tx.execute(
+                    "UPDATE coins SET spend_txid = ?1, spend_broadcast_time = ?2 WHERE txid = ?3 AND vout = ?4",
+                    rusqlite::params![
+                        spend_txid.to_vec(),
+                        now,
+                        outpoint.txid.to_vec(),
+                        outpoint.vout,
+                    ],
+                )?;
+                // Panicking here drops the transaction without committing it.
+                assert_eq!(updated, 1, "Unknown coin '{}'", outpoint);
+            }
+
+            Ok(())
+        })
+        .expect("Database must be available")
+    }
+
+    /// Freeze or unfreeze a set of coins.
+    pub fn set_frozen<'a>(
+        &mut self,
+        outpoints: impl IntoIterator<Item = &'a bitcoin::OutPoint>,
+        frozen: bool,
+    ) {
+        db_exec(&mut self.conn, |db_tx| {
+            for outpoint in outpoints {
+                db_tx.execute(
+                    "UPDATE coins SET is_frozen = ?1 WHERE txid = ?2 AND vout = ?3",
+                    rusqlite::params![frozen, outpoint.txid.to_vec(), outpoint.vout],
+                )?;
+            }
+
+            Ok(())
+        })
+        .expect("Database must be available")
+    }
+
+    /// Remove a set of unconfirmed coins.
+    pub fn remove_coins<'a>(&mut self, outpoints: impl IntoIterator<Item = &'a bitcoin::OutPoint>) {
+        db_exec(&mut self.conn, |db_tx| {
+            for outpoint in outpoints {
                 db_tx.execute(
-                    "UPDATE coins SET spend_txid = ?1 WHERE txid = ?2 AND vout = ?3",
-                    rusqlite::params![spend_txid.to_vec(), outpoint.txid.to_vec(), outpoint.vout,],
+                    "DELETE FROM coins WHERE txid = ?1 AND vout = ?2 AND blockheight IS NULL",
+                    rusqlite::params![outpoint.txid.to_vec(), outpoint.vout],
                 )?;
             }
 
@@ -401,22 +534,25 @@ impl SqliteConn {
 
     /// Mark the Spend transaction of a given set of coins as being confirmed at a given
     /// block.
+    ///
+    /// All coins must be in database. The update is atomic: if one of them isn't, none is marked.
     pub fn confirm_spend<'a>(
         &mut self,
         outpoints: impl IntoIterator<Item = &'a (bitcoin::OutPoint, bitcoin::Txid, i32, u32)>,
     ) {
         db_exec(&mut self.conn, |db_tx| {
+            let mut stmt = db_tx.prepare(
+                "UPDATE coins SET spend_txid = ?1, spend_block_height = ?2, spend_block_time = ?3 WHERE txid = ?4 AND vout = ?5",
+            )?;
             for (outpoint, spend_txid, height, time) in outpoints {
-                db_tx.execute(
-                    "UPDATE coins SET spend_txid = ?1, spend_block_height = ?2, spend_block_time = ?3 WHERE txid = ?4 AND vout = ?5",
-                    rusqlite::params![
-                        spend_txid.to_vec(),
-                        height,
-                        time,
-                        outpoint.txid.to_vec(),
-                        outpoint.vout,
-                    ],
-                )?;
+                let updated = stmt.execute(rusqlite::params![
+                    spend_txid.to_vec(),
+                    height,
+                    time,
+                    outpoint.txid.to_vec(),
+                    outpoint.vout,
+                ])?;
+                assert_eq!(updated, 1, "Unknown coin '{}'", outpoint);
             }
 
             Ok(())
@@ -435,7 +571,42 @@ impl SqliteConn {
         .pop()
     }
 
+    /// Get the coins paying to any of the addresses at these (derivation index, is change)
+    /// pairs. This is an indexed query, addresses don't need to be derived.
+    pub fn db_coins_by_derivation_indexes(
+        &mut self,
+        indexes: &[(bip32::ChildNumber, bool)],
+    ) -> Vec<DbCoin> {
+        if indexes.is_empty() {
+            return Vec::new();
+        }
+
+        // Only query by derivation index and filter by the change flag afterward. Contrary to a
+        // row value comparison, an IN clause on the first column of the index lets SQLite use
+        // it.
+        let deriv_indexes: Vec<String> = indexes
+            .iter()
+            .map(|(index, _)| u32::from(*index).to_string())
+            .collect();
+        let query = format!(
+            "SELECT * FROM coins WHERE derivation_index IN ({})",
+            deriv_indexes.join(", ")
+        );
+        db_query(&mut self.conn, &query, rusqlite::params![], |row| {
+            row.try_into()
+        })
+        .expect("Db must not fail")
+        .into_iter()
+        .filter(|db_coin: &DbCoin| indexes.contains(&(db_coin.derivation_index, db_coin.is_change)))
+        .collect()
+    }
+
     pub fn db_coins(&mut self, outpoints: &[bitcoin::OutPoint]) -> Vec<DbCoin> {
+        // An empty list of values is a syntax error.
+        if outpoints.is_empty() {
+            return Vec::new();
+        }
+
         // SELECT * FROM coins WHERE (txid, vout) IN ((txidA, voutA), (txidB, voutB));
         let mut query = "SELECT * FROM coins WHERE (txid, vout) IN (VALUES ".to_string();
         for (i, outpoint) in outpoints.iter().enumerate() {
@@ -472,23 +643,42 @@ impl SqliteConn {
     pub fn store_spend(&mut self, psbt: &Psbt) {
         let txid = psbt.unsigned_tx.txid().to_vec();
         let psbt = encode::serialize(psbt);
+        let now = curr_timestamp();
 
         db_exec(&mut self.conn, |db_tx| {
             db_tx.execute(
-                "INSERT into spend_transactions (psbt, txid) VALUES (?1, ?2) \
-                 ON CONFLICT DO UPDATE SET psbt=excluded.psbt",
-                rusqlite::params![psbt, txid],
+                "INSERT into spend_transactions (psbt, txid, updated_at) VALUES (?1, ?2, ?3) \
+                 ON CONFLICT DO UPDATE SET psbt=excluded.psbt, updated_at=excluded.updated_at",
+                rusqlite::params![psbt, txid, now],
             )?;
             Ok(())
         })
         .expect("Db must not fail");
     }
 
-    pub fn list_spend(&mut self) -> Vec<DbSpendTransaction> {
+    /// List the Spend transactions, optionally only those updated at or after `since`.
+    pub fn list_spend(&mut self, since: Option<u32>) -> Vec<DbSpendTransaction> {
         db_query(
             &mut self.conn,
-            "SELECT * FROM spend_transactions",
-            rusqlite::params![],
+            "SELECT * FROM spend_transactions WHERE updated_at >= ?1",
+            rusqlite::params![since.unwrap_or(0)],
+            |row| row.try_into(),
+        )
+        .expect("Db must not fail")
+    }
+
+    /// List at most `count` Spend transactions ordered by insertion, skipping the first `start`.
+    /// Optionally only consider those updated at or after `since`.
+    pub fn list_spend_page(
+        &mut self,
+        since: Option<u32>,
+        start: u64,
+        count: u64,
+    ) -> Vec<DbSpendTransaction> {
+        db_query(
+            &mut self.conn,
+            "SELECT * FROM spend_transactions WHERE updated_at >= ?1 ORDER BY id LIMIT ?2 OFFSET ?3",
+            rusqlite::params![since.unwrap_or(0), page_param(count), page_param(start)],
             |row| row.try_into(),
         )
         .expect("Db must not fail")
@@ -526,6 +716,39 @@ impl SqliteConn {
         .expect("Db must not fail")
     }
 
+    /// Get up to `limit` confirmed txids and their block time, ordered by (block time, txid) from
+    /// the most recent. Only those strictly before `before` if it's set.
+    pub fn db_list_txids_page(
+        &mut self,
+        before: Option<(u32, bitcoin::Txid)>,
+        limit: u64,
+    ) -> Vec<(u32, bitcoin::Txid)> {
+        let (before_time, before_txid) = match before {
+            Some((time, txid)) => (Some(time), Some(txid.to_vec())),
+            None => (None, None),
+        };
+        db_query(
+            &mut self.conn,
+            "SELECT date, txid FROM ( \
+                SELECT blocktime AS date, txid FROM coins WHERE blocktime IS NOT NULL \
+                UNION \
+                SELECT spend_block_time AS date, spend_txid AS txid FROM coins \
+                WHERE spend_block_time IS NOT NULL \
+            ) \
+            WHERE (?1) IS NULL OR date < (?1) OR (date = (?1) AND txid < (?2)) \
+            ORDER BY date DESC, txid DESC LIMIT (?3)",
+            rusqlite::params![before_time, before_txid, limit],
+            |row| {
+                let time: u32 = row.get(0)?;
+                let txid: Vec<u8> = row.get(1)?;
+                let txid: bitcoin::Txid =
+                    encode::deserialize(&txid).expect("We only store valid txids");
+                Ok((time, txid))
+            },
+        )
+        .expect("Db must not fail")
+    }
+
     pub fn delete_spend(&mut self, txid: &bitcoin::Txid) {
         db_exec(&mut self.conn, |db_tx| {
             db_tx.execute(
@@ -537,6 +760,66 @@ impl SqliteConn {
         .expect("Db must not fail");
     }
 
+    /// Store the registration token of the device with this fingerprint for this descriptor,
+    /// replacing any existing one.
+    pub fn store_hw_token(
+        &mut self,
+        descriptor: &MultipathDescriptor,
+        fingerprint: &bip32::Fingerprint,
+        token: &[u8; 32],
+    ) {
+        db_exec(&mut self.conn, |db_tx| {
+            db_tx.execute(
+                "INSERT INTO hw_tokens (descriptor, fingerprint, token) VALUES (?1, ?2, ?3) \
+                 ON CONFLICT DO UPDATE SET token=excluded.token",
+                rusqlite::params![
+                    descriptor.to_string(),
+                    fingerprint[..].to_vec(),
+                    token.to_vec()
+                ],
+            )?;
+            Ok(())
+        })
+        .expect("Db must not fail");
+    }
+
+    /// Get all the registration tokens stored for this descriptor.
+    pub fn db_hw_tokens(&mut self, descriptor: &MultipathDescriptor) -> Vec<DbHwToken> {
+        db_query(
+            &mut self.conn,
+            "SELECT * FROM hw_tokens WHERE descriptor = ?1",
+            rusqlite::params![descriptor.to_string()],
+            |row| row.try_into(),
+        )
+        .expect("Db must not fail")
+    }
+
+    /// Store these labels, replacing the existing ones for the same items.
+    pub fn update_labels(&mut self, labels: &BTreeMap<String, String>) {
+        db_exec(&mut self.conn, |db_tx| {
+            for (item, value) in labels {
+                db_tx.execute(
+                    "INSERT INTO labels (item, value) VALUES (?1, ?2) \
+                     ON CONFLICT DO UPDATE SET value=excluded.value",
+                    rusqlite::params![item, value],
+                )?;
+            }
+            Ok(())
+        })
+        .expect("Db must not fail");
+    }
+
+    /// Get all the labels.
+    pub fn db_labels(&mut self) -> Vec<DbLabel> {
+        db_query(
+            &mut self.conn,
+            "SELECT * FROM labels",
+            rusqlite::params![],
+            |row| row.try_into(),
+        )
+        .expect("Db must not fail")
+    }
+
     /// Unconfirm all data that was marked as being confirmed *after* the given chain
     /// tip, and set it as our new best block seen.
     ///
@@ -567,8 +850,46 @@ impl SqliteConn {
     }
 }
 
+// The query to select the coins in any of the given statuses, among the given outpoints.
+fn list_coins_query(statuses: &[CoinStatus], outpoints: &[bitcoin::OutPoint]) -> String {
+    let mut conditions = Vec::with_capacity(2);
+    if !statuses.is_empty() {
+        let statuses: Vec<&str> = statuses.iter().map(status_condition).collect();
+        conditions.push(format!("({})", statuses.join(" OR ")));
+    }
+    if !outpoints.is_empty() {
+        // NOTE: the txid is not stored as little-endian. Convert it to vec first.
+        let values: Vec<String> = outpoints
+            .iter()
+            .map(|op| format!("(x'{}', {})", op.txid.to_vec().to_hex(), op.vout))
+            .collect();
+        conditions.push(format!("(txid, vout) IN (VALUES {})", values.join(", ")));
+    }
+    let mut query = "SELECT * FROM coins".to_string();
+    if !conditions.is_empty() {
+        query += " WHERE ";
+        query += &conditions.join(" AND ");
+    }
+    query
+}
+
+// SQLite integers are signed, cap a LIMIT or OFFSET value to the largest one it can represent.
+fn page_param(n: u64) -> i64 {
+    n.try_into().unwrap_or(i64::MAX)
+}
+
+// The condition on a row of the coins table for this coin to be in the given state.
+fn status_condition(status: &CoinStatus) -> &'static str {
+    match status {
+        CoinStatus::Unconfirmed => "(spend_txid IS NULL AND blockheight IS NULL)",
+        CoinStatus::Confirmed => "(spend_txid IS NULL AND blockheight IS NOT NULL)",
+        CoinStatus::Spending => "(spend_txid IS NOT NULL AND spend_block_height IS NULL)",
+        CoinStatus::Spent => "spend_block_height IS NOT NULL",
+    }
+}
+
 #[cfg(test)]
-mod tests {
+pub mod tests {
     use super::*;
     use crate::database::SpendBlock;
     use crate::testutils::*;
@@ -580,12 +901,13 @@ mod tests {
 
     use bitcoin::{hashes::Hash, util::bip32};
 
-    fn dummy_options() -> FreshDbOptions {
+    pub fn dummy_options() -> FreshDbOptions {
         let desc_str = "wsh(andor(pk(tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk(tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#5f6qd0d9";
         let main_descriptor = MultipathDescriptor::from_str(desc_str).unwrap();
         FreshDbOptions {
             bitcoind_network: bitcoin::Network::Bitcoin,
             main_descriptor,
+            birthday_timestamp: None,
         }
     }
 
@@ -625,18 +947,20 @@ mod tests {
         let options = dummy_options();
 
         let db = SqliteDb::new(db_path.clone(), Some(options.clone()), &secp).unwrap();
-        db.sanity_check(bitcoin::Network::Testnet, &options.main_descriptor)
+        assert!(db
+            .sanity_check(bitcoin::Network::Testnet, &options.main_descriptor)
             .unwrap_err()
             .to_string()
-            .contains("Database was created for network");
+            .contains("Database was created for network"));
         fs::remove_file(&db_path).unwrap();
         let other_desc_str = "wsh(andor(pk(tpubDExU4YLJkyQ9RRbVScQq2brFxWWha7WmAUByPWyaWYwmcTv3Shx8aHp6mVwuE5n4TeM4z5DTWGf2YhNPmXtfvyr8cUDVvA3txdrFnFgNdF7/<0;1>/*),older(10000),pk(tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))";
         let other_desc = MultipathDescriptor::from_str(other_desc_str).unwrap();
         let db = SqliteDb::new(db_path.clone(), Some(options.clone()), &secp).unwrap();
-        db.sanity_check(bitcoin::Network::Bitcoin, &other_desc)
+        assert!(db
+            .sanity_check(bitcoin::Network::Bitcoin, &other_desc)
             .unwrap_err()
             .to_string()
-            .contains("Database descriptor mismatch");
+            .contains("Config descriptor does not match wallet database descriptor"));
         fs::remove_file(&db_path).unwrap();
         // TODO: version check
 
@@ -650,6 +974,120 @@ mod tests {
         fs::remove_dir_all(tmp_dir).unwrap();
     }
 
+    #[test]
+    fn db_migration() {
+        let (tmp_dir, options, _, db) = dummy_db();
+        let coin = Coin {
+            outpoint: bitcoin::OutPoint::from_str(
+                "6f0dc85a369f44d4e5b7b7a6b1e0c2f1e2d7a7c0f5b2b5c1d1a4f0b8a3b2c1d0:1",
+            )
+            .unwrap(),
+            block_height: None,
+            block_time: None,
+            amount: bitcoin::Amount::from_sat(98765),
+            derivation_index: bip32::ChildNumber::from_normal_idx(10).unwrap(),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        };
+
+        // Turn our fresh database into a version 0 one, before coins could be frozen, before
+        // the broadcast time of spends was recorded, before the rescan progress was, before
+        // coins were indexed by derivation index, before labels could be stored and before the
+        // signing devices tokens were.
+        {
+            let mut conn = db.connection().unwrap();
+            conn.new_unspent_coins(&[coin]);
+            conn.spend_coins(&[(coin.outpoint, coin.outpoint.txid)]);
+            conn.store_spend(&dummy_psbt(coin.outpoint));
+            conn.conn
+                .execute_batch(
+                    "DROP TABLE hw_tokens; \
+                     DROP TABLE labels; \
+                     ALTER TABLE spend_transactions DROP COLUMN updated_at; \
+                     DROP INDEX coins_derivation_index; \
+                     ALTER TABLE wallets DROP COLUMN rescan_progress_timestamp; \
+                     ALTER TABLE coins DROP COLUMN spend_broadcast_time; \
+                     ALTER TABLE coins DROP COLUMN is_frozen; UPDATE version SET version = 0;",
+                )
+                .unwrap();
+            assert_eq!(conn.db_version(), 0);
+        }
+        assert!(db
+            .sanity_check(options.bitcoind_network, &options.main_descriptor)
+            .unwrap_err()
+            .to_string()
+            .contains("Unsupported database version '0'"));
+
+        // Once upgraded, the existing coins are unfrozen and can be frozen. Those being spent
+        // are considered broadcast at the time of the upgrade.
+        db.maybe_apply_migrations().unwrap();
+        db.sanity_check(options.bitcoind_network, &options.main_descriptor)
+            .unwrap();
+        {
+            let mut conn = db.connection().unwrap();
+            assert_eq!(conn.db_version(), DB_VERSION);
+            let db_coin = conn.db_coins(&[coin.outpoint]).pop().unwrap();
+            assert!(!db_coin.is_frozen);
+            assert!(db_coin.spend_broadcast_time.is_some());
+            conn.set_frozen(&[coin.outpoint], true);
+            let db_coin = conn.db_coins(&[coin.outpoint]).pop().unwrap();
+            assert!(db_coin.is_frozen);
+            assert!(conn.db_wallet().rescan_progress_timestamp.is_none());
+            assert_eq!(
+                conn.db_coins_by_derivation_indexes(&[(coin.derivation_index, false)])
+                    .len(),
+                1
+            );
+            let index_count: i64 = conn
+                .conn
+                .query_row(
+                    "SELECT COUNT(*) FROM sqlite_master WHERE type = 'index' AND name = 'coins_derivation_index'",
+                    rusqlite::params![],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(index_count, 1);
+            // Existing Spends are considered updated at the time of the upgrade.
+            let spends = conn.list_spend(None);
+            assert_eq!(spends.len(), 1);
+            assert!(spends[0].updated_at + 60 > curr_timestamp());
+            // Labels can be stored.
+            assert!(conn.db_labels().is_empty());
+            let mut labels = BTreeMap::new();
+            labels.insert("aabbccdd".to_string(), "Owner".to_string());
+            conn.update_labels(&labels);
+            assert_eq!(conn.db_labels().len(), 1);
+            // So can the signing devices tokens.
+            let desc = &options.main_descriptor;
+            assert!(conn.db_hw_tokens(desc).is_empty());
+            let fingerprint = bip32::Fingerprint::from(&[0xde, 0xad, 0xbe, 0xef][..]);
+            conn.store_hw_token(desc, &fingerprint, &[1; 32]);
+            assert_eq!(conn.db_hw_tokens(desc).len(), 1);
+        }
+
+        // Applying the migrations again is a no-op.
+        db.maybe_apply_migrations().unwrap();
+
+        // A database from the future is refused.
+        {
+            let mut conn = db.connection().unwrap();
+            conn.conn
+                .execute("UPDATE version SET version = 1000", rusqlite::params![])
+                .unwrap();
+        }
+        db.maybe_apply_migrations().unwrap();
+        assert!(db
+            .sanity_check(options.bitcoind_network, &options.main_descriptor)
+            .unwrap_err()
+            .to_string()
+            .contains("Unsupported database version '1000'"));
+
+        fs::remove_dir_all(tmp_dir).unwrap();
+    }
+
     #[test]
     fn db_tip_update() {
         let (tmp_dir, options, _, db) = dummy_db();
@@ -701,6 +1139,8 @@ mod tests {
                 is_change: false,
                 spend_txid: None,
                 spend_block: None,
+                spend_broadcast_time: None,
+                is_frozen: false,
             };
             conn.new_unspent_coins(&[coin_a]);
             assert_eq!(conn.coins(CoinType::All)[0].outpoint, coin_a.outpoint);
@@ -709,6 +1149,7 @@ mod tests {
             let coins = conn.db_coins(&[coin_a.outpoint]);
             assert_eq!(coins.len(), 1);
             assert_eq!(coins[0].outpoint, coin_a.outpoint);
+            assert!(conn.db_coins(&[]).is_empty());
 
             // It is unspent.
             assert_eq!(conn.coins(CoinType::Unspent)[0].outpoint, coin_a.outpoint);
@@ -727,6 +1168,8 @@ mod tests {
                 is_change: true,
                 spend_txid: None,
                 spend_block: None,
+                spend_broadcast_time: None,
+                is_frozen: false,
             };
             conn.new_unspent_coins(&[coin_b]);
             let outpoints: HashSet<bitcoin::OutPoint> = conn
@@ -822,6 +1265,121 @@ mod tests {
         fs::remove_dir_all(tmp_dir).unwrap();
     }
 
+    #[test]
+    fn db_coins_batch_update() {
+        let (tmp_dir, _, _, db) = dummy_db();
+
+        {
+            let mut conn = db.connection().unwrap();
+
+            // Two sets of a few thousand coins, one is updated in a single batch and the other one
+            // coin at a time.
+            const BATCH_SIZE: u32 = 3_000;
+            let batch_txid = bitcoin::Txid::from_str(
+                "6f0dc85a369b44458eba3a1f0ea5b5935d563afb6994f70f5b0094e05be1676c",
+            )
+            .unwrap();
+            let loop_txid = bitcoin::Txid::from_str(
+                "0c62a990d20d54429e70859292e82374ba6b1b951a3ab60f26bb65fee5724ff7",
+            )
+            .unwrap();
+            let coin = |txid: bitcoin::Txid, vout: u32| Coin {
+                outpoint: bitcoin::OutPoint::new(txid, vout),
+                block_height: None,
+                block_time: None,
+                amount: bitcoin::Amount::from_sat(10_000 + vout as u64),
+                derivation_index: bip32::ChildNumber::from_normal_idx(vout).unwrap(),
+                is_change: vout % 2 == 0,
+                spend_txid: None,
+                spend_block: None,
+                spend_broadcast_time: None,
+                is_frozen: false,
+            };
+            let batch_coins: Vec<Coin> = (0..BATCH_SIZE).map(|i| coin(batch_txid, i)).collect();
+            let loop_coins: Vec<Coin> = (0..BATCH_SIZE).map(|i| coin(loop_txid, i)).collect();
+            conn.new_unspent_coins(&batch_coins);
+            conn.new_unspent_coins(&loop_coins);
+
+            // Confirm them all, at various heights.
+            let confirmed = |txid: bitcoin::Txid| -> Vec<(bitcoin::OutPoint, i32, u32)> {
+                (0..BATCH_SIZE)
+                    .map(|i| {
+                        let height = 700_000 + (i / 10) as i32;
+                        (bitcoin::OutPoint::new(txid, i), height, 1_600_000_000 + i)
+                    })
+                    .collect()
+            };
+            conn.confirm_coins(&confirmed(batch_txid));
+            for row in confirmed(loop_txid).iter() {
+                conn.confirm_coins(&[*row]);
+            }
+
+            // Then mark them as spent by confirmed transactions.
+            let spend_txid = bitcoin::Txid::from_str(
+                "5e5c1d6fd1c4d3ef1b16b3a0b03c0c4cd0d6eb8fad8e3a4a8e43ab8be11bd0bd",
+            )
+            .unwrap();
+            let spent = |txid: bitcoin::Txid| -> Vec<(bitcoin::OutPoint, bitcoin::Txid, i32, u32)> {
+                (0..BATCH_SIZE)
+                    .map(|i| {
+                        let height = 710_000 + (i / 7) as i32;
+                        let outpoint = bitcoin::OutPoint::new(txid, i);
+                        (outpoint, spend_txid, height, 1_700_000_000 + i)
+                    })
+                    .collect()
+            };
+            conn.confirm_spend(&spent(batch_txid));
+            for row in spent(loop_txid).iter() {
+                conn.confirm_spend(&[*row]);
+            }
+
+            // Both sets end up in the very same state.
+            let batch_outpoints: Vec<bitcoin::OutPoint> =
+                batch_coins.iter().map(|c| c.outpoint).collect();
+            let loop_outpoints: Vec<bitcoin::OutPoint> =
+                loop_coins.iter().map(|c| c.outpoint).collect();
+            let mut batch_res = conn.db_coins(&batch_outpoints);
+            let mut loop_res = conn.db_coins(&loop_outpoints);
+            batch_res.sort_by_key(|c| c.outpoint.vout);
+            loop_res.sort_by_key(|c| c.outpoint.vout);
+            assert_eq!(batch_res.len(), BATCH_SIZE as usize);
+            assert_eq!(loop_res.len(), BATCH_SIZE as usize);
+            for (i, (b, l)) in batch_res.iter().zip(loop_res.iter()).enumerate() {
+                assert_eq!(b.outpoint.vout, i as u32);
+                assert_eq!(l.outpoint.vout, i as u32);
+                assert_eq!(b.block_height, Some(700_000 + (i / 10) as i32));
+                assert_eq!(b.block_time, Some(1_600_000_000 + i as u32));
+                assert_eq!(b.spend_txid, Some(spend_txid));
+                assert_eq!(
+                    b.spend_block.as_ref().map(|b| (b.height, b.time)),
+                    Some((710_000 + (i / 7) as i32, 1_700_000_000 + i as u32))
+                );
+                assert_eq!(
+                    (b.block_height, b.block_time, b.spend_txid, b.amount),
+                    (l.block_height, l.block_time, l.spend_txid, l.amount)
+                );
+                assert_eq!(
+                    b.spend_block.as_ref().map(|b| (b.height, b.time)),
+                    l.spend_block.as_ref().map(|b| (b.height, b.time))
+                );
+            }
+
+            // An unknown coin in a batch of spends still makes it fail as a whole.
+            let unknown = bitcoin::OutPoint::new(spend_txid, 0);
+            let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                conn.confirm_spend(&[
+                    (batch_outpoints[0], batch_txid, 720_000, 1_710_000_000),
+                    (unknown, batch_txid, 720_000, 1_710_000_000),
+                ])
+            }));
+            assert!(res.is_err());
+            let first = conn.db_coins(&batch_outpoints[..1]).pop().unwrap();
+            assert_eq!(first.spend_txid, Some(spend_txid));
+        }
+
+        fs::remove_dir_all(tmp_dir).unwrap();
+    }
+
     #[test]
     fn sqlite_addresses_cache() {
         let (tmp_dir, options, secp, db) = dummy_db();
@@ -919,6 +1477,245 @@ mod tests {
         fs::remove_dir_all(tmp_dir).unwrap();
     }
 
+    #[test]
+    fn sqlite_change_index() {
+        let (tmp_dir, _, secp, db) = dummy_db();
+
+        {
+            let mut conn = db.connection().unwrap();
+            let db_wallet = conn.db_wallet();
+            assert_eq!(db_wallet.deposit_derivation_index, 0.into());
+            assert_eq!(db_wallet.change_derivation_index, 0.into());
+
+            // Bump the receive index once.
+            conn.set_derivation_index(3.into(), false, &secp);
+
+            // Incrementing the change index several times doesn't affect the deposit index.
+            for i in 1..10 {
+                conn.set_derivation_index(i.into(), true, &secp);
+                let db_wallet = conn.db_wallet();
+                assert_eq!(db_wallet.change_derivation_index, i.into());
+                assert_eq!(db_wallet.deposit_derivation_index, 3.into());
+            }
+
+            // And the other way around.
+            conn.set_derivation_index(4.into(), false, &secp);
+            let db_wallet = conn.db_wallet();
+            assert_eq!(db_wallet.deposit_derivation_index, 4.into());
+            assert_eq!(db_wallet.change_derivation_index, 9.into());
+        }
+
+        fs::remove_dir_all(tmp_dir).unwrap();
+    }
+
+    #[test]
+    fn sqlite_coins_by_derivation_indexes() {
+        let (tmp_dir, options, secp, db) = dummy_db();
+
+        {
+            let mut conn = db.connection().unwrap();
+
+            // Many coins, paying to all the addresses within the look-ahead limit, on both
+            // keychains. Some addresses are reused.
+            let txid = bitcoin::Txid::from_str(
+                "0c62a990d20d54429e70859292e82374ba6b1b951a3ab60f26bb65fee5724ff7",
+            )
+            .unwrap();
+            let coins: Vec<Coin> = (0..LOOK_AHEAD_LIMIT * 2 + 50)
+                .map(|vout| Coin {
+                    outpoint: bitcoin::OutPoint::new(txid, vout),
+                    block_height: None,
+                    block_time: None,
+                    amount: bitcoin::Amount::from_sat(10_000),
+                    derivation_index: (vout % LOOK_AHEAD_LIMIT).into(),
+                    is_change: (vout / LOOK_AHEAD_LIMIT) % 2 == 1,
+                    spend_txid: None,
+                    spend_block: None,
+                    spend_broadcast_time: None,
+                    is_frozen: false,
+                })
+                .collect();
+            conn.new_unspent_coins(&coins);
+
+            // Resolve some addresses to their derivation index and keychain, like when filtering
+            // transactions by address.
+            let indexes: Vec<(bip32::ChildNumber, bool)> = (0..LOOK_AHEAD_LIMIT)
+                .step_by(7)
+                .flat_map(|index| {
+                    let receive_desc = options.main_descriptor.receive_descriptor();
+                    let change_desc = options.main_descriptor.change_descriptor();
+                    vec![
+                        receive_desc.derive(index.into(), &secp),
+                        change_desc.derive((index + 3).into(), &secp),
+                    ]
+                })
+                .map(|desc| {
+                    let addr = desc.address(options.bitcoind_network);
+                    let db_addr = conn.db_address(&addr).unwrap();
+                    (db_addr.derivation_index, addr == db_addr.change_address)
+                })
+                .collect();
+            assert!(indexes.iter().any(|(_, is_change)| *is_change));
+            assert!(indexes.iter().any(|(_, is_change)| !*is_change));
+
+            // The indexed lookup returns exactly what scanning all the coins would.
+            let mut scanned: Vec<bitcoin::OutPoint> = conn
+                .coins(CoinType::All)
+                .into_iter()
+                .filter(|c| indexes.contains(&(c.derivation_index, c.is_change)))
+                .map(|c| c.outpoint)
+                .collect();
+            let mut looked_up: Vec<bitcoin::OutPoint> = conn
+                .db_coins_by_derivation_indexes(&indexes)
+                .into_iter()
+                .map(|c| c.outpoint)
+                .collect();
+            scanned.sort();
+            looked_up.sort();
+            assert!(!looked_up.is_empty());
+            assert_eq!(looked_up, scanned);
+            assert!(conn.db_coins_by_derivation_indexes(&[]).is_empty());
+            assert!(conn
+                .db_coins_by_derivation_indexes(&[(LOOK_AHEAD_LIMIT.into(), false)])
+                .is_empty());
+
+            // And it does use the index.
+            let plan: Vec<String> = db_query(
+                &mut conn.conn,
+                "EXPLAIN QUERY PLAN SELECT * FROM coins WHERE derivation_index IN (1, 2, 3)",
+                rusqlite::params![],
+                |row| row.get(3),
+            )
+            .unwrap();
+            assert!(plan
+                .iter()
+                .any(|step| step.contains("USING INDEX coins_derivation_index")));
+        }
+
+        fs::remove_dir_all(tmp_dir).unwrap();
+    }
+
+    fn dummy_psbt(prev_outpoint: bitcoin::OutPoint) -> Psbt {
+        Psbt::from_unsigned_tx(bitcoin::Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime(0),
+            input: vec![bitcoin::TxIn {
+                previous_output: prev_outpoint,
+                ..bitcoin::TxIn::default()
+            }],
+            output: vec![bitcoin::TxOut {
+                value: 90_000,
+                script_pubkey: bitcoin::Script::new(),
+            }],
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn sqlite_spend_updated_at() {
+        let (tmp_dir, _, _, db) = dummy_db();
+
+        {
+            let mut conn = db.connection().unwrap();
+            let txid = bitcoin::Txid::from_str(
+                "0c62a990d20d54429e70859292e82374ba6b1b951a3ab60f26bb65fee5724ff7",
+            )
+            .unwrap();
+            let psbts: Vec<Psbt> = (0..3)
+                .map(|vout| dummy_psbt(bitcoin::OutPoint::new(txid, vout)))
+                .collect();
+            for psbt in &psbts {
+                conn.store_spend(psbt);
+            }
+            assert_eq!(conn.list_spend(None).len(), 3);
+
+            // Pretend they were updated a long time ago, at different times.
+            for (i, psbt) in psbts.iter().enumerate() {
+                conn.conn
+                    .execute(
+                        "UPDATE spend_transactions SET updated_at = ?1 WHERE txid = ?2",
+                        rusqlite::params![1_000 * (i as u32 + 1), psbt.unsigned_tx.txid().to_vec()],
+                    )
+                    .unwrap();
+            }
+
+            // Only those updated at or after the cutoff are returned.
+            let updated = |conn: &mut SqliteConn, since: u32| -> Vec<bitcoin::Txid> {
+                let mut txids: Vec<bitcoin::Txid> = conn
+                    .list_spend(Some(since))
+                    .into_iter()
+                    .map(|db_spend| db_spend.txid)
+                    .collect();
+                txids.sort();
+                txids
+            };
+            let txids: Vec<bitcoin::Txid> =
+                psbts.iter().map(|psbt| psbt.unsigned_tx.txid()).collect();
+            let mut expected = vec![txids[1], txids[2]];
+            expected.sort();
+            assert_eq!(updated(&mut conn, 2_000), expected);
+            assert_eq!(updated(&mut conn, 2_001), vec![txids[2]]);
+            assert!(updated(&mut conn, 3_001).is_empty());
+            assert_eq!(conn.list_spend(Some(0)).len(), 3);
+            assert_eq!(conn.list_spend_page(Some(2_000), 0, 10).len(), 2);
+            assert_eq!(conn.list_spend_page(Some(2_000), 1, 10).len(), 1);
+
+            // Updating a Spend bumps its update time.
+            let mut psbt = psbts[0].clone();
+            psbt.inputs[0].witness_utxo = Some(bitcoin::TxOut {
+                value: 100_000,
+                script_pubkey: bitcoin::Script::new(),
+            });
+            conn.store_spend(&psbt);
+            let mut expected = vec![txids[0], txids[2]];
+            expected.sort();
+            assert_eq!(updated(&mut conn, 3_000), expected);
+            assert_eq!(conn.db_spend(&txids[0]).unwrap().psbt, psbt);
+        }
+
+        fs::remove_dir_all(tmp_dir).unwrap();
+    }
+
+    #[test]
+    fn sqlite_hw_tokens() {
+        let (tmp_dir, options, _, db) = dummy_db();
+
+        {
+            let mut conn = db.connection().unwrap();
+            let desc = &options.main_descriptor;
+            assert!(conn.db_hw_tokens(desc).is_empty());
+
+            let fg_a = bip32::Fingerprint::from_str("aabbccdd").unwrap();
+            let fg_b = bip32::Fingerprint::from_str("11223344").unwrap();
+            conn.store_hw_token(desc, &fg_a, &[1; 32]);
+            conn.store_hw_token(desc, &fg_b, &[2; 32]);
+            let tokens: HashMap<_, _> = conn
+                .db_hw_tokens(desc)
+                .into_iter()
+                .map(|t| (t.fingerprint, t.token))
+                .collect();
+            assert_eq!(tokens.len(), 2);
+            assert_eq!(tokens[&fg_a], [1; 32]);
+            assert_eq!(tokens[&fg_b], [2; 32]);
+
+            // Storing a token again for the same device replaces it.
+            conn.store_hw_token(desc, &fg_a, &[3; 32]);
+            let tokens = conn.db_hw_tokens(desc);
+            assert_eq!(tokens.len(), 2);
+            assert_eq!(
+                tokens.iter().find(|t| t.fingerprint == fg_a).unwrap().token,
+                [3; 32]
+            );
+
+            // The tokens are specific to a descriptor.
+            let other_desc_str = "wsh(andor(pk(tpubDExU4YLJkyQ9RRbVScQq2brFxWWha7WmAUByPWyaWYwmcTv3Shx8aHp6mVwuE5n4TeM4z5DTWGf2YhNPmXtfvyr8cUDVvA3txdrFnFgNdF7/<0;1>/*),older(10000),pk(tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))";
+            let other_desc = MultipathDescriptor::from_str(other_desc_str).unwrap();
+            assert!(conn.db_hw_tokens(&other_desc).is_empty());
+        }
+
+        fs::remove_dir_all(tmp_dir).unwrap();
+    }
+
     #[test]
     fn sqlite_tip_rollback() {
         let (tmp_dir, _, _, db) = dummy_db();
@@ -954,6 +1751,8 @@ mod tests {
                     is_change: false,
                     spend_txid: None,
                     spend_block: None,
+                    spend_broadcast_time: None,
+                    is_frozen: false,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::from_str(
@@ -967,6 +1766,8 @@ mod tests {
                     is_change: false,
                     spend_txid: None,
                     spend_block: None,
+                    spend_broadcast_time: None,
+                    is_frozen: false,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::from_str(
@@ -988,6 +1789,8 @@ mod tests {
                         height: 101_199,
                         time: 1_231_678,
                     }),
+                    spend_broadcast_time: None,
+                    is_frozen: false,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::from_str(
@@ -1001,6 +1804,8 @@ mod tests {
                     is_change: false,
                     spend_txid: None,
                     spend_block: None,
+                    spend_broadcast_time: None,
+                    is_frozen: false,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::from_str(
@@ -1022,6 +1827,8 @@ mod tests {
                         height: 101_105,
                         time: 1_201_678,
                     }),
+                    spend_broadcast_time: None,
+                    is_frozen: false,
                 },
             ];
             conn.new_unspent_coins(&coins);
@@ -1107,6 +1914,25 @@ mod tests {
         fs::remove_dir_all(tmp_dir).unwrap();
     }
 
+    #[test]
+    fn db_birthday() {
+        let tmp_dir = tmp_dir();
+        fs::create_dir_all(&tmp_dir).unwrap();
+        let secp = secp256k1::Secp256k1::verification_only();
+        let db_path: path::PathBuf = [tmp_dir.as_path(), path::Path::new("lianad.sqlite3")]
+            .iter()
+            .collect();
+
+        // The birthday of an imported wallet is used as its creation timestamp.
+        let mut options = dummy_options();
+        options.birthday_timestamp = Some(1_600_000_000);
+        let db = SqliteDb::new(db_path, Some(options), &secp).unwrap();
+        let mut conn = db.connection().unwrap();
+        assert_eq!(conn.db_wallet().timestamp, 1_600_000_000);
+
+        fs::remove_dir_all(tmp_dir).unwrap();
+    }
+
     #[test]
     fn db_rescan() {
         let (tmp_dir, _, _, db) = dummy_db();
@@ -1123,6 +1949,16 @@ mod tests {
             // But if we set one there'll be
             conn.set_wallet_rescan_timestamp(dummy_timestamp);
             assert_eq!(conn.db_wallet().rescan_timestamp, Some(dummy_timestamp));
+            assert!(conn.db_wallet().rescan_progress_timestamp.is_none());
+
+            // Its progress can be recorded, and is reset along with the rescan timestamp.
+            conn.set_wallet_rescan_progress(dummy_timestamp + 10);
+            let db_wallet = conn.db_wallet();
+            assert_eq!(db_wallet.rescan_timestamp, Some(dummy_timestamp));
+            assert_eq!(
+                db_wallet.rescan_progress_timestamp,
+                Some(dummy_timestamp + 10)
+            );
 
             // Once it's done the rescan timestamp will be erased, and the
             // wallet timestamp will be set to the dummy timestamp since it's
@@ -1130,6 +1966,7 @@ mod tests {
             conn.complete_wallet_rescan();
             let db_wallet = conn.db_wallet();
             assert!(db_wallet.rescan_timestamp.is_none());
+            assert!(db_wallet.rescan_progress_timestamp.is_none());
             assert_eq!(db_wallet.timestamp, dummy_timestamp);
 
             // If we rescan from a later timestamp, we'll keep the existing
@@ -1165,6 +2002,8 @@ mod tests {
                     is_change: false,
                     spend_txid: None,
                     spend_block: None,
+                    spend_broadcast_time: None,
+                    is_frozen: false,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::from_str(
@@ -1178,6 +2017,8 @@ mod tests {
                     is_change: false,
                     spend_txid: None,
                     spend_block: None,
+                    spend_broadcast_time: None,
+                    is_frozen: false,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::from_str(
@@ -1199,6 +2040,8 @@ mod tests {
                         height: 101_199,
                         time: 1_123_000,
                     }),
+                    spend_broadcast_time: None,
+                    is_frozen: false,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::from_str(
@@ -1212,6 +2055,8 @@ mod tests {
                     is_change: false,
                     spend_txid: None,
                     spend_block: None,
+                    spend_broadcast_time: None,
+                    is_frozen: false,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::from_str(
@@ -1233,6 +2078,8 @@ mod tests {
                         height: 101_105,
                         time: 1_126_000,
                     }),
+                    spend_broadcast_time: None,
+                    is_frozen: false,
                 },
             ];
             conn.new_unspent_coins(&coins);
@@ -1297,4 +2144,11 @@ mod tests {
 
         fs::remove_dir_all(tmp_dir).unwrap();
     }
+
+    #[test]
+    fn sqlite_conformance() {
+        let (tmp_dir, options, _, db) = dummy_db();
+        crate::database::tests::conformance_checks(&db, &options);
+        fs::remove_dir_all(tmp_dir).unwrap();
+    }
 }
@@ -1,6 +1,9 @@
 use crate::descriptors::MultipathDescriptor;
 
-use std::{convert::TryFrom, str::FromStr};
+use std::{
+    convert::{TryFrom, TryInto},
+    str::FromStr,
+};
 
 use miniscript::bitcoin::{
     self,
@@ -27,6 +30,8 @@ CREATE TABLE tip (
  * information related to our descriptor(s) that occured after this date.
  * The optional 'rescan_timestamp' field is a the timestamp we need to rescan the chain
  * for events related to our descriptor(s) from.
+ * The optional 'rescan_progress_timestamp' field is a lower bound of how far the ongoing
+ * rescan got, to resume it from there if it gets interrupted.
  */
 CREATE TABLE wallets (
     id INTEGER PRIMARY KEY NOT NULL,
@@ -34,13 +39,18 @@ CREATE TABLE wallets (
     main_descriptor TEXT NOT NULL,
     deposit_derivation_index INTEGER NOT NULL,
     change_derivation_index INTEGER NOT NULL,
-    rescan_timestamp INTEGER
+    rescan_timestamp INTEGER,
+    rescan_progress_timestamp INTEGER
 );
 
 /* Our (U)TxOs.
  *
  * The 'spend_block_height' and 'spend_block.time' are only present if the spending
  * transaction for this coin exists and was confirmed.
+ * The 'is_frozen' field marks coins the user does not want to be spent unless explicitly
+ * selected.
+ * The 'spend_broadcast_time' field is the time at which the coin was last marked as spent by an
+ * unconfirmed transaction.
  */
 CREATE TABLE coins (
     id INTEGER PRIMARY KEY NOT NULL,
@@ -55,12 +65,17 @@ CREATE TABLE coins (
     spend_txid BLOB,
     spend_block_height INTEGER,
     spend_block_time INTEGER,
+    is_frozen BOOLEAN NOT NULL DEFAULT 0 CHECK (is_frozen IN (0,1)),
+    spend_broadcast_time INTEGER,
     UNIQUE (txid, vout),
     FOREIGN KEY (wallet_id) REFERENCES wallets (id)
         ON UPDATE RESTRICT
         ON DELETE RESTRICT
 );
 
+/* Coins are looked up by the address they pay to, that is by derivation index and keychain. */
+CREATE INDEX coins_derivation_index ON coins (derivation_index, is_change);
+
 /* A mapping from descriptor address to derivation index. Necessary until
  * we can get the derivation index from the parent descriptor from bitcoind.
  */
@@ -70,11 +85,36 @@ CREATE TABLE addresses (
     derivation_index INTEGER NOT NULL UNIQUE
 );
 
-/* Transactions we created that spend some of our coins. */
+/* Transactions we created that spend some of our coins.
+ *
+ * The 'updated_at' field is the last time the transaction was stored or updated.
+ */
 CREATE TABLE spend_transactions (
     id INTEGER PRIMARY KEY NOT NULL,
     psbt BLOB UNIQUE NOT NULL,
-    txid BLOB UNIQUE NOT NULL
+    txid BLOB UNIQUE NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+/* The tokens returned by the signing devices upon registration of a descriptor (for instance
+ * the Ledger's hmac), needed to sign with the device afterward. Keyed by descriptor and device
+ * fingerprint.
+ */
+CREATE TABLE hw_tokens (
+    id INTEGER PRIMARY KEY NOT NULL,
+    descriptor TEXT NOT NULL,
+    fingerprint BLOB NOT NULL,
+    token BLOB NOT NULL,
+    UNIQUE (descriptor, fingerprint)
+);
+
+/* Labels given by the user, for instance to the keys or the addresses. Keyed by the labelled
+ * item.
+ */
+CREATE TABLE labels (
+    id INTEGER PRIMARY KEY NOT NULL,
+    item TEXT UNIQUE NOT NULL,
+    value TEXT NOT NULL
 );
 ";
 
@@ -116,6 +156,7 @@ pub struct DbWallet {
     pub deposit_derivation_index: bip32::ChildNumber,
     pub change_derivation_index: bip32::ChildNumber,
     pub rescan_timestamp: Option<u32>,
+    pub rescan_progress_timestamp: Option<u32>,
 }
 
 impl TryFrom<&rusqlite::Row<'_>> for DbWallet {
@@ -135,6 +176,7 @@ impl TryFrom<&rusqlite::Row<'_>> for DbWallet {
         let change_derivation_index = bip32::ChildNumber::from(der_idx);
 
         let rescan_timestamp = row.get(5)?;
+        let rescan_progress_timestamp = row.get(6)?;
 
         Ok(DbWallet {
             id,
@@ -143,6 +185,7 @@ impl TryFrom<&rusqlite::Row<'_>> for DbWallet {
             deposit_derivation_index,
             change_derivation_index,
             rescan_timestamp,
+            rescan_progress_timestamp,
         })
     }
 }
@@ -165,6 +208,8 @@ pub struct DbCoin {
     pub is_change: bool,
     pub spend_txid: Option<bitcoin::Txid>,
     pub spend_block: Option<DbSpendBlock>,
+    pub spend_broadcast_time: Option<u32>,
+    pub is_frozen: bool,
 }
 
 impl TryFrom<&rusqlite::Row<'_>> for DbCoin {
@@ -197,6 +242,8 @@ impl TryFrom<&rusqlite::Row<'_>> for DbCoin {
             height,
             time: spend_time.expect("Must be there if height is"),
         });
+        let is_frozen: bool = row.get(12)?;
+        let spend_broadcast_time: Option<u32> = row.get(13)?;
 
         Ok(DbCoin {
             id,
@@ -209,6 +256,8 @@ impl TryFrom<&rusqlite::Row<'_>> for DbCoin {
             is_change,
             spend_txid,
             spend_block,
+            spend_broadcast_time,
+            is_frozen,
         })
     }
 }
@@ -250,6 +299,7 @@ pub struct DbSpendTransaction {
     pub id: i64,
     pub psbt: Psbt,
     pub txid: bitcoin::Txid,
+    pub updated_at: u32,
 }
 
 impl TryFrom<&rusqlite::Row<'_>> for DbSpendTransaction {
@@ -265,6 +315,67 @@ impl TryFrom<&rusqlite::Row<'_>> for DbSpendTransaction {
         let txid: bitcoin::Txid = encode::deserialize(&txid).expect("We only store valid txids");
         assert_eq!(txid, psbt.unsigned_tx.txid());
 
-        Ok(DbSpendTransaction { id, psbt, txid })
+        let updated_at: u32 = row.get(3)?;
+
+        Ok(DbSpendTransaction {
+            id,
+            psbt,
+            txid,
+            updated_at,
+        })
+    }
+}
+
+/// A row in the "hw_tokens" table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DbHwToken {
+    pub id: i64,
+    pub descriptor: String,
+    pub fingerprint: bip32::Fingerprint,
+    pub token: [u8; 32],
+}
+
+impl TryFrom<&rusqlite::Row<'_>> for DbHwToken {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &rusqlite::Row) -> Result<Self, Self::Error> {
+        let id: i64 = row.get(0)?;
+        let descriptor: String = row.get(1)?;
+
+        let fingerprint: Vec<u8> = row.get(2)?;
+        let fingerprint = bip32::Fingerprint::from(fingerprint.as_slice());
+
+        let token: Vec<u8> = row.get(3)?;
+        let token: [u8; 32] = token
+            .as_slice()
+            .try_into()
+            .expect("We only store 32 bytes tokens");
+
+        Ok(DbHwToken {
+            id,
+            descriptor,
+            fingerprint,
+            token,
+        })
+    }
+}
+
+/// A row in the "labels" table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DbLabel {
+    pub id: i64,
+    pub item: String,
+    pub value: String,
+}
+
+impl TryFrom<&rusqlite::Row<'_>> for DbLabel {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &rusqlite::Row) -> Result<Self, Self::Error> {
+        let id: i64 = row.get(0)?;
+        let item: String = row.get(1)?;
+        let value: String = row.get(2)?;
+
+        Ok(DbLabel { id, item, value })
     }
 }
@@ -58,6 +58,14 @@ fn timestamp_to_u32(n: u64) -> u32 {
         .expect("Is this the year 2106 yet? Misconfigured system clock.")
 }
 
+/// The current time as a UNIX timestamp.
+pub fn curr_timestamp() -> u32 {
+    time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map(|dur| timestamp_to_u32(dur.as_secs()))
+        .expect("System clock went backward the epoch?")
+}
+
 // Create the db file with RW permissions only for the user
 pub fn create_db_file(db_path: &path::Path) -> Result<(), std::io::Error> {
     let mut options = fs::OpenOptions::new();
@@ -86,10 +94,7 @@ pub fn create_fresh_db(
 ) -> Result<(), SqliteDbError> {
     create_db_file(db_path)?;
 
-    let timestamp = time::SystemTime::now()
-        .duration_since(time::UNIX_EPOCH)
-        .map(|dur| timestamp_to_u32(dur.as_secs()))
-        .expect("System clock went backward the epoch?");
+    let timestamp = options.birthday_timestamp.unwrap_or_else(curr_timestamp);
 
     // Fill the initial addresses. On a fresh database, the deposit_derivation_index is
     // necessarily 0.
@@ -134,3 +139,151 @@ pub fn create_fresh_db(
 
     Ok(())
 }
+
+// Add the 'is_frozen' column to the coins table.
+fn migrate_v0_to_v1(conn: &mut rusqlite::Connection) -> Result<(), SqliteDbError> {
+    db_exec(conn, |tx| {
+        tx.execute(
+            "ALTER TABLE coins ADD COLUMN is_frozen BOOLEAN NOT NULL DEFAULT 0 CHECK (is_frozen IN (0,1))",
+            rusqlite::params![],
+        )?;
+        tx.execute("UPDATE version SET version = 1", rusqlite::params![])?;
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+// Add the 'spend_broadcast_time' column to the coins table. The coins currently being spent are
+// considered to have been broadcast at the time of the migration.
+fn migrate_v1_to_v2(conn: &mut rusqlite::Connection) -> Result<(), SqliteDbError> {
+    db_exec(conn, |tx| {
+        tx.execute(
+            "ALTER TABLE coins ADD COLUMN spend_broadcast_time INTEGER",
+            rusqlite::params![],
+        )?;
+        tx.execute(
+            "UPDATE coins SET spend_broadcast_time = ?1 WHERE spend_txid IS NOT NULL AND spend_block_time IS NULL",
+            rusqlite::params![curr_timestamp()],
+        )?;
+        tx.execute("UPDATE version SET version = 2", rusqlite::params![])?;
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+// Add the 'rescan_progress_timestamp' column to the wallets table. It is left empty: an ongoing
+// rescan will be resumed from its start.
+fn migrate_v2_to_v3(conn: &mut rusqlite::Connection) -> Result<(), SqliteDbError> {
+    db_exec(conn, |tx| {
+        tx.execute(
+            "ALTER TABLE wallets ADD COLUMN rescan_progress_timestamp INTEGER",
+            rusqlite::params![],
+        )?;
+        tx.execute("UPDATE version SET version = 3", rusqlite::params![])?;
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+// Index the coins by derivation index and keychain, to look them up by address without deriving
+// the addresses.
+fn migrate_v3_to_v4(conn: &mut rusqlite::Connection) -> Result<(), SqliteDbError> {
+    db_exec(conn, |tx| {
+        tx.execute(
+            "CREATE INDEX coins_derivation_index ON coins (derivation_index, is_change)",
+            rusqlite::params![],
+        )?;
+        tx.execute("UPDATE version SET version = 4", rusqlite::params![])?;
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+// Add the 'updated_at' column to the spend_transactions table. The existing Spend transactions are
+// considered to have been updated at the time of the migration.
+fn migrate_v4_to_v5(conn: &mut rusqlite::Connection) -> Result<(), SqliteDbError> {
+    db_exec(conn, |tx| {
+        tx.execute(
+            "ALTER TABLE spend_transactions ADD COLUMN updated_at INTEGER NOT NULL DEFAULT 0",
+            rusqlite::params![],
+        )?;
+        tx.execute(
+            "UPDATE spend_transactions SET updated_at = ?1",
+            rusqlite::params![curr_timestamp()],
+        )?;
+        tx.execute("UPDATE version SET version = 5", rusqlite::params![])?;
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+// Create the 'labels' table.
+fn migrate_v5_to_v6(conn: &mut rusqlite::Connection) -> Result<(), SqliteDbError> {
+    db_exec(conn, |tx| {
+        tx.execute_batch(
+            "CREATE TABLE labels ( \
+                id INTEGER PRIMARY KEY NOT NULL, \
+                item TEXT UNIQUE NOT NULL, \
+                value TEXT NOT NULL \
+            ); \
+            UPDATE version SET version = 6;",
+        )?;
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+// Create the 'hw_tokens' table. It was only part of the schema of fresh databases, hence the
+// 'IF NOT EXISTS'.
+fn migrate_v6_to_v7(conn: &mut rusqlite::Connection) -> Result<(), SqliteDbError> {
+    db_exec(conn, |tx| {
+        tx.execute_batch(
+            "CREATE TABLE IF NOT EXISTS hw_tokens ( \
+                id INTEGER PRIMARY KEY NOT NULL, \
+                descriptor TEXT NOT NULL, \
+                fingerprint BLOB NOT NULL, \
+                token BLOB NOT NULL, \
+                UNIQUE (descriptor, fingerprint) \
+            ); \
+            UPDATE version SET version = 7;",
+        )?;
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+/// Apply the migrations needed to bring a database at version `db_version` to the current
+/// version. Each migration is applied in its own database transaction.
+pub fn maybe_apply_migrations(
+    conn: &mut rusqlite::Connection,
+    mut db_version: i64,
+) -> Result<(), SqliteDbError> {
+    while db_version < DB_VERSION {
+        log::warn!(
+            "Upgrading database from version {} to version {}.",
+            db_version,
+            db_version + 1
+        );
+        match db_version {
+            0 => migrate_v0_to_v1(conn)?,
+            1 => migrate_v1_to_v2(conn)?,
+            2 => migrate_v2_to_v3(conn)?,
+            3 => migrate_v3_to_v4(conn)?,
+            4 => migrate_v4_to_v5(conn)?,
+            5 => migrate_v5_to_v6(conn)?,
+            6 => migrate_v6_to_v7(conn)?,
+            _ => return Err(SqliteDbError::UnsupportedVersion(db_version)),
+        }
+        db_version += 1;
+        log::warn!("Database upgraded to version {}.", db_version);
+    }
+
+    Ok(())
+}
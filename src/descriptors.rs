@@ -4,7 +4,11 @@ use miniscript::{
         blockdata::transaction::Sequence,
         hashes::{hash160, ripemd160, sha256},
         secp256k1,
-        util::bip32,
+        util::{
+            bip32,
+            psbt::{Input as PsbtIn, Output as PsbtOut},
+            taproot::{LeafVersion, TapLeafHash},
+        },
     },
     descriptor, hash256,
     miniscript::{decode::Terminal, Miniscript},
@@ -13,7 +17,11 @@ use miniscript::{
     Translator,
 };
 
-use std::{collections::BTreeMap, convert::TryFrom, error, fmt, str, sync};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    error, fmt, str, sync,
+};
 
 use serde::{Deserialize, Serialize};
 
@@ -26,6 +34,40 @@ fn wu_to_vb(vb: usize) -> usize {
         .expect("Non 0")
 }
 
+/// The network extended keys are encoded for on the given network. They can only be encoded for
+/// mainnet or for the test networks, so signet and regtest use testnet keys.
+pub fn xpub_network(network: bitcoin::Network) -> bitcoin::Network {
+    match network {
+        bitcoin::Network::Bitcoin => bitcoin::Network::Bitcoin,
+        bitcoin::Network::Testnet | bitcoin::Network::Signet | bitcoin::Network::Regtest => {
+            bitcoin::Network::Testnet
+        }
+    }
+}
+
+/// Whether this address may be used on the given network. Parsed addresses are only ever for
+/// mainnet, testnet or regtest: signet shares the testnet encoding, and regtest shares it for
+/// base58 addresses.
+pub fn address_is_for_network(address: &bitcoin::Address, network: bitcoin::Network) -> bool {
+    match network {
+        bitcoin::Network::Bitcoin => address.network == bitcoin::Network::Bitcoin,
+        bitcoin::Network::Testnet => address.network == bitcoin::Network::Testnet,
+        bitcoin::Network::Signet => matches!(
+            address.network,
+            bitcoin::Network::Testnet | bitcoin::Network::Signet
+        ),
+        bitcoin::Network::Regtest => {
+            address.network == bitcoin::Network::Regtest
+                || (address.network == bitcoin::Network::Testnet
+                    && matches!(
+                        address.payload,
+                        bitcoin::util::address::Payload::PubkeyHash(_)
+                            | bitcoin::util::address::Payload::ScriptHash(_)
+                    ))
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum DescCreationError {
     InsaneTimelock(u32),
@@ -34,6 +76,7 @@ pub enum DescCreationError {
     Miniscript(miniscript::Error),
     IncompatibleDesc,
     DerivedKeyParsing,
+    NoRecoveryPath,
 }
 
 impl std::fmt::Display for DescCreationError {
@@ -55,6 +98,7 @@ impl std::fmt::Display for DescCreationError {
             Self::Miniscript(e) => write!(f, "Miniscript error: '{}'.", e),
             Self::IncompatibleDesc => write!(f, "Descriptor is not compatible."),
             Self::DerivedKeyParsing => write!(f, "Parsing derived key,"),
+            Self::NoRecoveryPath => write!(f, "At least one recovery path is required."),
         }
     }
 }
@@ -208,6 +252,237 @@ fn is_valid_desc_key(key: &descriptor::DescriptorPublicKey) -> bool {
     }
 }
 
+/// The keys required to spend through a given path of the descriptor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathInfo {
+    /// A single key.
+    Single(descriptor::DescriptorPublicKey),
+    /// A threshold of keys.
+    Multi(usize, Vec<descriptor::DescriptorPublicKey>),
+}
+
+impl PathInfo {
+    /// Get all the keys in this spending path.
+    pub fn keys(&self) -> Vec<&descriptor::DescriptorPublicKey> {
+        match self {
+            PathInfo::Single(key) => vec![key],
+            PathInfo::Multi(_, keys) => keys.iter().collect(),
+        }
+    }
+
+    /// Get the origin (master key fingerprint and derivation path from the master key) of each
+    /// key in this spending path. For keys without origin, the fingerprint of the xpub itself and
+    /// an empty derivation path are returned.
+    pub fn keys_origins(&self) -> Vec<(bip32::Fingerprint, bip32::DerivationPath)> {
+        self.keys()
+            .into_iter()
+            .filter_map(|key| match key {
+                descriptor::DescriptorPublicKey::MultiXPub(xpub) => Some(
+                    xpub.origin
+                        .clone()
+                        .unwrap_or_else(|| (xpub.xkey.fingerprint(), Vec::new().into())),
+                ),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Get the number of signatures required to spend through this path.
+    pub fn threshold(&self) -> usize {
+        match self {
+            PathInfo::Single(_) => 1,
+            PathInfo::Multi(thresh, _) => *thresh,
+        }
+    }
+
+    // Get a Miniscript of type "B" (with the "d" and "u" properties) checking signature(s) for
+    // this path. Used for the primary path.
+    fn into_ms(
+        self,
+    ) -> Result<Miniscript<descriptor::DescriptorPublicKey, miniscript::Segwitv0>, DescCreationError>
+    {
+        match self {
+            PathInfo::Single(key) => Ok(Miniscript::from_ast(Terminal::Check(sync::Arc::from(
+                Miniscript::from_ast(Terminal::PkK(key)).expect("pk_k is a valid Miniscript"),
+            )))
+            .expect("Well typed")),
+            PathInfo::Multi(thresh, keys) => Miniscript::from_ast(Terminal::Multi(thresh, keys))
+                .map_err(DescCreationError::Miniscript),
+        }
+    }
+
+    // Get a Miniscript of type "V" checking signature(s) for this path. Used for the recovery
+    // paths.
+    fn into_verify_ms(
+        self,
+    ) -> Result<Miniscript<descriptor::DescriptorPublicKey, miniscript::Segwitv0>, DescCreationError>
+    {
+        let keys_check = match self {
+            PathInfo::Single(key) => Miniscript::from_ast(Terminal::Check(sync::Arc::from(
+                Miniscript::from_ast(Terminal::PkH(key)).expect("pk_h is a valid Miniscript"),
+            )))
+            .expect("Well typed"),
+            multi => multi.into_ms()?,
+        };
+        Ok(Miniscript::from_ast(Terminal::Verify(keys_check.into())).expect("Well typed"))
+    }
+
+    // Get a Tapscript leaf checking signature(s) for this path and the given relative timelock.
+    // Used for the recovery paths of Taproot descriptors.
+    fn into_tap_leaf(
+        self,
+        timelock: u16,
+    ) -> Result<Miniscript<descriptor::DescriptorPublicKey, miniscript::Tap>, DescCreationError>
+    {
+        let keys_check = match self {
+            PathInfo::Single(key) => Miniscript::from_ast(Terminal::Check(sync::Arc::from(
+                Miniscript::from_ast(Terminal::PkK(key)).expect("pk_k is a valid Miniscript"),
+            )))
+            .expect("Well typed"),
+            PathInfo::Multi(thresh, keys) => Miniscript::from_ast(Terminal::MultiA(thresh, keys))
+                .map_err(DescCreationError::Miniscript)?,
+        };
+        let keys_check =
+            Miniscript::from_ast(Terminal::Verify(keys_check.into())).expect("Well typed");
+        let timelock = Terminal::Older(Sequence::from_height(timelock));
+        let leaf = Miniscript::from_ast(Terminal::AndV(
+            keys_check.into(),
+            Miniscript::from_ast(timelock).expect("Well typed").into(),
+        ))
+        .expect("Well typed");
+        miniscript::Tap::check_local_validity(&leaf)
+            .map_err(|e| DescCreationError::Miniscript(miniscript::Error::ContextError(e)))?;
+        Ok(leaf)
+    }
+
+    // Get the spending path out of a (normalized) semantic policy of the keys. It must be either a
+    // single key, a conjunction of keys or a threshold of keys.
+    fn from_keys_policy(
+        mut subs: Vec<SemanticPolicy<descriptor::DescriptorPublicKey>>,
+    ) -> Result<PathInfo, DescCreationError> {
+        if subs.len() == 1 {
+            match subs.pop().expect("Just checked the length") {
+                SemanticPolicy::Key(key) => Ok(PathInfo::Single(key)),
+                SemanticPolicy::Threshold(thresh, subs) => {
+                    let keys = keys_from_policies(subs)?;
+                    Ok(PathInfo::Multi(thresh, keys))
+                }
+                _ => Err(DescCreationError::IncompatibleDesc),
+            }
+        } else {
+            let keys = keys_from_policies(subs)?;
+            Ok(PathInfo::Multi(keys.len(), keys))
+        }
+    }
+}
+
+// Get the keys out of a list of semantic policies, which must all be keys.
+fn keys_from_policies(
+    subs: Vec<SemanticPolicy<descriptor::DescriptorPublicKey>>,
+) -> Result<Vec<descriptor::DescriptorPublicKey>, DescCreationError> {
+    subs.into_iter()
+        .map(|sub| match sub {
+            SemanticPolicy::Key(key) => Ok(key),
+            _ => Err(DescCreationError::IncompatibleDesc),
+        })
+        .collect()
+}
+
+/// The spending paths of a descriptor: the primary path, spendable at any time, and the recovery
+/// paths, each spendable after its own relative timelock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LianaDescInfo {
+    primary_path: PathInfo,
+    recovery_paths: BTreeMap<u16, PathInfo>,
+}
+
+impl LianaDescInfo {
+    // Parse the spending paths from the descriptor, making sure it is one of ours. For a Taproot
+    // descriptor the internal key is lifted as an alternative to the leaves, so both P2WSH and
+    // P2TR descriptors share the same semantic.
+    fn from_desc(
+        desc: &descriptor::Descriptor<descriptor::DescriptorPublicKey>,
+    ) -> Result<LianaDescInfo, DescCreationError> {
+        let policy = desc
+            .lift()
+            .map_err(DescCreationError::Miniscript)?
+            .normalized();
+        let subs = match policy {
+            SemanticPolicy::Threshold(1, subs) => Some(subs),
+            _ => None,
+        }
+        .ok_or(DescCreationError::IncompatibleDesc)?;
+
+        let mut primary_branches = Vec::new();
+        let mut recovery_paths = BTreeMap::new();
+        for sub in subs {
+            let is_timelocked = match sub {
+                SemanticPolicy::Threshold(_, ref subs) => {
+                    subs.iter().any(|s| matches!(s, SemanticPolicy::Older(_)))
+                }
+                _ => false,
+            };
+            match sub {
+                // Primary branch. Note a 1-of-N multisig would have been flattened into the
+                // top-level threshold.
+                SemanticPolicy::Key(_) | SemanticPolicy::Threshold(..) if !is_timelocked => {
+                    primary_branches.push(sub)
+                }
+                // Recovery branch. It must be a conjunction of a timelock and some keys.
+                SemanticPolicy::Threshold(k, subs) if k == subs.len() => {
+                    let (timelocks, keys): (Vec<_>, Vec<_>) = subs
+                        .into_iter()
+                        .partition(|s| matches!(s, SemanticPolicy::Older(_)));
+                    if timelocks.len() != 1 || keys.is_empty() {
+                        return Err(DescCreationError::IncompatibleDesc);
+                    }
+                    let csv_value = match timelocks[0] {
+                        SemanticPolicy::Older(csv) => csv.to_consensus_u32(),
+                        _ => unreachable!("Only timelocks were kept"),
+                    };
+                    csv_check(csv_value)?;
+                    let timelock = u16::try_from(csv_value).expect("Checked by csv_check()");
+                    let path = PathInfo::from_keys_policy(keys)?;
+                    // Each recovery path must have a distinct timelock.
+                    if recovery_paths.insert(timelock, path).is_some() {
+                        return Err(DescCreationError::IncompatibleDesc);
+                    }
+                }
+                _ => return Err(DescCreationError::IncompatibleDesc),
+            }
+        }
+
+        // There must be a single primary path. Either a single key, a threshold of keys, or
+        // a 1-of-N that was flattened.
+        let primary_path = match primary_branches.len() {
+            0 => return Err(DescCreationError::IncompatibleDesc),
+            1 => PathInfo::from_keys_policy(primary_branches)?,
+            _ => {
+                let keys = keys_from_policies(primary_branches)?;
+                PathInfo::Multi(1, keys)
+            }
+        };
+        if recovery_paths.is_empty() {
+            return Err(DescCreationError::NoRecoveryPath);
+        }
+
+        Ok(LianaDescInfo {
+            primary_path,
+            recovery_paths,
+        })
+    }
+
+    /// Get the primary spending path, available at any time.
+    pub fn primary_path(&self) -> &PathInfo {
+        &self.primary_path
+    }
+
+    /// Get the recovery paths, indexed by their timelock (in blocks).
+    pub fn recovery_paths(&self) -> &BTreeMap<u16, PathInfo> {
+        &self.recovery_paths
+    }
+}
+
 /// An [InheritanceDescriptor] that contains multipath keys for (and only for) the receive keychain
 /// and the change keychain.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -236,88 +511,36 @@ impl str::FromStr for MultipathDescriptor {
     type Err = DescCreationError;
 
     fn from_str(s: &str) -> Result<MultipathDescriptor, Self::Err> {
-        let wsh_desc = descriptor::Wsh::<descriptor::DescriptorPublicKey>::from_str(s)
+        let multi_desc = descriptor::Descriptor::<descriptor::DescriptorPublicKey>::from_str(s)
             .map_err(DescCreationError::Miniscript)?;
-        let ms = match wsh_desc.as_inner() {
-            descriptor::WshInner::Ms(ms) => ms,
+        match multi_desc {
+            descriptor::Descriptor::Wsh(ref wsh_desc) => {
+                if !matches!(wsh_desc.as_inner(), descriptor::WshInner::Ms(_)) {
+                    return Err(DescCreationError::IncompatibleDesc);
+                }
+            }
+            descriptor::Descriptor::Tr(_) => {}
             _ => return Err(DescCreationError::IncompatibleDesc),
-        };
-        let invalid_key = ms.iter_pk().find_map(|pk| {
-            if is_valid_desc_key(&pk) {
-                None
+        }
+
+        let mut invalid_key = None;
+        multi_desc.for_each_key(|pk| {
+            if is_valid_desc_key(pk) {
+                true
             } else {
-                Some(pk)
+                invalid_key = Some(pk.clone());
+                false
             }
         });
         if let Some(key) = invalid_key {
             return Err(DescCreationError::InvalidKey(key.into()));
         }
 
-        // Semantic of the Miniscript must be either the owner now, or the heir after
-        // a timelock.
-        let policy = ms
-            .lift()
-            .expect("Lifting can't fail on a Miniscript")
-            .normalized();
-        let subs = match policy {
-            SemanticPolicy::Threshold(1, subs) => Some(subs),
-            _ => None,
-        }
-        .ok_or(DescCreationError::IncompatibleDesc)?;
-        if subs.len() != 2 {
-            return Err(DescCreationError::IncompatibleDesc);
-        }
+        // Semantic of the descriptor must be either the owner now, or any of the recovery
+        // paths after their respective timelock.
+        LianaDescInfo::from_desc(&multi_desc)?;
 
-        // Owner branch
-        subs.iter()
-            .find(|s| matches!(s, SemanticPolicy::Key(_)))
-            .ok_or(DescCreationError::IncompatibleDesc)?;
-
-        // Heir branch
-        let heir_subs = subs
-            .iter()
-            .find_map(|s| match s {
-                SemanticPolicy::Threshold(2, subs) => Some(subs),
-                _ => None,
-            })
-            .ok_or(DescCreationError::IncompatibleDesc)?;
-        if heir_subs.len() != 2 {
-            return Err(DescCreationError::IncompatibleDesc);
-        }
-        // Must be timelocked
-        let csv_value = heir_subs
-            .iter()
-            .find_map(|s| match s {
-                SemanticPolicy::Older(csv) => Some(csv),
-                _ => None,
-            })
-            .ok_or(DescCreationError::IncompatibleDesc)?;
-        csv_check(csv_value.to_consensus_u32())?;
-        // And key locked
-        heir_subs
-            .iter()
-            .find(|s| matches!(s, SemanticPolicy::Key(_)))
-            .ok_or(DescCreationError::IncompatibleDesc)?;
-        let multi_desc = descriptor::Descriptor::Wsh(wsh_desc);
-
-        // Compute the receive and change "sub" descriptors right away. According to our pubkey
-        // check above, there must be only two of those, 0 and 1.
-        // We use /0/* for receiving and /1/* for change.
-        // FIXME: don't rely on into_single_descs()'s ordering.
-        let mut singlepath_descs = multi_desc
-            .clone()
-            .into_single_descriptors()
-            .expect("Can't error, all paths have the same length")
-            .into_iter();
-        assert_eq!(singlepath_descs.len(), 2);
-        let receive_desc = InheritanceDescriptor(singlepath_descs.next().expect("First of 2"));
-        let change_desc = InheritanceDescriptor(singlepath_descs.next().expect("Second of 2"));
-
-        Ok(MultipathDescriptor {
-            multi_desc,
-            receive_desc,
-            change_desc,
-        })
+        Ok(MultipathDescriptor::from_multi_desc(multi_desc))
     }
 }
 
@@ -334,11 +557,15 @@ impl PartialEq<descriptor::Descriptor<descriptor::DescriptorPublicKey>> for Inhe
 }
 
 impl MultipathDescriptor {
-    pub fn new(
-        owner_key: descriptor::DescriptorPublicKey,
-        heir_key: descriptor::DescriptorPublicKey,
-        timelock: u16,
-    ) -> Result<MultipathDescriptor, DescCreationError> {
+    // Sanity check the spending paths of a descriptor we are about to create.
+    fn check_paths(
+        primary_path: &PathInfo,
+        recovery_paths: &BTreeMap<u16, PathInfo>,
+    ) -> Result<(), DescCreationError> {
+        if recovery_paths.is_empty() {
+            return Err(DescCreationError::NoRecoveryPath);
+        }
+
         // We require the locktime to:
         //  - not be disabled
         //  - be in number of blocks
@@ -346,63 +573,40 @@ impl MultipathDescriptor {
         //  - be positive (Miniscript requires it not to be 0)
         //
         // All this is achieved through asking for a 16-bit integer.
-        if timelock == 0 {
-            return Err(DescCreationError::InsaneTimelock(timelock as u32));
+        if let Some(timelock) = recovery_paths.keys().find(|tl| **tl == 0) {
+            return Err(DescCreationError::InsaneTimelock(*timelock as u32));
         }
-        let timelock = Sequence::from_height(timelock);
 
-        if let Some(key) = vec![&owner_key, &heir_key]
-            .iter()
-            .find(|k| !is_valid_desc_key(k))
-        {
+        let all_keys: Vec<&descriptor::DescriptorPublicKey> = primary_path
+            .keys()
+            .into_iter()
+            .chain(recovery_paths.values().flat_map(|path| path.keys()))
+            .collect();
+        if let Some(key) = all_keys.iter().find(|k| !is_valid_desc_key(k)) {
             return Err(DescCreationError::InvalidKey((**key).clone().into()));
         }
 
         // Check for key duplicates. They are invalid in (nonmalleable) miniscripts.
-        let owner_xpub = match owner_key {
-            descriptor::DescriptorPublicKey::MultiXPub(ref multi_xpub) => multi_xpub.xkey,
-            _ => unreachable!("Just checked it was a multixpub above"),
-        };
-        let heir_xpub = match heir_key {
-            descriptor::DescriptorPublicKey::MultiXPub(ref multi_xpub) => multi_xpub.xkey,
-            _ => unreachable!("Just checked it was a multixpub above"),
-        };
-        if owner_xpub == heir_xpub {
-            return Err(DescCreationError::DuplicateKey(owner_key.into()));
+        let mut xpubs = Vec::with_capacity(all_keys.len());
+        for key in all_keys {
+            let xpub = match key {
+                descriptor::DescriptorPublicKey::MultiXPub(ref multi_xpub) => multi_xpub.xkey,
+                _ => unreachable!("Just checked it was a multixpub above"),
+            };
+            if xpubs.contains(&xpub) {
+                return Err(DescCreationError::DuplicateKey(key.clone().into()));
+            }
+            xpubs.push(xpub);
         }
 
-        let owner_pk = Miniscript::from_ast(Terminal::Check(sync::Arc::from(
-            Miniscript::from_ast(Terminal::PkK(owner_key)).expect("pk_k is a valid Miniscript"),
-        )))
-        .expect("Well typed");
-
-        let heir_pkh = Miniscript::from_ast(Terminal::Check(sync::Arc::from(
-            Miniscript::from_ast(Terminal::PkH(heir_key)).expect("pk_h is a valid Miniscript"),
-        )))
-        .expect("Well typed");
-
-        let heir_timelock = Terminal::Older(timelock);
-        let heir_branch = Miniscript::from_ast(Terminal::AndV(
-            Miniscript::from_ast(Terminal::Verify(heir_pkh.into()))
-                .expect("Well typed")
-                .into(),
-            Miniscript::from_ast(heir_timelock)
-                .expect("Well typed")
-                .into(),
-        ))
-        .expect("Well typed");
-
-        let tl_miniscript =
-            Miniscript::from_ast(Terminal::OrD(owner_pk.into(), heir_branch.into()))
-                .expect("Well typed");
-        miniscript::Segwitv0::check_local_validity(&tl_miniscript)
-            .expect("Miniscript must be sane");
-        let multi_desc = descriptor::Descriptor::Wsh(
-            descriptor::Wsh::new(tl_miniscript).expect("Must pass sanity checks"),
-        );
+        Ok(())
+    }
 
-        // Compute the receive and change "sub" descriptors right away. According to our pubkey
-        // check above, there must be only two of those, 0 and 1.
+    // Compute the receive and change "sub" descriptors of a sanity checked multipath descriptor.
+    fn from_multi_desc(
+        multi_desc: descriptor::Descriptor<descriptor::DescriptorPublicKey>,
+    ) -> MultipathDescriptor {
+        // According to our pubkey check, there must be only two of those, 0 and 1.
         // We use /0/* for receiving and /1/* for change.
         // FIXME: don't rely on into_single_descs()'s ordering.
         let mut singlepath_descs = multi_desc
@@ -414,11 +618,84 @@ impl MultipathDescriptor {
         let receive_desc = InheritanceDescriptor(singlepath_descs.next().expect("First of 2"));
         let change_desc = InheritanceDescriptor(singlepath_descs.next().expect("Second of 2"));
 
-        Ok(MultipathDescriptor {
+        MultipathDescriptor {
             multi_desc,
             receive_desc,
             change_desc,
-        })
+        }
+    }
+
+    /// Create a descriptor spendable through the primary path at any time, or through any of the
+    /// recovery paths once their respective timelock expired.
+    pub fn new(
+        primary_path: PathInfo,
+        recovery_paths: BTreeMap<u16, PathInfo>,
+    ) -> Result<MultipathDescriptor, DescCreationError> {
+        MultipathDescriptor::check_paths(&primary_path, &recovery_paths)?;
+
+        let primary_ms = primary_path.into_ms()?;
+
+        // Each recovery path is its keys check followed by its timelock. The recovery branches
+        // are nested in or_i()s, starting from the one with the shortest timelock.
+        let mut recovery_tree: Option<Miniscript<_, miniscript::Segwitv0>> = None;
+        for (timelock, path) in recovery_paths.into_iter().rev() {
+            let timelock = Terminal::Older(Sequence::from_height(timelock));
+            let branch = Miniscript::from_ast(Terminal::AndV(
+                path.into_verify_ms()?.into(),
+                Miniscript::from_ast(timelock).expect("Well typed").into(),
+            ))
+            .expect("Well typed");
+            recovery_tree = Some(match recovery_tree {
+                None => branch,
+                Some(tree) => Miniscript::from_ast(Terminal::OrI(branch.into(), tree.into()))
+                    .expect("Well typed"),
+            });
+        }
+        let recovery_tree = recovery_tree.expect("Checked it's not empty above");
+
+        let tl_miniscript =
+            Miniscript::from_ast(Terminal::OrD(primary_ms.into(), recovery_tree.into()))
+                .expect("Well typed");
+        miniscript::Segwitv0::check_local_validity(&tl_miniscript)
+            .map_err(|e| DescCreationError::Miniscript(miniscript::Error::ContextError(e)))?;
+        let multi_desc = descriptor::Descriptor::Wsh(
+            descriptor::Wsh::new(tl_miniscript).map_err(DescCreationError::Miniscript)?,
+        );
+
+        Ok(MultipathDescriptor::from_multi_desc(multi_desc))
+    }
+
+    /// Create a Taproot descriptor spendable through the primary path at any time, or through
+    /// any of the recovery paths once their respective timelock expired.
+    ///
+    /// The primary path is the key path spend, therefore it must be a single key. Each recovery
+    /// path is a leaf of the Taproot tree.
+    pub fn new_taproot(
+        primary_path: PathInfo,
+        recovery_paths: BTreeMap<u16, PathInfo>,
+    ) -> Result<MultipathDescriptor, DescCreationError> {
+        MultipathDescriptor::check_paths(&primary_path, &recovery_paths)?;
+
+        let internal_key = match primary_path {
+            PathInfo::Single(key) => key,
+            PathInfo::Multi(..) => return Err(DescCreationError::IncompatibleDesc),
+        };
+
+        // The tree is built starting from the leaf with the longest timelock, so the shortest
+        // timelock recovery path ends up closest to the root.
+        let mut tap_tree: Option<descriptor::TapTree<_>> = None;
+        for (timelock, path) in recovery_paths.into_iter().rev() {
+            let leaf = descriptor::TapTree::Leaf(path.into_tap_leaf(timelock)?.into());
+            tap_tree = Some(match tap_tree {
+                None => leaf,
+                Some(tree) => descriptor::TapTree::Tree(leaf.into(), tree.into()),
+            });
+        }
+        let multi_desc = descriptor::Descriptor::Tr(
+            descriptor::Tr::new(internal_key, tap_tree).map_err(DescCreationError::Miniscript)?,
+        );
+
+        Ok(MultipathDescriptor::from_multi_desc(multi_desc))
     }
 
     /// Whether all xpubs contained in this descriptor are for the passed expected network.
@@ -432,6 +709,40 @@ impl MultipathDescriptor {
         })
     }
 
+    /// Get the checksum of this descriptor.
+    pub fn checksum(&self) -> String {
+        self.multi_desc
+            .to_string()
+            .rsplit('#')
+            .next()
+            .expect("There is always a checksum")
+            .to_string()
+    }
+
+    /// Get the origin (master key fingerprint and derivation path from the master key) and the
+    /// extended public key of each key in this descriptor. For keys without origin, the
+    /// fingerprint of the xpub itself and an empty derivation path are returned.
+    pub fn keys_origins(
+        &self,
+    ) -> Vec<(
+        bip32::Fingerprint,
+        bip32::DerivationPath,
+        bip32::ExtendedPubKey,
+    )> {
+        let mut origins = Vec::new();
+        self.multi_desc.for_each_key(|key| {
+            if let descriptor::DescriptorPublicKey::MultiXPub(xpub) = key {
+                let (fingerprint, der_path) = xpub
+                    .origin
+                    .clone()
+                    .unwrap_or_else(|| (xpub.xkey.fingerprint(), Vec::new().into()));
+                origins.push((fingerprint, der_path, xpub.xkey));
+            }
+            true
+        });
+        origins
+    }
+
     /// Get the descriptor for receiving addresses.
     pub fn receive_descriptor(&self) -> &InheritanceDescriptor {
         &self.receive_desc
@@ -442,56 +753,46 @@ impl MultipathDescriptor {
         &self.change_desc
     }
 
-    /// Get the value (in blocks) of the relative timelock for the heir's spending path.
-    pub fn timelock_value(&self) -> u32 {
-        let wsh_desc = match &self.multi_desc {
-            descriptor::Descriptor::Wsh(desc) => desc,
-            _ => unreachable!(),
-        };
-        let ms = match wsh_desc.as_inner() {
-            descriptor::WshInner::Ms(ms) => ms,
-            _ => unreachable!(),
-        };
+    /// Get the spending paths of this descriptor.
+    pub fn info(&self) -> LianaDescInfo {
+        LianaDescInfo::from_desc(&self.multi_desc).expect("Checked at creation")
+    }
 
-        let policy = ms
-            .lift()
-            .expect("Lifting can't fail on a Miniscript")
-            .normalized();
-        let subs = match policy {
-            SemanticPolicy::Threshold(1, subs) => subs,
-            _ => unreachable!(),
-        };
-        let heir_subs = subs
-            .iter()
-            .find_map(|s| match s {
-                SemanticPolicy::Threshold(2, subs) => Some(subs),
-                _ => None,
-            })
-            .expect("Always present");
-        let csv = heir_subs
-            .iter()
-            .find_map(|s| match s {
-                SemanticPolicy::Older(csv) => Some(csv),
-                _ => None,
-            })
-            .expect("Always present");
+    /// Whether this is a Taproot descriptor.
+    pub fn is_taproot(&self) -> bool {
+        matches!(self.multi_desc, descriptor::Descriptor::Tr(_))
+    }
+
+    /// Get the value (in blocks) of the relative timelock for the first (that is, the one with
+    /// the shortest timelock) recovery path.
+    pub fn timelock_value(&self) -> u32 {
+        let info = self.info();
+        let timelock = info
+            .recovery_paths()
+            .keys()
+            .next()
+            .expect("There is always at least one recovery path");
+        *timelock as u32
+    }
 
-        assert!(csv.is_height_locked());
-        csv.to_consensus_u32()
+    /// Get the values (in blocks) of the relative timelocks of all the recovery paths, from the
+    /// shortest to the longest.
+    pub fn timelock_values(&self) -> Vec<u16> {
+        self.info().recovery_paths().keys().copied().collect()
     }
 
     /// Get the maximum size in WU of a satisfaction for this descriptor.
     pub fn max_sat_weight(&self) -> usize {
         self.multi_desc
             .max_satisfaction_weight()
-            .expect("Cannot fail for P2WSH")
+            .expect("Cannot fail for P2WSH or P2TR")
     }
 
     /// Get the maximum size in vbytes (rounded up) of a satisfaction for this descriptor.
     pub fn max_sat_vbytes(&self) -> usize {
         self.multi_desc
             .max_satisfaction_weight()
-            .expect("Cannot fail for P2WSH")
+            .expect("Cannot fail for P2WSH or P2TR")
             .checked_add(WITNESS_FACTOR - 1)
             .unwrap()
             .checked_div(WITNESS_FACTOR)
@@ -568,36 +869,190 @@ impl InheritanceDescriptor {
 /// Map of a raw public key to the xpub used to derive it and its derivation path
 pub type Bip32Deriv = BTreeMap<secp256k1::PublicKey, (bip32::Fingerprint, bip32::DerivationPath)>;
 
+/// Map of a raw x-only public key to the leaves it appears in, the xpub used to derive it and
+/// its derivation path.
+pub type TapKeyOrigins = BTreeMap<
+    secp256k1::XOnlyPublicKey,
+    (
+        Vec<TapLeafHash>,
+        (bip32::Fingerprint, bip32::DerivationPath),
+    ),
+>;
+
 impl DerivedInheritanceDescriptor {
     pub fn address(&self, network: bitcoin::Network) -> bitcoin::Address {
         self.0
             .address(network)
-            .expect("A P2WSH always has an address")
+            .expect("A P2WSH or P2TR always has an address")
     }
 
     pub fn script_pubkey(&self) -> bitcoin::Script {
         self.0.script_pubkey()
     }
 
-    pub fn witness_script(&self) -> bitcoin::Script {
-        self.0.explicit_script().expect("Not a Taproot descriptor")
+    /// Get the maximum size in WU of a satisfaction for this derived descriptor.
+    pub fn max_sat_weight(&self) -> usize {
+        self.0
+            .max_satisfaction_weight()
+            .expect("Cannot fail for P2WSH or P2TR")
+    }
+
+    /// The witness script, if this is not a Taproot descriptor.
+    pub fn witness_script(&self) -> Option<bitcoin::Script> {
+        self.0.explicit_script().ok()
     }
 
     pub fn bip32_derivations(&self) -> Bip32Deriv {
-        let ms = match self.0 {
-            descriptor::Descriptor::Wsh(ref wsh) => match wsh.as_inner() {
-                descriptor::WshInner::Ms(ms) => ms,
-                descriptor::WshInner::SortedMulti(_) => {
-                    unreachable!("None of our descriptors is a sorted multi")
-                }
-            },
-            _ => unreachable!("All our descriptors are always P2WSH"),
+        let mut derivs = BTreeMap::new();
+        // For DerivedPublicKey, Pk::Hash == Self.
+        self.0.for_each_key(|k| {
+            derivs.insert(k.key.inner, (k.origin.0, k.origin.1.clone()));
+            true
+        });
+        derivs
+    }
+
+    /// The origin of each key in this Taproot descriptor along with the leaves it appears in.
+    /// Empty if this is not a Taproot descriptor.
+    pub fn tap_key_origins(&self) -> TapKeyOrigins {
+        let tr = match self.0 {
+            descriptor::Descriptor::Tr(ref tr) => tr,
+            _ => return BTreeMap::new(),
         };
 
-        // For DerivedPublicKey, Pk::Hash == Self.
-        ms.iter_pk()
-            .map(|k| (k.key.inner, (k.origin.0, k.origin.1)))
-            .collect()
+        let mut origins = BTreeMap::new();
+        let internal_key = tr.internal_key();
+        origins.insert(
+            internal_key.to_x_only_pubkey(),
+            (Vec::new(), internal_key.origin.clone()),
+        );
+        for (_, ms) in tr.iter_scripts() {
+            let leaf_hash = TapLeafHash::from_script(&ms.encode(), LeafVersion::TapScript);
+            for key in ms.iter_pk() {
+                origins
+                    .entry(key.to_x_only_pubkey())
+                    .or_insert_with(|| (Vec::new(), key.origin.clone()))
+                    .0
+                    .push(leaf_hash);
+            }
+        }
+        origins
+    }
+
+    /// Fill a PSBT input spending a coin from this descriptor with the information signing
+    /// devices need: the witness script for P2WSH, the internal key and leaves for Taproot.
+    pub fn update_psbt_in(&self, psbtin: &mut PsbtIn) {
+        match self.0 {
+            descriptor::Descriptor::Tr(ref tr) => {
+                let spend_info = tr.spend_info();
+                psbtin.tap_internal_key = Some(spend_info.internal_key());
+                psbtin.tap_merkle_root = spend_info.merkle_root();
+                for (_, ms) in tr.iter_scripts() {
+                    let script = ms.encode();
+                    let leaf = (script, LeafVersion::TapScript);
+                    let control_block = spend_info
+                        .control_block(&leaf)
+                        .expect("The leaf is part of the tree");
+                    psbtin.tap_scripts.insert(control_block, leaf);
+                }
+                psbtin.tap_key_origins = self.tap_key_origins();
+            }
+            _ => {
+                psbtin.witness_script = self.witness_script();
+                psbtin.bip32_derivation = self.bip32_derivations();
+            }
+        }
+    }
+
+    /// Fill a PSBT output paying to this descriptor with the information signing devices need
+    /// to recognize it as ours.
+    pub fn update_psbt_out(&self, psbtout: &mut PsbtOut) {
+        match self.0 {
+            descriptor::Descriptor::Tr(ref tr) => {
+                psbtout.tap_internal_key = Some(tr.internal_key().to_x_only_pubkey());
+                psbtout.tap_key_origins = self.tap_key_origins();
+            }
+            _ => {
+                psbtout.witness_script = self.witness_script();
+                psbtout.bip32_derivation = self.bip32_derivations();
+            }
+        }
+    }
+}
+
+/// A bounded cache of the derivations of a multipath descriptor, keyed by keychain and
+/// derivation index. The least recently used derivation is evicted once the cache is full.
+///
+/// Deriving a descriptor is CPU intensive, this avoids doing it over and over for the same coins
+/// (for instance when creating transactions spending many of them).
+#[derive(Debug)]
+pub struct DerivedDescCache {
+    capacity: usize,
+    // Incremented at each access, to tell which entry was used the least recently.
+    counter: u64,
+    entries: HashMap<(bool, bip32::ChildNumber), (DerivedInheritanceDescriptor, u64)>,
+    // The key of each entry by its last access, so the least recently used one is found without
+    // going through all of them.
+    recency: BTreeMap<u64, (bool, bip32::ChildNumber)>,
+}
+
+impl DerivedDescCache {
+    pub fn new(capacity: usize) -> DerivedDescCache {
+        assert!(capacity > 0);
+        DerivedDescCache {
+            capacity,
+            counter: 0,
+            entries: HashMap::with_capacity(capacity),
+            recency: BTreeMap::new(),
+        }
+    }
+
+    /// Number of derivations currently in cache.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Get the derivation of the change or receive descriptor of `desc` at this index. It must
+    /// always be called with the same descriptor.
+    ///
+    /// # Panics
+    /// - If the given index is hardened.
+    pub fn derive(
+        &mut self,
+        desc: &MultipathDescriptor,
+        index: bip32::ChildNumber,
+        is_change: bool,
+        secp: &secp256k1::Secp256k1<impl secp256k1::Verification>,
+    ) -> DerivedInheritanceDescriptor {
+        self.counter += 1;
+        let key = (is_change, index);
+        if let Some((derived, last_used)) = self.entries.get_mut(&key) {
+            self.recency.remove(last_used);
+            self.recency.insert(self.counter, key);
+            *last_used = self.counter;
+            return derived.clone();
+        }
+
+        let derived = if is_change {
+            desc.change_descriptor().derive(index, secp)
+        } else {
+            desc.receive_descriptor().derive(index, secp)
+        };
+        if self.entries.len() >= self.capacity {
+            let last_used = *self.recency.keys().next().expect("Capacity is never 0");
+            let lru_key = self
+                .recency
+                .remove(&last_used)
+                .expect("Just got it from the map");
+            self.entries.remove(&lru_key);
+        }
+        self.entries.insert(key, (derived.clone(), self.counter));
+        self.recency.insert(self.counter, key);
+        derived
     }
 }
 
@@ -607,54 +1062,123 @@ mod tests {
 
     use std::str::FromStr;
 
+    fn heir_path(timelock: u16, key: descriptor::DescriptorPublicKey) -> BTreeMap<u16, PathInfo> {
+        let mut recovery_paths = BTreeMap::new();
+        recovery_paths.insert(timelock, PathInfo::Single(key));
+        recovery_paths
+    }
+
+    #[test]
+    fn network_encodings() {
+        assert_eq!(
+            xpub_network(bitcoin::Network::Bitcoin),
+            bitcoin::Network::Bitcoin
+        );
+        for network in &[
+            bitcoin::Network::Testnet,
+            bitcoin::Network::Signet,
+            bitcoin::Network::Regtest,
+        ] {
+            assert_eq!(xpub_network(*network), bitcoin::Network::Testnet);
+        }
+
+        let addr = |s: &str| bitcoin::Address::from_str(s).unwrap();
+        let mainnet = addr("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4");
+        let testnet_segwit = addr("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx");
+        let testnet_legacy = addr("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn");
+        let regtest_segwit = addr("bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080");
+        let cases = [
+            (bitcoin::Network::Bitcoin, [true, false, false, false]),
+            (bitcoin::Network::Testnet, [false, true, true, false]),
+            (bitcoin::Network::Signet, [false, true, true, false]),
+            (bitcoin::Network::Regtest, [false, false, true, true]),
+        ];
+        for (network, valid) in &cases {
+            let addresses = [&mainnet, &testnet_segwit, &testnet_legacy, &regtest_segwit];
+            for (address, valid) in addresses.iter().zip(valid.iter()) {
+                assert_eq!(
+                    address_is_for_network(address, *network),
+                    *valid,
+                    "{} on {}",
+                    address,
+                    network
+                );
+            }
+        }
+    }
+
     #[test]
     fn inheritance_descriptor_creation() {
         let owner_key = descriptor::DescriptorPublicKey::from_str("xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*").unwrap();
         let heir_key = descriptor::DescriptorPublicKey::from_str("xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/<0;1>/*").unwrap();
         let timelock = 52560;
-        assert_eq!(MultipathDescriptor::new(owner_key.clone(), heir_key.clone(), timelock).unwrap().to_string(), "wsh(or_d(pk(xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*),and_v(v:pkh(xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/<0;1>/*),older(52560))))#8n2ydpkt");
+        assert_eq!(MultipathDescriptor::new(PathInfo::Single(owner_key.clone()), heir_path(timelock, heir_key.clone())).unwrap().to_string(), "wsh(or_d(pk(xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*),and_v(v:pkh(xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/<0;1>/*),older(52560))))#8n2ydpkt");
 
         // We prevent footguns with timelocks by requiring a u16. Note how the following wouldn't
         // compile:
-        //MultipathDescriptor::new(owner_key.clone(), heir_key.clone(), 0x00_01_0f_00).unwrap_err();
-        //MultipathDescriptor::new(owner_key.clone(), heir_key.clone(), (1 << 31) + 1).unwrap_err();
-        //MultipathDescriptor::new(owner_key, heir_key, (1 << 22) + 1).unwrap_err();
+        //MultipathDescriptor::new(PathInfo::Single(owner_key.clone()), heir_path(0x00_01_0f_00, heir_key.clone())).unwrap_err();
+        //MultipathDescriptor::new(PathInfo::Single(owner_key.clone()), heir_path((1 << 31) + 1, heir_key.clone())).unwrap_err();
+        //MultipathDescriptor::new(PathInfo::Single(owner_key), heir_path((1 << 22) + 1, heir_key)).unwrap_err();
 
         // You can't use a null timelock in Miniscript.
-        MultipathDescriptor::new(owner_key, heir_key, 0).unwrap_err();
+        MultipathDescriptor::new(PathInfo::Single(owner_key.clone()), heir_path(0, heir_key))
+            .unwrap_err();
+
+        // You need at least one recovery path.
+        MultipathDescriptor::new(PathInfo::Single(owner_key), BTreeMap::new()).unwrap_err();
 
         let owner_key = descriptor::DescriptorPublicKey::from_str("[aabb0011/10/4893]xpub661MyMwAqRbcFG59fiikD8UV762quhruT8K8bdjqy6N2o3LG7yohoCdLg1m2HAY1W6rfBrtauHkBhbfA4AQ3iazaJj5wVPhwgaRCHBW2DBg/<0;1>/*").unwrap();
         let heir_key = descriptor::DescriptorPublicKey::from_str("xpub661MyMwAqRbcFfxf71L4Dx4w5TmyNXrBicTEAM7vLzumxangwATWWgdJPb6xH1JHcJH9S3jNZx3fCnkkB1WyqrqGgavj1rehHcbythmruvZ/24/32/<0;1>/*").unwrap();
         let timelock = 57600;
-        assert_eq!(MultipathDescriptor::new(owner_key.clone(), heir_key, timelock).unwrap().to_string(), "wsh(or_d(pk([aabb0011/10/4893]xpub661MyMwAqRbcFG59fiikD8UV762quhruT8K8bdjqy6N2o3LG7yohoCdLg1m2HAY1W6rfBrtauHkBhbfA4AQ3iazaJj5wVPhwgaRCHBW2DBg/<0;1>/*),and_v(v:pkh(xpub661MyMwAqRbcFfxf71L4Dx4w5TmyNXrBicTEAM7vLzumxangwATWWgdJPb6xH1JHcJH9S3jNZx3fCnkkB1WyqrqGgavj1rehHcbythmruvZ/24/32/<0;1>/*),older(57600))))#l6dlpc2l");
+        assert_eq!(MultipathDescriptor::new(PathInfo::Single(owner_key.clone()), heir_path(timelock, heir_key)).unwrap().to_string(), "wsh(or_d(pk([aabb0011/10/4893]xpub661MyMwAqRbcFG59fiikD8UV762quhruT8K8bdjqy6N2o3LG7yohoCdLg1m2HAY1W6rfBrtauHkBhbfA4AQ3iazaJj5wVPhwgaRCHBW2DBg/<0;1>/*),and_v(v:pkh(xpub661MyMwAqRbcFfxf71L4Dx4w5TmyNXrBicTEAM7vLzumxangwATWWgdJPb6xH1JHcJH9S3jNZx3fCnkkB1WyqrqGgavj1rehHcbythmruvZ/24/32/<0;1>/*),older(57600))))#l6dlpc2l");
 
         // We can't pass a raw key, an xpub that is not deriveable, only hardened derivable,
         // without both the change and receive derivation paths, or with more than 2 different
         // derivation paths.
         let heir_key = descriptor::DescriptorPublicKey::from_str("xpub661MyMwAqRbcFfxf71L4Dx4w5TmyNXrBicTEAM7vLzumxangwATWWgdJPb6xH1JHcJH9S3jNZx3fCnkkB1WyqrqGgavj1rehHcbythmruvZ/0/<0;1>/354").unwrap();
-        MultipathDescriptor::new(owner_key.clone(), heir_key, timelock).unwrap_err();
+        MultipathDescriptor::new(
+            PathInfo::Single(owner_key.clone()),
+            heir_path(timelock, heir_key),
+        )
+        .unwrap_err();
         let heir_key = descriptor::DescriptorPublicKey::from_str("xpub661MyMwAqRbcFfxf71L4Dx4w5TmyNXrBicTEAM7vLzumxangwATWWgdJPb6xH1JHcJH9S3jNZx3fCnkkB1WyqrqGgavj1rehHcbythmruvZ/0/<0;1>/*'").unwrap();
-        MultipathDescriptor::new(owner_key.clone(), heir_key, timelock).unwrap_err();
+        MultipathDescriptor::new(
+            PathInfo::Single(owner_key.clone()),
+            heir_path(timelock, heir_key),
+        )
+        .unwrap_err();
         let heir_key = descriptor::DescriptorPublicKey::from_str(
             "02e24913be26dbcfdf8e8e94870b28725cdae09b448b6c127767bf0154e3a3c8e5",
         )
         .unwrap();
-        MultipathDescriptor::new(owner_key.clone(), heir_key, timelock).unwrap_err();
+        MultipathDescriptor::new(
+            PathInfo::Single(owner_key.clone()),
+            heir_path(timelock, heir_key),
+        )
+        .unwrap_err();
         let heir_key = descriptor::DescriptorPublicKey::from_str("xpub661MyMwAqRbcFfxf71L4Dx4w5TmyNXrBicTEAM7vLzumxangwATWWgdJPb6xH1JHcJH9S3jNZx3fCnkkB1WyqrqGgavj1rehHcbythmruvZ/0/*'").unwrap();
-        MultipathDescriptor::new(owner_key.clone(), heir_key, timelock).unwrap_err();
+        MultipathDescriptor::new(
+            PathInfo::Single(owner_key.clone()),
+            heir_path(timelock, heir_key),
+        )
+        .unwrap_err();
         let heir_key = descriptor::DescriptorPublicKey::from_str("xpub661MyMwAqRbcFfxf71L4Dx4w5TmyNXrBicTEAM7vLzumxangwATWWgdJPb6xH1JHcJH9S3jNZx3fCnkkB1WyqrqGgavj1rehHcbythmruvZ/<0;1;2>/*'").unwrap();
-        MultipathDescriptor::new(owner_key, heir_key, timelock).unwrap_err();
+        MultipathDescriptor::new(PathInfo::Single(owner_key), heir_path(timelock, heir_key))
+            .unwrap_err();
 
         // You can't pass duplicate keys, even if they are encoded differently.
         let owner_key = descriptor::DescriptorPublicKey::from_str("xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*").unwrap();
         let heir_key = descriptor::DescriptorPublicKey::from_str("xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*").unwrap();
-        MultipathDescriptor::new(owner_key, heir_key, timelock).unwrap_err();
+        MultipathDescriptor::new(PathInfo::Single(owner_key), heir_path(timelock, heir_key))
+            .unwrap_err();
         let owner_key = descriptor::DescriptorPublicKey::from_str("[00aabb44]xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*").unwrap();
         let heir_key = descriptor::DescriptorPublicKey::from_str("xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*").unwrap();
-        MultipathDescriptor::new(owner_key, heir_key, timelock).unwrap_err();
+        MultipathDescriptor::new(PathInfo::Single(owner_key), heir_path(timelock, heir_key))
+            .unwrap_err();
         let owner_key = descriptor::DescriptorPublicKey::from_str("[00aabb44]xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*").unwrap();
         let heir_key = descriptor::DescriptorPublicKey::from_str("[11223344/2/98]xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*").unwrap();
-        MultipathDescriptor::new(owner_key, heir_key, timelock).unwrap_err();
+        MultipathDescriptor::new(PathInfo::Single(owner_key), heir_path(timelock, heir_key))
+            .unwrap_err();
     }
 
     #[test]
@@ -669,10 +1193,82 @@ mod tests {
 
         // Sanity check we can call the methods on the derived desc
         der_desc.script_pubkey();
-        der_desc.witness_script();
+        der_desc.witness_script().unwrap();
         assert!(!der_desc.bip32_derivations().is_empty());
     }
 
+    // Test vectors for the descriptor used in the functional and unit tests. The derived
+    // witness scripts, script pubkeys and addresses must never change.
+    #[test]
+    fn inheritance_descriptor_derivation_vectors() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let owner_key = descriptor::DescriptorPublicKey::from_str("xpub68JJTXc1MWK8KLW4HGLXZBJknja7kDUJuFHnM424LbziEXsfkh1WQCiEjjHw4zLqSUm4rvhgyGkkuRowE9tCJSgt3TQB5J3SKAbZ2SdcKST/<0;1>/*").unwrap();
+        let heir_key = descriptor::DescriptorPublicKey::from_str("xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*").unwrap();
+        let desc =
+            MultipathDescriptor::new(PathInfo::Single(owner_key), heir_path(10_000, heir_key))
+                .unwrap();
+
+        // (derivation index, witness script, script pubkey, address)
+        let receive_vectors = [
+            (
+                0,
+                "21030f86d31010f1248a14021a4d4cf0a494a5e1a6f2f39d3881173ccd594ec7841dac736476a9143d0fc81211e42a0733d64cb5c5e7a8c4a99bf7c088ad021027b268",
+                "00202da03c6abeb98e20ab1977ce1a26a7f351c347def133981c72c1aba5d5bd0084",
+                "bc1q9ksrc647hx8zp2cewl8p5f487dgux3777yees8rjcx46t4daqzzqt7yga8",
+            ),
+            (
+                1,
+                "2102714a59503b5f28ccec8210b0544141a73de33e970bdcf6af9460c12edb2f125dac736476a914d72ded3684ab58c67845a609ea33da0ed1f39bcf88ad021027b268",
+                "0020dbf58c9117fac676531bce2d7d601cb90b3b434ad07b7e870fa99a53d9c9af8e",
+                "bc1qm06ceyghltr8v5cmeckh6cquhy9nks626pahapc04xd98kwf478qwmhqew",
+            ),
+            (
+                0x7f_ff_ff_ff,
+                "2103f1ae22af4040889232fbfda719729ac253ede5b0973c686698b930d9567a8ef2ac736476a91490cef31def9f563798389b06d3e08db8ee080f7488ad021027b268",
+                "00200f82faa8e29ff8bb984ae1b57e9e7add0751a6161d342b45179c543604b43ac2",
+                "bc1qp7p0428znluthxz2ux6ha8n6m5r4rfskr56zk3ghn32rvp958tpq0mn0hp",
+            ),
+        ];
+        let change_vectors = [
+            (
+                0,
+                "2103dbe44716a0af547dae17f2f9589dda124b73f2d49dc134a6d77672bb347a0952ac736476a91407ea4d8328b1481e63d8330d0d25a05abd1cfe5e88ad021027b268",
+                "00206d36a8cb3b19f6991cd5054ea1060199b3743e48784821dcc941cc31ac80cb98",
+                "bc1qd5m23jemr8mfj8x4q482zpspnxehg0jg0pyzrhxfg8xrrtyqewvqjrq3x6",
+            ),
+            (
+                1,
+                "21026bd799df737938b75fcd5ab6a18535f2f2eecd686a0a625949c75d68a144a75bac736476a914d0306054c7383f55021d9f19b017dfdd9a51008c88ad021027b268",
+                "0020a1f976094a2fb72e77809119e597856ec3b81ac33ecb5a382ae65031c932d633",
+                "bc1q58uhvz2297mjuauqjyv7t9u9dmpmsxkr8m945wp2uegrrjfj6ces3a3833",
+            ),
+            (
+                0x7f_ff_ff_ff,
+                "2102ffde7783499169bba25065b2040dc988094edf22d76a04cdc0689a41c96e49c4ac736476a914af7013273921bd02e41475e828f74f3ea0a21a3f88ad021027b268",
+                "00202914a4b6d589ad00af00ae091f32dd3545a9795c9642f717fb22fce0582fbda2",
+                "bc1q9y22fdk43xksptcq4cy37vkax4z6j72ujep0w9lmyt7wqkp0hk3qk0jkzz",
+            ),
+        ];
+
+        for (desc, vectors) in &[
+            (desc.receive_descriptor(), receive_vectors),
+            (desc.change_descriptor(), change_vectors),
+        ] {
+            for (index, witness_script, script_pubkey, address) in vectors.iter() {
+                let der_desc = desc.derive((*index).into(), &secp);
+                assert_eq!(
+                    &format!("{:x}", der_desc.witness_script().unwrap()),
+                    witness_script
+                );
+                assert_eq!(&format!("{:x}", der_desc.script_pubkey()), script_pubkey);
+                assert_eq!(
+                    &der_desc.address(bitcoin::Network::Bitcoin).to_string(),
+                    address
+                );
+            }
+        }
+    }
+
     #[test]
     fn inheritance_descriptor_tl_value() {
         let desc = MultipathDescriptor::from_str("wsh(andor(pk(tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(1),pk(tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))").unwrap();
@@ -711,5 +1307,326 @@ mod tests {
         );
     }
 
+    #[test]
+    fn inheritance_descriptor_multiple_recovery_paths() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let owner_key = descriptor::DescriptorPublicKey::from_str("xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*").unwrap();
+        let heir_a_key = descriptor::DescriptorPublicKey::from_str("xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/<0;1>/*").unwrap();
+        let heir_b_key = descriptor::DescriptorPublicKey::from_str("xpub661MyMwAqRbcFG59fiikD8UV762quhruT8K8bdjqy6N2o3LG7yohoCdLg1m2HAY1W6rfBrtauHkBhbfA4AQ3iazaJj5wVPhwgaRCHBW2DBg/<0;1>/*").unwrap();
+        let heir_c_key = descriptor::DescriptorPublicKey::from_str("xpub661MyMwAqRbcFfxf71L4Dx4w5TmyNXrBicTEAM7vLzumxangwATWWgdJPb6xH1JHcJH9S3jNZx3fCnkkB1WyqrqGgavj1rehHcbythmruvZ/24/32/<0;1>/*").unwrap();
+
+        // Heir A can recover after ~6 months, heirs B and C together after ~1 year.
+        let mut recovery_paths = BTreeMap::new();
+        recovery_paths.insert(26280, PathInfo::Single(heir_a_key.clone()));
+        recovery_paths.insert(
+            52560,
+            PathInfo::Multi(2, vec![heir_b_key.clone(), heir_c_key.clone()]),
+        );
+        let desc =
+            MultipathDescriptor::new(PathInfo::Single(owner_key.clone()), recovery_paths.clone())
+                .unwrap();
+        let desc_str = desc.to_string();
+        assert!(desc_str.starts_with("wsh(or_d(pk(xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*),or_i(and_v(v:pkh(xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/<0;1>/*),older(26280)),and_v(v:multi(2,"));
+
+        // It roundtrips, and we can get back the spending paths.
+        let parsed_desc = MultipathDescriptor::from_str(&desc_str).unwrap();
+        assert_eq!(parsed_desc, desc);
+        let info = parsed_desc.info();
+        assert_eq!(info.primary_path(), &PathInfo::Single(owner_key.clone()));
+        assert_eq!(info.recovery_paths(), &recovery_paths);
+        assert_eq!(desc.timelock_value(), 26280);
+
+        // The worst case satisfaction is through the 2-of-2, it must be larger than for a
+        // descriptor with only the first recovery path.
+        let single_desc = MultipathDescriptor::new(
+            PathInfo::Single(owner_key.clone()),
+            heir_path(26280, heir_a_key),
+        )
+        .unwrap();
+        assert!(desc.max_sat_weight() > single_desc.max_sat_weight());
+        assert!(desc.spender_input_size() > single_desc.spender_input_size());
+
+        // Derivation works, for all keys.
+        let recv_desc = desc.receive_descriptor().derive(11.into(), &secp);
+        let change_desc = desc.change_descriptor().derive(11.into(), &secp);
+        assert_ne!(
+            recv_desc.address(bitcoin::Network::Bitcoin),
+            change_desc.address(bitcoin::Network::Bitcoin)
+        );
+        assert_eq!(recv_desc.bip32_derivations().len(), 4);
+        assert_eq!(change_desc.bip32_derivations().len(), 4);
+
+        // Two recovery paths can't have the same timelock, and keys can't be reused across paths.
+        let desc_str = format!(
+            "wsh(or_d(pk({}),or_i(and_v(v:pkh({}),older(26280)),and_v(v:pkh({}),older(26280)))))",
+            owner_key, heir_b_key, heir_c_key
+        );
+        MultipathDescriptor::from_str(&desc_str).unwrap_err();
+        let mut recovery_paths = BTreeMap::new();
+        recovery_paths.insert(26280, PathInfo::Single(heir_b_key.clone()));
+        recovery_paths.insert(52560, PathInfo::Multi(2, vec![heir_b_key, heir_c_key]));
+        MultipathDescriptor::new(PathInfo::Single(owner_key), recovery_paths).unwrap_err();
+    }
+
+    #[test]
+    fn inheritance_descriptor_multisig_primary() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let primary_keys = vec![
+            descriptor::DescriptorPublicKey::from_str("xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*").unwrap(),
+            descriptor::DescriptorPublicKey::from_str("xpub661MyMwAqRbcFG59fiikD8UV762quhruT8K8bdjqy6N2o3LG7yohoCdLg1m2HAY1W6rfBrtauHkBhbfA4AQ3iazaJj5wVPhwgaRCHBW2DBg/<0;1>/*").unwrap(),
+            descriptor::DescriptorPublicKey::from_str("xpub661MyMwAqRbcFfxf71L4Dx4w5TmyNXrBicTEAM7vLzumxangwATWWgdJPb6xH1JHcJH9S3jNZx3fCnkkB1WyqrqGgavj1rehHcbythmruvZ/24/32/<0;1>/*").unwrap(),
+        ];
+        let heir_key = descriptor::DescriptorPublicKey::from_str("xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/<0;1>/*").unwrap();
+
+        // A 2-of-3 primary path, with a single heir after ~1 year.
+        let primary_path = PathInfo::Multi(2, primary_keys.clone());
+        let desc =
+            MultipathDescriptor::new(primary_path.clone(), heir_path(52560, heir_key.clone()))
+                .unwrap();
+        let desc_str = desc.to_string();
+        assert!(desc_str.starts_with(&format!(
+            "wsh(or_d(multi(2,{},{},{}),and_v(v:pkh({}),older(52560))))#",
+            primary_keys[0], primary_keys[1], primary_keys[2], heir_key
+        )));
+        let info = MultipathDescriptor::from_str(&desc_str).unwrap().info();
+        assert_eq!(info.primary_path(), &primary_path);
+        assert_eq!(info.primary_path().threshold(), 2);
+        assert_eq!(desc.timelock_value(), 52560);
+
+        // The derived Script checks the signatures of the primary path with a CHECKMULTISIG.
+        let der_desc = desc.receive_descriptor().derive(0.into(), &secp);
+        let script_asm = der_desc.witness_script().unwrap().asm();
+        assert!(script_asm.contains("OP_PUSHNUM_2"));
+        assert!(script_asm.contains("OP_PUSHNUM_3 OP_CHECKMULTISIG"));
+        assert!(script_asm.contains("OP_CSV"));
+        assert_eq!(der_desc.bip32_derivations().len(), 4);
+
+        // The worst case satisfaction is now through the primary path, with two signatures, and
+        // the Script is larger.
+        let single_desc = MultipathDescriptor::new(
+            PathInfo::Single(primary_keys[0].clone()),
+            heir_path(52560, heir_key.clone()),
+        )
+        .unwrap();
+        assert!(desc.max_sat_weight() > single_desc.max_sat_weight() + 73);
+        assert!(desc.spender_input_size() > single_desc.spender_input_size());
+
+        // An invalid threshold is refused.
+        MultipathDescriptor::new(
+            PathInfo::Multi(4, primary_keys.clone()),
+            heir_path(52560, heir_key.clone()),
+        )
+        .unwrap_err();
+        MultipathDescriptor::new(PathInfo::Multi(0, primary_keys), heir_path(52560, heir_key))
+            .unwrap_err();
+    }
+
+    #[test]
+    fn inheritance_descriptor_keys_origins() {
+        let desc = MultipathDescriptor::from_str("wsh(or_d(pk(xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*),and_v(v:pkh(xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/<0;1>/*),older(52560))))#8n2ydpkt").unwrap();
+        assert_eq!(desc.checksum(), "8n2ydpkt");
+
+        // Keys without origin are their own master key.
+        let owner_xpub = bip32::ExtendedPubKey::from_str("xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW").unwrap();
+        let heir_xpub = bip32::ExtendedPubKey::from_str("xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe").unwrap();
+        assert_eq!(
+            desc.keys_origins(),
+            vec![
+                (owner_xpub.fingerprint(), Vec::new().into(), owner_xpub),
+                (heir_xpub.fingerprint(), Vec::new().into(), heir_xpub)
+            ]
+        );
+
+        // Keys with an origin report the master key fingerprint and the path from it.
+        let owner_key = descriptor::DescriptorPublicKey::from_str("[aabbccdd/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*").unwrap();
+        let heir_key = descriptor::DescriptorPublicKey::from_str("[11223344/48'/0'/1'/2']xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/<0;1>/*").unwrap();
+        let desc =
+            MultipathDescriptor::new(PathInfo::Single(owner_key), heir_path(52560, heir_key))
+                .unwrap();
+        assert_eq!(desc.checksum(), desc.to_string().split('#').nth(1).unwrap());
+        assert_eq!(
+            desc.keys_origins(),
+            vec![
+                (
+                    bip32::Fingerprint::from_str("aabbccdd").unwrap(),
+                    bip32::DerivationPath::from_str("m/48'/0'/0'/2'").unwrap(),
+                    owner_xpub
+                ),
+                (
+                    bip32::Fingerprint::from_str("11223344").unwrap(),
+                    bip32::DerivationPath::from_str("m/48'/0'/1'/2'").unwrap(),
+                    heir_xpub
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn taproot_descriptor() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let owner_key = descriptor::DescriptorPublicKey::from_str("xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*").unwrap();
+        let heir_key = descriptor::DescriptorPublicKey::from_str("xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/<0;1>/*").unwrap();
+        let desc = MultipathDescriptor::new_taproot(
+            PathInfo::Single(owner_key.clone()),
+            heir_path(52560, heir_key.clone()),
+        )
+        .unwrap();
+        assert!(desc.is_taproot());
+        assert_eq!(
+            desc.info().primary_path(),
+            &PathInfo::Single(owner_key.clone())
+        );
+        assert_eq!(
+            desc.info().recovery_paths(),
+            &heir_path(52560, heir_key.clone())
+        );
+
+        // It roundtrips, and we can parse it from a user-provided string too.
+        let desc_str = desc.to_string();
+        assert!(desc_str.starts_with("tr("));
+        assert_eq!(
+            MultipathDescriptor::from_str(&desc_str)
+                .unwrap()
+                .to_string(),
+            desc_str
+        );
+        let user_desc = MultipathDescriptor::from_str(&format!(
+            "tr({},and_v(v:pk({}),older(52560)))",
+            owner_key, heir_key
+        ))
+        .unwrap();
+        assert_eq!(user_desc.to_string(), desc_str);
+
+        // The primary path is the key path, so it must be a single key.
+        let other_key = descriptor::DescriptorPublicKey::from_str("xpub661MyMwAqRbcFfxf71L4Dx4w5TmyNXrBicTEAM7vLzumxangwATWWgdJPb6xH1JHcJH9S3jNZx3fCnkkB1WyqrqGgavj1rehHcbythmruvZ/<0;1>/*").unwrap();
+        MultipathDescriptor::new_taproot(
+            PathInfo::Multi(1, vec![owner_key.clone(), other_key.clone()]),
+            heir_path(52560, heir_key.clone()),
+        )
+        .unwrap_err();
+        // But the recovery paths may be multisigs, and there may be many of them.
+        let mut recovery_paths = heir_path(52560, heir_key.clone());
+        // Keys can't be reused across paths.
+        recovery_paths.insert(26280, PathInfo::Multi(2, vec![heir_key, other_key.clone()]));
+        MultipathDescriptor::new_taproot(
+            PathInfo::Single(owner_key.clone()),
+            recovery_paths.clone(),
+        )
+        .unwrap_err();
+        let third_key = descriptor::DescriptorPublicKey::from_str("xpub661MyMwAqRbcFG59fiikD8UV762quhruT8K8bdjqy6N2o3LG7yohoCdLg1m2HAY1W6rfBrtauHkBhbfA4AQ3iazaJj5wVPhwgaRCHBW2DBg/<0;1>/*").unwrap();
+        recovery_paths.insert(
+            26280,
+            PathInfo::Multi(2, vec![third_key.clone(), other_key.clone()]),
+        );
+        let multi_desc =
+            MultipathDescriptor::new_taproot(PathInfo::Single(owner_key), recovery_paths.clone())
+                .unwrap();
+        assert_eq!(multi_desc.info().recovery_paths(), &recovery_paths);
+        assert_eq!(
+            MultipathDescriptor::from_str(&multi_desc.to_string())
+                .unwrap()
+                .info(),
+            multi_desc.info()
+        );
+        assert_eq!(multi_desc.timelock_value(), 26280);
+
+        // Derive a Taproot address and fill a PSBT input and output with the Taproot fields.
+        let der_desc = desc.receive_descriptor().derive(11.into(), &secp);
+        assert!(der_desc
+            .address(bitcoin::Network::Bitcoin)
+            .script_pubkey()
+            .is_v1_p2tr());
+        assert_eq!(
+            der_desc.address(bitcoin::Network::Bitcoin).script_pubkey(),
+            der_desc.script_pubkey()
+        );
+        assert!(der_desc.witness_script().is_none());
+        let mut psbtin = PsbtIn::default();
+        der_desc.update_psbt_in(&mut psbtin);
+        assert!(psbtin.tap_internal_key.is_some());
+        assert!(psbtin.tap_merkle_root.is_some());
+        assert_eq!(psbtin.tap_scripts.len(), 1);
+        assert_eq!(psbtin.tap_key_origins.len(), 2);
+        let (leaf_hashes, (_, der_path)) = psbtin
+            .tap_key_origins
+            .get(&psbtin.tap_internal_key.unwrap())
+            .unwrap();
+        assert!(leaf_hashes.is_empty());
+        assert_eq!(
+            der_path,
+            &bip32::DerivationPath::from_str("m/0/11").unwrap()
+        );
+        assert!(psbtin
+            .tap_key_origins
+            .values()
+            .any(|(leaf_hashes, _)| leaf_hashes.len() == 1));
+        assert!(psbtin.witness_script.is_none());
+        assert!(psbtin.bip32_derivation.is_empty());
+        let mut psbtout = PsbtOut::default();
+        der_desc.update_psbt_out(&mut psbtout);
+        assert_eq!(psbtout.tap_internal_key, psbtin.tap_internal_key);
+        assert_eq!(psbtout.tap_key_origins, psbtin.tap_key_origins);
+        assert!(psbtout.witness_script.is_none());
+
+        // With the multisig recovery path, the key is in one leaf and one more leaf exists.
+        let der_desc = multi_desc.change_descriptor().derive(0.into(), &secp);
+        let mut psbtin = PsbtIn::default();
+        der_desc.update_psbt_in(&mut psbtin);
+        assert_eq!(psbtin.tap_scripts.len(), 2);
+        assert_eq!(psbtin.tap_key_origins.len(), 4);
+
+        // P2WSH descriptors still get the witness script and BIP32 derivations.
+        let wsh_desc = MultipathDescriptor::from_str("wsh(or_d(pk(xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*),and_v(v:pkh(xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/<0;1>/*),older(52560))))#8n2ydpkt").unwrap();
+        assert!(!wsh_desc.is_taproot());
+        let der_desc = wsh_desc.receive_descriptor().derive(11.into(), &secp);
+        let mut psbtin = PsbtIn::default();
+        der_desc.update_psbt_in(&mut psbtin);
+        assert_eq!(psbtin.witness_script, der_desc.witness_script());
+        assert_eq!(psbtin.bip32_derivation.len(), 2);
+        assert!(psbtin.tap_internal_key.is_none());
+        assert!(psbtin.tap_scripts.is_empty());
+    }
+
+    #[test]
+    fn derived_desc_cache() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let desc = MultipathDescriptor::from_str("wsh(andor(pk(tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk(tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#5f6qd0d9").unwrap();
+        let mut cache = DerivedDescCache::new(16);
+        assert!(cache.is_empty());
+
+        // Cached or not, the derivation is the same as a fresh one. On both keychains, and
+        // whether the entry was evicted in between or not.
+        for _ in 0..3 {
+            for index in (0..40).chain(0..8) {
+                let index = bip32::ChildNumber::from(index);
+                assert_eq!(
+                    cache.derive(&desc, index, false, &secp),
+                    desc.receive_descriptor().derive(index, &secp)
+                );
+                assert_eq!(
+                    cache.derive(&desc, index, true, &secp),
+                    desc.change_descriptor().derive(index, &secp)
+                );
+                assert!(cache.len() <= 16);
+                assert_eq!(cache.recency.len(), cache.len());
+            }
+        }
+        assert_ne!(
+            cache.derive(&desc, 0.into(), false, &secp),
+            cache.derive(&desc, 0.into(), true, &secp)
+        );
+
+        // The least recently used entry is the one evicted.
+        let mut cache = DerivedDescCache::new(2);
+        cache.derive(&desc, 0.into(), false, &secp);
+        cache.derive(&desc, 1.into(), false, &secp);
+        cache.derive(&desc, 0.into(), false, &secp);
+        cache.derive(&desc, 2.into(), false, &secp);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.entries.contains_key(&(false, 0.into())));
+        assert!(cache.entries.contains_key(&(false, 2.into())));
+        assert!(!cache.entries.contains_key(&(false, 1.into())));
+    }
+
     // TODO: test error conditions of deserialization.
 }
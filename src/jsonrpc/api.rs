@@ -1,11 +1,17 @@
 use crate::{
+    backup::WalletBackup,
+    database::CoinStatus,
     jsonrpc::{Error, Params, Request, Response},
     DaemonControl,
 };
 
 use std::{collections::HashMap, convert::TryInto, str::FromStr};
 
-use miniscript::bitcoin::{self, consensus, util::psbt::PartiallySignedTransaction as Psbt};
+use miniscript::bitcoin::{
+    self, consensus,
+    hashes::hex::FromHex,
+    util::{bip32, psbt::PartiallySignedTransaction as Psbt},
+};
 
 fn create_spend(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
     let destinations = params
@@ -22,137 +28,239 @@ fn create_spend(control: &DaemonControl, params: Params) -> Result<serde_json::V
                 .collect::<Option<HashMap<bitcoin::Address, u64>>>()
         })
         .ok_or_else(|| Error::invalid_params("Invalid 'destinations' parameter."))?;
-    let outpoints = params
-        .get(1, "outpoints")
-        .ok_or_else(|| Error::invalid_params("Missing 'outpoints' parameter."))?
+    let outpoints: Vec<bitcoin::OutPoint> = params.get_as(1, "outpoints")?;
+    let feerate: u64 = params.get_as(2, "feerate")?;
+    let subtract_fee_from: Option<bitcoin::Address> = params.get_opt_as(3, "subtract_fee_from")?;
+    let data: Option<Vec<u8>> = params
+        .get_opt_as::<String>(4, "data")?
+        .map(|s| Vec::from_hex(&s))
+        .transpose()
+        .map_err(|_| Error::invalid_params("Invalid 'data' parameter."))?;
+
+    let res = control.create_spend(
+        &destinations,
+        &outpoints,
+        feerate,
+        subtract_fee_from.as_ref(),
+        data.as_deref(),
+    )?;
+    Ok(serde_json::json!(&res))
+}
+
+fn update_spend(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let psbt: String = params.get_as(0, "psbt")?;
+    let res = control.update_spend_encoded(&psbt)?;
+
+    Ok(serde_json::json!(&res))
+}
+
+fn combine_psbts(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let psbts: Vec<Psbt> = params
+        .get(0, "psbts")
+        .ok_or_else(|| Error::invalid_params("Missing 'psbts' parameter."))?
         .as_array()
         .and_then(|arr| {
             arr.iter()
                 .map(|entry| {
                     entry
                         .as_str()
-                        .and_then(|e| bitcoin::OutPoint::from_str(e).ok())
+                        .and_then(|s| base64::decode(s).ok())
+                        .and_then(|bytes| consensus::deserialize(&bytes).ok())
                 })
-                .collect::<Option<Vec<bitcoin::OutPoint>>>()
+                .collect()
         })
-        .ok_or_else(|| Error::invalid_params("Invalid 'outpoints' parameter."))?;
-    let feerate: u64 = params
-        .get(2, "feerate")
-        .ok_or_else(|| Error::invalid_params("Missing 'feerate' parameter."))?
-        .as_u64()
-        .ok_or_else(|| Error::invalid_params("Invalid 'feerate' parameter."))?;
-
-    let res = control.create_spend(&destinations, &outpoints, feerate)?;
-    Ok(serde_json::json!(&res))
-}
-
-fn update_spend(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
-    let psbt: Psbt = params
-        .get(0, "psbt")
-        .ok_or_else(|| Error::invalid_params("Missing 'psbt' parameter."))?
-        .as_str()
-        .and_then(|s| base64::decode(s).ok())
-        .and_then(|bytes| consensus::deserialize(&bytes).ok())
-        .ok_or_else(|| Error::invalid_params("Invalid 'psbt' parameter."))?;
-    control.update_spend(psbt)?;
+        .ok_or_else(|| Error::invalid_params("Invalid 'psbts' parameter."))?;
+    let psbt = control.combine_psbts(&psbts)?;
 
-    Ok(serde_json::json!({}))
+    Ok(serde_json::json!({ "psbt": base64::encode(consensus::serialize(&psbt)) }))
 }
 
 fn delete_spend(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
-    let txid = params
-        .get(0, "txid")
-        .ok_or_else(|| Error::invalid_params("Missing 'txid' parameter."))?
-        .as_str()
-        .and_then(|s| bitcoin::Txid::from_str(s).ok())
-        .ok_or_else(|| Error::invalid_params("Invalid 'txid' parameter."))?;
+    let txid: bitcoin::Txid = params.get_as(0, "txid")?;
     control.delete_spend(&txid);
 
     Ok(serde_json::json!({}))
 }
 
 fn broadcast_spend(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
-    let txid = params
-        .get(0, "txid")
-        .ok_or_else(|| Error::invalid_params("Missing 'txid' parameter."))?
-        .as_str()
-        .and_then(|s| bitcoin::Txid::from_str(s).ok())
-        .ok_or_else(|| Error::invalid_params("Invalid 'txid' parameter."))?;
+    let txid: bitcoin::Txid = params.get_as(0, "txid")?;
     control.broadcast_spend(&txid)?;
 
     Ok(serde_json::json!({}))
 }
 
+fn finalize_spend(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let txid: bitcoin::Txid = params.get_as(0, "txid")?;
+    let tx = control.finalize_spend(&txid)?;
+
+    Ok(serde_json::json!({ "tx": tx }))
+}
+
 fn list_confirmed(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
-    let start: u32 = params
-        .get(0, "start")
-        .ok_or_else(|| Error::invalid_params("Missing 'start' parameter."))?
-        .as_i64()
-        .and_then(|i| i.try_into().ok())
-        .ok_or_else(|| Error::invalid_params("Invalid 'start' parameter."))?;
-
-    let end: u32 = params
-        .get(1, "end")
-        .ok_or_else(|| Error::invalid_params("Missing 'end' parameter."))?
-        .as_i64()
-        .and_then(|i| i.try_into().ok())
-        .ok_or_else(|| Error::invalid_params("Invalid 'end' parameter."))?;
-
-    let limit: u64 = params
-        .get(2, "limit")
-        .ok_or_else(|| Error::invalid_params("Missing 'limit' parameter."))?
-        .as_i64()
-        .and_then(|i| i.try_into().ok())
-        .ok_or_else(|| Error::invalid_params("Invalid 'limit' parameter."))?;
+    let start: u32 = params.get_as(0, "start")?;
+    let end: u32 = params.get_as(1, "end")?;
+    let limit: u64 = params.get_as(2, "limit")?;
+    let outpoints: Option<Vec<bitcoin::OutPoint>> = params.get_opt_as(3, "outpoints")?;
+    let addresses: Option<Vec<bitcoin::Address>> = params.get_opt_as(4, "addresses")?;
+
+    Ok(serde_json::json!(&control.list_confirmed_transactions(
+        start,
+        end,
+        limit,
+        outpoints.as_deref(),
+        addresses.as_deref()
+    )))
+}
+
+fn list_confirmed_page(
+    control: &DaemonControl,
+    params: Params,
+) -> Result<serde_json::Value, Error> {
+    let limit: u64 = params.get_as(0, "limit")?;
+    let before: Option<(u32, bitcoin::Txid)> = params.get_opt_as(1, "before")?;
 
     Ok(serde_json::json!(
-        &control.list_confirmed_transactions(start, end, limit)
+        &control.list_confirmed_transactions_page(before, limit)
     ))
 }
 
+fn list_coins(control: &DaemonControl, params: Option<Params>) -> Result<serde_json::Value, Error> {
+    let (statuses, outpoints, start, count): (
+        Vec<CoinStatus>,
+        Vec<bitcoin::OutPoint>,
+        Option<u64>,
+        Option<u64>,
+    ) = match params {
+        Some(params) => (
+            params.get_opt_as(0, "statuses")?.unwrap_or_default(),
+            params.get_opt_as(1, "outpoints")?.unwrap_or_default(),
+            params.get_opt_as(2, "start")?,
+            params.get_opt_as(3, "count")?,
+        ),
+        None => (Vec::new(), Vec::new(), None, None),
+    };
+    let res = if start.is_none() && count.is_none() {
+        control.list_coins(&statuses, &outpoints)
+    } else {
+        control.list_coins_page(
+            &statuses,
+            &outpoints,
+            start.unwrap_or(0),
+            count.unwrap_or(u64::MAX),
+        )
+    };
+    Ok(serde_json::json!(&res))
+}
+
+fn list_spend(control: &DaemonControl, params: Option<Params>) -> Result<serde_json::Value, Error> {
+    let (start, count, since): (Option<u64>, Option<u64>, Option<u32>) = match params {
+        Some(params) => (
+            params.get_opt_as(0, "start")?,
+            params.get_opt_as(1, "count")?,
+            params.get_opt_as(2, "since")?,
+        ),
+        None => (None, None, None),
+    };
+    let res = if start.is_none() && count.is_none() {
+        control.list_spend(since)
+    } else {
+        control.list_spend_page(since, start.unwrap_or(0), count.unwrap_or(u64::MAX))
+    };
+    Ok(serde_json::json!(&res))
+}
+
+fn get_coins(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let outpoints: Vec<bitcoin::OutPoint> = params.get_as(0, "outpoints")?;
+    Ok(serde_json::json!(&control.get_coins(&outpoints)))
+}
+
+fn peek_address(
+    control: &DaemonControl,
+    params: Option<Params>,
+) -> Result<serde_json::Value, Error> {
+    let index: Option<u32> = match params {
+        Some(params) => params.get_opt_as(0, "index")?,
+        None => None,
+    };
+    Ok(serde_json::json!(&control.peek_address(index)?))
+}
+
 fn list_transactions(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
-    let txids: Vec<bitcoin::Txid> = params
-        .get(0, "txids")
-        .ok_or_else(|| Error::invalid_params("Missing 'txids' parameter."))?
-        .as_array()
-        .and_then(|arr| {
-            arr.iter()
-                .map(|entry| entry.as_str().and_then(|e| bitcoin::Txid::from_str(e).ok()))
-                .collect()
-        })
-        .ok_or_else(|| Error::invalid_params("Invalid 'txids' parameter."))?;
+    let txids: Vec<bitcoin::Txid> = params.get_as(0, "txids")?;
     Ok(serde_json::json!(&control.list_transactions(&txids)))
 }
 
+fn get_transaction(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let txid: bitcoin::Txid = params.get_as(0, "txid")?;
+    Ok(serde_json::json!({ "transaction": control.get_transaction(&txid) }))
+}
+
 fn start_rescan(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
-    let timestamp: u32 = params
-        .get(0, "timestamp")
-        .ok_or_else(|| Error::invalid_params("Missing 'timestamp' parameter."))?
-        .as_u64()
-        .and_then(|t| t.try_into().ok())
-        .ok_or_else(|| Error::invalid_params("Invalid 'timestamp' parameter."))?;
-    control.start_rescan(timestamp)?;
+    let timestamp: u32 = params.get_as(0, "timestamp")?;
+    let use_wallet_birthday: bool = params
+        .get_opt_as(1, "use_wallet_birthday")?
+        .unwrap_or(false);
+    control.start_rescan(timestamp, use_wallet_birthday)?;
 
     Ok(serde_json::json!({}))
 }
 
 fn create_recovery(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
-    let address = params
-        .get(0, "address")
-        .ok_or_else(|| Error::invalid_params("Missing 'address' parameter."))?
-        .as_str()
-        .and_then(|s| bitcoin::Address::from_str(s).ok())
-        .ok_or_else(|| Error::invalid_params("Invalid 'address' parameter."))?;
-    let feerate: u64 = params
-        .get(1, "feerate")
-        .ok_or_else(|| Error::invalid_params("Missing 'feerate' parameter."))?
-        .as_u64()
-        .ok_or_else(|| Error::invalid_params("Invalid 'feerate' parameter."))?;
-
-    let res = control.create_recovery(address, feerate)?;
+    let address: bitcoin::Address = params.get_as(0, "address")?;
+    let feerate: u64 = params.get_as(1, "feerate")?;
+    let timelock: Option<u16> = params.get_opt_as(2, "timelock")?;
+
+    let res = control.create_recovery(address, feerate, timelock)?;
+    Ok(serde_json::json!(&res))
+}
+
+fn estimate_feerate(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let target: u16 = params.get_as(0, "target")?;
+
+    let feerate = control.estimate_feerate(target)?;
+    Ok(serde_json::json!({ "feerate": feerate }))
+}
+
+fn import_backup(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let backup = params
+        .get(0, "backup")
+        .ok_or_else(|| Error::invalid_params("Missing 'backup' parameter."))?;
+    let backup = WalletBackup::from_json(&backup.to_string())
+        .map_err(|e| Error::invalid_params(e.to_string()))?;
+    control.import_backup(&backup)?;
+
+    Ok(serde_json::json!({}))
+}
+
+fn rbf_spend(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let txid: bitcoin::Txid = params.get_as(0, "txid")?;
+    let feerate: u64 = params.get_as(1, "feerate")?;
+    let res = control.rbf_spend(&txid, feerate)?;
+
     Ok(serde_json::json!(&res))
 }
 
+fn set_frozen(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let outpoints: Vec<bitcoin::OutPoint> = params.get_as(0, "outpoints")?;
+    let frozen: bool = params.get_as(1, "frozen")?;
+    control.set_frozen(&outpoints, frozen)?;
+
+    Ok(serde_json::json!({}))
+}
+
+fn store_hw_token(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let fingerprint: bip32::Fingerprint = params.get_as(0, "fingerprint")?;
+    let token: [u8; 32] = params.get_as::<String>(1, "token").and_then(|s| {
+        Vec::from_hex(&s)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| Error::invalid_params("Invalid 'token' parameter."))
+    })?;
+    control.store_hw_token(&fingerprint, &token);
+
+    Ok(serde_json::json!({}))
+}
+
 /// Handle an incoming JSONRPC2 request.
 pub fn handle_request(control: &DaemonControl, req: Request) -> Result<Response, Error> {
     let result = match req.method.as_str() {
@@ -162,6 +270,16 @@ pub fn handle_request(control: &DaemonControl, req: Request) -> Result<Response,
                 .ok_or_else(|| Error::invalid_params("Missing 'txid' parameter."))?;
             broadcast_spend(control, params)?
         }
+        "cancelrescan" => {
+            control.cancel_rescan()?;
+            serde_json::json!({})
+        }
+        "combinepsbts" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'psbts' parameter."))?;
+            combine_psbts(control, params)?
+        }
         "createrecovery" => {
             let params = req.params.ok_or_else(|| {
                 Error::invalid_params("Missing 'address' and 'feerate' parameters.")
@@ -182,9 +300,43 @@ pub fn handle_request(control: &DaemonControl, req: Request) -> Result<Response,
                 .ok_or_else(|| Error::invalid_params("Missing 'txid' parameter."))?;
             delete_spend(control, params)?
         }
+        "estimatefeerate" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'target' parameter."))?;
+            estimate_feerate(control, params)?
+        }
+        "finalizespend" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'txid' parameter."))?;
+            finalize_spend(control, params)?
+        }
+        "getbackup" => serde_json::json!(&control.wallet_backup()),
+        "getcoins" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'outpoints' parameter."))?;
+            get_coins(control, params)?
+        }
         "getinfo" => serde_json::json!(&control.get_info()),
         "getnewaddress" => serde_json::json!(&control.get_new_address()),
-        "listcoins" => serde_json::json!(&control.list_coins()),
+        "getrescanstatus" => serde_json::json!({ "status": control.rescan_status() }),
+        "gettransaction" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'txid' parameter."))?;
+            get_transaction(control, params)?
+        }
+        "importbackup" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'backup' parameter."))?;
+            import_backup(control, params)?
+        }
+        "listcoins" => list_coins(control, req.params)?,
+        "listdescriptors" => serde_json::json!(&control.list_descriptors()),
+        "listhwtokens" => serde_json::json!(&control.list_hw_tokens()),
         "listconfirmed" => {
             let params = req.params.ok_or_else(|| {
                 Error::invalid_params(
@@ -193,7 +345,15 @@ pub fn handle_request(control: &DaemonControl, req: Request) -> Result<Response,
             })?;
             list_confirmed(control, params)?
         }
-        "listspendtxs" => serde_json::json!(&control.list_spend()),
+        "listconfirmedpage" => {
+            let params = req.params.ok_or_else(|| {
+                Error::invalid_params(
+                    "The 'listconfirmedpage' command requires at least the 'limit' parameter",
+                )
+            })?;
+            list_confirmed_page(control, params)?
+        }
+        "listspendtxs" => list_spend(control, req.params)?,
         "listtransactions" => {
             let params = req.params.ok_or_else(|| {
                 Error::invalid_params(
@@ -202,6 +362,19 @@ pub fn handle_request(control: &DaemonControl, req: Request) -> Result<Response,
             })?;
             list_transactions(control, params)?
         }
+        "peekaddress" => peek_address(control, req.params)?,
+        "rbfspend" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'txid' and 'feerate' parameters."))?;
+            rbf_spend(control, params)?
+        }
+        "setfrozen" => {
+            let params = req.params.ok_or_else(|| {
+                Error::invalid_params("Missing 'outpoints' and 'frozen' parameters.")
+            })?;
+            set_frozen(control, params)?
+        }
         "startrescan" => {
             let params = req
                 .params
@@ -209,6 +382,12 @@ pub fn handle_request(control: &DaemonControl, req: Request) -> Result<Response,
             start_rescan(control, params)?
         }
         "stop" => serde_json::json!({}),
+        "storehwtoken" => {
+            let params = req.params.ok_or_else(|| {
+                Error::invalid_params("Missing 'fingerprint' and 'token' parameters.")
+            })?;
+            store_hw_token(control, params)?
+        }
         "updatespend" => {
             let params = req
                 .params
@@ -222,3 +401,168 @@ pub fn handle_request(control: &DaemonControl, req: Request) -> Result<Response,
 
     Ok(Response::success(req.id, result))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        database::{Coin, DatabaseConnection},
+        jsonrpc::ErrorCode,
+        testutils::*,
+    };
+
+    fn request(method: &str, params: serde_json::Value) -> Request {
+        serde_json::from_value(serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+            "id": 0,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn named_and_positional_params() {
+        let dummy_op = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        let mut dummy_bitcoind = DummyBitcoind::new();
+        dummy_bitcoind.txs.insert(
+            dummy_op.txid,
+            (
+                bitcoin::Transaction {
+                    version: 2,
+                    lock_time: bitcoin::PackedLockTime(0),
+                    input: vec![],
+                    output: vec![],
+                },
+                None,
+            ),
+        );
+        let ms = DummyLiana::new(dummy_bitcoind, DummyDatabase::new());
+        let control = &ms.handle.control;
+        let mut db_conn = control.db().lock().unwrap().connection();
+        db_conn.new_unspent_coins(&[Coin {
+            outpoint: dummy_op,
+            block_height: None,
+            block_time: None,
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(13),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            spend_broadcast_time: None,
+            is_frozen: false,
+        }]);
+
+        // Not enough left to create a change output, so the result is deterministic and the
+        // same Spend must be created whether the parameters are passed by position or by name.
+        let addr = "bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv";
+        let positional = handle_request(
+            control,
+            request(
+                "createspend",
+                serde_json::json!([{ addr: 95_000 }, [dummy_op.to_string()], 2]),
+            ),
+        )
+        .unwrap();
+        let named = handle_request(
+            control,
+            request(
+                "createspend",
+                serde_json::json!({
+                    "outpoints": [dummy_op.to_string()],
+                    "destinations": { addr: 95_000 },
+                    "feerate": 2,
+                }),
+            ),
+        )
+        .unwrap();
+        assert_eq!(positional, named);
+
+        let positional = handle_request(
+            control,
+            request("listconfirmed", serde_json::json!([0, 1_000_000, 10])),
+        )
+        .unwrap();
+        let named = handle_request(
+            control,
+            request(
+                "listconfirmed",
+                serde_json::json!({ "limit": 10, "start": 0, "end": 1_000_000, "outpoints": null }),
+            ),
+        )
+        .unwrap();
+        assert_eq!(positional, named);
+
+        // A parameter of the wrong type is rejected, be it passed by position or by name.
+        for params in &[
+            serde_json::json!([{ addr: 95_000 }, [dummy_op.to_string()], "2"]),
+            serde_json::json!({
+                "outpoints": [dummy_op.to_string()],
+                "destinations": { addr: 95_000 },
+                "feerate": "2",
+            }),
+            serde_json::json!({
+                "outpoints": dummy_op.to_string(),
+                "destinations": { addr: 95_000 },
+                "feerate": 2,
+            }),
+            serde_json::json!({ "destinations": { addr: 95_000 }, "feerate": 2 }),
+        ] {
+            let err = handle_request(control, request("createspend", params.clone())).unwrap_err();
+            assert_eq!(err.code, ErrorCode::InvalidParams);
+        }
+        let err = handle_request(
+            control,
+            request("listconfirmed", serde_json::json!([0, -1, 10])),
+        )
+        .unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidParams);
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn hw_tokens() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.handle.control;
+        let list = || {
+            handle_request(control, request("listhwtokens", serde_json::json!([])))
+                .unwrap()
+                .result
+                .unwrap()
+        };
+        assert_eq!(list(), serde_json::json!({ "tokens": [] }));
+
+        // A stored token is listed, and can be replaced.
+        let token_a = "01".repeat(32);
+        let token_b = "02".repeat(32);
+        for token in &[&token_a, &token_b] {
+            handle_request(
+                control,
+                request("storehwtoken", serde_json::json!(["aabbccdd", token])),
+            )
+            .unwrap();
+        }
+        assert_eq!(
+            list(),
+            serde_json::json!({ "tokens": [{ "fingerprint": "aabbccdd", "token": token_b }] })
+        );
+        assert_eq!(
+            control.list_hw_tokens().tokens[0].fingerprint,
+            bip32::Fingerprint::from_str("aabbccdd").unwrap()
+        );
+
+        // The token must be 32 bytes.
+        let err = handle_request(
+            control,
+            request("storehwtoken", serde_json::json!(["aabbccdd", "0102"])),
+        )
+        .unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidParams);
+
+        ms.shutdown();
+    }
+}
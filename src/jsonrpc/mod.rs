@@ -5,7 +5,7 @@ use crate::commands;
 
 use std::{error, fmt};
 
-use serde::{self, Deserialize, Deserializer, Serialize, Serializer};
+use serde::{self, de, Deserialize, Deserializer, Serialize, Serializer};
 
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
@@ -27,6 +27,28 @@ impl Params {
             Params::Map(map) => map.get(name),
         }
     }
+
+    /// Get the parameter at a given index / of a given name, deserialized to the expected type.
+    /// Errors if it's missing or of the wrong type.
+    pub fn get_as<T: de::DeserializeOwned>(&self, index: usize, name: &str) -> Result<T, Error> {
+        self.get_opt_as(index, name)?
+            .ok_or_else(|| Error::invalid_params(format!("Missing '{}' parameter.", name)))
+    }
+
+    /// Same as `get_as` for an optional parameter. A `null` value is treated as if it was absent.
+    pub fn get_opt_as<T: de::DeserializeOwned>(
+        &self,
+        index: usize,
+        name: &str,
+    ) -> Result<Option<T>, Error> {
+        self.get(index, name)
+            .filter(|v| !v.is_null())
+            .map(|v| {
+                serde_json::from_value(v.clone())
+                    .map_err(|_| Error::invalid_params(format!("Invalid '{}' parameter.", name)))
+            })
+            .transpose()
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
@@ -53,10 +75,16 @@ pub struct Request {
 
 /// A failure to broadcast a transaction to the P2P network.
 const BROADCAST_ERROR: i64 = 1_000;
+/// The client did not authenticate with the RPC cookie.
+const UNAUTHORIZED_ERROR: i64 = 1_001;
 
 /// JSONRPC2 error codes. See https://www.jsonrpc.org/specification#error_object.
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum ErrorCode {
+    /// Invalid JSON was received.
+    ParseError,
+    /// The JSON sent is not a valid Request object.
+    InvalidRequest,
     /// The method does not exist / is not available.
     MethodNotFound,
     /// Invalid method parameter(s).
@@ -70,6 +98,8 @@ pub enum ErrorCode {
 impl From<&ErrorCode> for i64 {
     fn from(code: &ErrorCode) -> i64 {
         match code {
+            ErrorCode::ParseError => -32700,
+            ErrorCode::InvalidRequest => -32600,
             ErrorCode::MethodNotFound => -32601,
             ErrorCode::InvalidParams => -32602,
             ErrorCode::InternalError => -32603,
@@ -81,6 +111,8 @@ impl From<&ErrorCode> for i64 {
 impl From<i64> for ErrorCode {
     fn from(code: i64) -> ErrorCode {
         match code {
+            -32700 => ErrorCode::ParseError,
+            -32600 => ErrorCode::InvalidRequest,
             -32601 => ErrorCode::MethodNotFound,
             -32602 => ErrorCode::InvalidParams,
             -32603 => ErrorCode::InternalError,
@@ -127,6 +159,20 @@ impl Error {
         }
     }
 
+    pub fn parse_error(message: impl Into<String>) -> Error {
+        Error::new(
+            ErrorCode::ParseError,
+            format!("Parse error: {}", message.into()),
+        )
+    }
+
+    pub fn invalid_request(message: impl Into<String>) -> Error {
+        Error::new(
+            ErrorCode::InvalidRequest,
+            format!("Invalid request: {}", message.into()),
+        )
+    }
+
     pub fn method_not_found() -> Error {
         Error::new(ErrorCode::MethodNotFound, "Method not found")
     }
@@ -137,6 +183,13 @@ impl Error {
             format!("Invalid params: {}", message.into()),
         )
     }
+
+    pub fn unauthorized() -> Error {
+        Error::new(
+            ErrorCode::ServerError(UNAUTHORIZED_ERROR),
+            "Unauthorized: the first request must be an 'auth' with the RPC cookie",
+        )
+    }
 }
 
 impl fmt::Display for Error {
@@ -155,20 +208,33 @@ impl From<commands::CommandError> for Error {
             | commands::CommandError::NoDestination
             | commands::CommandError::UnknownOutpoint(..)
             | commands::CommandError::InvalidFeerate(..)
+            | commands::CommandError::FeerateBelowRelay(..)
             | commands::CommandError::AlreadySpent(..)
             | commands::CommandError::AddressNetwork(..)
             | commands::CommandError::InvalidOutputValue(..)
+            | commands::CommandError::NonStandardOutput(..)
+            | commands::CommandError::UnknownFeePayer(..)
             | commands::CommandError::InsufficientFunds(..)
+            | commands::CommandError::InsufficientFeeBump(..)
             | commands::CommandError::UnknownSpend(..)
+            | commands::CommandError::UndecodableSpend
+            | commands::CommandError::PsbtCombination(..)
             | commands::CommandError::SpendFinalization(..)
+            | commands::CommandError::SpendVerification(..)
             | commands::CommandError::InsaneRescanTimestamp(..)
             | commands::CommandError::AlreadyRescanning
-            | commands::CommandError::RecoveryNotAvailable => {
+            | commands::CommandError::NoOngoingRescan
+            | commands::CommandError::RecoveryNotAvailable
+            | commands::CommandError::UnknownRecoveryPath(..)
+            | commands::CommandError::InvalidBackup(..)
+            | commands::CommandError::InvalidDerivationIndex(..)
+            | commands::CommandError::InvalidFeeTarget(..) => {
                 Error::new(ErrorCode::InvalidParams, e.to_string())
             }
             commands::CommandError::FetchingTransaction(..)
             | commands::CommandError::SanityCheckFailure(_)
-            | commands::CommandError::RescanTrigger(..) => {
+            | commands::CommandError::RescanTrigger(..)
+            | commands::CommandError::FeeEstimation(..) => {
                 Error::new(ErrorCode::InternalError, e.to_string())
             }
             commands::CommandError::TxBroadcast(_) => {
@@ -190,12 +256,12 @@ pub struct Response {
     /// Required on error. Must not exist on success.
     #[serde(skip_serializing_if = "Option::is_none")]
     error: Option<Error>,
-    /// Request identifier.
-    id: ReqId,
+    /// Request identifier. Null if it could not be read from the request.
+    id: Option<ReqId>,
 }
 
 impl Response {
-    fn new(id: ReqId, result: Option<serde_json::Value>, error: Option<Error>) -> Response {
+    fn new(id: Option<ReqId>, result: Option<serde_json::Value>, error: Option<Error>) -> Response {
         Response {
             jsonrpc: "2.0".to_string(),
             result,
@@ -205,10 +271,15 @@ impl Response {
     }
 
     pub fn success(id: ReqId, result: serde_json::Value) -> Response {
-        Response::new(id, Some(result), None)
+        Response::new(Some(id), Some(result), None)
     }
 
     pub fn error(id: ReqId, error: Error) -> Response {
-        Response::new(id, None, Some(error))
+        Response::new(Some(id), None, Some(error))
+    }
+
+    /// An error response to a request we could not even parse.
+    pub fn unidentified_error(error: Error) -> Response {
+        Response::new(None, None, Some(error))
     }
 }
@@ -4,21 +4,75 @@
 //! JSONRPC2 requests on a Unix Domain Socket.
 
 use crate::{
-    jsonrpc::{api, Request, Response},
+    jsonrpc::{api, Error, Request, Response},
+    notifications::WalletEvent,
     DaemonControl,
 };
 
 use std::{
-    io,
-    os::unix::net,
+    error, fmt, fs,
+    io::{self, Read, Write},
+    os::unix::{
+        fs::{OpenOptionsExt, PermissionsExt},
+        net,
+    },
     path,
-    sync::{self, atomic},
+    sync::{self, atomic, mpsc},
     thread, time,
 };
 
+use miniscript::bitcoin::hashes::hex::ToHex;
+
 // Maximum number of concurrent RPC connections we may accept.
 const MAX_CONNECTIONS: u32 = 16;
 
+// Maximum number of requests from a single connection we may be treating at the same time.
+const MAX_CONCURRENT_REQUESTS: u32 = 4;
+
+/// An error while handling a connection to the JSONRPC server.
+#[derive(Debug)]
+pub enum ServerError {
+    /// The request isn't valid JSON, or isn't a valid JSONRPC2 request object.
+    Parse(serde_json::Error),
+    /// The request is larger than the maximum size we accept, in bytes.
+    RequestTooLarge(usize),
+    /// Error reading from or writing to the socket.
+    Io(io::Error),
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Parse(e) => write!(f, "Error parsing request: '{}'", e),
+            Self::RequestTooLarge(max) => {
+                write!(f, "Request is larger than the maximum of {} bytes", max)
+            }
+            Self::Io(e) => write!(f, "I/O error: '{}'", e),
+        }
+    }
+}
+
+impl error::Error for ServerError {}
+
+impl From<io::Error> for ServerError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl ServerError {
+    // The JSONRPC2 error to respond with to the client, if any.
+    fn to_jsonrpc(&self) -> Option<Error> {
+        match self {
+            // Valid JSON, but not a valid request object.
+            Self::Parse(e) if e.is_data() => Some(Error::invalid_request(e.to_string())),
+            Self::Parse(e) => Some(Error::parse_error(e.to_string())),
+            Self::RequestTooLarge(..) => Some(Error::invalid_request(self.to_string())),
+            Self::Io(..) => None,
+        }
+    }
+}
+
 // Read a command from the stream.
 //
 // In order to both treat commands separately (respond as soon as we read one), and support
@@ -31,12 +85,15 @@ const MAX_CONNECTIONS: u32 = 16;
 //   for the separator character in the parts of the buffer with dummy values.
 //   - `cursor`: The index at which we checked for the separator character (`\n`). Used to not
 //   check twice for it on the same buffer chunk.
+// A request which can't be parsed is consumed from the buffer, so the next one can still be read.
+// A request larger than `max_size` bytes is not, and the stream should not be read from anymore.
 fn read_command(
     stream: &mut dyn io::Read,
     buf: &mut Vec<u8>,
     end: &mut usize,
     cursor: &mut usize,
-) -> Result<Option<Request>, io::Error> {
+    max_size: usize,
+) -> Result<Option<Request>, ServerError> {
     assert!(!buf.is_empty());
 
     loop {
@@ -47,20 +104,24 @@ fn read_command(
             pos,
             String::from_utf8_lossy(&buf[*cursor..*end])
         );
+
+        // Don't keep on reading a request which is already too large, nor parse one.
+        let req_size = pos.map(|pos| *cursor + pos).unwrap_or(*end);
+        if req_size > max_size {
+            return Err(ServerError::RequestTooLarge(max_size));
+        }
+
         if let Some(pos) = pos {
             log::trace!(
                 "Parsing Request from: {:?}",
                 String::from_utf8_lossy(&buf[..*cursor + pos])
             );
-            // TODO: don't return an io::Error here, instead try to parse a Request. Failing that,
-            // try to parse a serde_json::Value. Then return accordingly a JSONRPC "malformed
-            // request" or "invalid JSON" error.
-            let req: Request = serde_json::from_slice(&buf[..*cursor + pos])?;
+            let req: Result<Request, _> = serde_json::from_slice(&buf[..*cursor + pos]);
             *buf = buf[pos + 1..].to_vec(); // FIXME: can we avoid reallocating here?
             *cursor = 0;
             *end -= pos + 1;
 
-            return Ok(Some(req));
+            return req.map(Some).map_err(ServerError::Parse);
         }
 
         // If nothing can be gathered from the buffer, continue reading.
@@ -79,54 +140,270 @@ fn read_command(
     }
 }
 
+// Push the wallet events to a client that subscribed to them, as JSONRPC2 notifications, until
+// it goes away or we are shutting down.
+fn notifications_loop(
+    events: mpsc::Receiver<WalletEvent>,
+    stream: &net::UnixStream,
+    shutdown: &atomic::AtomicBool,
+) -> Result<(), io::Error> {
+    while !shutdown.load(atomic::Ordering::Relaxed) {
+        let event = match events.recv_timeout(time::Duration::from_millis(500)) {
+            Ok(event) => event,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+        };
+        let notif = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "notification",
+            "params": event,
+        });
+        log::trace!("JSONRPC notification: {:?}", serde_json::to_string(&notif));
+        if let Err(e) = serde_json::to_writer(stream, &notif) {
+            log::debug!("Subscriber went away: '{}'", e);
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
+
+// Compare the cookie presented by a client to ours, in constant time.
+fn cookie_matches(cookie: &str, presented: &str) -> bool {
+    cookie.len() == presented.len()
+        && cookie
+            .bytes()
+            .zip(presented.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+// Check the first request of a connection is an 'auth' presenting the expected cookie. Returns
+// whether the client may proceed.
+fn authenticate(
+    cookie: &str,
+    stream: &mut net::UnixStream,
+    buf: &mut Vec<u8>,
+    end: &mut usize,
+    cursor: &mut usize,
+    max_request_size: usize,
+) -> Result<bool, ServerError> {
+    let req = match read_command(stream, buf, end, cursor, max_request_size) {
+        Ok(Some(req)) => req,
+        Ok(None) => return Ok(false),
+        Err(e) => {
+            if let Some(error) = e.to_jsonrpc() {
+                let response = Response::unidentified_error(error);
+                serde_json::to_writer(&*stream, &response).map_err(io::Error::from)?;
+            }
+            return Err(e);
+        }
+    };
+
+    let authenticated = req.method == "auth"
+        && req
+            .params
+            .and_then(|p| p.get_as::<String>(0, "cookie").ok())
+            .map(|presented| cookie_matches(cookie, &presented))
+            .unwrap_or(false);
+    let response = if authenticated {
+        Response::success(req.id, serde_json::json!({}))
+    } else {
+        log::debug!("Rejecting unauthenticated JSONRPC connection.");
+        Response::error(req.id, Error::unauthorized())
+    };
+    serde_json::to_writer(&*stream, &response).map_err(io::Error::from)?;
+
+    Ok(authenticated)
+}
+
+// Treat a request and get the response to send back.
+fn handle_request(control: &DaemonControl, req: Request) -> Response {
+    let req_id = req.id.clone();
+    let response = api::handle_request(control, req).unwrap_or_else(|e| Response::error(req_id, e));
+    log::trace!("JSONRPC response: {:?}", serde_json::to_string(&response));
+    response
+}
+
+// Write a response on a stream shared with the threads treating the requests of this connection.
+fn write_response(
+    writer: &sync::Mutex<net::UnixStream>,
+    response: &Response,
+) -> Result<(), serde_json::Error> {
+    serde_json::to_writer(&*writer.lock().unwrap(), response)
+}
+
+// The number of requests of a connection being treated.
+#[derive(Debug, Default)]
+struct InFlightRequests {
+    count: sync::Mutex<u32>,
+    cond: sync::Condvar,
+}
+
+impl InFlightRequests {
+    fn lock_below(&self, max: u32) -> sync::MutexGuard<'_, u32> {
+        let mut count = self.count.lock().unwrap();
+        while *count >= max {
+            count = self.cond.wait(count).unwrap();
+        }
+        count
+    }
+
+    // Wait until there is less than `max` requests being treated.
+    fn wait_below(&self, max: u32) {
+        drop(self.lock_below(max));
+    }
+}
+
+// A request being treated. It is unregistered when dropped, even if the thread treating it panics.
+struct InFlightGuard(sync::Arc<InFlightRequests>);
+
+impl InFlightGuard {
+    // Wait until there is less than `max` requests being treated and register a new one.
+    fn start(in_flight: &sync::Arc<InFlightRequests>, max: u32) -> InFlightGuard {
+        *in_flight.lock_below(max) += 1;
+        InFlightGuard(in_flight.clone())
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        // Don't panic while unwinding if another thread poisoned the lock.
+        let mut count = match self.0.count.lock() {
+            Ok(count) => count,
+            Err(e) => e.into_inner(),
+        };
+        *count -= 1;
+        self.0.cond.notify_all();
+    }
+}
+
 // Handle all messages from this connection.
+//
+// Requests are treated concurrently, so a slow command doesn't hold up the next ones. Responses are
+// therefore not necessarily sent in the order the requests were received. Requests affecting the
+// whole connection or server ('subscribe' and 'stop') are only treated after all previous ones.
 fn connection_handler(
     control: DaemonControl,
     mut stream: net::UnixStream,
     shutdown: sync::Arc<atomic::AtomicBool>,
-) -> Result<(), io::Error> {
+    cookie: Option<sync::Arc<String>>,
+    max_request_size: usize,
+) -> Result<(), ServerError> {
     let mut buf = vec![0; 2048];
     let mut end = 0;
     let mut cursor = 0;
 
+    if let Some(cookie) = cookie {
+        if !authenticate(
+            &cookie,
+            &mut stream,
+            &mut buf,
+            &mut end,
+            &mut cursor,
+            max_request_size,
+        )? {
+            return Ok(());
+        }
+    }
+
+    let writer = sync::Arc::new(sync::Mutex::new(stream.try_clone()?));
+    let in_flight = sync::Arc::new(InFlightRequests::default());
     while !shutdown.load(atomic::Ordering::Relaxed) {
-        let req = match read_command(&mut stream, &mut buf, &mut end, &mut cursor)? {
-            Some(req) => req,
-            None => {
+        let req = match read_command(
+            &mut stream,
+            &mut buf,
+            &mut end,
+            &mut cursor,
+            max_request_size,
+        ) {
+            Ok(Some(req)) => req,
+            Ok(None) => {
                 // Connection closed.
-                return Ok(());
+                break;
+            }
+            Err(e) => {
+                // Tell the client about a malformed request, and keep on serving the next ones.
+                let error = match e.to_jsonrpc() {
+                    Some(error) => error,
+                    None => return Err(e),
+                };
+                log::debug!("Malformed JSONRPC request: '{}'", error);
+                let response = Response::unidentified_error(error);
+                write_response(&writer, &response).map_err(io::Error::from)?;
+                // We can't tell where an oversized request ends, so we can't read the next ones.
+                if let ServerError::RequestTooLarge(..) = e {
+                    log::warn!("Closing JSONRPC connection after a too large request.");
+                    break;
+                }
+                continue;
             }
         };
+        log::trace!("JSONRPC request: {:?}", serde_json::to_string(&req));
+
+        let is_sequential = &req.method == "stop" || &req.method == "subscribe";
+        if is_sequential {
+            in_flight.wait_below(1);
+        }
 
-        let req_id = req.id.clone();
         if &req.method == "stop" {
             shutdown.store(true, atomic::Ordering::Relaxed);
             log::info!("Stopping the liana daemon.");
         }
 
-        log::trace!("JSONRPC request: {:?}", serde_json::to_string(&req));
-        let response =
-            api::handle_request(&control, req).unwrap_or_else(|e| Response::error(req_id, e));
-        log::trace!("JSONRPC response: {:?}", serde_json::to_string(&response));
-        if let Err(e) = serde_json::to_writer(&stream, &response) {
-            log::error!("Error writing response: '{}'", e);
-            return Ok(());
+        // Once subscribed to notifications, the connection is only used to push them.
+        if &req.method == "subscribe" {
+            let events = control.notifier().subscribe();
+            let response = Response::success(req.id, serde_json::json!({}));
+            write_response(&writer, &response).map_err(io::Error::from)?;
+            return Ok(notifications_loop(events, &stream, &shutdown)?);
+        }
+
+        if is_sequential {
+            let response = handle_request(&control, req);
+            if let Err(e) = write_response(&writer, &response) {
+                log::error!("Error writing response: '{}'", e);
+                break;
+            }
+            continue;
         }
+
+        let in_flight_guard = InFlightGuard::start(&in_flight, MAX_CONCURRENT_REQUESTS);
+        thread::Builder::new()
+            .name("liana-jsonrpc-request".to_string())
+            .spawn({
+                let control = control.clone();
+                let writer = writer.clone();
+                move || {
+                    let _in_flight_guard = in_flight_guard;
+                    let response = handle_request(&control, req);
+                    if let Err(e) = write_response(&writer, &response) {
+                        log::error!("Error writing response: '{}'", e);
+                    }
+                }
+            })?;
     }
 
+    // Don't leave requests of this connection behind.
+    in_flight.wait_below(1);
     Ok(())
 }
 
 // FIXME: have a decent way to share the DaemonControl between connections. Maybe make it Clone?
 /// The main event loop. Wait for connections, and treat requests sent through them.
+/// If a `cookie` is given, clients must first present it in an 'auth' request. The connection of
+/// clients sending requests larger than `max_request_size` bytes is closed.
 pub fn rpcserver_loop(
     listener: net::UnixListener,
     daemon_control: DaemonControl,
+    cookie: Option<String>,
+    max_request_size: usize,
 ) -> Result<(), io::Error> {
     // Keep it simple. We don't need great performances so just treat each connection in
     // its thread, with a given maximum number of connections.
     let connections_counter = sync::Arc::from(atomic::AtomicU32::new(0));
     let shutdown = sync::Arc::from(atomic::AtomicBool::new(false));
+    let cookie = cookie.map(sync::Arc::new);
 
     listener.set_nonblocking(true)?;
     while !shutdown.load(atomic::Ordering::Relaxed) {
@@ -151,9 +428,12 @@ pub fn rpcserver_loop(
                 let control = daemon_control.clone();
                 let counter = connections_counter.clone();
                 let shutdown = shutdown.clone();
+                let cookie = cookie.clone();
 
                 move || {
-                    if let Err(e) = connection_handler(control, connection, shutdown) {
+                    if let Err(e) =
+                        connection_handler(control, connection, shutdown, cookie, max_request_size)
+                    {
                         log::error!("Error while handling connection {}: '{}'", handler_id, e);
                     } else {
                         log::trace!("Connection {} terminated without error.", handler_id);
@@ -207,15 +487,41 @@ pub fn rpcserver_setup(socket_path: &path::Path) -> Result<net::UnixListener, io
     listener
 }
 
+/// Write a new random cookie to the file at `cookie_path`, readable only by the user. Returns
+/// the cookie that clients must present.
+pub fn rpc_cookie_setup(cookie_path: &path::Path) -> Result<String, io::Error> {
+    let mut rand_bytes = [0; 32];
+    fs::File::open("/dev/urandom")?.read_exact(&mut rand_bytes)?;
+    let cookie = rand_bytes.to_hex();
+
+    log::debug!("Writing RPC cookie at {}", cookie_path.display());
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(cookie_path)?;
+    // The mode is only set when creating the file. A cookie file left over from a previous run
+    // may have been made readable by others since.
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(cookie.as_bytes())?;
+
+    Ok(cookie)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
-        jsonrpc::{Params, ReqId},
+        jsonrpc::{ErrorCode, Params, ReqId},
         testutils::*,
     };
 
-    use std::{env, fs, io::Write, process};
+    use std::{env, fs, io::Write, process, str::FromStr};
+
+    use miniscript::bitcoin;
+
+    const MAX_SIZE: usize = 1024 * 1024;
 
     fn read_one_command(socket_path: &path::Path) -> thread::JoinHandle<Option<Request>> {
         let listener = rpcserver_setup(socket_path).unwrap();
@@ -224,7 +530,7 @@ mod tests {
             let mut buf = vec![0; 32];
             let mut end = 0;
             let mut cursor = 0;
-            read_command(&mut conn, &mut buf, &mut end, &mut cursor).unwrap()
+            read_command(&mut conn, &mut buf, &mut end, &mut cursor, MAX_SIZE).unwrap()
         })
     }
 
@@ -238,7 +544,7 @@ mod tests {
             let mut reqs = Vec::new();
 
             loop {
-                match read_command(&mut conn, &mut buf, &mut end, &mut cursor).unwrap() {
+                match read_command(&mut conn, &mut buf, &mut end, &mut cursor, MAX_SIZE).unwrap() {
                     Some(req) => {
                         reqs.push(req);
                     }
@@ -396,6 +702,419 @@ mod tests {
         fs::remove_file(&socket_path).unwrap();
     }
 
+    #[test]
+    fn command_read_malformed() {
+        let socket_path = env::temp_dir().join(format!(
+            "lianad-jsonrpc-socket-{}-{:?}",
+            process::id(),
+            thread::current().id()
+        ));
+
+        // Invalid JSON, then a valid JSON which isn't a request, then a valid request. We can
+        // tell apart both errors and still read the valid request afterward.
+        let listener = rpcserver_setup(&socket_path).unwrap();
+        let t = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut buf = vec![0; 32];
+            let mut end = 0;
+            let mut cursor = 0;
+            (0..3)
+                .map(|_| read_command(&mut conn, &mut buf, &mut end, &mut cursor, MAX_SIZE))
+                .collect::<Vec<_>>()
+        });
+        let req = br#"{"jsonrpc": "2.0", "id": 0, "method": "test", "params": ["a", 10]}"#;
+        let parsed_req: Request = serde_json::from_slice(req).unwrap();
+        write_messages(
+            &socket_path,
+            &[
+                b"{\"jsonrpc\": \"2.0\", \"id\n",
+                b"{\"a\": 1}\n",
+                req,
+                b"\n",
+            ],
+        );
+        let mut results = t.join().unwrap().into_iter();
+        let err = results.next().unwrap().unwrap_err();
+        assert_eq!(err.to_jsonrpc().unwrap().code, ErrorCode::ParseError);
+        let err = results.next().unwrap().unwrap_err();
+        assert_eq!(err.to_jsonrpc().unwrap().code, ErrorCode::InvalidRequest);
+        assert_eq!(results.next().unwrap().unwrap().unwrap(), parsed_req);
+
+        fs::remove_file(&socket_path).unwrap();
+    }
+
+    // TODO: debug on MacOS
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn server_parse_error() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let socket_path = ms.tmp_dir.join("rpc");
+        let listener = rpcserver_setup(&socket_path).unwrap();
+        let t = thread::spawn({
+            let control = ms.handle.control.clone();
+            move || rpcserver_loop(listener, control, None, MAX_SIZE).unwrap()
+        });
+
+        // Sending invalid JSON gets us a parse error, without an id since it couldn't be read.
+        let mut client = net::UnixStream::connect(&socket_path).unwrap();
+        client
+            .write_all(b"{\"jsonrpc\": \"2.0\", \"method\": \n")
+            .unwrap();
+        let mut messages = serde_json::Deserializer::from_reader(client.try_clone().unwrap())
+            .into_iter::<serde_json::Value>();
+        let response = messages.next().unwrap().unwrap();
+        assert_eq!(response["error"]["code"], -32_700);
+        assert_eq!(response["id"], serde_json::Value::Null);
+
+        // The connection isn't dropped, we can still send valid requests.
+        let req = serde_json::json!({"jsonrpc": "2.0", "method": "getinfo", "id": 1});
+        client
+            .write_all(&[serde_json::to_vec(&req).unwrap(), b"\n".to_vec()].concat())
+            .unwrap();
+        let response = messages.next().unwrap().unwrap();
+        assert_eq!(response["result"]["network"], "bitcoin");
+        assert_eq!(response["id"], 1);
+
+        let stop_req = serde_json::json!({"jsonrpc": "2.0", "method": "stop", "id": 2});
+        client
+            .write_all(&[serde_json::to_vec(&stop_req).unwrap(), b"\n".to_vec()].concat())
+            .unwrap();
+        t.join().unwrap();
+        ms.shutdown();
+    }
+
+    #[test]
+    fn command_read_too_large() {
+        let socket_path = env::temp_dir().join(format!(
+            "lianad-jsonrpc-socket-{}-{:?}",
+            process::id(),
+            thread::current().id()
+        ));
+
+        // A request of exactly the maximum size is fine, but not a larger one.
+        let req = br#"{"jsonrpc": "2.0", "id": 0, "method": "test", "params": ["a", 10]}"#;
+        let listener = rpcserver_setup(&socket_path).unwrap();
+        let t = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut buf = vec![0; 32];
+            let mut end = 0;
+            let mut cursor = 0;
+            let first = read_command(&mut conn, &mut buf, &mut end, &mut cursor, req.len());
+            let second = read_command(&mut conn, &mut buf, &mut end, &mut cursor, req.len() - 1);
+            (first, second)
+        });
+        write_messages(&socket_path, &[req, b"\n", req, b"\n"]);
+        let (first, second) = t.join().unwrap();
+        assert!(first.unwrap().is_some());
+        assert!(matches!(second, Err(ServerError::RequestTooLarge(_))));
+
+        // A never-ending request is rejected without reading it all.
+        let listener = rpcserver_setup(&socket_path).unwrap();
+        let t = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut buf = vec![0; 32];
+            let mut end = 0;
+            let mut cursor = 0;
+            read_command(&mut conn, &mut buf, &mut end, &mut cursor, 1024)
+        });
+        let mut client = net::UnixStream::connect(&socket_path).unwrap();
+        // The server may stop reading before we're done writing.
+        let _ = client.write_all(&[b'a'; 16 * 1024]);
+        assert!(matches!(
+            t.join().unwrap(),
+            Err(ServerError::RequestTooLarge(1024))
+        ));
+
+        fs::remove_file(&socket_path).unwrap();
+    }
+
+    // TODO: debug on MacOS
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn server_request_too_large() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let socket_path = ms.tmp_dir.join("rpc");
+        let listener = rpcserver_setup(&socket_path).unwrap();
+        let t = thread::spawn({
+            let control = ms.handle.control.clone();
+            move || rpcserver_loop(listener, control, None, 1024).unwrap()
+        });
+
+        // An oversized request gets an error response and the connection is closed.
+        let mut client = net::UnixStream::connect(&socket_path).unwrap();
+        let params: Vec<u32> = (0..1024).collect();
+        let req =
+            serde_json::json!({"jsonrpc": "2.0", "method": "getinfo", "params": params, "id": 0});
+        let _ = client.write_all(&[serde_json::to_vec(&req).unwrap(), b"\n".to_vec()].concat());
+        let mut messages = serde_json::Deserializer::from_reader(client.try_clone().unwrap())
+            .into_iter::<serde_json::Value>();
+        let response = messages.next().unwrap().unwrap();
+        assert_eq!(response["error"]["code"], -32_600);
+        // The server closed the connection without reading our whole request, so we may get an
+        // error reading from it instead of a clean EOF.
+        assert!(!matches!(messages.next(), Some(Ok(_))));
+
+        // Other clients are still served.
+        let mut client = net::UnixStream::connect(&socket_path).unwrap();
+        let req = serde_json::json!({"jsonrpc": "2.0", "method": "stop", "id": 1});
+        client
+            .write_all(&[serde_json::to_vec(&req).unwrap(), b"\n".to_vec()].concat())
+            .unwrap();
+        let mut messages = serde_json::Deserializer::from_reader(client.try_clone().unwrap())
+            .into_iter::<serde_json::Value>();
+        assert_eq!(
+            messages.next().unwrap().unwrap()["result"],
+            serde_json::json!({})
+        );
+        t.join().unwrap();
+        ms.shutdown();
+    }
+
+    // TODO: debug on MacOS
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn server_concurrent_requests() {
+        let mut bitcoind = DummyBitcoind::new();
+        bitcoind.start_rescan_delay = time::Duration::from_secs(1);
+        let ms = DummyLiana::new(bitcoind, DummyDatabase::new());
+        let socket_path = ms.tmp_dir.join("rpc");
+        let listener = rpcserver_setup(&socket_path).unwrap();
+        let t = thread::spawn({
+            let control = ms.handle.control.clone();
+            move || rpcserver_loop(listener, control, None, MAX_SIZE).unwrap()
+        });
+
+        let send = |client: &mut net::UnixStream, method: &str, params: serde_json::Value, id| {
+            let req = serde_json::json!({
+                "jsonrpc": "2.0",
+                "method": method,
+                "params": params,
+                "id": id,
+            });
+            client
+                .write_all(&[serde_json::to_vec(&req).unwrap(), b"\n".to_vec()].concat())
+                .unwrap();
+        };
+
+        // Send a slow command then a fast one on the same connection. We get the response to the
+        // fast one first. (Note the fast one must not need the Bitcoin backend, as access to it is
+        // serialized.)
+        let mut client = net::UnixStream::connect(&socket_path).unwrap();
+        let mut messages = serde_json::Deserializer::from_reader(client.try_clone().unwrap())
+            .into_iter::<serde_json::Value>();
+        send(
+            &mut client,
+            "startrescan",
+            serde_json::json!([1_600_000_000]),
+            0,
+        );
+        send(&mut client, "getbackup", serde_json::json!([]), 1);
+        let response = messages.next().unwrap().unwrap();
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["result"]["network"], "bitcoin");
+        let response = messages.next().unwrap().unwrap();
+        assert_eq!(response["id"], 0);
+        assert_eq!(response["result"], serde_json::json!({}));
+
+        // Stopping waits for the ongoing requests to be answered.
+        ms.handle
+            .control
+            .db()
+            .lock()
+            .unwrap()
+            .connection()
+            .complete_rescan();
+        send(
+            &mut client,
+            "startrescan",
+            serde_json::json!([1_600_000_000]),
+            2,
+        );
+        send(&mut client, "stop", serde_json::json!([]), 3);
+        let response = messages.next().unwrap().unwrap();
+        assert_eq!(response["id"], 2);
+        assert_eq!(response["result"], serde_json::json!({}));
+        let response = messages.next().unwrap().unwrap();
+        assert_eq!(response["id"], 3);
+
+        t.join().unwrap();
+        ms.shutdown();
+    }
+
+    #[test]
+    fn in_flight_requests() {
+        let in_flight = sync::Arc::new(InFlightRequests::default());
+
+        // A request whose treatment panics doesn't stay registered.
+        let guard = InFlightGuard::start(&in_flight, MAX_CONCURRENT_REQUESTS);
+        assert!(thread::spawn(move || {
+            let _guard = guard;
+            panic!("Treating request");
+        })
+        .join()
+        .is_err());
+        in_flight.wait_below(1);
+
+        // We wait for a slot to be available before registering a new request.
+        let guards: Vec<_> = (0..MAX_CONCURRENT_REQUESTS)
+            .map(|_| InFlightGuard::start(&in_flight, MAX_CONCURRENT_REQUESTS))
+            .collect();
+        assert_eq!(*in_flight.count.lock().unwrap(), MAX_CONCURRENT_REQUESTS);
+        let t = thread::spawn({
+            let in_flight = in_flight.clone();
+            move || InFlightGuard::start(&in_flight, MAX_CONCURRENT_REQUESTS)
+        });
+        thread::sleep(time::Duration::from_millis(50));
+        assert_eq!(*in_flight.count.lock().unwrap(), MAX_CONCURRENT_REQUESTS);
+        drop(guards);
+        let guard = t.join().unwrap();
+        assert_eq!(*in_flight.count.lock().unwrap(), 1);
+        drop(guard);
+        in_flight.wait_below(1);
+    }
+
+    // TODO: debug on MacOS
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn server_notifications() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let notifier = ms.handle.control.notifier().clone();
+        let socket_path: path::PathBuf = [
+            ms.tmp_dir.as_path(),
+            path::Path::new("d"),
+            path::Path::new("bitcoin"),
+            path::Path::new("lianad_rpc"),
+        ]
+        .iter()
+        .collect();
+
+        let t = thread::spawn(move || ms.rpc_server().unwrap());
+        while !socket_path.exists() {
+            thread::sleep(time::Duration::from_millis(100));
+        }
+
+        // Subscribe to notifications on a connection.
+        let mut client = net::UnixStream::connect(&socket_path).unwrap();
+        let sub_req = Request {
+            jsonrpc: "2.0".to_string(),
+            method: "subscribe".to_string(),
+            params: None,
+            id: ReqId::Num(0),
+        };
+        client
+            .write_all(&[serde_json::to_vec(&sub_req).unwrap(), b"\n".to_vec()].concat())
+            .unwrap();
+        let mut messages = serde_json::Deserializer::from_reader(client.try_clone().unwrap())
+            .into_iter::<serde_json::Value>();
+        let response = messages.next().unwrap().unwrap();
+        assert_eq!(response["result"], serde_json::json!({}));
+
+        // A new coin event gets pushed to us.
+        let outpoint = bitcoin::OutPoint::from_str(
+            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
+        )
+        .unwrap();
+        notifier.notify(WalletEvent::NewCoin {
+            outpoint,
+            amount_sat: 100_000,
+            derivation_index: 3,
+            is_change: false,
+        });
+        let notif = messages.next().unwrap().unwrap();
+        assert_eq!(notif["method"], "notification");
+        assert_eq!(notif["params"]["event"], "new_coin");
+        assert_eq!(notif["params"]["outpoint"], outpoint.to_string());
+        assert_eq!(notif["params"]["amount_sat"], 100_000);
+        assert_eq!(notif["params"]["derivation_index"], 3);
+        assert_eq!(notif["params"]["is_change"], false);
+
+        // Stop the daemon through another connection.
+        let stop_req = Request {
+            jsonrpc: "2.0".to_string(),
+            method: "stop".to_string(),
+            params: None,
+            id: ReqId::Num(1),
+        };
+        write_messages(
+            &socket_path,
+            &[&serde_json::to_vec(&stop_req).unwrap(), b"\n"],
+        );
+
+        t.join().unwrap();
+    }
+
+    // TODO: debug on MacOS
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn server_cookie_auth() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let socket_path = ms.tmp_dir.join("rpc");
+        let cookie_path = ms.tmp_dir.join("rpc.cookie");
+        let cookie = rpc_cookie_setup(&cookie_path).unwrap();
+        assert_eq!(fs::read_to_string(&cookie_path).unwrap(), cookie);
+        let mode = |path: &path::Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&cookie_path), 0o600);
+
+        // Upon restart a new cookie is written, and the file is made private again if its
+        // permissions were loosened.
+        fs::set_permissions(&cookie_path, fs::Permissions::from_mode(0o644)).unwrap();
+        let cookie = rpc_cookie_setup(&cookie_path).unwrap();
+        assert_eq!(fs::read_to_string(&cookie_path).unwrap(), cookie);
+        assert_eq!(mode(&cookie_path), 0o600);
+
+        let listener = rpcserver_setup(&socket_path).unwrap();
+        let t = thread::spawn({
+            let control = ms.handle.control.clone();
+            let cookie = cookie.clone();
+            move || rpcserver_loop(listener, control, Some(cookie), MAX_SIZE).unwrap()
+        });
+
+        let send = |client: &mut net::UnixStream, method: &str, params: serde_json::Value| {
+            let req = serde_json::json!({
+                "jsonrpc": "2.0",
+                "method": method,
+                "params": params,
+                "id": 0,
+            });
+            client
+                .write_all(&[serde_json::to_vec(&req).unwrap(), b"\n".to_vec()].concat())
+                .unwrap();
+        };
+
+        // A client not presenting the cookie is refused and disconnected.
+        let mut client = net::UnixStream::connect(&socket_path).unwrap();
+        send(&mut client, "getinfo", serde_json::json!([]));
+        let mut messages = serde_json::Deserializer::from_reader(client.try_clone().unwrap())
+            .into_iter::<serde_json::Value>();
+        let response = messages.next().unwrap().unwrap();
+        assert_eq!(response["error"]["code"], 1_001);
+        assert!(messages.next().is_none());
+
+        // Same for one presenting the wrong cookie.
+        let mut client = net::UnixStream::connect(&socket_path).unwrap();
+        send(&mut client, "auth", serde_json::json!({ "cookie": "abcd" }));
+        let mut messages = serde_json::Deserializer::from_reader(client.try_clone().unwrap())
+            .into_iter::<serde_json::Value>();
+        let response = messages.next().unwrap().unwrap();
+        assert_eq!(response["error"]["code"], 1_001);
+        assert!(messages.next().is_none());
+
+        // A client presenting the cookie may send commands.
+        let mut client = net::UnixStream::connect(&socket_path).unwrap();
+        send(&mut client, "auth", serde_json::json!([cookie]));
+        let mut messages = serde_json::Deserializer::from_reader(client.try_clone().unwrap())
+            .into_iter::<serde_json::Value>();
+        let response = messages.next().unwrap().unwrap();
+        assert_eq!(response["result"], serde_json::json!({}));
+        send(&mut client, "getinfo", serde_json::json!([]));
+        let response = messages.next().unwrap().unwrap();
+        assert_eq!(response["result"]["network"], "bitcoin");
+        send(&mut client, "stop", serde_json::json!([]));
+
+        t.join().unwrap();
+        ms.shutdown();
+    }
+
     // TODO: debug on MacOS
     #[cfg(not(target_os = "macos"))]
     #[test]
@@ -1,3 +1,4 @@
+pub mod backup;
 mod bitcoin;
 pub mod commands;
 pub mod config;
@@ -7,26 +8,30 @@ mod database;
 pub mod descriptors;
 #[cfg(feature = "jsonrpc_server")]
 mod jsonrpc;
+pub mod notifications;
+pub mod price;
 #[cfg(test)]
 mod testutils;
 
+pub use crate::bitcoin::d::BitcoindError;
 pub use miniscript;
 
+#[cfg(feature = "postgres")]
+use crate::database::postgres::{PostgresDb, PostgresDbError};
 #[cfg(feature = "jsonrpc_server")]
-use crate::jsonrpc::server::{rpcserver_loop, rpcserver_setup};
+use crate::jsonrpc::server::{rpc_cookie_setup, rpcserver_loop, rpcserver_setup};
 use crate::{
-    bitcoin::{
-        d::{BitcoinD, BitcoindError},
-        poller, BitcoinInterface,
-    },
+    bitcoin::{d::BitcoinD, poller, BitcoinInterface},
     config::Config,
     database::{
         sqlite::{FreshDbOptions, SqliteDb, SqliteDbError},
         DatabaseInterface,
     },
+    notifications::Notifier,
+    price::PriceFeed,
 };
 
-use std::{error, fmt, fs, io, path, sync};
+use std::{error, fmt, fs, io, path, sync, time};
 
 use miniscript::bitcoin::secp256k1;
 
@@ -84,6 +89,8 @@ pub enum StartupError {
     DatadirCreation(path::PathBuf, io::Error),
     MissingBitcoindConfig,
     Database(SqliteDbError),
+    #[cfg(feature = "postgres")]
+    PostgresDatabase(PostgresDbError),
     Bitcoind(BitcoindError),
     #[cfg(unix)]
     Daemonization(&'static str),
@@ -106,6 +113,10 @@ impl fmt::Display for StartupError {
                 "Our Bitcoin interface is bitcoind but we have no 'bitcoind_config' entry in the configuration."
             ),
             Self::Database(e) => write!(f, "Error initializing database: '{}'.", e),
+            #[cfg(feature = "postgres")]
+            Self::PostgresDatabase(e) => {
+                write!(f, "Error initializing Postgres database: '{}'.", e)
+            }
             Self::Bitcoind(e) => write!(f, "Error setting up bitcoind interface: '{}'.", e),
             #[cfg(unix)]
             Self::Daemonization(e) => write!(f, "Error when daemonizing: '{}'.", e),
@@ -127,6 +138,13 @@ impl From<SqliteDbError> for StartupError {
     }
 }
 
+#[cfg(feature = "postgres")]
+impl From<PostgresDbError> for StartupError {
+    fn from(e: PostgresDbError) -> Self {
+        Self::PostgresDatabase(e)
+    }
+}
+
 impl From<BitcoindError> for StartupError {
     fn from(e: BitcoindError) -> Self {
         Self::Bitcoind(e)
@@ -170,17 +188,53 @@ fn setup_sqlite(
         Some(FreshDbOptions {
             bitcoind_network: config.bitcoin_config.network,
             main_descriptor: config.main_descriptor.clone(),
+            birthday_timestamp: config.birthday_timestamp,
         })
     } else {
         None
     };
     let sqlite = SqliteDb::new(db_path, options, secp)?;
+    sqlite.maybe_apply_migrations()?;
     sqlite.sanity_check(config.bitcoin_config.network, &config.main_descriptor)?;
     log::info!("Database initialized and checked.");
 
     Ok(sqlite)
 }
 
+// Connect to the Postgres database set in our configuration. Create the tables if they don't exist
+// yet, and do some sanity checks. If all went well, returns the interface to the Postgres database.
+#[cfg(feature = "postgres")]
+fn setup_postgres(
+    config: &Config,
+    secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+) -> Result<PostgresDb, StartupError> {
+    let url = &config
+        .postgres_config
+        .as_ref()
+        .expect("Only called with a Postgres config")
+        .url;
+    let db_config = url
+        .parse::<postgres::Config>()
+        .map_err(PostgresDbError::from)?;
+    // The database may be shared with other machines, don't rely on our data directory to tell
+    // whether it's fresh.
+    let options = if PostgresDb::new(db_config.clone(), None, secp)?.is_initialized()? {
+        None
+    } else {
+        Some(FreshDbOptions {
+            bitcoind_network: config.bitcoin_config.network,
+            main_descriptor: config.main_descriptor.clone(),
+            birthday_timestamp: config.birthday_timestamp,
+        })
+    };
+    let postgres = PostgresDb::new(db_config, options, secp)?;
+    postgres.maybe_apply_migrations()?;
+    postgres.sanity_check(config.bitcoin_config.network, &config.main_descriptor)?;
+    log::info!("Postgres database initialized and checked.");
+
+    Ok(postgres)
+}
+
 // Connect to bitcoind. Setup the watchonly wallet, and do some sanity checks.
 // If all went well, returns the interface to bitcoind.
 fn setup_bitcoind(
@@ -217,6 +271,47 @@ fn setup_bitcoind(
     Ok(bitcoind)
 }
 
+/// Check the bitcoind we are configured to connect to can be used for a new wallet on this
+/// network. To be called before creating the wallet, as it gives a more specific error than the
+/// startup would.
+pub fn check_bitcoind(
+    bitcoind_config: &config::BitcoindConfig,
+    network: miniscript::bitcoin::Network,
+) -> Result<(), BitcoindError> {
+    BitcoinD::preflight_check(bitcoind_config, network)
+}
+
+// If we are importing a wallet and were told when it was created, scan the block chain for
+// transactions related to our descriptor from this date.
+fn maybe_initial_scan(
+    config: &Config,
+    fresh_data_dir: bool,
+    bit: &impl BitcoinInterface,
+    db: &impl DatabaseInterface,
+) {
+    let timestamp = match config.birthday_timestamp {
+        Some(timestamp) if fresh_data_dir => timestamp,
+        _ => return,
+    };
+
+    log::info!(
+        "Scanning the block chain from the wallet birthday at '{}'.",
+        timestamp
+    );
+    match bit.start_rescan(&config.main_descriptor, timestamp) {
+        Ok(()) => db.connection().set_rescan(timestamp),
+        // Not fatal, the user can still manually trigger a rescan.
+        Err(e) => log::error!(
+            "Error when starting the initial scan from the wallet birthday: '{}'.",
+            e
+        ),
+    }
+}
+
+// How many derivations of our descriptor to keep in cache. Enough for creating transactions
+// spending thousands of coins without deriving the same descriptor twice.
+const DERIVED_DESC_CACHE_SIZE: usize = 4096;
+
 #[derive(Clone)]
 pub struct DaemonControl {
     config: Config,
@@ -224,6 +319,13 @@ pub struct DaemonControl {
     // FIXME: Should we require Sync on DatabaseInterface rather than using a Mutex?
     db: sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
     secp: secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+    price_feed: Option<sync::Arc<sync::Mutex<dyn PriceFeed>>>,
+    notifier: Notifier,
+    // Serializes the triggering of rescans, so we don't race with ourselves between checking
+    // whether one is ongoing and starting it.
+    rescan_lock: sync::Arc<sync::Mutex<()>>,
+    // The derivations of our descriptor for the coins we recently worked with.
+    derived_descs: sync::Arc<sync::Mutex<descriptors::DerivedDescCache>>,
 }
 
 impl DaemonControl {
@@ -232,15 +334,32 @@ impl DaemonControl {
         bitcoin: sync::Arc<sync::Mutex<dyn BitcoinInterface>>,
         db: sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
         secp: secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+        notifier: Notifier,
     ) -> DaemonControl {
         DaemonControl {
             config,
             bitcoin,
             db,
             secp,
+            price_feed: None,
+            notifier,
+            rescan_lock: sync::Arc::new(sync::Mutex::new(())),
+            derived_descs: sync::Arc::new(sync::Mutex::new(descriptors::DerivedDescCache::new(
+                DERIVED_DESC_CACHE_SIZE,
+            ))),
         }
     }
 
+    /// The dispatcher of the events about our wallet, to subscribe to them.
+    pub fn notifier(&self) -> &Notifier {
+        &self.notifier
+    }
+
+    /// Use this source of prices to value the transactions we list in fiat currency.
+    pub fn set_price_feed(&mut self, price_feed: impl PriceFeed + 'static) {
+        self.price_feed = Some(sync::Arc::from(sync::Mutex::from(price_feed)));
+    }
+
     // Useful for unit test to directly mess up with the DB
     #[cfg(test)]
     pub fn db(&self) -> sync::Arc<sync::Mutex<dyn DatabaseInterface>> {
@@ -259,7 +378,7 @@ impl DaemonHandle {
     /// You may specify a custom Bitcoin interface through the `bitcoin` parameter. If `None`, the
     /// default Bitcoin interface (`bitcoind` JSONRPC) will be used.
     /// You may specify a custom Database interface through the `db` parameter. If `None`, the
-    /// default Database interface (SQLite) will be used.
+    /// default Database interface (SQLite, or Postgres if configured) will be used.
     ///
     /// **Note**: we internally use threads, and set a panic hook. A downstream application must
     /// not overwrite this panic hook.
@@ -287,6 +406,11 @@ impl DaemonHandle {
         // Then set up the database
         let db = match db {
             Some(db) => sync::Arc::from(sync::Mutex::from(db)),
+            #[cfg(feature = "postgres")]
+            None if config.postgres_config.is_some() => {
+                sync::Arc::from(sync::Mutex::from(setup_postgres(&config, &secp)?))
+                    as sync::Arc<sync::Mutex<dyn DatabaseInterface>>
+            }
             None => sync::Arc::from(sync::Mutex::from(setup_sqlite(
                 &config,
                 &data_dir,
@@ -305,6 +429,8 @@ impl DaemonHandle {
             )?)) as sync::Arc<sync::Mutex<dyn BitcoinInterface>>,
         };
 
+        maybe_initial_scan(&config, fresh_data_dir, &bit, &db);
+
         // If we are on a UNIX system and they told us to daemonize, do it now.
         // NOTE: it's safe to daemonize now, as we don't carry any open DB connection
         // https://www.sqlite.org/howtocorrupt.html#_carrying_an_open_database_connection_across_a_fork_
@@ -320,15 +446,22 @@ impl DaemonHandle {
         }
 
         // Spawn the bitcoind poller with a retry limit high enough that we'd fail after that.
+        let notifier = Notifier::new();
         let bitcoin_poller = poller::Poller::start(
             bit.clone(),
             db.clone(),
             config.bitcoin_config.poll_interval_secs,
+            config.bitcoin_config.gap_limit,
+            config
+                .bitcoin_config
+                .rescan_timeout_secs
+                .map(time::Duration::from_secs),
             config.main_descriptor.clone(),
+            notifier.clone(),
         );
 
         // Finally, set up the API.
-        let control = DaemonControl::new(config, bit, db, secp);
+        let control = DaemonControl::new(config, bit, db, secp, notifier);
 
         Ok(Self {
             control,
@@ -351,22 +484,29 @@ impl DaemonHandle {
             bitcoin_poller: poller,
         } = self;
 
-        let rpc_socket: path::PathBuf = [
-            control
-                .config
-                .data_dir()
-                .expect("Didn't fail at startup, must not now")
-                .as_path(),
-            path::Path::new(&control.config.bitcoin_config.network.to_string()),
-            path::Path::new("lianad_rpc"),
-        ]
-        .iter()
-        .collect();
+        let mut net_data_dir = control
+            .config
+            .data_dir()
+            .expect("Didn't fail at startup, must not now");
+        net_data_dir.push(control.config.bitcoin_config.network.to_string());
+        let rpc_socket = net_data_dir.join("lianad_rpc");
+        let cookie_path = net_data_dir.join("lianad_rpc.cookie");
+        let cookie = if control.config.rpc_cookie {
+            Some(rpc_cookie_setup(&cookie_path)?)
+        } else {
+            None
+        };
         let listener = rpcserver_setup(&rpc_socket)?;
         log::info!("JSONRPC server started.");
 
-        rpcserver_loop(listener, control)?;
+        let max_request_size = control.config.rpc_max_request_size;
+        rpcserver_loop(listener, control, cookie, max_request_size)?;
         log::info!("JSONRPC server stopped.");
+        if cookie_path.exists() {
+            if let Err(e) = fs::remove_file(&cookie_path) {
+                log::error!("Error removing RPC cookie file: '{}'", e);
+            }
+        }
 
         poller.stop();
 
@@ -404,6 +544,103 @@ mod tests {
         thread, time,
     };
 
+    #[test]
+    fn initial_scan_from_birthday() {
+        let desc_str = "wsh(andor(pk(xpub68JJTXc1MWK8KLW4HGLXZBJknja7kDUJuFHnM424LbziEXsfkh1WQCiEjjHw4zLqSUm4rvhgyGkkuRowE9tCJSgt3TQB5J3SKAbZ2SdcKST/<0;1>/*),older(10000),pk(xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*)))#yudtr0k5";
+        let mut config = Config {
+            bitcoin_config: BitcoinConfig {
+                network: bitcoin::Network::Bitcoin,
+                poll_interval_secs: time::Duration::from_secs(2),
+                gap_limit: 20,
+                dust_output_sats: 5_000,
+                max_fee_sats: bitcoin::blockdata::constants::COIN_VALUE,
+                max_feerate_sats_vb: 1_000,
+                min_confirmations: 1,
+                psbt_non_witness_utxo: true,
+                long_term_feerate_vb: None,
+                rescan_timeout_secs: None,
+            },
+            bitcoind_config: None,
+            data_dir: None,
+            #[cfg(unix)]
+            daemon: false,
+            log_level: log::LevelFilter::Debug,
+            rpc_cookie: false,
+            rpc_max_request_size: 1024 * 1024,
+            main_descriptor: MultipathDescriptor::from_str(desc_str).unwrap(),
+            birthday_timestamp: None,
+            #[cfg(feature = "postgres")]
+            postgres_config: None,
+        };
+        let bitcoind = DummyBitcoind::new();
+
+        // Without a birthday we don't scan anything.
+        let db = DummyDatabase::new();
+        maybe_initial_scan(&config, true, &bitcoind, &db);
+        assert!(db.connection().rescan_timestamp().is_none());
+
+        // Only when importing a wallet with a known birthday, from this date.
+        config.birthday_timestamp = Some(1_600_000_000);
+        maybe_initial_scan(&config, false, &bitcoind, &db);
+        assert!(db.connection().rescan_timestamp().is_none());
+        maybe_initial_scan(&config, true, &bitcoind, &db);
+        assert_eq!(db.connection().rescan_timestamp(), Some(1_600_000_000));
+    }
+
+    #[test]
+    fn daemon_startup_descriptor_mismatch() {
+        let tmp_dir = tmp_dir();
+        fs::create_dir_all(&tmp_dir).unwrap();
+        let desc_a = "wsh(andor(pk(xpub68JJTXc1MWK8KLW4HGLXZBJknja7kDUJuFHnM424LbziEXsfkh1WQCiEjjHw4zLqSUm4rvhgyGkkuRowE9tCJSgt3TQB5J3SKAbZ2SdcKST/<0;1>/*),older(10000),pk(xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*)))#yudtr0k5";
+        let desc_b = "wsh(andor(pk(xpub68JJTXc1MWK8KLW4HGLXZBJknja7kDUJuFHnM424LbziEXsfkh1WQCiEjjHw4zLqSUm4rvhgyGkkuRowE9tCJSgt3TQB5J3SKAbZ2SdcKST/<0;1>/*),older(20000),pk(xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*)))";
+        let mut config = Config {
+            bitcoin_config: BitcoinConfig {
+                network: bitcoin::Network::Bitcoin,
+                poll_interval_secs: time::Duration::from_secs(2),
+                gap_limit: 20,
+                dust_output_sats: 5_000,
+                max_fee_sats: bitcoin::blockdata::constants::COIN_VALUE,
+                max_feerate_sats_vb: 1_000,
+                min_confirmations: 1,
+                psbt_non_witness_utxo: true,
+                long_term_feerate_vb: None,
+                rescan_timeout_secs: None,
+            },
+            bitcoind_config: None,
+            data_dir: Some(tmp_dir.join("datadir")),
+            #[cfg(unix)]
+            daemon: false,
+            log_level: log::LevelFilter::Debug,
+            rpc_cookie: false,
+            rpc_max_request_size: 1024 * 1024,
+            main_descriptor: MultipathDescriptor::from_str(desc_a).unwrap(),
+            birthday_timestamp: None,
+            #[cfg(feature = "postgres")]
+            postgres_config: None,
+        };
+
+        // The wallet database gets created for descriptor A.
+        DaemonHandle::start(
+            config.clone(),
+            Some(DummyBitcoind::new()),
+            None::<DummyDatabase>,
+        )
+        .unwrap()
+        .shutdown();
+
+        // The user then edits the configuration to use descriptor B. We refuse to start.
+        config.main_descriptor = MultipathDescriptor::from_str(desc_b).unwrap();
+        match DaemonHandle::start(config, Some(DummyBitcoind::new()), None::<DummyDatabase>) {
+            Err(e @ StartupError::Database(SqliteDbError::DescriptorMismatch(_))) => assert!(e
+                .to_string()
+                .contains("Config descriptor does not match wallet database descriptor")),
+            Err(e) => panic!("Unexpected error: '{}'", e),
+            Ok(_) => panic!("Startup must fail"),
+        }
+
+        fs::remove_dir_all(&tmp_dir).unwrap();
+    }
+
     // Read all bytes from the socket until the end of a JSON object, good enough approximation.
     fn read_til_json_end(stream: &mut net::TcpStream) {
         stream
@@ -493,6 +730,22 @@ mod tests {
         stream.flush().unwrap();
     }
 
+    // Fail the creation of a fresh wallet as bitcoind would if it can't write to the directory.
+    fn fail_wallet_creation(server: &net::TcpListener) {
+        let listwallets_resp =
+            "HTTP/1.1 200\n\r\n{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":[]}\n".as_bytes();
+        let (mut stream, _) = server.accept().unwrap();
+        read_til_json_end(&mut stream);
+        stream.write_all(listwallets_resp).unwrap();
+        stream.flush().unwrap();
+
+        let createwallet_resp = "HTTP/1.1 200\n\r\n{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":null,\"error\":{\"code\":-1,\"message\":\"filesystem error: cannot create directories: Permission denied [/lianad_watchonly_wallet]\"}}\n".as_bytes();
+        let (mut stream, _) = server.accept().unwrap();
+        read_til_json_end(&mut stream);
+        stream.write_all(createwallet_resp).unwrap();
+        stream.flush().unwrap();
+    }
+
     // Send them a dummy result to loadwallet.
     fn complete_wallet_loading(server: &net::TcpListener) {
         let listwallets_resp =
@@ -566,6 +819,135 @@ mod tests {
         stream.flush().unwrap();
     }
 
+    // Respond to a single request with the given JSONRPC result.
+    fn complete_request(server: &net::TcpListener, result: &str) {
+        let (mut stream, _) = server.accept().unwrap();
+        read_til_json_end(&mut stream);
+        stream
+            .write_all(
+                format!(
+                    "HTTP/1.1 200\n\r\n{{\"jsonrpc\":\"2.0\",\"id\":1,{}}}\n",
+                    result
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+        stream.flush().unwrap();
+    }
+
+    #[test]
+    fn bitcoind_preflight_check() {
+        let tmp_dir = tmp_dir();
+        fs::create_dir_all(&tmp_dir).unwrap();
+        let cookie = tmp_dir.join("dummy_bitcoind.cookie");
+        fs::write(&cookie, [0; 32]).unwrap();
+        let addr: net::SocketAddr =
+            net::SocketAddrV4::new(net::Ipv4Addr::new(127, 0, 0, 1), 0).into();
+        let server = net::TcpListener::bind(addr).unwrap();
+        let bitcoind_config = BitcoindConfig::new(cookie, server.local_addr().unwrap());
+        let echo = "\"result\":[]";
+        let version = |v: u64| format!("\"result\":{{\"version\":{}}}", v);
+        let chain = |c: &str| format!("\"result\":{{\"chain\":\"{}\"}}", c);
+
+        // A supported bitcoind on the right network with wallet support is fine.
+        let check_thread = thread::spawn({
+            let config = bitcoind_config.clone();
+            move || check_bitcoind(&config, bitcoin::Network::Bitcoin)
+        });
+        complete_request(&server, echo);
+        complete_request(&server, &version(240000));
+        complete_request(&server, &chain("main"));
+        complete_request(&server, "\"result\":[]");
+        check_thread.join().unwrap().unwrap();
+
+        // A too old bitcoind.
+        let check_thread = thread::spawn({
+            let config = bitcoind_config.clone();
+            move || check_bitcoind(&config, bitcoin::Network::Bitcoin)
+        });
+        complete_request(&server, echo);
+        complete_request(&server, &version(230000));
+        match check_thread.join().unwrap() {
+            Err(BitcoindError::InvalidVersion(230000)) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        // A bitcoind on another network than the one selected.
+        let check_thread = thread::spawn({
+            let config = bitcoind_config.clone();
+            move || check_bitcoind(&config, bitcoin::Network::Bitcoin)
+        });
+        complete_request(&server, echo);
+        complete_request(&server, &version(240000));
+        complete_request(&server, &chain("test"));
+        match check_thread.join().unwrap() {
+            Err(BitcoindError::NetworkMismatch(conf_net, bitcoind_net)) => {
+                assert_eq!((conf_net.as_str(), bitcoind_net.as_str()), ("main", "test"))
+            }
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        // A bitcoind without wallet support.
+        let check_thread =
+            thread::spawn(move || check_bitcoind(&bitcoind_config, bitcoin::Network::Bitcoin));
+        complete_request(&server, echo);
+        complete_request(&server, &version(240000));
+        complete_request(&server, &chain("main"));
+        complete_request(
+            &server,
+            "\"result\":null,\"error\":{\"code\":-32601,\"message\":\"Method not found\"}",
+        );
+        match check_thread.join().unwrap() {
+            Err(BitcoindError::WalletDisabled) => {}
+            res => panic!("Unexpected result: {:?}", res),
+        }
+
+        fs::remove_dir_all(&tmp_dir).unwrap();
+    }
+
+    #[test]
+    fn bitcoind_preflight_auth() {
+        let addr: net::SocketAddr =
+            net::SocketAddrV4::new(net::Ipv4Addr::new(127, 0, 0, 1), 0).into();
+        let server = net::TcpListener::bind(addr).unwrap();
+        let addr = server.local_addr().unwrap();
+
+        // Bitcoind rejecting our credentials.
+        let check_thread = thread::spawn(move || {
+            let config = BitcoindConfig::with_user_pass("alice", "wrong", addr);
+            check_bitcoind(&config, bitcoin::Network::Bitcoin)
+        });
+        let (mut stream, _) = server.accept().unwrap();
+        read_til_json_end(&mut stream);
+        stream.write_all(b"HTTP/1.1 401\n\r\n").unwrap();
+        stream.flush().unwrap();
+        drop(stream);
+        let auth_err = check_thread.join().unwrap().unwrap_err();
+        assert!(
+            matches!(auth_err, BitcoindError::Unauthorized),
+            "{:?}",
+            auth_err
+        );
+
+        // Nothing listening at this address anymore.
+        drop(server);
+        let config = BitcoindConfig::with_user_pass("alice", "hunter2", addr);
+        let conn_err = check_bitcoind(&config, bitcoin::Network::Bitcoin).unwrap_err();
+        assert!(
+            matches!(conn_err, BitcoindError::ConnectionRefused),
+            "{:?}",
+            conn_err
+        );
+
+        // The user is told which one it is.
+        assert!(auth_err
+            .to_string()
+            .contains("Authentication to bitcoind failed"));
+        assert!(conn_err
+            .to_string()
+            .contains("Connection to bitcoind refused"));
+    }
+
     // TODO: we could move the dummy bitcoind thread stuff to the bitcoind module to test the
     // bitcoind interface, and use the DummyLiana from testutils to sanity check the startup.
     // Note that startup as checked by this unit test is also tested in the functional test
@@ -605,11 +987,16 @@ mod tests {
         let bitcoin_config = BitcoinConfig {
             network,
             poll_interval_secs: time::Duration::from_secs(2),
+            gap_limit: 20,
+            dust_output_sats: 5_000,
+            max_fee_sats: bitcoin::blockdata::constants::COIN_VALUE,
+            max_feerate_sats_vb: 1_000,
+            min_confirmations: 1,
+            psbt_non_witness_utxo: true,
+            long_term_feerate_vb: None,
+            rescan_timeout_secs: None,
         };
-        let bitcoind_config = BitcoindConfig {
-            addr,
-            cookie_path: cookie,
-        };
+        let bitcoind_config = BitcoindConfig::new(cookie, addr);
 
         // Create a dummy config with this bitcoind
         let desc_str = "wsh(andor(pk(xpub68JJTXc1MWK8KLW4HGLXZBJknja7kDUJuFHnM424LbziEXsfkh1WQCiEjjHw4zLqSUm4rvhgyGkkuRowE9tCJSgt3TQB5J3SKAbZ2SdcKST/<0;1>/*),older(10000),pk(xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*)))#yudtr0k5";
@@ -623,7 +1010,12 @@ mod tests {
             #[cfg(unix)]
             daemon: false,
             log_level: log::LevelFilter::Debug,
+            rpc_cookie: false,
+            rpc_max_request_size: 1024 * 1024,
             main_descriptor: desc,
+            birthday_timestamp: None,
+            #[cfg(feature = "postgres")]
+            postgres_config: None,
         };
 
         // Start the daemon in a new thread so the current one acts as the bitcoind server.
@@ -661,4 +1053,73 @@ mod tests {
 
         fs::remove_dir_all(&tmp_dir).unwrap();
     }
+
+    #[test]
+    fn daemon_startup_wallet_permission() {
+        let tmp_dir = tmp_dir();
+        fs::create_dir_all(&tmp_dir).unwrap();
+        let data_dir: path::PathBuf = [tmp_dir.as_path(), path::Path::new("datadir")]
+            .iter()
+            .collect();
+
+        // Configure a dummy bitcoind
+        let cookie: path::PathBuf = [
+            tmp_dir.as_path(),
+            path::Path::new(&format!(
+                "dummy_bitcoind_{:?}.cookie",
+                thread::current().id()
+            )),
+        ]
+        .iter()
+        .collect();
+        fs::write(&cookie, [0; 32]).unwrap(); // Will overwrite should it exist already
+        let addr: net::SocketAddr =
+            net::SocketAddrV4::new(net::Ipv4Addr::new(127, 0, 0, 1), 0).into();
+        let server = net::TcpListener::bind(addr).unwrap();
+        let addr = server.local_addr().unwrap();
+        let bitcoin_config = BitcoinConfig {
+            network: bitcoin::Network::Bitcoin,
+            poll_interval_secs: time::Duration::from_secs(2),
+            gap_limit: 20,
+            dust_output_sats: 5_000,
+            max_fee_sats: bitcoin::blockdata::constants::COIN_VALUE,
+            max_feerate_sats_vb: 1_000,
+            min_confirmations: 1,
+            psbt_non_witness_utxo: true,
+            long_term_feerate_vb: None,
+            rescan_timeout_secs: None,
+        };
+        let bitcoind_config = BitcoindConfig::new(cookie, addr);
+        let desc_str = "wsh(andor(pk(xpub68JJTXc1MWK8KLW4HGLXZBJknja7kDUJuFHnM424LbziEXsfkh1WQCiEjjHw4zLqSUm4rvhgyGkkuRowE9tCJSgt3TQB5J3SKAbZ2SdcKST/<0;1>/*),older(10000),pk(xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*)))#yudtr0k5";
+        let config = Config {
+            bitcoin_config,
+            bitcoind_config: Some(bitcoind_config),
+            data_dir: Some(data_dir),
+            #[cfg(unix)]
+            daemon: false,
+            log_level: log::LevelFilter::Debug,
+            rpc_cookie: false,
+            rpc_max_request_size: 1024 * 1024,
+            main_descriptor: MultipathDescriptor::from_str(desc_str).unwrap(),
+            birthday_timestamp: None,
+            #[cfg(feature = "postgres")]
+            postgres_config: None,
+        };
+
+        // Bitcoind failing to create the watchonly wallet makes the startup fail with an error
+        // telling so, without retrying nor panicking.
+        let daemon_thread = thread::spawn(move || DaemonHandle::start_default(config));
+        complete_sanity_check(&server);
+        fail_wallet_creation(&server);
+        match daemon_thread.join().unwrap() {
+            Err(StartupError::Bitcoind(e)) => {
+                assert!(e.is_filesystem_error());
+                assert!(matches!(e, BitcoindError::WalletFilesystem(_)));
+            }
+            Err(e) => panic!("Unexpected error: '{}'", e),
+            Ok(_) => panic!("Startup must fail"),
+        }
+
+        fs::remove_dir_all(&tmp_dir).unwrap();
+    }
 }
@@ -0,0 +1,67 @@
+///! Notifications about events concerning our wallet.
+///!
+///! The Bitcoin poller emits them as it updates our state, and any number of subscribers may
+///! listen for them (for instance a client of the JSONRPC server).
+use std::sync::{self, mpsc};
+
+use miniscript::bitcoin;
+use serde::Serialize;
+
+/// An event concerning our wallet.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WalletEvent {
+    /// We moved to a new block chain tip.
+    NewBlock {
+        height: i32,
+        hash: bitcoin::BlockHash,
+    },
+    /// We received a new coin.
+    NewCoin {
+        outpoint: bitcoin::OutPoint,
+        amount_sat: u64,
+        derivation_index: u32,
+        is_change: bool,
+    },
+    /// The transaction spending one of our coins got confirmed.
+    SpendConfirmed {
+        outpoint: bitcoin::OutPoint,
+        txid: bitcoin::Txid,
+        height: i32,
+    },
+    /// One of our unconfirmed coins vanished, for instance because the transaction creating it
+    /// was double spent.
+    CoinDropped {
+        outpoint: bitcoin::OutPoint,
+        amount_sat: u64,
+    },
+    /// Progress of an ongoing rescan, as a percentage between 0 and 1.
+    RescanProgress { progress: f64 },
+}
+
+/// Dispatches wallet events to all the current subscribers.
+#[derive(Debug, Clone, Default)]
+pub struct Notifier {
+    subscribers: sync::Arc<sync::Mutex<Vec<mpsc::Sender<WalletEvent>>>>,
+}
+
+impl Notifier {
+    pub fn new() -> Notifier {
+        Notifier::default()
+    }
+
+    /// Get a channel on which all the events from now on will be sent.
+    pub fn subscribe(&self) -> mpsc::Receiver<WalletEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Send this event to all subscribers. Those that went away are removed.
+    pub fn notify(&self, event: WalletEvent) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|sender| sender.send(event.clone()).is_ok());
+    }
+}
@@ -0,0 +1,9 @@
+///! Optional source of Bitcoin prices, used to value transactions in fiat currency.
+///!
+///! None is used by default, as the daemon must be able to operate offline.
+
+/// A source of historical Bitcoin prices.
+pub trait PriceFeed: Send {
+    /// The price of a bitcoin in fiat currency at the given UNIX timestamp, if known.
+    fn price_at(&self, timestamp: u32) -> Option<f64>;
+}
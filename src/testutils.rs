@@ -1,15 +1,24 @@
 use crate::{
-    bitcoin::{BitcoinInterface, Block, BlockChainTip, UTxO},
+    bitcoin::{BitcoinInterface, Block, BlockChainTip, RescanError, RescanInfo, UTxO},
     config::{BitcoinConfig, Config},
-    database::{Coin, CoinType, DatabaseConnection, DatabaseInterface, SpendBlock},
-    descriptors, DaemonHandle,
+    database::{Coin, CoinStatus, CoinType, DatabaseConnection, DatabaseInterface, SpendBlock},
+    descriptors::{self, MultipathDescriptor},
+    DaemonHandle,
 };
 
-use std::{collections::HashMap, env, fs, io, path, process, str::FromStr, sync, thread, time};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    convert::TryInto,
+    env, fs, io, path, process,
+    str::FromStr,
+    sync, thread, time,
+};
 
 use miniscript::{
     bitcoin::{
-        self, secp256k1,
+        self,
+        hashes::Hash,
+        secp256k1,
         util::{bip32, psbt::PartiallySignedTransaction as Psbt},
         Transaction, Txid,
     },
@@ -17,44 +26,222 @@ use miniscript::{
 };
 
 pub struct DummyBitcoind {
+    /// The progress of the block chain synchronization, between 0 and 1.
+    pub sync_progress: f64,
     pub txs: HashMap<Txid, (Transaction, Option<Block>)>,
+    pub received: Vec<UTxO>,
+    pub relay_feerate: u64,
+    pub incremental_relay_feerate: u64,
+    /// The feerate estimate in BTC/kvb, regardless of the target.
+    pub fee_estimate: Option<f64>,
+    /// The unconfirmed transactions which were double spent.
+    pub conflicted: Vec<Txid>,
+    /// The addresses which already received coins.
+    pub used_addresses: Vec<bitcoin::Address>,
+    /// How long starting a rescan takes.
+    pub start_rescan_delay: time::Duration,
+    /// The ongoing rescan, if any.
+    pub rescan_info: Option<RescanInfo>,
+    /// Set once the ongoing rescan was aborted. Shared so it can be checked after the dummy was
+    /// handed over.
+    pub rescan_aborted: sync::Arc<sync::atomic::AtomicBool>,
+    /// The error to return when asked to start a rescan, if any.
+    pub rescan_error: Option<RescanError>,
+    /// The timestamp the last successfully started rescan was started from.
+    pub last_rescan_timestamp: sync::Mutex<Option<u32>>,
+    /// The simulated best chain, indexed by height. Its last block is the tip. Use `mine_block()`
+    /// and `reorg()` to modify it.
+    chain: Vec<Block>,
+    /// Every block of the simulated chains, including the ones reorged out, with their parent.
+    blocks: HashMap<bitcoin::BlockHash, (Block, Option<bitcoin::BlockHash>)>,
+    /// How many reorgs happened, to create different blocks at the same height on each chain.
+    forks: u32,
+    /// Transactions broadcast or added to the mempool, but not mined yet.
+    mempool: sync::Mutex<HashMap<Txid, Transaction>>,
+    /// The error to return when asked to broadcast a transaction, if any.
+    pub broadcast_error: Option<String>,
 }
 
-impl DummyBitcoind {}
+// The hash of a simulated block. It differs for each chain at the same height.
+fn dummy_block_hash(height: i32, fork: u32) -> bitcoin::BlockHash {
+    let mut data = height.to_be_bytes().to_vec();
+    data.extend_from_slice(&fork.to_be_bytes());
+    bitcoin::BlockHash::hash(&data)
+}
 
 impl DummyBitcoind {
     pub fn new() -> Self {
+        Self::with_network(bitcoin::Network::Bitcoin)
+    }
+
+    /// A dummy Bitcoin backend whose simulated chain starts at the genesis block of this network.
+    pub fn with_network(network: bitcoin::Network) -> Self {
+        let tip = BlockChainTip {
+            hash: bitcoin::BlockHash::from_str(
+                "000000007bc154e0fa7ea32218a72fe2c1bb9f86cf8c9ebf9a715ed27fdb229a",
+            )
+            .unwrap(),
+            height: 100,
+        };
+        let now = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32;
+
+        // The simulated chain goes from the genesis block to the tip, a block every 10 minutes.
+        let genesis_block = bitcoin::blockdata::constants::genesis_block(network);
+        let genesis = Block {
+            hash: genesis_block.block_hash(),
+            height: 0,
+            time: genesis_block.header.time,
+        };
+        let mut chain = vec![genesis];
+        for height in 1..=tip.height {
+            let hash = if height == tip.height {
+                tip.hash
+            } else {
+                dummy_block_hash(height, 0)
+            };
+            let time = now - (tip.height - height) as u32 * 600;
+            chain.push(Block { hash, height, time });
+        }
+        let blocks = chain
+            .iter()
+            .enumerate()
+            .map(|(i, block)| {
+                let parent = i.checked_sub(1).map(|i| chain[i].hash);
+                (block.hash, (*block, parent))
+            })
+            .collect();
+
         Self {
+            sync_progress: 1.0,
             txs: HashMap::new(),
+            received: Vec::new(),
+            relay_feerate: 1,
+            incremental_relay_feerate: 1,
+            fee_estimate: None,
+            conflicted: Vec::new(),
+            used_addresses: Vec::new(),
+            start_rescan_delay: time::Duration::from_secs(0),
+            rescan_info: None,
+            rescan_aborted: sync::Arc::new(sync::atomic::AtomicBool::new(false)),
+            rescan_error: None,
+            last_rescan_timestamp: sync::Mutex::new(None),
+            chain,
+            blocks,
+            forks: 0,
+            mempool: sync::Mutex::new(HashMap::new()),
+            broadcast_error: None,
+        }
+    }
+
+    fn connect_block(&mut self, txs: Vec<Transaction>) -> BlockChainTip {
+        let parent = *self.chain.last().expect("Always the genesis block");
+        let height = parent.height + 1;
+        let block = Block {
+            hash: dummy_block_hash(height, self.forks),
+            height,
+            time: parent.time + 600,
+        };
+        for tx in txs {
+            self.txs.insert(tx.txid(), (tx, Some(block)));
+        }
+        self.blocks.insert(block.hash, (block, Some(parent.hash)));
+        self.chain.push(block);
+        self.chain_tip()
+    }
+
+    /// Add a transaction to the mempool, to be confirmed in the next mined block.
+    pub fn add_to_mempool(&mut self, tx: Transaction) {
+        self.mempool.lock().unwrap().insert(tx.txid(), tx.clone());
+        self.txs.insert(tx.txid(), (tx, None));
+    }
+
+    /// Whether this transaction is in the mempool.
+    pub fn in_mempool(&self, txid: &Txid) -> bool {
+        self.mempool.lock().unwrap().contains_key(txid)
+    }
+
+    /// Make the wallet know about a transaction with this txid, for instance the one which
+    /// created a coin we are going to spend. Its content doesn't matter.
+    pub fn add_wallet_tx(&mut self, txid: Txid) {
+        let tx = Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime(0),
+            input: vec![],
+            output: vec![],
+        };
+        self.txs.insert(txid, (tx, None));
+    }
+
+    /// Mine a new block on top of the chain, confirming all the transactions of the mempool.
+    pub fn mine_block(&mut self) -> BlockChainTip {
+        let txs = self
+            .mempool
+            .lock()
+            .unwrap()
+            .drain()
+            .map(|(_, tx)| tx)
+            .collect();
+        self.connect_block(txs)
+    }
+
+    /// Disconnect the last `depth` blocks and replace them by `new_blocks` empty blocks. The
+    /// transactions confirmed in the disconnected blocks go back to the mempool.
+    pub fn reorg(&mut self, depth: usize, new_blocks: usize) -> BlockChainTip {
+        assert!(depth < self.chain.len(), "Can't reorg the genesis block");
+        self.forks += 1;
+        let disconnected = self.chain.split_off(self.chain.len() - depth);
+        for (txid, (tx, block)) in self.txs.iter_mut() {
+            if block.map(|b| disconnected.contains(&b)).unwrap_or(false) {
+                *block = None;
+                self.mempool.lock().unwrap().insert(*txid, tx.clone());
+            }
+        }
+        for _ in 0..new_blocks {
+            self.connect_block(Vec::new());
         }
+        self.chain_tip()
+    }
+
+    /// The block of the simulated best chain at this height.
+    pub fn block_at(&self, height: i32) -> BlockChainTip {
+        let block = self.chain[height as usize];
+        BlockChainTip {
+            hash: block.hash,
+            height: block.height,
+        }
+    }
+
+    /// The timestamp of the block of the simulated best chain at this height.
+    pub fn block_time(&self, height: i32) -> u32 {
+        self.chain[height as usize].time
     }
 }
 
 impl BitcoinInterface for DummyBitcoind {
     fn genesis_block(&self) -> BlockChainTip {
-        let hash = bitcoin::BlockHash::from_str(
-            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
-        )
-        .unwrap();
-        BlockChainTip { hash, height: 0 }
+        self.block_at(0)
     }
 
     fn sync_progress(&self) -> f64 {
-        1.0
+        self.sync_progress
     }
 
     fn chain_tip(&self) -> BlockChainTip {
-        let hash = bitcoin::BlockHash::from_str(
-            "000000007bc154e0fa7ea32218a72fe2c1bb9f86cf8c9ebf9a715ed27fdb229a",
-        )
-        .unwrap();
-        let height = 100;
-        BlockChainTip { hash, height }
+        let tip = self.chain.last().expect("Always the genesis block");
+        BlockChainTip {
+            hash: tip.hash,
+            height: tip.height,
+        }
     }
 
-    fn is_in_chain(&self, _: &BlockChainTip) -> bool {
-        // No reorg
-        true
+    fn is_in_chain(&self, tip: &BlockChainTip) -> bool {
+        self.chain
+            .get(tip.height as usize)
+            .map(|block| block.hash == tip.hash)
+            .unwrap_or(false)
     }
 
     fn received_coins(
@@ -62,13 +249,21 @@ impl BitcoinInterface for DummyBitcoind {
         _: &BlockChainTip,
         _: &[descriptors::InheritanceDescriptor],
     ) -> Vec<UTxO> {
-        Vec::new()
+        self.received.clone()
     }
 
     fn confirmed_coins(&self, _: &[bitcoin::OutPoint]) -> Vec<(bitcoin::OutPoint, i32, u32)> {
         Vec::new()
     }
 
+    fn dropped_coins(&self, outpoints: &[bitcoin::OutPoint]) -> Vec<bitcoin::OutPoint> {
+        outpoints
+            .iter()
+            .filter(|op| self.conflicted.contains(&op.txid))
+            .copied()
+            .collect()
+    }
+
     fn spending_coins(&self, _: &[bitcoin::OutPoint]) -> Vec<(bitcoin::OutPoint, bitcoin::Txid)> {
         Vec::new()
     }
@@ -80,35 +275,105 @@ impl BitcoinInterface for DummyBitcoind {
         Vec::new()
     }
 
-    fn common_ancestor(&self, _: &BlockChainTip) -> Option<BlockChainTip> {
-        todo!()
+    fn common_ancestor(&self, tip: &BlockChainTip) -> Option<BlockChainTip> {
+        // Walk back the chain of this tip until we find a block of the best chain. Never return
+        // None, the poller would loop forever.
+        let mut hash = tip.hash;
+        loop {
+            let (block, parent) = self.blocks.get(&hash).expect("Unknown block");
+            if self.chain.get(block.height as usize) == Some(block) {
+                return Some(BlockChainTip {
+                    hash: block.hash,
+                    height: block.height,
+                });
+            }
+            hash = parent.expect("The genesis block is always in chain");
+        }
     }
 
-    fn broadcast_tx(&self, _: &bitcoin::Transaction) -> Result<(), String> {
-        todo!()
+    fn broadcast_tx(&self, tx: &bitcoin::Transaction) -> Result<(), String> {
+        if let Some(ref e) = self.broadcast_error {
+            return Err(e.clone());
+        }
+        self.mempool.lock().unwrap().insert(tx.txid(), tx.clone());
+        Ok(())
     }
 
-    fn start_rescan(&self, _: &descriptors::MultipathDescriptor, _: u32) -> Result<(), String> {
-        todo!()
+    fn start_rescan(
+        &self,
+        _: &descriptors::MultipathDescriptor,
+        timestamp: u32,
+    ) -> Result<(), RescanError> {
+        thread::sleep(self.start_rescan_delay);
+        match self.rescan_error {
+            Some(ref e) => Err(e.clone()),
+            None => {
+                *self.last_rescan_timestamp.lock().unwrap() = Some(timestamp);
+                Ok(())
+            }
+        }
     }
 
     fn rescan_progress(&self) -> Option<f64> {
-        None
+        self.rescan_info().map(|info| info.progress)
     }
 
-    fn block_before_date(&self, _: u32) -> Option<BlockChainTip> {
-        todo!()
+    fn rescan_info(&self) -> Option<RescanInfo> {
+        if self.rescan_aborted.load(sync::atomic::Ordering::Relaxed) {
+            return None;
+        }
+        self.rescan_info
+    }
+
+    fn abort_rescan(&self) -> bool {
+        self.rescan_info().is_some()
+            && !self
+                .rescan_aborted
+                .swap(true, sync::atomic::Ordering::Relaxed)
+    }
+
+    fn block_before_date(&self, timestamp: u32) -> Option<BlockChainTip> {
+        self.chain
+            .iter()
+            .rev()
+            .find(|block| block.time < timestamp)
+            .map(|block| BlockChainTip {
+                hash: block.hash,
+                height: block.height,
+            })
     }
 
     fn tip_time(&self) -> u32 {
-        todo!()
+        self.chain.last().expect("Always the genesis block").time
     }
 
     fn wallet_transaction(
         &self,
         txid: &bitcoin::Txid,
     ) -> Option<(bitcoin::Transaction, Option<Block>)> {
-        self.txs.get(txid).cloned()
+        self.txs.get(txid).cloned().or_else(|| {
+            self.mempool
+                .lock()
+                .unwrap()
+                .get(txid)
+                .map(|tx| (tx.clone(), None))
+        })
+    }
+
+    fn relay_feerate(&self) -> u64 {
+        self.relay_feerate
+    }
+
+    fn incremental_relay_feerate(&self) -> u64 {
+        self.incremental_relay_feerate
+    }
+
+    fn estimate_smart_fee(&self, _: u16) -> Option<f64> {
+        self.fee_estimate
+    }
+
+    fn is_address_used(&self, address: &bitcoin::Address) -> bool {
+        self.used_addresses.contains(address)
     }
 }
 
@@ -117,7 +382,13 @@ struct DummyDbState {
     change_index: bip32::ChildNumber,
     curr_tip: Option<BlockChainTip>,
     coins: HashMap<bitcoin::OutPoint, Coin>,
-    spend_txs: HashMap<bitcoin::Txid, Psbt>,
+    addresses: HashMap<bitcoin::Address, (bip32::ChildNumber, bool)>,
+    spend_txs: HashMap<bitcoin::Txid, (Psbt, u32)>,
+    hw_tokens: HashMap<(String, bip32::Fingerprint), [u8; 32]>,
+    labels: BTreeMap<String, String>,
+    timestamp: u32,
+    rescan_timestamp: Option<u32>,
+    rescan_progress: Option<u32>,
 }
 
 pub struct DummyDatabase {
@@ -140,7 +411,16 @@ impl DummyDatabase {
                 change_index: 0.into(),
                 curr_tip: None,
                 coins: HashMap::new(),
+                addresses: HashMap::new(),
                 spend_txs: HashMap::new(),
+                hw_tokens: HashMap::new(),
+                labels: BTreeMap::new(),
+                timestamp: time::SystemTime::now()
+                    .duration_since(time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs() as u32,
+                rescan_timestamp: None,
+                rescan_progress: None,
             })),
         }
     }
@@ -150,6 +430,24 @@ impl DummyDatabase {
             self.db.write().unwrap().coins.insert(coin.outpoint, coin);
         }
     }
+
+    pub fn set_wallet_timestamp(&mut self, timestamp: u32) {
+        self.db.write().unwrap().timestamp = timestamp;
+    }
+
+    /// Record the derivation index of one of our addresses.
+    pub fn insert_address(
+        &mut self,
+        address: bitcoin::Address,
+        index: bip32::ChildNumber,
+        is_change: bool,
+    ) {
+        self.db
+            .write()
+            .unwrap()
+            .addresses
+            .insert(address, (index, is_change));
+    }
 }
 
 impl DatabaseConnection for DummyDatabase {
@@ -178,7 +476,7 @@ impl DatabaseConnection for DummyDatabase {
     }
 
     fn change_index(&mut self) -> bip32::ChildNumber {
-        self.db.read().unwrap().deposit_index
+        self.db.read().unwrap().change_index
     }
 
     fn set_change_index(
@@ -189,6 +487,12 @@ impl DatabaseConnection for DummyDatabase {
         self.db.write().unwrap().change_index = index;
     }
 
+    fn first_unstored_index(&mut self) -> bip32::ChildNumber {
+        // We don't store any address past our current derivation indexes.
+        let db = self.db.read().unwrap();
+        std::cmp::max(db.deposit_index, db.change_index)
+    }
+
     fn coins(&mut self, coin_type: CoinType) -> HashMap<bitcoin::OutPoint, Coin> {
         let coins = self.db.read().unwrap().coins.clone();
         match coin_type {
@@ -214,6 +518,45 @@ impl DatabaseConnection for DummyDatabase {
         result
     }
 
+    fn list_coins(
+        &mut self,
+        statuses: &[CoinStatus],
+        outpoints: &[bitcoin::OutPoint],
+    ) -> HashMap<bitcoin::OutPoint, Coin> {
+        self.db
+            .read()
+            .unwrap()
+            .coins
+            .iter()
+            .filter(|(op, coin)| {
+                (statuses.is_empty() || statuses.iter().any(|s| s.matches(coin)))
+                    && (outpoints.is_empty() || outpoints.contains(op))
+            })
+            .map(|(op, coin)| (*op, *coin))
+            .collect()
+    }
+
+    fn list_coins_page(
+        &mut self,
+        statuses: &[CoinStatus],
+        outpoints: &[bitcoin::OutPoint],
+        start: u64,
+        count: u64,
+    ) -> Vec<Coin> {
+        // There is no insertion order in the dummy database, order by outpoint instead.
+        let mut coins: Vec<Coin> = self
+            .list_coins(statuses, outpoints)
+            .values()
+            .copied()
+            .collect();
+        coins.sort_by_key(|coin| coin.outpoint);
+        coins
+            .into_iter()
+            .skip(start.try_into().unwrap_or(usize::MAX))
+            .take(count.try_into().unwrap_or(usize::MAX))
+            .collect()
+    }
+
     fn new_unspent_coins<'a>(&mut self, coins: &[Coin]) {
         for coin in coins {
             self.db.write().unwrap().coins.insert(coin.outpoint, *coin);
@@ -232,21 +575,33 @@ impl DatabaseConnection for DummyDatabase {
     }
 
     fn spend_coins<'a>(&mut self, outpoints: &[(bitcoin::OutPoint, bitcoin::Txid)]) {
+        // Check all coins before modifying any, so the update is all-or-nothing.
+        let mut db = self.db.write().unwrap();
+        for (op, _) in outpoints {
+            let coin = db.coins.get(op).unwrap();
+            assert!(coin.spend_txid.is_none());
+            assert!(coin.spend_block.is_none());
+        }
+        let now = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32;
         for (op, spend_txid) in outpoints {
-            let mut db = self.db.write().unwrap();
-            let spent = &mut db.coins.get_mut(op).unwrap();
-            assert!(spent.spend_txid.is_none());
-            assert!(spent.spend_block.is_none());
+            let spent = db.coins.get_mut(op).unwrap();
             spent.spend_txid = Some(*spend_txid);
+            spent.spend_broadcast_time = Some(now);
         }
     }
 
     fn confirm_spend<'a>(&mut self, outpoints: &[(bitcoin::OutPoint, bitcoin::Txid, i32, u32)]) {
+        let mut db = self.db.write().unwrap();
+        for (op, ..) in outpoints {
+            let coin = db.coins.get(op).unwrap();
+            assert!(coin.spend_txid.is_some());
+            assert!(coin.spend_block.is_none());
+        }
         for (op, spend_txid, height, time) in outpoints {
-            let mut db = self.db.write().unwrap();
-            let spent = &mut db.coins.get_mut(op).unwrap();
-            assert!(spent.spend_txid.is_some());
-            assert!(spent.spend_block.is_none());
+            let spent = db.coins.get_mut(op).unwrap();
             spent.spend_txid = Some(*spend_txid);
             spent.spend_block = Some(SpendBlock {
                 height: *height,
@@ -255,11 +610,29 @@ impl DatabaseConnection for DummyDatabase {
         }
     }
 
+    fn set_frozen(&mut self, outpoints: &[bitcoin::OutPoint], frozen: bool) {
+        let mut db = self.db.write().unwrap();
+        for op in outpoints {
+            if let Some(coin) = db.coins.get_mut(op) {
+                coin.is_frozen = frozen;
+            }
+        }
+    }
+
+    fn remove_coins(&mut self, outpoints: &[bitcoin::OutPoint]) {
+        let mut db = self.db.write().unwrap();
+        for op in outpoints {
+            if db.coins.get(op).map(|c| c.block_height.is_none()) == Some(true) {
+                db.coins.remove(op);
+            }
+        }
+    }
+
     fn derivation_index_by_address(
         &mut self,
-        _: &bitcoin::Address,
+        address: &bitcoin::Address,
     ) -> Option<(bip32::ChildNumber, bool)> {
-        None
+        self.db.read().unwrap().addresses.get(address).copied()
     }
 
     fn coins_by_outpoints(
@@ -277,47 +650,121 @@ impl DatabaseConnection for DummyDatabase {
             .collect()
     }
 
+    fn coins_by_derivation_indexes(
+        &mut self,
+        indexes: &[(bip32::ChildNumber, bool)],
+    ) -> HashMap<bitcoin::OutPoint, Coin> {
+        self.db
+            .read()
+            .unwrap()
+            .coins
+            .iter()
+            .filter(|(_, coin)| indexes.contains(&(coin.derivation_index, coin.is_change)))
+            .map(|(op, coin)| (*op, *coin))
+            .collect()
+    }
+
     fn store_spend(&mut self, psbt: &Psbt) {
         let txid = psbt.unsigned_tx.txid();
+        let now = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32;
         self.db
             .write()
             .unwrap()
             .spend_txs
-            .insert(txid, psbt.clone());
+            .insert(txid, (psbt.clone(), now));
     }
 
     fn spend_tx(&mut self, txid: &bitcoin::Txid) -> Option<Psbt> {
-        self.db.read().unwrap().spend_txs.get(txid).cloned()
+        self.db
+            .read()
+            .unwrap()
+            .spend_txs
+            .get(txid)
+            .map(|(psbt, _)| psbt.clone())
     }
 
-    fn list_spend(&mut self) -> Vec<Psbt> {
+    fn list_spend(&mut self, since: Option<u32>) -> Vec<(Psbt, u32)> {
         self.db
             .read()
             .unwrap()
             .spend_txs
             .values()
+            .filter(|(_, updated_at)| *updated_at >= since.unwrap_or(0))
             .cloned()
             .collect()
     }
 
+    fn list_spend_page(&mut self, since: Option<u32>, start: u64, count: u64) -> Vec<(Psbt, u32)> {
+        let mut spend_txs = self.list_spend(since);
+        spend_txs.sort_by_key(|(psbt, _)| psbt.unsigned_tx.txid());
+        spend_txs
+            .into_iter()
+            .skip(start.try_into().unwrap_or(usize::MAX))
+            .take(count.try_into().unwrap_or(usize::MAX))
+            .collect()
+    }
+
     fn delete_spend(&mut self, txid: &bitcoin::Txid) {
         self.db.write().unwrap().spend_txs.remove(txid);
     }
 
-    fn rollback_tip(&mut self, _: &BlockChainTip) {
-        todo!()
+    fn rollback_tip(&mut self, new_tip: &BlockChainTip) {
+        let mut db = self.db.write().unwrap();
+        for coin in db.coins.values_mut() {
+            if coin
+                .block_height
+                .map(|h| h > new_tip.height)
+                .unwrap_or(false)
+            {
+                coin.block_height = None;
+                coin.block_time = None;
+            }
+            if coin
+                .spend_block
+                .map(|b| b.height > new_tip.height)
+                .unwrap_or(false)
+            {
+                coin.spend_block = None;
+            }
+        }
+        db.curr_tip = Some(*new_tip);
+    }
+
+    fn wallet_timestamp(&mut self) -> u32 {
+        self.db.read().unwrap().timestamp
     }
 
     fn rescan_timestamp(&mut self) -> Option<u32> {
-        None
+        self.db.read().unwrap().rescan_timestamp
     }
 
-    fn set_rescan(&mut self, _: u32) {
-        todo!()
+    fn set_rescan(&mut self, timestamp: u32) {
+        let mut db = self.db.write().unwrap();
+        db.rescan_timestamp = Some(timestamp);
+        db.rescan_progress = None;
+    }
+
+    fn rescan_progress(&mut self) -> Option<u32> {
+        self.db.read().unwrap().rescan_progress
+    }
+
+    fn set_rescan_progress(&mut self, timestamp: u32) {
+        self.db.write().unwrap().rescan_progress = Some(timestamp);
     }
 
     fn complete_rescan(&mut self) {
-        todo!()
+        let mut db = self.db.write().unwrap();
+        db.rescan_timestamp = None;
+        db.rescan_progress = None;
+    }
+
+    fn cancel_rescan(&mut self) {
+        let mut db = self.db.write().unwrap();
+        db.rescan_timestamp = None;
+        db.rescan_progress = None;
     }
 
     fn list_txids(&mut self, start: u32, end: u32, limit: u64) -> Vec<bitcoin::Txid> {
@@ -348,6 +795,69 @@ impl DatabaseConnection for DummyDatabase {
         txids_and_time.truncate(limit as usize);
         txids_and_time.into_iter().map(|(txid, _)| txid).collect()
     }
+
+    fn list_txids_page(
+        &mut self,
+        before: Option<(u32, bitcoin::Txid)>,
+        limit: u64,
+    ) -> Vec<(u32, bitcoin::Txid)> {
+        let coins = &self.db.read().unwrap().coins;
+        let mut times_and_txids = BTreeSet::new();
+        for coin in coins.values() {
+            if let Some(time) = coin.block_time {
+                times_and_txids.insert((time, coin.outpoint.txid));
+            }
+            if let (Some(txid), Some(block)) = (coin.spend_txid, coin.spend_block) {
+                times_and_txids.insert((block.time, txid));
+            }
+        }
+        times_and_txids
+            .into_iter()
+            .rev()
+            .filter(|row| before.map(|cursor| row < &cursor).unwrap_or(true))
+            .take(limit as usize)
+            .collect()
+    }
+
+    fn store_hw_token(
+        &mut self,
+        descriptor: &MultipathDescriptor,
+        fingerprint: &bip32::Fingerprint,
+        token: &[u8; 32],
+    ) {
+        self.db
+            .write()
+            .unwrap()
+            .hw_tokens
+            .insert((descriptor.to_string(), *fingerprint), *token);
+    }
+
+    fn hw_tokens(
+        &mut self,
+        descriptor: &MultipathDescriptor,
+    ) -> HashMap<bip32::Fingerprint, [u8; 32]> {
+        let desc_str = descriptor.to_string();
+        self.db
+            .read()
+            .unwrap()
+            .hw_tokens
+            .iter()
+            .filter(|((desc, _), _)| desc == &desc_str)
+            .map(|((_, fg), token)| (*fg, *token))
+            .collect()
+    }
+
+    fn update_labels(&mut self, labels: &BTreeMap<String, String>) {
+        self.db.write().unwrap().labels.extend(
+            labels
+                .iter()
+                .map(|(item, value)| (item.clone(), value.clone())),
+        );
+    }
+
+    fn labels(&mut self) -> BTreeMap<String, String> {
+        self.db.read().unwrap().labels.clone()
+    }
 }
 
 pub struct DummyLiana {
@@ -373,27 +883,164 @@ pub fn tmp_dir() -> path::PathBuf {
     ))
 }
 
+/// Configure a DummyLiana with another network, descriptor, Bitcoin backend or database than
+/// the default ones.
+pub struct DummyLianaBuilder {
+    bitcoin_interface: sync::Arc<sync::Mutex<dyn BitcoinInterface>>,
+    database: sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
+    descriptor: Option<descriptors::MultipathDescriptor>,
+    bitcoin_config: BitcoinConfig,
+}
+
+impl DummyLianaBuilder {
+    fn new() -> Self {
+        DummyLianaBuilder {
+            bitcoin_interface: sync::Arc::new(sync::Mutex::new(DummyBitcoind::new())),
+            database: sync::Arc::new(sync::Mutex::new(DummyDatabase::new())),
+            descriptor: None,
+            bitcoin_config: BitcoinConfig {
+                network: bitcoin::Network::Bitcoin,
+                poll_interval_secs: time::Duration::from_secs(2),
+                gap_limit: 20,
+                dust_output_sats: 5_000,
+                max_fee_sats: bitcoin::blockdata::constants::COIN_VALUE,
+                max_feerate_sats_vb: 1_000,
+                min_confirmations: 1,
+                psbt_non_witness_utxo: true,
+                long_term_feerate_vb: None,
+                rescan_timeout_secs: None,
+            },
+        }
+    }
+
+    pub fn bitcoind(mut self, bitcoin_interface: impl BitcoinInterface + 'static) -> Self {
+        self.bitcoin_interface = sync::Arc::new(sync::Mutex::new(bitcoin_interface));
+        self
+    }
+
+    pub fn database(mut self, database: impl DatabaseInterface + 'static) -> Self {
+        self.database = sync::Arc::new(sync::Mutex::new(database));
+        self
+    }
+
+    /// The network to operate on. Unless a descriptor is set, a descriptor with xpubs for this
+    /// network is used.
+    pub fn network(mut self, network: bitcoin::Network) -> Self {
+        self.bitcoin_config.network = network;
+        self
+    }
+
+    pub fn descriptor(mut self, descriptor: descriptors::MultipathDescriptor) -> Self {
+        self.descriptor = Some(descriptor);
+        self
+    }
+
+    /// Use these Bitcoin settings, including the network.
+    pub fn bitcoin_config(mut self, bitcoin_config: BitcoinConfig) -> Self {
+        self.bitcoin_config = bitcoin_config;
+        self
+    }
+
+    pub fn build(self) -> DummyLiana {
+        let descriptor = self
+            .descriptor
+            .unwrap_or_else(|| default_descriptor(self.bitcoin_config.network));
+        DummyLiana::with_bitcoin_config(
+            self.bitcoin_interface,
+            self.database,
+            descriptor,
+            self.bitcoin_config,
+        )
+    }
+}
+
+/// An unconfirmed and unspent coin at this outpoint, paying to our first deposit address.
+pub fn dummy_coin(outpoint: bitcoin::OutPoint, amount_sat: u64) -> Coin {
+    Coin {
+        outpoint,
+        block_height: None,
+        block_time: None,
+        amount: bitcoin::Amount::from_sat(amount_sat),
+        derivation_index: 0.into(),
+        is_change: false,
+        spend_txid: None,
+        spend_block: None,
+        spend_broadcast_time: None,
+        is_frozen: false,
+    }
+}
+
+// A descriptor with a single key for the primary path and a single key for a recovery path
+// after 10'000 blocks, with xpubs for the given network.
+pub fn default_descriptor(network: bitcoin::Network) -> descriptors::MultipathDescriptor {
+    let (owner_xpub, heir_xpub) = if network == bitcoin::Network::Bitcoin {
+        ("xpub68JJTXc1MWK8KLW4HGLXZBJknja7kDUJuFHnM424LbziEXsfkh1WQCiEjjHw4zLqSUm4rvhgyGkkuRowE9tCJSgt3TQB5J3SKAbZ2SdcKST", "xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK")
+    } else {
+        ("tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr", "tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV")
+    };
+    let owner_key =
+        descriptor::DescriptorPublicKey::from_str(&format!("{}/<0;1>/*", owner_xpub)).unwrap();
+    let heir_key =
+        descriptor::DescriptorPublicKey::from_str(&format!("{}/<0;1>/*", heir_xpub)).unwrap();
+    let mut recovery_paths = BTreeMap::new();
+    recovery_paths.insert(10_000, descriptors::PathInfo::Single(heir_key));
+    descriptors::MultipathDescriptor::new(descriptors::PathInfo::Single(owner_key), recovery_paths)
+        .unwrap()
+}
+
 impl DummyLiana {
+    /// Configure a DummyLiana interface, starting from the default settings.
+    pub fn builder() -> DummyLianaBuilder {
+        DummyLianaBuilder::new()
+    }
+
     /// Creates a new DummyLiana interface
     pub fn new(
         bitcoin_interface: impl BitcoinInterface + 'static,
         database: impl DatabaseInterface + 'static,
+    ) -> DummyLiana {
+        DummyLiana::builder()
+            .bitcoind(bitcoin_interface)
+            .database(database)
+            .build()
+    }
+
+    /// Creates a new DummyLiana interface with these coins in its database. The transactions
+    /// which created them are known to the Bitcoin backend, so they can be spent.
+    pub fn with_coins(mut bitcoind: DummyBitcoind, coins: &[Coin]) -> DummyLiana {
+        for coin in coins {
+            bitcoind.add_wallet_tx(coin.outpoint.txid);
+        }
+        let mut db = DummyDatabase::new();
+        db.insert_coins(coins.to_vec());
+        DummyLiana::new(bitcoind, db)
+    }
+
+    /// Creates a new DummyLiana interface using the given main descriptor
+    pub fn with_descriptor(
+        bitcoin_interface: impl BitcoinInterface + 'static,
+        database: impl DatabaseInterface + 'static,
+        desc: descriptors::MultipathDescriptor,
+    ) -> DummyLiana {
+        DummyLiana::builder()
+            .bitcoind(bitcoin_interface)
+            .database(database)
+            .descriptor(desc)
+            .build()
+    }
+
+    /// Creates a new DummyLiana interface using the given main descriptor and Bitcoin settings
+    pub fn with_bitcoin_config(
+        bitcoin_interface: impl BitcoinInterface + 'static,
+        database: impl DatabaseInterface + 'static,
+        desc: descriptors::MultipathDescriptor,
+        bitcoin_config: BitcoinConfig,
     ) -> DummyLiana {
         let tmp_dir = tmp_dir();
         fs::create_dir_all(&tmp_dir).unwrap();
         // Use a shorthand for 'datadir', to avoid overflowing SUN_LEN on MacOS.
         let data_dir: path::PathBuf = [tmp_dir.as_path(), path::Path::new("d")].iter().collect();
 
-        let network = bitcoin::Network::Bitcoin;
-        let bitcoin_config = BitcoinConfig {
-            network,
-            poll_interval_secs: time::Duration::from_secs(2),
-        };
-
-        let owner_key = descriptor::DescriptorPublicKey::from_str("xpub68JJTXc1MWK8KLW4HGLXZBJknja7kDUJuFHnM424LbziEXsfkh1WQCiEjjHw4zLqSUm4rvhgyGkkuRowE9tCJSgt3TQB5J3SKAbZ2SdcKST/<0;1>/*").unwrap();
-        let heir_key = descriptor::DescriptorPublicKey::from_str("xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*").unwrap();
-        let desc =
-            crate::descriptors::MultipathDescriptor::new(owner_key, heir_key, 10_000).unwrap();
         let config = Config {
             bitcoin_config,
             bitcoind_config: None,
@@ -401,7 +1048,12 @@ impl DummyLiana {
             #[cfg(unix)]
             daemon: false,
             log_level: log::LevelFilter::Debug,
+            rpc_cookie: false,
+            rpc_max_request_size: 1024 * 1024,
             main_descriptor: desc,
+            birthday_timestamp: None,
+            #[cfg(feature = "postgres")]
+            postgres_config: None,
         };
 
         let handle = DaemonHandle::start(config, Some(bitcoin_interface), Some(database)).unwrap();
@@ -420,3 +1072,68 @@ impl DummyLiana {
         fs::remove_dir_all(&self.tmp_dir).unwrap();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dummy_bitcoind_reorg() {
+        let mut bitcoind = DummyBitcoind::new();
+        let initial_tip = bitcoind.chain_tip();
+        assert_eq!(bitcoind.chain.len(), initial_tip.height as usize + 1);
+        assert_eq!(bitcoind.block_at(0), bitcoind.genesis_block());
+        assert_eq!(bitcoind.block_at(initial_tip.height), initial_tip);
+
+        // A transaction is confirmed in the next block.
+        let tx = Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime(0),
+            input: Vec::new(),
+            output: Vec::new(),
+        };
+        let txid = tx.txid();
+        bitcoind.broadcast_tx(&tx).unwrap();
+        assert!(bitcoind.in_mempool(&txid));
+        assert_eq!(bitcoind.wallet_transaction(&txid), Some((tx.clone(), None)));
+        let tip_a = bitcoind.mine_block();
+        assert_eq!(tip_a.height, initial_tip.height + 1);
+        assert_eq!(bitcoind.chain_tip(), tip_a);
+        assert!(!bitcoind.in_mempool(&txid));
+        let (_, block) = bitcoind.wallet_transaction(&txid).unwrap();
+        assert_eq!(block.unwrap().height, tip_a.height);
+        let tip_b = bitcoind.mine_block();
+        assert!(bitcoind.is_in_chain(&initial_tip));
+        assert!(bitcoind.is_in_chain(&tip_a));
+        assert_eq!(bitcoind.common_ancestor(&tip_b), Some(tip_b));
+
+        // Reorg out the last two blocks and replace them by three new ones. The transaction
+        // goes back to the mempool.
+        let new_tip = bitcoind.reorg(2, 3);
+        assert_eq!(new_tip.height, tip_b.height + 1);
+        assert_eq!(bitcoind.chain_tip(), new_tip);
+        assert_ne!(bitcoind.block_at(tip_a.height), tip_a);
+        assert!(!bitcoind.is_in_chain(&tip_a));
+        assert!(!bitcoind.is_in_chain(&tip_b));
+        assert!(bitcoind.is_in_chain(&initial_tip));
+        assert_eq!(bitcoind.common_ancestor(&tip_b), Some(initial_tip));
+        assert_eq!(bitcoind.common_ancestor(&tip_a), Some(initial_tip));
+        assert!(bitcoind.in_mempool(&txid));
+        assert_eq!(bitcoind.wallet_transaction(&txid), Some((tx, None)));
+        assert_eq!(
+            bitcoind.tip_time(),
+            bitcoind.chain[new_tip.height as usize].time
+        );
+
+        // Broadcast failures can be simulated.
+        bitcoind.broadcast_error = Some("insufficient fee".to_string());
+        assert!(bitcoind
+            .broadcast_tx(&Transaction {
+                version: 1,
+                lock_time: bitcoin::PackedLockTime(0),
+                input: Vec::new(),
+                output: Vec::new(),
+            })
+            .is_err());
+    }
+}
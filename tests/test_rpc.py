@@ -27,6 +27,17 @@ def test_getinfo(lianad):
     assert res["sync"] == 1.0
     assert "main" in res["descriptors"]
     assert res["rescan_progress"] is None
+    assert isinstance(res["wallet_birthday"], int)
+    # No coin, nothing to recover.
+    assert res["recovery_available"] is False
+    assert res["recovery_height"] is None
+
+
+def test_listdescriptors(lianad):
+    main_desc = lianad.rpc.getinfo()["descriptors"]["main"]
+    res = lianad.rpc.listdescriptors()
+    assert res["receive"].split("#")[0] == main_desc.split("#")[0].replace("<0;1>", "0")
+    assert res["change"].split("#")[0] == main_desc.split("#")[0].replace("<0;1>", "1")
 
 
 def test_getaddress(lianad):
@@ -36,6 +47,16 @@ def test_getaddress(lianad):
     assert res["address"] != lianad.rpc.getnewaddress()["address"]
 
 
+def test_peekaddress(lianad):
+    # Peeking doesn't allocate the address
+    addr = lianad.rpc.peekaddress()["address"]
+    assert lianad.rpc.peekaddress()["address"] == addr
+    assert lianad.rpc.getnewaddress()["address"] == addr
+    assert lianad.rpc.peekaddress()["address"] != addr
+    # We can peek at a given index
+    assert lianad.rpc.peekaddress(0)["address"] != lianad.rpc.peekaddress(1)["address"]
+
+
 def test_listcoins(lianad, bitcoind):
     # Initially empty
     res = lianad.rpc.listcoins()
@@ -74,6 +95,23 @@ def test_listcoins(lianad, bitcoind):
     assert spend_info["txid"] == spend_txid
     assert spend_info["height"] == curr_height
 
+    # The coins can be filtered by status and outpoint.
+    outpoint = res[0]["outpoint"]
+    assert len(lianad.rpc.listcoins(["spent"])["coins"]) == 1
+    assert len(lianad.rpc.listcoins(["unconfirmed", "confirmed"])["coins"]) == 0
+    assert len(lianad.rpc.listcoins([], [outpoint])["coins"]) == 1
+    assert len(lianad.rpc.listcoins(["spending"], [outpoint])["coins"]) == 0
+
+    # They can also be listed by pages.
+    assert lianad.rpc.listcoins([], [], 0, 1)["coins"][0]["outpoint"] == outpoint
+    assert len(lianad.rpc.listcoins([], [], 1, 1)["coins"]) == 0
+
+    # Coins can be queried by outpoint, unknown ones are ignored.
+    unknown_outpoint = f"{spend_txid}:42"
+    res = lianad.rpc.getcoins([outpoint, unknown_outpoint])["coins"]
+    assert len(res) == 1
+    assert res[0]["outpoint"] == outpoint
+
 
 def test_jsonrpc_server(lianad, bitcoind):
     """Test passing parameters as a list or a mapping."""
@@ -169,6 +207,21 @@ def test_list_spend(lianad, bitcoind):
     assert len(list_res) == 2
     first_psbt = next(entry for entry in list_res if entry["psbt"] == res["psbt"])
     second_psbt = next(entry for entry in list_res if entry["psbt"] == res_b["psbt"])
+    assert any(o["is_change"] for o in first_psbt["info"]["outputs"])
+    assert not any(o["is_change"] for o in second_psbt["info"]["outputs"])
+    assert all(i["is_ours"] for i in second_psbt["info"]["inputs"])
+    assert len(second_psbt["info"]["inputs"]) == 2
+    assert second_psbt["info"]["fee"] == sum(
+        i["value"] for i in second_psbt["info"]["inputs"]
+    ) - sum(o["value"] for o in second_psbt["info"]["outputs"])
+    signing_status = second_psbt["info"]["signing_status"]
+    assert [i["signatures"] for i in signing_status["inputs"]] == [0, 0]
+    assert not signing_status["finalizable"]
+
+    # They can be filtered by the time of their last update.
+    last_update = max(entry["updated_at"] for entry in list_res)
+    assert len(lianad.rpc.listspendtxs(None, None, last_update)["spend_txs"]) >= 1
+    assert len(lianad.rpc.listspendtxs(None, None, last_update + 1)["spend_txs"]) == 0
 
     # If we delete the first one, we'll get only the second one.
     first_psbt = PSBT.from_base64(res["psbt"])
@@ -269,6 +322,58 @@ def test_broadcast_spend(lianad, bitcoind):
     # the PSBT before broadcasting the transaction.
     lianad.rpc.broadcastspend(txid)
 
+    # The coins are immediately marked as spent by a broadcast transaction.
+    def spend_statuses():
+        return [
+            c["spend_status"]
+            for c in lianad.rpc.listcoins()["coins"]
+            if c["outpoint"] in outpoints
+        ]
+
+    assert all(s == "broadcast" for s in spend_statuses())
+    bitcoind.generate_block(1, wait_for_mempool=txid)
+    wait_for(lambda: all(s == "confirmed" for s in spend_statuses()))
+
+
+def test_finalize_spend(lianad, bitcoind):
+    # Create a new coin and a spending tx for it.
+    addr = lianad.rpc.getnewaddress()["address"]
+    bitcoind.rpc.sendtoaddress(addr, 0.2567)
+    wait_for(lambda: len(lianad.rpc.listcoins()["coins"]) > 0)
+    outpoints = [c["outpoint"] for c in lianad.rpc.listcoins()["coins"]]
+    destinations = {
+        bitcoind.rpc.getnewaddress(): 200_000,
+    }
+    res = lianad.rpc.createspend(destinations, outpoints, 6)
+    psbt = PSBT.from_base64(res["psbt"])
+    txid = psbt.tx.txid().hex()
+
+    # We can't finalize an unknown Spend
+    with pytest.raises(RpcError, match="Unknown spend transaction.*"):
+        lianad.rpc.finalizespend(txid)
+    lianad.rpc.updatespend(res["psbt"])
+
+    # We can't finalize an unsigned transaction
+    with pytest.raises(RpcError, match="Failed to finalize the spend transaction.*"):
+        lianad.rpc.finalizespend(txid)
+    signed_psbt = lianad.sign_psbt(PSBT.from_base64(res["psbt"]))
+    lianad.rpc.updatespend(signed_psbt.to_base64())
+
+    # Once signed we get the final transaction, which wasn't broadcast.
+    tx_hex = lianad.rpc.finalizespend(txid)["tx"]
+    assert bitcoind.rpc.decoderawtransaction(tx_hex)["txid"] == txid
+    assert all(c["spend_info"] is None for c in lianad.rpc.listcoins()["coins"])
+
+    # We can broadcast it by other means.
+    bitcoind.rpc.sendrawtransaction(tx_hex)
+    wait_for(
+        lambda: all(
+            c["spend_info"] is not None
+            for c in lianad.rpc.listcoins()["coins"]
+            if c["outpoint"] in outpoints
+        )
+    )
+
 
 def test_start_rescan(lianad, bitcoind):
     """Test we successfully retrieve all our transactions after losing state by rescanning."""
@@ -356,7 +461,12 @@ def test_start_rescan(lianad, bitcoind):
     lianad.rpc.startrescan(initial_timestamp)
     rescan_progress = lianad.rpc.getinfo()["rescan_progress"]
     assert rescan_progress is None or 0 <= rescan_progress <= 1
+    status = lianad.rpc.getrescanstatus()["status"]
+    if status is not None:
+        assert status["start_height"] <= status["current_height"]
+        assert status["current_height"] <= status["target_height"]
     wait_for(lambda: lianad.rpc.getinfo()["rescan_progress"] is None)
+    assert lianad.rpc.getrescanstatus()["status"] is None
     wait_for(
         lambda: lianad.rpc.getinfo()["block_height"] == bitcoind.rpc.getblockcount()
     )
@@ -367,6 +477,23 @@ def test_start_rescan(lianad, bitcoind):
     assert lianad.rpc.getnewaddress() not in (first_address, second_address)
 
 
+def test_cancel_rescan(lianad, bitcoind):
+    """Test aborting a rescan, and that there must be one to abort"""
+    with pytest.raises(RpcError, match="There is no ongoing rescan."):
+        lianad.rpc.cancelrescan()
+
+    # Start a rescan and cancel it right away. Whether the backend had time to
+    # complete it or not, it's not ongoing anymore.
+    lianad.rpc.startrescan(int(time.time()) - 60 * 60 * 24 * 30)
+    try:
+        lianad.rpc.cancelrescan()
+    except RpcError:
+        # The poller may have noticed the rescan completed in the meantime.
+        pass
+    assert lianad.rpc.getrescanstatus()["status"] is None
+    wait_for(lambda: lianad.rpc.getinfo()["rescan_progress"] is None)
+
+
 def test_listtransactions(lianad, bitcoind):
     """Test listing of transactions by txid and timespan"""
 
@@ -536,6 +663,19 @@ def test_listtransactions(lianad, bitcoind):
     bit_txids = set(bitcoind.rpc.decoderawtransaction(tx["tx"])["txid"] for tx in txs)
     assert bit_txids == txids
 
+    # We can restrict the query to the transactions referencing some coins or addresses.
+    # The last deposit is confirmed but the transaction spending it isn't.
+    txs = lianad.rpc.listconfirmed(initial_timestamp, final_timestamp, 10, [outpoint])[
+        "transactions"
+    ]
+    assert len(txs) == 1
+    assert bitcoind.rpc.decoderawtransaction(txs[0]["tx"])["txid"] == outpoint[:-2]
+    txs = lianad.rpc.listconfirmed(
+        initial_timestamp, final_timestamp, 10, None, [addr]
+    )["transactions"]
+    assert len(txs) == 1
+    assert bitcoind.rpc.decoderawtransaction(txs[0]["tx"])["txid"] == outpoint[:-2]
+
 
 def test_create_recovery(lianad, bitcoind):
     """Test the sweep of coins that are available through the timelocked path."""
@@ -1,6 +1,8 @@
+import pytest
+
 from fixtures import *
 from test_framework.serializations import PSBT
-from test_framework.utils import wait_for, COIN
+from test_framework.utils import wait_for, COIN, RpcError
 
 
 def test_spend_change(lianad, bitcoind):
@@ -170,3 +172,30 @@ def test_coin_marked_spent(lianad, bitcoind):
         return True
 
     wait_for(lambda: all(is_spent(c) for c in deposited_coins()))
+
+
+def test_spend_with_data(lianad, bitcoind):
+    """We can embed data in a Spend transaction and it gets relayed."""
+    addr = lianad.rpc.getnewaddress()["address"]
+    txid = bitcoind.rpc.sendtoaddress(addr, 0.01)
+    bitcoind.generate_block(1, wait_for_mempool=txid)
+    wait_for(lambda: len(lianad.rpc.listcoins()["coins"]) == 1)
+    outpoints = [c["outpoint"] for c in lianad.rpc.listcoins()["coins"]]
+    destinations = {bitcoind.rpc.getnewaddress(): 100_000}
+
+    # Too much data would make the transaction non-standard.
+    with pytest.raises(RpcError, match="is not standard"):
+        lianad.rpc.createspend(destinations, outpoints, 2, None, "42" * 81)
+
+    data = "42" * 80
+    res = lianad.rpc.createspend(destinations, outpoints, 2, None, data)
+    spend_psbt = PSBT.from_base64(res["psbt"])
+    signed_psbt = lianad.sign_psbt(spend_psbt)
+    lianad.rpc.updatespend(signed_psbt.to_base64())
+    spend_txid = signed_psbt.tx.txid().hex()
+    lianad.rpc.broadcastspend(spend_txid)
+    bitcoind.generate_block(1, wait_for_mempool=spend_txid)
+    tx = bitcoind.rpc.getrawtransaction(spend_txid, True)
+    data_outputs = [o for o in tx["vout"] if o["scriptPubKey"]["type"] == "nulldata"]
+    assert len(data_outputs) == 1
+    assert data_outputs[0]["scriptPubKey"]["hex"].endswith(data)